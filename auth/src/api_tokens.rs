@@ -0,0 +1,126 @@
+//! Personal API tokens
+//!
+//! Scripts authenticate with `Authorization: Bearer <token>` instead of a
+//! session cookie. A token belongs to a user and is limited to a set of scopes,
+//! which are the same [`Permission`]s the route groups declare: a request is
+//! allowed only if the token has the route's permission in its scopes and the
+//! owner's role still grants it. Tokens are kept by an [`ApiTokenStore`], such
+//! as a database table holding token hashes.
+
+use crate::roles::Permission;
+use crate::session::{AuthProvider, SessionData};
+use axum::http::{header, HeaderMap};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Owner and scopes of a valid API token
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiTokenIdentity {
+    /// Token identifier (not the secret)
+    pub token_id: String,
+    /// Provider of the user who created the token (`AuthProvider::name`)
+    pub provider: String,
+    /// Handle of the user who created the token
+    pub handle: String,
+    /// Groups of the user when the token was created (for group role assignments)
+    pub groups: Vec<String>,
+    /// Permissions the token may be used for
+    pub scopes: Vec<Permission>,
+    /// Token creation timestamp (Unix timestamp)
+    pub created_at: u64,
+}
+
+impl ApiTokenIdentity {
+    /// Check whether the token's scopes include a permission
+    pub fn allows(&self, permission: Permission) -> bool {
+        self.scopes.contains(&permission)
+    }
+
+    /// Session data of the token's owner, made available to handlers like a cookie session
+    pub fn session_data(&self) -> SessionData {
+        SessionData {
+            auth_provider: AuthProvider::ApiToken(self.provider.clone()),
+            user_id: self.handle.clone(),
+            handle: self.handle.clone(),
+            display_name: None,
+            groups: self.groups.clone(),
+            created_at: self.created_at,
+            session_id: None,
+        }
+    }
+}
+
+/// Extract the token from an `Authorization: Bearer` header
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+    let token = token.trim();
+    (!token.is_empty()).then_some(token)
+}
+
+/// Future returned by [`ApiTokenStore::authenticate`]
+pub type ApiTokenFuture<'a> = Pin<Box<dyn Future<Output = Option<ApiTokenIdentity>> + Send + 'a>>;
+
+/// Store of API tokens
+pub trait ApiTokenStore: Send + Sync {
+    /// Look up a token that is neither expired nor revoked and record its use
+    fn authenticate<'a>(&'a self, token: &'a str) -> ApiTokenFuture<'a>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_bearer_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer npk_abc"),
+        );
+        assert_eq!(bearer_token(&headers), Some("npk_abc"));
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("bearer  npk_abc "),
+        );
+        assert_eq!(bearer_token(&headers), Some("npk_abc"));
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic dXNlcjpwYXNz"),
+        );
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer "));
+        assert_eq!(bearer_token(&headers), None);
+    }
+
+    #[test]
+    fn test_scopes() {
+        let identity = ApiTokenIdentity {
+            token_id: "t1".to_string(),
+            provider: "plain".to_string(),
+            handle: "alice".to_string(),
+            groups: vec![],
+            scopes: vec![Permission::ViewSurveys, Permission::DownloadCaptures],
+            created_at: 0,
+        };
+        assert!(identity.allows(Permission::ViewSurveys));
+        assert!(!identity.allows(Permission::DownloadKeylogs));
+        // Roles are those of the owner on their provider
+        let session_data = identity.session_data();
+        assert_eq!(
+            session_data.auth_provider,
+            AuthProvider::ApiToken("plain".to_string())
+        );
+        assert_eq!(session_data.auth_provider.name(), "api_token");
+        assert_eq!(session_data.auth_provider.user_provider(), "plain");
+    }
+}
@@ -0,0 +1,169 @@
+//! Security audit log
+//!
+//! Logins, logouts, second factor and password changes, lockouts and denied
+//! permissions are recorded as [`AuditEvent`]s to the [`AuditLog`] set on the
+//! service, e.g. an append-only database table. Applications record their own
+//! administrative actions (downloads, wipes, ...) to the same log, so there is
+//! one accountable trail. Without an audit log, events are only traced.
+
+use crate::error::AuthError;
+use crate::session::SessionData;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+
+/// Result of an audited action
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    /// The action was performed
+    Success,
+    /// The action failed, e.g. wrong credentials
+    Failure,
+    /// The actor was not allowed to perform the action
+    Denied,
+}
+
+impl AuditOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+            AuditOutcome::Denied => "denied",
+        }
+    }
+}
+
+impl fmt::Display for AuditOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuditOutcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success" => Ok(AuditOutcome::Success),
+            "failure" => Ok(AuditOutcome::Failure),
+            "denied" => Ok(AuditOutcome::Denied),
+            other => Err(format!("Unknown audit outcome '{}'", other)),
+        }
+    }
+}
+
+/// An audited action: who did what to what, from where, and how it went
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// Handle of the user, or "magic_key:<key>" for survey sessions, if known
+    pub actor: Option<String>,
+    /// Authentication provider of the actor
+    pub provider: Option<String>,
+    /// Client IP
+    pub ip: Option<IpAddr>,
+    /// What was done, e.g. "login" or "keylog_download"
+    pub action: String,
+    /// What it was done to, e.g. a user, a lockout key or a request path
+    pub target: Option<String>,
+    pub outcome: AuditOutcome,
+    /// Further detail, e.g. why the action failed
+    pub detail: Option<String>,
+}
+
+impl AuditEvent {
+    /// Event of an action with its outcome; the other fields are set with the
+    /// builder methods
+    pub fn new(action: impl Into<String>, outcome: AuditOutcome) -> Self {
+        Self {
+            actor: None,
+            provider: None,
+            ip: None,
+            action: action.into(),
+            target: None,
+            outcome,
+            detail: None,
+        }
+    }
+
+    /// Set the actor and provider from a session
+    pub fn session(mut self, session_data: &SessionData) -> Self {
+        self.actor = Some(session_data.handle.clone());
+        self.provider = Some(format!("{:?}", session_data.auth_provider));
+        self
+    }
+
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    pub fn provider(mut self, provider: impl Into<String>) -> Self {
+        self.provider = Some(provider.into());
+        self
+    }
+
+    pub fn ip(mut self, ip: Option<IpAddr>) -> Self {
+        self.ip = ip;
+        self
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// Future returned by [`AuditLog`] methods
+pub type AuditFuture<'a> = Pin<Box<dyn Future<Output = Result<(), AuthError>> + Send + 'a>>;
+
+/// Append-only log of audit events
+pub trait AuditLog: Send + Sync {
+    /// Append an event
+    fn record<'a>(&'a self, event: &'a AuditEvent) -> AuditFuture<'a>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::AuthProvider;
+
+    #[test]
+    fn test_outcome_names() {
+        for outcome in [
+            AuditOutcome::Success,
+            AuditOutcome::Failure,
+            AuditOutcome::Denied,
+        ] {
+            assert_eq!(outcome.as_str().parse::<AuditOutcome>(), Ok(outcome));
+        }
+        assert!("granted".parse::<AuditOutcome>().is_err());
+    }
+
+    #[test]
+    fn test_event_from_session() {
+        let session_data = SessionData {
+            auth_provider: AuthProvider::PlainLogin,
+            user_id: "alice".to_string(),
+            handle: "alice".to_string(),
+            display_name: None,
+            groups: Vec::new(),
+            created_at: 0,
+            session_id: None,
+        };
+        let event = AuditEvent::new("logout", AuditOutcome::Success)
+            .session(&session_data)
+            .ip(Some("192.0.2.1".parse().unwrap()));
+        assert_eq!(event.actor.as_deref(), Some("alice"));
+        assert_eq!(event.provider.as_deref(), Some("PlainLogin"));
+        assert_eq!(event.target, None);
+    }
+}
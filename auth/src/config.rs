@@ -1,3 +1,4 @@
+use crate::roles::Role;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -29,6 +30,18 @@ pub struct AuthConfig {
     /// Magic Key configuration for surveyors
     #[serde(default)]
     pub magic_keys: MagicKeyConfig,
+
+    /// Role assignments for authenticated users
+    #[serde(default)]
+    pub roles: RoleConfig,
+
+    /// Brute-force protection of plain login and Magic Key authentication
+    #[serde(default)]
+    pub lockout: LockoutConfig,
+
+    /// Security audit log of logins and administrative actions
+    #[serde(default)]
+    pub audit: AuditConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,6 +82,44 @@ pub struct OAuthConfig {
     pub linkedin_client_id: Option<String>,
     pub linkedin_client_secret: Option<String>,
     pub linkedin_redirect_url: Option<String>,
+
+    /// Generic OpenID Connect providers (Keycloak, Entra ID, ...)
+    #[serde(default)]
+    pub oidc: Vec<OidcProviderConfig>,
+}
+
+/// Configuration of a generic OpenID Connect provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcProviderConfig {
+    /// Short name used in the login and callback URLs (/auth/oidc/{name}/...)
+    pub name: String,
+
+    /// Label shown on the login page (defaults to the name)
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// Issuer URL; the provider metadata is discovered from
+    /// {issuer_url}/.well-known/openid-configuration
+    pub issuer_url: String,
+
+    /// OAuth client ID
+    pub client_id: String,
+
+    /// OAuth client secret (omit for public clients)
+    #[serde(default)]
+    pub client_secret: Option<String>,
+
+    /// Redirect URL, normally {base_url}/auth/oidc/{name}/callback
+    pub redirect_url: String,
+
+    /// Scopes to request ("openid" is always requested)
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+
+    /// Claim mapped into the session groups, e.g. "groups" or, for nested
+    /// claims, a dotted path such as "realm_access.roles"
+    #[serde(default)]
+    pub groups_claim: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,8 +129,51 @@ pub struct PlainLoginConfig {
     pub enabled: bool,
 
     /// List of allowed users with passwords
+    /// (users can also be kept in the database, see the local user store)
     #[serde(default)]
     pub users: Vec<UserCredentials>,
+
+    /// Refuse to start if a configured user has a plain text password,
+    /// instead of accepting it with a warning
+    #[serde(default)]
+    pub reject_plaintext_passwords: bool,
+
+    /// Rules for passwords set through the admin API or the password change page
+    #[serde(default)]
+    pub password_policy: PasswordPolicy,
+
+    /// TOTP second factor
+    #[serde(default)]
+    pub totp: TotpConfig,
+}
+
+/// Rules for new passwords
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordPolicy {
+    /// Minimum length in characters
+    #[serde(default = "default_password_min_length")]
+    pub min_length: usize,
+
+    /// Minimum number of character classes (lowercase, uppercase, digits, other)
+    #[serde(default = "default_password_min_character_classes")]
+    pub min_character_classes: usize,
+}
+
+/// TOTP (RFC 6238) second factor for plain login users
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpConfig {
+    /// Let users enroll an authenticator app (requires a TOTP store and `session.cookie_secret`)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Issuer shown in authenticator apps
+    #[serde(default = "default_totp_issuer")]
+    pub issuer: String,
+
+    /// Require TOTP for users whose role is at least this one, e.g. "analyst"
+    /// (analysts, operators and admins); they enroll at their next login
+    #[serde(default)]
+    pub required_for_role: Option<Role>,
 }
 
 /// User credentials for file-based authentication
@@ -88,8 +182,8 @@ pub struct UserCredentials {
     /// Username
     pub username: String,
 
-    /// Password (plain text in config, hashed in memory)
-    /// In production, use bcrypt or argon2 hashed passwords
+    /// Password: an argon2id hash (recommended), a bcrypt hash, or plain
+    /// text (refused with `reject_plaintext_passwords`)
     pub password: String,
 
     /// Optional display name
@@ -107,6 +201,17 @@ pub struct SessionConfig {
     #[serde(default = "default_session_timeout")]
     pub timeout_seconds: u64,
 
+    /// Track sessions server-side in a session store, so they can be listed
+    /// and revoked (default: false). Cookies issued without it carry no
+    /// session id, so enabling it logs out existing sessions.
+    #[serde(default)]
+    pub server_side: bool,
+
+    /// Idle timeout in seconds: sessions without a request for this long end
+    /// before `timeout_seconds` (requires a session store; default: none)
+    #[serde(default)]
+    pub idle_timeout_seconds: Option<u64>,
+
     /// Secure cookie (HTTPS only)
     #[serde(default)]
     pub secure: bool,
@@ -118,6 +223,68 @@ pub struct SessionConfig {
     pub cookie_secret: Option<String>,
 }
 
+/// Role assignments. A user's effective role is the most privileged one
+/// assigned to their handle, to any of their groups, or by the role store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleConfig {
+    /// Role of authenticated users without any assignment,
+    /// or "none" to deny access to users without an explicit role
+    #[serde(default = "default_role", with = "optional_role")]
+    pub default_role: Option<Role>,
+
+    /// Roles per user, as "<provider>:<handle>" or as a bare handle, which
+    /// matches that handle on every provider
+    /// Example: { "plain:admin" = ["admin"], "bluesky:alice.bsky.social" = ["analyst"] }
+    #[serde(default)]
+    pub users: HashMap<String, Vec<Role>>,
+
+    /// Roles per group (from the OIDC groups claim or plain login users)
+    /// Example: { "noc" = ["operator"] }
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<Role>>,
+}
+
+/// Lockout of clients and accounts after repeated failed logins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockoutConfig {
+    /// Enable brute-force protection
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Failed logins (password or second factor) on one account before it is locked out
+    #[serde(default = "default_max_failures_per_account")]
+    pub max_failures_per_account: u32,
+
+    /// Failed logins and Magic Key attempts from one IP before it is locked out
+    #[serde(default = "default_max_failures_per_ip")]
+    pub max_failures_per_ip: u32,
+
+    /// Failures are forgotten after this many seconds without a failure or lockout
+    #[serde(default = "default_lockout_window")]
+    pub window_seconds: u64,
+
+    /// First lockout in seconds; each further failure doubles it
+    #[serde(default = "default_lockout_seconds")]
+    pub lockout_seconds: u64,
+
+    /// Longest lockout in seconds
+    #[serde(default = "default_max_lockout_seconds")]
+    pub max_lockout_seconds: u64,
+}
+
+/// Security audit log, kept by the application (e.g. in its database)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// Record security-relevant actions to the audit log
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Chain each entry to the previous one by its hash, so altered or deleted
+    /// entries are detected when the log is verified
+    #[serde(default)]
+    pub hash_chain: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MagicKeyConfig {
     /// Enable Magic Key authentication
@@ -136,6 +303,11 @@ pub struct MagicKeyConfig {
     #[serde(default = "default_survey_timeout")]
     pub survey_timeout_seconds: u64,
 
+    /// Bind survey session tokens to the client IP they were issued to
+    /// (a surveyor changing networks must re-enter the Magic Key)
+    #[serde(default)]
+    pub bind_survey_session_to_ip: bool,
+
     /// Default maximum measuring time in seconds for all magic keys (default: 3600 = 1 hour)
     #[serde(default = "default_max_measuring_time")]
     pub max_measuring_time_seconds: u64,
@@ -147,6 +319,74 @@ pub struct MagicKeyConfig {
     pub magic_key_max_measuring_time: HashMap<String, u64>,
 }
 
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "profile".to_string(),
+        "email".to_string(),
+    ]
+}
+
+fn default_role() -> Option<Role> {
+    Some(Role::Viewer)
+}
+
+/// (De)serialize an optional role as its name, with "none" for no role
+mod optional_role {
+    use crate::roles::Role;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(role: &Option<Role>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(role.map_or("none", Role::as_str))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Role>, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if name == "none" {
+            return Ok(None);
+        }
+        name.parse().map(Some).map_err(serde::de::Error::custom)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_failures_per_account() -> u32 {
+    5
+}
+
+fn default_max_failures_per_ip() -> u32 {
+    20
+}
+
+fn default_lockout_window() -> u64 {
+    900 // 15 minutes
+}
+
+fn default_lockout_seconds() -> u64 {
+    60
+}
+
+fn default_max_lockout_seconds() -> u64 {
+    3600 // 1 hour
+}
+
+fn default_password_min_length() -> usize {
+    12
+}
+
+fn default_password_min_character_classes() -> usize {
+    2
+}
+
+fn default_totp_issuer() -> String {
+    "NetPoke".to_string()
+}
+
 fn default_survey_cookie_name() -> String {
     "survey_session_id".to_string()
 }
@@ -176,6 +416,9 @@ impl Default for AuthConfig {
             session: SessionConfig::default(),
             allowed_users: vec![],
             magic_keys: MagicKeyConfig::default(),
+            roles: RoleConfig::default(),
+            lockout: LockoutConfig::default(),
+            audit: AuditConfig::default(),
         }
     }
 }
@@ -198,6 +441,7 @@ impl Default for OAuthConfig {
             linkedin_client_id: None,
             linkedin_client_secret: None,
             linkedin_redirect_url: None,
+            oidc: vec![],
         }
     }
 }
@@ -207,6 +451,69 @@ impl Default for PlainLoginConfig {
         Self {
             enabled: false,
             users: vec![],
+            reject_plaintext_passwords: false,
+            password_policy: PasswordPolicy::default(),
+            totp: TotpConfig::default(),
+        }
+    }
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: default_password_min_length(),
+            min_character_classes: default_password_min_character_classes(),
+        }
+    }
+}
+
+/// Longest password accepted, to bound the cost of hashing
+const MAX_PASSWORD_LENGTH: usize = 1024;
+
+impl PasswordPolicy {
+    /// Check a new password for a user, returning the reason it is refused
+    pub fn check(&self, username: &str, password: &str) -> Result<(), String> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(format!(
+                "Password must be at least {} characters long",
+                self.min_length
+            ));
+        }
+        if length > MAX_PASSWORD_LENGTH {
+            return Err(format!(
+                "Password must be at most {} characters long",
+                MAX_PASSWORD_LENGTH
+            ));
+        }
+        let classes = [
+            password.chars().any(|c| c.is_lowercase()),
+            password.chars().any(|c| c.is_uppercase()),
+            password.chars().any(|c| c.is_numeric()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+        ]
+        .iter()
+        .filter(|&&present| present)
+        .count();
+        if classes < self.min_character_classes {
+            return Err(format!(
+                "Password must mix at least {} of lowercase letters, uppercase letters, digits and other characters",
+                self.min_character_classes
+            ));
+        }
+        if password.eq_ignore_ascii_case(username) {
+            return Err("Password must not be the username".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Default for TotpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            issuer: default_totp_issuer(),
+            required_for_role: None,
         }
     }
 }
@@ -216,12 +523,65 @@ impl Default for SessionConfig {
         Self {
             cookie_name: default_session_cookie_name(),
             timeout_seconds: default_session_timeout(),
+            server_side: false,
+            idle_timeout_seconds: None,
             secure: false,
             cookie_secret: None,
         }
     }
 }
 
+impl Default for RoleConfig {
+    fn default() -> Self {
+        Self {
+            default_role: default_role(),
+            users: HashMap::new(),
+            groups: HashMap::new(),
+        }
+    }
+}
+
+impl RoleConfig {
+    /// Roles assigned by configuration to a user of a provider and its
+    /// groups, including the default role
+    pub fn roles_for(&self, provider: &str, handle: &str, groups: &[String]) -> Vec<Role> {
+        let mut roles: Vec<Role> = self.default_role.into_iter().collect();
+        for key in [format!("{}:{}", provider, handle), handle.to_string()] {
+            if let Some(user_roles) = self.users.get(&key) {
+                roles.extend(user_roles);
+            }
+        }
+        for group in groups {
+            if let Some(group_roles) = self.groups.get(group) {
+                roles.extend(group_roles);
+            }
+        }
+        roles
+    }
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_failures_per_account: default_max_failures_per_account(),
+            max_failures_per_ip: default_max_failures_per_ip(),
+            window_seconds: default_lockout_window(),
+            lockout_seconds: default_lockout_seconds(),
+            max_lockout_seconds: default_max_lockout_seconds(),
+        }
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            hash_chain: false,
+        }
+    }
+}
+
 impl Default for MagicKeyConfig {
     fn default() -> Self {
         Self {
@@ -229,6 +589,7 @@ impl Default for MagicKeyConfig {
             magic_keys: vec![],
             survey_cookie_name: default_survey_cookie_name(),
             survey_timeout_seconds: default_survey_timeout(),
+            bind_survey_session_to_ip: false,
             max_measuring_time_seconds: default_max_measuring_time(),
             magic_key_max_measuring_time: HashMap::new(),
         }
@@ -255,6 +616,92 @@ impl MagicKeyConfig {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_oidc_provider_config() {
+        let config: OAuthConfig = serde_json::from_value(serde_json::json!({
+            "oidc": [{
+                "name": "keycloak",
+                "issuer_url": "https://sso.example.com/realms/netpoke",
+                "client_id": "netpoke",
+                "client_secret": "secret",
+                "redirect_url": "https://netpoke.example.com/auth/oidc/keycloak/callback",
+                "groups_claim": "realm_access.roles"
+            }]
+        }))
+        .unwrap();
+        assert_eq!(config.oidc.len(), 1);
+        let provider = &config.oidc[0];
+        assert_eq!(provider.name, "keycloak");
+        assert_eq!(provider.display_name, None);
+        assert_eq!(provider.scopes, vec!["openid", "profile", "email"]);
+        assert_eq!(provider.groups_claim.as_deref(), Some("realm_access.roles"));
+    }
+
+    #[test]
+    fn test_role_config() {
+        let config: RoleConfig = serde_json::from_value(serde_json::json!({
+            "default_role": "none",
+            "users": { "alice": ["analyst"], "plain:root": ["admin"] },
+            "groups": { "noc": ["operator"], "sre": ["admin"] }
+        }))
+        .unwrap();
+        assert_eq!(config.default_role, None);
+        assert!(config.roles_for("plain", "bob", &[]).is_empty());
+        assert_eq!(
+            config.roles_for("github", "alice", &[]),
+            vec![Role::Analyst]
+        );
+        assert_eq!(
+            config.roles_for("github", "alice", &["noc".to_string(), "other".to_string()]),
+            vec![Role::Analyst, Role::Operator]
+        );
+        // Provider-scoped assignments only match that provider
+        assert_eq!(config.roles_for("plain", "root", &[]), vec![Role::Admin]);
+        assert!(config.roles_for("github", "root", &[]).is_empty());
+        assert!(serde_json::from_value::<RoleConfig>(serde_json::json!({
+            "users": { "alice": ["root"] }
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn test_default_role_config() {
+        // Nobody is an administrator unless the configuration says so
+        let config = RoleConfig::default();
+        assert_eq!(
+            config.roles_for("plain", "someone", &[]),
+            vec![Role::Viewer]
+        );
+        assert_eq!(config.roles_for("github", "admin", &[]), vec![Role::Viewer]);
+        let config: RoleConfig = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert!(config.users.is_empty());
+    }
+
+    #[test]
+    fn test_session_config() {
+        // Existing configurations keep cookie-only sessions
+        let config: SessionConfig = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert!(!config.server_side);
+        assert!(!SessionConfig::default().server_side);
+
+        let config: SessionConfig =
+            serde_json::from_value(serde_json::json!({ "server_side": true })).unwrap();
+        assert!(config.server_side);
+    }
+
+    #[test]
+    fn test_totp_config() {
+        let config: PlainLoginConfig = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "totp": { "enabled": true, "required_for_role": "analyst" }
+        }))
+        .unwrap();
+        assert!(config.totp.enabled);
+        assert_eq!(config.totp.issuer, "NetPoke");
+        assert_eq!(config.totp.required_for_role, Some(Role::Analyst));
+        assert_eq!(PlainLoginConfig::default().totp.required_for_role, None);
+    }
+
     #[test]
     fn test_default_magic_key_config() {
         let config = MagicKeyConfig::default();
@@ -305,4 +752,25 @@ mod tests {
         // DEMO still has its built-in default
         assert_eq!(config.get_max_measuring_time_seconds("DEMO"), 120);
     }
+
+    #[test]
+    fn test_password_policy() {
+        let policy = PasswordPolicy::default();
+        assert!(policy.check("alice", "correct-horse-battery").is_ok());
+        assert!(policy.check("alice", "Short1!").is_err());
+        assert!(policy.check("alice", "alllowercaseletters").is_err());
+        assert!(policy.check("alice", &"a1".repeat(600)).is_err());
+        assert!(policy
+            .check("alice.example.org", "Alice.Example.Org")
+            .is_err());
+
+        let config: PlainLoginConfig = serde_json::from_value(serde_json::json!({
+            "reject_plaintext_passwords": true,
+            "password_policy": { "min_length": 8 }
+        }))
+        .unwrap();
+        assert!(config.reject_plaintext_passwords);
+        assert_eq!(config.password_policy.min_length, 8);
+        assert_eq!(config.password_policy.min_character_classes, 2);
+    }
 }
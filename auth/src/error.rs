@@ -44,6 +44,39 @@ pub enum AuthError {
 
     #[error("Access denied: User not in allowed list")]
     AccessDenied,
+
+    #[error("Invalid ID token: {0}")]
+    InvalidIdToken(String),
+
+    #[error("Session store error: {0}")]
+    SessionStoreError(String),
+
+    #[error("Invalid TOTP or recovery code")]
+    InvalidTotpCode,
+
+    #[error("TOTP already enrolled")]
+    TotpAlreadyEnrolled,
+
+    #[error("TOTP error: {0}")]
+    TotpError(String),
+
+    #[error("User store error: {0}")]
+    UserStoreError(String),
+
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+
+    #[error("{0}")]
+    PasswordPolicy(String),
+
+    #[error("Too many failed attempts, retry in {0} seconds")]
+    LockedOut(u64),
+
+    #[error("Login attempt store error: {0}")]
+    LoginAttemptStoreError(String),
+
+    #[error("Audit log error: {0}")]
+    AuditLogError(String),
 }
 
 impl From<AuthError> for StatusCode {
@@ -52,6 +85,12 @@ impl From<AuthError> for StatusCode {
             AuthError::InvalidHandleFormat => StatusCode::BAD_REQUEST,
             AuthError::AuthenticationRequired => StatusCode::UNAUTHORIZED,
             AuthError::AccessDenied => StatusCode::FORBIDDEN,
+            AuthError::InvalidIdToken(_) => StatusCode::UNAUTHORIZED,
+            AuthError::InvalidTotpCode => StatusCode::UNAUTHORIZED,
+            AuthError::TotpAlreadyEnrolled => StatusCode::CONFLICT,
+            AuthError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            AuthError::PasswordPolicy(_) => StatusCode::BAD_REQUEST,
+            AuthError::LockedOut(_) => StatusCode::TOO_MANY_REQUESTS,
             AuthError::SessionNotFound | AuthError::SessionExpired | AuthError::InvalidSession => {
                 StatusCode::UNAUTHORIZED
             }
@@ -62,7 +101,12 @@ impl From<AuthError> for StatusCode {
             | AuthError::JsonError(_)
             | AuthError::UrlError(_)
             | AuthError::OAuthError(_)
-            | AuthError::ConfigError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            | AuthError::ConfigError(_)
+            | AuthError::SessionStoreError(_)
+            | AuthError::TotpError(_)
+            | AuthError::UserStoreError(_)
+            | AuthError::LoginAttemptStoreError(_)
+            | AuthError::AuditLogError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
@@ -7,9 +7,16 @@
 //! # Features
 //!
 //! - OAuth2 authentication with multiple providers
-//! - Session management with configurable timeouts
+//! - Session management with configurable absolute and idle timeouts
+//! - Optional server-side session store for logout everywhere and revocation
 //! - Professional login page with "NetPoke" branding
 //! - Middleware for protecting routes
+//! - Role-based permissions per route group
+//! - Scoped personal API tokens for scripted access
+//! - Optional TOTP second factor for plain login users
+//! - Plain login users managed at runtime with argon2id password hashes
+//! - Brute-force protection with per-IP and per-account lockouts
+//! - Security audit log of logins and other security-relevant actions
 //! - Configurable via TOML configuration files
 //! - Designed for easy portability to other projects
 //!
@@ -28,13 +35,21 @@
 //! }
 //! ```
 
+pub mod api_tokens;
+pub mod audit;
 pub mod config;
 pub mod error;
+pub mod local_users;
+pub mod lockout;
 pub mod middleware;
+pub mod passwords;
 pub mod providers;
+pub mod roles;
 pub mod routes;
 pub mod service;
 pub mod session;
+pub mod session_store;
+pub mod totp;
 pub mod views;
 
 use axum::extract::FromRef;
@@ -43,12 +58,19 @@ use std::ops::Deref;
 use std::sync::Arc;
 
 // Re-export commonly used types
+pub use api_tokens::{ApiTokenIdentity, ApiTokenStore};
+pub use audit::{AuditEvent, AuditLog, AuditOutcome};
 pub use config::AuthConfig;
 pub use error::AuthError;
-pub use middleware::{optional_auth, require_auth};
+pub use local_users::{LocalUser, LocalUserStore};
+pub use lockout::LoginAttemptStore;
+pub use middleware::{optional_auth, require_auth, require_permission};
+pub use roles::{Permission, Role, RoleStore};
 pub use routes::auth_routes;
 pub use service::AuthService;
-pub use session::{AuthProvider, OAuthTempState, SessionData};
+pub use session::{AuthProvider, OAuthTempState, SessionData, SurveySessionData};
+pub use session_store::{ClientInfo, SessionStore};
+pub use totp::{TotpEnrollment, TotpStore};
 
 /// State wrapper for AuthService that implements FromRef for Key
 /// This allows PrivateCookieJar to extract the cookie key from state
@@ -64,6 +86,14 @@ impl AuthState {
         }
     }
 
+    /// State for `require_permission` protecting a route group with a permission
+    pub fn with_permission(&self, permission: Permission) -> PermissionState {
+        PermissionState {
+            auth_state: self.clone(),
+            permission,
+        }
+    }
+
     /// Get the inner Arc<AuthService>
     pub fn into_inner(self) -> Arc<AuthService> {
         self.inner
@@ -96,3 +126,18 @@ impl FromRef<AuthState> for Key {
         state.cookie_key()
     }
 }
+
+/// State for `require_permission`: the auth state plus the permission the
+/// protected route group requires
+#[derive(Clone)]
+pub struct PermissionState {
+    pub auth_state: AuthState,
+    pub permission: Permission,
+}
+
+/// Implement FromRef to allow PrivateCookieJar to extract Key from PermissionState
+impl FromRef<PermissionState> for Key {
+    fn from_ref(state: &PermissionState) -> Self {
+        state.auth_state.cookie_key()
+    }
+}
@@ -0,0 +1,37 @@
+//! Local users managed outside the configuration file
+//!
+//! Plain login users can be kept in a [`LocalUserStore`] (such as a database
+//! table) instead of `[[auth.plain_login.users]]`, so admins can create,
+//! disable and reset them at runtime and users can change their own password.
+//! Users in the store take precedence over configured users of the same name.
+
+use crate::error::AuthError;
+use std::future::Future;
+use std::pin::Pin;
+
+/// A plain login user kept in a [`LocalUserStore`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalUser {
+    pub username: String,
+    /// argon2id hash (bcrypt hashes are accepted and rehashed at login)
+    pub password_hash: String,
+    pub display_name: Option<String>,
+    /// Disabled users cannot log in
+    pub disabled: bool,
+}
+
+/// Future returned by [`LocalUserStore`] methods
+pub type LocalUserFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, AuthError>> + Send + 'a>>;
+
+/// Store of local users
+pub trait LocalUserStore: Send + Sync {
+    /// Look up a user
+    fn user<'a>(&'a self, username: &'a str) -> LocalUserFuture<'a, Option<LocalUser>>;
+
+    /// Replace the password hash of a user; returns false if there is no such user
+    fn set_password_hash<'a>(
+        &'a self,
+        username: &'a str,
+        password_hash: &'a str,
+    ) -> LocalUserFuture<'a, bool>;
+}
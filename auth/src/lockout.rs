@@ -0,0 +1,217 @@
+//! Brute-force protection for logins
+//!
+//! Failed attempts are counted per client IP and per account. Once a key
+//! reaches its threshold, every further failure locks it out for a time that
+//! doubles with each failure, up to a maximum. Failures are forgotten once a
+//! key has been quiet for the configured window. Records live in a
+//! [`LoginAttemptStore`]: in memory by default, or shared (e.g. in a database
+//! table) so they survive restarts and apply across workers.
+
+use crate::config::LockoutConfig;
+use crate::error::AuthError;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+/// Failed attempts of one key (an IP or an account)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AttemptRecord {
+    /// Consecutive failures
+    pub failures: u32,
+    /// Time of the last failure (Unix seconds)
+    pub last_failure_at: u64,
+    /// End of the current or last lockout (Unix seconds)
+    pub locked_until: Option<u64>,
+}
+
+impl AttemptRecord {
+    /// Seconds until the lockout ends, if the key is locked out
+    pub fn retry_after(&self, now: u64) -> Option<u64> {
+        self.locked_until
+            .filter(|&until| until > now)
+            .map(|until| until - now)
+    }
+
+    /// Last time the key failed or was locked out
+    pub fn last_active(&self) -> u64 {
+        self.locked_until.unwrap_or(0).max(self.last_failure_at)
+    }
+
+    /// Record after one more failure, given the threshold for this kind of key
+    pub fn after_failure(
+        previous: Option<AttemptRecord>,
+        now: u64,
+        max_failures: u32,
+        config: &LockoutConfig,
+    ) -> AttemptRecord {
+        let mut record = previous
+            .filter(|record| now.saturating_sub(record.last_active()) <= config.window_seconds)
+            .unwrap_or_default();
+        record.failures = record.failures.saturating_add(1);
+        record.last_failure_at = now;
+        if record.failures >= max_failures {
+            let doublings = (record.failures - max_failures).min(31);
+            let duration = config
+                .lockout_seconds
+                .saturating_mul(1 << doublings)
+                .min(config.max_lockout_seconds);
+            record.locked_until = Some(now + duration);
+        }
+        record
+    }
+}
+
+/// Update applied atomically by [`LoginAttemptStore::update`]
+pub type AttemptUpdate<'a> = dyn Fn(Option<AttemptRecord>) -> AttemptRecord + Send + Sync + 'a;
+
+/// Future returned by [`LoginAttemptStore`] methods
+pub type LoginAttemptFuture<'a, T> =
+    Pin<Box<dyn Future<Output = Result<T, AuthError>> + Send + 'a>>;
+
+/// Store of failed login attempts
+pub trait LoginAttemptStore: Send + Sync {
+    /// Get the record of a key
+    fn get<'a>(&'a self, key: &'a str) -> LoginAttemptFuture<'a, Option<AttemptRecord>>;
+
+    /// Atomically replace the record of a key, returning the new record
+    fn update<'a>(
+        &'a self,
+        key: &'a str,
+        update: &'a AttemptUpdate<'a>,
+    ) -> LoginAttemptFuture<'a, AttemptRecord>;
+
+    /// Remove the record of a key
+    fn clear<'a>(&'a self, key: &'a str) -> LoginAttemptFuture<'a, ()>;
+
+    /// Remove the records of keys last active before a time (Unix seconds)
+    fn prune<'a>(&'a self, before: u64) -> LoginAttemptFuture<'a, ()>;
+
+    /// Keep a lasting record of a lockout (the default only logs it)
+    fn record_lockout<'a>(
+        &'a self,
+        _key: &'a str,
+        _record: &'a AttemptRecord,
+    ) -> LoginAttemptFuture<'a, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// In-memory store, used when no shared store is set
+#[derive(Default)]
+pub struct MemoryLoginAttemptStore {
+    records: Mutex<HashMap<String, AttemptRecord>>,
+}
+
+impl LoginAttemptStore for MemoryLoginAttemptStore {
+    fn get<'a>(&'a self, key: &'a str) -> LoginAttemptFuture<'a, Option<AttemptRecord>> {
+        let record = self.records.lock().unwrap().get(key).copied();
+        Box::pin(async move { Ok(record) })
+    }
+
+    fn update<'a>(
+        &'a self,
+        key: &'a str,
+        update: &'a AttemptUpdate<'a>,
+    ) -> LoginAttemptFuture<'a, AttemptRecord> {
+        let mut records = self.records.lock().unwrap();
+        let record = update(records.get(key).copied());
+        records.insert(key.to_string(), record);
+        Box::pin(async move { Ok(record) })
+    }
+
+    fn clear<'a>(&'a self, key: &'a str) -> LoginAttemptFuture<'a, ()> {
+        self.records.lock().unwrap().remove(key);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn prune<'a>(&'a self, before: u64) -> LoginAttemptFuture<'a, ()> {
+        self.records
+            .lock()
+            .unwrap()
+            .retain(|_, record| record.last_active() >= before);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Key of the attempts from a client IP
+pub fn ip_key(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+/// Key of the attempts on a plain login account
+pub fn account_key(username: &str) -> String {
+    format!("account:{}", username)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LockoutConfig {
+        LockoutConfig {
+            window_seconds: 900,
+            lockout_seconds: 60,
+            max_lockout_seconds: 300,
+            ..LockoutConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_exponential_lockout() {
+        let config = config();
+        let mut record = None;
+        for now in 1000..1002 {
+            record = Some(AttemptRecord::after_failure(record, now, 3, &config));
+            assert_eq!(record.unwrap().retry_after(now), None);
+        }
+
+        // The third failure locks the key out, each further one for twice as long
+        let third = AttemptRecord::after_failure(record, 1002, 3, &config);
+        assert_eq!(third.retry_after(1002), Some(60));
+        let fourth = AttemptRecord::after_failure(Some(third), 1062, 3, &config);
+        assert_eq!(fourth.retry_after(1062), Some(120));
+        let fifth = AttemptRecord::after_failure(Some(fourth), 1182, 3, &config);
+        assert_eq!(fifth.retry_after(1182), Some(240));
+        let sixth = AttemptRecord::after_failure(Some(fifth), 1422, 3, &config);
+        assert_eq!(sixth.retry_after(1422), Some(300));
+        assert_eq!(sixth.retry_after(1722), None);
+    }
+
+    #[test]
+    fn test_failures_are_forgotten_after_window() {
+        let config = config();
+        let locked = AttemptRecord {
+            failures: 3,
+            last_failure_at: 1000,
+            locked_until: Some(1060),
+        };
+        // Within the window after the lockout ended, failures keep counting
+        let next = AttemptRecord::after_failure(Some(locked), 1900, 3, &config);
+        assert_eq!(next.failures, 4);
+        // After it, they start over
+        let next = AttemptRecord::after_failure(Some(locked), 2000, 3, &config);
+        assert_eq!(next.failures, 1);
+        assert_eq!(next.retry_after(2000), None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = MemoryLoginAttemptStore::default();
+        let config = config();
+        let update = |record| AttemptRecord::after_failure(record, 1000, 1, &config);
+        let record = store.update("ip:192.0.2.1", &update).await.unwrap();
+        assert_eq!(record.retry_after(1000), Some(60));
+        assert_eq!(store.get("ip:192.0.2.1").await.unwrap(), Some(record));
+
+        store.prune(1060).await.unwrap();
+        assert!(store.get("ip:192.0.2.1").await.unwrap().is_some());
+        store.prune(1061).await.unwrap();
+        assert!(store.get("ip:192.0.2.1").await.unwrap().is_none());
+
+        store.update("account:alice", &update).await.unwrap();
+        store.clear("account:alice").await.unwrap();
+        assert!(store.get("account:alice").await.unwrap().is_none());
+    }
+}
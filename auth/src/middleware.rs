@@ -6,9 +6,12 @@ use axum::{
 };
 use axum_extra::extract::cookie::PrivateCookieJar;
 
+use crate::audit::{AuditEvent, AuditOutcome};
+use crate::roles::Permission;
 use crate::session::SessionData;
+use crate::session_store::ClientInfo;
 use crate::views::access_denied_page_html;
-use crate::AuthState;
+use crate::{AuthState, PermissionState};
 
 /// Extract session data from PrivateCookieJar
 fn extract_session_from_jar(jar: &PrivateCookieJar, cookie_name: &str) -> Option<SessionData> {
@@ -21,6 +24,25 @@ pub async fn require_auth(
     State(auth_state): State<AuthState>,
     request: Request,
     next: Next,
+) -> Response {
+    authorize(&auth_state, None, request, next).await
+}
+
+/// Middleware to require authentication and the permission declared by the route group
+pub async fn require_permission(
+    State(state): State<PermissionState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    authorize(&state.auth_state, Some(state.permission), request, next).await
+}
+
+/// Check the session, the allowed users list and, if given, the permission
+async fn authorize(
+    auth_state: &AuthState,
+    permission: Option<Permission>,
+    request: Request,
+    next: Next,
 ) -> Response {
     // Skip authentication if disabled
     if !auth_state.is_enabled() {
@@ -29,7 +51,7 @@ pub async fn require_auth(
 
     // Extract PrivateCookieJar from request
     let (mut parts, body) = request.into_parts();
-    let jar = match PrivateCookieJar::from_request_parts(&mut parts, &auth_state).await {
+    let jar = match PrivateCookieJar::from_request_parts(&mut parts, auth_state).await {
         Ok(jar) => jar,
         Err(_) => {
             return Redirect::to("/auth/login").into_response();
@@ -40,15 +62,53 @@ pub async fn require_auth(
     if let Some(session_data) =
         extract_session_from_jar(&jar, &auth_state.config.session.cookie_name)
     {
-        // Validate session (check expiration)
-        if auth_state.validate_session(&session_data).is_ok() {
+        // Validate session (expiration, and revocation with a session store)
+        let client = ClientInfo::from_parts(&parts);
+        if auth_state
+            .verify_session(&session_data, &client)
+            .await
+            .is_ok()
+        {
             // Check if user is in allowed list
             if !auth_state.is_user_allowed(&session_data.handle) {
                 tracing::warn!("Access denied for user: {}", session_data.handle);
+                auth_state
+                    .audit(
+                        AuditEvent::new("access", AuditOutcome::Denied)
+                            .session(&session_data)
+                            .ip(client.ip)
+                            .target(format!("{} {}", parts.method, parts.uri.path()))
+                            .detail("not in allowed users"),
+                    )
+                    .await;
                 let html = access_denied_page_html(&session_data.handle);
                 return (StatusCode::FORBIDDEN, Html(html)).into_response();
             }
 
+            // Check the role of the user against the route's permission
+            if let Some(permission) = permission {
+                if !auth_state.has_permission(&session_data, permission).await {
+                    tracing::warn!(
+                        "Permission {:?} denied for user: {} ({} {})",
+                        permission,
+                        session_data.handle,
+                        parts.method,
+                        parts.uri.path()
+                    );
+                    auth_state
+                        .audit(
+                            AuditEvent::new("access", AuditOutcome::Denied)
+                                .session(&session_data)
+                                .ip(client.ip)
+                                .target(format!("{} {}", parts.method, parts.uri.path()))
+                                .detail(format!("missing permission {}", permission.as_str())),
+                        )
+                        .await;
+                    let html = access_denied_page_html(&session_data.handle);
+                    return (StatusCode::FORBIDDEN, Html(html)).into_response();
+                }
+            }
+
             // Session is valid and user is allowed, continue
             let mut request = Request::from_parts(parts, body);
             request.extensions_mut().insert(session_data);
@@ -56,6 +116,21 @@ pub async fn require_auth(
         }
     }
 
+    // Scripts authenticate with an API token instead of a session cookie
+    if let Some(result) = auth_state
+        .authorize_api_token(&parts.headers, permission)
+        .await
+    {
+        return match result {
+            Ok(session_data) => {
+                let mut request = Request::from_parts(parts, body);
+                request.extensions_mut().insert(session_data);
+                next.run(request).await
+            }
+            Err(e) => StatusCode::from(e).into_response(),
+        };
+    }
+
     // No valid session, redirect to login
     Redirect::to("/auth/login").into_response()
 }
@@ -82,12 +157,17 @@ pub async fn optional_auth(
     };
 
     // Try to extract session from private cookie
+    let client = ClientInfo::from_parts(&parts);
     let mut request = Request::from_parts(parts, body);
     if let Some(session_data) =
         extract_session_from_jar(&jar, &auth_state.config.session.cookie_name)
     {
-        // Validate session (check expiration)
-        if auth_state.validate_session(&session_data).is_ok() {
+        // Validate session (expiration, and revocation with a session store)
+        if auth_state
+            .verify_session(&session_data, &client)
+            .await
+            .is_ok()
+        {
             // Store session data in request extensions for handlers to use
             request.extensions_mut().insert(session_data);
         }
@@ -0,0 +1,139 @@
+//! Password hashing for plain login users
+//!
+//! New passwords are hashed with argon2id. Existing bcrypt hashes (and, unless
+//! refused by configuration, plain text passwords) are still verified, and
+//! reported as outdated so the caller can transparently rehash them.
+
+use crate::error::AuthError;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+/// Length of generated passwords
+const GENERATED_PASSWORD_LENGTH: usize = 20;
+
+/// Result of checking a password against a stored hash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordVerification {
+    /// The password does not match
+    Invalid,
+    /// The password matches an argon2id hash
+    Valid,
+    /// The password matches, but the hash is bcrypt, an older argon2 variant or
+    /// plain text and should be replaced by an argon2id hash
+    Outdated,
+}
+
+/// Hash a password with argon2id
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AuthError::ConfigError(format!("Failed to hash password: {}", e)))
+}
+
+/// Check whether a stored password is plain text rather than a hash
+pub fn is_plaintext(stored: &str) -> bool {
+    !stored.starts_with("$argon2") && !stored.starts_with("$2")
+}
+
+/// Verify a password against a stored argon2 or bcrypt hash, or a plain text
+/// password if `allow_plaintext` is set
+pub fn verify_password(
+    password: &str,
+    stored: &str,
+    allow_plaintext: bool,
+) -> Result<PasswordVerification, AuthError> {
+    let matches = if stored.starts_with("$argon2") {
+        let hash = PasswordHash::new(stored)
+            .map_err(|e| AuthError::ConfigError(format!("Invalid argon2 hash: {}", e)))?;
+        let valid = Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok();
+        if valid && hash.algorithm != argon2::Algorithm::Argon2id.ident() {
+            return Ok(PasswordVerification::Outdated);
+        }
+        return Ok(if valid {
+            PasswordVerification::Valid
+        } else {
+            PasswordVerification::Invalid
+        });
+    } else if stored.starts_with("$2") {
+        bcrypt::verify(password, stored)
+            .map_err(|e| AuthError::OAuthError(format!("Password verification failed: {}", e)))?
+    } else if allow_plaintext {
+        password == stored
+    } else {
+        tracing::warn!("Plain text password rejected by configuration");
+        false
+    };
+
+    Ok(if matches {
+        PasswordVerification::Outdated
+    } else {
+        PasswordVerification::Invalid
+    })
+}
+
+/// Generate a random password, e.g. for a new or reset user
+pub fn generate_password() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(GENERATED_PASSWORD_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_argon2id_hash() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(!is_plaintext(&hash));
+        assert_eq!(
+            verify_password("correct horse", &hash, false).unwrap(),
+            PasswordVerification::Valid
+        );
+        assert_eq!(
+            verify_password("wrong horse", &hash, false).unwrap(),
+            PasswordVerification::Invalid
+        );
+    }
+
+    #[test]
+    fn test_bcrypt_and_plaintext_are_outdated() {
+        let bcrypt_hash = bcrypt::hash("secret", 4).unwrap();
+        assert_eq!(
+            verify_password("secret", &bcrypt_hash, false).unwrap(),
+            PasswordVerification::Outdated
+        );
+        assert_eq!(
+            verify_password("other", &bcrypt_hash, false).unwrap(),
+            PasswordVerification::Invalid
+        );
+
+        assert!(is_plaintext("secret"));
+        assert_eq!(
+            verify_password("secret", "secret", true).unwrap(),
+            PasswordVerification::Outdated
+        );
+        // Refused by configuration
+        assert_eq!(
+            verify_password("secret", "secret", false).unwrap(),
+            PasswordVerification::Invalid
+        );
+    }
+
+    #[test]
+    fn test_generate_password() {
+        let password = generate_password();
+        assert_eq!(password.len(), GENERATED_PASSWORD_LENGTH);
+        assert_ne!(password, generate_password());
+    }
+}
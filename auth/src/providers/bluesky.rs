@@ -118,6 +118,7 @@ impl BlueskyProvider {
             pkce_verifier: Some(pkce_verifier.secret().clone()),
             oauth_endpoints: Some(oauth_endpoints),
             dpop_private_key: Some(dpop_private_key),
+            nonce: None,
             created_at: now,
         };
 
@@ -246,6 +247,7 @@ impl BlueskyProvider {
             display_name,
             groups: vec![],
             created_at: now,
+            session_id: None,
         })
     }
 
@@ -81,6 +81,7 @@ impl GitHubProvider {
             pkce_verifier: Some(pkce_verifier.secret().clone()),
             oauth_endpoints: None,
             dpop_private_key: None,
+            nonce: None,
             created_at: now,
         };
 
@@ -141,6 +142,7 @@ impl GitHubProvider {
             display_name: user_info.name,
             groups: vec![],
             created_at: now,
+            session_id: None,
         })
     }
 }
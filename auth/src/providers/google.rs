@@ -81,6 +81,7 @@ impl GoogleProvider {
             pkce_verifier: Some(pkce_verifier.secret().clone()),
             oauth_endpoints: None,
             dpop_private_key: None,
+            nonce: None,
             created_at: now,
         };
 
@@ -140,6 +141,7 @@ impl GoogleProvider {
             display_name: user_info.name,
             groups: vec![],
             created_at: now,
+            session_id: None,
         })
     }
 }
@@ -78,6 +78,7 @@ impl LinkedInProvider {
             pkce_verifier: None,
             oauth_endpoints: None,
             dpop_private_key: None,
+            nonce: None,
             created_at: now,
         };
 
@@ -146,6 +147,7 @@ impl LinkedInProvider {
             display_name: user_info.name,
             groups: vec![],
             created_at: now,
+            session_id: None,
         })
     }
 }
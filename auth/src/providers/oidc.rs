@@ -0,0 +1,495 @@
+use crate::config::OidcProviderConfig;
+use crate::error::AuthError;
+use crate::session::{AuthProvider, OAuthTempState, SessionData};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use oauth2::basic::{
+    BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse,
+    BasicTokenType,
+};
+use oauth2::{
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, ExtraTokenFields,
+    PkceCodeChallenge, RedirectUrl, Scope, StandardRevocableToken, StandardTokenResponse,
+    TokenResponse, TokenUrl,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// How long discovered provider metadata and keys are reused before refetching
+const DISCOVERY_CACHE_SECS: u64 = 3600;
+
+/// Allowed clock skew in seconds when checking ID token expiry
+const ID_TOKEN_LEEWAY_SECS: u64 = 60;
+
+/// Provider metadata from `.well-known/openid-configuration`
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    userinfo_endpoint: Option<String>,
+}
+
+/// Discovered metadata and signing keys
+struct Discovery {
+    metadata: ProviderMetadata,
+    jwks: JwkSet,
+    fetched_at: Instant,
+}
+
+/// Token response fields beyond the OAuth2 standard ones
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IdTokenFields {
+    id_token: Option<String>,
+}
+
+impl ExtraTokenFields for IdTokenFields {}
+
+type OidcClient = oauth2::Client<
+    BasicErrorResponse,
+    StandardTokenResponse<IdTokenFields, BasicTokenType>,
+    BasicTokenType,
+    BasicTokenIntrospectionResponse,
+    StandardRevocableToken,
+    BasicRevocationErrorResponse,
+>;
+
+/// Generic OpenID Connect provider configured by issuer URL
+pub struct OidcProvider {
+    config: OidcProviderConfig,
+    discovery: RwLock<Option<Discovery>>,
+}
+
+impl OidcProvider {
+    pub fn new(config: &OidcProviderConfig) -> Result<Self, AuthError> {
+        if config.name.is_empty()
+            || !config
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(AuthError::ConfigError(format!(
+                "OIDC provider name '{}' must be non-empty and contain only letters, digits, '-' and '_'",
+                config.name
+            )));
+        }
+        // Role assignments are keyed by provider name, so an OIDC provider must
+        // not pass itself off as a built-in one
+        if AuthProvider::RESERVED_NAMES.contains(&config.name.as_str()) {
+            return Err(AuthError::ConfigError(format!(
+                "OIDC provider name '{}' is reserved for a built-in provider",
+                config.name
+            )));
+        }
+        url::Url::parse(&config.issuer_url)?;
+        RedirectUrl::new(config.redirect_url.clone())?;
+
+        Ok(Self {
+            config: config.clone(),
+            discovery: RwLock::new(None),
+        })
+    }
+
+    /// Name used in the login and callback URLs
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Label shown on the login page
+    pub fn display_name(&self) -> &str {
+        self.config
+            .display_name
+            .as_deref()
+            .unwrap_or(&self.config.name)
+    }
+
+    pub async fn start_auth(&self) -> Result<(String, OAuthTempState), AuthError> {
+        let metadata = self.metadata().await?;
+        let client = self.client(&metadata)?;
+
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let csrf_state = CsrfToken::new_random();
+        let nonce = CsrfToken::new_random().secret().clone();
+
+        let mut request = client
+            .authorize_url(|| csrf_state.clone())
+            .add_scope(Scope::new("openid".to_string()));
+        for scope in self.config.scopes.iter().filter(|s| *s != "openid") {
+            request = request.add_scope(Scope::new(scope.clone()));
+        }
+        let (auth_url, _) = request
+            .add_extra_param("nonce", nonce.clone())
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let temp_state = OAuthTempState {
+            auth_provider: AuthProvider::Oidc(self.config.name.clone()),
+            handle: None,
+            user_did: None,
+            pkce_verifier: Some(pkce_verifier.secret().clone()),
+            oauth_endpoints: None,
+            dpop_private_key: None,
+            nonce: Some(nonce),
+            created_at: now,
+        };
+
+        Ok((auth_url.to_string(), temp_state))
+    }
+
+    pub async fn complete_auth(
+        &self,
+        code: &str,
+        temp_state: &OAuthTempState,
+    ) -> Result<SessionData, AuthError> {
+        if temp_state.auth_provider != AuthProvider::Oidc(self.config.name.clone()) {
+            return Err(AuthError::InvalidSession);
+        }
+
+        let metadata = self.metadata().await?;
+        let client = self.client(&metadata)?;
+
+        let mut token_request = client.exchange_code(AuthorizationCode::new(code.to_string()));
+        if let Some(verifier) = &temp_state.pkce_verifier {
+            token_request =
+                token_request.set_pkce_verifier(oauth2::PkceCodeVerifier::new(verifier.clone()));
+        }
+
+        let token_result = token_request
+            .request_async(oauth2::reqwest::async_http_client)
+            .await
+            .map_err(|e| {
+                AuthError::OAuthError(format!(
+                    "OIDC token exchange with {} failed: {}",
+                    self.config.name, e
+                ))
+            })?;
+
+        let id_token = token_result
+            .extra_fields()
+            .id_token
+            .as_deref()
+            .ok_or_else(|| {
+                AuthError::InvalidIdToken("Token response contains no ID token".to_string())
+            })?;
+        let mut claims = self
+            .validate_id_token(id_token, temp_state.nonce.as_deref())
+            .await?;
+
+        // Some providers only put the group claim in the userinfo response
+        let groups_missing = self
+            .config
+            .groups_claim
+            .as_deref()
+            .is_some_and(|claim| claim_value(&claims, claim).is_none());
+        if groups_missing {
+            if let Some(userinfo_endpoint) = &metadata.userinfo_endpoint {
+                let userinfo = reqwest::Client::new()
+                    .get(userinfo_endpoint)
+                    .bearer_auth(token_result.access_token().secret())
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<HashMap<String, Value>>()
+                    .await?;
+                // The userinfo response must be about the same user
+                if userinfo.get("sub") == claims.get("sub") {
+                    for (name, value) in userinfo {
+                        claims.entry(name).or_insert(value);
+                    }
+                }
+            }
+        }
+
+        let sub = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or_else(|| AuthError::InvalidIdToken("Missing sub claim".to_string()))?
+            .to_string();
+        let string_claim =
+            |name: &str| claims.get(name).and_then(Value::as_str).map(str::to_string);
+
+        let groups = self
+            .config
+            .groups_claim
+            .as_deref()
+            .map(|claim| groups_from_claim(&claims, claim))
+            .unwrap_or_default();
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        Ok(SessionData {
+            auth_provider: AuthProvider::Oidc(self.config.name.clone()),
+            user_id: format!("oidc:{}:{}", self.config.name, sub),
+            handle: string_claim("preferred_username")
+                .or_else(|| string_claim("email"))
+                .unwrap_or_else(|| sub.clone()),
+            display_name: string_claim("name"),
+            groups,
+            created_at: now,
+            session_id: None,
+        })
+    }
+
+    fn client(&self, metadata: &ProviderMetadata) -> Result<OidcClient, AuthError> {
+        Ok(OidcClient::new(
+            ClientId::new(self.config.client_id.clone()),
+            self.config.client_secret.clone().map(ClientSecret::new),
+            AuthUrl::new(metadata.authorization_endpoint.clone())?,
+            Some(TokenUrl::new(metadata.token_endpoint.clone())?),
+        )
+        .set_redirect_uri(RedirectUrl::new(self.config.redirect_url.clone())?))
+    }
+
+    /// Get the provider metadata, discovering it if not cached
+    async fn metadata(&self) -> Result<ProviderMetadata, AuthError> {
+        if let Some(discovery) = self.discovery.read().await.as_ref() {
+            if discovery.fetched_at.elapsed() < Duration::from_secs(DISCOVERY_CACHE_SECS) {
+                return Ok(discovery.metadata.clone());
+            }
+        }
+        self.discover().await?;
+        Ok(self
+            .discovery
+            .read()
+            .await
+            .as_ref()
+            .map(|d| d.metadata.clone())
+            .expect("discovery was just stored"))
+    }
+
+    /// Fetch the provider metadata and signing keys
+    async fn discover(&self) -> Result<(), AuthError> {
+        let issuer = self.config.issuer_url.trim_end_matches('/');
+        let discovery_url = format!("{}/.well-known/openid-configuration", issuer);
+        tracing::info!(
+            "OIDC provider {}: discovering metadata from {}",
+            self.config.name,
+            discovery_url
+        );
+
+        let client = reqwest::Client::new();
+        let metadata = client
+            .get(&discovery_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ProviderMetadata>()
+            .await?;
+
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            return Err(AuthError::ServiceMetadataError(format!(
+                "OIDC discovery issuer {} does not match configured issuer {}",
+                metadata.issuer, self.config.issuer_url
+            )));
+        }
+
+        let jwks = client
+            .get(&metadata.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<JwkSet>()
+            .await?;
+
+        *self.discovery.write().await = Some(Discovery {
+            metadata,
+            jwks,
+            fetched_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Validate an ID token's signature, issuer, audience, expiry and nonce,
+    /// returning its claims
+    async fn validate_id_token(
+        &self,
+        id_token: &str,
+        expected_nonce: Option<&str>,
+    ) -> Result<HashMap<String, Value>, AuthError> {
+        let header = jsonwebtoken::decode_header(id_token)
+            .map_err(|e| AuthError::InvalidIdToken(format!("Malformed header: {}", e)))?;
+
+        // Only asymmetric signatures can be checked against the JWKS
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(AuthError::InvalidIdToken(format!(
+                "Unsupported signing algorithm {:?}",
+                header.alg
+            )));
+        }
+
+        let mut key = self.find_key(header.kid.as_deref()).await;
+        if key.is_none() {
+            // The provider may have rotated its keys since discovery
+            self.discover().await?;
+            key = self.find_key(header.kid.as_deref()).await;
+        }
+        let key = key.ok_or_else(|| {
+            AuthError::InvalidIdToken(format!("No signing key found for kid {:?}", header.kid))
+        })??;
+
+        let issuer = self
+            .discovery
+            .read()
+            .await
+            .as_ref()
+            .map(|d| d.metadata.issuer.clone())
+            .unwrap_or_else(|| self.config.issuer_url.clone());
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.leeway = ID_TOKEN_LEEWAY_SECS;
+
+        let claims = jsonwebtoken::decode::<HashMap<String, Value>>(id_token, &key, &validation)
+            .map_err(|e| AuthError::InvalidIdToken(e.to_string()))?
+            .claims;
+
+        if let Some(expected_nonce) = expected_nonce {
+            if claims.get("nonce").and_then(Value::as_str) != Some(expected_nonce) {
+                return Err(AuthError::InvalidIdToken("Nonce mismatch".to_string()));
+            }
+        }
+
+        Ok(claims)
+    }
+
+    /// Look up the decoding key for a key ID in the cached JWKS
+    async fn find_key(&self, kid: Option<&str>) -> Option<Result<DecodingKey, AuthError>> {
+        let discovery = self.discovery.read().await;
+        let jwks = &discovery.as_ref()?.jwks;
+        let jwk = match kid {
+            Some(kid) => jwks.find(kid),
+            // Without a key ID the token can only be matched to a lone key
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }?;
+        Some(
+            DecodingKey::from_jwk(jwk)
+                .map_err(|e| AuthError::InvalidIdToken(format!("Unusable signing key: {}", e))),
+        )
+    }
+}
+
+/// Look up a claim by name or dotted path (e.g. "realm_access.roles")
+fn claim_value<'a>(claims: &'a HashMap<String, Value>, path: &str) -> Option<&'a Value> {
+    // A claim whose name contains dots takes precedence over a nested lookup
+    if let Some(value) = claims.get(path) {
+        return Some(value);
+    }
+    let mut parts = path.split('.');
+    let mut value = claims.get(parts.next()?)?;
+    for part in parts {
+        value = value.get(part)?;
+    }
+    Some(value)
+}
+
+/// Map a claim to session groups: an array of strings, or a single string
+fn groups_from_claim(claims: &HashMap<String, Value>, path: &str) -> Vec<String> {
+    match claim_value(claims, path) {
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        Some(Value::String(value)) => vec![value.clone()],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn claims(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_reserved_provider_names_rejected() {
+        let config = |name: &str| OidcProviderConfig {
+            name: name.to_string(),
+            display_name: None,
+            issuer_url: "https://idp.example.com".to_string(),
+            client_id: "netpoke".to_string(),
+            client_secret: None,
+            redirect_url: format!("https://netpoke.example.com/auth/oidc/{}/callback", name),
+            scopes: Vec::new(),
+            groups_claim: None,
+        };
+        assert!(OidcProvider::new(&config("keycloak")).is_ok());
+        for name in AuthProvider::RESERVED_NAMES {
+            assert!(matches!(
+                OidcProvider::new(&config(name)),
+                Err(AuthError::ConfigError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_groups_from_array_claim() {
+        let claims = claims(json!({"sub": "1", "groups": ["admins", "analysts", 7]}));
+        assert_eq!(
+            groups_from_claim(&claims, "groups"),
+            vec!["admins".to_string(), "analysts".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_groups_from_nested_claim() {
+        // Keycloak puts realm roles under realm_access.roles
+        let claims = claims(json!({"sub": "1", "realm_access": {"roles": ["analyst"]}}));
+        assert_eq!(
+            groups_from_claim(&claims, "realm_access.roles"),
+            vec!["analyst".to_string()]
+        );
+        assert!(groups_from_claim(&claims, "realm_access.missing").is_empty());
+    }
+
+    #[test]
+    fn test_groups_from_string_and_dotted_name() {
+        let claims = claims(json!({"sub": "1", "https://example.com/role": "admin"}));
+        assert_eq!(
+            groups_from_claim(&claims, "https://example.com/role"),
+            vec!["admin".to_string()]
+        );
+        assert!(groups_from_claim(&claims, "groups").is_empty());
+    }
+
+    #[test]
+    fn test_provider_name_validation() {
+        let mut config = OidcProviderConfig {
+            name: "entra-id".to_string(),
+            display_name: Some("Entra ID".to_string()),
+            issuer_url: "https://login.microsoftonline.com/tenant/v2.0".to_string(),
+            client_id: "client".to_string(),
+            client_secret: None,
+            redirect_url: "https://netpoke.example.com/auth/oidc/entra-id/callback".to_string(),
+            scopes: vec!["openid".to_string()],
+            groups_claim: Some("groups".to_string()),
+        };
+        let provider = OidcProvider::new(&config).unwrap();
+        assert_eq!(provider.name(), "entra-id");
+        assert_eq!(provider.display_name(), "Entra ID");
+
+        config.name = "entra/id".to_string();
+        assert!(OidcProvider::new(&config).is_err());
+    }
+}
@@ -1,20 +1,42 @@
 use crate::config::{PlainLoginConfig, UserCredentials};
 use crate::error::AuthError;
+use crate::local_users::LocalUserStore;
+use crate::passwords::{self, PasswordVerification};
 use crate::session::{AuthProvider, SessionData};
-use bcrypt;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Plain login (username/password) provider
 pub struct PlainLoginProvider {
     users: HashMap<String, UserCredentials>,
+    allow_plaintext: bool,
 }
 
 impl PlainLoginProvider {
     pub fn new(config: &PlainLoginConfig) -> Result<Self, AuthError> {
         if config.users.is_empty() {
-            return Err(AuthError::ConfigError(
-                "Plain login enabled but no users configured".to_string(),
-            ));
+            tracing::warn!(
+                "Plain login enabled without configured users; only users from the local user store can log in"
+            );
+        }
+
+        let plaintext_users: Vec<&str> = config
+            .users
+            .iter()
+            .filter(|user| passwords::is_plaintext(&user.password))
+            .map(|user| user.username.as_str())
+            .collect();
+        if !plaintext_users.is_empty() {
+            if config.reject_plaintext_passwords {
+                return Err(AuthError::ConfigError(format!(
+                    "Plain text passwords are rejected, but configured for: {}",
+                    plaintext_users.join(", ")
+                )));
+            }
+            tracing::warn!(
+                "Plain text passwords configured for: {}. Consider using argon2id hashed passwords.",
+                plaintext_users.join(", ")
+            );
         }
 
         let mut users = HashMap::new();
@@ -22,64 +44,74 @@ impl PlainLoginProvider {
             users.insert(user.username.clone(), user.clone());
         }
 
-        Ok(Self { users })
+        Ok(Self {
+            users,
+            allow_plaintext: !config.reject_plaintext_passwords,
+        })
     }
 
     /// Authenticate a user with username and password
+    ///
+    /// Users in the local user store take precedence over configured users.
+    /// Their bcrypt hashes are replaced by argon2id hashes on a successful login.
     pub async fn authenticate(
         &self,
         username: &str,
         password: &str,
+        store: Option<Arc<dyn LocalUserStore>>,
     ) -> Result<SessionData, AuthError> {
+        if let Some(store) = store {
+            if let Some(user) = store.user(username).await? {
+                if user.disabled {
+                    tracing::warn!("Login attempt for disabled user '{}'", username);
+                    return Err(AuthError::InvalidCredentials);
+                }
+                match passwords::verify_password(password, &user.password_hash, false)? {
+                    PasswordVerification::Invalid => return Err(AuthError::InvalidCredentials),
+                    PasswordVerification::Valid => {}
+                    PasswordVerification::Outdated => {
+                        let hash = passwords::hash_password(password)?;
+                        if let Err(e) = store.set_password_hash(username, &hash).await {
+                            tracing::error!("Failed to rehash password of '{}': {}", username, e);
+                        }
+                    }
+                }
+                return Ok(Self::session_data(username, user.display_name));
+            }
+        }
+
         let user = self
             .users
             .get(username)
-            .ok_or_else(|| AuthError::OAuthError("Invalid username or password".to_string()))?;
+            .ok_or(AuthError::InvalidCredentials)?;
 
-        // Check if password is bcrypt hashed (starts with $2)
-        let password_valid = if user.password.starts_with("$2") {
-            // Already hashed - verify with bcrypt
-            bcrypt::verify(password, &user.password).map_err(|e| {
-                AuthError::OAuthError(format!("Password verification failed: {}", e))
-            })?
-        } else {
-            // Plain text password - direct comparison (not recommended for production)
-            tracing::warn!(
-                "Plain text password used for user '{}'. Consider using bcrypt hashed passwords.",
-                username
-            );
-            password == user.password
-        };
-
-        if !password_valid {
-            return Err(AuthError::OAuthError(
-                "Invalid username or password".to_string(),
-            ));
+        match passwords::verify_password(password, &user.password, self.allow_plaintext)? {
+            PasswordVerification::Invalid => Err(AuthError::InvalidCredentials),
+            PasswordVerification::Valid | PasswordVerification::Outdated => {
+                Ok(Self::session_data(username, user.display_name.clone()))
+            }
         }
+    }
 
+    fn session_data(username: &str, display_name: Option<String>) -> SessionData {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
 
-        Ok(SessionData {
+        SessionData {
             auth_provider: AuthProvider::PlainLogin,
             user_id: format!("local:{}", username),
             handle: username.to_string(),
-            display_name: user.display_name.clone(),
+            display_name,
             groups: vec![],
             created_at: now,
-        })
+            session_id: None,
+        }
     }
 
-    /// Check if a username exists
+    /// Check if a username exists in the configuration
     pub fn user_exists(&self, username: &str) -> bool {
         self.users.contains_key(username)
     }
 }
-
-/// Helper function to hash a password with bcrypt
-pub fn hash_password(password: &str) -> Result<String, AuthError> {
-    bcrypt::hash(password, bcrypt::DEFAULT_COST)
-        .map_err(|e| AuthError::ConfigError(format!("Failed to hash password: {}", e)))
-}
@@ -0,0 +1,212 @@
+//! Role-based authorization
+//!
+//! Authenticated users get one or more roles, assigned in the `[auth.roles]`
+//! configuration by handle or by group, or by an external [`RoleStore`] such as
+//! a database table. Roles are ordered - each one grants everything the roles
+//! below it grant - and every protected route group declares the
+//! [`Permission`] it requires.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+
+/// User role, ordered from least to most privileged
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Live dashboard and network test
+    Viewer,
+    /// Survey data browsing
+    Analyst,
+    /// Capture and keylog downloads, client management
+    Operator,
+    /// Everything, including destructive operations
+    Admin,
+}
+
+impl Role {
+    /// Check whether this role grants a permission
+    pub fn grants(self, permission: Permission) -> bool {
+        self >= permission.required_role()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Analyst => "analyst",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "analyst" => Ok(Role::Analyst),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("Unknown role '{}'", other)),
+        }
+    }
+}
+
+/// Permission required by a protected route group, also used as API token scope
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Live dashboard, diagnostics and packet tracking
+    ViewDashboard,
+    /// Survey sessions, metrics and recordings
+    ViewSurveys,
+    /// Server-wide packet capture and tracing buffer downloads
+    DownloadCaptures,
+    /// DTLS keylogs, which decrypt captured traffic
+    DownloadKeylogs,
+    /// Disconnecting and cleaning up clients
+    ManageClients,
+    /// Deleting survey sessions and their data
+    WipeSessions,
+    /// Creating, revoking and rotating magic keys
+    ManageMagicKeys,
+    /// Listing and revoking other users' login sessions and API tokens
+    ManageSessions,
+    /// Creating, disabling and resetting local users
+    ManageUsers,
+    /// Querying and exporting the security audit log
+    ViewAuditLog,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 10] = [
+        Permission::ViewDashboard,
+        Permission::ViewSurveys,
+        Permission::DownloadCaptures,
+        Permission::DownloadKeylogs,
+        Permission::ManageClients,
+        Permission::WipeSessions,
+        Permission::ManageMagicKeys,
+        Permission::ManageSessions,
+        Permission::ManageUsers,
+        Permission::ViewAuditLog,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::ViewDashboard => "view_dashboard",
+            Permission::ViewSurveys => "view_surveys",
+            Permission::DownloadCaptures => "download_captures",
+            Permission::DownloadKeylogs => "download_keylogs",
+            Permission::ManageClients => "manage_clients",
+            Permission::WipeSessions => "wipe_sessions",
+            Permission::ManageMagicKeys => "manage_magic_keys",
+            Permission::ManageSessions => "manage_sessions",
+            Permission::ManageUsers => "manage_users",
+            Permission::ViewAuditLog => "view_audit_log",
+        }
+    }
+
+    /// Least privileged role granting this permission
+    pub fn required_role(self) -> Role {
+        match self {
+            Permission::ViewDashboard => Role::Viewer,
+            Permission::ViewSurveys => Role::Analyst,
+            Permission::DownloadCaptures
+            | Permission::DownloadKeylogs
+            | Permission::ManageClients => Role::Operator,
+            Permission::WipeSessions
+            | Permission::ManageMagicKeys
+            | Permission::ManageSessions
+            | Permission::ManageUsers
+            | Permission::ViewAuditLog => Role::Admin,
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Permission::ALL
+            .into_iter()
+            .find(|permission| permission.as_str() == s)
+            .ok_or_else(|| format!("Unknown permission '{}'", s))
+    }
+}
+
+/// Future returned by [`RoleStore::roles_for`]
+pub type RolesFuture<'a> = Pin<Box<dyn Future<Output = Vec<Role>> + Send + 'a>>;
+
+/// External source of role assignments (e.g. a database table), consulted in
+/// addition to the roles from the configuration
+pub trait RoleStore: Send + Sync {
+    /// Roles assigned to a user of a provider (`AuthProvider::name`) or to any
+    /// of the user's groups
+    fn roles_for<'a>(
+        &'a self,
+        provider: &'a str,
+        handle: &'a str,
+        groups: &'a [String],
+    ) -> RolesFuture<'a>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_hierarchy() {
+        assert!(Role::Admin.grants(Permission::WipeSessions));
+        assert!(Role::Admin.grants(Permission::ViewDashboard));
+        assert!(Role::Operator.grants(Permission::DownloadKeylogs));
+        assert!(!Role::Operator.grants(Permission::WipeSessions));
+        assert!(!Role::Operator.grants(Permission::ManageMagicKeys));
+        assert!(!Role::Operator.grants(Permission::ManageSessions));
+        assert!(Role::Admin.grants(Permission::ManageSessions));
+        assert!(!Role::Operator.grants(Permission::ManageUsers));
+        assert!(Role::Admin.grants(Permission::ManageUsers));
+        assert!(!Role::Operator.grants(Permission::ViewAuditLog));
+        assert!(Role::Admin.grants(Permission::ViewAuditLog));
+        assert!(Role::Analyst.grants(Permission::ViewSurveys));
+        assert!(!Role::Analyst.grants(Permission::DownloadCaptures));
+        assert!(Role::Viewer.grants(Permission::ViewDashboard));
+        assert!(!Role::Viewer.grants(Permission::ViewSurveys));
+    }
+
+    #[test]
+    fn test_role_parsing() {
+        for role in [Role::Viewer, Role::Analyst, Role::Operator, Role::Admin] {
+            assert_eq!(role.as_str().parse::<Role>(), Ok(role));
+        }
+        assert!("root".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn test_permission_parsing() {
+        for permission in Permission::ALL {
+            assert_eq!(permission.as_str().parse::<Permission>(), Ok(permission));
+            assert_eq!(
+                serde_json::to_string(&permission).unwrap(),
+                format!("\"{}\"", permission)
+            );
+        }
+        assert!("everything".parse::<Permission>().is_err());
+    }
+}
@@ -1,21 +1,58 @@
 use axum::{
-    extract::{Query, State},
+    extract::{Path, Query, State},
     http::{header, StatusCode},
     response::{Html, IntoResponse, Redirect, Response},
     routing::{get, post},
     Form, Router,
 };
 use axum_extra::extract::cookie::{Cookie, PrivateCookieJar};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::session::SessionData;
-use crate::views::login_page_html;
+use crate::audit::{AuditEvent, AuditOutcome};
+use crate::error::AuthError;
+use crate::session::{AuthProvider, SessionData};
+use crate::session_store::ClientInfo;
+use crate::totp::TotpRequirement;
+use crate::views::{
+    login_page_html, password_change_page_html, totp_enroll_page_html, totp_manage_page_html,
+    totp_page_html, totp_recovery_codes_page_html,
+};
 use crate::AuthState;
 
 /// Cookie name for storing OAuth temp state ID during OAuth flow
 const OAUTH_STATE_COOKIE: &str = "oauth_state_id";
 
+/// Cookie name for a password login waiting for its second factor
+const TOTP_PENDING_COOKIE: &str = "totp_pending";
+
+/// Time to enter the second factor after the password (seconds)
+const TOTP_PENDING_TIMEOUT: u64 = 300;
+
+/// Password login waiting for its second factor, kept in a private cookie
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    session: SessionData,
+    issued_at: u64,
+}
+
+#[derive(Deserialize)]
+struct TotpForm {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct TotpQuery {
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PasswordChangeForm {
+    current_password: String,
+    new_password: String,
+    confirm_password: String,
+}
+
 #[derive(Deserialize)]
 struct HandleForm {
     handle: String,
@@ -41,6 +78,10 @@ pub fn auth_routes() -> Router<AuthState> {
     Router::new()
         .route("/login", get(login_page))
         .route("/plain/login", post(plain_login))
+        .route("/totp", get(totp_page).post(totp_verify))
+        .route("/totp/enroll", get(totp_enroll_page).post(totp_enroll))
+        .route("/totp/disable", post(totp_disable))
+        .route("/password", get(password_page).post(password_change))
         .route("/bluesky/login", post(bluesky_login))
         .route("/bluesky/callback", get(bluesky_callback))
         .route("/github/login", get(github_login))
@@ -49,6 +90,8 @@ pub fn auth_routes() -> Router<AuthState> {
         .route("/google/callback", get(google_callback))
         .route("/linkedin/login", get(linkedin_login))
         .route("/linkedin/callback", get(linkedin_callback))
+        .route("/oidc/{name}/login", get(oidc_login))
+        .route("/oidc/{name}/callback", get(oidc_callback))
         .route("/logout", post(logout))
 }
 
@@ -59,16 +102,32 @@ async fn login_page(State(auth_state): State<AuthState>) -> Html<String> {
         auth_state.config.oauth.enable_github,
         auth_state.config.oauth.enable_google,
         auth_state.config.oauth.enable_linkedin,
+        &auth_state.oidc_providers(),
     );
     Html(html)
 }
 
-/// Helper to create session cookie
-fn create_session_cookie(
+/// Helper to register a new session and create its cookie
+async fn create_session_cookie(
     auth_state: &AuthState,
-    session_data: &SessionData,
+    session_data: SessionData,
+    client: &ClientInfo,
 ) -> Result<Cookie<'static>, StatusCode> {
-    let session_json = serde_json::to_string(session_data).map_err(|e| {
+    let session_data = auth_state
+        .register_session(session_data, client)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to register session: {}", e);
+            StatusCode::from(e)
+        })?;
+    auth_state
+        .audit(
+            AuditEvent::new("login", AuditOutcome::Success)
+                .session(&session_data)
+                .ip(client.ip),
+        )
+        .await;
+    let session_json = serde_json::to_string(&session_data).map_err(|e| {
         tracing::error!("Failed to serialize session data: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
@@ -103,28 +162,418 @@ fn create_oauth_state_cookie(state_id: &str, secure: bool) -> Cookie<'static> {
 
 async fn plain_login(
     State(auth_state): State<AuthState>,
+    client: ClientInfo,
     jar: PrivateCookieJar,
     Form(form): Form<PlainLoginForm>,
 ) -> Result<(PrivateCookieJar, Response), StatusCode> {
     tracing::info!("Plain login request for username: {}", form.username);
 
-    let session_data = auth_state
+    if let Err(e) = auth_state
+        .check_lockout(client.ip, Some(&form.username))
+        .await
+    {
+        tracing::warn!("Plain login for {} refused: {}", form.username, e);
+        auth_state
+            .audit(
+                AuditEvent::new("login", AuditOutcome::Denied)
+                    .actor(form.username.as_str())
+                    .provider(format!("{:?}", AuthProvider::PlainLogin))
+                    .ip(client.ip)
+                    .detail(e.to_string()),
+            )
+            .await;
+        return Ok((jar, locked_out_response(e)));
+    }
+
+    let session_data = match auth_state
         .authenticate_plain_login(&form.username, &form.password)
         .await
-        .map_err(|e| {
+    {
+        Ok(session_data) => session_data,
+        Err(e) => {
             tracing::error!("Plain login failed: {}", e);
-            StatusCode::UNAUTHORIZED
+            auth_state
+                .audit(
+                    AuditEvent::new("login", AuditOutcome::Failure)
+                        .actor(form.username.as_str())
+                        .provider(format!("{:?}", AuthProvider::PlainLogin))
+                        .ip(client.ip)
+                        .detail(e.to_string()),
+                )
+                .await;
+            if matches!(e, AuthError::InvalidCredentials) {
+                auth_state
+                    .record_login_failure(client.ip, Some(&form.username))
+                    .await;
+            }
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    };
+
+    let requirement = auth_state
+        .totp_requirement(&session_data)
+        .await
+        .map_err(|e| {
+            tracing::error!("TOTP check for {} failed: {}", form.username, e);
+            StatusCode::from(e)
         })?;
+    let next = match requirement {
+        TotpRequirement::NotRequired => {
+            auth_state.clear_login_failures(&session_data.handle).await;
+            let cookie = create_session_cookie(&auth_state, session_data, &client).await?;
+            return Ok((
+                jar.add(cookie),
+                (StatusCode::FOUND, [(header::LOCATION, "/".to_string())]).into_response(),
+            ));
+        }
+        TotpRequirement::Verify => "/auth/totp",
+        TotpRequirement::Enroll => "/auth/totp/enroll",
+    };
+
+    // The session is only created once the second factor is verified
+    let pending = create_pending_login_cookie(session_data, auth_state.config.session.secure)?;
+    Ok((jar.add(pending), Redirect::to(next).into_response()))
+}
+
+/// Response to an attempt refused because the client or account is locked out
+fn locked_out_response(error: AuthError) -> Response {
+    let retry_after = match error {
+        AuthError::LockedOut(seconds) => seconds,
+        _ => 0,
+    };
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        error.to_string(),
+    )
+        .into_response()
+}
+
+/// Helper to create the cookie of a login waiting for its second factor
+fn create_pending_login_cookie(
+    session: SessionData,
+    secure: bool,
+) -> Result<Cookie<'static>, StatusCode> {
+    let pending = PendingLogin {
+        session,
+        issued_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    };
+    let value = serde_json::to_string(&pending).map_err(|e| {
+        tracing::error!("Failed to serialize pending login: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let mut cookie = Cookie::new(TOTP_PENDING_COOKIE, value);
+    cookie.set_path("/auth");
+    cookie.set_http_only(true);
+    cookie.set_same_site(axum_extra::extract::cookie::SameSite::Strict);
+    if secure {
+        cookie.set_secure(true);
+    }
+    cookie.set_max_age(time::Duration::seconds(TOTP_PENDING_TIMEOUT as i64));
+    Ok(cookie)
+}
+
+/// Get the unexpired login waiting for its second factor, if any
+fn pending_login(jar: &PrivateCookieJar) -> Option<SessionData> {
+    let pending: PendingLogin = serde_json::from_str(jar.get(TOTP_PENDING_COOKIE)?.value()).ok()?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    (now.saturating_sub(pending.issued_at) < TOTP_PENDING_TIMEOUT).then_some(pending.session)
+}
+
+/// Get the session of a logged-in plain login user, if any
+async fn current_plain_session(
+    auth_state: &AuthState,
+    jar: &PrivateCookieJar,
+    client: &ClientInfo,
+) -> Option<SessionData> {
+    let cookie = jar.get(&auth_state.config.session.cookie_name)?;
+    let session_data: SessionData = serde_json::from_str(cookie.value()).ok()?;
+    if session_data.auth_provider != AuthProvider::PlainLogin {
+        return None;
+    }
+    auth_state
+        .verify_session(&session_data, client)
+        .await
+        .ok()
+        .map(|_| session_data)
+}
+
+/// Complete a pending login: create its session and drop the pending cookie
+async fn complete_pending_login(
+    auth_state: &AuthState,
+    jar: PrivateCookieJar,
+    session_data: SessionData,
+    client: &ClientInfo,
+) -> Result<PrivateCookieJar, StatusCode> {
+    auth_state.clear_login_failures(&session_data.handle).await;
+    let cookie = create_session_cookie(auth_state, session_data, client).await?;
+    let mut pending = Cookie::from(TOTP_PENDING_COOKIE);
+    pending.set_path("/auth");
+    Ok(jar.remove(pending).add(cookie))
+}
+
+async fn totp_page(jar: PrivateCookieJar, Query(query): Query<TotpQuery>) -> Response {
+    if pending_login(&jar).is_none() {
+        return Redirect::to("/auth/login").into_response();
+    }
+    Html(totp_page_html(query.error.is_some())).into_response()
+}
+
+async fn totp_verify(
+    State(auth_state): State<AuthState>,
+    client: ClientInfo,
+    jar: PrivateCookieJar,
+    Form(form): Form<TotpForm>,
+) -> Result<(PrivateCookieJar, Response), StatusCode> {
+    let Some(session_data) = pending_login(&jar) else {
+        return Ok((jar, Redirect::to("/auth/login").into_response()));
+    };
+
+    if let Err(e) = auth_state
+        .check_lockout(client.ip, Some(&session_data.handle))
+        .await
+    {
+        tracing::warn!(
+            "TOTP verification for {} refused: {}",
+            session_data.handle,
+            e
+        );
+        auth_state
+            .audit(
+                AuditEvent::new("totp_verify", AuditOutcome::Denied)
+                    .session(&session_data)
+                    .ip(client.ip)
+                    .detail(e.to_string()),
+            )
+            .await;
+        return Ok((jar, locked_out_response(e)));
+    }
+
+    if let Err(e) = auth_state
+        .verify_totp(&session_data.handle, &form.code)
+        .await
+    {
+        tracing::warn!(
+            "TOTP verification for {} failed: {}",
+            session_data.handle,
+            e
+        );
+        auth_state
+            .audit(
+                AuditEvent::new("totp_verify", AuditOutcome::Failure)
+                    .session(&session_data)
+                    .ip(client.ip)
+                    .detail(e.to_string()),
+            )
+            .await;
+        return match e {
+            AuthError::InvalidTotpCode => {
+                auth_state
+                    .record_login_failure(client.ip, Some(&session_data.handle))
+                    .await;
+                Ok((
+                    jar,
+                    Redirect::to("/auth/totp?error=invalid").into_response(),
+                ))
+            }
+            e => Err(StatusCode::from(e)),
+        };
+    }
+
+    let updated_jar = complete_pending_login(&auth_state, jar, session_data, &client).await?;
+    Ok((updated_jar, Redirect::to("/").into_response()))
+}
 
-    let cookie = create_session_cookie(&auth_state, &session_data)?;
-    let updated_jar = jar.add(cookie);
+/// Enrollment is open to a pending login required to enroll, and to logged-in
+/// plain login users
+async fn totp_enroll_page(
+    State(auth_state): State<AuthState>,
+    client: ClientInfo,
+    jar: PrivateCookieJar,
+    Query(query): Query<TotpQuery>,
+) -> Result<Response, StatusCode> {
+    let (session_data, required) = match pending_login(&jar) {
+        Some(session_data) => (session_data, true),
+        None => match current_plain_session(&auth_state, &jar, &client).await {
+            Some(session_data) => (session_data, false),
+            None => return Ok(Redirect::to("/auth/login").into_response()),
+        },
+    };
+
+    match auth_state.begin_totp_enrollment(&session_data.handle).await {
+        Ok(provisioning) => Ok(Html(totp_enroll_page_html(
+            &provisioning,
+            required,
+            query.error.is_some(),
+        ))
+        .into_response()),
+        Err(AuthError::TotpAlreadyEnrolled) if !required => {
+            Ok(Html(totp_manage_page_html(query.error.is_some())).into_response())
+        }
+        Err(e) => {
+            tracing::error!("TOTP enrollment for {} failed: {}", session_data.handle, e);
+            Err(StatusCode::from(e))
+        }
+    }
+}
 
+async fn totp_enroll(
+    State(auth_state): State<AuthState>,
+    client: ClientInfo,
+    jar: PrivateCookieJar,
+    Form(form): Form<TotpForm>,
+) -> Result<(PrivateCookieJar, Response), StatusCode> {
+    let pending = pending_login(&jar);
+    let session_data = match &pending {
+        Some(session_data) => session_data.clone(),
+        None => match current_plain_session(&auth_state, &jar, &client).await {
+            Some(session_data) => session_data,
+            None => return Ok((jar, Redirect::to("/auth/login").into_response())),
+        },
+    };
+
+    let recovery_codes = match auth_state
+        .confirm_totp_enrollment(&session_data.handle, &form.code)
+        .await
+    {
+        Ok(recovery_codes) => recovery_codes,
+        Err(AuthError::InvalidTotpCode) => {
+            return Ok((
+                jar,
+                Redirect::to("/auth/totp/enroll?error=invalid").into_response(),
+            ));
+        }
+        Err(e) => {
+            tracing::error!("TOTP enrollment for {} failed: {}", session_data.handle, e);
+            return Err(StatusCode::from(e));
+        }
+    };
+
+    auth_state
+        .audit(
+            AuditEvent::new("totp_enroll", AuditOutcome::Success)
+                .session(&session_data)
+                .ip(client.ip),
+        )
+        .await;
+
+    // A login that had to enroll first is complete now
+    let updated_jar = match pending {
+        Some(session_data) => {
+            complete_pending_login(&auth_state, jar, session_data, &client).await?
+        }
+        None => jar,
+    };
     Ok((
         updated_jar,
-        (StatusCode::FOUND, [(header::LOCATION, "/".to_string())]).into_response(),
+        Html(totp_recovery_codes_page_html(&recovery_codes)).into_response(),
     ))
 }
 
+async fn totp_disable(
+    State(auth_state): State<AuthState>,
+    client: ClientInfo,
+    jar: PrivateCookieJar,
+    Form(form): Form<TotpForm>,
+) -> Result<Response, StatusCode> {
+    let session_data = current_plain_session(&auth_state, &jar, &client)
+        .await
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    match auth_state.disable_totp(&session_data, &form.code).await {
+        Ok(()) => {
+            auth_state
+                .audit(
+                    AuditEvent::new("totp_disable", AuditOutcome::Success)
+                        .session(&session_data)
+                        .ip(client.ip),
+                )
+                .await;
+            Ok(Redirect::to("/").into_response())
+        }
+        Err(AuthError::InvalidTotpCode) => {
+            Ok(Redirect::to("/auth/totp/enroll?error=invalid").into_response())
+        }
+        Err(e) => {
+            tracing::warn!("Removing TOTP for {} failed: {}", session_data.handle, e);
+            Err(StatusCode::from(e))
+        }
+    }
+}
+
+async fn password_page(
+    State(auth_state): State<AuthState>,
+    client: ClientInfo,
+    jar: PrivateCookieJar,
+) -> Response {
+    if current_plain_session(&auth_state, &jar, &client)
+        .await
+        .is_none()
+    {
+        return Redirect::to("/auth/login").into_response();
+    }
+    Html(password_change_page_html(None, false)).into_response()
+}
+
+async fn password_change(
+    State(auth_state): State<AuthState>,
+    client: ClientInfo,
+    jar: PrivateCookieJar,
+    Form(form): Form<PasswordChangeForm>,
+) -> Result<Response, StatusCode> {
+    let Some(session_data) = current_plain_session(&auth_state, &jar, &client).await else {
+        return Ok(Redirect::to("/auth/login").into_response());
+    };
+
+    if form.new_password != form.confirm_password {
+        return Ok(Html(password_change_page_html(
+            Some("The new passwords do not match"),
+            false,
+        ))
+        .into_response());
+    }
+
+    let result = auth_state
+        .change_password(&session_data, &form.current_password, &form.new_password)
+        .await;
+    let outcome = match &result {
+        Ok(()) => Some(AuditOutcome::Success),
+        Err(AuthError::InvalidCredentials) => Some(AuditOutcome::Failure),
+        Err(_) => None,
+    };
+    if let Some(outcome) = outcome {
+        auth_state
+            .audit(
+                AuditEvent::new("password_change", outcome)
+                    .session(&session_data)
+                    .ip(client.ip),
+            )
+            .await;
+    }
+
+    match result {
+        Ok(()) => Ok(Html(password_change_page_html(None, true)).into_response()),
+        Err(AuthError::InvalidCredentials) => Ok(Html(password_change_page_html(
+            Some("The current password is incorrect"),
+            false,
+        ))
+        .into_response()),
+        Err(AuthError::PasswordPolicy(reason)) => {
+            Ok(Html(password_change_page_html(Some(&reason), false)).into_response())
+        }
+        Err(e) => {
+            tracing::error!("Password change for {} failed: {}", session_data.handle, e);
+            Err(StatusCode::from(e))
+        }
+    }
+}
+
 async fn bluesky_login(
     State(auth_state): State<AuthState>,
     jar: PrivateCookieJar,
@@ -168,6 +617,7 @@ async fn bluesky_login(
 
 async fn bluesky_callback(
     State(auth_state): State<AuthState>,
+    client: ClientInfo,
     jar: PrivateCookieJar,
     Query(query): Query<AuthCallback>,
 ) -> Result<(PrivateCookieJar, Response), StatusCode> {
@@ -208,7 +658,7 @@ async fn bluesky_callback(
     auth_state.remove_oauth_temp_state(&state_id).await;
 
     // Store session in private cookie
-    let session_cookie = create_session_cookie(&auth_state, &session_data)?;
+    let session_cookie = create_session_cookie(&auth_state, session_data, &client).await?;
     let updated_jar = jar
         .remove(Cookie::from(OAUTH_STATE_COOKIE))
         .add(session_cookie);
@@ -243,6 +693,7 @@ async fn github_login(
 
 async fn github_callback(
     State(auth_state): State<AuthState>,
+    client: ClientInfo,
     jar: PrivateCookieJar,
     Query(query): Query<AuthCallback>,
 ) -> Result<(PrivateCookieJar, Response), StatusCode> {
@@ -276,7 +727,7 @@ async fn github_callback(
     auth_state.remove_oauth_temp_state(&state_id).await;
 
     // Store session in private cookie
-    let session_cookie = create_session_cookie(&auth_state, &session_data)?;
+    let session_cookie = create_session_cookie(&auth_state, session_data, &client).await?;
     let updated_jar = jar
         .remove(Cookie::from(OAUTH_STATE_COOKIE))
         .add(session_cookie);
@@ -311,6 +762,7 @@ async fn google_login(
 
 async fn google_callback(
     State(auth_state): State<AuthState>,
+    client: ClientInfo,
     jar: PrivateCookieJar,
     Query(query): Query<AuthCallback>,
 ) -> Result<(PrivateCookieJar, Response), StatusCode> {
@@ -344,7 +796,7 @@ async fn google_callback(
     auth_state.remove_oauth_temp_state(&state_id).await;
 
     // Store session in private cookie
-    let session_cookie = create_session_cookie(&auth_state, &session_data)?;
+    let session_cookie = create_session_cookie(&auth_state, session_data, &client).await?;
     let updated_jar = jar
         .remove(Cookie::from(OAUTH_STATE_COOKIE))
         .add(session_cookie);
@@ -379,6 +831,7 @@ async fn linkedin_login(
 
 async fn linkedin_callback(
     State(auth_state): State<AuthState>,
+    client: ClientInfo,
     jar: PrivateCookieJar,
     Query(query): Query<AuthCallback>,
 ) -> Result<(PrivateCookieJar, Response), StatusCode> {
@@ -412,7 +865,88 @@ async fn linkedin_callback(
     auth_state.remove_oauth_temp_state(&state_id).await;
 
     // Store session in private cookie
-    let session_cookie = create_session_cookie(&auth_state, &session_data)?;
+    let session_cookie = create_session_cookie(&auth_state, session_data, &client).await?;
+    let updated_jar = jar
+        .remove(Cookie::from(OAUTH_STATE_COOKIE))
+        .add(session_cookie);
+
+    Ok((updated_jar, Redirect::to("/").into_response()))
+}
+
+async fn oidc_login(
+    State(auth_state): State<AuthState>,
+    Path(name): Path<String>,
+    jar: PrivateCookieJar,
+) -> Result<(PrivateCookieJar, Response), StatusCode> {
+    let (auth_url, temp_state) = auth_state.start_oidc_auth(&name).await.map_err(|e| {
+        tracing::error!("OIDC auth start with {} failed: {}", name, e);
+        match e {
+            AuthError::ConfigError(_) => StatusCode::NOT_FOUND,
+            e => StatusCode::from(e),
+        }
+    })?;
+
+    // Store temp state in memory with a unique ID
+    let state_id = Uuid::new_v4().to_string();
+    auth_state
+        .store_oauth_temp_state(state_id.clone(), temp_state)
+        .await;
+
+    // Store state ID in cookie for callback
+    let state_cookie = create_oauth_state_cookie(&state_id, auth_state.config.session.secure);
+    let updated_jar = jar.add(state_cookie);
+
+    Ok((
+        updated_jar,
+        (StatusCode::FOUND, [(header::LOCATION, auth_url)]).into_response(),
+    ))
+}
+
+async fn oidc_callback(
+    State(auth_state): State<AuthState>,
+    client: ClientInfo,
+    Path(name): Path<String>,
+    jar: PrivateCookieJar,
+    Query(query): Query<AuthCallback>,
+) -> Result<(PrivateCookieJar, Response), StatusCode> {
+    if let Some(error) = &query.error {
+        let error_msg = query
+            .error_description
+            .as_deref()
+            .unwrap_or("Unknown error");
+        tracing::error!("OIDC error from {}: {} - {}", name, error, error_msg);
+        return Ok((
+            jar,
+            Redirect::to("/auth/login?error=oidc_auth_failed").into_response(),
+        ));
+    }
+
+    let code = query.code.as_ref().ok_or(StatusCode::BAD_REQUEST)?;
+
+    // Get OAuth state ID from cookie
+    let state_id = jar
+        .get(OAUTH_STATE_COOKIE)
+        .map(|c| c.value().to_string())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let temp_state = auth_state
+        .get_oauth_temp_state(&state_id)
+        .await
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // The nonce is single-use: drop the temp state whether or not validation succeeds
+    auth_state.remove_oauth_temp_state(&state_id).await;
+
+    let session_data = auth_state
+        .complete_oidc_auth(&name, code, &temp_state)
+        .await
+        .map_err(|e| {
+            tracing::error!("OIDC auth completion with {} failed: {}", name, e);
+            StatusCode::from(e)
+        })?;
+
+    // Store session in private cookie
+    let session_cookie = create_session_cookie(&auth_state, session_data, &client).await?;
     let updated_jar = jar
         .remove(Cookie::from(OAUTH_STATE_COOKIE))
         .add(session_cookie);
@@ -422,10 +956,27 @@ async fn linkedin_callback(
 
 async fn logout(
     State(auth_state): State<AuthState>,
+    client: ClientInfo,
     jar: PrivateCookieJar,
 ) -> Result<(PrivateCookieJar, Response), StatusCode> {
-    // Remove session cookie
     let cookie_name = auth_state.config.session.cookie_name.clone();
+
+    // Revoke the session server-side, so a copy of the cookie cannot be reused
+    if let Some(session_data) = jar
+        .get(&cookie_name)
+        .and_then(|cookie| serde_json::from_str::<SessionData>(cookie.value()).ok())
+    {
+        auth_state.end_session(&session_data).await;
+        auth_state
+            .audit(
+                AuditEvent::new("logout", AuditOutcome::Success)
+                    .session(&session_data)
+                    .ip(client.ip),
+            )
+            .await;
+    }
+
+    // Remove session cookie
     let updated_jar = jar.remove(Cookie::from(cookie_name));
 
     Ok((
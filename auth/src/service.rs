@@ -1,11 +1,22 @@
+use crate::api_tokens::{bearer_token, ApiTokenStore};
+use crate::audit::{AuditEvent, AuditLog, AuditOutcome};
 use crate::config::AuthConfig;
 use crate::error::AuthError;
+use crate::local_users::LocalUserStore;
+use crate::lockout::{self, AttemptRecord, LoginAttemptStore, MemoryLoginAttemptStore};
+use crate::passwords::{self, PasswordVerification};
 use crate::providers::{
-    BlueskyProvider, GitHubProvider, GoogleProvider, LinkedInProvider, PlainLoginProvider,
+    BlueskyProvider, GitHubProvider, GoogleProvider, LinkedInProvider, OidcProvider,
+    PlainLoginProvider,
 };
-use crate::session::{OAuthTempState, SessionData};
+use crate::roles::{Permission, Role, RoleStore};
+use crate::session::{AuthProvider, OAuthTempState, SessionData};
+use crate::session_store::{ClientInfo, SessionStore};
+use crate::totp::{self, SecretCipher, TotpProvisioning, TotpRequirement, TotpStore};
+use axum::http::HeaderMap;
 use axum_extra::extract::cookie::Key;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use trust_dns_resolver::TokioAsyncResolver;
@@ -22,7 +33,24 @@ pub struct AuthService {
     github_provider: Option<GitHubProvider>,
     google_provider: Option<GoogleProvider>,
     linkedin_provider: Option<LinkedInProvider>,
+    oidc_providers: Vec<OidcProvider>,
     plain_login_provider: Option<PlainLoginProvider>,
+    /// Additional role assignments (e.g. from the database), set after construction
+    role_store: std::sync::RwLock<Option<Arc<dyn RoleStore>>>,
+    /// Server-side session store (e.g. the database), set after construction
+    session_store: std::sync::RwLock<Option<Arc<dyn SessionStore>>>,
+    /// Personal API token store, set after construction
+    api_token_store: std::sync::RwLock<Option<Arc<dyn ApiTokenStore>>>,
+    /// TOTP enrollment store, set after construction
+    totp_store: std::sync::RwLock<Option<Arc<dyn TotpStore>>>,
+    /// Encryption of TOTP secrets (if TOTP is enabled)
+    totp_cipher: Option<SecretCipher>,
+    /// Plain login users managed at runtime (e.g. in the database), set after construction
+    local_user_store: std::sync::RwLock<Option<Arc<dyn LocalUserStore>>>,
+    /// Failed login attempts, kept in memory unless a shared store is set
+    login_attempts: std::sync::RwLock<Arc<dyn LoginAttemptStore>>,
+    /// Security audit log (e.g. in the database), set after construction
+    audit_log: std::sync::RwLock<Option<Arc<dyn AuditLog>>>,
 }
 
 impl AuthService {
@@ -52,6 +80,19 @@ impl AuthService {
             Key::generate()
         };
 
+        // TOTP secrets are encrypted with a key derived from the cookie secret, so
+        // they can only be read across restarts with a configured secret
+        let totp_cipher = if config.plain_login.totp.enabled {
+            if config.session.cookie_secret.is_none() {
+                return Err(AuthError::ConfigError(
+                    "TOTP requires a configured session.cookie_secret".to_string(),
+                ));
+            }
+            Some(SecretCipher::new(cookie_key.master()))
+        } else {
+            None
+        };
+
         // Initialize enabled providers
         let bluesky_provider = if config.oauth.enable_bluesky {
             let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(|e| {
@@ -80,6 +121,17 @@ impl AuthService {
             None
         };
 
+        let mut oidc_providers: Vec<OidcProvider> = Vec::new();
+        for oidc_config in &config.oauth.oidc {
+            if oidc_providers.iter().any(|p| p.name() == oidc_config.name) {
+                return Err(AuthError::ConfigError(format!(
+                    "Duplicate OIDC provider name '{}'",
+                    oidc_config.name
+                )));
+            }
+            oidc_providers.push(OidcProvider::new(oidc_config)?);
+        }
+
         let plain_login_provider = if config.plain_login.enabled {
             Some(PlainLoginProvider::new(&config.plain_login)?)
         } else {
@@ -94,7 +146,16 @@ impl AuthService {
             github_provider,
             google_provider,
             linkedin_provider,
+            oidc_providers,
             plain_login_provider,
+            role_store: std::sync::RwLock::new(None),
+            session_store: std::sync::RwLock::new(None),
+            api_token_store: std::sync::RwLock::new(None),
+            totp_store: std::sync::RwLock::new(None),
+            totp_cipher,
+            local_user_store: std::sync::RwLock::new(None),
+            login_attempts: std::sync::RwLock::new(Arc::new(MemoryLoginAttemptStore::default())),
+            audit_log: std::sync::RwLock::new(None),
         })
     }
 
@@ -114,6 +175,7 @@ impl AuthService {
             || self.config.oauth.enable_github
             || self.config.oauth.enable_google
             || self.config.oauth.enable_linkedin
+            || !self.oidc_providers.is_empty()
             || self.config.plain_login.enabled
     }
 
@@ -133,6 +195,87 @@ impl AuthService {
         }
     }
 
+    /// Set the store consulted for role assignments in addition to the configuration
+    pub fn set_role_store(&self, store: Arc<dyn RoleStore>) {
+        *self.role_store.write().unwrap() = Some(store);
+    }
+
+    /// Get the effective role of a session: the most privileged role assigned
+    /// to the user or their groups, or None if the user has no role
+    pub async fn role_for(&self, session_data: &SessionData) -> Option<Role> {
+        let mut roles = self.config.roles.roles_for(
+            session_data.auth_provider.user_provider(),
+            &session_data.handle,
+            &session_data.groups,
+        );
+
+        let store = self.role_store.read().unwrap().clone();
+        if let Some(store) = store {
+            roles.extend(
+                store
+                    .roles_for(
+                        session_data.auth_provider.user_provider(),
+                        &session_data.handle,
+                        &session_data.groups,
+                    )
+                    .await,
+            );
+        }
+
+        roles.into_iter().max()
+    }
+
+    /// Check if a session has a permission
+    pub async fn has_permission(&self, session_data: &SessionData, permission: Permission) -> bool {
+        self.role_for(session_data)
+            .await
+            .is_some_and(|role| role.grants(permission))
+    }
+
+    /// Set the store API tokens are checked against
+    pub fn set_api_token_store(&self, store: Arc<dyn ApiTokenStore>) {
+        *self.api_token_store.write().unwrap() = Some(store);
+    }
+
+    /// Authorize a request by its `Authorization: Bearer` API token, if it has one
+    ///
+    /// Returns None without a bearer token. Otherwise the token must be valid, its
+    /// owner allowed, and - if a permission is given - the permission must be in
+    /// the token's scopes and still granted by the owner's role. On success the
+    /// owner's session data is returned.
+    pub async fn authorize_api_token(
+        &self,
+        headers: &HeaderMap,
+        permission: Option<Permission>,
+    ) -> Option<Result<SessionData, AuthError>> {
+        let token = bearer_token(headers)?;
+        let store = self.api_token_store.read().unwrap().clone();
+        let Some(identity) = (match store {
+            Some(store) => store.authenticate(token).await,
+            None => None,
+        }) else {
+            return Some(Err(AuthError::AuthenticationRequired));
+        };
+
+        let session_data = identity.session_data();
+        if !self.is_user_allowed(&session_data.handle) {
+            return Some(Err(AuthError::AccessDenied));
+        }
+        if let Some(permission) = permission {
+            if !identity.allows(permission) || !self.has_permission(&session_data, permission).await
+            {
+                tracing::warn!(
+                    "Permission {} denied for API token {} of {}",
+                    permission,
+                    identity.token_id,
+                    identity.handle
+                );
+                return Some(Err(AuthError::AccessDenied));
+            }
+        }
+        Some(Ok(session_data))
+    }
+
     /// Start Bluesky authentication
     pub async fn start_bluesky_auth(
         &self,
@@ -224,6 +367,38 @@ impl AuthService {
         provider.complete_auth(code, temp_state).await
     }
 
+    /// Names and display names of the configured OpenID Connect providers
+    pub fn oidc_providers(&self) -> Vec<(String, String)> {
+        self.oidc_providers
+            .iter()
+            .map(|p| (p.name().to_string(), p.display_name().to_string()))
+            .collect()
+    }
+
+    fn oidc_provider(&self, name: &str) -> Result<&OidcProvider, AuthError> {
+        self.oidc_providers
+            .iter()
+            .find(|p| p.name() == name)
+            .ok_or_else(|| AuthError::ConfigError(format!("OIDC provider {} not configured", name)))
+    }
+
+    /// Start OpenID Connect authentication with a named provider
+    pub async fn start_oidc_auth(&self, name: &str) -> Result<(String, OAuthTempState), AuthError> {
+        self.oidc_provider(name)?.start_auth().await
+    }
+
+    /// Complete OpenID Connect authentication with a named provider
+    pub async fn complete_oidc_auth(
+        &self,
+        name: &str,
+        code: &str,
+        temp_state: &OAuthTempState,
+    ) -> Result<SessionData, AuthError> {
+        self.oidc_provider(name)?
+            .complete_auth(code, temp_state)
+            .await
+    }
+
     /// Authenticate with plain login (username/password)
     pub async fn authenticate_plain_login(
         &self,
@@ -233,7 +408,342 @@ impl AuthService {
         let provider = self.plain_login_provider.as_ref().ok_or_else(|| {
             AuthError::ConfigError("Plain login provider not enabled".to_string())
         })?;
-        provider.authenticate(username, password).await
+        let store = self.local_user_store.read().unwrap().clone();
+        provider.authenticate(username, password, store).await
+    }
+
+    /// Set the store of plain login users managed at runtime
+    pub fn set_local_user_store(&self, store: Arc<dyn LocalUserStore>) {
+        *self.local_user_store.write().unwrap() = Some(store);
+    }
+
+    /// Change the password of a plain login user from the local user store
+    ///
+    /// Users from the configuration file cannot change their password here.
+    pub async fn change_password(
+        &self,
+        session_data: &SessionData,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), AuthError> {
+        if session_data.auth_provider != AuthProvider::PlainLogin {
+            return Err(AuthError::AccessDenied);
+        }
+        let username = &session_data.handle;
+        let store = self.local_user_store.read().unwrap().clone();
+        let user = match store.as_ref() {
+            Some(store) => store.user(username).await?,
+            None => None,
+        };
+        let (Some(store), Some(user)) = (store, user.filter(|user| !user.disabled)) else {
+            return Err(AuthError::PasswordPolicy(
+                "This password is managed in the server configuration".to_string(),
+            ));
+        };
+
+        if passwords::verify_password(current_password, &user.password_hash, false)?
+            == PasswordVerification::Invalid
+        {
+            return Err(AuthError::InvalidCredentials);
+        }
+        if new_password == current_password {
+            return Err(AuthError::PasswordPolicy(
+                "The new password must differ from the current one".to_string(),
+            ));
+        }
+        self.config
+            .plain_login
+            .password_policy
+            .check(username, new_password)
+            .map_err(AuthError::PasswordPolicy)?;
+
+        let hash = passwords::hash_password(new_password)?;
+        store.set_password_hash(username, &hash).await?;
+        tracing::info!("Password changed for user {}", username);
+        Ok(())
+    }
+
+    /// Set the store of TOTP enrollments
+    pub fn set_totp_store(&self, store: Arc<dyn TotpStore>) {
+        *self.totp_store.write().unwrap() = Some(store);
+    }
+
+    /// TOTP store and secret cipher, if TOTP is enabled and a store is set
+    fn totp(&self) -> Option<(Arc<dyn TotpStore>, &SecretCipher)> {
+        let store = self.totp_store.read().unwrap().clone()?;
+        Some((store, self.totp_cipher.as_ref()?))
+    }
+
+    /// Check whether the TOTP policy requires a second factor for a session
+    async fn totp_required(&self, session_data: &SessionData) -> bool {
+        match self.config.plain_login.totp.required_for_role {
+            Some(min_role) => self
+                .role_for(session_data)
+                .await
+                .is_some_and(|role| role >= min_role),
+            None => false,
+        }
+    }
+
+    /// Determine the second step a password login needs before its session is created
+    pub async fn totp_requirement(
+        &self,
+        session_data: &SessionData,
+    ) -> Result<TotpRequirement, AuthError> {
+        if session_data.auth_provider != AuthProvider::PlainLogin {
+            return Ok(TotpRequirement::NotRequired);
+        }
+        let required = self.totp_required(session_data).await;
+        let Some((store, _)) = self.totp() else {
+            if required {
+                return Err(AuthError::TotpError(
+                    "TOTP is required by policy but not available".to_string(),
+                ));
+            }
+            return Ok(TotpRequirement::NotRequired);
+        };
+        match store.enrollment(&session_data.handle).await? {
+            Some(enrollment) if enrollment.confirmed => Ok(TotpRequirement::Verify),
+            _ if required => Ok(TotpRequirement::Enroll),
+            _ => Ok(TotpRequirement::NotRequired),
+        }
+    }
+
+    /// Start (or resume) the TOTP enrollment of a user
+    pub async fn begin_totp_enrollment(
+        &self,
+        username: &str,
+    ) -> Result<TotpProvisioning, AuthError> {
+        let (store, cipher) = self
+            .totp()
+            .ok_or_else(|| AuthError::ConfigError("TOTP not enabled".to_string()))?;
+
+        // Resume an unconfirmed enrollment, so a QR code already scanned stays valid
+        let secret = match store.enrollment(username).await? {
+            Some(enrollment) if enrollment.confirmed => return Err(AuthError::TotpAlreadyEnrolled),
+            Some(enrollment) => cipher.decrypt(&enrollment.secret)?,
+            None => {
+                let secret = totp::generate_secret();
+                store
+                    .begin_enrollment(username, &cipher.encrypt(&secret)?)
+                    .await?;
+                secret
+            }
+        };
+
+        let uri = totp::provisioning_uri(&self.config.plain_login.totp.issuer, username, &secret);
+        Ok(TotpProvisioning {
+            secret: totp::encode_secret(&secret),
+            qr_code_svg: totp::qr_code_svg(&uri)?,
+            uri,
+        })
+    }
+
+    /// Complete the TOTP enrollment of a user with a code from their app
+    ///
+    /// Returns the recovery codes, which are only stored as hashes.
+    pub async fn confirm_totp_enrollment(
+        &self,
+        username: &str,
+        code: &str,
+    ) -> Result<Vec<String>, AuthError> {
+        let (store, cipher) = self
+            .totp()
+            .ok_or_else(|| AuthError::ConfigError("TOTP not enabled".to_string()))?;
+        let enrollment = match store.enrollment(username).await? {
+            Some(enrollment) if enrollment.confirmed => return Err(AuthError::TotpAlreadyEnrolled),
+            Some(enrollment) => enrollment,
+            None => return Err(AuthError::InvalidTotpCode),
+        };
+
+        let secret = cipher.decrypt(&enrollment.secret)?;
+        let step =
+            totp::verify_code(&secret, code, unix_now()).ok_or(AuthError::InvalidTotpCode)?;
+        if !store.use_step(username, step).await? {
+            return Err(AuthError::InvalidTotpCode);
+        }
+
+        let recovery_codes = totp::generate_recovery_codes();
+        let hashes: Vec<String> = recovery_codes
+            .iter()
+            .map(|code| totp::hash_recovery_code(code))
+            .collect();
+        store.confirm_enrollment(username, &hashes).await?;
+        tracing::info!("TOTP enrolled for user {}", username);
+        Ok(recovery_codes)
+    }
+
+    /// Verify the second factor of an enrolled user: a current code from their
+    /// app (each usable once) or an unused recovery code
+    pub async fn verify_totp(&self, username: &str, code: &str) -> Result<(), AuthError> {
+        let (store, cipher) = self
+            .totp()
+            .ok_or_else(|| AuthError::ConfigError("TOTP not enabled".to_string()))?;
+        let enrollment = store
+            .enrollment(username)
+            .await?
+            .filter(|enrollment| enrollment.confirmed)
+            .ok_or(AuthError::InvalidTotpCode)?;
+
+        let secret = cipher.decrypt(&enrollment.secret)?;
+        if let Some(step) = totp::verify_code(&secret, code, unix_now()) {
+            if store.use_step(username, step).await? {
+                return Ok(());
+            }
+            tracing::warn!("Replayed TOTP code for user {}", username);
+        } else if store
+            .use_recovery_code(username, &totp::hash_recovery_code(code))
+            .await?
+        {
+            tracing::info!("Recovery code used by user {}", username);
+            return Ok(());
+        }
+        Err(AuthError::InvalidTotpCode)
+    }
+
+    /// Remove the TOTP enrollment of a user after verifying a current code
+    ///
+    /// Users the policy requires a second factor for cannot remove it.
+    pub async fn disable_totp(
+        &self,
+        session_data: &SessionData,
+        code: &str,
+    ) -> Result<(), AuthError> {
+        if self.totp_required(session_data).await {
+            return Err(AuthError::AccessDenied);
+        }
+        let (store, _) = self
+            .totp()
+            .ok_or_else(|| AuthError::ConfigError("TOTP not enabled".to_string()))?;
+        self.verify_totp(&session_data.handle, code).await?;
+        store.remove(&session_data.handle).await?;
+        tracing::info!("TOTP removed for user {}", session_data.handle);
+        Ok(())
+    }
+
+    /// Set the store of failed login attempts shared across workers and restarts
+    pub fn set_login_attempt_store(&self, store: Arc<dyn LoginAttemptStore>) {
+        *self.login_attempts.write().unwrap() = store;
+    }
+
+    fn login_attempts(&self) -> Arc<dyn LoginAttemptStore> {
+        self.login_attempts.read().unwrap().clone()
+    }
+
+    /// Check that neither a client IP nor an account is locked out
+    ///
+    /// Errors of the store are logged and let the attempt through.
+    pub async fn check_lockout(
+        &self,
+        ip: Option<IpAddr>,
+        username: Option<&str>,
+    ) -> Result<(), AuthError> {
+        if !self.config.lockout.enabled {
+            return Ok(());
+        }
+        let store = self.login_attempts();
+        let now = unix_now();
+        let keys = ip
+            .map(lockout::ip_key)
+            .into_iter()
+            .chain(username.map(lockout::account_key));
+        for key in keys {
+            match store.get(&key).await {
+                Ok(record) => {
+                    if let Some(retry_after) = record.and_then(|record| record.retry_after(now)) {
+                        return Err(AuthError::LockedOut(retry_after));
+                    }
+                }
+                Err(e) => tracing::error!("Failed to check lockout of {}: {}", key, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a failed login or Magic Key attempt from a client IP, and on an
+    /// account if known, locking them out once they reach their threshold
+    pub async fn record_login_failure(&self, ip: Option<IpAddr>, username: Option<&str>) {
+        let config = &self.config.lockout;
+        if !config.enabled {
+            return;
+        }
+        let store = self.login_attempts();
+        let now = unix_now();
+        let keys = [
+            (ip.map(lockout::ip_key), config.max_failures_per_ip),
+            (
+                username.map(lockout::account_key),
+                config.max_failures_per_account,
+            ),
+        ];
+        for (key, max_failures) in keys {
+            let Some(key) = key else {
+                continue;
+            };
+            let update =
+                move |record| AttemptRecord::after_failure(record, now, max_failures, config);
+            let record = match store.update(&key, &update).await {
+                Ok(record) => record,
+                Err(e) => {
+                    tracing::error!("Failed to record login failure of {}: {}", key, e);
+                    continue;
+                }
+            };
+            if let Some(retry_after) = record.retry_after(now) {
+                tracing::warn!(
+                    "Lockout: {} locked out for {} seconds after {} failed attempts",
+                    key,
+                    retry_after,
+                    record.failures
+                );
+                if let Err(e) = store.record_lockout(&key, &record).await {
+                    tracing::error!("Failed to record lockout of {}: {}", key, e);
+                }
+                self.audit(
+                    AuditEvent::new("lockout", AuditOutcome::Denied)
+                        .ip(ip)
+                        .target(key.as_str())
+                        .detail(format!(
+                            "{} failed attempts, locked out for {} seconds",
+                            record.failures, retry_after
+                        )),
+                )
+                .await;
+            }
+        }
+
+        // Records quiet for longer than the window no longer count
+        if let Err(e) = store.prune(now.saturating_sub(config.window_seconds)).await {
+            tracing::error!("Failed to prune login attempts: {}", e);
+        }
+    }
+
+    /// Forget the failed attempts on an account after a complete login
+    pub async fn clear_login_failures(&self, username: &str) {
+        if !self.config.lockout.enabled {
+            return;
+        }
+        let key = lockout::account_key(username);
+        if let Err(e) = self.login_attempts().clear(&key).await {
+            tracing::error!("Failed to clear login failures of {}: {}", key, e);
+        }
+    }
+
+    /// Set the security audit log
+    pub fn set_audit_log(&self, log: Arc<dyn AuditLog>) {
+        *self.audit_log.write().unwrap() = Some(log);
+    }
+
+    /// Record an event to the audit log, if any
+    ///
+    /// Errors of the log are logged; the audited action goes ahead regardless.
+    pub async fn audit(&self, event: AuditEvent) {
+        let Some(log) = self.audit_log.read().unwrap().clone() else {
+            return;
+        };
+        if let Err(e) = log.record(&event).await {
+            tracing::error!("Failed to record audit event {}: {}", event.action, e);
+        }
     }
 
     /// Store temporary OAuth state (PKCE verifier, etc.)
@@ -262,6 +772,60 @@ impl AuthService {
         Ok(())
     }
 
+    /// Set the server-side session store checked on every request
+    pub fn set_session_store(&self, store: Arc<dyn SessionStore>) {
+        *self.session_store.write().unwrap() = Some(store);
+    }
+
+    fn session_store(&self) -> Option<Arc<dyn SessionStore>> {
+        self.session_store.read().unwrap().clone()
+    }
+
+    /// Assign a new session its id and register it with the session store, if any
+    pub async fn register_session(
+        &self,
+        mut session_data: SessionData,
+        client: &ClientInfo,
+    ) -> Result<SessionData, AuthError> {
+        session_data.session_id = Some(uuid::Uuid::new_v4().to_string());
+        if let Some(store) = self.session_store() {
+            store.create(&session_data, client).await?;
+        }
+        Ok(session_data)
+    }
+
+    /// Validate a session (expiration) and, with a session store, check that it
+    /// has not been revoked or been idle too long, recording the activity
+    pub async fn verify_session(
+        &self,
+        session_data: &SessionData,
+        client: &ClientInfo,
+    ) -> Result<(), AuthError> {
+        self.validate_session(session_data)?;
+
+        let Some(store) = self.session_store() else {
+            return Ok(());
+        };
+        // Sessions from before the store was set up cannot be revoked, so they end
+        let Some(session_id) = &session_data.session_id else {
+            return Err(AuthError::SessionNotFound);
+        };
+        if !store
+            .touch(session_id, self.config.session.idle_timeout_seconds, client)
+            .await
+        {
+            return Err(AuthError::InvalidSession);
+        }
+        Ok(())
+    }
+
+    /// End a session at logout, revoking it in the session store
+    pub async fn end_session(&self, session_data: &SessionData) {
+        if let (Some(store), Some(session_id)) = (self.session_store(), &session_data.session_id) {
+            store.revoke(session_id).await;
+        }
+    }
+
     /// Clean up expired OAuth temp states (called periodically or on access)
     pub async fn cleanup_expired_oauth_states(&self) {
         let mut states = self.oauth_temp_states.write().await;
@@ -270,3 +834,10 @@ impl AuthService {
         states.retain(|_, state| !state.is_expired(timeout));
     }
 }
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
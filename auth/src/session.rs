@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 
 /// Authentication provider types
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -8,6 +9,38 @@ pub enum AuthProvider {
     Google,
     LinkedIn,
     PlainLogin, // For future username/password auth
+    /// Generic OpenID Connect provider, identified by its configured name
+    Oidc(String),
+    /// Personal API token (`Authorization: Bearer`) of a user of the named provider
+    ApiToken(String),
+}
+
+impl AuthProvider {
+    /// Names of the built-in providers, which OIDC providers cannot take
+    pub const RESERVED_NAMES: [&'static str; 6] =
+        ["bluesky", "github", "google", "linkedin", "plain", "api_token"];
+
+    /// Name of the provider in role assignments (`"<provider>:<handle>"`)
+    pub fn name(&self) -> &str {
+        match self {
+            AuthProvider::Bluesky => "bluesky",
+            AuthProvider::GitHub => "github",
+            AuthProvider::Google => "google",
+            AuthProvider::LinkedIn => "linkedin",
+            AuthProvider::PlainLogin => "plain",
+            AuthProvider::Oidc(name) => name,
+            AuthProvider::ApiToken(_) => "api_token",
+        }
+    }
+
+    /// Provider the user's roles are assigned under: for API tokens, the
+    /// provider of the token's owner
+    pub fn user_provider(&self) -> &str {
+        match self {
+            AuthProvider::ApiToken(owner) => owner,
+            provider => provider.name(),
+        }
+    }
 }
 
 /// Session data stored for authenticated users (stored in encrypted private cookie)
@@ -31,6 +64,32 @@ pub struct SessionData {
 
     /// Session creation timestamp (Unix timestamp)
     pub created_at: u64,
+
+    /// Random session identifier, assigned when the session is registered at login
+    /// (used to look the session up in the server-side session store)
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// Survey session token issued to a surveyor for a Magic Key (stored in encrypted
+/// private cookie, so it cannot be forged or altered by the client)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SurveySessionData {
+    /// Random token identifier, used to list and revoke the session
+    pub token_id: String,
+
+    /// Magic Key the session was issued for
+    pub magic_key: String,
+
+    /// Issue timestamp (Unix timestamp)
+    pub issued_at: u64,
+
+    /// Expiry timestamp (Unix timestamp)
+    pub expires_at: u64,
+
+    /// Client IP the token is bound to, if IP binding is enabled
+    #[serde(default)]
+    pub client_ip: Option<IpAddr>,
 }
 
 /// Temporary OAuth state stored during authentication flow
@@ -58,6 +117,10 @@ pub struct OAuthTempState {
     /// DPoP private key (for Bluesky)
     pub dpop_private_key: Option<String>,
 
+    /// Nonce expected in the ID token (for OpenID Connect)
+    #[serde(default)]
+    pub nonce: Option<String>,
+
     /// State creation timestamp (Unix timestamp)
     pub created_at: u64,
 }
@@ -81,6 +144,22 @@ impl SessionData {
     }
 }
 
+impl SurveySessionData {
+    pub fn is_expired(&self) -> bool {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        now >= self.expires_at
+    }
+
+    /// Check the IP binding; unbound tokens are valid from any address
+    pub fn is_valid_from(&self, ip: IpAddr) -> bool {
+        self.client_ip.is_none_or(|bound| bound == ip)
+    }
+}
+
 impl OAuthTempState {
     pub fn is_expired(&self, timeout_seconds: u64) -> bool {
         let now = std::time::SystemTime::now()
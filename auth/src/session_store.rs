@@ -0,0 +1,75 @@
+//! Server-side session store
+//!
+//! Sessions live in an encrypted private cookie, so without a store logging out
+//! only clears the browser's copy. When a [`SessionStore`] (such as a database
+//! table) is set, every login is registered in it and every request checks it,
+//! so sessions can be revoked individually or all at once, and sessions idle for
+//! longer than `idle_timeout_seconds` end before their absolute timeout.
+
+use crate::error::AuthError;
+use crate::session::SessionData;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::{header, request::Parts};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+
+/// Client a request comes from, recorded with the session it uses
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// Client IP (requires the service to be built with connect info)
+    pub ip: Option<IpAddr>,
+    /// User-Agent header
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    /// Get the client info from request parts
+    pub fn from_parts(parts: &Parts) -> Self {
+        Self {
+            ip: parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip()),
+            user_agent: parts
+                .headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_parts(parts))
+    }
+}
+
+/// Future returned by [`SessionStore`] methods
+pub type SessionStoreFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Server-side record of the sessions handed out at login
+pub trait SessionStore: Send + Sync {
+    /// Register a new session (the session id is set)
+    fn create<'a>(
+        &'a self,
+        session: &'a SessionData,
+        client: &'a ClientInfo,
+    ) -> SessionStoreFuture<'a, Result<(), AuthError>>;
+
+    /// Check that a session is registered, not revoked and not idle for longer than
+    /// `idle_timeout_seconds`, and record the activity
+    fn touch<'a>(
+        &'a self,
+        session_id: &'a str,
+        idle_timeout_seconds: Option<u64>,
+        client: &'a ClientInfo,
+    ) -> SessionStoreFuture<'a, bool>;
+
+    /// Revoke a session; returns false if it is unknown or already revoked
+    fn revoke<'a>(&'a self, session_id: &'a str) -> SessionStoreFuture<'a, bool>;
+}
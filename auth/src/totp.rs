@@ -0,0 +1,322 @@
+//! TOTP second factor (RFC 6238)
+//!
+//! Plain login users can enroll an authenticator app by scanning a QR code of
+//! the provisioning URI. After a correct password they are asked for a 6-digit
+//! code, or one of the single-use recovery codes handed out at enrollment.
+//! Secrets are encrypted before they reach the [`TotpStore`], and recovery codes
+//! are only stored as hashes.
+
+use crate::error::AuthError;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Number of digits of a code
+pub const DIGITS: usize = 6;
+
+/// Time step of a code in seconds
+pub const PERIOD_SECONDS: u64 = 30;
+
+/// Accepted clock drift, in time steps before and after the current one
+const SKEW_STEPS: u64 = 1;
+
+/// Length of generated secrets in bytes (160 bits, as recommended by RFC 4226)
+const SECRET_LENGTH: usize = 20;
+
+/// Number of recovery codes handed out at enrollment
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Generate a random secret
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_LENGTH];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// Encode a secret in base32, as entered manually into authenticator apps
+pub fn encode_secret(secret: &[u8]) -> String {
+    data_encoding::BASE32_NOPAD.encode(secret)
+}
+
+/// Build the `otpauth://` URI authenticator apps enroll from
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &[u8]) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={PERIOD_SECONDS}",
+        issuer = percent_encode(issuer),
+        account = percent_encode(account),
+        secret = encode_secret(secret),
+    )
+}
+
+/// Render a provisioning URI as an SVG QR code
+pub fn qr_code_svg(uri: &str) -> Result<String, AuthError> {
+    let code = qrcode::QrCode::new(uri.as_bytes())
+        .map_err(|e| AuthError::TotpError(format!("Failed to render QR code: {}", e)))?;
+    Ok(code
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(200, 200)
+        .build())
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// HOTP value (RFC 4226) of a counter
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac =
+        <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS as u32)
+}
+
+/// Code for a time step
+fn code_for_step(secret: &[u8], step: u64) -> String {
+    format!("{:0width$}", hotp(secret, step), width = DIGITS)
+}
+
+/// Code valid at a Unix timestamp
+pub fn code_at(secret: &[u8], unix_time: u64) -> String {
+    code_for_step(secret, unix_time / PERIOD_SECONDS)
+}
+
+/// Check a code against the time steps around a Unix timestamp
+///
+/// Returns the matching time step, to be recorded so the code cannot be replayed.
+pub fn verify_code(secret: &[u8], code: &str, unix_time: u64) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != DIGITS || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let current = unix_time / PERIOD_SECONDS;
+    (current.saturating_sub(SKEW_STEPS)..=current + SKEW_STEPS)
+        .find(|&step| constant_time_eq(code_for_step(secret, step).as_bytes(), code.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Generate single-use recovery codes, formatted as `xxxxx-xxxxx`
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let code = data_encoding::HEXLOWER.encode(&bytes);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Hash of a recovery code as stored, ignoring case, dashes and whitespace
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    data_encoding::HEXLOWER.encode(&Sha256::digest(normalized.as_bytes()))
+}
+
+/// Encryption of TOTP secrets at rest (AES-256-GCM)
+pub struct SecretCipher {
+    cipher: Aes256Gcm,
+}
+
+impl SecretCipher {
+    /// Create a cipher with a key derived from a master secret (the cookie secret)
+    pub fn new(master_secret: &[u8]) -> Self {
+        let key = Sha256::new()
+            .chain_update(b"netpoke-totp-secret:")
+            .chain_update(master_secret)
+            .finalize();
+        Self {
+            cipher: Aes256Gcm::new(&key),
+        }
+    }
+
+    /// Encrypt a secret; the result is the base64 encoded nonce and ciphertext
+    pub fn encrypt(&self, secret: &[u8]) -> Result<String, AuthError> {
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), secret)
+            .map_err(|_| AuthError::TotpError("Failed to encrypt TOTP secret".to_string()))?;
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend(ciphertext);
+        Ok(base64::engine::general_purpose::STANDARD.encode(encrypted))
+    }
+
+    /// Decrypt a secret encrypted with [`SecretCipher::encrypt`]
+    pub fn decrypt(&self, encrypted: &str) -> Result<Vec<u8>, AuthError> {
+        let invalid = || AuthError::TotpError("Failed to decrypt TOTP secret".to_string());
+        let encrypted = base64::engine::general_purpose::STANDARD
+            .decode(encrypted)
+            .map_err(|_| invalid())?;
+        if encrypted.len() < 12 {
+            return Err(invalid());
+        }
+        let (nonce, ciphertext) = encrypted.split_at(12);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| invalid())
+    }
+}
+
+/// Second step a password login needs before the session is created
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TotpRequirement {
+    /// No second factor: create the session
+    NotRequired,
+    /// The user is enrolled and must enter a code
+    Verify,
+    /// A second factor is required by policy but the user has not enrolled yet
+    Enroll,
+}
+
+/// Enrollment data returned when a user starts enrolling
+#[derive(Clone, Debug)]
+pub struct TotpProvisioning {
+    /// Base32 secret for manual entry
+    pub secret: String,
+    /// `otpauth://` provisioning URI
+    pub uri: String,
+    /// QR code of the provisioning URI
+    pub qr_code_svg: String,
+}
+
+/// TOTP enrollment of a user, as kept by a [`TotpStore`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TotpEnrollment {
+    /// Encrypted secret
+    pub secret: String,
+    /// Whether the enrollment was completed with a valid code
+    pub confirmed: bool,
+}
+
+/// Future returned by [`TotpStore`] methods
+pub type TotpStoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, AuthError>> + Send + 'a>>;
+
+/// Store of TOTP enrollments and recovery codes
+pub trait TotpStore: Send + Sync {
+    /// Enrollment of a user, if any
+    fn enrollment<'a>(&'a self, username: &'a str) -> TotpStoreFuture<'a, Option<TotpEnrollment>>;
+
+    /// Start an enrollment with an encrypted secret, replacing an unconfirmed one
+    fn begin_enrollment<'a>(
+        &'a self,
+        username: &'a str,
+        secret: &'a str,
+    ) -> TotpStoreFuture<'a, ()>;
+
+    /// Complete an enrollment and replace the user's recovery codes (as hashes)
+    fn confirm_enrollment<'a>(
+        &'a self,
+        username: &'a str,
+        recovery_code_hashes: &'a [String],
+    ) -> TotpStoreFuture<'a, ()>;
+
+    /// Record the time step of a used code; returns false if this or a later
+    /// step was used already (a replayed code)
+    fn use_step<'a>(&'a self, username: &'a str, step: u64) -> TotpStoreFuture<'a, bool>;
+
+    /// Use up a recovery code; returns false if it is unknown or already used
+    fn use_recovery_code<'a>(
+        &'a self,
+        username: &'a str,
+        code_hash: &'a str,
+    ) -> TotpStoreFuture<'a, bool>;
+
+    /// Remove the enrollment and recovery codes of a user
+    fn remove<'a>(&'a self, username: &'a str) -> TotpStoreFuture<'a, ()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B test vectors (SHA-1), truncated to 6 digits
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        assert_eq!(code_at(RFC_SECRET, 59), "287082");
+        assert_eq!(code_at(RFC_SECRET, 1111111109), "081804");
+        assert_eq!(code_at(RFC_SECRET, 1111111111), "050471");
+        assert_eq!(code_at(RFC_SECRET, 1234567890), "005924");
+        assert_eq!(code_at(RFC_SECRET, 2000000000), "279037");
+    }
+
+    #[test]
+    fn test_verify_code_window() {
+        let now = 1234567890;
+        let step = now / PERIOD_SECONDS;
+        assert_eq!(verify_code(RFC_SECRET, "005924", now), Some(step));
+        // Previous and next periods are accepted for clock drift
+        let previous = code_at(RFC_SECRET, now - PERIOD_SECONDS);
+        assert_eq!(verify_code(RFC_SECRET, &previous, now), Some(step - 1));
+        let later = code_at(RFC_SECRET, now + 3 * PERIOD_SECONDS);
+        assert_eq!(verify_code(RFC_SECRET, &later, now), None);
+        assert_eq!(verify_code(RFC_SECRET, " 005924 ", now), Some(step));
+        assert_eq!(verify_code(RFC_SECRET, "5924", now), None);
+        assert_eq!(verify_code(RFC_SECRET, "00592a", now), None);
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        let uri = provisioning_uri("NetPoke Lab", "alice@example.com", RFC_SECRET);
+        assert_eq!(
+            uri,
+            "otpauth://totp/NetPoke%20Lab:alice%40example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=NetPoke%20Lab&algorithm=SHA1&digits=6&period=30"
+        );
+        assert!(qr_code_svg(&uri).unwrap().starts_with("<?xml"));
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes
+            .iter()
+            .all(|code| code.len() == 11 && &code[5..6] == "-"));
+        assert_eq!(
+            hash_recovery_code(&codes[0]),
+            hash_recovery_code(&format!(" {} ", codes[0].to_uppercase().replace('-', "")))
+        );
+        assert_ne!(hash_recovery_code(&codes[0]), hash_recovery_code(&codes[1]));
+    }
+
+    #[test]
+    fn test_secret_cipher() {
+        let cipher = SecretCipher::new(&[7u8; 64]);
+        let secret = generate_secret();
+        let encrypted = cipher.encrypt(&secret).unwrap();
+        assert_ne!(encrypted, encode_secret(&secret));
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), secret);
+        assert!(SecretCipher::new(&[8u8; 64]).decrypt(&encrypted).is_err());
+        assert!(cipher.decrypt("not base64!").is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use crate::totp::TotpProvisioning;
+
 /// NetPoke login page HTML - light theme with blue/orange accents
 pub fn login_page_html(
     enable_plain: bool,
@@ -5,6 +7,7 @@ pub fn login_page_html(
     enable_github: bool,
     enable_google: bool,
     enable_linkedin: bool,
+    oidc_providers: &[(String, String)],
 ) -> String {
     let mut providers_html = String::new();
 
@@ -101,6 +104,22 @@ pub fn login_page_html(
         "#);
     }
 
+    for (name, display_name) in oidc_providers {
+        let display_name = escape_html(display_name);
+        providers_html.push_str(&format!(
+            r#"
+        <div class="provider-section">
+            <h3>Login with {display_name}</h3>
+            <a href="/auth/oidc/{name}/login" class="btn btn-primary">
+                Login with {display_name}
+            </a>
+        </div>
+        "#,
+            name = escape_html(name),
+            display_name = display_name,
+        ));
+    }
+
     format!(
         r#"<!DOCTYPE html>
 <html>
@@ -573,3 +592,297 @@ pub fn access_denied_page_html(user_handle: &str) -> String {
         user_handle
     )
 }
+
+/// Second login step: code from the authenticator app or a recovery code
+pub fn totp_page_html(error: bool) -> String {
+    card_page_html(
+        "Two-Factor Authentication",
+        &format!(
+            r#"
+        <p>Enter the 6-digit code from your authenticator app, or one of your recovery codes.</p>
+        {}
+        <form method="post" action="/auth/totp">
+            <input type="text" name="code" autocomplete="one-time-code" autofocus required>
+            <button type="submit" class="btn">Verify</button>
+        </form>
+        <p class="hint"><a href="/auth/login">Back to login</a></p>
+        "#,
+            totp_error_html(error)
+        ),
+    )
+}
+
+/// TOTP enrollment page with the QR code of the provisioning URI
+pub fn totp_enroll_page_html(
+    provisioning: &TotpProvisioning,
+    required: bool,
+    error: bool,
+) -> String {
+    let intro = if required {
+        "Your account requires two-factor authentication. Scan the QR code with an authenticator app, then enter the code it shows."
+    } else {
+        "Scan the QR code with an authenticator app, then enter the code it shows."
+    };
+    card_page_html(
+        "Set Up Two-Factor Authentication",
+        &format!(
+            r#"
+        <p>{intro}</p>
+        <div class="qr"><a href="{uri}">{qr}</a></div>
+        <p class="hint">Or enter this key manually:</p>
+        <p class="secret">{secret}</p>
+        {error}
+        <form method="post" action="/auth/totp/enroll">
+            <input type="text" name="code" autocomplete="one-time-code" required>
+            <button type="submit" class="btn">Enable</button>
+        </form>
+        "#,
+            intro = intro,
+            uri = escape_html(&provisioning.uri),
+            qr = provisioning.qr_code_svg,
+            secret = escape_html(&provisioning.secret),
+            error = totp_error_html(error),
+        ),
+    )
+}
+
+/// Recovery codes, shown once after enrollment
+pub fn totp_recovery_codes_page_html(codes: &[String]) -> String {
+    let codes_html: String = codes
+        .iter()
+        .map(|code| format!("<li>{}</li>", escape_html(code)))
+        .collect();
+    card_page_html(
+        "Two-Factor Authentication Enabled",
+        &format!(
+            r#"
+        <p>Save these recovery codes in a safe place. Each one can be used once instead of a code from your app. They will not be shown again.</p>
+        <ul class="codes">{}</ul>
+        <a href="/" class="btn">Continue</a>
+        "#,
+            codes_html
+        ),
+    )
+}
+
+/// Page for enrolled users to remove their second factor
+pub fn totp_manage_page_html(error: bool) -> String {
+    card_page_html(
+        "Two-Factor Authentication",
+        &format!(
+            r#"
+        <p>Two-factor authentication is enabled for your account. To remove it, enter a current code or a recovery code.</p>
+        {}
+        <form method="post" action="/auth/totp/disable">
+            <input type="text" name="code" autocomplete="one-time-code" required>
+            <button type="submit" class="btn btn-danger">Remove</button>
+        </form>
+        <p class="hint"><a href="/">Back</a></p>
+        "#,
+            totp_error_html(error)
+        ),
+    )
+}
+
+fn totp_error_html(error: bool) -> &'static str {
+    if error {
+        r#"<p class="error">Invalid code, please try again.</p>"#
+    } else {
+        ""
+    }
+}
+
+/// Password change page for plain login users, with the reason a previous
+/// attempt failed or a confirmation that it succeeded
+pub fn password_change_page_html(error: Option<&str>, changed: bool) -> String {
+    let status = match error {
+        Some(error) => format!(r#"<p class="error">{}</p>"#, escape_html(error)),
+        None if changed => r#"<p class="success">Your password has been changed.</p>"#.to_string(),
+        None => String::new(),
+    };
+    card_page_html(
+        "Change Password",
+        &format!(
+            r#"
+        {}
+        <form method="post" action="/auth/password">
+            <label for="current_password">Current password</label>
+            <input type="password" id="current_password" name="current_password" autocomplete="current-password" required>
+            <label for="new_password">New password</label>
+            <input type="password" id="new_password" name="new_password" autocomplete="new-password" required>
+            <label for="confirm_password">Confirm new password</label>
+            <input type="password" id="confirm_password" name="confirm_password" autocomplete="new-password" required>
+            <button type="submit" class="btn">Change Password</button>
+        </form>
+        <p class="hint"><a href="/">Back</a></p>
+        "#,
+            status
+        ),
+    )
+}
+
+/// Card layout shared by the TOTP and password pages
+fn card_page_html(title: &str, content: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <title>{title} - NetPoke</title>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <style>
+        * {{
+            box-sizing: border-box;
+            margin: 0;
+            padding: 0;
+        }}
+
+        body {{
+            font-family: Arial, sans-serif;
+            background-color: #f5f5f5;
+            min-height: 100vh;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+            padding: 20px;
+        }}
+
+        .container {{
+            background: white;
+            border-radius: 8px;
+            box-shadow: 0 2px 10px rgba(0, 0, 0, 0.1);
+            max-width: 450px;
+            width: 100%;
+            padding: 40px;
+            text-align: center;
+            border-top: 4px solid #2196F3;
+        }}
+
+        h1 {{
+            color: #333;
+            font-size: 22px;
+            font-weight: bold;
+            margin-bottom: 16px;
+        }}
+
+        p {{
+            color: #666;
+            font-size: 14px;
+            line-height: 1.6;
+            margin-bottom: 12px;
+        }}
+
+        .hint {{
+            font-size: 12px;
+            color: #999;
+        }}
+
+        .hint a {{
+            color: #2196F3;
+            text-decoration: none;
+        }}
+
+        .error {{
+            color: #c62828;
+        }}
+
+        .qr svg {{
+            width: 200px;
+            height: 200px;
+            margin: 8px auto;
+        }}
+
+        .secret {{
+            font-family: monospace;
+            font-size: 15px;
+            color: #333;
+            word-break: break-all;
+        }}
+
+        .codes {{
+            list-style: none;
+            font-family: monospace;
+            font-size: 15px;
+            color: #333;
+            columns: 2;
+            margin: 20px 0;
+        }}
+
+        input[type="password"] {{
+            width: 100%;
+            padding: 12px;
+            border: 1px solid #ddd;
+            border-radius: 4px;
+            font-size: 15px;
+            margin: 4px 0 12px;
+        }}
+
+        form label {{
+            display: block;
+            text-align: left;
+            color: #333;
+            font-size: 13px;
+        }}
+
+        .success {{
+            color: #2e7d32;
+        }}
+
+        input[type="text"] {{
+            width: 100%;
+            padding: 12px;
+            border: 1px solid #ddd;
+            border-radius: 4px;
+            font-size: 18px;
+            text-align: center;
+            letter-spacing: 2px;
+            margin: 8px 0 16px;
+        }}
+
+        .btn {{
+            display: inline-block;
+            padding: 12px 24px;
+            background-color: #2196F3;
+            color: white;
+            text-decoration: none;
+            border: none;
+            border-radius: 4px;
+            font-size: 15px;
+            font-weight: 600;
+            cursor: pointer;
+            transition: background-color 0.2s ease;
+        }}
+
+        .btn:hover {{
+            background-color: #1976D2;
+        }}
+
+        .btn-danger {{
+            background-color: #f44336;
+        }}
+
+        .btn-danger:hover {{
+            background-color: #d32f2f;
+        }}
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>{title}</h1>
+        {content}
+    </div>
+</body>
+</html>"#,
+        title = escape_html(title),
+        content = content,
+    )
+}
+
+/// Escape text for inclusion in HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -1,7 +1,9 @@
 mod measurements;
+mod nat_behavior;
 mod recorder;
 mod signaling;
 mod webrtc;
+mod websocket_probes;
 use crate::measurements::current_time_ms;
 
 use gloo_timers::callback::Interval;
@@ -12,7 +14,7 @@ use std::rc::Rc;
 use std::sync::Mutex;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{window, Document, RtcPeerConnection};
+use web_sys::{window, Document, RtcPeerConnection, WebSocket};
 
 // Global SENSOR_MANAGER for recorder subsystem
 static SENSOR_MANAGER: Lazy<Mutex<Option<recorder::sensors::SensorManager>>> =
@@ -50,6 +52,31 @@ const TRACEROUTE_ROUNDS: u32 = 3;
 const MTU_TRACEROUTE_ROUNDS: u32 = 9;
 // MTU sizes to test (in bytes)
 const MTU_SIZES: [u32; 9] = [576, 1280, 1350, 1400, 1450, 1472, 1490, 1500, 1500];
+// IPv6 extension header tests (RFC 9098): header type and size in bytes
+const EXT_HEADER_TESTS: [(common::Ipv6ExtHeaderType, u16); 3] = [
+    (common::Ipv6ExtHeaderType::DestinationOptions, 8),
+    (common::Ipv6ExtHeaderType::HopByHop, 8),
+    (common::Ipv6ExtHeaderType::DestinationOptions, 256),
+];
+// Time the server waits for ICMPv6 errors after an extension header test
+const EXT_HEADER_COLLECT_TIMEOUT_MS: usize = 2000;
+// Maximum wait for the results of an extension header test round
+const EXT_HEADER_TEST_MAX_WAIT_MS: u32 = 10000;
+// IP fragmentation tests: packet size in bytes, and whether the fragments are
+// sent out of order. Sizes above the path MTU are the ones that get fragmented
+const FRAGMENTATION_TESTS: [(u32, bool); 4] =
+    [(1800, false), (4000, false), (8000, false), (1800, true)];
+// Time the server waits for echoes and ICMP errors after a fragmentation test
+const FRAGMENTATION_COLLECT_TIMEOUT_MS: usize = 2000;
+// Maximum wait for the results of a fragmentation test round
+const FRAGMENTATION_TEST_MAX_WAIT_MS: u32 = 10000;
+// NAT binding timeout test: shortest and longest idle periods, and the
+// precision at which the search stops
+const NAT_LIFETIME_MIN_IDLE_MS: u64 = 5000;
+const NAT_LIFETIME_MAX_IDLE_MS: u64 = 600000;
+const NAT_LIFETIME_RESOLUTION_MS: u64 = 5000;
+// Maximum wait for the results of the NAT type classification tests
+const NAT_BEHAVIOR_TEST_MAX_WAIT_MS: u32 = 10000;
 // Timeout in milliseconds for control channels to be ready
 const CONTROL_CHANNEL_READY_TIMEOUT_MS: u32 = 2000;
 
@@ -58,6 +85,7 @@ thread_local! {
     static WAKE_LOCK: RefCell<Option<JsValue>> = RefCell::new(None);
     static ACTIVE_PEERS: RefCell<Vec<RtcPeerConnection>> = RefCell::new(Vec::new());
     static ACTIVE_INTERVALS: RefCell<Vec<Interval>> = RefCell::new(Vec::new());
+    static ACTIVE_WEBSOCKETS: RefCell<Vec<WebSocket>> = const { RefCell::new(Vec::new()) };
     static IS_TESTING_ACTIVE: RefCell<bool> = RefCell::new(false);
     // Flag to signal that testing should be aborted
     static ABORT_TESTING: RefCell<bool> = RefCell::new(false);
@@ -74,12 +102,20 @@ thread_local! {
 struct ClientConfig {
     /// Delay in milliseconds between WebRTC connection establishment attempts
     webrtc_connection_delay_ms: u32,
+    /// Ports of the server's NAT behavior responder (empty when disabled)
+    #[serde(default)]
+    nat_behavior_ports: Vec<u16>,
+    /// Alternate addresses of the server's NAT behavior responder
+    #[serde(default)]
+    nat_behavior_alternate_addresses: Vec<String>,
 }
 
 impl Default for ClientConfig {
     fn default() -> Self {
         Self {
             webrtc_connection_delay_ms: DEFAULT_WEBRTC_CONNECTION_DELAY_MS,
+            nat_behavior_ports: Vec::new(),
+            nat_behavior_alternate_addresses: Vec::new(),
         }
     }
 }
@@ -374,6 +410,13 @@ fn register_interval(interval: Interval) {
     });
 }
 
+/// Register a WebSocket to be closed with the peer connections
+fn register_websocket(socket: WebSocket) {
+    ACTIVE_WEBSOCKETS.with(|sockets| {
+        sockets.borrow_mut().push(socket);
+    });
+}
+
 /// Clear all tracked connections and intervals
 fn clear_active_resources() {
     // Close all peer connections
@@ -384,6 +427,13 @@ fn clear_active_resources() {
         }
     });
 
+    // Close all WebSockets
+    ACTIVE_WEBSOCKETS.with(|sockets| {
+        for socket in sockets.borrow_mut().drain(..) {
+            let _ = socket.close();
+        }
+    });
+
     // Cancel all intervals
     ACTIVE_INTERVALS.with(|intervals| {
         let mut intervals_mut = intervals.borrow_mut();
@@ -929,6 +979,60 @@ pub async fn analyze_network_with_options(
         sleep_ms(100).await;
     }
 
+    // PHASE 0.3: NAT binding timeout, on a dedicated connection per address
+    // family that the server leaves idle. The test takes minutes and runs in
+    // the background during the other phases
+    let mut nat_lifetime_connections: Vec<webrtc::WebRtcConnection> = Vec::new();
+    if !skip_path_tests {
+        log::info!("PHASE 0.3: Starting NAT binding timeout tests...");
+        set_doc_status("PHASE 0.3: Starting NAT binding timeout tests...");
+
+        for ip_version in ["ipv4", "ipv6"] {
+            if should_abort_testing() {
+                log::info!("Testing aborted during NAT binding timeout test setup");
+                return Ok(());
+            }
+
+            let mut conn = webrtc::WebRtcConnection::new_with_ip_version_and_mode(
+                ip_version,
+                parent_id.clone(),
+                Some(common::NAT_LIFETIME_MODE.to_string()),
+                None,
+            )
+            .await?;
+            conn.set_traceroute_mode(true);
+
+            if conn
+                .wait_for_control_channel_ready(CONTROL_CHANNEL_READY_TIMEOUT_MS)
+                .await
+            {
+                if let Err(e) = conn
+                    .send_start_survey_session(&survey_session_id, magic_key.clone())
+                    .await
+                {
+                    log::warn!("Failed to send StartSurveySession: {:?}", e);
+                }
+                if let Err(e) = conn
+                    .send_start_nat_lifetime_test(
+                        &survey_session_id,
+                        NAT_LIFETIME_MIN_IDLE_MS,
+                        NAT_LIFETIME_MAX_IDLE_MS,
+                        NAT_LIFETIME_RESOLUTION_MS,
+                    )
+                    .await
+                {
+                    log::warn!("Failed to send StartNatLifetimeTest: {:?}", e);
+                }
+            } else {
+                log::warn!(
+                    "Control channel not ready for {} NAT binding timeout connection, skipping test",
+                    ip_version
+                );
+            }
+            nat_lifetime_connections.push(conn);
+        }
+    }
+
     // PHASE 1: Traceroute (5 rounds) - skip if skip_path_tests is set
     if skip_path_tests {
         log::info!("PHASE 1: Skipping traceroute (skip_path_tests=true)");
@@ -1095,6 +1199,188 @@ pub async fn analyze_network_with_options(
 
     log::info!("PHASE 2 complete: MTU traceroute finished");
 
+    // PHASE 2b: IPv6 extension header survivability, on the IPv6 connections
+    if !ipv6_connections.is_empty() {
+        log::info!(
+            "PHASE 2b: Testing IPv6 extension headers {:?}...",
+            EXT_HEADER_TESTS
+        );
+        set_doc_status("PHASE 2b: Testing IPv6 extension headers...");
+    }
+
+    for &(header_type, header_size) in EXT_HEADER_TESTS.iter() {
+        if ipv6_connections.is_empty() {
+            break;
+        }
+        if should_abort_testing() {
+            log::info!("Testing aborted during extension header phase");
+            return Ok(());
+        }
+
+        for conn in ipv6_connections.iter() {
+            if conn.failed {
+                log::warn!("conn is failed, ignore");
+                continue;
+            }
+            let path_ttl = conn
+                .state
+                .borrow()
+                .path_ttl
+                .filter(|&path_ttl| path_ttl > 0)
+                .unwrap_or(16);
+
+            if let Err(e) = conn
+                .send_start_ext_header_test(
+                    &survey_session_id,
+                    header_type,
+                    header_size,
+                    path_ttl,
+                    EXT_HEADER_COLLECT_TIMEOUT_MS,
+                )
+                .await
+            {
+                log::warn!("Failed to send StartExtHeaderTest: {:?}", e);
+            }
+        }
+
+        let mut count = EXT_HEADER_TEST_MAX_WAIT_MS / TRACE_POLL_CHECK_MS;
+        loop {
+            sleep_ms(TRACE_POLL_CHECK_MS).await;
+            let total_active: usize = ipv6_connections
+                .iter()
+                .filter(|conn| !conn.failed)
+                .map(|conn| {
+                    let st = conn.state.borrow();
+                    st.ext_header_test_started - st.ext_header_test_done
+                })
+                .sum();
+            if count == 0 || total_active == 0 {
+                break;
+            }
+            count -= 1;
+        }
+    }
+
+    // PHASE 2c: IP fragmentation delivery, on all connections
+    log::info!(
+        "PHASE 2c: Testing IP fragmentation {:?}...",
+        FRAGMENTATION_TESTS
+    );
+    set_doc_status("PHASE 2c: Testing IP fragmentation...");
+
+    for &(packet_size, out_of_order) in FRAGMENTATION_TESTS.iter() {
+        if should_abort_testing() {
+            log::info!("Testing aborted during fragmentation phase");
+            return Ok(());
+        }
+
+        for conn in ipv4_connections.iter().chain(ipv6_connections.iter()) {
+            if conn.failed {
+                log::warn!("conn is failed, ignore");
+                continue;
+            }
+
+            if let Err(e) = conn
+                .send_start_fragmentation_test(
+                    &survey_session_id,
+                    packet_size,
+                    out_of_order,
+                    FRAGMENTATION_COLLECT_TIMEOUT_MS,
+                )
+                .await
+            {
+                log::warn!("Failed to send StartFragmentationTest: {:?}", e);
+            }
+        }
+
+        let mut count = FRAGMENTATION_TEST_MAX_WAIT_MS / TRACE_POLL_CHECK_MS;
+        loop {
+            sleep_ms(TRACE_POLL_CHECK_MS).await;
+            let total_active: usize = ipv4_connections
+                .iter()
+                .chain(ipv6_connections.iter())
+                .filter(|conn| !conn.failed)
+                .map(|conn| {
+                    let st = conn.state.borrow();
+                    st.fragmentation_test_started - st.fragmentation_test_done
+                })
+                .sum();
+            if count == 0 || total_active == 0 {
+                break;
+            }
+            count -= 1;
+        }
+    }
+
+    // PHASE 2d: NAT type classification, on one connection per address family
+    if !client_config.nat_behavior_ports.is_empty() {
+        log::info!("PHASE 2d: Classifying NAT type...");
+        set_doc_status("PHASE 2d: Classifying NAT type...");
+
+        let observations = match nat_behavior::gather_mapped_addresses(
+            &client_config.nat_behavior_ports,
+            &client_config.nat_behavior_alternate_addresses,
+        )
+        .await
+        {
+            Ok(observations) => observations,
+            Err(e) => {
+                log::warn!("Failed to gather NAT mapped addresses: {:?}", e);
+                Default::default()
+            }
+        };
+
+        for (connections, ipv6) in [(&ipv4_connections, false), (&ipv6_connections, true)] {
+            if should_abort_testing() {
+                log::info!("Testing aborted during NAT type phase");
+                return Ok(());
+            }
+            let Some(conn) = connections.iter().find(|conn| !conn.failed) else {
+                continue;
+            };
+
+            let hairpinning =
+                match nat_behavior::test_hairpinning(&client_config.nat_behavior_ports, ipv6)
+                    .await
+                {
+                    Ok(hairpinning) => hairpinning,
+                    Err(e) => {
+                        log::warn!("Failed to test NAT hairpinning: {:?}", e);
+                        None
+                    }
+                };
+            if let Err(e) = conn
+                .send_start_nat_behavior_test(
+                    &survey_session_id,
+                    observations.mapped_addresses(ipv6),
+                    observations.failed_servers(ipv6),
+                    hairpinning,
+                )
+                .await
+            {
+                log::warn!("Failed to send StartNatBehaviorTest: {:?}", e);
+            }
+        }
+
+        let mut count = NAT_BEHAVIOR_TEST_MAX_WAIT_MS / TRACE_POLL_CHECK_MS;
+        loop {
+            sleep_ms(TRACE_POLL_CHECK_MS).await;
+            let total_active: usize = ipv4_connections
+                .iter()
+                .chain(ipv6_connections.iter())
+                .filter(|conn| !conn.failed)
+                .map(|conn| {
+                    let st = conn.state.borrow();
+                    st.nat_behavior_test_started - st.nat_behavior_test_done
+                })
+                .sum();
+            if count == 0 || total_active == 0 {
+                break;
+            }
+            count -= 1;
+        }
+    }
+
     // Add a brief pause between phases to allow server processing to complete
     // sleep_ms(1000).await;
     } // end of else !skip_path_tests (Phase 1 traceroute + Phase 2 MTU)
@@ -1168,17 +1454,7 @@ pub async fn analyze_network_with_options(
                         return;
                     }
 
-                    let seq = state.measurement_probe_seq;
-                    state.measurement_probe_seq += 1;
-                    let feedback = state.last_feedback.clone();
-
-                    let probe = common::MeasurementProbePacket {
-                        seq,
-                        sent_at_ms: current_time_ms(),
-                        direction: common::Direction::ClientToServer,
-                        conn_id: conn_id.clone(),
-                        feedback,
-                    };
+                    let probe = state.next_measurement_probe(&conn_id, current_time_ms());
 
                     if let Ok(json) = serde_json::to_string(&probe) {
                         if let Err(e) = channel.send_with_str(&json) {
@@ -1237,6 +1513,31 @@ pub async fn analyze_network_with_options(
         register_interval(interval);
     }
 
+    // Start the probe stream over WebSocket (TCP) to compare with the WebRTC
+    // probe streams (UDP); with ICE failed on every connection it is the only one
+    let webrtc_states: Vec<_> = ipv4_connections
+        .iter()
+        .chain(ipv6_connections.iter())
+        .filter(|conn| !conn.failed)
+        .map(|conn| conn.state.clone())
+        .collect();
+    if webrtc_states.is_empty() {
+        log::warn!("No WebRTC connection established, measuring over WebSocket only");
+        set_doc_status(
+            "PHASE 3: No WebRTC connection established, measuring over WebSocket only...",
+        );
+    }
+    if let Err(e) = websocket_probes::start_probe_stream(
+        &survey_session_id,
+        &generate_uuid(),
+        magic_key.clone(),
+        webrtc_states,
+    )
+    .await
+    {
+        log::warn!("Failed to start WebSocket probe stream: {:?}", e);
+    }
+
     // Collect states for calculation and UI updates
     let mut calc_states: Vec<Rc<RefCell<measurements::MeasurementState>>> = Vec::new();
     for conn in &ipv4_connections {
@@ -1293,6 +1594,10 @@ pub async fn analyze_network_with_options(
         register_peer(conn.peer.clone());
         std::mem::forget(conn);
     }
+    for conn in nat_lifetime_connections {
+        register_peer(conn.peer.clone());
+        std::mem::forget(conn);
+    }
 
     set_testing_active(true);
 
@@ -1398,9 +1703,13 @@ fn calculate_client_s2c_stats(state: &measurements::MeasurementState) -> common:
         0.0
     };
 
+    // RTT from the feedback the server sent with the probes
+    let mut rtts: Vec<f64> = recent_probes.iter().filter_map(|p| p.rtt_ms).collect();
+    let rtt_ms = common::percentiles(&mut rtts);
+
     common::DirectionStats {
         delay_deviation_ms,
-        rtt_ms: [0.0; 4], // RTT requires echo, not calculated here
+        rtt_ms,
         jitter_ms,
         loss_rate,
         reorder_rate,
@@ -20,6 +20,14 @@ pub struct MeasurementState {
     pub traceroute_done: usize,
     pub mtu_traceroute_started: usize,
     pub mtu_traceroute_done: usize,
+    pub ext_header_test_started: usize,
+    pub ext_header_test_done: usize,
+    pub fragmentation_test_started: usize,
+    pub fragmentation_test_done: usize,
+    pub nat_lifetime_test_started: usize,
+    pub nat_lifetime_test_done: usize,
+    pub nat_behavior_test_started: usize,
+    pub nat_behavior_test_done: usize,
     pub traceroute_active: bool,
     pub server_side_ready: bool,
     pub measuring_time_ms: Option<u64>,
@@ -28,6 +36,7 @@ pub struct MeasurementState {
     // Probe stream fields
     pub probe_streams_active: bool,
     pub measurement_probe_seq: u64,
+    pub sent_measurement_probes: VecDeque<SentMeasurementProbe>,
     pub received_measurement_probes: VecDeque<ReceivedMeasurementProbe>,
     pub baseline_delay_sum: f64,
     pub baseline_delay_count: u64,
@@ -56,6 +65,14 @@ pub struct ReceivedMeasurementProbe {
     pub sent_at_ms: u64,
     pub received_at_ms: u64,
     pub feedback: common::ProbeFeedback,
+    pub rtt_ms: Option<f64>, // RTT from the feedback in this probe
+}
+
+/// Sent measurement probe, kept to take the RTT from the server's feedback
+#[derive(Clone, Debug)]
+pub struct SentMeasurementProbe {
+    pub seq: u64,
+    pub sent_at_ms: u64,
 }
 
 impl MeasurementState {
@@ -76,6 +93,14 @@ impl MeasurementState {
             traceroute_done: 0,
             mtu_traceroute_started: 0,
             mtu_traceroute_done: 0,
+            ext_header_test_started: 0,
+            ext_header_test_done: 0,
+            fragmentation_test_started: 0,
+            fragmentation_test_done: 0,
+            nat_lifetime_test_started: 0,
+            nat_lifetime_test_done: 0,
+            nat_behavior_test_started: 0,
+            nat_behavior_test_done: 0,
             traceroute_active: false,
             server_side_ready: false,
             measuring_time_ms: None,
@@ -83,6 +108,7 @@ impl MeasurementState {
             // Probe stream fields
             probe_streams_active: false,
             measurement_probe_seq: 0,
+            sent_measurement_probes: VecDeque::new(),
             received_measurement_probes: VecDeque::new(),
             baseline_delay_sum: 0.0,
             baseline_delay_count: 0,
@@ -92,6 +118,127 @@ impl MeasurementState {
         }
     }
 
+    /// Next measurement probe to send, carrying feedback about received probes
+    pub fn next_measurement_probe(
+        &mut self,
+        conn_id: &str,
+        now_ms: u64,
+    ) -> common::MeasurementProbePacket {
+        let seq = self.measurement_probe_seq;
+        self.measurement_probe_seq += 1;
+
+        self.sent_measurement_probes
+            .push_back(SentMeasurementProbe {
+                seq,
+                sent_at_ms: now_ms,
+            });
+        let cutoff = now_ms.saturating_sub(common::PROBE_STATS_WINDOW_MS);
+        while let Some(p) = self.sent_measurement_probes.front() {
+            if p.sent_at_ms < cutoff {
+                self.sent_measurement_probes.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        common::MeasurementProbePacket {
+            seq,
+            sent_at_ms: now_ms,
+            direction: Direction::ClientToServer,
+            conn_id: conn_id.to_string(),
+            feedback: self.last_feedback.clone(),
+        }
+    }
+
+    /// Record a received measurement probe: baseline delay, RTT from its
+    /// feedback, and the feedback for outgoing probes
+    pub fn record_measurement_probe(
+        &mut self,
+        probe: &common::MeasurementProbePacket,
+        now_ms: u64,
+    ) {
+        // Use signed arithmetic to handle clock skew between client and server
+        // If server clock is ahead, delay will be negative; if behind, it will be larger than actual
+        // The baseline calculation will capture the clock offset, and deviations will be meaningful
+        let delay = (now_ms as i64 - probe.sent_at_ms as i64) as f64;
+
+        // RTT from the feedback about the highest probe the server has received from us
+        let rtt_ms = self
+            .sent_measurement_probes
+            .binary_search_by_key(&probe.feedback.highest_seq, |p| p.seq)
+            .ok()
+            .and_then(|index| {
+                common::feedback_rtt_ms(
+                    self.sent_measurement_probes[index].sent_at_ms,
+                    probe,
+                    now_ms,
+                )
+            });
+
+        // Store received probe
+        self.received_measurement_probes
+            .push_back(ReceivedMeasurementProbe {
+                seq: probe.seq,
+                sent_at_ms: probe.sent_at_ms,
+                received_at_ms: now_ms,
+                feedback: probe.feedback.clone(),
+                rtt_ms,
+            });
+
+        // Update baseline delay (exponential moving average with outlier exclusion)
+        // Use absolute difference to handle negative delays due to clock skew
+        let baseline = if self.baseline_delay_count > 0 {
+            self.baseline_delay_sum / self.baseline_delay_count as f64
+        } else {
+            delay
+        };
+
+        // Use absolute difference from baseline for outlier detection
+        // This works correctly even with clock skew (negative delays)
+        let deviation_from_baseline = (delay - baseline).abs();
+        let baseline_threshold = baseline.abs() * common::BASELINE_OUTLIER_MULTIPLIER;
+        if self.baseline_delay_count < common::BASELINE_MIN_SAMPLES
+            || deviation_from_baseline < baseline_threshold.max(common::BASELINE_MIN_THRESHOLD_MS)
+        {
+            self.baseline_delay_sum += delay;
+            self.baseline_delay_count += 1;
+        }
+
+        // Update feedback for outgoing probes; the receive time is that of the
+        // highest seq, which the server needs to take the RTT from it
+        if probe.seq >= self.last_feedback.highest_seq {
+            self.last_feedback.highest_seq = probe.seq;
+            self.last_feedback.highest_seq_received_at_ms = now_ms;
+        }
+
+        // Keep only last PROBE_STATS_WINDOW_MS of probes for stats calculation
+        let cutoff = now_ms.saturating_sub(common::PROBE_STATS_WINDOW_MS);
+        while let Some(p) = self.received_measurement_probes.front() {
+            if p.received_at_ms < cutoff {
+                self.received_measurement_probes.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        // Count recent probes and reorders for feedback
+        let mut recent_count = 0u32;
+        let mut recent_reorders = 0u32;
+        let mut last_seq = 0u64;
+        let feedback_cutoff = now_ms.saturating_sub(common::PROBE_FEEDBACK_WINDOW_MS);
+        for p in self.received_measurement_probes.iter() {
+            if p.received_at_ms >= feedback_cutoff {
+                recent_count += 1;
+                if p.seq < last_seq {
+                    recent_reorders += 1;
+                }
+                last_seq = last_seq.max(p.seq);
+            }
+        }
+        self.last_feedback.recent_count = recent_count;
+        self.last_feedback.recent_reorders = recent_reorders;
+    }
+
     pub fn clear_metrics(&mut self) {
         // Clear accumulated measurement data
         self.received_probes.clear();
@@ -242,77 +389,9 @@ pub fn setup_probe_channel(channel: RtcDataChannel, state: Rc<RefCell<Measuremen
                 if probe_streams_active {
                     if let Ok(probe) = serde_json::from_str::<common::MeasurementProbePacket>(&txt)
                     {
-                        let now_ms = current_time_ms();
-                        // Use signed arithmetic to handle clock skew between client and server
-                        // If server clock is ahead, delay will be negative; if behind, it will be larger than actual
-                        // The baseline calculation will capture the clock offset, and deviations will be meaningful
-                        let delay = (now_ms as i64 - probe.sent_at_ms as i64) as f64;
-
-                        let mut state = state_receiver.borrow_mut();
-
-                        // Store received probe
-                        state
-                            .received_measurement_probes
-                            .push_back(ReceivedMeasurementProbe {
-                                seq: probe.seq,
-                                sent_at_ms: probe.sent_at_ms,
-                                received_at_ms: now_ms,
-                                feedback: probe.feedback.clone(),
-                            });
-
-                        // Update baseline delay (exponential moving average with outlier exclusion)
-                        // Use absolute difference to handle negative delays due to clock skew
-                        let baseline = if state.baseline_delay_count > 0 {
-                            state.baseline_delay_sum / state.baseline_delay_count as f64
-                        } else {
-                            delay
-                        };
-
-                        // Use absolute difference from baseline for outlier detection
-                        // This works correctly even with clock skew (negative delays)
-                        let deviation_from_baseline = (delay - baseline).abs();
-                        let baseline_threshold =
-                            baseline.abs() * common::BASELINE_OUTLIER_MULTIPLIER;
-                        if state.baseline_delay_count < common::BASELINE_MIN_SAMPLES
-                            || deviation_from_baseline
-                                < baseline_threshold.max(common::BASELINE_MIN_THRESHOLD_MS)
-                        {
-                            state.baseline_delay_sum += delay;
-                            state.baseline_delay_count += 1;
-                        }
-
-                        // Update feedback for outgoing probes
-                        state.last_feedback.highest_seq =
-                            state.last_feedback.highest_seq.max(probe.seq);
-                        state.last_feedback.highest_seq_received_at_ms = now_ms;
-
-                        // Keep only last PROBE_STATS_WINDOW_MS of probes for stats calculation
-                        let cutoff = now_ms.saturating_sub(common::PROBE_STATS_WINDOW_MS);
-                        while let Some(p) = state.received_measurement_probes.front() {
-                            if p.received_at_ms < cutoff {
-                                state.received_measurement_probes.pop_front();
-                            } else {
-                                break;
-                            }
-                        }
-
-                        // Count recent probes and reorders for feedback
-                        let mut recent_count = 0u32;
-                        let mut recent_reorders = 0u32;
-                        let mut last_seq = 0u64;
-                        let feedback_cutoff =
-                            now_ms.saturating_sub(common::PROBE_FEEDBACK_WINDOW_MS);
-                        for p in state.received_measurement_probes.iter() {
-                            if p.received_at_ms >= feedback_cutoff {
-                                recent_count += 1;
-                                if p.seq < last_seq {
-                                    recent_reorders += 1;
-                                }
-                                last_seq = last_seq.max(p.seq);
-                            }
-                        }
-                        state.last_feedback.recent_count = recent_count;
-                        state.last_feedback.recent_reorders = recent_reorders;
+                        state_receiver
+                            .borrow_mut()
+                            .record_measurement_probe(&probe, current_time_ms());
 
                         return; // Handled as measurement probe
                     }
@@ -520,6 +599,161 @@ pub fn setup_control_channel(channel: RtcDataChannel, state: Rc<RefCell<Measurem
                         state_for_handler.borrow_mut().mtu_traceroute_done += 1;
                     }
 
+                    common::ControlMessage::ExtHeaderTestResult(result_msg) => {
+                        let expected_conn_id = state_for_handler.borrow().conn_id.clone();
+                        if !expected_conn_id.is_empty() && result_msg.conn_id != expected_conn_id {
+                            log::warn!(
+                                "ExtHeaderTestResult conn_id mismatch: received '{}' but expected '{}', ignoring",
+                                result_msg.conn_id, expected_conn_id
+                            );
+                            return;
+                        }
+                        state_for_handler.borrow_mut().ext_header_test_done += 1;
+
+                        let conn_prefix = if result_msg.conn_id.len() >= 8 {
+                            &result_msg.conn_id[..8]
+                        } else {
+                            &result_msg.conn_id
+                        };
+                        let outcome = if let Some(error) = &result_msg.error {
+                            format!("not run: {}", error)
+                        } else if result_msg.delivered {
+                            format!(
+                                "delivered ({}/{} probes)",
+                                result_msg.probes_delivered, result_msg.probes_sent
+                            )
+                        } else if let Some(drop_hop) = result_msg.drop_hop {
+                            format!("dropped at hop {}", drop_hop)
+                        } else {
+                            "dropped".to_string()
+                        };
+                        append_server_message(&format!(
+                            "[{}][IPv6 EH] {} header of {} bytes: {}",
+                            conn_prefix,
+                            result_msg.header_type.as_str(),
+                            result_msg.header_size,
+                            outcome
+                        ));
+                    }
+
+                    common::ControlMessage::FragmentationTestResult(result_msg) => {
+                        let expected_conn_id = state_for_handler.borrow().conn_id.clone();
+                        if !expected_conn_id.is_empty() && result_msg.conn_id != expected_conn_id {
+                            log::warn!(
+                                "FragmentationTestResult conn_id mismatch: received '{}' but expected '{}', ignoring",
+                                result_msg.conn_id, expected_conn_id
+                            );
+                            return;
+                        }
+                        state_for_handler.borrow_mut().fragmentation_test_done += 1;
+
+                        let conn_prefix = if result_msg.conn_id.len() >= 8 {
+                            &result_msg.conn_id[..8]
+                        } else {
+                            &result_msg.conn_id
+                        };
+                        let outcome = if let Some(error) = &result_msg.error {
+                            format!("not run: {}", error)
+                        } else if result_msg.delivered {
+                            format!(
+                                "delivered ({}/{} probes)",
+                                result_msg.probes_delivered, result_msg.probes_sent
+                            )
+                        } else {
+                            "dropped".to_string()
+                        };
+                        let order = if result_msg.out_of_order {
+                            "out of order"
+                        } else {
+                            "in order"
+                        };
+                        append_server_message(&format!(
+                            "[{}][Fragmentation] {} bytes, fragments {}: {}",
+                            conn_prefix, result_msg.packet_size, order, outcome
+                        ));
+                    }
+
+                    common::ControlMessage::NatLifetimeTestResult(result_msg) => {
+                        let expected_conn_id = state_for_handler.borrow().conn_id.clone();
+                        if !expected_conn_id.is_empty() && result_msg.conn_id != expected_conn_id {
+                            log::warn!(
+                                "NatLifetimeTestResult conn_id mismatch: received '{}' but expected '{}', ignoring",
+                                result_msg.conn_id, expected_conn_id
+                            );
+                            return;
+                        }
+                        state_for_handler.borrow_mut().nat_lifetime_test_done += 1;
+
+                        let conn_prefix = if result_msg.conn_id.len() >= 8 {
+                            &result_msg.conn_id[..8]
+                        } else {
+                            &result_msg.conn_id
+                        };
+                        let outcome = match (
+                            &result_msg.error,
+                            result_msg.survived_idle_ms,
+                            result_msg.expired_idle_ms,
+                        ) {
+                            (Some(error), _, _) => format!("not run: {}", error),
+                            (None, Some(survived), Some(expired)) => format!(
+                                "binding timeout ~{}s (kept after {}s idle, lost after {}s)",
+                                result_msg.binding_timeout_ms.unwrap_or(expired) / 1000,
+                                survived / 1000,
+                                expired / 1000
+                            ),
+                            (None, None, Some(expired)) => {
+                                format!("binding lost after {}s idle already", expired / 1000)
+                            }
+                            (None, Some(survived), None) => {
+                                format!("binding kept after {}s idle", survived / 1000)
+                            }
+                            (None, None, None) => "no result".to_string(),
+                        };
+                        append_server_message(&format!(
+                            "[{}][NAT] {}: {}",
+                            conn_prefix, result_msg.ip_version, outcome
+                        ));
+                    }
+
+                    common::ControlMessage::NatBehaviorResult(result_msg) => {
+                        let expected_conn_id = state_for_handler.borrow().conn_id.clone();
+                        if !expected_conn_id.is_empty() && result_msg.conn_id != expected_conn_id {
+                            log::warn!(
+                                "NatBehaviorResult conn_id mismatch: received '{}' but expected '{}', ignoring",
+                                result_msg.conn_id, expected_conn_id
+                            );
+                            return;
+                        }
+                        state_for_handler.borrow_mut().nat_behavior_test_done += 1;
+
+                        let conn_prefix = if result_msg.conn_id.len() >= 8 {
+                            &result_msg.conn_id[..8]
+                        } else {
+                            &result_msg.conn_id
+                        };
+                        let yes_no = |value: Option<bool>| match value {
+                            Some(true) => "yes",
+                            Some(false) => "no",
+                            None => "unknown",
+                        };
+                        let outcome = if let Some(error) = &result_msg.error {
+                            format!("not run: {}", error)
+                        } else {
+                            format!(
+                                "mapping {}, filtering {}, symmetric {}, hairpinning {}, port preservation {}",
+                                result_msg.mapping.as_str(),
+                                result_msg.filtering.as_str(),
+                                yes_no(result_msg.symmetric),
+                                yes_no(result_msg.hairpinning),
+                                yes_no(result_msg.port_preservation)
+                            )
+                        };
+                        append_server_message(&format!(
+                            "[{}][NAT type] {}: {}",
+                            conn_prefix, result_msg.ip_version, outcome
+                        ));
+                    }
+
                     common::ControlMessage::MtuHop(mtu_msg) => {
                         let expected_conn_id = state_for_handler.borrow().conn_id.clone();
                         if !expected_conn_id.is_empty() && mtu_msg.conn_id != expected_conn_id {
@@ -636,7 +870,7 @@ pub fn setup_control_channel(channel: RtcDataChannel, state: Rc<RefCell<Measurem
 }
 
 /// Append a message to the server messages text area
-fn append_server_message(message: &str) {
+pub(crate) fn append_server_message(message: &str) {
     use wasm_bindgen::JsCast;
     use web_sys::{window, HtmlTextAreaElement};
 
@@ -720,6 +954,26 @@ fn update_traceroute_visualization(hop_msg: &common::TraceHopMessage) {
         )
         .ok();
 
+        // Reverse DNS name of the router, when the server resolved one
+        if let Some(hostname) = &hop_msg.hostname {
+            js_sys::Reflect::set(
+                &js_obj,
+                &JsValue::from_str("hostname"),
+                &JsValue::from_str(hostname),
+            )
+            .ok();
+        }
+
+        // MPLS label stack and interface information from ICMP extensions
+        for (key, value) in [
+            ("mpls_labels", serde_json::to_string(&hop_msg.mpls_labels)),
+            ("interfaces", serde_json::to_string(&hop_msg.interfaces)),
+        ] {
+            if let Some(value) = value.ok().and_then(|json| js_sys::JSON::parse(&json).ok()) {
+                js_sys::Reflect::set(&js_obj, &JsValue::from_str(key), &value).ok();
+            }
+        }
+
         // Call the JavaScript function
         if let Ok(add_fn) = js_sys::Reflect::get(&window, &JsValue::from_str("addTracerouteHop")) {
             if let Ok(add_fn) = add_fn.dyn_into::<js_sys::Function>() {
@@ -960,7 +1214,15 @@ pub fn setup_testprobe_channel(
 
             log::debug!("conn {:?}: Received test probe test_seq {} ttl {} from server, echoing back on control channel", &testprobe.conn_id, testprobe.test_seq, ttl);
 
-            if state_for_handler.borrow().path_ttl.is_none() {
+            // Probes with IPv6 extension headers may take another path, if any,
+            // and fragmentation probes are sent without a TTL
+            let special_probe = testprobe.send_options.is_some_and(|so| {
+                so.ipv6_hop_by_hop_len.is_some()
+                    || so.ipv6_dest_opts_len.is_some()
+                    || so.out_of_order_fragment_size.is_some()
+                    || so.df_bit == Some(false)
+            });
+            if !special_probe && state_for_handler.borrow().path_ttl.is_none() {
                 state_for_handler.borrow_mut().path_ttl = Some(ttl);
             }
 
@@ -0,0 +1,351 @@
+//! NAT type classification (RFC 4787)
+//!
+//! Browsers cannot send STUN requests of their own, so the mapping behavior is
+//! observed through ICE gathering: one peer connection gathers server
+//! reflexive candidates from each port and alternate address of the server's
+//! STUN behavior discovery responder, from the same local sockets. The server
+//! classifies the NAT from the mapped addresses, and tests its filtering
+//! behavior itself.
+//!
+//! Hairpinning is tested by connecting two peer connections of the browser to
+//! each other through their server reflexive candidates only.
+
+use common::NatMappedAddress;
+use js_sys::Reflect;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    RtcConfiguration, RtcIceCandidateInit, RtcPeerConnection, RtcSdpType, RtcSessionDescriptionInit,
+};
+
+/// Server reflexive candidates of a peer connection, as (candidate, sdpMid)
+type Candidates = Rc<RefCell<Vec<(String, Option<String>)>>>;
+
+/// Maximum wait for ICE gathering from the responder
+const GATHER_TIMEOUT_MS: u64 = 5000;
+/// Maximum wait for two peer connections to reach each other through the NAT
+const HAIRPIN_TIMEOUT_MS: u64 = 5000;
+/// Interval at which peer connection states are checked
+const STATE_POLL_MS: u32 = 100;
+
+/// Mapped addresses the responder's servers reported, and the servers that
+/// did not answer, with the address family of the local address they failed
+/// from (unknown when the browser hides it)
+#[derive(Debug, Default)]
+pub struct MappingObservations {
+    mapped_addresses: Vec<NatMappedAddress>,
+    failed_servers: Vec<(Option<bool>, String)>,
+}
+
+impl MappingObservations {
+    /// Mapped addresses of one address family
+    pub fn mapped_addresses(&self, ipv6: bool) -> Vec<NatMappedAddress> {
+        self.mapped_addresses
+            .iter()
+            .filter(|mapped| mapped.mapped_address.starts_with('[') == ipv6)
+            .cloned()
+            .collect()
+    }
+
+    /// Servers that answered no local address of one address family
+    ///
+    /// Browsers report an error per local address, also for addresses of the
+    /// other family that cannot reach the server at all.
+    pub fn failed_servers(&self, ipv6: bool) -> Vec<String> {
+        let mut failed: Vec<String> = Vec::new();
+        for (family, server) in &self.failed_servers {
+            if family.is_some_and(|family| family != ipv6)
+                || failed.contains(server)
+                || self
+                    .mapped_addresses(ipv6)
+                    .iter()
+                    .any(|mapped| &mapped.server == server)
+            {
+                continue;
+            }
+            failed.push(server.clone());
+        }
+        failed
+    }
+}
+
+/// "host:port" of a server, with brackets around IPv6 addresses
+fn server_address(host: &str, port: u16) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Server of a STUN URL reported by the browser
+fn server_from_url(url: &str) -> String {
+    url.trim_start_matches("stun:").to_string()
+}
+
+fn string_field(object: &JsValue, name: &str) -> Option<String> {
+    Reflect::get(object, &name.into())
+        .ok()
+        .and_then(|value| value.as_string())
+}
+
+fn number_field(object: &JsValue, name: &str) -> Option<u16> {
+    Reflect::get(object, &name.into())
+        .ok()
+        .and_then(|value| value.as_f64())
+        .map(|value| value as u16)
+}
+
+/// Candidate of an icecandidate event, if it is a server reflexive one
+fn reflexive_candidate(event: &web_sys::Event) -> Option<JsValue> {
+    let candidate = Reflect::get(event, &"candidate".into()).ok()?;
+    if candidate.is_null() || candidate.is_undefined() {
+        return None;
+    }
+    (string_field(&candidate, "type").as_deref() == Some("srflx")).then_some(candidate)
+}
+
+/// Peer connection using the given STUN servers, with a data channel so that
+/// its offer has something to gather candidates for
+fn peer_connection(servers: &[String]) -> Result<RtcPeerConnection, JsValue> {
+    let config = RtcConfiguration::new();
+    let ice_servers = js_sys::Array::new();
+    for server in servers {
+        let ice_server = js_sys::Object::new();
+        Reflect::set(
+            &ice_server,
+            &"urls".into(),
+            &format!("stun:{}", server).into(),
+        )?;
+        ice_servers.push(&ice_server);
+    }
+    config.set_ice_servers(&ice_servers);
+
+    let peer = RtcPeerConnection::new_with_configuration(&config)?;
+    peer.create_data_channel("nat");
+    Ok(peer)
+}
+
+/// Set a description created by `create` as local description, and return it
+async fn set_local_description(
+    peer: &RtcPeerConnection,
+    sdp_type: RtcSdpType,
+    create: js_sys::Promise,
+) -> Result<RtcSessionDescriptionInit, JsValue> {
+    let description = JsFuture::from(create).await?;
+    let sdp = string_field(&description, "sdp").ok_or("No SDP in description")?;
+    let description = RtcSessionDescriptionInit::new(sdp_type);
+    description.set_sdp(&sdp);
+    JsFuture::from(peer.set_local_description(&description)).await?;
+    Ok(description)
+}
+
+/// Wait until a state of a peer connection is one of `states`, and return it
+async fn wait_for_state(
+    peer: &RtcPeerConnection,
+    field: &str,
+    states: &[&str],
+    timeout_ms: u64,
+) -> Option<String> {
+    let start = js_sys::Date::now() as u64;
+    loop {
+        let state = string_field(peer, field);
+        if state
+            .as_deref()
+            .is_some_and(|state| states.contains(&state))
+        {
+            return state;
+        }
+        if js_sys::Date::now() as u64 - start > timeout_ms {
+            return None;
+        }
+        crate::sleep_ms(STATE_POLL_MS).await;
+    }
+}
+
+/// Gather mapped addresses from the responder on the page's host, on each of
+/// its `ports` and on its alternate addresses at the primary port
+pub async fn gather_mapped_addresses(
+    ports: &[u16],
+    alternate_addresses: &[String],
+) -> Result<MappingObservations, JsValue> {
+    let host = web_sys::window()
+        .ok_or("No window")?
+        .location()
+        .hostname()?;
+    let mut servers: Vec<String> = ports
+        .iter()
+        .map(|&port| server_address(&host, port))
+        .collect();
+    if let Some(&primary_port) = ports.first() {
+        servers.extend(
+            alternate_addresses
+                .iter()
+                .map(|address| server_address(address, primary_port)),
+        );
+    }
+    log::info!("Gathering NAT mapped addresses from {:?}", servers);
+
+    let peer = peer_connection(&servers)?;
+    let observations = Rc::new(RefCell::new(MappingObservations::default()));
+
+    let observations_for_candidates = observations.clone();
+    let onicecandidate = Closure::wrap(Box::new(move |event: web_sys::Event| {
+        let Some(candidate) = reflexive_candidate(&event) else {
+            return;
+        };
+        let (Some(address), Some(port)) = (
+            string_field(&candidate, "address"),
+            number_field(&candidate, "port"),
+        ) else {
+            return;
+        };
+        // Newer browsers tell the server on the candidate, older ones on the event
+        let Some(url) = string_field(&candidate, "url").or_else(|| string_field(&event, "url"))
+        else {
+            log::warn!("Server of reflexive candidate {}:{} unknown", address, port);
+            return;
+        };
+        let mapped = NatMappedAddress {
+            server: server_from_url(&url),
+            mapped_address: server_address(&address, port),
+            local_port: number_field(&candidate, "relatedPort").filter(|&port| port != 0),
+        };
+        log::info!("NAT mapped address: {:?}", mapped);
+        observations_for_candidates
+            .borrow_mut()
+            .mapped_addresses
+            .push(mapped);
+    }) as Box<dyn FnMut(_)>);
+    peer.set_onicecandidate(Some(onicecandidate.as_ref().unchecked_ref()));
+
+    let observations_for_errors = observations.clone();
+    let onicecandidateerror = Closure::wrap(Box::new(move |event: web_sys::Event| {
+        let Some(url) = string_field(&event, "url") else {
+            return;
+        };
+        let ipv6 = string_field(&event, "address").map(|address| address.contains(':'));
+        log::info!(
+            "STUN server {} did not answer from {:?}: {:?}",
+            url,
+            string_field(&event, "address"),
+            string_field(&event, "errorText")
+        );
+        observations_for_errors
+            .borrow_mut()
+            .failed_servers
+            .push((ipv6, server_from_url(&url)));
+    }) as Box<dyn FnMut(_)>);
+    peer.add_event_listener_with_callback(
+        "icecandidateerror",
+        onicecandidateerror.as_ref().unchecked_ref(),
+    )?;
+
+    let gathered = async {
+        set_local_description(&peer, RtcSdpType::Offer, peer.create_offer()).await?;
+        if wait_for_state(&peer, "iceGatheringState", &["complete"], GATHER_TIMEOUT_MS)
+            .await
+            .is_none()
+        {
+            log::warn!("ICE gathering from the NAT behavior responder timed out");
+        }
+        Ok::<(), JsValue>(())
+    }
+    .await;
+
+    peer.close();
+    drop(onicecandidate);
+    drop(onicecandidateerror);
+    gathered?;
+    Ok(observations.take())
+}
+
+/// Whether two peer connections reach each other through the mapped
+/// addresses of one address family the NAT gives them, none when there are
+/// no such addresses
+pub async fn test_hairpinning(ports: &[u16], ipv6: bool) -> Result<Option<bool>, JsValue> {
+    let Some(&primary_port) = ports.first() else {
+        return Ok(None);
+    };
+    let host = web_sys::window()
+        .ok_or("No window")?
+        .location()
+        .hostname()?;
+    let servers = [server_address(&host, primary_port)];
+    let offerer = peer_connection(&servers)?;
+    let answerer = peer_connection(&servers)?;
+
+    // Server reflexive candidates of each side
+    let collect = |peer: &RtcPeerConnection| {
+        let candidates: Candidates = Rc::default();
+        let candidates_for_handler = candidates.clone();
+        let onicecandidate = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            let Some(candidate) = reflexive_candidate(&event) else {
+                return;
+            };
+            let family_matches = string_field(&candidate, "address")
+                .is_some_and(|address| address.contains(':') == ipv6);
+            if let (true, Some(sdp)) = (family_matches, string_field(&candidate, "candidate")) {
+                candidates_for_handler
+                    .borrow_mut()
+                    .push((sdp, string_field(&candidate, "sdpMid")));
+            }
+        }) as Box<dyn FnMut(_)>);
+        peer.set_onicecandidate(Some(onicecandidate.as_ref().unchecked_ref()));
+        (candidates, onicecandidate)
+    };
+    let (offerer_candidates, offerer_handler) = collect(&offerer);
+    let (answerer_candidates, answerer_handler) = collect(&answerer);
+
+    let result = async {
+        let offer =
+            set_local_description(&offerer, RtcSdpType::Offer, offerer.create_offer()).await?;
+        JsFuture::from(answerer.set_remote_description(&offer)).await?;
+        let answer =
+            set_local_description(&answerer, RtcSdpType::Answer, answerer.create_answer()).await?;
+        JsFuture::from(offerer.set_remote_description(&answer)).await?;
+
+        for peer in [&offerer, &answerer] {
+            wait_for_state(peer, "iceGatheringState", &["complete"], GATHER_TIMEOUT_MS).await;
+        }
+        if offerer_candidates.borrow().is_empty() || answerer_candidates.borrow().is_empty() {
+            return Ok(None);
+        }
+
+        // Only the mapped addresses are given to the other side, so that
+        // connectivity checks have to turn around in the NAT
+        for (peer, candidates) in [
+            (&answerer, &offerer_candidates),
+            (&offerer, &answerer_candidates),
+        ] {
+            // Gathering may still run after its timeout, so candidates can arrive meanwhile
+            let candidates = candidates.borrow().clone();
+            for (sdp, sdp_mid) in &candidates {
+                let candidate = RtcIceCandidateInit::new(sdp);
+                candidate.set_sdp_mid(sdp_mid.as_deref());
+                JsFuture::from(
+                    peer.add_ice_candidate_with_opt_rtc_ice_candidate_init(Some(&candidate)),
+                )
+                .await?;
+            }
+        }
+
+        let state = wait_for_state(
+            &offerer,
+            "connectionState",
+            &["connected", "failed"],
+            HAIRPIN_TIMEOUT_MS,
+        )
+        .await;
+        Ok(Some(state.as_deref() == Some("connected")))
+    }
+    .await;
+
+    offerer.close();
+    answerer.close();
+    drop(offerer_handler);
+    drop(answerer_handler);
+    result
+}
@@ -742,6 +742,113 @@ impl WebRtcConnection {
         )
     }
 
+    /// Send a start IPv6 extension header test message to the server
+    pub async fn send_start_ext_header_test(
+        &self,
+        survey_session_id: &str,
+        header_type: common::Ipv6ExtHeaderType,
+        header_size: u16,
+        path_ttl: i32,
+        collect_timeout_ms: usize,
+    ) -> Result<(), JsValue> {
+        let msg = common::ControlMessage::StartExtHeaderTest(common::StartExtHeaderTestMessage {
+            conn_id: self.conn_id.clone(),
+            survey_session_id: survey_session_id.to_string(),
+            header_type,
+            header_size,
+            path_ttl,
+            collect_timeout_ms,
+        });
+        self.state.borrow_mut().ext_header_test_started += 1;
+        self.send_control_message(
+            &msg,
+            &format!(
+                "start extension header test ({} header, size: {})",
+                header_type.as_str(),
+                header_size
+            ),
+        )
+    }
+
+    /// Send a start IP fragmentation test message to the server
+    pub async fn send_start_fragmentation_test(
+        &self,
+        survey_session_id: &str,
+        packet_size: u32,
+        out_of_order: bool,
+        collect_timeout_ms: usize,
+    ) -> Result<(), JsValue> {
+        let msg =
+            common::ControlMessage::StartFragmentationTest(common::StartFragmentationTestMessage {
+                conn_id: self.conn_id.clone(),
+                survey_session_id: survey_session_id.to_string(),
+                packet_size,
+                out_of_order,
+                collect_timeout_ms,
+            });
+        self.state.borrow_mut().fragmentation_test_started += 1;
+        self.send_control_message(
+            &msg,
+            &format!(
+                "start fragmentation test (size: {}, out of order: {})",
+                packet_size, out_of_order
+            ),
+        )
+    }
+
+    /// Send a start NAT binding timeout test message to the server
+    pub async fn send_start_nat_lifetime_test(
+        &self,
+        survey_session_id: &str,
+        min_idle_ms: u64,
+        max_idle_ms: u64,
+        resolution_ms: u64,
+    ) -> Result<(), JsValue> {
+        let msg =
+            common::ControlMessage::StartNatLifetimeTest(common::StartNatLifetimeTestMessage {
+                conn_id: self.conn_id.clone(),
+                survey_session_id: survey_session_id.to_string(),
+                min_idle_ms,
+                max_idle_ms,
+                resolution_ms,
+            });
+        self.state.borrow_mut().nat_lifetime_test_started += 1;
+        self.send_control_message(
+            &msg,
+            &format!(
+                "start NAT lifetime test (idle: {}..{}ms)",
+                min_idle_ms, max_idle_ms
+            ),
+        )
+    }
+
+    /// Send a start NAT type classification test message to the server
+    pub async fn send_start_nat_behavior_test(
+        &self,
+        survey_session_id: &str,
+        mapped_addresses: Vec<common::NatMappedAddress>,
+        failed_servers: Vec<String>,
+        hairpinning: Option<bool>,
+    ) -> Result<(), JsValue> {
+        let mapped_count = mapped_addresses.len();
+        let msg =
+            common::ControlMessage::StartNatBehaviorTest(common::StartNatBehaviorTestMessage {
+                conn_id: self.conn_id.clone(),
+                survey_session_id: survey_session_id.to_string(),
+                mapped_addresses,
+                failed_servers,
+                hairpinning,
+            });
+        self.state.borrow_mut().nat_behavior_test_started += 1;
+        self.send_control_message(
+            &msg,
+            &format!(
+                "start NAT behavior test ({} mapped addresses)",
+                mapped_count
+            ),
+        )
+    }
+
     /// Send get measuring time message to the server
     pub async fn send_get_measuring_time(&self, survey_session_id: &str) -> Result<(), JsValue> {
         let msg = common::ControlMessage::GetMeasuringTime(common::GetMeasuringTimeMessage {
@@ -783,6 +890,7 @@ impl WebRtcConnection {
             let mut state = self.state.borrow_mut();
             state.probe_streams_active = true;
             state.measurement_probe_seq = 0;
+            state.sent_measurement_probes.clear();
             state.received_measurement_probes.clear();
             state.baseline_delay_sum = 0.0;
             state.baseline_delay_count = 0;
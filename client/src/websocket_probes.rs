@@ -0,0 +1,185 @@
+//! Probe stream over WebSocket
+//!
+//! Runs the probe stream of the probe channel over a WebSocket to the server,
+//! which is TCP, next to the WebRTC probe streams, which are UDP. The per-second
+//! stats of both are compared to tell whether the network shapes or blocks
+//! UDP. When ICE fails on every connection this is the only stream left.
+
+use crate::measurements::{append_server_message, current_time_ms, MeasurementState};
+use crate::{register_interval, register_websocket};
+use common::{
+    compare_transports, DirectionStats, ProbeStatsReport, StartSurveySessionMessage, UdpThrottling,
+    WebSocketProbeMessage,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::{MessageEvent, WebSocket};
+
+/// Maximum wait for the WebSocket to open
+const OPEN_TIMEOUT_MS: u64 = 5000;
+/// Interval at which the WebSocket state is checked while opening
+const OPEN_POLL_MS: u32 = 100;
+
+/// URL of the server's probe stream endpoint on the page's host
+fn probe_stream_url() -> Result<String, JsValue> {
+    let location = web_sys::window().ok_or("No window")?.location();
+    let scheme = if location.protocol()? == "https:" {
+        "wss:"
+    } else {
+        "ws:"
+    };
+    Ok(format!("{}//{}/api/probe/ws", scheme, location.host()?))
+}
+
+fn send(socket: &WebSocket, message: &WebSocketProbeMessage) {
+    if socket.ready_state() != WebSocket::OPEN {
+        return;
+    }
+    if let Ok(json) = serde_json::to_string(message) {
+        if let Err(e) = socket.send_with_str(&json) {
+            log::error!("Failed to send WebSocket probe message: {:?}", e);
+        }
+    }
+}
+
+/// Latest per-second stats of the WebRTC probe streams, both directions, of
+/// the connections that have any
+fn latest_udp_stats(udp_states: &[Rc<RefCell<MeasurementState>>]) -> Vec<DirectionStats> {
+    udp_states
+        .iter()
+        .flat_map(|state| {
+            let state = state.borrow();
+            [
+                state.server_reported_c2s_stats.clone(),
+                state.calculated_s2c_stats.clone(),
+            ]
+        })
+        .flatten()
+        .collect()
+}
+
+/// Start the WebSocket probe stream of a survey session, comparing it every
+/// second with the WebRTC probe streams in `udp_states`
+pub async fn start_probe_stream(
+    survey_session_id: &str,
+    stream_id: &str,
+    magic_key: Option<String>,
+    udp_states: Vec<Rc<RefCell<MeasurementState>>>,
+) -> Result<(), JsValue> {
+    let socket = WebSocket::new(&probe_stream_url()?)?;
+    register_websocket(socket.clone());
+
+    let start = js_sys::Date::now() as u64;
+    while socket.ready_state() == WebSocket::CONNECTING {
+        if js_sys::Date::now() as u64 - start > OPEN_TIMEOUT_MS {
+            return Err("WebSocket probe stream did not open".into());
+        }
+        crate::sleep_ms(OPEN_POLL_MS).await;
+    }
+    if socket.ready_state() != WebSocket::OPEN {
+        return Err("WebSocket probe stream refused".into());
+    }
+
+    send(
+        &socket,
+        &WebSocketProbeMessage::Start(StartSurveySessionMessage {
+            survey_session_id: survey_session_id.to_string(),
+            conn_id: stream_id.to_string(),
+            magic_key,
+        }),
+    );
+
+    let state = Rc::new(RefCell::new(MeasurementState::with_conn_id(
+        stream_id.to_string(),
+    )));
+    state.borrow_mut().probe_streams_active = true;
+
+    // Probes and stats from the server
+    let state_for_messages = state.clone();
+    let onmessage = Closure::wrap(Box::new(move |event: MessageEvent| {
+        let Some(text) = event.data().as_string() else {
+            return;
+        };
+        match serde_json::from_str::<WebSocketProbeMessage>(&text) {
+            Ok(WebSocketProbeMessage::Probe(probe)) => {
+                state_for_messages
+                    .borrow_mut()
+                    .record_measurement_probe(&probe, current_time_ms());
+            }
+            Ok(WebSocketProbeMessage::Stats(report)) => {
+                state_for_messages.borrow_mut().server_reported_c2s_stats = Some(report.c2s_stats);
+            }
+            Ok(WebSocketProbeMessage::Start(_)) => {}
+            Err(e) => log::warn!("Invalid WebSocket probe message: {}", e),
+        }
+    }) as Box<dyn FnMut(_)>);
+    socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+
+    // Probes to the server
+    let state_for_probes = state.clone();
+    let socket_for_probes = socket.clone();
+    let stream_id_for_probes = stream_id.to_string();
+    let probe_interval =
+        gloo_timers::callback::Interval::new(common::PROBE_INTERVAL_MS, move || {
+            let probe = state_for_probes
+                .borrow_mut()
+                .next_measurement_probe(&stream_id_for_probes, current_time_ms());
+            send(&socket_for_probes, &WebSocketProbeMessage::Probe(probe));
+        });
+    register_interval(probe_interval);
+
+    log::info!("WebSocket probe stream {} started", stream_id);
+
+    // Per-second stats, and the comparison with the WebRTC probe streams
+    let survey_session_id = survey_session_id.to_string();
+    let stream_id = stream_id.to_string();
+    let mut udp_samples: Vec<DirectionStats> = Vec::new();
+    let mut tcp_samples: Vec<DirectionStats> = Vec::new();
+    let mut last_verdict = UdpThrottling::Unknown;
+    let stats_interval = gloo_timers::callback::Interval::new(1000, move || {
+        let s2c_stats = crate::calculate_client_s2c_stats(&state.borrow());
+        let c2s_stats = state
+            .borrow()
+            .server_reported_c2s_stats
+            .clone()
+            .unwrap_or_default();
+        state.borrow_mut().calculated_s2c_stats = Some(s2c_stats.clone());
+
+        udp_samples.extend(latest_udp_stats(&udp_states));
+        tcp_samples.push(c2s_stats.clone());
+        tcp_samples.push(s2c_stats.clone());
+        send(
+            &socket,
+            &WebSocketProbeMessage::Stats(Box::new(ProbeStatsReport {
+                conn_id: stream_id.clone(),
+                survey_session_id: survey_session_id.clone(),
+                timestamp_ms: current_time_ms(),
+                c2s_stats,
+                s2c_stats,
+            })),
+        );
+
+        let comparison = compare_transports(&udp_samples, &tcp_samples);
+        if comparison.udp_throttling != last_verdict
+            && comparison.udp_throttling != UdpThrottling::Unknown
+        {
+            last_verdict = comparison.udp_throttling;
+            log::info!("UDP versus TCP probe streams: {:?}", comparison);
+            let outcome = if comparison.reasons.is_empty() {
+                comparison.udp_throttling.as_str().to_string()
+            } else {
+                format!(
+                    "{} ({})",
+                    comparison.udp_throttling.as_str(),
+                    comparison.reasons.join(", ")
+                )
+            };
+            append_server_message(&format!("[WebSocket][UDP throttling] {}", outcome));
+        }
+    });
+    register_interval(stats_interval);
+
+    Ok(())
+}
@@ -1,7 +1,9 @@
 pub mod ice_candidate;
 pub mod metrics;
 pub mod protocol;
+pub mod transport;
 
 pub use ice_candidate::*;
 pub use metrics::*;
 pub use protocol::*;
+pub use transport::*;
@@ -56,6 +56,12 @@ pub const PROBE_STATS_WINDOW_MS: u64 = 2000;
 /// Duration to keep probes for feedback calculation (milliseconds)  
 pub const PROBE_FEEDBACK_WINDOW_MS: u64 = 1000;
 
+// ============ Connection Modes ============
+
+/// Signaling mode of the dedicated connections of NAT binding timeout tests,
+/// on which the server sends no ICE keepalives
+pub const NAT_LIFETIME_MODE: &str = "nat_lifetime";
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Direction {
     ClientToServer,
@@ -107,6 +113,19 @@ pub struct SendOptions {
     /// or fragmented by intermediate routers. Only use this for controlled testing.
     #[serde(default)]
     pub bypass_sctp_fragmentation: bool,
+
+    /// Size of the IPv6 Hop-by-Hop Options header added to the packet (IPv6 only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6_hop_by_hop_len: Option<u16>,
+
+    /// Size of the IPv6 Destination Options header added to the packet (IPv6 only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6_dest_opts_len: Option<u16>,
+
+    /// Payload bytes per IP fragment when the packet is sent as fragments in
+    /// reverse order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub out_of_order_fragment_size: Option<u16>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -177,6 +196,9 @@ pub struct ClientInfo {
     pub peer_address: Option<String>,
     pub peer_port: Option<u16>,
     pub current_seq: u64,
+    /// ASN and location of the peer address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_enrichment: Option<IpEnrichment>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -202,6 +224,9 @@ pub struct SessionDiagnostics {
     pub data_channels: DataChannelStatus,
     pub icmp_error_count: u32,
     pub last_icmp_error_secs_ago: Option<u64>,
+    /// Reverse path hop count inferred from the TTL of received packets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverse_path: Option<ReversePathInfo>,
 }
 
 /// ICE candidate pair information
@@ -237,6 +262,122 @@ pub struct ServerDiagnostics {
     pub sessions: Vec<SessionDiagnostics>,
 }
 
+/// MPLS label stack entry quoted by a router in an ICMP extension (RFC 4950)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MplsLabelEntry {
+    /// 20-bit label value
+    pub label: u32,
+    /// Traffic class (formerly EXP) bits
+    pub tc: u8,
+    /// Bottom of stack flag
+    pub bottom_of_stack: bool,
+    /// TTL of the label stack entry when the packet expired
+    pub ttl: u8,
+}
+
+/// Role of an interface described in an ICMP extension (RFC 5837)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InterfaceRole {
+    /// IP interface on which the packet arrived
+    Incoming,
+    /// Sub-IP component (e.g. a link aggregation member) of the incoming interface
+    IncomingSubIp,
+    /// IP interface through which the packet would have been forwarded
+    Outgoing,
+    /// IP next hop to which the packet would have been forwarded
+    NextHop,
+}
+
+/// Interface information quoted by a router in an ICMP extension (RFC 5837)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InterfaceInfo {
+    pub role: InterfaceRole,
+    /// ifIndex of the interface on the router
+    #[serde(default)]
+    pub if_index: Option<u32>,
+    /// IP address of the interface
+    #[serde(default)]
+    pub ip_address: Option<String>,
+    /// Interface name, e.g. "ge-0/0/1.0"
+    #[serde(default)]
+    pub name: Option<String>,
+    /// MTU of the interface
+    #[serde(default)]
+    pub mtu: Option<u32>,
+}
+
+/// Extension objects (RFC 4884) decoded from an ICMP error
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IcmpExtensions {
+    /// MPLS label stack of the expired packet, top entry first
+    #[serde(default)]
+    pub mpls_labels: Vec<MplsLabelEntry>,
+    /// Interfaces of the router the ICMP error came from
+    #[serde(default)]
+    pub interfaces: Vec<InterfaceInfo>,
+}
+
+impl IcmpExtensions {
+    pub fn is_empty(&self) -> bool {
+        self.mpls_labels.is_empty() && self.interfaces.is_empty()
+    }
+}
+
+/// ASN, prefix and location of an IP address from the server's offline datasets
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IpEnrichment {
+    /// Origin autonomous system number
+    #[serde(default)]
+    pub asn: Option<u32>,
+    /// Name of the autonomous system
+    #[serde(default)]
+    pub as_name: Option<String>,
+    /// Announced prefix containing the address, e.g. "192.0.2.0/24"
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// ISO 3166-1 country code
+    #[serde(default)]
+    pub country: Option<String>,
+    /// City name (English)
+    #[serde(default)]
+    pub city: Option<String>,
+}
+
+impl IpEnrichment {
+    pub fn is_empty(&self) -> bool {
+        self.asn.is_none()
+            && self.as_name.is_none()
+            && self.prefix.is_none()
+            && self.country.is_none()
+            && self.city.is_none()
+    }
+}
+
+/// Reverse (client to server) path length inferred from the TTL / Hop Limit of
+/// received packets, compared with the forward path length found by traceroute
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReversePathInfo {
+    /// TTL / Hop Limit of the last packet received from the client
+    pub received_ttl: u8,
+    /// Lowest and highest TTL / Hop Limit received from the client
+    pub min_received_ttl: u8,
+    pub max_received_ttl: u8,
+    /// Initial TTL / Hop Limit the client's packets were most likely sent with
+    pub initial_ttl: u8,
+    /// Routers on the client to server path
+    pub reverse_hops: u8,
+    /// Routers on the server to client path, once traceroute found the client
+    #[serde(default)]
+    pub forward_hops: Option<u8>,
+    /// Reverse minus forward hop count
+    #[serde(default)]
+    pub hop_difference: Option<i16>,
+    /// Whether the paths differ in length by more than the tolerance
+    #[serde(default)]
+    pub asymmetric: Option<bool>,
+}
+
 /// Message sent from server to client to report traceroute hop information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TraceHopMessage {
@@ -268,6 +409,22 @@ pub struct TraceHopMessage {
     /// Destination address (IP:port) from the original UDP packet
     #[serde(default)]
     pub original_dest_addr: String,
+
+    /// MPLS label stack the probe carried at this hop (from ICMP extensions)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mpls_labels: Vec<MplsLabelEntry>,
+
+    /// Interfaces of the hop's router (from ICMP extensions)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub interfaces: Vec<InterfaceInfo>,
+
+    /// ASN, prefix and location of the hop address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enrichment: Option<IpEnrichment>,
+
+    /// Reverse DNS name of the hop address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
 }
 
 /// Message sent from client to server to stop traceroute probes
@@ -400,6 +557,357 @@ pub struct MtuHopMessage {
 
     /// Packet size that was used for this probe
     pub packet_size: u32,
+
+    /// ASN, prefix and location of the hop address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enrichment: Option<IpEnrichment>,
+}
+
+/// IPv6 extension header added to extension header test probes
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Ipv6ExtHeaderType {
+    HopByHop,
+    DestinationOptions,
+}
+
+impl Ipv6ExtHeaderType {
+    /// Name as stored and displayed
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Ipv6ExtHeaderType::HopByHop => "hop_by_hop",
+            Ipv6ExtHeaderType::DestinationOptions => "destination_options",
+        }
+    }
+}
+
+/// Message sent from client to server to test whether IPv6 extension headers
+/// survive the path (RFC 9098)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartExtHeaderTestMessage {
+    /// Connection ID for multi-path ECMP testing (UUID string)
+    #[serde(default)]
+    pub conn_id: String,
+
+    /// Survey session ID (UUID) for cross-correlation
+    #[serde(default)]
+    pub survey_session_id: String,
+
+    /// Extension header to add to the test probes
+    pub header_type: Ipv6ExtHeaderType,
+
+    /// Size of the extension header in bytes (rounded up to a multiple of 8)
+    pub header_size: u16,
+
+    /// Max TTL to walk to find where the probes are dropped, 0 for no TTL walk
+    #[serde(default)]
+    pub path_ttl: i32,
+
+    /// For how long to wait with the timeouts
+    pub collect_timeout_ms: usize,
+}
+
+/// ICMPv6 error received for an extension header test probe
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExtHeaderHop {
+    /// TTL the probe was sent with, none for the end-to-end probes
+    pub hop: Option<u8>,
+
+    /// IP address of the router that sent the ICMPv6 error
+    pub ip_address: Option<String>,
+
+    /// Round-trip time to this hop in milliseconds
+    pub rtt_ms: f64,
+
+    /// ICMPv6 type (3 = Time Exceeded, 4 = Parameter Problem, ...)
+    pub icmp_type: u8,
+
+    /// ICMPv6 code
+    pub icmp_code: u8,
+}
+
+/// Message sent from server to client with the result of an extension header test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtHeaderTestResultMessage {
+    /// Connection ID for multi-path ECMP testing (UUID string)
+    #[serde(default)]
+    pub conn_id: String,
+
+    /// Survey session ID (UUID) for cross-correlation
+    #[serde(default)]
+    pub survey_session_id: String,
+
+    /// Extension header the test probes carried
+    pub header_type: Ipv6ExtHeaderType,
+
+    /// Size of the extension header as sent
+    pub header_size: u16,
+
+    /// Number of end-to-end probes sent
+    pub probes_sent: u32,
+
+    /// Number of end-to-end probes echoed by the client
+    pub probes_delivered: u32,
+
+    /// Whether any probe with the extension header reached the client
+    pub delivered: bool,
+
+    /// Smallest TTL with which a probe of the TTL walk reached the client
+    pub reached_at_ttl: Option<u8>,
+
+    /// Highest TTL for which a router returned Time Exceeded
+    pub last_responding_hop: Option<u8>,
+
+    /// Hop at which the probes are dropped, if they are
+    pub drop_hop: Option<u8>,
+
+    /// ICMPv6 errors received for the probes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hops: Vec<ExtHeaderHop>,
+
+    /// Why the test could not be run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Start an IP fragmentation delivery test of one packet size
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartFragmentationTestMessage {
+    /// Connection ID for multi-path ECMP testing (UUID string)
+    #[serde(default)]
+    pub conn_id: String,
+
+    /// Survey session ID (UUID) for cross-correlation
+    #[serde(default)]
+    pub survey_session_id: String,
+
+    /// Size of the IP packets to send before fragmentation
+    pub packet_size: u32,
+
+    /// Send the fragments last one first instead of leaving it to the kernel
+    #[serde(default)]
+    pub out_of_order: bool,
+
+    /// For how long to wait with the timeouts
+    pub collect_timeout_ms: usize,
+}
+
+/// ICMP error received for a fragmentation test probe
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FragmentationIcmpError {
+    /// IP address of the host that sent the ICMP error
+    pub ip_address: Option<String>,
+
+    /// ICMP or ICMPv6 type
+    pub icmp_type: u8,
+
+    /// ICMP or ICMPv6 code (1 with Time Exceeded = reassembly timed out)
+    pub icmp_code: u8,
+}
+
+/// Result of an IP fragmentation delivery test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FragmentationTestResultMessage {
+    /// Connection ID for multi-path ECMP testing (UUID string)
+    #[serde(default)]
+    pub conn_id: String,
+
+    /// Survey session ID (UUID) for cross-correlation
+    #[serde(default)]
+    pub survey_session_id: String,
+
+    /// Size of the IP packets sent before fragmentation
+    pub packet_size: u32,
+
+    /// Whether the fragments were sent last one first
+    pub out_of_order: bool,
+
+    /// Payload bytes per fragment of the out of order probes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fragment_size: Option<u16>,
+
+    /// Number of probes sent
+    pub probes_sent: u32,
+
+    /// Number of probes echoed by the client
+    pub probes_delivered: u32,
+
+    /// Whether any probe was reassembled and reached the client
+    pub delivered: bool,
+
+    /// ICMP errors received for the probes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub icmp_errors: Vec<FragmentationIcmpError>,
+
+    /// Why the test could not be run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Start a NAT binding timeout test on a connection of `NAT_LIFETIME_MODE`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartNatLifetimeTestMessage {
+    /// Connection ID for multi-path ECMP testing (UUID string)
+    #[serde(default)]
+    pub conn_id: String,
+
+    /// Survey session ID (UUID) for cross-correlation
+    #[serde(default)]
+    pub survey_session_id: String,
+
+    /// Shortest idle period to test
+    pub min_idle_ms: u64,
+
+    /// Longest idle period to test
+    pub max_idle_ms: u64,
+
+    /// Stop searching once the timeout is known to within this
+    pub resolution_ms: u64,
+}
+
+/// One idle period of a NAT binding timeout test
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NatLifetimeTrial {
+    /// How long the connection was left idle
+    pub idle_ms: u64,
+
+    /// Whether a server-initiated probe reached the client afterwards
+    pub delivered: bool,
+}
+
+/// Result of a NAT binding timeout test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NatLifetimeTestResultMessage {
+    /// Connection ID for multi-path ECMP testing (UUID string)
+    #[serde(default)]
+    pub conn_id: String,
+
+    /// Survey session ID (UUID) for cross-correlation
+    #[serde(default)]
+    pub survey_session_id: String,
+
+    /// Address family of the connection ("ipv4" or "ipv6")
+    pub ip_version: String,
+
+    /// Idle periods tested, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trials: Vec<NatLifetimeTrial>,
+
+    /// Longest idle period after which probes still got through
+    pub survived_idle_ms: Option<u64>,
+
+    /// Shortest idle period after which probes no longer got through
+    pub expired_idle_ms: Option<u64>,
+
+    /// Estimated binding timeout, none if the binding outlived the longest idle period
+    pub binding_timeout_ms: Option<u64>,
+
+    /// Why the test could not be run or completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// NAT mapping or filtering behavior (RFC 4787)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NatBehavior {
+    EndpointIndependent,
+    AddressDependent,
+    AddressAndPortDependent,
+    Unknown,
+}
+
+impl NatBehavior {
+    /// Name as stored and displayed
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NatBehavior::EndpointIndependent => "endpoint_independent",
+            NatBehavior::AddressDependent => "address_dependent",
+            NatBehavior::AddressAndPortDependent => "address_and_port_dependent",
+            NatBehavior::Unknown => "unknown",
+        }
+    }
+}
+
+/// Address a STUN behavior discovery server saw a client socket as
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NatMappedAddress {
+    /// STUN server that reported the mapping ("host:port")
+    pub server: String,
+
+    /// Mapped (server reflexive) address ("ip:port")
+    pub mapped_address: String,
+
+    /// Local port of the client socket, when the browser tells
+    #[serde(default)]
+    pub local_port: Option<u16>,
+}
+
+/// Message sent from client to server with what the client learned of its NAT
+/// from the server's STUN behavior discovery responder, to have the server test
+/// the NAT's filtering and classify it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartNatBehaviorTestMessage {
+    /// Connection ID for multi-path ECMP testing (UUID string)
+    #[serde(default)]
+    pub conn_id: String,
+
+    /// Survey session ID (UUID) for cross-correlation
+    #[serde(default)]
+    pub survey_session_id: String,
+
+    /// Mapped addresses of the connection's address family, one per STUN
+    /// server unless browsers merged identical ones
+    #[serde(default)]
+    pub mapped_addresses: Vec<NatMappedAddress>,
+
+    /// STUN servers that did not answer ("host:port")
+    #[serde(default)]
+    pub failed_servers: Vec<String>,
+
+    /// Whether two connections of the client reached each other through their
+    /// mapped addresses, none if there were no mapped addresses to try
+    #[serde(default)]
+    pub hairpinning: Option<bool>,
+}
+
+/// NAT type of a client, for one address family
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NatBehaviorResultMessage {
+    /// Connection ID for multi-path ECMP testing (UUID string)
+    #[serde(default)]
+    pub conn_id: String,
+
+    /// Survey session ID (UUID) for cross-correlation
+    #[serde(default)]
+    pub survey_session_id: String,
+
+    /// Address family of the connection ("ipv4" or "ipv6")
+    pub ip_version: String,
+
+    /// Whether the mapped address depends on the destination
+    pub mapping: NatBehavior,
+
+    /// Which sources may reach a mapped address
+    pub filtering: NatBehavior,
+
+    /// Whether the mapping depends on the destination port, as with symmetric
+    /// NATs and most CGNATs; known even when the mapping is not
+    pub symmetric: Option<bool>,
+
+    /// Whether the NAT loops packets to its own mapped addresses back
+    pub hairpinning: Option<bool>,
+
+    /// Whether the NAT kept the local port of the client socket
+    pub port_preservation: Option<bool>,
+
+    /// Mapped addresses the classification is based on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mapped_addresses: Vec<NatMappedAddress>,
+
+    /// Why the test could not be run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 /// Message sent from client to server to request measuring time limit
@@ -589,6 +1097,29 @@ pub enum ControlMessage {
     StartProbeStreams(StartProbeStreamsMessage),
     StopProbeStreams(StopProbeStreamsMessage),
     ProbeStats(ProbeStatsReport),
+    // IPv6 extension header survivability messages
+    StartExtHeaderTest(StartExtHeaderTestMessage),
+    ExtHeaderTestResult(ExtHeaderTestResultMessage),
+    // IP fragmentation delivery messages
+    StartFragmentationTest(StartFragmentationTestMessage),
+    FragmentationTestResult(FragmentationTestResultMessage),
+    // NAT binding timeout messages
+    StartNatLifetimeTest(StartNatLifetimeTestMessage),
+    NatLifetimeTestResult(NatLifetimeTestResultMessage),
+    // NAT type classification messages
+    StartNatBehaviorTest(StartNatBehaviorTestMessage),
+    NatBehaviorResult(NatBehaviorResultMessage),
+}
+
+/// Messages of the probe stream over WebSocket (TCP), the counterpart of the
+/// probe channel stream used to tell UDP throttling apart from the path
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebSocketProbeMessage {
+    /// Sent by the client to start the stream; `conn_id` identifies the stream
+    Start(StartSurveySessionMessage),
+    Probe(MeasurementProbePacket),
+    Stats(Box<ProbeStatsReport>),
 }
 
 /// Event generated when an ICMP error matches a tracked packet
@@ -626,6 +1157,9 @@ pub struct TrackedPacketEvent {
 
     /// Destination address (IP:port) from the original UDP packet
     pub original_dest_addr: String,
+
+    /// Extension objects of the ICMP error (MPLS label stack, interface information)
+    pub icmp_extensions: IcmpExtensions,
 }
 
 #[cfg(test)]
@@ -666,6 +1200,7 @@ mod tests {
                 peer_address: Some("192.168.1.100".to_string()),
                 peer_port: Some(54321),
                 current_seq: 42,
+                peer_enrichment: None,
             }],
         };
 
@@ -691,6 +1226,9 @@ mod tests {
                 track_for_ms: 5000,
                 bypass_dtls: false,
                 bypass_sctp_fragmentation: false,
+                ipv6_hop_by_hop_len: None,
+                ipv6_dest_opts_len: None,
+                out_of_order_fragment_size: None,
             }),
             conn_id: String::new(),
         };
@@ -0,0 +1,281 @@
+//! UDP versus TCP comparison of probe streams
+//!
+//! The same probe stream runs over the WebRTC probe channel (UDP) and over a
+//! WebSocket (TCP). Networks that rate-limit or deprioritize UDP show it as
+//! more loss, a lower delivered rate or a longer RTT on the UDP stream, and
+//! networks that block UDP as a UDP stream that never delivers anything.
+//!
+//! TCP retransmits lost segments, so losses on the TCP stream show up as
+//! delay rather than as missing probes.
+
+use crate::{DirectionStats, MeasurementProbePacket};
+use serde::{Deserialize, Serialize};
+
+/// Per-second stats samples each transport needs before they are compared
+pub const MIN_COMPARISON_SAMPLES: usize = 5;
+
+/// Loss rate UDP may have over TCP, in percentage points, before it counts as shaped
+pub const LOSS_MARGIN_PERCENT: f64 = 5.0;
+
+/// Share of the TCP delivered rate below which UDP counts as shaped
+pub const DELIVERED_RATE_RATIO: f64 = 0.8;
+
+/// RTT ratio, and absolute margin in milliseconds, above which UDP counts as shaped
+pub const RTT_RATIO: f64 = 1.5;
+pub const RTT_MARGIN_MS: f64 = 10.0;
+
+/// Round trip time from the feedback of a received probe stream probe
+///
+/// The feedback tells when the peer received the highest sequence number it
+/// has seen, which was sent at `highest_seq_sent_at_ms`. The time the peer
+/// held it before sending `probe` is taken off the time since it was sent.
+/// Both are differences on a single clock, so the clock offset between the
+/// ends cancels out.
+pub fn feedback_rtt_ms(
+    highest_seq_sent_at_ms: u64,
+    probe: &MeasurementProbePacket,
+    now_ms: u64,
+) -> Option<f64> {
+    let received_at_ms = probe.feedback.highest_seq_received_at_ms;
+    if received_at_ms == 0 {
+        return None;
+    }
+    let held_ms = probe.sent_at_ms.saturating_sub(received_at_ms);
+    let rtt_ms = now_ms as i64 - highest_seq_sent_at_ms as i64 - held_ms as i64;
+    (rtt_ms >= 0).then_some(rtt_ms as f64)
+}
+
+/// 50th percentile, 99th percentile, min and max, the layout of `DirectionStats`
+pub fn percentiles(values: &mut [f64]) -> [f64; 4] {
+    if values.is_empty() {
+        return [0.0; 4];
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let len = values.len();
+    [
+        values[len / 2],
+        values[((len * 99) / 100).min(len - 1)],
+        values[0],
+        values[len - 1],
+    ]
+}
+
+/// Whether UDP looks throttled compared to TCP
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UdpThrottling {
+    NotDetected,
+    /// UDP gets through, with more loss, a lower rate or a longer RTT
+    Shaped,
+    /// Only TCP gets through
+    Blocked,
+    /// Not enough samples to tell
+    Unknown,
+}
+
+impl UdpThrottling {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UdpThrottling::NotDetected => "not_detected",
+            UdpThrottling::Shaped => "shaped",
+            UdpThrottling::Blocked => "blocked",
+            UdpThrottling::Unknown => "unknown",
+        }
+    }
+}
+
+/// Probe stream performance of one transport over a survey session
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TransportSummary {
+    /// Per-second stats samples
+    pub samples: usize,
+    /// Median of the per-second median RTTs
+    pub rtt_ms: Option<f64>,
+    /// Mean loss rate as percentage, over the seconds that delivered probes
+    pub loss_rate: Option<f64>,
+    /// Mean probes delivered per second; both streams send `PROBE_STREAM_PPS`
+    pub delivered_pps: Option<f64>,
+}
+
+impl TransportSummary {
+    /// Summary of per-second stats samples, of both directions
+    pub fn from_samples(samples: &[DirectionStats]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let mut rtts: Vec<f64> = samples
+            .iter()
+            .map(|stats| stats.rtt_ms[0])
+            .filter(|&rtt| rtt > 0.0)
+            .collect();
+        let delivering: Vec<&DirectionStats> = samples
+            .iter()
+            .filter(|stats| stats.probe_count > 0)
+            .collect();
+        let probes: u64 = samples.iter().map(|stats| stats.probe_count as u64).sum();
+
+        Self {
+            samples: samples.len(),
+            rtt_ms: (!rtts.is_empty()).then(|| percentiles(&mut rtts)[0]),
+            loss_rate: (!delivering.is_empty()).then(|| {
+                delivering.iter().map(|stats| stats.loss_rate).sum::<f64>()
+                    / delivering.len() as f64
+            }),
+            delivered_pps: Some(probes as f64 / samples.len() as f64),
+        }
+    }
+}
+
+/// UDP versus TCP probe stream comparison of a survey session
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransportComparison {
+    pub udp: TransportSummary,
+    pub tcp: TransportSummary,
+    pub udp_throttling: UdpThrottling,
+    /// What the verdict rests on
+    pub reasons: Vec<String>,
+}
+
+/// Compare the per-second stats of the UDP and TCP probe streams
+pub fn compare_transports(udp: &[DirectionStats], tcp: &[DirectionStats]) -> TransportComparison {
+    let udp_summary = TransportSummary::from_samples(udp);
+    let tcp_summary = TransportSummary::from_samples(tcp);
+    let mut reasons = Vec::new();
+
+    let udp_delivering = udp.iter().filter(|stats| stats.probe_count > 0).count();
+    let tcp_delivering = tcp.iter().filter(|stats| stats.probe_count > 0).count();
+
+    let udp_throttling = if tcp_delivering < MIN_COMPARISON_SAMPLES {
+        UdpThrottling::Unknown
+    } else if udp_delivering == 0 {
+        reasons.push("no probes delivered over UDP".to_string());
+        UdpThrottling::Blocked
+    } else if udp.len() < MIN_COMPARISON_SAMPLES {
+        UdpThrottling::Unknown
+    } else {
+        if let (Some(udp_loss), Some(tcp_loss)) = (udp_summary.loss_rate, tcp_summary.loss_rate) {
+            if udp_loss - tcp_loss > LOSS_MARGIN_PERCENT {
+                reasons.push(format!(
+                    "UDP loss {:.1}% against {:.1}% over TCP",
+                    udp_loss, tcp_loss
+                ));
+            }
+        }
+        if let (Some(udp_pps), Some(tcp_pps)) =
+            (udp_summary.delivered_pps, tcp_summary.delivered_pps)
+        {
+            if udp_pps < tcp_pps * DELIVERED_RATE_RATIO {
+                reasons.push(format!(
+                    "UDP delivered {:.0} probes/s against {:.0} over TCP",
+                    udp_pps, tcp_pps
+                ));
+            }
+        }
+        if let (Some(udp_rtt), Some(tcp_rtt)) = (udp_summary.rtt_ms, tcp_summary.rtt_ms) {
+            if udp_rtt > tcp_rtt * RTT_RATIO + RTT_MARGIN_MS {
+                reasons.push(format!(
+                    "UDP RTT {:.0}ms against {:.0}ms over TCP",
+                    udp_rtt, tcp_rtt
+                ));
+            }
+        }
+        if reasons.is_empty() {
+            UdpThrottling::NotDetected
+        } else {
+            UdpThrottling::Shaped
+        }
+    };
+
+    TransportComparison {
+        udp: udp_summary,
+        tcp: tcp_summary,
+        udp_throttling,
+        reasons,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Direction, ProbeFeedback};
+
+    fn sample(rtt_ms: f64, loss_rate: f64, probe_count: u32) -> DirectionStats {
+        DirectionStats {
+            rtt_ms: [rtt_ms, rtt_ms, rtt_ms, rtt_ms],
+            loss_rate,
+            probe_count,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_feedback_rtt_ms() {
+        let probe = MeasurementProbePacket {
+            seq: 7,
+            // Peer clock 500ms ahead, held the probe for 5ms
+            sent_at_ms: 10_525,
+            direction: Direction::ClientToServer,
+            conn_id: String::new(),
+            feedback: ProbeFeedback {
+                highest_seq: 3,
+                highest_seq_received_at_ms: 10_520,
+                recent_count: 0,
+                recent_reorders: 0,
+            },
+        };
+        assert_eq!(feedback_rtt_ms(10_000, &probe, 10_045), Some(40.0));
+
+        let no_feedback = MeasurementProbePacket {
+            feedback: ProbeFeedback::default(),
+            ..probe
+        };
+        assert_eq!(feedback_rtt_ms(10_000, &no_feedback, 10_045), None);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut values = vec![5.0, 1.0, 3.0, 2.0, 4.0];
+        assert_eq!(percentiles(&mut values), [3.0, 5.0, 1.0, 5.0]);
+        assert_eq!(percentiles(&mut []), [0.0; 4]);
+    }
+
+    #[test]
+    fn test_udp_not_throttled() {
+        let udp = vec![sample(20.0, 0.0, 100); 10];
+        let tcp = vec![sample(22.0, 0.0, 100); 10];
+        let comparison = compare_transports(&udp, &tcp);
+        assert_eq!(comparison.udp_throttling, UdpThrottling::NotDetected);
+        assert_eq!(comparison.udp.rtt_ms, Some(20.0));
+        assert_eq!(comparison.tcp.delivered_pps, Some(100.0));
+    }
+
+    #[test]
+    fn test_udp_shaped() {
+        let udp = vec![sample(80.0, 30.0, 70); 10];
+        let tcp = vec![sample(20.0, 0.0, 100); 10];
+        let comparison = compare_transports(&udp, &tcp);
+        assert_eq!(comparison.udp_throttling, UdpThrottling::Shaped);
+        assert_eq!(comparison.reasons.len(), 3);
+    }
+
+    #[test]
+    fn test_udp_blocked() {
+        // ICE failed: no UDP samples at all, or only empty ones
+        let tcp = vec![sample(20.0, 0.0, 100); 10];
+        assert_eq!(
+            compare_transports(&[], &tcp).udp_throttling,
+            UdpThrottling::Blocked
+        );
+        let udp = vec![sample(0.0, 0.0, 0); 10];
+        assert_eq!(
+            compare_transports(&udp, &tcp).udp_throttling,
+            UdpThrottling::Blocked
+        );
+
+        // Without TCP there is nothing to compare with
+        assert_eq!(
+            compare_transports(&udp, &[]).udp_throttling,
+            UdpThrottling::Unknown
+        );
+    }
+}
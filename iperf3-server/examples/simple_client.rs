@@ -0,0 +1,62 @@
+//! Simple iperf3 client example.
+//!
+//! This example demonstrates how to run a test against any iperf3 server,
+//! including the `simple_server` example.
+//!
+//! Run with:
+//! ```bash
+//! cargo run --example simple_client -- 127.0.0.1 [udp] [reverse]
+//! ```
+
+use iperf3_server::{Iperf3Client, Iperf3ClientConfig};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let host = args
+        .first()
+        .cloned()
+        .unwrap_or_else(|| "127.0.0.1".to_string());
+
+    // Configure the test
+    let mut config = Iperf3ClientConfig::new(host, 5201);
+    config.udp = args.iter().any(|a| a == "udp");
+    config.reverse = args.iter().any(|a| a == "reverse");
+    config.duration_secs = 5;
+    if config.udp {
+        config.bandwidth = 10_000_000; // 10 Mbps per stream
+    }
+
+    println!(
+        "Running {} test against {}:{}{}",
+        if config.udp { "UDP" } else { "TCP" },
+        config.host,
+        config.port,
+        if config.reverse { " (reverse)" } else { "" }
+    );
+
+    let results = Iperf3Client::new(config).run().await?;
+
+    println!(
+        "Sent:     {} bytes, {:.2} Mbps",
+        results.sum_sent.bytes,
+        results.sum_sent.bits_per_second / 1_000_000.0
+    );
+    println!(
+        "Received: {} bytes, {:.2} Mbps",
+        results.sum_received.bytes,
+        results.sum_received.bits_per_second / 1_000_000.0
+    );
+    if let (Some(jitter), Some(lost), Some(packets)) = (
+        results.sum_received.jitter_ms,
+        results.sum_received.lost_packets,
+        results.sum_received.packets,
+    ) {
+        println!(
+            "Jitter:   {:.3} ms, lost {}/{} datagrams",
+            jitter, lost, packets
+        );
+    }
+
+    Ok(())
+}
@@ -27,7 +27,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         max_duration_secs: 3600,     // Maximum test duration (1 hour)
         require_auth: false,         // No authentication required
         auth_timeout_secs: 60,
-        max_bandwidth: 0, // No bandwidth limit (0 = unlimited)
+        max_bandwidth: 0,      // No bandwidth limit (0 = unlimited)
+        max_tests_per_ip: 2,   // At most 2 concurrent tests per client IP
+        daily_bytes_per_ip: 0, // No daily quota (0 = unlimited)
+        cooldown_secs: 0,      // No cooldown between tests
+        magic_key_max_bandwidth: Default::default(),
     };
 
     let server = Arc::new(Iperf3Server::new(config));
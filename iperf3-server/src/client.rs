@@ -0,0 +1,597 @@
+//! iperf3 client implementation.
+//!
+//! Runs a single test against any iperf3 server (including `Iperf3Server`),
+//! driving the client side of the same protocol state machine. The data
+//! streams are handled by a `TestSession`, exactly like on the server side,
+//! with the roles of sender and receiver swapped.
+
+use crate::config::Iperf3ClientConfig;
+use crate::error::{Iperf3Error, Result};
+use crate::protocol::{
+    ExchangeResultsData, ExchangeStreamResult, IntervalResult, State, StreamEndResult,
+    StreamResult, TestParameters, COOKIE_SIZE, LEGACY_UDP_CONNECT_REPLY, UDP_CONNECT_MSG,
+    UDP_CONNECT_REPLY,
+};
+use crate::session::TestSession;
+use crate::tcp;
+use serde::Serialize;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+
+/// Default TCP block size (128 KB, as in the reference client)
+const DEFAULT_TCP_BLKSIZE: u32 = 128 * 1024;
+
+/// Default UDP block size, small enough to avoid fragmentation on common paths
+const DEFAULT_UDP_BLKSIZE: u32 = 1460;
+
+/// Default UDP bandwidth in bits per second (1 Mbps, as in the reference client)
+const DEFAULT_UDP_BANDWIDTH_BPS: u64 = 1024 * 1024;
+
+/// Number of datagrams to look at for the UDP connect reply. In reverse mode
+/// the first data datagrams may overtake the reply.
+const UDP_CONNECT_MAX_DATAGRAMS: usize = 16;
+
+/// Results of a completed client test
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientResults {
+    /// Server address the test ran against
+    pub server_addr: SocketAddr,
+
+    /// Session cookie used for the test
+    pub cookie: String,
+
+    /// Test parameters sent to the server
+    pub params: TestParameters,
+
+    /// Measured test duration in seconds
+    pub duration_secs: f64,
+
+    /// Per-interval results measured locally
+    pub intervals: Vec<IntervalResult>,
+
+    /// Per-stream sender and receiver results
+    pub streams: Vec<StreamEndResult>,
+
+    /// Sum of the sending side
+    pub sum_sent: StreamResult,
+
+    /// Sum of the receiving side (UDP jitter and loss are measured here)
+    pub sum_received: StreamResult,
+
+    /// Results the client sent to the server during EXCHANGE_RESULTS
+    pub local_results: ExchangeResultsData,
+
+    /// Results the server sent to the client during EXCHANGE_RESULTS
+    pub server_results: ExchangeResultsData,
+}
+
+/// An iperf3 client running tests against a single server
+pub struct Iperf3Client {
+    /// Client configuration
+    config: Iperf3ClientConfig,
+}
+
+impl Iperf3Client {
+    /// Create a new iperf3 client
+    pub fn new(config: Iperf3ClientConfig) -> Self {
+        Self { config }
+    }
+
+    /// Get the client configuration
+    pub fn config(&self) -> &Iperf3ClientConfig {
+        &self.config
+    }
+
+    /// Test parameters sent to the server, with protocol defaults filled in
+    pub fn test_parameters(&self) -> TestParameters {
+        let config = &self.config;
+        let (protocol, default_blksize) = if config.udp {
+            ("UDP", DEFAULT_UDP_BLKSIZE)
+        } else {
+            ("TCP", DEFAULT_TCP_BLKSIZE)
+        };
+
+        TestParameters {
+            protocol: protocol.to_string(),
+            time: config.duration_secs,
+            parallel: config.parallel.max(1),
+            reverse: config.reverse,
+            bandwidth: if config.udp && config.bandwidth == 0 {
+                DEFAULT_UDP_BANDWIDTH_BPS
+            } else {
+                config.bandwidth
+            },
+            blksize: if config.blksize > 0 {
+                config.blksize
+            } else {
+                default_blksize
+            },
+            window: config.window,
+            mss: config.mss,
+            nodelay: config.nodelay,
+            client_version: client_version(),
+            udp: config.udp,
+            congestion: config.congestion.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Run a test against the configured server.
+    ///
+    /// Protocol flow (client side of `Iperf3Server::run_session_protocol`):
+    /// 1. Connect the control connection and send the cookie
+    /// 2. PARAM_EXCHANGE: send JSON parameters
+    /// 3. CREATE_STREAMS: connect the data streams
+    /// 4. TEST_START, TEST_RUNNING: transfer data for the test duration
+    /// 5. Send TEST_END
+    /// 6. EXCHANGE_RESULTS: send our results JSON, read the server's
+    /// 7. DISPLAY_RESULTS: send IPERF_DONE
+    pub async fn run(&self) -> Result<ClientResults> {
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        let server_addr = self.resolve_server().await?;
+        let params = self.test_parameters();
+
+        let mut control = with_timeout(timeout, TcpStream::connect(server_addr)).await??;
+        let _ = control.set_nodelay(true);
+        let cookie = make_cookie();
+        control.write_all(&cookie_bytes(&cookie)).await?;
+
+        tracing::info!(
+            "iperf3 client: Connected to {} ({} {}s, {} stream(s){})",
+            server_addr,
+            params.protocol,
+            params.time,
+            params.parallel,
+            if params.reverse { ", reverse" } else { "" }
+        );
+
+        let session = Arc::new(TestSession::new(cookie.clone(), server_addr, control));
+        session.set_params(params.clone());
+        session.set_udp_mode(params.udp);
+
+        let mut test_duration = Duration::ZERO;
+        let mut exchanged = None;
+
+        loop {
+            let state = with_timeout(timeout, session.read_state()).await??;
+            tracing::debug!("iperf3 client: Received state {:?}", state);
+
+            match state {
+                State::ParamExchange => {
+                    session
+                        .write_json_message(&parameters_json(&params))
+                        .await?;
+                }
+                State::CreateStreams => {
+                    self.create_streams(&session, server_addr, &cookie, &params, timeout)
+                        .await?;
+                }
+                State::TestStart => {
+                    session.start_test().await;
+                }
+                State::TestRunning => {
+                    test_duration = Self::run_test(&session, &params).await;
+                    session.send_state(State::TestEnd).await?;
+                }
+                State::ExchangeResults => {
+                    let local_results =
+                        session.generate_exchange_results(test_duration.as_secs_f64());
+                    session
+                        .write_json_message(&serde_json::to_value(&local_results)?)
+                        .await?;
+                    let server_json = with_timeout(timeout, session.read_json_message()).await??;
+                    let server_results: ExchangeResultsData = serde_json::from_value(server_json)?;
+                    exchanged = Some((local_results, server_results));
+                }
+                State::DisplayResults => {
+                    session.send_state(State::IperfDone).await?;
+                    break;
+                }
+                State::AccessDenied => return Err(Iperf3Error::AccessDenied),
+                State::ServerError => return Err(Iperf3Error::ServerError),
+                other => {
+                    return Err(Iperf3Error::Protocol(format!(
+                        "Unexpected state from server: {:?}",
+                        other
+                    )));
+                }
+            }
+        }
+
+        let (local_results, server_results) = exchanged
+            .ok_or_else(|| Iperf3Error::Protocol("Server did not exchange results".to_string()))?;
+
+        // Our side of each stream, including TCP_INFO figures when we sent
+        let duration_secs = test_duration.as_secs_f64();
+        let local_streams = session
+            .generate_results(duration_secs)
+            .end
+            .streams
+            .into_iter()
+            .map(|s| if params.reverse { s.receiver } else { s.sender })
+            .collect();
+
+        let results = build_results(
+            server_addr,
+            cookie,
+            params,
+            duration_secs,
+            session.intervals(),
+            local_streams,
+            local_results,
+            server_results,
+        );
+
+        tracing::info!(
+            "iperf3 client: Test with {} completed - sent: {} bytes ({:.0} bps), received: {} bytes ({:.0} bps)",
+            server_addr,
+            results.sum_sent.bytes,
+            results.sum_sent.bits_per_second,
+            results.sum_received.bytes,
+            results.sum_received.bits_per_second
+        );
+
+        Ok(results)
+    }
+
+    /// Resolve the configured host to the first matching socket address
+    async fn resolve_server(&self) -> Result<SocketAddr> {
+        let host = self.config.host.as_str();
+        tokio::net::lookup_host((host, self.config.port))
+            .await?
+            .next()
+            .ok_or_else(|| {
+                Iperf3Error::InvalidParameter(format!("Could not resolve host {}", host))
+            })
+    }
+
+    /// Connect all data streams for the test
+    async fn create_streams(
+        &self,
+        session: &TestSession,
+        server_addr: SocketAddr,
+        cookie: &str,
+        params: &TestParameters,
+        timeout: Duration,
+    ) -> Result<()> {
+        for stream_num in 0..params.parallel {
+            if params.udp {
+                let socket = with_timeout(timeout, connect_udp_stream(server_addr)).await??;
+                session.add_udp_stream(Arc::new(socket)).await;
+            } else {
+                let mut stream =
+                    with_timeout(timeout, connect_tcp_stream(server_addr, params)).await??;
+                stream.write_all(&cookie_bytes(cookie)).await?;
+                session.add_data_stream(stream).await;
+            }
+
+            tracing::debug!(
+                "iperf3 client: Data stream {} connected to {}",
+                stream_num + 1,
+                server_addr
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Transfer data for the test duration and return the measured duration
+    async fn run_test(session: &TestSession, params: &TestParameters) -> Duration {
+        let test_duration = Duration::from_secs(params.time);
+        let data_handles = if params.udp {
+            if params.reverse {
+                // Server sends to client (UDP)
+                session.start_udp_receiver_background(test_duration).await
+            } else {
+                // Client sends to server (UDP)
+                session
+                    .start_udp_sender_background(test_duration, params.bandwidth, params.blksize)
+                    .await
+            }
+        } else if params.reverse {
+            // Server sends to client (TCP)
+            session.start_receiver_background(test_duration).await
+        } else {
+            // Client sends to server (TCP)
+            session
+                .start_sender_background(test_duration, params.bandwidth)
+                .await
+        };
+
+        let interval_handle =
+            session.start_interval_reporter(Duration::from_secs_f64(params.interval.max(0.0)));
+
+        // The client decides when the test ends
+        tokio::time::sleep(test_duration).await;
+
+        session.cancel();
+        for handle in data_handles {
+            let _ = handle.await;
+        }
+        let _ = interval_handle.await;
+
+        session.test_elapsed().await.unwrap_or(test_duration)
+    }
+}
+
+/// Run a future with a timeout, mapping expiry to `Iperf3Error::Timeout`
+async fn with_timeout<T>(timeout: Duration, future: impl Future<Output = T>) -> Result<T> {
+    tokio::time::timeout(timeout, future)
+        .await
+        .map_err(|_| Iperf3Error::Timeout(timeout.as_secs()))
+}
+
+/// Client version string sent with the test parameters
+fn client_version() -> String {
+    format!("iperf3-server-rs {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Generate a session cookie (36 printable characters)
+fn make_cookie() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Cookie as sent on the wire: NUL-padded to `COOKIE_SIZE` bytes
+fn cookie_bytes(cookie: &str) -> [u8; COOKIE_SIZE] {
+    let mut buf = [0u8; COOKIE_SIZE];
+    let len = cookie.len().min(COOKIE_SIZE - 1);
+    buf[..len].copy_from_slice(&cookie.as_bytes()[..len]);
+    buf
+}
+
+/// Test parameters in the JSON form the reference server expects
+fn parameters_json(params: &TestParameters) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "omit": params.omit,
+        "time": params.time,
+        "num": params.bytes,
+        "blockcount": params.blockcount,
+        "parallel": params.parallel,
+        "len": params.blksize,
+        "client_version": params.client_version,
+    });
+
+    let object = json.as_object_mut().expect("parameters are a JSON object");
+    object.insert(
+        if params.udp { "udp" } else { "tcp" }.to_string(),
+        serde_json::Value::Bool(true),
+    );
+    if params.reverse {
+        object.insert("reverse".to_string(), serde_json::Value::Bool(true));
+    }
+    if params.bandwidth > 0 {
+        object.insert("bandwidth".to_string(), params.bandwidth.into());
+    }
+    if params.window > 0 {
+        object.insert("window".to_string(), params.window.into());
+    }
+    if params.mss > 0 {
+        object.insert("MSS".to_string(), params.mss.into());
+    }
+    if params.nodelay {
+        object.insert("nodelay".to_string(), serde_json::Value::Bool(true));
+    }
+    if let Some(congestion) = &params.congestion {
+        object.insert("congestion".to_string(), congestion.clone().into());
+    }
+    if params.udp_counters_64bit != 0 {
+        object.insert(
+            "udp_counters_64bit".to_string(),
+            params.udp_counters_64bit.into(),
+        );
+    }
+
+    json
+}
+
+/// Open a TCP data stream, applying the window size and MSS before connecting
+async fn connect_tcp_stream(server_addr: SocketAddr, params: &TestParameters) -> Result<TcpStream> {
+    let socket = if server_addr.is_ipv6() {
+        TcpSocket::new_v6()?
+    } else {
+        TcpSocket::new_v4()?
+    };
+    tcp::configure_socket(&socket, params);
+    Ok(socket.connect(server_addr).await?)
+}
+
+/// Open a UDP data stream: send the connect message and wait for the reply
+async fn connect_udp_stream(server_addr: SocketAddr) -> Result<UdpSocket> {
+    let bind_addr = if server_addr.is_ipv6() {
+        SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)
+    } else {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)
+    };
+    let socket = UdpSocket::bind(bind_addr).await?;
+    socket.connect(server_addr).await?;
+    socket.send(&UDP_CONNECT_MSG).await?;
+
+    let mut buf = vec![0u8; 64 * 1024];
+    for _ in 0..UDP_CONNECT_MAX_DATAGRAMS {
+        let n = socket.recv(&mut buf).await?;
+        if n == UDP_CONNECT_REPLY.len() {
+            let reply = [buf[0], buf[1], buf[2], buf[3]];
+            if reply == UDP_CONNECT_REPLY
+                || u32::from_le_bytes(reply) == LEGACY_UDP_CONNECT_REPLY
+                || u32::from_be_bytes(reply) == LEGACY_UDP_CONNECT_REPLY
+            {
+                return Ok(socket);
+            }
+        }
+    }
+
+    Err(Iperf3Error::Protocol(format!(
+        "No UDP connect reply from {}",
+        server_addr
+    )))
+}
+
+/// Convert one side's exchanged stream result to a stream result.
+/// UDP loss and jitter are only meaningful on the receiving side.
+fn stream_result(
+    stream: &ExchangeStreamResult,
+    udp: bool,
+    receiver: bool,
+    has_retransmits: bool,
+    test_duration: f64,
+) -> StreamResult {
+    let seconds = if stream.end_time > stream.start_time {
+        stream.end_time - stream.start_time
+    } else {
+        test_duration
+    };
+
+    let mut result = StreamResult {
+        id: stream.id,
+        bytes: stream.bytes,
+        seconds,
+        bits_per_second: if seconds > 0.0 {
+            stream.bytes as f64 * 8.0 / seconds
+        } else {
+            0.0
+        },
+        ..Default::default()
+    };
+
+    if udp {
+        result.packets = Some(stream.packets);
+        if receiver {
+            result.jitter_ms = Some(stream.jitter * 1000.0);
+            result.lost_packets = Some(stream.errors);
+            result.lost_percent = Some(if stream.packets > 0 {
+                100.0 * stream.errors as f64 / stream.packets as f64
+            } else {
+                0.0
+            });
+        }
+    } else if !receiver && has_retransmits && stream.retransmits >= 0 {
+        result.retransmits = Some(stream.retransmits as u64);
+    }
+
+    result
+}
+
+/// Sum per-stream results of one side; summed jitter is the per-stream average
+fn sum_results(streams: &[StreamResult], test_duration: f64) -> StreamResult {
+    let mut sum = StreamResult {
+        id: 0,
+        seconds: test_duration,
+        ..Default::default()
+    };
+
+    let mut jitter_total = 0.0;
+    let mut jitter_count = 0;
+    for stream in streams {
+        sum.bytes += stream.bytes;
+        sum.seconds = sum.seconds.max(stream.seconds);
+        if let Some(retransmits) = stream.retransmits {
+            *sum.retransmits.get_or_insert(0) += retransmits;
+        }
+        if let Some(packets) = stream.packets {
+            *sum.packets.get_or_insert(0) += packets;
+        }
+        if let Some(lost) = stream.lost_packets {
+            *sum.lost_packets.get_or_insert(0) += lost;
+        }
+        if let Some(jitter) = stream.jitter_ms {
+            jitter_total += jitter;
+            jitter_count += 1;
+        }
+    }
+
+    sum.bits_per_second = if sum.seconds > 0.0 {
+        sum.bytes as f64 * 8.0 / sum.seconds
+    } else {
+        0.0
+    };
+    if jitter_count > 0 {
+        sum.jitter_ms = Some(jitter_total / jitter_count as f64);
+    }
+    if let (Some(lost), Some(packets)) = (sum.lost_packets, sum.packets) {
+        sum.lost_percent = Some(if packets > 0 {
+            100.0 * lost as f64 / packets as f64
+        } else {
+            0.0
+        });
+    }
+
+    sum
+}
+
+/// Combine our own per-stream results with the server's exchanged results
+/// into the final results
+#[allow(clippy::too_many_arguments)]
+fn build_results(
+    server_addr: SocketAddr,
+    cookie: String,
+    params: TestParameters,
+    duration_secs: f64,
+    intervals: Vec<IntervalResult>,
+    local_streams: Vec<StreamResult>,
+    local_results: ExchangeResultsData,
+    server_results: ExchangeResultsData,
+) -> ClientResults {
+    // In reverse mode the server is the sender
+    let server_is_receiver = !params.reverse;
+    let has_retransmits = server_results.sender_has_retransmits == 1;
+    let remote_streams: Vec<StreamResult> = server_results
+        .streams
+        .iter()
+        .map(|s| {
+            stream_result(
+                s,
+                params.udp,
+                server_is_receiver,
+                has_retransmits,
+                duration_secs,
+            )
+        })
+        .collect();
+
+    let (sent, received) = if params.reverse {
+        (remote_streams, local_streams)
+    } else {
+        (local_streams, remote_streams)
+    };
+
+    // Pair streams by ID; a side that reported fewer streams leaves the
+    // other entries empty
+    let mut ids: Vec<u32> = sent.iter().chain(received.iter()).map(|s| s.id).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let find = |results: &[StreamResult], id: u32| {
+        results
+            .iter()
+            .find(|s| s.id == id)
+            .cloned()
+            .unwrap_or_else(|| StreamResult {
+                id,
+                seconds: duration_secs,
+                ..Default::default()
+            })
+    };
+    let streams = ids
+        .into_iter()
+        .map(|id| StreamEndResult {
+            sender: find(&sent, id),
+            receiver: find(&received, id),
+        })
+        .collect();
+
+    ClientResults {
+        server_addr,
+        cookie,
+        sum_sent: sum_results(&sent, duration_secs),
+        sum_received: sum_results(&received, duration_secs),
+        params,
+        duration_secs,
+        intervals,
+        streams,
+        local_results,
+        server_results,
+    }
+}
@@ -1,6 +1,7 @@
-//! Configuration for the iperf3 server.
+//! Configuration for the iperf3 server and client.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Configuration for the iperf3 server
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,6 +39,24 @@ pub struct Iperf3Config {
     /// Maximum bandwidth per stream in bits/second (0 = unlimited)
     #[serde(default)]
     pub max_bandwidth: u64,
+
+    /// Maximum concurrent tests per client IP (0 = unlimited)
+    #[serde(default)]
+    pub max_tests_per_ip: usize,
+
+    /// Bytes a client IP may transfer per day, UTC (0 = unlimited)
+    #[serde(default)]
+    pub daily_bytes_per_ip: u64,
+
+    /// Minimum time in seconds between the end of a client's test and the
+    /// start of its next one (0 = no cooldown)
+    #[serde(default)]
+    pub cooldown_secs: u64,
+
+    /// Maximum bandwidth per stream in bits/second for clients authorized by
+    /// a given magic key, applied on top of `max_bandwidth`
+    #[serde(default)]
+    pub magic_key_max_bandwidth: HashMap<String, u64>,
 }
 
 fn default_host() -> String {
@@ -71,6 +90,99 @@ impl Default for Iperf3Config {
             require_auth: false,
             auth_timeout_secs: default_auth_timeout(),
             max_bandwidth: 0,
+            max_tests_per_ip: 0,
+            daily_bytes_per_ip: 0,
+            cooldown_secs: 0,
+            magic_key_max_bandwidth: HashMap::new(),
+        }
+    }
+}
+
+/// Configuration for a test run by the iperf3 client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Iperf3ClientConfig {
+    /// Server host name or IP address
+    pub host: String,
+
+    /// Server control port (default: 5201)
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    /// Run a UDP test instead of TCP
+    #[serde(default)]
+    pub udp: bool,
+
+    /// Test duration in seconds
+    #[serde(default = "default_client_duration")]
+    pub duration_secs: u64,
+
+    /// Number of parallel streams
+    #[serde(default = "default_client_parallel")]
+    pub parallel: u32,
+
+    /// Reverse mode: server sends to client
+    #[serde(default)]
+    pub reverse: bool,
+
+    /// Target bandwidth per stream in bits/second
+    /// (0 = unlimited for TCP, 1 Mbps for UDP)
+    #[serde(default)]
+    pub bandwidth: u64,
+
+    /// Block size for writes in bytes (0 = protocol default)
+    #[serde(default)]
+    pub blksize: u32,
+
+    /// TCP window size (socket buffer size) in bytes (0 = system default)
+    #[serde(default)]
+    pub window: u32,
+
+    /// TCP maximum segment size (0 = system default)
+    #[serde(default)]
+    pub mss: u32,
+
+    /// Disable Nagle's algorithm (TCP_NODELAY)
+    #[serde(default)]
+    pub nodelay: bool,
+
+    /// TCP congestion-control algorithm (e.g. "cubic", "bbr"; None = system default)
+    #[serde(default)]
+    pub congestion: Option<String>,
+
+    /// Timeout in seconds for connecting and for each control message
+    #[serde(default = "default_client_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_client_duration() -> u64 {
+    10
+}
+
+fn default_client_parallel() -> u32 {
+    1
+}
+
+fn default_client_timeout() -> u64 {
+    10
+}
+
+impl Iperf3ClientConfig {
+    /// Create a client configuration for the given server with default test settings
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            udp: false,
+            duration_secs: default_client_duration(),
+            parallel: default_client_parallel(),
+            reverse: false,
+            bandwidth: 0,
+            blksize: 0,
+            window: 0,
+            mss: 0,
+            nodelay: false,
+            congestion: None,
+            timeout_secs: default_client_timeout(),
         }
     }
 }
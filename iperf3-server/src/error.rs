@@ -1,4 +1,4 @@
-//! Error types for the iperf3 server.
+//! Error types for the iperf3 server and client.
 
 use thiserror::Error;
 
@@ -21,6 +21,18 @@ pub enum Iperf3Error {
     #[error("Authentication error: IP {0} is not allowed")]
     Unauthorized(std::net::IpAddr),
 
+    /// The server refused the test (ACCESS_DENIED)
+    #[error("Access denied by server")]
+    AccessDenied,
+
+    /// The server aborted the test (SERVER_ERROR)
+    #[error("Server error during test")]
+    ServerError,
+
+    /// The test was refused by the admission policy
+    #[error("Test rejected by policy: {0}")]
+    Rejected(crate::policy::Rejection),
+
     /// Session limit reached
     #[error("Session limit reached: maximum {0} concurrent sessions")]
     SessionLimitReached(usize),
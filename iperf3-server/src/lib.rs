@@ -3,14 +3,21 @@
 //! A modular iperf3 server implementation in Rust.
 //!
 //! This crate provides a complete iperf3-compatible server that can be easily
-//! integrated into other applications or used standalone.
+//! integrated into other applications or used standalone, along with a matching
+//! client for running tests against any iperf3 server.
 //!
 //! ## Features
 //!
 //! - Full iperf3 protocol support (control connection + data streams)
-//! - TCP and UDP test modes
+//! - TCP and UDP test modes, with UDP jitter, loss and out-of-order reporting
+//! - TCP window, MSS, TCP_NODELAY and congestion-control tuning, with
+//!   `TCP_INFO` retransmit, congestion window and RTT reporting (Linux)
 //! - Configurable test parameters
 //! - IP-based access control for authenticated users
+//! - Per-IP concurrent test limits, daily byte quotas and cooldowns, and
+//!   per-magic-key bandwidth caps
+//! - Completed test records delivered to a callback for persistence
+//! - Async client (TCP/UDP, reverse, parallel, bandwidth) with typed results
 //! - Async/await based on Tokio
 //!
 //! ## Example
@@ -30,14 +37,36 @@
 //!     server.run().await.unwrap();
 //! }
 //! ```
+//!
+//! ## Client Example
+//!
+//! ```no_run
+//! use iperf3_server::{Iperf3Client, Iperf3ClientConfig};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut config = Iperf3ClientConfig::new("192.0.2.1", 5201);
+//!     config.udp = true;
+//!     config.bandwidth = 10_000_000;
+//!
+//!     let results = Iperf3Client::new(config).run().await.unwrap();
+//!     println!("jitter: {:?} ms", results.sum_received.jitter_ms);
+//! }
+//! ```
 
+pub mod client;
 pub mod config;
 pub mod error;
+pub mod policy;
 pub mod protocol;
 pub mod server;
 pub mod session;
+pub mod tcp;
+pub mod udp;
 
-pub use config::Iperf3Config;
+pub use client::{ClientResults, Iperf3Client};
+pub use config::{Iperf3ClientConfig, Iperf3Config};
 pub use error::Iperf3Error;
-pub use server::Iperf3Server;
+pub use policy::Iperf3Policy;
+pub use server::{Iperf3Server, TestRecord};
 pub use session::TestSession;
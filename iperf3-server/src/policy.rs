@@ -0,0 +1,208 @@
+//! Per-client admission policy for the iperf3 server.
+//!
+//! On top of the yes/no IP authorization, every new test is checked against
+//! the per-IP limits from the configuration: the number of concurrent tests,
+//! the bytes transferred today (UTC) and the cooldown since the previous test
+//! ended. The bandwidth of an admitted test is capped by the global limit and
+//! by the limit of the magic key that authorized the client, if any.
+
+use crate::config::Iperf3Config;
+use chrono::{NaiveDate, Utc};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Reason a test was refused by the policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// The client already runs the maximum number of concurrent tests
+    TooManyTests(usize),
+
+    /// The client used up its daily byte quota
+    DailyQuotaExceeded(u64),
+
+    /// The previous test ended too recently
+    Cooldown(Duration),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::TooManyTests(limit) => {
+                write!(f, "concurrent test limit of {} reached", limit)
+            }
+            Rejection::DailyQuotaExceeded(quota) => {
+                write!(f, "daily quota of {} bytes exceeded", quota)
+            }
+            Rejection::Cooldown(remaining) => {
+                write!(f, "cooldown, {} seconds remaining", remaining.as_secs() + 1)
+            }
+        }
+    }
+}
+
+/// Usage of the server by a single client IP
+#[derive(Debug)]
+struct ClientUsage {
+    /// Tests currently running
+    active_tests: usize,
+
+    /// Day (UTC) the byte count refers to
+    day: NaiveDate,
+
+    /// Bytes transferred on `day`
+    bytes_today: u64,
+
+    /// When the most recent test ended
+    last_test_end: Option<Instant>,
+}
+
+impl ClientUsage {
+    fn new(today: NaiveDate) -> Self {
+        Self {
+            active_tests: 0,
+            day: today,
+            bytes_today: 0,
+            last_test_end: None,
+        }
+    }
+
+    /// Start a new day's quota if the day changed
+    fn roll_over(&mut self, today: NaiveDate) {
+        if self.day != today {
+            self.day = today;
+            self.bytes_today = 0;
+        }
+    }
+}
+
+/// Admission policy and usage accounting for the iperf3 server
+#[derive(Debug)]
+pub struct Iperf3Policy {
+    /// Maximum concurrent tests per client IP (0 = unlimited)
+    max_tests_per_ip: usize,
+
+    /// Daily byte quota per client IP (0 = unlimited)
+    daily_bytes_per_ip: u64,
+
+    /// Minimum time between the end of a test and the start of the next one
+    cooldown: Duration,
+
+    /// Maximum test duration (zero = unlimited)
+    max_duration: Duration,
+
+    /// Global bandwidth limit in bits/second (0 = unlimited)
+    max_bandwidth: u64,
+
+    /// Bandwidth limits in bits/second per magic key
+    magic_key_max_bandwidth: HashMap<String, u64>,
+
+    /// Usage per client IP
+    usage: Mutex<HashMap<IpAddr, ClientUsage>>,
+}
+
+impl Iperf3Policy {
+    /// Create a policy from the server configuration
+    pub fn new(config: &Iperf3Config) -> Self {
+        Self {
+            max_tests_per_ip: config.max_tests_per_ip,
+            daily_bytes_per_ip: config.daily_bytes_per_ip,
+            cooldown: Duration::from_secs(config.cooldown_secs),
+            max_duration: Duration::from_secs(config.max_duration_secs),
+            max_bandwidth: config.max_bandwidth,
+            magic_key_max_bandwidth: config.magic_key_max_bandwidth.clone(),
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Maximum test duration (zero = unlimited)
+    pub fn max_duration(&self) -> Duration {
+        self.max_duration
+    }
+
+    /// Bandwidth limit in bits/second for a test authorized by the given
+    /// magic key: the stricter of the global and the per-key limit (0 = unlimited)
+    pub fn bandwidth_limit(&self, magic_key: Option<&str>) -> u64 {
+        let key_limit = magic_key
+            .and_then(|key| self.magic_key_max_bandwidth.get(key))
+            .copied()
+            .unwrap_or(0);
+
+        match (self.max_bandwidth, key_limit) {
+            (0, limit) | (limit, 0) => limit,
+            (global, key) => global.min(key),
+        }
+    }
+
+    /// Admit a new test from a client IP, counting it as running until
+    /// `finish` is called for it
+    pub fn admit(&self, ip: IpAddr) -> Result<(), Rejection> {
+        let today = Utc::now().date_naive();
+        let mut usage = self.usage.lock().unwrap();
+
+        // Forget clients that have nothing left to enforce
+        let cooldown = self.cooldown;
+        usage.retain(|_, client| {
+            client.active_tests > 0
+                || (client.day == today && client.bytes_today > 0)
+                || client
+                    .last_test_end
+                    .is_some_and(|end| end.elapsed() < cooldown)
+        });
+
+        let client = usage.entry(ip).or_insert_with(|| ClientUsage::new(today));
+        client.roll_over(today);
+
+        if self.max_tests_per_ip > 0 && client.active_tests >= self.max_tests_per_ip {
+            return Err(Rejection::TooManyTests(self.max_tests_per_ip));
+        }
+
+        if self.daily_bytes_per_ip > 0 && client.bytes_today >= self.daily_bytes_per_ip {
+            return Err(Rejection::DailyQuotaExceeded(self.daily_bytes_per_ip));
+        }
+
+        if let Some(end) = client.last_test_end {
+            let elapsed = end.elapsed();
+            if elapsed < self.cooldown {
+                return Err(Rejection::Cooldown(self.cooldown - elapsed));
+            }
+        }
+
+        client.active_tests += 1;
+        Ok(())
+    }
+
+    /// Record the end of an admitted test and the bytes it transferred
+    pub fn finish(&self, ip: IpAddr, bytes: u64) {
+        let today = Utc::now().date_naive();
+        let mut usage = self.usage.lock().unwrap();
+        let client = usage.entry(ip).or_insert_with(|| ClientUsage::new(today));
+        client.roll_over(today);
+
+        client.active_tests = client.active_tests.saturating_sub(1);
+        client.bytes_today += bytes;
+        client.last_test_end = Some(Instant::now());
+    }
+
+    /// Bytes a client IP transferred today
+    pub fn bytes_today(&self, ip: IpAddr) -> u64 {
+        let today = Utc::now().date_naive();
+        self.usage
+            .lock()
+            .unwrap()
+            .get(&ip)
+            .filter(|client| client.day == today)
+            .map_or(0, |client| client.bytes_today)
+    }
+
+    /// Number of tests a client IP is currently running
+    pub fn active_tests(&self, ip: IpAddr) -> usize {
+        self.usage
+            .lock()
+            .unwrap()
+            .get(&ip)
+            .map_or(0, |client| client.active_tests)
+    }
+}
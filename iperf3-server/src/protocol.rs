@@ -95,8 +95,8 @@ pub struct TestParameters {
     #[serde(default)]
     pub window: u32,
 
-    /// MSS for TCP
-    #[serde(default)]
+    /// MSS for TCP (sent as "MSS" by client)
+    #[serde(default, alias = "MSS")]
     pub mss: u32,
 
     /// No delay (TCP_NODELAY)
@@ -126,6 +126,14 @@ pub struct TestParameters {
     /// Interval for periodic reports (seconds)
     #[serde(default = "default_interval")]
     pub interval: f64,
+
+    /// Use 64-bit packet counters in UDP datagram headers (non-zero = yes)
+    #[serde(default)]
+    pub udp_counters_64bit: u32,
+
+    /// TCP congestion-control algorithm to use for the data streams
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub congestion: Option<String>,
 }
 
 fn default_protocol() -> String {
@@ -167,6 +175,8 @@ impl Default for TestParameters {
             client_version: String::new(),
             udp: false,
             interval: default_interval(),
+            udp_counters_64bit: 0,
+            congestion: None,
         }
     }
 }
@@ -177,6 +187,14 @@ pub struct StreamResult {
     /// Stream ID
     pub id: u32,
 
+    /// Interval start, in seconds since the test started (interval results only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<f64>,
+
+    /// Interval end, in seconds since the test started (interval results only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+
     /// Bytes transferred
     pub bytes: u64,
 
@@ -190,6 +208,42 @@ pub struct StreamResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub retransmits: Option<u64>,
 
+    /// Congestion window in bytes at the end of the interval (TCP sender only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snd_cwnd: Option<u64>,
+
+    /// Smoothed RTT in microseconds at the end of the interval (TCP sender only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtt: Option<u32>,
+
+    /// RTT variance in microseconds at the end of the interval (TCP sender only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rttvar: Option<u32>,
+
+    /// Path MTU (TCP sender only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pmtu: Option<u32>,
+
+    /// Pacing rate in bytes per second (TCP sender only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pacing_rate: Option<u64>,
+
+    /// Largest congestion window in bytes (TCP sender final results only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_snd_cwnd: Option<u64>,
+
+    /// Largest RTT in microseconds (TCP sender final results only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rtt: Option<u32>,
+
+    /// Smallest RTT in microseconds (TCP sender final results only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_rtt: Option<u32>,
+
+    /// Mean RTT in microseconds (TCP sender final results only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_rtt: Option<u32>,
+
     /// Jitter in milliseconds (UDP only)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub jitter_ms: Option<f64>,
@@ -205,6 +259,10 @@ pub struct StreamResult {
     /// Lost percentage (UDP only)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub lost_percent: Option<f64>,
+
+    /// Out-of-order packets (UDP only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub out_of_order: Option<u64>,
 }
 
 /// Interval result for periodic reporting
@@ -370,5 +428,31 @@ pub struct ExchangeStreamResult {
 /// Cookie length for stream identification
 pub const COOKIE_SIZE: usize = 37;
 
-/// iperf3 magic number for UDP packets
+/// UDP connect message sent by the client as the first datagram of each UDP stream.
+/// The reference implementation picks the integer so that it reads "9876" on
+/// the wire regardless of host byte order.
+pub const UDP_CONNECT_MSG: [u8; 4] = *b"9876";
+
+/// UDP connect reply sent by the server to accept a UDP stream ("6789")
+pub const UDP_CONNECT_REPLY: [u8; 4] = *b"6789";
+
+/// UDP connect reply sent by older servers (987654321 in host byte order)
+pub const LEGACY_UDP_CONNECT_REPLY: u32 = 987_654_321;
+
+/// Size of the UDP datagram header (sec, usec, 32-bit packet count)
 pub const UDP_HEADER_SIZE: usize = 12;
+
+/// Size of the UDP datagram header with 64-bit packet counters
+pub const UDP_HEADER_SIZE_64BIT: usize = 16;
+
+/// iperf3 stream ID for the stream at `index` in creation order.
+///
+/// The reference implementation numbers streams 1, 3, 4, 5, ... and the
+/// client matches exchanged results to its streams by this ID.
+pub fn stream_id(index: usize) -> u32 {
+    if index == 0 {
+        1
+    } else {
+        index as u32 + 2
+    }
+}
@@ -2,12 +2,15 @@
 
 use crate::config::Iperf3Config;
 use crate::error::{Iperf3Error, Result};
-use crate::protocol::{State, TestParameters, COOKIE_SIZE};
+use crate::policy::Iperf3Policy;
+use crate::protocol::{
+    ExchangeResultsData, ServerResults, State, TestParameters, COOKIE_SIZE, UDP_CONNECT_REPLY,
+};
 use crate::session::TestSession;
 use std::collections::HashMap;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tokio::sync::{broadcast, RwLock};
@@ -21,10 +24,6 @@ const STREAM_POLL_INTERVAL_MS: u64 = 50;
 /// Delay in milliseconds after test completion to allow remaining data to arrive
 const POST_TEST_DELAY_MS: u64 = 100;
 
-/// UDP connect reply magic value (iperf3 protocol specification)
-/// This is the ASCII bytes '6789' (0x36, 0x37, 0x38, 0x39)
-const UDP_CONNECT_REPLY: u32 = 0x36373839;
-
 /// Normalize an IP address by converting IPv4-mapped IPv6 addresses to IPv4.
 ///
 /// When an iperf3 server listens on :: (IPv6 any address), IPv4 connections
@@ -46,6 +45,40 @@ fn normalize_ip(ip: IpAddr) -> IpAddr {
 /// Callback type for checking if an IP is allowed
 pub type AuthCallback = Arc<dyn Fn(IpAddr) -> bool + Send + Sync>;
 
+/// Callback type for looking up the magic key that authorized an IP, if any
+pub type MagicKeyCallback = Arc<dyn Fn(IpAddr) -> Option<String> + Send + Sync>;
+
+/// Callback type invoked with the record of every completed test
+pub type ResultCallback = Arc<dyn Fn(TestRecord) + Send + Sync>;
+
+/// Record of a completed test, handed to the result callback
+#[derive(Debug, Clone)]
+pub struct TestRecord {
+    /// Session cookie sent by the client
+    pub cookie: String,
+
+    /// Client address of the control connection (IPv4-mapped addresses normalized)
+    pub client_addr: SocketAddr,
+
+    /// Test parameters, after server-side limits were applied
+    pub params: TestParameters,
+
+    /// Wall-clock time the test started
+    pub started_at: SystemTime,
+
+    /// Measured test duration in seconds
+    pub duration_secs: f64,
+
+    /// Server-side results, including per-interval results
+    pub results: ServerResults,
+
+    /// Results the server sent to the client during EXCHANGE_RESULTS
+    pub exchange_results: ExchangeResultsData,
+
+    /// Results the client sent to the server during EXCHANGE_RESULTS
+    pub client_results: serde_json::Value,
+}
+
 /// The iperf3 server
 pub struct Iperf3Server {
     /// Server configuration
@@ -62,6 +95,15 @@ pub struct Iperf3Server {
 
     /// Optional custom authentication callback
     auth_callback: Arc<RwLock<Option<AuthCallback>>>,
+
+    /// Optional callback receiving completed test records
+    result_callback: Arc<RwLock<Option<ResultCallback>>>,
+
+    /// Optional callback looking up the magic key of a client
+    magic_key_callback: Arc<RwLock<Option<MagicKeyCallback>>>,
+
+    /// Per-client limits and usage
+    policy: Arc<Iperf3Policy>,
 }
 
 impl Iperf3Server {
@@ -69,11 +111,14 @@ impl Iperf3Server {
     pub fn new(config: Iperf3Config) -> Self {
         let (shutdown_tx, _) = broadcast::channel(1);
         Self {
+            policy: Arc::new(Iperf3Policy::new(&config)),
             config,
             allowed_ips: Arc::new(RwLock::new(HashMap::new())),
             sessions: Arc::new(RwLock::new(HashMap::new())),
             shutdown_tx,
             auth_callback: Arc::new(RwLock::new(None)),
+            result_callback: Arc::new(RwLock::new(None)),
+            magic_key_callback: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -83,6 +128,22 @@ impl Iperf3Server {
         *self.auth_callback.write().await = Some(callback);
     }
 
+    /// Set a callback to receive the record of every completed test
+    pub async fn set_result_callback(&self, callback: ResultCallback) {
+        *self.result_callback.write().await = Some(callback);
+    }
+
+    /// Set a callback to look up the magic key that authorized a client IP.
+    /// The per-key bandwidth limits of the configuration apply to its tests.
+    pub async fn set_magic_key_callback(&self, callback: MagicKeyCallback) {
+        *self.magic_key_callback.write().await = Some(callback);
+    }
+
+    /// Get the per-client admission policy and usage
+    pub fn policy(&self) -> &Iperf3Policy {
+        &self.policy
+    }
+
     /// Add an allowed IP address
     pub async fn add_allowed_ip(&self, ip: IpAddr) {
         self.allowed_ips.write().await.insert(ip, ());
@@ -197,22 +258,35 @@ impl Iperf3Server {
     /// Handle a new connection
     async fn handle_connection(&self, stream: TcpStream, peer_addr: SocketAddr) {
         let sessions = self.sessions.clone();
-        let max_duration = Duration::from_secs(self.config.max_duration_secs);
-        let max_bandwidth = self.config.max_bandwidth;
+        let policy = self.policy.clone();
         let server_port = self.config.port;
+        let result_callback = self.result_callback.read().await.clone();
+        let magic_key = self
+            .magic_key_callback
+            .read()
+            .await
+            .as_ref()
+            .and_then(|callback| callback(normalize_ip(peer_addr.ip())));
 
         tokio::spawn(async move {
-            if let Err(e) = Self::handle_session(
+            match Self::handle_session(
                 stream,
                 peer_addr,
                 sessions,
-                max_duration,
-                max_bandwidth,
+                policy,
+                magic_key,
                 server_port,
+                result_callback,
             )
             .await
             {
-                tracing::error!("iperf3: Session error for {}: {}", peer_addr, e);
+                Ok(()) => {}
+                Err(Iperf3Error::Rejected(reason)) => {
+                    tracing::warn!("iperf3: Rejected test from {}: {}", peer_addr, reason);
+                }
+                Err(e) => {
+                    tracing::error!("iperf3: Session error for {}: {}", peer_addr, e);
+                }
             }
         });
     }
@@ -222,9 +296,10 @@ impl Iperf3Server {
         mut stream: TcpStream,
         peer_addr: SocketAddr,
         sessions: Arc<RwLock<HashMap<String, Arc<TestSession>>>>,
-        max_duration: Duration,
-        max_bandwidth: u64,
+        policy: Arc<Iperf3Policy>,
+        magic_key: Option<String>,
         server_port: u16,
+        result_callback: Option<ResultCallback>,
     ) -> Result<()> {
         // Step 1: Read the cookie (37 bytes for iperf3)
         let mut cookie_buf = [0u8; COOKIE_SIZE];
@@ -246,7 +321,23 @@ impl Iperf3Server {
             }
         }
 
-        // This is a new control connection
+        // This is a new control connection: check the per-client limits
+        let client_ip = normalize_ip(peer_addr.ip());
+        if let Err(reason) = policy.admit(client_ip) {
+            stream.write_all(&[State::AccessDenied.to_byte()]).await?;
+            return Err(Iperf3Error::Rejected(reason));
+        }
+
+        let max_bandwidth = policy.bandwidth_limit(magic_key.as_deref());
+        if let Some(key) = &magic_key {
+            tracing::debug!(
+                "iperf3: Test from {} authorized by magic key {}, bandwidth limit {} bps",
+                client_ip,
+                key,
+                max_bandwidth
+            );
+        }
+
         let session = Arc::new(TestSession::new(cookie.clone(), peer_addr, stream));
 
         // Store the session
@@ -256,9 +347,23 @@ impl Iperf3Server {
         }
 
         // Run the session protocol
-        let result =
-            Self::run_session_protocol(session.clone(), max_duration, max_bandwidth, server_port)
-                .await;
+        let result = Self::run_session_protocol(
+            session.clone(),
+            policy.max_duration(),
+            max_bandwidth,
+            server_port,
+        )
+        .await;
+
+        // Charge the transferred bytes whether or not the test completed
+        policy.finish(
+            client_ip,
+            session.get_bytes_sent() + session.get_bytes_received(),
+        );
+
+        if let (Ok(record), Some(callback)) = (&result, result_callback) {
+            callback(record.clone());
+        }
 
         // Clean up
         {
@@ -267,7 +372,7 @@ impl Iperf3Server {
         }
 
         tracing::info!("iperf3: Session {} ended", cookie);
-        result
+        result.map(|_| ())
     }
 
     /// Run the iperf3 protocol for a session.
@@ -287,12 +392,14 @@ impl Iperf3Server {
     /// 12. Server sends DISPLAY_RESULTS state
     /// 13. Client sends IPERF_DONE state
     /// 14. Server sends SERVER_TERMINATE state
+    ///
+    /// Returns the record of the completed test.
     async fn run_session_protocol(
         session: Arc<TestSession>,
         max_duration: Duration,
         max_bandwidth: u64,
         server_port: u16,
-    ) -> Result<()> {
+    ) -> Result<TestRecord> {
         // Step 1: Send PARAM_EXCHANGE state
         session.send_state(State::ParamExchange).await?;
 
@@ -314,18 +421,35 @@ impl Iperf3Server {
             );
         }
 
-        if max_bandwidth > 0 && params.bandwidth > max_bandwidth {
+        // An unlimited request (0) is capped as well
+        if max_bandwidth > 0 && (params.bandwidth == 0 || params.bandwidth > max_bandwidth) {
+            tracing::info!(
+                "iperf3: Limiting bandwidth from {} to {} bits/second",
+                params.bandwidth,
+                max_bandwidth
+            );
             params.bandwidth = max_bandwidth;
         }
 
+        session.set_params(params.clone());
+
         // Detect if this is a UDP test
         let is_udp = params.udp;
         session.set_udp_mode(is_udp);
 
         if is_udp {
             tracing::debug!("iperf3: UDP mode requested");
+            session.set_udp_counters_64bit(params.udp_counters_64bit != 0);
         }
 
+        // For UDP, bind the listener before announcing CREATE_STREAMS: the client
+        // sends its connect datagram as soon as it sees the state, only once
+        let udp_listener = if is_udp {
+            Some(Self::bind_udp_listener(server_port, session.client_addr).await?)
+        } else {
+            None
+        };
+
         // Step 3: Send CREATE_STREAMS state (no JSON acknowledgment needed)
         // The iperf3 protocol goes directly from reading parameters to sending CREATE_STREAMS
         session.send_state(State::CreateStreams).await?;
@@ -335,11 +459,12 @@ impl Iperf3Server {
         let timeout = Duration::from_secs(STREAM_CONNECT_TIMEOUT_SECS);
         let start = std::time::Instant::now();
 
-        if is_udp {
-            // For UDP, we need to create a UDP listener and accept connections
+        if let Some(udp_listener) = udp_listener {
+            // For UDP, we need to accept connections on the UDP listener
             // Pass the client address to determine the correct address family (IPv4 vs IPv6)
             Self::accept_udp_streams(
                 session.clone(),
+                udp_listener,
                 expected_streams,
                 server_port,
                 timeout,
@@ -370,6 +495,7 @@ impl Iperf3Server {
 
         // Start the test
         session.start_test().await;
+        let started_at = SystemTime::now();
 
         session.send_state(State::TestRunning).await?;
 
@@ -396,6 +522,9 @@ impl Iperf3Server {
             session.start_receiver_background(test_duration).await
         };
 
+        let interval_handle =
+            session.start_interval_reporter(Duration::from_secs_f64(params.interval.max(0.0)));
+
         // Step 7: Wait for TEST_END from client (this is what actually ends the test)
         let client_state = session.read_state().await?;
         if client_state != State::TestEnd {
@@ -407,6 +536,7 @@ impl Iperf3Server {
         for handle in data_handles {
             let _ = handle.await;
         }
+        let _ = interval_handle.await;
 
         // Wait a bit for any remaining data
         tokio::time::sleep(Duration::from_millis(POST_TEST_DELAY_MS)).await;
@@ -415,7 +545,7 @@ impl Iperf3Server {
         session.send_state(State::ExchangeResults).await?;
 
         // Step 9: Read client results (client sends JSON directly, no state byte)
-        let _client_results = session.read_json_message().await?;
+        let client_results = session.read_json_message().await?;
 
         // Step 10: Generate and send server exchange results
         // Note: This uses the exchange format, not the final output format
@@ -442,25 +572,76 @@ impl Iperf3Server {
             session.get_bytes_received()
         );
 
-        Ok(())
+        if is_udp {
+            for (index, stats) in session.udp_stream_stats().iter().enumerate() {
+                tracing::info!(
+                    "iperf3: UDP stream {} - packets: {}, lost: {} ({:.2}%), out-of-order: {}, jitter: {:.3} ms",
+                    index + 1,
+                    stats.packet_count,
+                    stats.lost_packets,
+                    stats.lost_percent(),
+                    stats.out_of_order,
+                    stats.jitter_ms()
+                );
+            }
+        }
+
+        Ok(TestRecord {
+            cookie: session.cookie.clone(),
+            client_addr: SocketAddr::new(
+                normalize_ip(session.client_addr.ip()),
+                session.client_addr.port(),
+            ),
+            params,
+            started_at,
+            duration_secs: elapsed.as_secs_f64(),
+            results: session.generate_results(elapsed.as_secs_f64()),
+            exchange_results,
+            client_results,
+        })
+    }
+
+    /// Create a UDP socket bound to the server port, using the same address
+    /// family as the client (IPv4 or IPv6)
+    async fn bind_udp_listener(server_port: u16, client_addr: SocketAddr) -> Result<UdpSocket> {
+        let bind_addr: SocketAddr = if client_addr.is_ipv6() {
+            SocketAddr::new(
+                std::net::IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED),
+                server_port,
+            )
+        } else {
+            SocketAddr::new(
+                std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED),
+                server_port,
+            )
+        };
+        let socket = UdpSocket::bind(bind_addr).await.map_err(|e| {
+            Iperf3Error::Protocol(format!("Failed to bind UDP socket to {}: {}", bind_addr, e))
+        })?;
+
+        tracing::debug!("iperf3: UDP listener bound to {}", bind_addr);
+        Ok(socket)
     }
 
     /// Accept UDP streams for a session.
     ///
     /// For UDP, unlike TCP, we need to:
-    /// 1. Create a UDP socket bound to the server port
+    /// 1. Create a UDP socket bound to the server port (the first one is bound
+    ///    by the caller before CREATE_STREAMS is sent)
     /// 2. Wait for client to send a datagram
     /// 3. "Connect" the UDP socket to the client address
     /// 4. Send a reply to confirm connection
     /// 5. Repeat for each expected stream
     async fn accept_udp_streams(
         session: Arc<TestSession>,
+        first_listener: UdpSocket,
         expected_streams: usize,
         server_port: u16,
         timeout: Duration,
         client_addr: SocketAddr,
     ) -> Result<()> {
         let start = std::time::Instant::now();
+        let mut next_listener = Some(first_listener);
 
         for stream_num in 0..expected_streams {
             if start.elapsed() > timeout {
@@ -470,26 +651,12 @@ impl Iperf3Server {
                 )));
             }
 
-            // Create a UDP socket bound to the server port
-            // Use the same address family as the client (IPv4 or IPv6)
             // Note: For multiple parallel streams, we would need SO_REUSEPORT or different ports.
             // Currently, parallel UDP streams > 1 may not work correctly.
-            let bind_addr: SocketAddr = if client_addr.is_ipv6() {
-                SocketAddr::new(
-                    std::net::IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED),
-                    server_port,
-                )
-            } else {
-                SocketAddr::new(
-                    std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED),
-                    server_port,
-                )
+            let socket = match next_listener.take() {
+                Some(socket) => socket,
+                None => Self::bind_udp_listener(server_port, client_addr).await?,
             };
-            let socket = UdpSocket::bind(bind_addr).await.map_err(|e| {
-                Iperf3Error::Protocol(format!("Failed to bind UDP socket to {}: {}", bind_addr, e))
-            })?;
-
-            tracing::debug!("iperf3: UDP listener bound to {}", bind_addr);
 
             // Wait for a datagram from the client
             let mut buf = [0u8; 4];
@@ -522,9 +689,8 @@ impl Iperf3Server {
             })?;
 
             // Send reply to confirm connection
-            let reply = UDP_CONNECT_REPLY.to_be_bytes();
             socket
-                .send(&reply)
+                .send(&UDP_CONNECT_REPLY)
                 .await
                 .map_err(|e| Iperf3Error::Protocol(format!("Failed to send UDP reply: {}", e)))?;
 
@@ -2,9 +2,11 @@
 
 use crate::error::{Iperf3Error, Result};
 use crate::protocol::{
-    ConnectedInfo, EndInfo, ExchangeResultsData, ExchangeStreamResult, ServerResults, StartInfo,
-    State, StreamEndResult, StreamResult, TestParameters, TestStartInfo,
+    stream_id, ConnectedInfo, EndInfo, ExchangeResultsData, ExchangeStreamResult, IntervalResult,
+    ServerResults, StartInfo, State, StreamEndResult, StreamResult, TestParameters, TestStartInfo,
 };
+use crate::tcp::{self, TcpStreamStats};
+use crate::udp::{wall_clock_secs, UdpHeader, UdpStreamStats};
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
@@ -22,6 +24,9 @@ const BUFFER_SIZE: usize = 128 * 1024;
 /// Timeout in milliseconds for data stream read operations
 const READ_TIMEOUT_MS: u64 = 100;
 
+/// Interval in milliseconds between TCP_INFO samples of a data stream
+const TCP_INFO_SAMPLE_MS: u64 = 250;
+
 /// Sleep interval in milliseconds for bandwidth limiting
 const BANDWIDTH_LIMIT_SLEEP_MS: u64 = 1;
 
@@ -39,8 +44,8 @@ pub struct TestSession {
     /// Client address
     pub client_addr: SocketAddr,
 
-    /// Test parameters
-    pub params: TestParameters,
+    /// Test parameters (set once the client has sent them)
+    params: std::sync::RwLock<TestParameters>,
 
     /// Control connection
     control_stream: Arc<Mutex<TcpStream>>,
@@ -51,9 +56,18 @@ pub struct TestSession {
     /// Data streams (for TCP tests)
     data_streams: Arc<Mutex<Vec<Arc<Mutex<TcpStream>>>>>,
 
+    /// Per-stream TCP statistics, indexed like `data_streams`
+    tcp_stats: Arc<std::sync::Mutex<Vec<TcpStreamStats>>>,
+
+    /// Congestion-control algorithm in use on the TCP data streams
+    congestion_used: std::sync::Mutex<Option<String>>,
+
     /// UDP data sockets (for UDP tests)
     udp_streams: Arc<Mutex<Vec<Arc<UdpSocket>>>>,
 
+    /// Per-stream UDP statistics, indexed like `udp_streams`
+    udp_stats: Arc<std::sync::Mutex<Vec<UdpStreamStats>>>,
+
     /// Session start time
     pub started_at: Instant,
 
@@ -71,6 +85,12 @@ pub struct TestSession {
 
     /// Whether this is a UDP test
     is_udp: Arc<AtomicBool>,
+
+    /// Whether UDP datagram headers carry 64-bit packet counters
+    udp_counters_64bit: Arc<AtomicBool>,
+
+    /// Per-interval results collected while the test runs
+    intervals: Arc<std::sync::Mutex<Vec<IntervalResult>>>,
 }
 
 impl TestSession {
@@ -79,20 +99,40 @@ impl TestSession {
         Self {
             cookie,
             client_addr,
-            params: TestParameters::default(),
+            params: std::sync::RwLock::new(TestParameters::default()),
             control_stream: Arc::new(Mutex::new(control_stream)),
             state: Arc::new(Mutex::new(State::ParamExchange)),
             data_streams: Arc::new(Mutex::new(Vec::new())),
+            tcp_stats: Arc::new(std::sync::Mutex::new(Vec::new())),
+            congestion_used: std::sync::Mutex::new(None),
             udp_streams: Arc::new(Mutex::new(Vec::new())),
+            udp_stats: Arc::new(std::sync::Mutex::new(Vec::new())),
             started_at: Instant::now(),
             test_started_at: Arc::new(Mutex::new(None)),
             bytes_received: Arc::new(AtomicU64::new(0)),
             bytes_sent: Arc::new(AtomicU64::new(0)),
             cancelled: Arc::new(AtomicBool::new(false)),
             is_udp: Arc::new(AtomicBool::new(false)),
+            udp_counters_64bit: Arc::new(AtomicBool::new(false)),
+            intervals: Arc::new(std::sync::Mutex::new(Vec::new())),
         }
     }
 
+    /// Get the test parameters
+    pub fn params(&self) -> TestParameters {
+        self.params.read().unwrap().clone()
+    }
+
+    /// Set the test parameters (after server-side limits have been applied)
+    pub fn set_params(&self, params: TestParameters) {
+        *self.params.write().unwrap() = params;
+    }
+
+    /// Get the per-interval results collected so far
+    pub fn intervals(&self) -> Vec<IntervalResult> {
+        self.intervals.lock().unwrap().clone()
+    }
+
     /// Set whether this is a UDP test
     pub fn set_udp_mode(&self, is_udp: bool) {
         self.is_udp.store(is_udp, Ordering::SeqCst);
@@ -103,6 +143,31 @@ impl TestSession {
         self.is_udp.load(Ordering::SeqCst)
     }
 
+    /// Set whether UDP datagram headers use 64-bit packet counters
+    pub fn set_udp_counters_64bit(&self, enabled: bool) {
+        self.udp_counters_64bit.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Check if UDP datagram headers use 64-bit packet counters
+    pub fn udp_counters_64bit(&self) -> bool {
+        self.udp_counters_64bit.load(Ordering::SeqCst)
+    }
+
+    /// Get a snapshot of the per-stream UDP statistics
+    pub fn udp_stream_stats(&self) -> Vec<UdpStreamStats> {
+        self.udp_stats.lock().unwrap().clone()
+    }
+
+    /// Get a snapshot of the per-stream TCP statistics
+    pub fn tcp_stream_stats(&self) -> Vec<TcpStreamStats> {
+        self.tcp_stats.lock().unwrap().clone()
+    }
+
+    /// Get the congestion-control algorithm in use on the TCP data streams
+    pub fn congestion_used(&self) -> Option<String> {
+        self.congestion_used.lock().unwrap().clone()
+    }
+
     /// Cancel the session
     pub fn cancel(&self) {
         self.cancelled.store(true, Ordering::SeqCst);
@@ -143,16 +208,31 @@ impl TestSession {
         self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
     }
 
-    /// Add a TCP data stream
+    /// Add a TCP data stream, applying the socket options and congestion
+    /// control requested in the test parameters
     pub async fn add_data_stream(&self, stream: TcpStream) {
+        let params = self.params();
+        tcp::configure_stream(&stream, &params);
+        if let Some(congestion) = tcp::set_congestion(&stream, params.congestion.as_deref()) {
+            *self.congestion_used.lock().unwrap() = Some(congestion);
+        }
+
         let mut streams = self.data_streams.lock().await;
         streams.push(Arc::new(Mutex::new(stream)));
+        self.tcp_stats
+            .lock()
+            .unwrap()
+            .push(TcpStreamStats::default());
     }
 
     /// Add a UDP data stream
     pub async fn add_udp_stream(&self, socket: Arc<UdpSocket>) {
         let mut streams = self.udp_streams.lock().await;
         streams.push(socket);
+        self.udp_stats
+            .lock()
+            .unwrap()
+            .push(UdpStreamStats::default());
     }
 
     /// Get number of data streams (TCP or UDP)
@@ -251,10 +331,11 @@ impl TestSession {
 
         // Spawn receiver tasks for each stream
         let mut handles = Vec::new();
-        for stream in streams.iter() {
+        for (index, stream) in streams.iter().enumerate() {
             let stream = stream.clone();
             let cancelled = cancelled.clone();
             let bytes_received = bytes_received.clone();
+            let tcp_stats = self.tcp_stats.clone();
 
             let handle = tokio::spawn(async move {
                 let mut buf = vec![0u8; BUFFER_SIZE];
@@ -273,6 +354,7 @@ impl TestSession {
                         Ok(Ok(0)) => break, // Connection closed
                         Ok(Ok(n)) => {
                             bytes_received.fetch_add(n as u64, Ordering::Relaxed);
+                            tcp_stats.lock().unwrap()[index].record_bytes(n as u64);
                         }
                         Ok(Err(_)) => break, // Error
                         Err(_) => continue,  // Timeout, check again
@@ -287,50 +369,7 @@ impl TestSession {
 
     /// Run the data stream receiving loop (for normal mode - client sends to server)
     pub async fn run_receiver(&self, max_duration: Duration) -> Result<()> {
-        let streams = self.data_streams.lock().await;
-        if streams.is_empty() {
-            return Ok(());
-        }
-
-        let cancelled = self.cancelled.clone();
-        let bytes_received = self.bytes_received.clone();
-        let deadline = Instant::now() + max_duration;
-
-        // Spawn receiver tasks for each stream
-        let mut handles = Vec::new();
-        for stream in streams.iter() {
-            let stream = stream.clone();
-            let cancelled = cancelled.clone();
-            let bytes_received = bytes_received.clone();
-
-            let handle = tokio::spawn(async move {
-                let mut buf = vec![0u8; BUFFER_SIZE];
-                loop {
-                    if cancelled.load(Ordering::SeqCst) || Instant::now() > deadline {
-                        break;
-                    }
-
-                    let mut stream_guard = stream.lock().await;
-                    match tokio::time::timeout(
-                        Duration::from_millis(READ_TIMEOUT_MS),
-                        stream_guard.read(&mut buf),
-                    )
-                    .await
-                    {
-                        Ok(Ok(0)) => break, // Connection closed
-                        Ok(Ok(n)) => {
-                            bytes_received.fetch_add(n as u64, Ordering::Relaxed);
-                        }
-                        Ok(Err(_)) => break, // Error
-                        Err(_) => continue,  // Timeout, check again
-                    }
-                }
-            });
-            handles.push(handle);
-        }
-
-        // Wait for all receiver tasks
-        for handle in handles {
+        for handle in self.start_receiver_background(max_duration).await {
             let _ = handle.await;
         }
 
@@ -352,7 +391,7 @@ impl TestSession {
         let cancelled = self.cancelled.clone();
         let bytes_sent = self.bytes_sent.clone();
         let deadline = Instant::now() + max_duration;
-        let blksize = self.params.blksize as usize;
+        let blksize = self.params().blksize as usize;
 
         // Calculate bytes per interval for bandwidth limiting
         let bytes_per_second = if bandwidth > 0 {
@@ -363,14 +402,16 @@ impl TestSession {
 
         // Spawn sender tasks for each stream
         let mut handles = Vec::new();
-        for stream in streams.iter() {
+        for (index, stream) in streams.iter().enumerate() {
             let stream = stream.clone();
             let cancelled = cancelled.clone();
             let bytes_sent = bytes_sent.clone();
+            let tcp_stats = self.tcp_stats.clone();
 
             let handle = tokio::spawn(async move {
                 let buf = vec![0u8; blksize];
                 let mut last_send = Instant::now();
+                let mut last_sample = Instant::now();
                 let mut bytes_this_second: u64 = 0;
 
                 loop {
@@ -378,6 +419,16 @@ impl TestSession {
                         break;
                     }
 
+                    // The sender's TCP_INFO carries retransmits, cwnd and RTT.
+                    // Sample before bandwidth limiting so paced streams are covered too.
+                    if last_sample.elapsed() >= Duration::from_millis(TCP_INFO_SAMPLE_MS) {
+                        last_sample = Instant::now();
+                        let stream_guard = stream.lock().await;
+                        if let Some(info) = tcp::read_tcp_info(&stream_guard) {
+                            tcp_stats.lock().unwrap()[index].record_tcp_info(info);
+                        }
+                    }
+
                     // Bandwidth limiting
                     if bytes_per_second != u64::MAX {
                         let elapsed = last_send.elapsed();
@@ -396,10 +447,17 @@ impl TestSession {
                         Ok(_) => {
                             bytes_sent.fetch_add(blksize as u64, Ordering::Relaxed);
                             bytes_this_second += blksize as u64;
+                            tcp_stats.lock().unwrap()[index].record_bytes(blksize as u64);
                         }
                         Err(_) => break,
                     }
                 }
+
+                // Final sample so the results cover the whole test
+                let stream_guard = stream.lock().await;
+                if let Some(info) = tcp::read_tcp_info(&stream_guard) {
+                    tcp_stats.lock().unwrap()[index].record_tcp_info(info);
+                }
             });
             handles.push(handle);
         }
@@ -409,68 +467,7 @@ impl TestSession {
 
     /// Run the data stream sending loop (for reverse mode - server sends to client)
     pub async fn run_sender(&self, max_duration: Duration, bandwidth: u64) -> Result<()> {
-        let streams = self.data_streams.lock().await;
-        if streams.is_empty() {
-            return Ok(());
-        }
-
-        let cancelled = self.cancelled.clone();
-        let bytes_sent = self.bytes_sent.clone();
-        let deadline = Instant::now() + max_duration;
-        let blksize = self.params.blksize as usize;
-
-        // Calculate bytes per interval for bandwidth limiting
-        let bytes_per_second = if bandwidth > 0 {
-            bandwidth / 8 // Convert bits to bytes
-        } else {
-            u64::MAX // No limit
-        };
-
-        // Spawn sender tasks for each stream
-        let mut handles = Vec::new();
-        for stream in streams.iter() {
-            let stream = stream.clone();
-            let cancelled = cancelled.clone();
-            let bytes_sent = bytes_sent.clone();
-
-            let handle = tokio::spawn(async move {
-                let buf = vec![0u8; blksize];
-                let mut last_send = Instant::now();
-                let mut bytes_this_second: u64 = 0;
-
-                loop {
-                    if cancelled.load(Ordering::SeqCst) || Instant::now() > deadline {
-                        break;
-                    }
-
-                    // Bandwidth limiting
-                    if bytes_per_second != u64::MAX {
-                        let elapsed = last_send.elapsed();
-                        if elapsed >= Duration::from_secs(1) {
-                            last_send = Instant::now();
-                            bytes_this_second = 0;
-                        } else if bytes_this_second >= bytes_per_second {
-                            tokio::time::sleep(Duration::from_millis(BANDWIDTH_LIMIT_SLEEP_MS))
-                                .await;
-                            continue;
-                        }
-                    }
-
-                    let mut stream_guard = stream.lock().await;
-                    match stream_guard.write_all(&buf).await {
-                        Ok(_) => {
-                            bytes_sent.fetch_add(blksize as u64, Ordering::Relaxed);
-                            bytes_this_second += blksize as u64;
-                        }
-                        Err(_) => break,
-                    }
-                }
-            });
-            handles.push(handle);
-        }
-
-        // Wait for all sender tasks
-        for handle in handles {
+        for handle in self.start_sender_background(max_duration, bandwidth).await {
             let _ = handle.await;
         }
 
@@ -491,13 +488,15 @@ impl TestSession {
         let cancelled = self.cancelled.clone();
         let bytes_received = self.bytes_received.clone();
         let deadline = Instant::now() + max_duration;
+        let counters_64bit = self.udp_counters_64bit();
 
         // Spawn receiver tasks for each UDP stream
         let mut handles = Vec::new();
-        for socket in streams.iter() {
+        for (index, socket) in streams.iter().enumerate() {
             let socket = socket.clone();
             let cancelled = cancelled.clone();
             let bytes_received = bytes_received.clone();
+            let udp_stats = self.udp_stats.clone();
 
             let handle = tokio::spawn(async move {
                 let mut buf = vec![0u8; BUFFER_SIZE];
@@ -515,6 +514,14 @@ impl TestSession {
                         Ok(Ok(0)) => continue, // UDP can receive 0-byte datagrams, continue
                         Ok(Ok(n)) => {
                             bytes_received.fetch_add(n as u64, Ordering::Relaxed);
+
+                            // Datagrams too short for a header (e.g. a repeated
+                            // connect message) only count towards bytes
+                            if let Some(header) = UdpHeader::parse(&buf[..n], counters_64bit) {
+                                let arrival = wall_clock_secs();
+                                let mut stats = udp_stats.lock().unwrap();
+                                stats[index].record_received(&header, arrival, n as u64);
+                            }
                         }
                         Ok(Err(_)) => break, // Error
                         Err(_) => continue,  // Timeout, check again
@@ -543,11 +550,14 @@ impl TestSession {
         let cancelled = self.cancelled.clone();
         let bytes_sent = self.bytes_sent.clone();
         let deadline = Instant::now() + max_duration;
+        let counters_64bit = self.udp_counters_64bit();
         let blksize = if blksize > 0 {
             blksize as usize
         } else {
             DEFAULT_UDP_BLKSIZE
         };
+        // Every datagram must be able to carry the header
+        let blksize = blksize.max(UdpHeader::size(counters_64bit));
 
         // Calculate bytes per interval for bandwidth limiting
         let bytes_per_second = if bandwidth > 0 {
@@ -558,15 +568,17 @@ impl TestSession {
 
         // Spawn sender tasks for each UDP stream
         let mut handles = Vec::new();
-        for socket in streams.iter() {
+        for (index, socket) in streams.iter().enumerate() {
             let socket = socket.clone();
             let cancelled = cancelled.clone();
             let bytes_sent = bytes_sent.clone();
+            let udp_stats = self.udp_stats.clone();
 
             let handle = tokio::spawn(async move {
-                let buf = vec![0u8; blksize];
+                let mut buf = vec![0u8; blksize];
                 let mut last_send = Instant::now();
                 let mut bytes_this_second: u64 = 0;
+                let mut pcount: u64 = 0;
 
                 loop {
                     if cancelled.load(Ordering::SeqCst) || Instant::now() > deadline {
@@ -583,10 +595,14 @@ impl TestSession {
                         continue;
                     }
 
+                    pcount += 1;
+                    UdpHeader::now(pcount).write(&mut buf, counters_64bit);
+
                     match socket.send(&buf).await {
                         Ok(n) => {
                             bytes_sent.fetch_add(n as u64, Ordering::Relaxed);
                             bytes_this_second += n as u64;
+                            udp_stats.lock().unwrap()[index].record_sent(n as u64);
                         }
                         Err(_) => break,
                     }
@@ -598,6 +614,193 @@ impl TestSession {
         handles
     }
 
+    /// Start collecting per-interval results in background.
+    /// The final, possibly partial, interval is recorded when the session is cancelled.
+    pub fn start_interval_reporter(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let cancelled = self.cancelled.clone();
+        let bytes_sent = self.bytes_sent.clone();
+        let bytes_received = self.bytes_received.clone();
+        let udp_stats = self.udp_stats.clone();
+        let tcp_stats = self.tcp_stats.clone();
+        let intervals = self.intervals.clone();
+        let is_udp = self.is_udp_mode();
+        let interval = if interval.is_zero() {
+            Duration::from_secs(1)
+        } else {
+            interval
+        };
+
+        tokio::spawn(async move {
+            let test_start = Instant::now();
+            let mut interval_start = test_start;
+            let mut last_bytes = 0u64;
+            let mut last_udp: Vec<UdpStreamStats> = Vec::new();
+            let mut last_tcp: Vec<TcpStreamStats> = Vec::new();
+
+            loop {
+                let done = tokio::select! {
+                    _ = tokio::time::sleep_until((interval_start + interval).into()) => false,
+                    _ = async {
+                        while !cancelled.load(Ordering::SeqCst) {
+                            tokio::time::sleep(Duration::from_millis(READ_TIMEOUT_MS)).await;
+                        }
+                    } => true,
+                };
+
+                let now = Instant::now();
+                let start = interval_start.duration_since(test_start).as_secs_f64();
+                let end = now.duration_since(test_start).as_secs_f64();
+                let seconds = end - start;
+
+                // Only one direction carries data, so the total is the interval's traffic
+                let total_bytes =
+                    bytes_sent.load(Ordering::Relaxed) + bytes_received.load(Ordering::Relaxed);
+                let bytes = total_bytes - last_bytes;
+                last_bytes = total_bytes;
+
+                let stream_result = |id: u32, bytes: u64| StreamResult {
+                    id,
+                    bytes,
+                    seconds,
+                    bits_per_second: if seconds > 0.0 {
+                        bytes as f64 * 8.0 / seconds
+                    } else {
+                        0.0
+                    },
+                    start: Some(start),
+                    end: Some(end),
+                    ..Default::default()
+                };
+
+                let streams: Vec<StreamResult> = if is_udp {
+                    let current = udp_stats.lock().unwrap().clone();
+                    let streams = current
+                        .iter()
+                        .enumerate()
+                        .map(|(index, stats)| {
+                            let previous = last_udp.get(index).cloned().unwrap_or_default();
+                            let packets = stats.packet_count - previous.packet_count;
+                            let lost = stats.lost_packets.saturating_sub(previous.lost_packets);
+                            StreamResult {
+                                jitter_ms: Some(stats.jitter_ms()),
+                                lost_packets: Some(lost),
+                                packets: Some(packets),
+                                lost_percent: Some(if packets > 0 {
+                                    100.0 * lost as f64 / packets as f64
+                                } else {
+                                    0.0
+                                }),
+                                out_of_order: Some(stats.out_of_order - previous.out_of_order),
+                                ..stream_result(stream_id(index), stats.bytes - previous.bytes)
+                            }
+                        })
+                        .collect();
+                    last_udp = current;
+                    streams
+                } else {
+                    let current = tcp_stats.lock().unwrap().clone();
+                    let streams = current
+                        .iter()
+                        .enumerate()
+                        .map(|(index, stats)| {
+                            let previous = last_tcp.get(index).cloned().unwrap_or_default();
+                            let mut result =
+                                stream_result(stream_id(index), stats.bytes - previous.bytes);
+                            // Only the sending side samples TCP_INFO
+                            if let Some(info) = stats.tcp_info {
+                                result.retransmits = Some(
+                                    info.total_retransmits
+                                        .saturating_sub(previous.retransmits().unwrap_or(0)),
+                                );
+                                result.snd_cwnd = Some(info.snd_cwnd);
+                                result.rtt = Some(info.rtt_us);
+                                result.rttvar = Some(info.rttvar_us);
+                                result.pmtu = Some(info.pmtu);
+                                result.pacing_rate = Some(info.pacing_rate);
+                            }
+                            result
+                        })
+                        .collect();
+                    last_tcp = current;
+                    streams
+                };
+
+                // Skip an empty trailing interval when the test ends on a boundary
+                if !done || seconds > 0.0 {
+                    let retransmits = streams
+                        .iter()
+                        .filter_map(|s| s.retransmits)
+                        .reduce(|a, b| a + b);
+                    intervals.lock().unwrap().push(IntervalResult {
+                        streams,
+                        sum: StreamResult {
+                            retransmits,
+                            ..stream_result(0, bytes)
+                        },
+                    });
+                }
+
+                if done {
+                    break;
+                }
+                interval_start = now;
+            }
+        })
+    }
+
+    /// Build a stream result from UDP statistics
+    fn udp_stream_result(id: u32, stats: &UdpStreamStats, test_duration: f64) -> StreamResult {
+        let bits_per_second = if test_duration > 0.0 {
+            (stats.bytes as f64 * 8.0) / test_duration
+        } else {
+            0.0
+        };
+
+        StreamResult {
+            id,
+            bytes: stats.bytes,
+            seconds: test_duration,
+            bits_per_second,
+            jitter_ms: Some(stats.jitter_ms()),
+            lost_packets: Some(stats.lost_packets),
+            packets: Some(stats.packet_count),
+            lost_percent: Some(stats.lost_percent()),
+            out_of_order: Some(stats.out_of_order),
+            ..Default::default()
+        }
+    }
+
+    /// Build a stream result from TCP statistics, including the TCP_INFO
+    /// figures when this side sampled them (i.e. it was the sender)
+    fn tcp_stream_result(id: u32, stats: &TcpStreamStats, test_duration: f64) -> StreamResult {
+        let bits_per_second = if test_duration > 0.0 {
+            (stats.bytes as f64 * 8.0) / test_duration
+        } else {
+            0.0
+        };
+
+        let mut result = StreamResult {
+            id,
+            bytes: stats.bytes,
+            seconds: test_duration,
+            bits_per_second,
+            ..Default::default()
+        };
+
+        if let Some(info) = stats.tcp_info {
+            result.retransmits = Some(info.total_retransmits);
+            result.snd_cwnd = Some(info.snd_cwnd);
+            result.pmtu = Some(info.pmtu);
+            result.pacing_rate = Some(info.pacing_rate);
+            result.max_snd_cwnd = Some(stats.max_snd_cwnd);
+            result.max_rtt = Some(stats.max_rtt_us);
+            result.min_rtt = Some(stats.min_rtt_us);
+            result.mean_rtt = stats.mean_rtt_us();
+        }
+
+        result
+    }
+
     /// Generate server results
     pub fn generate_results(&self, test_duration: f64) -> ServerResults {
         let bytes_sent = self.bytes_sent.load(Ordering::Relaxed);
@@ -615,6 +818,110 @@ impl TestSession {
             0.0
         };
 
+        let params = self.params();
+        let udp_stats = self.udp_stream_stats();
+        let (streams, sum_sent, sum_received) = if self.is_udp_mode() && !udp_stats.is_empty() {
+            // The server only sees one side of each UDP stream; the other side
+            // carries plain byte counts
+            let server_is_sender = bytes_sent > 0;
+            let mut total = UdpStreamStats::default();
+            let mut jitter_total = 0.0;
+            let streams = udp_stats
+                .iter()
+                .enumerate()
+                .map(|(index, stats)| {
+                    total.bytes += stats.bytes;
+                    total.packet_count += stats.packet_count;
+                    total.lost_packets += stats.lost_packets;
+                    total.out_of_order += stats.out_of_order;
+                    jitter_total += stats.jitter;
+
+                    let measured = Self::udp_stream_result(stream_id(index), stats, test_duration);
+                    let other = StreamResult {
+                        id: stream_id(index),
+                        seconds: test_duration,
+                        ..Default::default()
+                    };
+                    if server_is_sender {
+                        StreamEndResult {
+                            sender: measured,
+                            receiver: other,
+                        }
+                    } else {
+                        StreamEndResult {
+                            sender: other,
+                            receiver: measured,
+                        }
+                    }
+                })
+                .collect();
+
+            // Like the reference implementation, the summed jitter is the
+            // average of the per-stream values
+            total.jitter = jitter_total / udp_stats.len() as f64;
+            let sum = Self::udp_stream_result(0, &total, test_duration);
+            let other = StreamResult {
+                id: 0,
+                seconds: test_duration,
+                ..Default::default()
+            };
+            if server_is_sender {
+                (streams, Some(sum), Some(other))
+            } else {
+                (streams, Some(other), Some(sum))
+            }
+        } else {
+            // As for UDP, only one side of each TCP stream is measured here
+            let server_is_sender = bytes_sent > 0;
+            let tcp_stats = self.tcp_stream_stats();
+            let streams: Vec<StreamEndResult> = tcp_stats
+                .iter()
+                .enumerate()
+                .map(|(index, stats)| {
+                    let measured = Self::tcp_stream_result(stream_id(index), stats, test_duration);
+                    let other = StreamResult {
+                        id: stream_id(index),
+                        seconds: test_duration,
+                        ..Default::default()
+                    };
+                    if server_is_sender {
+                        StreamEndResult {
+                            sender: measured,
+                            receiver: other,
+                        }
+                    } else {
+                        StreamEndResult {
+                            sender: other,
+                            receiver: measured,
+                        }
+                    }
+                })
+                .collect();
+            let retransmits = streams
+                .iter()
+                .filter_map(|s| s.sender.retransmits)
+                .reduce(|a, b| a + b);
+
+            (
+                streams,
+                Some(StreamResult {
+                    id: 0,
+                    bytes: bytes_sent,
+                    seconds: test_duration,
+                    bits_per_second: sent_bps,
+                    retransmits,
+                    ..Default::default()
+                }),
+                Some(StreamResult {
+                    id: 0,
+                    bytes: bytes_received,
+                    seconds: test_duration,
+                    bits_per_second: received_bps,
+                    ..Default::default()
+                }),
+            )
+        };
+
         ServerResults {
             start: StartInfo {
                 connected: vec![ConnectedInfo {
@@ -627,64 +934,21 @@ impl TestSession {
                 version: "iperf 3.16 (Rust)".to_string(),
                 system_info: "Rust iperf3 server".to_string(),
                 test_start: TestStartInfo {
-                    protocol: self.params.protocol.clone(),
-                    num_streams: self.params.parallel,
-                    blksize: self.params.blksize,
-                    omit: self.params.omit,
-                    duration: self.params.time,
-                    bytes: self.params.bytes,
-                    blocks: self.params.blockcount,
-                    reverse: self.params.reverse,
+                    protocol: params.protocol.clone(),
+                    num_streams: params.parallel,
+                    blksize: params.blksize,
+                    omit: params.omit,
+                    duration: params.time,
+                    bytes: params.bytes,
+                    blocks: params.blockcount,
+                    reverse: params.reverse,
                 },
             },
-            intervals: vec![],
+            intervals: self.intervals(),
             end: EndInfo {
-                streams: vec![StreamEndResult {
-                    sender: StreamResult {
-                        id: 1,
-                        bytes: bytes_sent,
-                        seconds: test_duration,
-                        bits_per_second: sent_bps,
-                        retransmits: Some(0),
-                        jitter_ms: None,
-                        lost_packets: None,
-                        packets: None,
-                        lost_percent: None,
-                    },
-                    receiver: StreamResult {
-                        id: 1,
-                        bytes: bytes_received,
-                        seconds: test_duration,
-                        bits_per_second: received_bps,
-                        retransmits: None,
-                        jitter_ms: None,
-                        lost_packets: None,
-                        packets: None,
-                        lost_percent: None,
-                    },
-                }],
-                sum_sent: Some(StreamResult {
-                    id: 0,
-                    bytes: bytes_sent,
-                    seconds: test_duration,
-                    bits_per_second: sent_bps,
-                    retransmits: Some(0),
-                    jitter_ms: None,
-                    lost_packets: None,
-                    packets: None,
-                    lost_percent: None,
-                }),
-                sum_received: Some(StreamResult {
-                    id: 0,
-                    bytes: bytes_received,
-                    seconds: test_duration,
-                    bits_per_second: received_bps,
-                    retransmits: None,
-                    jitter_ms: None,
-                    lost_packets: None,
-                    packets: None,
-                    lost_percent: None,
-                }),
+                streams,
+                sum_sent,
+                sum_received,
                 cpu_utilization_percent: None,
             },
         }
@@ -693,28 +957,66 @@ impl TestSession {
     /// Generate exchange results (format used during EXCHANGE_RESULTS phase)
     /// This is different from the final output format
     pub fn generate_exchange_results(&self, test_duration: f64) -> ExchangeResultsData {
-        let bytes_received = self.bytes_received.load(Ordering::Relaxed);
+        let bytes_sent = self.bytes_sent.load(Ordering::Relaxed);
+
+        let udp_stats = self.udp_stream_stats();
+        let tcp_stats = self.tcp_stream_stats();
+        let streams = if self.is_udp_mode() && !udp_stats.is_empty() {
+            // For UDP the peer takes jitter, loss and packet counts from us:
+            // as receiver these are our measurements, as sender the packets sent
+            udp_stats
+                .iter()
+                .enumerate()
+                .map(|(index, stats)| ExchangeStreamResult {
+                    id: stream_id(index),
+                    bytes: stats.bytes,
+                    retransmits: -1,
+                    jitter: stats.jitter,
+                    errors: stats.lost_packets,
+                    omitted_errors: 0,
+                    packets: stats.packet_count,
+                    omitted_packets: 0,
+                    start_time: 0.0,
+                    end_time: test_duration,
+                })
+                .collect()
+        } else {
+            tcp_stats
+                .iter()
+                .enumerate()
+                .map(|(index, stats)| ExchangeStreamResult {
+                    id: stream_id(index),
+                    bytes: stats.bytes,
+                    retransmits: stats.retransmits().map_or(-1, |r| r as i64),
+                    jitter: 0.0,
+                    errors: 0,
+                    omitted_errors: 0,
+                    packets: 0,
+                    omitted_packets: 0,
+                    start_time: 0.0,
+                    end_time: test_duration,
+                })
+                .collect()
+        };
+
+        // -1 means we're in receiver mode (client sends to server),
+        // 0 means we sent but have no retransmit information,
+        // 1 means we sent and report retransmits from TCP_INFO
+        let sender_has_retransmits = if bytes_sent == 0 {
+            -1
+        } else if tcp_stats.iter().any(|s| s.tcp_info.is_some()) {
+            1
+        } else {
+            0
+        };
 
         ExchangeResultsData {
             cpu_util_total: 0.0,
             cpu_util_user: 0.0,
             cpu_util_system: 0.0,
-            // -1 means we're in receiver mode (client sends to server)
-            // This will be different for reverse mode
-            sender_has_retransmits: -1,
-            congestion_used: Some("cubic".to_string()),
-            streams: vec![ExchangeStreamResult {
-                id: 1,
-                bytes: bytes_received,
-                retransmits: -1,
-                jitter: 0.0,
-                errors: 0,
-                omitted_errors: 0,
-                packets: 0,
-                omitted_packets: 0,
-                start_time: 0.0,
-                end_time: test_duration,
-            }],
+            sender_has_retransmits,
+            congestion_used: self.congestion_used(),
+            streams,
         }
     }
 }
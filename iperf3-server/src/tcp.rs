@@ -0,0 +1,265 @@
+//! TCP data stream tuning and `TCP_INFO` statistics.
+//!
+//! The window size, MSS, TCP_NODELAY and congestion-control algorithm
+//! requested in the test parameters are applied to each TCP data socket.
+//! While data flows, the kernel's `TCP_INFO` is sampled for each stream to
+//! report retransmits, congestion window, RTT and pacing rate the way the
+//! reference implementation does. Socket options other than the window size
+//! and TCP_NODELAY, and `TCP_INFO` itself, are only available on Linux.
+
+use crate::protocol::TestParameters;
+use socket2::SockRef;
+use tokio::net::{TcpSocket, TcpStream};
+
+/// Snapshot of the kernel's `TCP_INFO` for a socket
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpInfo {
+    /// Total retransmitted segments over the life of the connection
+    pub total_retransmits: u64,
+
+    /// Sender congestion window in bytes
+    pub snd_cwnd: u64,
+
+    /// Smoothed round-trip time in microseconds
+    pub rtt_us: u32,
+
+    /// Round-trip time variance in microseconds
+    pub rttvar_us: u32,
+
+    /// Path MTU
+    pub pmtu: u32,
+
+    /// Pacing rate in bytes per second (0 if not reported by the kernel)
+    pub pacing_rate: u64,
+}
+
+/// Statistics for a single TCP data stream
+#[derive(Debug, Clone, Default)]
+pub struct TcpStreamStats {
+    /// Bytes sent or received on this stream
+    pub bytes: u64,
+
+    /// Most recent `TCP_INFO` sample, if any was taken
+    pub tcp_info: Option<TcpInfo>,
+
+    /// Largest congestion window seen in bytes
+    pub max_snd_cwnd: u64,
+
+    /// Smallest smoothed RTT seen in microseconds
+    pub min_rtt_us: u32,
+
+    /// Largest smoothed RTT seen in microseconds
+    pub max_rtt_us: u32,
+
+    /// Sum of the RTT samples, for the mean
+    rtt_sum_us: u64,
+
+    /// Number of RTT samples
+    rtt_samples: u64,
+}
+
+impl TcpStreamStats {
+    /// Account for bytes sent or received on this stream
+    pub fn record_bytes(&mut self, bytes: u64) {
+        self.bytes += bytes;
+    }
+
+    /// Account for a `TCP_INFO` sample of this stream
+    pub fn record_tcp_info(&mut self, info: TcpInfo) {
+        self.max_snd_cwnd = self.max_snd_cwnd.max(info.snd_cwnd);
+        if info.rtt_us > 0 {
+            self.min_rtt_us = if self.rtt_samples == 0 {
+                info.rtt_us
+            } else {
+                self.min_rtt_us.min(info.rtt_us)
+            };
+            self.max_rtt_us = self.max_rtt_us.max(info.rtt_us);
+            self.rtt_sum_us += info.rtt_us as u64;
+            self.rtt_samples += 1;
+        }
+        self.tcp_info = Some(info);
+    }
+
+    /// Total retransmits so far, if `TCP_INFO` is available
+    pub fn retransmits(&self) -> Option<u64> {
+        self.tcp_info.map(|info| info.total_retransmits)
+    }
+
+    /// Mean of the smoothed RTT samples in microseconds
+    pub fn mean_rtt_us(&self) -> Option<u32> {
+        self.rtt_sum_us
+            .checked_div(self.rtt_samples)
+            .map(|mean| mean as u32)
+    }
+}
+
+/// Apply the window size (socket buffers) and TCP_NODELAY requested in the
+/// test parameters to a connected data stream. On Linux the MSS is applied as
+/// well, but it only takes full effect on sockets configured before connecting
+/// (see `configure_socket`).
+pub fn configure_stream(stream: &TcpStream, params: &TestParameters) {
+    let socket = SockRef::from(stream);
+
+    if params.nodelay {
+        if let Err(e) = stream.set_nodelay(true) {
+            tracing::warn!("iperf3: Failed to set TCP_NODELAY: {}", e);
+        }
+    }
+
+    if params.window > 0 {
+        let window = params.window as usize;
+        if let Err(e) = socket
+            .set_send_buffer_size(window)
+            .and_then(|_| socket.set_recv_buffer_size(window))
+        {
+            tracing::warn!("iperf3: Failed to set window size {}: {}", window, e);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if params.mss > 0 {
+        if let Err(e) = socket.set_mss(params.mss) {
+            tracing::warn!("iperf3: Failed to set MSS {}: {}", params.mss, e);
+        }
+    }
+}
+
+/// Apply the window size and MSS requested in the test parameters to a socket
+/// before it connects, so they shape the handshake (window scaling, MSS option)
+pub fn configure_socket(socket: &TcpSocket, params: &TestParameters) {
+    if params.window > 0 {
+        if let Err(e) = socket
+            .set_send_buffer_size(params.window)
+            .and_then(|_| socket.set_recv_buffer_size(params.window))
+        {
+            tracing::warn!("iperf3: Failed to set window size {}: {}", params.window, e);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if params.mss > 0 {
+        if let Err(e) = SockRef::from(socket).set_mss(params.mss) {
+            tracing::warn!("iperf3: Failed to set MSS {}: {}", params.mss, e);
+        }
+    }
+}
+
+/// Set the congestion-control algorithm of a data stream and return the
+/// algorithm actually in use afterwards
+#[cfg(target_os = "linux")]
+pub fn set_congestion(stream: &TcpStream, algorithm: Option<&str>) -> Option<String> {
+    let socket = SockRef::from(stream);
+
+    if let Some(algorithm) = algorithm {
+        if let Err(e) = socket.set_tcp_congestion(algorithm.as_bytes()) {
+            tracing::warn!(
+                "iperf3: Failed to set congestion control {}: {}",
+                algorithm,
+                e
+            );
+        }
+    }
+
+    socket.tcp_congestion().ok().map(|name| {
+        String::from_utf8_lossy(&name)
+            .trim_end_matches('\0')
+            .to_string()
+    })
+}
+
+/// Set the congestion-control algorithm of a data stream (not supported on this platform)
+#[cfg(not(target_os = "linux"))]
+pub fn set_congestion(_stream: &TcpStream, algorithm: Option<&str>) -> Option<String> {
+    if let Some(algorithm) = algorithm {
+        tracing::warn!(
+            "iperf3: Congestion control {} requested but not supported on this platform",
+            algorithm
+        );
+    }
+    None
+}
+
+/// Kernel `struct tcp_info` up to `tcpi_max_pacing_rate`. The libc definition
+/// stops before the pacing rate, so the layout is mirrored here.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct RawTcpInfo {
+    state: u8,
+    ca_state: u8,
+    retransmits: u8,
+    probes: u8,
+    backoff: u8,
+    options: u8,
+    wscale: u8,
+    flags: u8,
+    rto: u32,
+    ato: u32,
+    snd_mss: u32,
+    rcv_mss: u32,
+    unacked: u32,
+    sacked: u32,
+    lost: u32,
+    retrans: u32,
+    fackets: u32,
+    last_data_sent: u32,
+    last_ack_sent: u32,
+    last_data_recv: u32,
+    last_ack_recv: u32,
+    pmtu: u32,
+    rcv_ssthresh: u32,
+    rtt: u32,
+    rttvar: u32,
+    snd_ssthresh: u32,
+    snd_cwnd: u32,
+    advmss: u32,
+    reordering: u32,
+    rcv_rtt: u32,
+    rcv_space: u32,
+    total_retrans: u32,
+    pacing_rate: u64,
+    max_pacing_rate: u64,
+}
+
+/// Read `TCP_INFO` for a stream
+#[cfg(target_os = "linux")]
+pub fn read_tcp_info(stream: &TcpStream) -> Option<TcpInfo> {
+    use std::os::fd::AsRawFd;
+
+    let mut raw = RawTcpInfo::default();
+    let mut len = std::mem::size_of::<RawTcpInfo>() as libc::socklen_t;
+
+    // Older kernels fill in less than we ask for; the remaining fields stay zero
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut raw as *mut RawTcpInfo as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return None;
+    }
+
+    Some(TcpInfo {
+        total_retransmits: raw.total_retrans as u64,
+        snd_cwnd: raw.snd_cwnd as u64 * raw.snd_mss as u64,
+        rtt_us: raw.rtt,
+        rttvar_us: raw.rttvar,
+        pmtu: raw.pmtu,
+        // u64::MAX means pacing is not in use
+        pacing_rate: if raw.pacing_rate == u64::MAX {
+            0
+        } else {
+            raw.pacing_rate
+        },
+    })
+}
+
+/// Read `TCP_INFO` for a stream (not supported on this platform)
+#[cfg(not(target_os = "linux"))]
+pub fn read_tcp_info(_stream: &TcpStream) -> Option<TcpInfo> {
+    None
+}
@@ -0,0 +1,174 @@
+//! iperf3 UDP datagram header and per-stream statistics.
+//!
+//! Every datagram of an iperf3 UDP test starts with a small header written by
+//! the sender:
+//!
+//! ```text
+//! +--------+--------+----------------------------------+
+//! | sec    | usec   | packet count                     |
+//! | u32 BE | u32 BE | u32 BE (or u64 BE, 64-bit mode)  |
+//! +--------+--------+----------------------------------+
+//! ```
+//!
+//! The timestamp is the sender's wall clock at send time and the packet count
+//! starts at 1. The receiver uses both to compute jitter (RFC 1889), loss and
+//! out-of-order counts the same way the reference implementation does.
+
+use crate::protocol::{UDP_HEADER_SIZE, UDP_HEADER_SIZE_64BIT};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Header carried at the start of every iperf3 UDP datagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdpHeader {
+    /// Send time, seconds part
+    pub sec: u32,
+    /// Send time, microseconds part
+    pub usec: u32,
+    /// Packet sequence number (first packet is 1)
+    pub pcount: u64,
+}
+
+impl UdpHeader {
+    /// Create a header stamped with the current wall-clock time
+    pub fn now(pcount: u64) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            sec: now.as_secs() as u32,
+            usec: now.subsec_micros(),
+            pcount,
+        }
+    }
+
+    /// Size of the header on the wire
+    pub fn size(counters_64bit: bool) -> usize {
+        if counters_64bit {
+            UDP_HEADER_SIZE_64BIT
+        } else {
+            UDP_HEADER_SIZE
+        }
+    }
+
+    /// Parse a header from the start of a datagram.
+    /// Returns None if the datagram is too short to carry one.
+    pub fn parse(buf: &[u8], counters_64bit: bool) -> Option<Self> {
+        if buf.len() < Self::size(counters_64bit) {
+            return None;
+        }
+
+        let sec = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let usec = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        let pcount = if counters_64bit {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&buf[8..16]);
+            u64::from_be_bytes(bytes)
+        } else {
+            u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]) as u64
+        };
+
+        Some(Self { sec, usec, pcount })
+    }
+
+    /// Write the header into the start of a datagram buffer.
+    /// Returns false if the buffer is too short to hold it.
+    pub fn write(&self, buf: &mut [u8], counters_64bit: bool) -> bool {
+        if buf.len() < Self::size(counters_64bit) {
+            return false;
+        }
+
+        buf[0..4].copy_from_slice(&self.sec.to_be_bytes());
+        buf[4..8].copy_from_slice(&self.usec.to_be_bytes());
+        if counters_64bit {
+            buf[8..16].copy_from_slice(&self.pcount.to_be_bytes());
+        } else {
+            // 32-bit counters wrap, as they do in the reference implementation
+            buf[8..12].copy_from_slice(&(self.pcount as u32).to_be_bytes());
+        }
+
+        true
+    }
+
+    /// Send time in fractional seconds
+    pub fn sent_secs(&self) -> f64 {
+        self.sec as f64 + self.usec as f64 / 1_000_000.0
+    }
+}
+
+/// Current wall-clock time in fractional seconds, comparable with `UdpHeader::sent_secs`
+pub fn wall_clock_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Statistics for a single UDP data stream
+#[derive(Debug, Clone, Default)]
+pub struct UdpStreamStats {
+    /// Bytes sent or received on this stream
+    pub bytes: u64,
+
+    /// Highest packet count seen (receiver) or packets sent (sender)
+    pub packet_count: u64,
+
+    /// Packets inferred lost from gaps in the sequence
+    pub lost_packets: u64,
+
+    /// Packets that arrived with a sequence number below the highest seen
+    pub out_of_order: u64,
+
+    /// Smoothed jitter in seconds
+    pub jitter: f64,
+
+    /// Transit time of the previous packet, used for jitter
+    prev_transit: Option<f64>,
+}
+
+impl UdpStreamStats {
+    /// Account for a datagram sent on this stream
+    pub fn record_sent(&mut self, bytes: u64) {
+        self.bytes += bytes;
+        self.packet_count += 1;
+    }
+
+    /// Account for a datagram received on this stream.
+    ///
+    /// `arrival_secs` is the local wall-clock arrival time; the sender's clock
+    /// offset cancels out because jitter only looks at transit differences.
+    pub fn record_received(&mut self, header: &UdpHeader, arrival_secs: f64, bytes: u64) {
+        self.bytes += bytes;
+
+        if header.pcount > self.packet_count {
+            // Anything skipped between the previous highest and this one is
+            // presumed lost until it shows up late
+            self.lost_packets += header.pcount - self.packet_count - 1;
+            self.packet_count = header.pcount;
+        } else {
+            self.out_of_order += 1;
+            self.lost_packets = self.lost_packets.saturating_sub(1);
+        }
+
+        // RFC 1889 interarrival jitter: J += (|D| - J) / 16
+        let transit = arrival_secs - header.sent_secs();
+        if let Some(prev_transit) = self.prev_transit {
+            let d = (transit - prev_transit).abs();
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.prev_transit = Some(transit);
+    }
+
+    /// Jitter in milliseconds
+    pub fn jitter_ms(&self) -> f64 {
+        self.jitter * 1000.0
+    }
+
+    /// Lost packets as a percentage of the packets expected
+    pub fn lost_percent(&self) -> f64 {
+        if self.packet_count > 0 {
+            100.0 * self.lost_packets as f64 / self.packet_count as f64
+        } else {
+            0.0
+        }
+    }
+}
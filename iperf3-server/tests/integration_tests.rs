@@ -1,7 +1,11 @@
-//! Integration tests for the iperf3 server.
+//! Integration tests for the iperf3 server and client.
 
-use iperf3_server::{Iperf3Config, Iperf3Server};
+use iperf3_server::{
+    Iperf3Client, Iperf3ClientConfig, Iperf3Config, Iperf3Error, Iperf3Server, TestRecord,
+};
 use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 #[test]
 fn test_config_defaults() {
@@ -26,6 +30,10 @@ fn test_config_serialization() {
         require_auth: true,
         auth_timeout_secs: 300,
         max_bandwidth: 1_000_000_000,
+        max_tests_per_ip: 2,
+        daily_bytes_per_ip: 10_000_000_000,
+        cooldown_secs: 30,
+        magic_key_max_bandwidth: [("survey-key".to_string(), 5_000_000)].into(),
     };
 
     let json = serde_json::to_string(&config).unwrap();
@@ -39,6 +47,13 @@ fn test_config_serialization() {
     assert_eq!(parsed.require_auth, config.require_auth);
     assert_eq!(parsed.auth_timeout_secs, config.auth_timeout_secs);
     assert_eq!(parsed.max_bandwidth, config.max_bandwidth);
+    assert_eq!(parsed.max_tests_per_ip, config.max_tests_per_ip);
+    assert_eq!(parsed.daily_bytes_per_ip, config.daily_bytes_per_ip);
+    assert_eq!(parsed.cooldown_secs, config.cooldown_secs);
+    assert_eq!(
+        parsed.magic_key_max_bandwidth,
+        config.magic_key_max_bandwidth
+    );
 }
 
 #[tokio::test]
@@ -125,3 +140,480 @@ fn test_test_parameters_defaults() {
     assert_eq!(params.bandwidth, 0);
     assert_eq!(params.blksize, 128 * 1024);
 }
+
+#[test]
+fn test_udp_header_roundtrip() {
+    use iperf3_server::udp::UdpHeader;
+
+    let header = UdpHeader {
+        sec: 1_700_000_000,
+        usec: 123_456,
+        pcount: 42,
+    };
+
+    let mut buf = [0u8; 16];
+    assert!(header.write(&mut buf, false));
+    assert_eq!(UdpHeader::parse(&buf, false), Some(header));
+
+    assert!(header.write(&mut buf, true));
+    assert_eq!(UdpHeader::parse(&buf, true), Some(header));
+
+    // Too short to carry a header (e.g. the 4-byte connect message)
+    assert_eq!(UdpHeader::parse(&[0u8; 4], false), None);
+    assert!(!header.write(&mut [0u8; 12], true));
+}
+
+#[test]
+fn test_udp_stats_loss_and_reordering() {
+    use iperf3_server::udp::{UdpHeader, UdpStreamStats};
+
+    let mut stats = UdpStreamStats::default();
+    let header = |pcount| UdpHeader {
+        sec: 100,
+        usec: 0,
+        pcount,
+    };
+
+    // 1, 2, 5 (3 and 4 missing), then 3 arrives late
+    for pcount in [1, 2, 5, 3] {
+        stats.record_received(&header(pcount), 100.010, 1000);
+    }
+
+    assert_eq!(stats.packet_count, 5);
+    assert_eq!(stats.lost_packets, 1);
+    assert_eq!(stats.out_of_order, 1);
+    assert_eq!(stats.bytes, 4000);
+    assert!((stats.lost_percent() - 20.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_udp_stats_jitter() {
+    use iperf3_server::udp::{UdpHeader, UdpStreamStats};
+
+    let mut stats = UdpStreamStats::default();
+
+    // Constant transit time produces no jitter, including on the first packet
+    for pcount in 1..=3 {
+        let header = UdpHeader {
+            sec: pcount as u32,
+            usec: 0,
+            pcount,
+        };
+        stats.record_received(&header, pcount as f64 + 0.050, 100);
+    }
+    assert!(stats.jitter_ms() < 1e-6);
+
+    // A 16 ms transit change moves jitter by 1/16 of it
+    let header = UdpHeader {
+        sec: 4,
+        usec: 0,
+        pcount: 4,
+    };
+    stats.record_received(&header, 4.066, 100);
+    assert!((stats.jitter_ms() - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_stream_ids() {
+    use iperf3_server::protocol::stream_id;
+
+    assert_eq!(stream_id(0), 1);
+    assert_eq!(stream_id(1), 3);
+    assert_eq!(stream_id(2), 4);
+}
+
+/// Find a local port that is free for both TCP and UDP
+fn free_port() -> u16 {
+    loop {
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = tcp.local_addr().unwrap().port();
+        if std::net::UdpSocket::bind(("0.0.0.0", port)).is_ok() {
+            return port;
+        }
+    }
+}
+
+/// Start a server on a free local port, collecting completed test records
+async fn start_server(require_auth: bool) -> (Arc<Iperf3Server>, u16, Arc<Mutex<Vec<TestRecord>>>) {
+    start_server_with(Iperf3Config {
+        require_auth,
+        ..Default::default()
+    })
+    .await
+}
+
+/// Start a server with the given limits on a free local port
+async fn start_server_with(
+    config: Iperf3Config,
+) -> (Arc<Iperf3Server>, u16, Arc<Mutex<Vec<TestRecord>>>) {
+    let port = free_port();
+    let config = Iperf3Config {
+        enabled: true,
+        host: "127.0.0.1".to_string(),
+        port,
+        ..config
+    };
+    let server = Arc::new(Iperf3Server::new(config));
+
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let records_clone = records.clone();
+    server
+        .set_result_callback(Arc::new(move |record| {
+            records_clone.lock().unwrap().push(record);
+        }))
+        .await;
+
+    let server_clone = server.clone();
+    tokio::spawn(async move { server_clone.run().await });
+
+    // Wait until the listener is up
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .is_ok()
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    (server, port, records)
+}
+
+/// Wait for the server to report the test with the given cookie
+async fn wait_for_record(records: &Mutex<Vec<TestRecord>>, cookie: &str) -> TestRecord {
+    for _ in 0..100 {
+        if let Some(record) = records.lock().unwrap().iter().find(|r| r.cookie == cookie) {
+            return record.clone();
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("server did not record test {}", cookie);
+}
+
+fn client_config(port: u16, udp: bool, reverse: bool) -> Iperf3ClientConfig {
+    let mut config = Iperf3ClientConfig::new("127.0.0.1", port);
+    config.udp = udp;
+    config.reverse = reverse;
+    config.duration_secs = 1;
+    if udp {
+        config.bandwidth = 2_000_000;
+        config.blksize = 1000;
+    } else {
+        config.bandwidth = 50_000_000;
+    }
+    config
+}
+
+#[test]
+fn test_client_parameters_defaults() {
+    let client = Iperf3Client::new(Iperf3ClientConfig::new("127.0.0.1", 5201));
+    let params = client.test_parameters();
+    assert_eq!(params.protocol, "TCP");
+    assert_eq!(params.time, 10);
+    assert_eq!(params.parallel, 1);
+    assert_eq!(params.bandwidth, 0);
+    assert_eq!(params.blksize, 128 * 1024);
+
+    let mut config = Iperf3ClientConfig::new("127.0.0.1", 5201);
+    config.udp = true;
+    let params = Iperf3Client::new(config).test_parameters();
+    assert!(params.udp);
+    assert_eq!(params.protocol, "UDP");
+    assert!(params.bandwidth > 0);
+    assert!(params.blksize <= 1500);
+}
+
+#[tokio::test]
+async fn test_client_tcp_round_trip() {
+    let (server, port, records) = start_server(false).await;
+
+    let results = Iperf3Client::new(client_config(port, false, false))
+        .run()
+        .await
+        .unwrap();
+    assert!(results.sum_sent.bytes > 0);
+    assert!(results.sum_received.bytes > 0);
+    assert!(results.duration_secs >= 1.0);
+    assert!(!results.intervals.is_empty());
+
+    let record = wait_for_record(&records, &results.cookie).await;
+    assert!(!record.params.reverse);
+    assert_eq!(record.params.time, 1);
+    assert_eq!(
+        record.results.end.sum_received.unwrap().bytes,
+        results.sum_received.bytes
+    );
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn test_client_tcp_reverse_round_trip() {
+    let (server, port, records) = start_server(false).await;
+
+    let results = Iperf3Client::new(client_config(port, false, true))
+        .run()
+        .await
+        .unwrap();
+    assert!(results.sum_sent.bytes > 0);
+    assert!(results.sum_received.bytes > 0);
+
+    let record = wait_for_record(&records, &results.cookie).await;
+    assert!(record.params.reverse);
+    assert_eq!(
+        record.results.end.sum_sent.unwrap().bytes,
+        results.sum_sent.bytes
+    );
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn test_client_udp_round_trip() {
+    let (server, port, records) = start_server(false).await;
+
+    let results = Iperf3Client::new(client_config(port, true, false))
+        .run()
+        .await
+        .unwrap();
+    assert!(results.sum_sent.packets.unwrap() > 0);
+    let received_packets = results.sum_received.packets.unwrap();
+    assert!(received_packets > 0);
+    assert!(results.sum_received.lost_packets.unwrap() <= received_packets);
+    assert!(results.sum_received.jitter_ms.is_some());
+
+    // The server measured the receiving side and handed its figures to the client
+    let record = wait_for_record(&records, &results.cookie).await;
+    assert!(record.params.udp);
+    assert_eq!(
+        record.results.end.sum_received.unwrap().packets,
+        Some(received_packets)
+    );
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn test_client_udp_reverse_round_trip() {
+    let (server, port, _records) = start_server(false).await;
+
+    let results = Iperf3Client::new(client_config(port, true, true))
+        .run()
+        .await
+        .unwrap();
+    assert!(results.sum_sent.packets.unwrap() > 0);
+    assert!(results.sum_received.packets.unwrap() > 0);
+    assert!(results.sum_received.jitter_ms.is_some());
+    assert_eq!(results.streams.len(), 1);
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn test_client_access_denied() {
+    let (server, port, _records) = start_server(true).await;
+
+    let result = Iperf3Client::new(client_config(port, false, false))
+        .run()
+        .await;
+    assert!(matches!(result, Err(Iperf3Error::AccessDenied)));
+
+    server.shutdown();
+}
+
+#[test]
+fn test_test_parameters_tcp_options() {
+    use iperf3_server::protocol::TestParameters;
+
+    // Reference clients send the MSS as "MSS" and the block size as "len"
+    let params: TestParameters = serde_json::from_str(
+        r#"{"tcp": true, "time": 5, "len": 65536, "window": 262144, "MSS": 1200, "nodelay": true, "congestion": "reno"}"#,
+    )
+    .unwrap();
+    assert_eq!(params.blksize, 65536);
+    assert_eq!(params.window, 262144);
+    assert_eq!(params.mss, 1200);
+    assert!(params.nodelay);
+    assert_eq!(params.congestion.as_deref(), Some("reno"));
+}
+
+#[test]
+fn test_tcp_stream_stats_rtt() {
+    use iperf3_server::tcp::{TcpInfo, TcpStreamStats};
+
+    let mut stats = TcpStreamStats::default();
+    assert_eq!(stats.retransmits(), None);
+    assert_eq!(stats.mean_rtt_us(), None);
+
+    for (rtt_us, snd_cwnd) in [(300, 14_480), (100, 28_960), (200, 20_000)] {
+        stats.record_tcp_info(TcpInfo {
+            total_retransmits: 2,
+            snd_cwnd,
+            rtt_us,
+            ..Default::default()
+        });
+    }
+
+    assert_eq!(stats.retransmits(), Some(2));
+    assert_eq!(stats.min_rtt_us, 100);
+    assert_eq!(stats.max_rtt_us, 300);
+    assert_eq!(stats.mean_rtt_us(), Some(200));
+    assert_eq!(stats.max_snd_cwnd, 28_960);
+    assert_eq!(stats.tcp_info.unwrap().snd_cwnd, 20_000);
+}
+
+#[tokio::test]
+async fn test_client_tcp_tuning_round_trip() {
+    let (server, port, records) = start_server(false).await;
+
+    let mut config = client_config(port, false, false);
+    config.parallel = 2;
+    config.window = 256 * 1024;
+    config.nodelay = true;
+    config.congestion = Some("reno".to_string());
+
+    let results = Iperf3Client::new(config).run().await.unwrap();
+    assert_eq!(results.streams.len(), 2);
+    assert!(results.streams.iter().all(|s| s.receiver.bytes > 0));
+
+    let record = wait_for_record(&records, &results.cookie).await;
+    assert_eq!(record.params.window, 256 * 1024);
+    assert!(record.params.nodelay);
+    assert_eq!(record.results.end.streams.len(), 2);
+
+    // TCP_INFO of the sending side is reported per stream and in the sum
+    #[cfg(target_os = "linux")]
+    {
+        assert!(results.sum_sent.retransmits.is_some());
+        for stream in &results.streams {
+            assert!(stream.sender.snd_cwnd.unwrap() > 0);
+            assert!(stream.sender.mean_rtt.is_some());
+        }
+        assert!(results
+            .intervals
+            .iter()
+            .all(|i| i.streams.iter().all(|s| s.rtt.is_some())));
+        assert_eq!(
+            record.exchange_results.congestion_used.as_deref(),
+            Some("reno")
+        );
+    }
+
+    server.shutdown();
+}
+
+#[test]
+fn test_policy_bandwidth_limit() {
+    use iperf3_server::Iperf3Policy;
+
+    let mut config = Iperf3Config {
+        magic_key_max_bandwidth: [("slow-key".to_string(), 5_000_000)].into(),
+        ..Default::default()
+    };
+    let policy = Iperf3Policy::new(&config);
+    assert_eq!(policy.bandwidth_limit(None), 0);
+    assert_eq!(policy.bandwidth_limit(Some("other-key")), 0);
+    assert_eq!(policy.bandwidth_limit(Some("slow-key")), 5_000_000);
+
+    // The stricter of the global and the per-key limit applies
+    config.max_bandwidth = 1_000_000;
+    let policy = Iperf3Policy::new(&config);
+    assert_eq!(policy.bandwidth_limit(None), 1_000_000);
+    assert_eq!(policy.bandwidth_limit(Some("slow-key")), 1_000_000);
+}
+
+#[test]
+fn test_policy_limits() {
+    use iperf3_server::policy::{Iperf3Policy, Rejection};
+
+    let ip: IpAddr = "192.0.2.1".parse().unwrap();
+    let other_ip: IpAddr = "192.0.2.2".parse().unwrap();
+
+    // Concurrent tests
+    let policy = Iperf3Policy::new(&Iperf3Config {
+        max_tests_per_ip: 1,
+        ..Default::default()
+    });
+    assert_eq!(policy.admit(ip), Ok(()));
+    assert_eq!(policy.admit(ip), Err(Rejection::TooManyTests(1)));
+    assert_eq!(policy.admit(other_ip), Ok(()));
+    policy.finish(ip, 1000);
+    assert_eq!(policy.active_tests(ip), 0);
+    assert_eq!(policy.admit(ip), Ok(()));
+
+    // Daily quota, charged when a test ends
+    let policy = Iperf3Policy::new(&Iperf3Config {
+        daily_bytes_per_ip: 1_000_000,
+        ..Default::default()
+    });
+    assert_eq!(policy.admit(ip), Ok(()));
+    policy.finish(ip, 600_000);
+    assert_eq!(policy.admit(ip), Ok(()));
+    policy.finish(ip, 600_000);
+    assert_eq!(policy.bytes_today(ip), 1_200_000);
+    assert_eq!(
+        policy.admit(ip),
+        Err(Rejection::DailyQuotaExceeded(1_000_000))
+    );
+    assert_eq!(policy.admit(other_ip), Ok(()));
+
+    // Cooldown after a test ends
+    let policy = Iperf3Policy::new(&Iperf3Config {
+        cooldown_secs: 60,
+        ..Default::default()
+    });
+    assert_eq!(policy.admit(ip), Ok(()));
+    policy.finish(ip, 0);
+    assert!(matches!(policy.admit(ip), Err(Rejection::Cooldown(_))));
+}
+
+#[tokio::test]
+async fn test_client_rejected_during_cooldown() {
+    let (server, port, records) = start_server_with(Iperf3Config {
+        cooldown_secs: 60,
+        ..Default::default()
+    })
+    .await;
+
+    let results = Iperf3Client::new(client_config(port, false, false))
+        .run()
+        .await
+        .unwrap();
+    wait_for_record(&records, &results.cookie).await;
+    let client_ip: IpAddr = "127.0.0.1".parse().unwrap();
+    assert!(server.policy().bytes_today(client_ip) > 0);
+
+    // The second test is refused with ACCESS_DENIED
+    let result = Iperf3Client::new(client_config(port, false, false))
+        .run()
+        .await;
+    assert!(matches!(result, Err(Iperf3Error::AccessDenied)));
+
+    server.shutdown();
+}
+
+#[tokio::test]
+async fn test_magic_key_bandwidth_cap() {
+    let (server, port, records) = start_server_with(Iperf3Config {
+        max_bandwidth: 40_000_000,
+        magic_key_max_bandwidth: [("slow-key".to_string(), 8_000_000)].into(),
+        ..Default::default()
+    })
+    .await;
+    server
+        .set_magic_key_callback(Arc::new(|_ip| Some("slow-key".to_string())))
+        .await;
+
+    let results = Iperf3Client::new(client_config(port, false, true))
+        .run()
+        .await
+        .unwrap();
+
+    // The reverse-mode sender (the server) is held to the per-key cap
+    let record = wait_for_record(&records, &results.cookie).await;
+    assert_eq!(record.params.bandwidth, 8_000_000);
+    assert!(results.sum_sent.bits_per_second < 16_000_000.0);
+
+    server.shutdown();
+}
@@ -0,0 +1,42 @@
+-- iperf3 Test Results Migration
+-- Version: 002
+-- Description: Completed iperf3 tests, linked to survey sessions when the client was authenticated
+
+-- iperf3 tests table - one row per completed test run against the embedded iperf3 server
+CREATE TABLE IF NOT EXISTS iperf3_tests (
+  test_id TEXT PRIMARY KEY,
+  session_id TEXT,
+  client_ip TEXT NOT NULL,
+  client_port INTEGER NOT NULL,
+  user_id TEXT,
+  auth_source TEXT,
+  protocol TEXT NOT NULL,
+  reverse INTEGER NOT NULL,
+  parallel INTEGER NOT NULL,
+  requested_duration_secs INTEGER NOT NULL,
+  bandwidth_bps INTEGER,
+  blksize INTEGER,
+  start_time INTEGER NOT NULL,
+  duration_secs REAL NOT NULL,
+  bytes_sent INTEGER NOT NULL,
+  bytes_received INTEGER NOT NULL,
+  sent_bps REAL,
+  received_bps REAL,
+  jitter_ms REAL,
+  lost_packets INTEGER,
+  packets INTEGER,
+  lost_percent REAL,
+  params_json TEXT NOT NULL,
+  intervals_json TEXT NOT NULL,
+  results_json TEXT NOT NULL,
+  client_results_json TEXT,
+  created_at INTEGER NOT NULL,
+  deleted INTEGER DEFAULT 0,
+  deleted_at INTEGER,
+  deleted_by TEXT,
+  FOREIGN KEY(session_id) REFERENCES survey_sessions(session_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_iperf3_session ON iperf3_tests(session_id, start_time);
+CREATE INDEX IF NOT EXISTS idx_iperf3_client_ip ON iperf3_tests(client_ip, start_time);
+CREATE INDEX IF NOT EXISTS idx_iperf3_deleted ON iperf3_tests(deleted);
@@ -0,0 +1,13 @@
+-- Role Assignments Migration
+-- Version: 003
+-- Description: Roles assigned to users and groups, in addition to the [auth.roles] configuration
+
+-- role assignments table - one row per (subject, role); subject_type is 'user'
+-- (subject "<provider>:<handle>", e.g. "plain:alice") or 'group'
+CREATE TABLE IF NOT EXISTS role_assignments (
+  subject_type TEXT NOT NULL CHECK (subject_type IN ('user', 'group')),
+  subject TEXT NOT NULL,
+  role TEXT NOT NULL CHECK (role IN ('viewer', 'analyst', 'operator', 'admin')),
+  created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  PRIMARY KEY (subject_type, subject, role)
+);
@@ -0,0 +1,23 @@
+-- Magic Keys Migration
+-- Version: 004
+-- Description: Magic keys managed at runtime, with expiry, usage limits and revocation
+
+-- magic keys table - one row per key handed out to surveyors
+CREATE TABLE IF NOT EXISTS magic_keys (
+  magic_key TEXT PRIMARY KEY,
+  label TEXT,
+  owner TEXT,
+  created_at INTEGER NOT NULL,
+  created_by TEXT,
+  expires_at INTEGER,
+  max_sessions INTEGER,
+  session_count INTEGER NOT NULL DEFAULT 0,
+  last_used_at INTEGER,
+  max_measuring_time_secs INTEGER,
+  enabled INTEGER NOT NULL DEFAULT 1,
+  revoked_at INTEGER,
+  revoked_by TEXT,
+  replaced_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_magic_keys_owner ON magic_keys(owner);
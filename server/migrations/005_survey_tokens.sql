@@ -0,0 +1,18 @@
+-- Survey Tokens Migration
+-- Version: 005
+-- Description: Server-issued survey session tokens, revocable individually
+
+-- survey tokens table - one row per survey session token issued for a magic key
+CREATE TABLE IF NOT EXISTS survey_tokens (
+  token_id TEXT PRIMARY KEY,
+  magic_key TEXT NOT NULL,
+  issued_at INTEGER NOT NULL,
+  expires_at INTEGER NOT NULL,
+  issued_to_ip TEXT,
+  bound_ip TEXT,
+  last_seen_at INTEGER,
+  revoked_at INTEGER,
+  revoked_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_survey_tokens_magic_key ON survey_tokens(magic_key, issued_at);
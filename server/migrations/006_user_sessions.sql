@@ -0,0 +1,20 @@
+-- User Sessions Migration
+-- Version: 006
+-- Description: Server-side record of login sessions, for logout everywhere and revocation
+
+-- user sessions table - one row per login of a regular (non Magic Key) user
+CREATE TABLE IF NOT EXISTS user_sessions (
+  session_id TEXT PRIMARY KEY,
+  handle TEXT NOT NULL,
+  user_id TEXT NOT NULL,
+  auth_provider TEXT NOT NULL,
+  created_at INTEGER NOT NULL,
+  expires_at INTEGER NOT NULL,
+  last_seen_at INTEGER NOT NULL,
+  ip TEXT,
+  user_agent TEXT,
+  revoked_at INTEGER,
+  revoked_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_sessions_handle ON user_sessions(handle, created_at);
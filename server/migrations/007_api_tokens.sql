@@ -0,0 +1,22 @@
+-- API Tokens Migration
+-- Version: 007
+-- Description: Personal API tokens for scripted access, stored as hashes
+
+-- api tokens table - one row per token; the token itself is only shown at creation.
+-- provider is the provider the owner logged in with, under which their roles are assigned
+CREATE TABLE IF NOT EXISTS api_tokens (
+  token_id TEXT PRIMARY KEY,
+  token_hash TEXT NOT NULL UNIQUE,
+  name TEXT NOT NULL,
+  provider TEXT NOT NULL,
+  handle TEXT NOT NULL,
+  groups TEXT NOT NULL DEFAULT '[]',
+  scopes TEXT NOT NULL,
+  created_at INTEGER NOT NULL,
+  expires_at INTEGER NOT NULL,
+  last_used_at INTEGER,
+  revoked_at INTEGER,
+  revoked_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_tokens_handle ON api_tokens(provider, handle, created_at);
@@ -0,0 +1,20 @@
+-- TOTP Migration
+-- Version: 008
+-- Description: TOTP second factor enrollments and recovery codes of plain login users
+
+-- totp enrollments table - one row per user; the secret is encrypted by the auth service
+CREATE TABLE IF NOT EXISTS totp_enrollments (
+  username TEXT PRIMARY KEY,
+  secret TEXT NOT NULL,
+  created_at INTEGER NOT NULL,
+  confirmed_at INTEGER,
+  last_used_step INTEGER
+);
+
+-- totp recovery codes table - single-use codes, stored as hashes
+CREATE TABLE IF NOT EXISTS totp_recovery_codes (
+  username TEXT NOT NULL,
+  code_hash TEXT NOT NULL,
+  used_at INTEGER,
+  PRIMARY KEY (username, code_hash)
+);
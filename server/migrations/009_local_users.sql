@@ -0,0 +1,14 @@
+-- Local Users Migration
+-- Version: 009
+-- Description: Plain login users managed at runtime, with argon2id password hashes
+
+-- local users table - take precedence over users in the configuration file
+CREATE TABLE IF NOT EXISTS local_users (
+  username TEXT PRIMARY KEY,
+  password_hash TEXT NOT NULL,
+  display_name TEXT,
+  disabled INTEGER NOT NULL DEFAULT 0,
+  created_at INTEGER NOT NULL,
+  created_by TEXT,
+  password_changed_at INTEGER NOT NULL
+);
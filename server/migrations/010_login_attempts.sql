@@ -0,0 +1,27 @@
+-- Login Attempts Migration
+-- Version: 010
+-- Description: Failed login and Magic Key attempts per IP and account, and lockouts
+
+-- login attempts table - one row per key ("ip:<address>" or "account:<username>")
+CREATE TABLE IF NOT EXISTS login_attempts (
+  attempt_key TEXT PRIMARY KEY,
+  failures INTEGER NOT NULL,
+  last_failure_at INTEGER NOT NULL,
+  locked_until INTEGER,
+  last_active_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_login_attempts_last_active ON login_attempts(last_active_at);
+
+-- login lockouts table - append-only record of every lockout
+CREATE TABLE IF NOT EXISTS login_lockouts (
+  lockout_id INTEGER PRIMARY KEY AUTOINCREMENT,
+  attempt_key TEXT NOT NULL,
+  failures INTEGER NOT NULL,
+  locked_at INTEGER NOT NULL,
+  locked_until INTEGER NOT NULL,
+  cleared_at INTEGER,
+  cleared_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_login_lockouts_locked_at ON login_lockouts(locked_at);
@@ -0,0 +1,40 @@
+-- Traceroute Hops Migration
+-- Version: 012
+-- Description: Traceroute hops and client peer addresses of survey sessions, with ASN, prefix and geolocation
+
+-- Traceroute hops table - one row per ICMP response to a traceroute probe
+CREATE TABLE IF NOT EXISTS traceroute_hops (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id TEXT NOT NULL,
+  conn_id TEXT NOT NULL,
+  timestamp_ms INTEGER NOT NULL,
+  hop INTEGER NOT NULL,
+  ip_address TEXT,
+  hostname TEXT,
+  rtt_ms REAL NOT NULL,
+  asn INTEGER,
+  as_name TEXT,
+  prefix TEXT,
+  country TEXT,
+  city TEXT,
+  created_at INTEGER NOT NULL,
+  FOREIGN KEY(session_id) REFERENCES survey_sessions(session_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_traceroute_hops_session ON traceroute_hops(session_id, conn_id, hop);
+
+-- Survey peers table - client addresses seen by each connection of a survey session
+CREATE TABLE IF NOT EXISTS survey_peers (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id TEXT NOT NULL,
+  conn_id TEXT NOT NULL,
+  peer_address TEXT NOT NULL,
+  asn INTEGER,
+  as_name TEXT,
+  prefix TEXT,
+  country TEXT,
+  city TEXT,
+  first_seen_at INTEGER NOT NULL,
+  UNIQUE(session_id, conn_id, peer_address),
+  FOREIGN KEY(session_id) REFERENCES survey_sessions(session_id)
+);
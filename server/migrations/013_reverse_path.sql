@@ -0,0 +1,23 @@
+-- Reverse Path Migration
+-- Version: 013
+-- Description: Reverse path hop counts inferred from received TTLs, compared with the forward path
+
+-- Reverse path metrics table - one row per connection per statistics interval
+CREATE TABLE IF NOT EXISTS reverse_path_metrics (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id TEXT NOT NULL,
+  conn_id TEXT NOT NULL,
+  timestamp_ms INTEGER NOT NULL,
+  received_ttl INTEGER NOT NULL,
+  min_received_ttl INTEGER NOT NULL,
+  max_received_ttl INTEGER NOT NULL,
+  initial_ttl INTEGER NOT NULL,
+  reverse_hops INTEGER NOT NULL,
+  forward_hops INTEGER,
+  hop_difference INTEGER,
+  asymmetric INTEGER,
+  created_at INTEGER NOT NULL,
+  FOREIGN KEY(session_id) REFERENCES survey_sessions(session_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_reverse_path_session ON reverse_path_metrics(session_id, conn_id, timestamp_ms);
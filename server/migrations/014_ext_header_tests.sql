@@ -0,0 +1,24 @@
+-- IPv6 Extension Header Tests Migration
+-- Version: 014
+-- Description: Results of IPv6 extension header survivability tests (RFC 9098)
+
+-- Extension header tests table - one row per test of a connection
+CREATE TABLE IF NOT EXISTS ext_header_tests (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id TEXT NOT NULL,
+  conn_id TEXT NOT NULL,
+  timestamp_ms INTEGER NOT NULL,
+  header_type TEXT NOT NULL,
+  header_size INTEGER NOT NULL,
+  probes_sent INTEGER NOT NULL,
+  probes_delivered INTEGER NOT NULL,
+  delivered INTEGER NOT NULL,
+  reached_at_ttl INTEGER,
+  last_responding_hop INTEGER,
+  drop_hop INTEGER,
+  hops_json TEXT NOT NULL,
+  created_at INTEGER NOT NULL,
+  FOREIGN KEY(session_id) REFERENCES survey_sessions(session_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ext_header_tests_session ON ext_header_tests(session_id, conn_id, timestamp_ms);
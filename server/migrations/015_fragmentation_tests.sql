@@ -0,0 +1,23 @@
+-- IP Fragmentation Tests Migration
+-- Version: 015
+-- Description: Results of IP fragmentation delivery tests
+
+-- Fragmentation tests table - one row per test of a connection
+CREATE TABLE IF NOT EXISTS fragmentation_tests (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id TEXT NOT NULL,
+  conn_id TEXT NOT NULL,
+  timestamp_ms INTEGER NOT NULL,
+  ip_version TEXT NOT NULL,
+  packet_size INTEGER NOT NULL,
+  out_of_order INTEGER NOT NULL,
+  fragment_size INTEGER,
+  probes_sent INTEGER NOT NULL,
+  probes_delivered INTEGER NOT NULL,
+  delivered INTEGER NOT NULL,
+  icmp_errors_json TEXT NOT NULL,
+  created_at INTEGER NOT NULL,
+  FOREIGN KEY(session_id) REFERENCES survey_sessions(session_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_fragmentation_tests_session ON fragmentation_tests(session_id, conn_id, timestamp_ms);
@@ -0,0 +1,20 @@
+-- NAT Binding Timeout Tests Migration
+-- Version: 016
+-- Description: Results of NAT binding timeout tests
+
+-- NAT lifetime tests table - one row per test of a connection
+CREATE TABLE IF NOT EXISTS nat_lifetime_tests (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id TEXT NOT NULL,
+  conn_id TEXT NOT NULL,
+  timestamp_ms INTEGER NOT NULL,
+  ip_version TEXT NOT NULL,
+  survived_idle_ms INTEGER,
+  expired_idle_ms INTEGER,
+  binding_timeout_ms INTEGER,
+  trials_json TEXT NOT NULL,
+  created_at INTEGER NOT NULL,
+  FOREIGN KEY(session_id) REFERENCES survey_sessions(session_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_nat_lifetime_tests_session ON nat_lifetime_tests(session_id, conn_id, timestamp_ms);
@@ -0,0 +1,22 @@
+-- NAT Type Classification Migration
+-- Version: 017
+-- Description: NAT mapping and filtering behavior (RFC 4787) of connections
+
+-- NAT behavior tests table - one row per test of a connection
+CREATE TABLE IF NOT EXISTS nat_behavior_tests (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id TEXT NOT NULL,
+  conn_id TEXT NOT NULL,
+  timestamp_ms INTEGER NOT NULL,
+  ip_version TEXT NOT NULL,
+  mapping TEXT NOT NULL,
+  filtering TEXT NOT NULL,
+  symmetric INTEGER,
+  hairpinning INTEGER,
+  port_preservation INTEGER,
+  mapped_addresses_json TEXT NOT NULL,
+  created_at INTEGER NOT NULL,
+  FOREIGN KEY(session_id) REFERENCES survey_sessions(session_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_nat_behavior_tests_session ON nat_behavior_tests(session_id, conn_id, timestamp_ms);
@@ -0,0 +1,28 @@
+-- WebSocket Probe Stream Migration
+-- Version: 018
+-- Description: Per-second stats of the probe stream over WebSocket (TCP), compared
+-- with the probe channel stream in survey_metrics to detect UDP throttling
+
+-- WebSocket probe metrics table - one row per second and direction of a stream
+CREATE TABLE IF NOT EXISTS websocket_probe_metrics (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id TEXT NOT NULL,
+  stream_id TEXT NOT NULL,
+  timestamp_ms INTEGER NOT NULL,
+  direction TEXT NOT NULL,
+  delay_p50_ms REAL,
+  delay_p99_ms REAL,
+  jitter_p50_ms REAL,
+  jitter_p99_ms REAL,
+  rtt_p50_ms REAL,
+  rtt_p99_ms REAL,
+  rtt_min_ms REAL,
+  rtt_max_ms REAL,
+  loss_rate REAL,
+  reorder_rate REAL,
+  probe_count INTEGER,
+  created_at INTEGER NOT NULL,
+  FOREIGN KEY(session_id) REFERENCES survey_sessions(session_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_websocket_probe_metrics_session ON websocket_probe_metrics(session_id, timestamp_ms);
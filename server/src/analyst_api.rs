@@ -169,6 +169,7 @@ pub struct SessionDetails {
     pub has_keylog: bool,
     pub recordings: Vec<RecordingSummary>,
     pub metric_count: i32,
+    pub iperf3_test_count: i32,
 }
 
 /// Summary information about a recording
@@ -189,7 +190,7 @@ pub async fn get_session(
     session_data: Option<Extension<SessionData>>,
     Path(session_id): Path<String>,
 ) -> Result<Json<SessionDetails>, StatusCode> {
-    let (session, recordings, metric_count) = {
+    let (session, recordings, metric_count, iperf3_test_count) = {
         let db = state.db.lock().await;
 
         // Get session info
@@ -270,7 +271,16 @@ pub async fn get_session(
             )
             .unwrap_or(0);
 
-        (session, recordings, metric_count)
+        // Get iperf3 test count
+        let iperf3_test_count: i32 = db
+            .query_row(
+                "SELECT COUNT(*) FROM iperf3_tests WHERE session_id = ? AND deleted = 0",
+                params![&session_id],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+
+        (session, recordings, metric_count, iperf3_test_count)
     };
 
     // Check data availability from DB paths and in-memory services
@@ -300,6 +310,7 @@ pub async fn get_session(
         has_keylog,
         recordings,
         metric_count,
+        iperf3_test_count,
     }))
 }
 
@@ -595,6 +606,8 @@ pub struct WipeSessionResult {
     pub session_id: String,
     pub metrics_deleted: usize,
     pub recordings_deleted: usize,
+    pub iperf3_tests_deleted: usize,
+    pub traceroute_hops_deleted: usize,
     pub files_deleted: Vec<String>,
     pub files_failed: Vec<String>,
 }
@@ -659,8 +672,8 @@ pub async fn wipe_session(
         result
     };
 
-    // Delete from database tables (metrics, recordings, then session)
-    let (metrics_deleted, recordings_deleted) = {
+    // Delete from database tables (metrics, recordings, iperf3 tests, traceroute, then session)
+    let (metrics_deleted, recordings_deleted, iperf3_tests_deleted, traceroute_hops_deleted) = {
         let db = state.db.lock().await;
         let metrics_deleted = db
             .execute(
@@ -671,6 +684,67 @@ pub async fn wipe_session(
                 tracing::error!("Failed to delete metrics for session {}: {}", session_id, e);
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
+        let reverse_path_deleted = db
+            .execute(
+                "DELETE FROM reverse_path_metrics WHERE session_id = ?",
+                params![&session_id],
+            )
+            .map_err(|e| {
+                tracing::error!("Failed to delete reverse path metrics for session {}: {}", session_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let ext_header_tests_deleted = db
+            .execute(
+                "DELETE FROM ext_header_tests WHERE session_id = ?",
+                params![&session_id],
+            )
+            .map_err(|e| {
+                tracing::error!("Failed to delete extension header tests for session {}: {}", session_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let fragmentation_tests_deleted = db
+            .execute(
+                "DELETE FROM fragmentation_tests WHERE session_id = ?",
+                params![&session_id],
+            )
+            .map_err(|e| {
+                tracing::error!("Failed to delete fragmentation tests for session {}: {}", session_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let nat_lifetime_tests_deleted = db
+            .execute(
+                "DELETE FROM nat_lifetime_tests WHERE session_id = ?",
+                params![&session_id],
+            )
+            .map_err(|e| {
+                tracing::error!("Failed to delete NAT lifetime tests for session {}: {}", session_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let nat_behavior_tests_deleted = db
+            .execute(
+                "DELETE FROM nat_behavior_tests WHERE session_id = ?",
+                params![&session_id],
+            )
+            .map_err(|e| {
+                tracing::error!("Failed to delete NAT behavior tests for session {}: {}", session_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let websocket_probe_metrics_deleted = db
+            .execute(
+                "DELETE FROM websocket_probe_metrics WHERE session_id = ?",
+                params![&session_id],
+            )
+            .map_err(|e| {
+                tracing::error!("Failed to delete WebSocket probe metrics for session {}: {}", session_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let metrics_deleted = metrics_deleted
+            + reverse_path_deleted
+            + ext_header_tests_deleted
+            + fragmentation_tests_deleted
+            + nat_lifetime_tests_deleted
+            + nat_behavior_tests_deleted
+            + websocket_probe_metrics_deleted;
         let recordings_deleted = db
             .execute(
                 "DELETE FROM recordings WHERE session_id = ?",
@@ -680,6 +754,32 @@ pub async fn wipe_session(
                 tracing::error!("Failed to delete recordings for session {}: {}", session_id, e);
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
+        let iperf3_tests_deleted = db
+            .execute(
+                "DELETE FROM iperf3_tests WHERE session_id = ?",
+                params![&session_id],
+            )
+            .map_err(|e| {
+                tracing::error!("Failed to delete iperf3 tests for session {}: {}", session_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let traceroute_hops_deleted = db
+            .execute(
+                "DELETE FROM traceroute_hops WHERE session_id = ?",
+                params![&session_id],
+            )
+            .map_err(|e| {
+                tracing::error!("Failed to delete traceroute hops for session {}: {}", session_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        db.execute(
+            "DELETE FROM survey_peers WHERE session_id = ?",
+            params![&session_id],
+        )
+        .map_err(|e| {
+            tracing::error!("Failed to delete peer addresses for session {}: {}", session_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
         db.execute(
             "DELETE FROM survey_sessions WHERE session_id = ?",
             params![&session_id],
@@ -688,7 +788,7 @@ pub async fn wipe_session(
             tracing::error!("Failed to delete session {}: {}", session_id, e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
-        (metrics_deleted, recordings_deleted)
+        (metrics_deleted, recordings_deleted, iperf3_tests_deleted, traceroute_hops_deleted)
     };
 
     // Delete files from disk
@@ -729,11 +829,13 @@ pub async fn wipe_session(
         .map(|Extension(s)| s.handle.as_str())
         .unwrap_or("anonymous");
     tracing::info!(
-        "Session {} wiped by {}: {} metrics, {} recordings, {} files deleted",
+        "Session {} wiped by {}: {} metrics, {} recordings, {} iperf3 tests, {} traceroute hops, {} files deleted",
         session_id,
         username,
         metrics_deleted,
         recordings_deleted,
+        iperf3_tests_deleted,
+        traceroute_hops_deleted,
         files_deleted.len()
     );
 
@@ -741,6 +843,8 @@ pub async fn wipe_session(
         session_id,
         metrics_deleted,
         recordings_deleted,
+        iperf3_tests_deleted,
+        traceroute_hops_deleted,
         files_deleted,
         files_failed,
     }))
@@ -855,3 +959,753 @@ pub async fn get_session_metrics(
 
     Ok(Json(result))
 }
+
+// ============================================================================
+// Session Transport Comparison Endpoint
+// ============================================================================
+
+/// Per-second stats of one table, as the fields the transport comparison uses
+fn direction_stats_samples(
+    db: &rusqlite::Connection,
+    sql: &str,
+    session_id: &str,
+) -> rusqlite::Result<Vec<common::DirectionStats>> {
+    let mut stmt = db.prepare(sql)?;
+    let samples = stmt
+        .query_map(params![session_id], |row| {
+            Ok(common::DirectionStats {
+                rtt_ms: [
+                    row.get::<_, Option<f64>>(0)?.unwrap_or(0.0),
+                    row.get::<_, Option<f64>>(1)?.unwrap_or(0.0),
+                    0.0,
+                    0.0,
+                ],
+                loss_rate: row.get::<_, Option<f64>>(2)?.unwrap_or(0.0),
+                probe_count: row.get::<_, Option<u32>>(3)?.unwrap_or(0),
+                ..Default::default()
+            })
+        })?
+        .collect();
+    samples
+}
+
+/// Compare the probe channel (UDP) and WebSocket (TCP) probe streams of a
+/// session, to tell whether UDP is shaped or blocked on its network
+pub async fn get_session_transport(
+    State(state): State<Arc<AnalystState>>,
+    session_data: Option<Extension<SessionData>>,
+    Path(session_id): Path<String>,
+) -> Result<Json<common::TransportComparison>, StatusCode> {
+    let db = state.db.lock().await;
+
+    let magic_key: String = db
+        .query_row(
+            "SELECT magic_key FROM survey_sessions WHERE session_id = ? AND deleted = 0",
+            params![&session_id],
+            |row| row.get(0),
+        )
+        .map_err(|_| {
+            tracing::warn!("Session not found for transport comparison: {}", session_id);
+            StatusCode::NOT_FOUND
+        })?;
+
+    if let Some(Extension(session_info)) = &session_data {
+        if !user_has_access(&state.analyst_access, &session_info.handle, &magic_key) {
+            tracing::warn!(
+                "User {} denied access to transport comparison for session {} (magic key {})",
+                session_info.handle,
+                session_id,
+                magic_key
+            );
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let udp = direction_stats_samples(
+        &db,
+        "SELECT rtt_p50_ms, rtt_p99_ms, loss_rate, probe_count
+         FROM survey_metrics
+         WHERE session_id = ? AND source = 'server' AND deleted = 0",
+        &session_id,
+    )
+    .map_err(|e| {
+        tracing::error!("Failed to query probe channel metrics: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let tcp = direction_stats_samples(
+        &db,
+        "SELECT rtt_p50_ms, rtt_p99_ms, loss_rate, probe_count
+         FROM websocket_probe_metrics
+         WHERE session_id = ?",
+        &session_id,
+    )
+    .map_err(|e| {
+        tracing::error!("Failed to query WebSocket probe metrics: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(common::compare_transports(&udp, &tcp)))
+}
+
+// ============================================================================
+// Session Traceroute Endpoint
+// ============================================================================
+
+/// A traceroute hop with the name, ASN, prefix and location of its address
+#[derive(Debug, Serialize)]
+pub struct TracerouteHopEntry {
+    pub conn_id: String,
+    pub timestamp_ms: i64,
+    pub hop: u8,
+    pub ip_address: Option<String>,
+    pub hostname: Option<String>,
+    pub rtt_ms: f64,
+    #[serde(flatten)]
+    pub enrichment: common::IpEnrichment,
+}
+
+/// A client peer address of a connection with its ASN, prefix and location
+#[derive(Debug, Serialize)]
+pub struct PeerAddressEntry {
+    pub conn_id: String,
+    pub peer_address: String,
+    pub first_seen_at: i64,
+    #[serde(flatten)]
+    pub enrichment: common::IpEnrichment,
+}
+
+/// Reverse path hop count of a connection at a point in time
+#[derive(Debug, Serialize)]
+pub struct ReversePathEntry {
+    pub conn_id: String,
+    pub timestamp_ms: i64,
+    #[serde(flatten)]
+    pub reverse_path: common::ReversePathInfo,
+}
+
+/// Result of an IPv6 extension header test of a connection
+#[derive(Debug, Serialize)]
+pub struct ExtHeaderTestEntry {
+    pub conn_id: String,
+    pub timestamp_ms: i64,
+    pub header_type: String,
+    pub header_size: u16,
+    pub probes_sent: u32,
+    pub probes_delivered: u32,
+    pub delivered: bool,
+    pub reached_at_ttl: Option<u8>,
+    pub last_responding_hop: Option<u8>,
+    pub drop_hop: Option<u8>,
+    pub hops: Vec<common::ExtHeaderHop>,
+}
+
+/// Result of an IP fragmentation test of a connection
+#[derive(Debug, Serialize)]
+pub struct FragmentationTestEntry {
+    pub conn_id: String,
+    pub timestamp_ms: i64,
+    pub ip_version: String,
+    pub packet_size: u32,
+    pub out_of_order: bool,
+    pub fragment_size: Option<u16>,
+    pub probes_sent: u32,
+    pub probes_delivered: u32,
+    pub delivered: bool,
+    pub icmp_errors: Vec<common::FragmentationIcmpError>,
+}
+
+/// Result of a NAT binding timeout test of a connection
+#[derive(Debug, Serialize)]
+pub struct NatLifetimeTestEntry {
+    pub conn_id: String,
+    pub timestamp_ms: i64,
+    pub ip_version: String,
+    pub survived_idle_ms: Option<i64>,
+    pub expired_idle_ms: Option<i64>,
+    pub binding_timeout_ms: Option<i64>,
+    pub trials: Vec<common::NatLifetimeTrial>,
+}
+
+/// NAT mapping and filtering behavior of a connection
+#[derive(Debug, Serialize)]
+pub struct NatBehaviorTestEntry {
+    pub conn_id: String,
+    pub timestamp_ms: i64,
+    pub ip_version: String,
+    pub mapping: String,
+    pub filtering: String,
+    pub symmetric: Option<bool>,
+    pub hairpinning: Option<bool>,
+    pub port_preservation: Option<bool>,
+    pub mapped_addresses: Vec<common::NatMappedAddress>,
+}
+
+/// Traceroute hops, client peer addresses, reverse paths, extension header,
+/// fragmentation, NAT binding timeout and NAT type tests of a session
+#[derive(Debug, Serialize)]
+pub struct SessionTraceroute {
+    pub hops: Vec<TracerouteHopEntry>,
+    pub peers: Vec<PeerAddressEntry>,
+    pub reverse_path: Vec<ReversePathEntry>,
+    pub ext_header_tests: Vec<ExtHeaderTestEntry>,
+    pub fragmentation_tests: Vec<FragmentationTestEntry>,
+    pub nat_lifetime_tests: Vec<NatLifetimeTestEntry>,
+    pub nat_behavior_tests: Vec<NatBehaviorTestEntry>,
+}
+
+/// Enrichment columns (asn, as_name, prefix, country, city) starting at `start`
+fn enrichment_from_row(row: &rusqlite::Row, start: usize) -> rusqlite::Result<common::IpEnrichment> {
+    Ok(common::IpEnrichment {
+        asn: row.get(start)?,
+        as_name: row.get(start + 1)?,
+        prefix: row.get(start + 2)?,
+        country: row.get(start + 3)?,
+        city: row.get(start + 4)?,
+    })
+}
+
+/// Get the traceroute hops, peer addresses, reverse paths, extension header,
+/// fragmentation and NAT binding timeout tests of a session
+pub async fn get_session_traceroute(
+    State(state): State<Arc<AnalystState>>,
+    session_data: Option<Extension<SessionData>>,
+    Path(session_id): Path<String>,
+) -> Result<Json<SessionTraceroute>, StatusCode> {
+    let db = state.db.lock().await;
+
+    let magic_key: String = db
+        .query_row(
+            "SELECT magic_key FROM survey_sessions WHERE session_id = ? AND deleted = 0",
+            params![&session_id],
+            |row| row.get(0),
+        )
+        .map_err(|_| {
+            tracing::warn!("Session not found for traceroute: {}", session_id);
+            StatusCode::NOT_FOUND
+        })?;
+
+    if let Some(Extension(session_info)) = &session_data {
+        if !user_has_access(&state.analyst_access, &session_info.handle, &magic_key) {
+            tracing::warn!(
+                "User {} denied access to traceroute for session {} (magic key {})",
+                session_info.handle,
+                session_id,
+                magic_key
+            );
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let hops = db
+        .prepare(
+            "SELECT conn_id, timestamp_ms, hop, ip_address, hostname, rtt_ms,
+                    asn, as_name, prefix, country, city
+             FROM traceroute_hops
+             WHERE session_id = ?
+             ORDER BY conn_id, timestamp_ms, hop",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![&session_id], |row| {
+                Ok(TracerouteHopEntry {
+                    conn_id: row.get(0)?,
+                    timestamp_ms: row.get(1)?,
+                    hop: row.get(2)?,
+                    ip_address: row.get(3)?,
+                    hostname: row.get(4)?,
+                    rtt_ms: row.get(5)?,
+                    enrichment: enrichment_from_row(row, 6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| {
+            tracing::error!("Failed to query traceroute hops: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let peers = db
+        .prepare(
+            "SELECT conn_id, peer_address, first_seen_at,
+                    asn, as_name, prefix, country, city
+             FROM survey_peers
+             WHERE session_id = ?
+             ORDER BY first_seen_at",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![&session_id], |row| {
+                Ok(PeerAddressEntry {
+                    conn_id: row.get(0)?,
+                    peer_address: row.get(1)?,
+                    first_seen_at: row.get(2)?,
+                    enrichment: enrichment_from_row(row, 3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| {
+            tracing::error!("Failed to query peer addresses: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let reverse_path = db
+        .prepare(
+            "SELECT conn_id, timestamp_ms, received_ttl, min_received_ttl, max_received_ttl,
+                    initial_ttl, reverse_hops, forward_hops, hop_difference, asymmetric
+             FROM reverse_path_metrics
+             WHERE session_id = ?
+             ORDER BY conn_id, timestamp_ms",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![&session_id], |row| {
+                Ok(ReversePathEntry {
+                    conn_id: row.get(0)?,
+                    timestamp_ms: row.get(1)?,
+                    reverse_path: common::ReversePathInfo {
+                        received_ttl: row.get(2)?,
+                        min_received_ttl: row.get(3)?,
+                        max_received_ttl: row.get(4)?,
+                        initial_ttl: row.get(5)?,
+                        reverse_hops: row.get(6)?,
+                        forward_hops: row.get(7)?,
+                        hop_difference: row.get(8)?,
+                        asymmetric: row.get(9)?,
+                    },
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| {
+            tracing::error!("Failed to query reverse path metrics: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let ext_header_tests = db
+        .prepare(
+            "SELECT conn_id, timestamp_ms, header_type, header_size, probes_sent,
+                    probes_delivered, delivered, reached_at_ttl, last_responding_hop,
+                    drop_hop, hops_json
+             FROM ext_header_tests
+             WHERE session_id = ?
+             ORDER BY conn_id, timestamp_ms",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![&session_id], |row| {
+                let hops_json: String = row.get(10)?;
+                Ok(ExtHeaderTestEntry {
+                    conn_id: row.get(0)?,
+                    timestamp_ms: row.get(1)?,
+                    header_type: row.get(2)?,
+                    header_size: row.get(3)?,
+                    probes_sent: row.get(4)?,
+                    probes_delivered: row.get(5)?,
+                    delivered: row.get(6)?,
+                    reached_at_ttl: row.get(7)?,
+                    last_responding_hop: row.get(8)?,
+                    drop_hop: row.get(9)?,
+                    hops: serde_json::from_str(&hops_json).unwrap_or_default(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| {
+            tracing::error!("Failed to query extension header tests: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let fragmentation_tests = db
+        .prepare(
+            "SELECT conn_id, timestamp_ms, ip_version, packet_size, out_of_order,
+                    fragment_size, probes_sent, probes_delivered, delivered,
+                    icmp_errors_json
+             FROM fragmentation_tests
+             WHERE session_id = ?
+             ORDER BY conn_id, timestamp_ms",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![&session_id], |row| {
+                let icmp_errors_json: String = row.get(9)?;
+                Ok(FragmentationTestEntry {
+                    conn_id: row.get(0)?,
+                    timestamp_ms: row.get(1)?,
+                    ip_version: row.get(2)?,
+                    packet_size: row.get(3)?,
+                    out_of_order: row.get(4)?,
+                    fragment_size: row.get(5)?,
+                    probes_sent: row.get(6)?,
+                    probes_delivered: row.get(7)?,
+                    delivered: row.get(8)?,
+                    icmp_errors: serde_json::from_str(&icmp_errors_json).unwrap_or_default(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| {
+            tracing::error!("Failed to query fragmentation tests: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let nat_lifetime_tests = db
+        .prepare(
+            "SELECT conn_id, timestamp_ms, ip_version, survived_idle_ms, expired_idle_ms,
+                    binding_timeout_ms, trials_json
+             FROM nat_lifetime_tests
+             WHERE session_id = ?
+             ORDER BY conn_id, timestamp_ms",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![&session_id], |row| {
+                let trials_json: String = row.get(6)?;
+                Ok(NatLifetimeTestEntry {
+                    conn_id: row.get(0)?,
+                    timestamp_ms: row.get(1)?,
+                    ip_version: row.get(2)?,
+                    survived_idle_ms: row.get(3)?,
+                    expired_idle_ms: row.get(4)?,
+                    binding_timeout_ms: row.get(5)?,
+                    trials: serde_json::from_str(&trials_json).unwrap_or_default(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| {
+            tracing::error!("Failed to query NAT lifetime tests: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let nat_behavior_tests = db
+        .prepare(
+            "SELECT conn_id, timestamp_ms, ip_version, mapping, filtering, symmetric,
+                    hairpinning, port_preservation, mapped_addresses_json
+             FROM nat_behavior_tests
+             WHERE session_id = ?
+             ORDER BY conn_id, timestamp_ms",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![&session_id], |row| {
+                let mapped_addresses_json: String = row.get(8)?;
+                Ok(NatBehaviorTestEntry {
+                    conn_id: row.get(0)?,
+                    timestamp_ms: row.get(1)?,
+                    ip_version: row.get(2)?,
+                    mapping: row.get(3)?,
+                    filtering: row.get(4)?,
+                    symmetric: row.get(5)?,
+                    hairpinning: row.get(6)?,
+                    port_preservation: row.get(7)?,
+                    mapped_addresses: serde_json::from_str(&mapped_addresses_json)
+                        .unwrap_or_default(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| {
+            tracing::error!("Failed to query NAT behavior tests: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(SessionTraceroute {
+        hops,
+        peers,
+        reverse_path,
+        ext_header_tests,
+        fragmentation_tests,
+        nat_lifetime_tests,
+        nat_behavior_tests,
+    }))
+}
+
+// ============================================================================
+// iperf3 Test Endpoints
+// ============================================================================
+
+/// Columns selected for an iperf3 test summary (joined with its survey session as `s`)
+const IPERF3_SUMMARY_COLUMNS: &str = "t.test_id, t.session_id, s.magic_key, t.client_ip, t.user_id,
+        t.auth_source, t.protocol, t.reverse, t.parallel, t.start_time, t.duration_secs,
+        t.bytes_sent, t.bytes_received, t.sent_bps, t.received_bps,
+        t.jitter_ms, t.lost_packets, t.packets, t.lost_percent";
+
+/// Summary information about a completed iperf3 test
+#[derive(Debug, Serialize)]
+pub struct Iperf3TestSummary {
+    pub test_id: String,
+    pub session_id: Option<String>,
+    pub magic_key: Option<String>,
+    pub client_ip: String,
+    pub user_id: Option<String>,
+    pub auth_source: Option<String>,
+    pub protocol: String,
+    pub reverse: bool,
+    pub parallel: i32,
+    pub start_time: i64,
+    pub duration_secs: f64,
+    pub bytes_sent: i64,
+    pub bytes_received: i64,
+    pub sent_bps: Option<f64>,
+    pub received_bps: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    pub lost_packets: Option<i64>,
+    pub packets: Option<i64>,
+    pub lost_percent: Option<f64>,
+}
+
+fn iperf3_summary_from_row(row: &rusqlite::Row) -> rusqlite::Result<Iperf3TestSummary> {
+    Ok(Iperf3TestSummary {
+        test_id: row.get(0)?,
+        session_id: row.get(1)?,
+        magic_key: row.get(2)?,
+        client_ip: row.get(3)?,
+        user_id: row.get(4)?,
+        auth_source: row.get(5)?,
+        protocol: row.get(6)?,
+        reverse: row.get(7)?,
+        parallel: row.get(8)?,
+        start_time: row.get(9)?,
+        duration_secs: row.get(10)?,
+        bytes_sent: row.get(11)?,
+        bytes_received: row.get(12)?,
+        sent_bps: row.get(13)?,
+        received_bps: row.get(14)?,
+        jitter_ms: row.get(15)?,
+        lost_packets: row.get(16)?,
+        packets: row.get(17)?,
+        lost_percent: row.get(18)?,
+    })
+}
+
+/// Check access to an iperf3 test: tests linked to a survey session follow the
+/// session's magic key, unlinked tests are only visible with wildcard access
+fn user_has_iperf3_access(
+    analyst_access: &HashMap<String, Vec<String>>,
+    username: &str,
+    magic_key: Option<&str>,
+) -> bool {
+    match magic_key {
+        Some(key) => user_has_access(analyst_access, username, key),
+        None => user_has_wildcard_access(analyst_access, username),
+    }
+}
+
+/// Query parameters for listing iperf3 tests
+#[derive(Debug, Deserialize)]
+pub struct ListIperf3TestsQuery {
+    /// Only tests linked to sessions with this magic key
+    pub magic_key: Option<String>,
+    /// Only tests from this client IP
+    pub client_ip: Option<String>,
+    /// Maximum number of tests to return (default 100)
+    pub limit: Option<u32>,
+}
+
+/// List recent iperf3 tests (filtered by user access)
+pub async fn list_iperf3_tests(
+    State(state): State<Arc<AnalystState>>,
+    session_data: Option<Extension<SessionData>>,
+    Query(query): Query<ListIperf3TestsQuery>,
+) -> Result<Json<Vec<Iperf3TestSummary>>, StatusCode> {
+    if let (Some(Extension(session)), Some(magic_key)) = (&session_data, &query.magic_key) {
+        if !user_has_access(&state.analyst_access, &session.handle, magic_key) {
+            tracing::warn!(
+                "User {} denied access to iperf3 tests for magic key {}",
+                session.handle,
+                magic_key
+            );
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let limit = query.limit.unwrap_or(100).min(1000);
+    let all_tests: Vec<Iperf3TestSummary> = {
+        let db = state.db.lock().await;
+
+        let mut stmt = db
+            .prepare(&format!(
+                "SELECT {}
+                 FROM iperf3_tests t
+                 LEFT JOIN survey_sessions s ON t.session_id = s.session_id AND s.deleted = 0
+                 WHERE t.deleted = 0
+                   AND (?1 IS NULL OR s.magic_key = ?1)
+                   AND (?2 IS NULL OR t.client_ip = ?2)
+                 ORDER BY t.start_time DESC
+                 LIMIT ?3",
+                IPERF3_SUMMARY_COLUMNS
+            ))
+            .map_err(|e| {
+                tracing::error!("Failed to prepare iperf3 tests query: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        let tests = stmt
+            .query_map(
+                params![&query.magic_key, &query.client_ip, limit],
+                iperf3_summary_from_row,
+            )
+            .map_err(|e| {
+                tracing::error!("Failed to query iperf3 tests: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        tests.collect::<Result<Vec<_>, _>>().map_err(|e| {
+            tracing::error!("Failed to collect iperf3 tests: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+    };
+
+    // Filter by user access if session data is available
+    let filtered = if let Some(Extension(session)) = &session_data {
+        all_tests
+            .into_iter()
+            .filter(|t| {
+                user_has_iperf3_access(
+                    &state.analyst_access,
+                    &session.handle,
+                    t.magic_key.as_deref(),
+                )
+            })
+            .collect()
+    } else {
+        all_tests
+    };
+
+    Ok(Json(filtered))
+}
+
+/// List iperf3 tests linked to a survey session
+pub async fn get_session_iperf3_tests(
+    State(state): State<Arc<AnalystState>>,
+    session_data: Option<Extension<SessionData>>,
+    Path(session_id): Path<String>,
+) -> Result<Json<Vec<Iperf3TestSummary>>, StatusCode> {
+    let db = state.db.lock().await;
+
+    // First check the session exists and get the magic key for access control
+    let magic_key: String = db
+        .query_row(
+            "SELECT magic_key FROM survey_sessions WHERE session_id = ? AND deleted = 0",
+            params![&session_id],
+            |row| row.get(0),
+        )
+        .map_err(|_| {
+            tracing::warn!("Session not found for iperf3 tests: {}", session_id);
+            StatusCode::NOT_FOUND
+        })?;
+
+    // Check access control
+    if let Some(Extension(session_info)) = &session_data {
+        if !user_has_access(&state.analyst_access, &session_info.handle, &magic_key) {
+            tracing::warn!(
+                "User {} denied access to iperf3 tests for session {} (magic key {})",
+                session_info.handle,
+                session_id,
+                magic_key
+            );
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let mut stmt = db
+        .prepare(&format!(
+            "SELECT {}
+             FROM iperf3_tests t
+             JOIN survey_sessions s ON t.session_id = s.session_id
+             WHERE t.session_id = ? AND t.deleted = 0
+             ORDER BY t.start_time ASC",
+            IPERF3_SUMMARY_COLUMNS
+        ))
+        .map_err(|e| {
+            tracing::error!("Failed to prepare session iperf3 tests query: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let tests = stmt
+        .query_map(params![&session_id], iperf3_summary_from_row)
+        .map_err(|e| {
+            tracing::error!("Failed to query session iperf3 tests: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let result: Vec<Iperf3TestSummary> = tests
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            tracing::error!("Failed to collect session iperf3 tests: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(result))
+}
+
+/// Full details of an iperf3 test, including per-interval and raw results
+#[derive(Debug, Serialize)]
+pub struct Iperf3TestDetails {
+    #[serde(flatten)]
+    pub summary: Iperf3TestSummary,
+    pub params: serde_json::Value,
+    pub intervals: serde_json::Value,
+    pub results: serde_json::Value,
+    pub client_results: Option<serde_json::Value>,
+}
+
+/// Get full details of an iperf3 test
+pub async fn get_iperf3_test(
+    State(state): State<Arc<AnalystState>>,
+    session_data: Option<Extension<SessionData>>,
+    Path(test_id): Path<String>,
+) -> Result<Json<Iperf3TestDetails>, StatusCode> {
+    let db = state.db.lock().await;
+
+    let (summary, params_json, intervals_json, results_json, client_results_json): (
+        Iperf3TestSummary,
+        String,
+        String,
+        String,
+        Option<String>,
+    ) = db
+        .query_row(
+            &format!(
+                "SELECT {}, t.params_json, t.intervals_json, t.results_json, t.client_results_json
+                 FROM iperf3_tests t
+                 LEFT JOIN survey_sessions s ON t.session_id = s.session_id AND s.deleted = 0
+                 WHERE t.test_id = ? AND t.deleted = 0",
+                IPERF3_SUMMARY_COLUMNS
+            ),
+            params![&test_id],
+            |row| {
+                Ok((
+                    iperf3_summary_from_row(row)?,
+                    row.get(19)?,
+                    row.get(20)?,
+                    row.get(21)?,
+                    row.get(22)?,
+                ))
+            },
+        )
+        .map_err(|e| {
+            tracing::warn!("iperf3 test not found: {} - {}", test_id, e);
+            StatusCode::NOT_FOUND
+        })?;
+
+    // Check access control
+    if let Some(Extension(session_info)) = &session_data {
+        if !user_has_iperf3_access(
+            &state.analyst_access,
+            &session_info.handle,
+            summary.magic_key.as_deref(),
+        ) {
+            tracing::warn!(
+                "User {} denied access to iperf3 test {}",
+                session_info.handle,
+                test_id
+            );
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let parse = |json: &str| serde_json::from_str(json).unwrap_or(serde_json::Value::Null);
+
+    Ok(Json(Iperf3TestDetails {
+        params: parse(&params_json),
+        intervals: parse(&intervals_json),
+        results: parse(&results_json),
+        client_results: client_results_json.as_deref().map(parse),
+        summary,
+    }))
+}
@@ -0,0 +1,166 @@
+//! API for minting and revoking personal API tokens
+//!
+//! Users create tokens for their own scripts, limited to scopes their role
+//! grants, and list or revoke them. The token itself is only returned by the
+//! create endpoint. Admins can list and revoke the tokens of all users.
+
+use crate::api_tokens::{ApiToken, DbApiTokenStore, NewApiToken};
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use netpoke_auth::{AuthProvider, AuthState, SessionData};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// State of the API token endpoints
+pub struct ApiTokenApiState {
+    pub store: Arc<DbApiTokenStore>,
+    /// To check that the scopes of new tokens are granted by the user's role
+    pub auth_state: AuthState,
+}
+
+/// Response of the create endpoint
+#[derive(Debug, Serialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub token: ApiToken,
+    /// The token to send as `Authorization: Bearer <token>` (shown only once)
+    pub secret: String,
+}
+
+/// Query parameters for listing tokens
+#[derive(Debug, Deserialize)]
+pub struct ListTokensQuery {
+    /// Only list the tokens of users of this provider
+    pub provider: Option<String>,
+    /// Only list the tokens of this user
+    pub handle: Option<String>,
+}
+
+// ============================================================================
+// Own Tokens Endpoints
+// ============================================================================
+
+/// List the tokens of the logged-in user
+pub async fn list_own_tokens(
+    State(state): State<Arc<ApiTokenApiState>>,
+    Extension(session_data): Extension<SessionData>,
+) -> Result<Json<Vec<ApiToken>>, StatusCode> {
+    let provider = session_data.auth_provider.user_provider();
+    list_tokens(&state.store, Some(provider), Some(&session_data.handle)).await
+}
+
+/// Create a token for the logged-in user
+pub async fn create_own_token(
+    State(state): State<Arc<ApiTokenApiState>>,
+    Extension(session_data): Extension<SessionData>,
+    Json(new_token): Json<NewApiToken>,
+) -> Result<(StatusCode, Json<CreatedApiToken>), StatusCode> {
+    // Tokens cannot mint further tokens
+    if matches!(session_data.auth_provider, AuthProvider::ApiToken(_)) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if new_token.name.trim().is_empty() || new_token.scopes.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    for &scope in &new_token.scopes {
+        if !state.auth_state.has_permission(&session_data, scope).await {
+            tracing::warn!(
+                "{} requested an API token with scope {} not granted by their role",
+                session_data.handle,
+                scope
+            );
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    match state
+        .store
+        .create(
+            session_data.auth_provider.name(),
+            &session_data.handle,
+            &session_data.groups,
+            &new_token,
+        )
+        .await
+    {
+        Ok((token, secret)) => {
+            tracing::info!(
+                "API token {} ({}) created by {}",
+                token.token_id,
+                token.name,
+                session_data.handle
+            );
+            Ok((StatusCode::CREATED, Json(CreatedApiToken { token, secret })))
+        }
+        Err(e) => {
+            tracing::error!("Failed to create API token: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Revoke one of the tokens of the logged-in user
+pub async fn revoke_own_token(
+    State(state): State<Arc<ApiTokenApiState>>,
+    Extension(session_data): Extension<SessionData>,
+    Path(token_id): Path<String>,
+) -> StatusCode {
+    let handle = &session_data.handle;
+    let owner = (session_data.auth_provider.user_provider(), handle.as_str());
+    revoke_token(&state.store, &token_id, Some(owner), handle).await
+}
+
+// ============================================================================
+// Admin Endpoints
+// ============================================================================
+
+/// List the tokens of all users, or of one user
+pub async fn list_all_tokens(
+    State(state): State<Arc<ApiTokenApiState>>,
+    Query(query): Query<ListTokensQuery>,
+) -> Result<Json<Vec<ApiToken>>, StatusCode> {
+    list_tokens(&state.store, query.provider.as_deref(), query.handle.as_deref()).await
+}
+
+/// Revoke the token of any user
+pub async fn revoke_any_token(
+    State(state): State<Arc<ApiTokenApiState>>,
+    Extension(admin): Extension<SessionData>,
+    Path(token_id): Path<String>,
+) -> StatusCode {
+    revoke_token(&state.store, &token_id, None, &admin.handle).await
+}
+
+async fn list_tokens(
+    store: &DbApiTokenStore,
+    provider: Option<&str>,
+    handle: Option<&str>,
+) -> Result<Json<Vec<ApiToken>>, StatusCode> {
+    store.list(provider, handle).await.map(Json).map_err(|e| {
+        tracing::error!("Failed to list API tokens: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn revoke_token(
+    store: &DbApiTokenStore,
+    token_id: &str,
+    owner: Option<(&str, &str)>,
+    revoked_by: &str,
+) -> StatusCode {
+    match store.revoke(token_id, owner, Some(revoked_by)).await {
+        Ok(true) => {
+            tracing::info!("API token {} revoked by {}", token_id, revoked_by);
+            StatusCode::NO_CONTENT
+        }
+        // Unknown, not the user's, or already revoked
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            tracing::error!("Failed to revoke API token {}: {}", token_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
@@ -0,0 +1,454 @@
+//! Database-backed personal API tokens
+//!
+//! Users mint tokens for their scripts with a name, a set of scopes and an
+//! expiry. Only the SHA-256 hash of a token is stored in the `api_tokens`
+//! table; the token itself is shown once, when it is created. Each use of a
+//! token is recorded in `last_used_at`.
+
+use crate::database::DbConnection;
+use netpoke_auth::api_tokens::{ApiTokenFuture, ApiTokenIdentity, ApiTokenStore};
+use netpoke_auth::Permission;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+type StoreResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Prefix of API tokens, to make them recognizable (e.g. by secret scanners)
+pub const TOKEN_PREFIX: &str = "npk_";
+
+/// Lifetime of tokens created without an explicit one
+pub const DEFAULT_EXPIRY_DAYS: u32 = 90;
+
+/// Longest lifetime a token can be created with
+pub const MAX_EXPIRY_DAYS: u32 = 365;
+
+/// An API token as stored in the database (without its hash)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ApiToken {
+    pub token_id: String,
+    pub name: String,
+    /// Provider of the user who owns the token
+    pub provider: String,
+    /// Handle of the user who owns the token
+    pub handle: String,
+    pub scopes: Vec<Permission>,
+    /// Creation time (Unix milliseconds)
+    pub created_at: i64,
+    /// Expiry time (Unix milliseconds)
+    pub expires_at: i64,
+    /// Last use (Unix milliseconds)
+    pub last_used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    pub revoked_by: Option<String>,
+}
+
+impl ApiToken {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let scopes: String = row.get(4)?;
+        Ok(Self {
+            token_id: row.get(0)?,
+            name: row.get(1)?,
+            provider: row.get(2)?,
+            handle: row.get(3)?,
+            scopes: parse_scopes(&scopes),
+            created_at: row.get(5)?,
+            expires_at: row.get(6)?,
+            last_used_at: row.get(7)?,
+            revoked_at: row.get(8)?,
+            revoked_by: row.get(9)?,
+        })
+    }
+}
+
+const SELECT_TOKEN: &str =
+    "SELECT token_id, name, provider, handle, scopes, created_at, expires_at,
+        last_used_at, revoked_at, revoked_by
+     FROM api_tokens";
+
+/// Request to create an API token
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewApiToken {
+    pub name: String,
+    pub scopes: Vec<Permission>,
+    /// Lifetime in days (default: 90, at most 365)
+    pub expires_in_days: Option<u32>,
+}
+
+/// Scopes are stored as a comma-separated list of permission names
+fn parse_scopes(scopes: &str) -> Vec<Permission> {
+    scopes
+        .split(',')
+        .filter(|scope| !scope.is_empty())
+        .filter_map(|scope| match scope.parse() {
+            Ok(permission) => Some(permission),
+            Err(e) => {
+                tracing::warn!("Ignoring API token scope: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Generate a random token: the prefix followed by 244 random bits in hex
+fn generate_token() -> String {
+    format!(
+        "{}{}{}",
+        TOKEN_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// API token store over the `api_tokens` table
+pub struct DbApiTokenStore {
+    db: DbConnection,
+}
+
+impl DbApiTokenStore {
+    /// Create a new DbApiTokenStore with the given database connection
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// Create a token for a user of a provider (`AuthProvider::name`)
+    ///
+    /// Returns the stored token and the token itself, which is not kept.
+    pub async fn create(
+        &self,
+        provider: &str,
+        handle: &str,
+        groups: &[String],
+        new_token: &NewApiToken,
+    ) -> StoreResult<(ApiToken, String)> {
+        let token = generate_token();
+        let token_id = Uuid::new_v4().to_string();
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let expiry_days = new_token
+            .expires_in_days
+            .unwrap_or(DEFAULT_EXPIRY_DAYS)
+            .min(MAX_EXPIRY_DAYS);
+        let expires_at = now_ms + i64::from(expiry_days) * 86_400_000;
+        let scopes: Vec<&str> = new_token
+            .scopes
+            .iter()
+            .map(|scope| scope.as_str())
+            .collect();
+
+        let db = self.db.lock().await;
+        db.execute(
+            "INSERT INTO api_tokens
+                (token_id, token_hash, name, provider, handle, groups, scopes, created_at,
+                 expires_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                token_id,
+                hash_token(&token),
+                new_token.name,
+                provider,
+                handle,
+                serde_json::to_string(groups)?,
+                scopes.join(","),
+                now_ms,
+                expires_at,
+            ],
+        )?;
+        let stored = db.query_row(
+            &format!("{} WHERE token_id = ?", SELECT_TOKEN),
+            params![token_id],
+            ApiToken::from_row,
+        )?;
+        Ok((stored, token))
+    }
+
+    /// List tokens, newest first, optionally only those of one provider
+    /// and/or handle
+    pub async fn list(
+        &self,
+        provider: Option<&str>,
+        handle: Option<&str>,
+    ) -> StoreResult<Vec<ApiToken>> {
+        let db = self.db.lock().await;
+        let mut stmt = db.prepare(&format!(
+            "{} WHERE (?1 IS NULL OR provider = ?1) AND (?2 IS NULL OR handle = ?2)
+             ORDER BY created_at DESC",
+            SELECT_TOKEN
+        ))?;
+        let tokens = stmt
+            .query_map(params![provider, handle], ApiToken::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tokens)
+    }
+
+    /// Revoke a token, optionally only if it belongs to a user, given as
+    /// provider and handle
+    ///
+    /// Returns false if there is no such token or it was already revoked.
+    pub async fn revoke(
+        &self,
+        token_id: &str,
+        owner: Option<(&str, &str)>,
+        revoked_by: Option<&str>,
+    ) -> StoreResult<bool> {
+        let (provider, handle) = owner.unzip();
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE api_tokens SET revoked_at = ?, revoked_by = ?
+             WHERE token_id = ? AND (?4 IS NULL OR (provider = ?4 AND handle = ?5))
+               AND revoked_at IS NULL",
+            params![
+                chrono::Utc::now().timestamp_millis(),
+                revoked_by,
+                token_id,
+                provider,
+                handle
+            ],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Revoke all tokens of a user of one provider (`AuthProvider::name`)
+    ///
+    /// Returns the number of tokens revoked.
+    pub async fn revoke_all_for(
+        &self,
+        provider: &str,
+        handle: &str,
+        revoked_by: Option<&str>,
+    ) -> StoreResult<usize> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE api_tokens SET revoked_at = ?, revoked_by = ?
+             WHERE provider = ? AND handle = ? AND revoked_at IS NULL",
+            params![
+                chrono::Utc::now().timestamp_millis(),
+                revoked_by,
+                provider,
+                handle
+            ],
+        )?;
+        Ok(updated)
+    }
+
+    async fn authenticate_token(&self, token: &str) -> StoreResult<Option<ApiTokenIdentity>> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return Ok(None);
+        }
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let db = self.db.lock().await;
+        let row: Option<(String, String, String, String, String, i64)> = db
+            .query_row(
+                "SELECT token_id, provider, handle, groups, scopes, created_at FROM api_tokens
+                 WHERE token_hash = ? AND revoked_at IS NULL AND expires_at > ?",
+                params![hash_token(token), now_ms],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((token_id, provider, handle, groups, scopes, created_at)) = row else {
+            return Ok(None);
+        };
+
+        db.execute(
+            "UPDATE api_tokens SET last_used_at = ? WHERE token_id = ?",
+            params![now_ms, token_id],
+        )?;
+
+        Ok(Some(ApiTokenIdentity {
+            token_id,
+            provider,
+            handle,
+            groups: serde_json::from_str(&groups)?,
+            scopes: parse_scopes(&scopes),
+            created_at: (created_at / 1000) as u64,
+        }))
+    }
+}
+
+impl ApiTokenStore for DbApiTokenStore {
+    fn authenticate<'a>(&'a self, token: &'a str) -> ApiTokenFuture<'a> {
+        Box::pin(async move {
+            self.authenticate_token(token).await.unwrap_or_else(|e| {
+                tracing::error!("Failed to check API token: {}", e);
+                None
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_database;
+    use tempfile::NamedTempFile;
+
+    fn new_token(scopes: Vec<Permission>) -> NewApiToken {
+        NewApiToken {
+            name: "nightly export".to_string(),
+            scopes,
+            expires_in_days: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_and_authenticate() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbApiTokenStore::new(init_database(temp_file.path()).unwrap());
+
+        let (stored, token) = store
+            .create(
+                "plain",
+                "alice",
+                &["noc".to_string()],
+                &new_token(vec![Permission::ViewSurveys, Permission::DownloadCaptures]),
+            )
+            .await
+            .unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(
+            stored.scopes,
+            vec![Permission::ViewSurveys, Permission::DownloadCaptures]
+        );
+        assert_eq!(stored.expires_at - stored.created_at, 90 * 86_400_000);
+        assert_eq!(stored.last_used_at, None);
+
+        // Only the hash is stored
+        {
+            let conn = store.db.lock().await;
+            let hash: String = conn
+                .query_row("SELECT token_hash FROM api_tokens", [], |row| row.get(0))
+                .unwrap();
+            assert_ne!(hash, token);
+        }
+
+        let identity = store.authenticate(&token).await.unwrap();
+        assert_eq!(identity.token_id, stored.token_id);
+        assert_eq!(identity.provider, "plain");
+        assert_eq!(identity.handle, "alice");
+        assert_eq!(identity.groups, vec!["noc".to_string()]);
+        assert!(identity.allows(Permission::DownloadCaptures));
+        assert!(!identity.allows(Permission::DownloadKeylogs));
+
+        let used = store.list(Some("plain"), Some("alice")).await.unwrap();
+        assert!(used[0].last_used_at.is_some());
+
+        assert!(store.authenticate("npk_wrong").await.is_none());
+        assert!(store.authenticate("not-a-token").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_and_expiry() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbApiTokenStore::new(init_database(temp_file.path()).unwrap());
+        let (first, first_token) = store
+            .create(
+                "plain",
+                "alice",
+                &[],
+                &new_token(vec![Permission::ViewSurveys]),
+            )
+            .await
+            .unwrap();
+        let (second, second_token) = store
+            .create(
+                "plain",
+                "bob",
+                &[],
+                &new_token(vec![Permission::ViewSurveys]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.list(None, None).await.unwrap().len(), 2);
+        assert_eq!(
+            store.list(Some("plain"), Some("alice")).await.unwrap(),
+            vec![first.clone()]
+        );
+        assert!(store
+            .list(Some("github"), Some("alice"))
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Users can only revoke their own tokens, not those of a same-named
+        // user of another provider
+        assert!(!store
+            .revoke(&first.token_id, Some(("plain", "bob")), Some("bob"))
+            .await
+            .unwrap());
+        assert!(!store
+            .revoke(&first.token_id, Some(("github", "alice")), Some("alice"))
+            .await
+            .unwrap());
+        assert!(store
+            .revoke(&first.token_id, Some(("plain", "alice")), Some("alice"))
+            .await
+            .unwrap());
+        assert!(!store
+            .revoke(&first.token_id, None, Some("admin"))
+            .await
+            .unwrap());
+        assert!(store.authenticate(&first_token).await.is_none());
+
+        {
+            let conn = store.db.lock().await;
+            conn.execute(
+                "UPDATE api_tokens SET expires_at = 0 WHERE token_id = ?",
+                params![second.token_id],
+            )
+            .unwrap();
+        }
+        assert!(store.authenticate(&second_token).await.is_none());
+
+        // Revoking all tokens of a user leaves same-named users of other providers alone
+        let (_, github_token) = store
+            .create(
+                "github",
+                "bob",
+                &[],
+                &new_token(vec![Permission::ViewSurveys]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .revoke_all_for("plain", "bob", Some("admin"))
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            store
+                .revoke_all_for("plain", "bob", Some("admin"))
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(store.authenticate(&github_token).await.is_some());
+    }
+
+    #[test]
+    fn test_parse_scopes() {
+        assert_eq!(
+            parse_scopes("view_surveys,download_keylogs"),
+            vec![Permission::ViewSurveys, Permission::DownloadKeylogs]
+        );
+        assert_eq!(parse_scopes(""), vec![]);
+        assert_eq!(
+            parse_scopes("view_surveys,root"),
+            vec![Permission::ViewSurveys]
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Database-backed security audit log
+//!
+//! Records logins, Magic Key redemptions, downloads of captures and keylogs,
+//! session wipes, client cleanups and administrative changes in the
+//! append-only `audit_log` table (triggers refuse updates and deletes). With
+//! hash chaining enabled, each entry carries the SHA-256 hash of its content
+//! and of the previous entry's hash, so that altering or deleting entries
+//! breaks the chain and is found by [`DbAuditLog::verify_chain`].
+
+use crate::database::DbConnection;
+use netpoke_auth::audit::{AuditEvent, AuditFuture, AuditLog};
+use netpoke_auth::AuthError;
+use rusqlite::{params, OptionalExtension, Row, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+type StoreResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Number of entries returned by a query unless a limit is given
+const DEFAULT_QUERY_LIMIT: u32 = 1000;
+
+/// Number of entries returned by a query at most
+const MAX_QUERY_LIMIT: u32 = 100_000;
+
+/// A recorded audit entry
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AuditEntry {
+    pub audit_id: i64,
+    /// Time of the action (Unix milliseconds)
+    pub timestamp: i64,
+    pub actor: Option<String>,
+    pub provider: Option<String>,
+    pub ip: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    /// "success", "failure" or "denied"
+    pub outcome: String,
+    pub detail: Option<String>,
+    pub prev_hash: Option<String>,
+    pub entry_hash: Option<String>,
+}
+
+impl AuditEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            audit_id: row.get(0)?,
+            timestamp: row.get(1)?,
+            actor: row.get(2)?,
+            provider: row.get(3)?,
+            ip: row.get(4)?,
+            action: row.get(5)?,
+            target: row.get(6)?,
+            outcome: row.get(7)?,
+            detail: row.get(8)?,
+            prev_hash: row.get(9)?,
+            entry_hash: row.get(10)?,
+        })
+    }
+
+    /// Hash of the entry's content chained to the previous entry's hash
+    fn chained_hash(&self, prev_hash: &str) -> String {
+        let content = serde_json::json!([
+            self.timestamp,
+            self.actor,
+            self.provider,
+            self.ip,
+            self.action,
+            self.target,
+            self.outcome,
+            self.detail,
+        ]);
+        let mut hasher = Sha256::new();
+        hasher.update(prev_hash.as_bytes());
+        hasher.update(content.to_string().as_bytes());
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Filters of an audit log query; all given filters must match
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub outcome: Option<String>,
+    pub ip: Option<String>,
+    /// Earliest time (Unix milliseconds)
+    pub since: Option<i64>,
+    /// Latest time (Unix milliseconds)
+    pub until: Option<i64>,
+    /// Maximum number of entries (default 1000)
+    pub limit: Option<u32>,
+}
+
+/// Result of verifying the hash chain
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ChainVerification {
+    /// Whether every chained entry is intact
+    pub valid: bool,
+    /// Number of chained entries checked
+    pub checked: u64,
+    /// First entry whose hash or link to the previous entry does not match
+    pub first_invalid_id: Option<i64>,
+}
+
+/// Audit log over the `audit_log` table
+pub struct DbAuditLog {
+    db: DbConnection,
+    /// Chain new entries by their hashes
+    hash_chain: bool,
+}
+
+impl DbAuditLog {
+    /// Create a new DbAuditLog with the given database connection
+    pub fn new(db: DbConnection, hash_chain: bool) -> Self {
+        Self { db, hash_chain }
+    }
+
+    /// Append an entry, chaining it to the last chained entry if enabled
+    pub async fn append(&self, event: &AuditEvent) -> StoreResult<()> {
+        let mut entry = AuditEntry {
+            audit_id: 0,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            actor: event.actor.clone(),
+            provider: event.provider.clone(),
+            ip: event.ip.map(|ip| ip.to_string()),
+            action: event.action.clone(),
+            target: event.target.clone(),
+            outcome: event.outcome.as_str().to_string(),
+            detail: event.detail.clone(),
+            prev_hash: None,
+            entry_hash: None,
+        };
+
+        let mut db = self.db.lock().await;
+        // Appends are serialized, so concurrent entries cannot fork the chain
+        let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if self.hash_chain {
+            let prev_hash = tx
+                .query_row(
+                    "SELECT entry_hash FROM audit_log WHERE entry_hash IS NOT NULL
+                     ORDER BY audit_id DESC LIMIT 1",
+                    [],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?
+                .unwrap_or_default();
+            entry.entry_hash = Some(entry.chained_hash(&prev_hash));
+            entry.prev_hash = Some(prev_hash);
+        }
+        tx.execute(
+            "INSERT INTO audit_log
+                (timestamp, actor, provider, ip, action, target, outcome, detail, prev_hash, entry_hash)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                entry.timestamp,
+                entry.actor,
+                entry.provider,
+                entry.ip,
+                entry.action,
+                entry.target,
+                entry.outcome,
+                entry.detail,
+                entry.prev_hash,
+                entry.entry_hash
+            ],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Query entries matching a filter, newest first
+    pub async fn query(&self, filter: &AuditFilter) -> StoreResult<Vec<AuditEntry>> {
+        let limit = filter
+            .limit
+            .unwrap_or(DEFAULT_QUERY_LIMIT)
+            .min(MAX_QUERY_LIMIT);
+        let db = self.db.lock().await;
+        let mut stmt = db.prepare(
+            "SELECT audit_id, timestamp, actor, provider, ip, action, target, outcome, detail,
+                    prev_hash, entry_hash
+             FROM audit_log
+             WHERE (?1 IS NULL OR actor = ?1)
+               AND (?2 IS NULL OR action = ?2)
+               AND (?3 IS NULL OR outcome = ?3)
+               AND (?4 IS NULL OR ip = ?4)
+               AND (?5 IS NULL OR timestamp >= ?5)
+               AND (?6 IS NULL OR timestamp <= ?6)
+             ORDER BY audit_id DESC LIMIT ?7",
+        )?;
+        let entries = stmt
+            .query_map(
+                params![
+                    filter.actor,
+                    filter.action,
+                    filter.outcome,
+                    filter.ip,
+                    filter.since,
+                    filter.until,
+                    limit
+                ],
+                AuditEntry::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    /// Check every chained entry against its hash and its predecessor
+    ///
+    /// Entries recorded while hash chaining was disabled are not checked.
+    /// Removing the newest entries cannot be detected from the chain alone.
+    pub async fn verify_chain(&self) -> StoreResult<ChainVerification> {
+        let db = self.db.lock().await;
+        let mut stmt = db.prepare(
+            "SELECT audit_id, timestamp, actor, provider, ip, action, target, outcome, detail,
+                    prev_hash, entry_hash
+             FROM audit_log WHERE entry_hash IS NOT NULL ORDER BY audit_id",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut prev_hash = String::new();
+        let mut checked = 0;
+        while let Some(row) = rows.next()? {
+            let entry = AuditEntry::from_row(row)?;
+            checked += 1;
+            let linked = entry.prev_hash.as_deref() == Some(prev_hash.as_str());
+            let expected = entry.chained_hash(&prev_hash);
+            if !linked || entry.entry_hash.as_deref() != Some(expected.as_str()) {
+                return Ok(ChainVerification {
+                    valid: false,
+                    checked,
+                    first_invalid_id: Some(entry.audit_id),
+                });
+            }
+            prev_hash = expected;
+        }
+        Ok(ChainVerification {
+            valid: true,
+            checked,
+            first_invalid_id: None,
+        })
+    }
+}
+
+/// Audit actor of a Magic Key: the first 12 hex digits of its SHA-256 hash
+///
+/// Entries cannot be deleted, so neither valid keys nor guesses are recorded
+/// in plaintext. `printf %s KEY | sha256sum | cut -c1-12` finds a key's entries.
+pub fn magic_key_actor(magic_key: &str) -> String {
+    let hash = hex::encode(Sha256::digest(magic_key.as_bytes()));
+    format!("magic_key:{}", &hash[..12])
+}
+
+impl AuditLog for DbAuditLog {
+    fn record<'a>(&'a self, event: &'a AuditEvent) -> AuditFuture<'a> {
+        Box::pin(async move {
+            self.append(event)
+                .await
+                .map_err(|e| AuthError::AuditLogError(e.to_string()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_database;
+    use netpoke_auth::audit::AuditOutcome;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_magic_key_actor() {
+        let actor = magic_key_actor("SURVEY-2024-001");
+        assert_eq!(actor.len(), "magic_key:".len() + 12);
+        assert!(!actor.contains("SURVEY"));
+        assert_eq!(actor, magic_key_actor("SURVEY-2024-001"));
+        assert_ne!(actor, magic_key_actor("SURVEY-2024-002"));
+        assert_eq!(magic_key_actor(""), "magic_key:e3b0c44298fc");
+    }
+
+    fn login(actor: &str, outcome: AuditOutcome) -> AuditEvent {
+        AuditEvent::new("login", outcome)
+            .actor(actor)
+            .provider("PlainLogin")
+            .ip(Some("192.0.2.1".parse().unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_append_and_query() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let log = DbAuditLog::new(init_database(temp_file.path()).unwrap(), false);
+        log.record(&login("alice", AuditOutcome::Success))
+            .await
+            .unwrap();
+        log.record(&login("bob", AuditOutcome::Failure))
+            .await
+            .unwrap();
+        log.record(
+            &AuditEvent::new("keylog_download", AuditOutcome::Success)
+                .actor("alice")
+                .target("/api/keylog/download/session?survey_session_id=abc"),
+        )
+        .await
+        .unwrap();
+
+        let all = log.query(&AuditFilter::default()).await.unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].action, "keylog_download");
+        assert_eq!(all[0].entry_hash, None);
+
+        let alice = AuditFilter {
+            actor: Some("alice".to_string()),
+            ..AuditFilter::default()
+        };
+        assert_eq!(log.query(&alice).await.unwrap().len(), 2);
+        let failures = AuditFilter {
+            outcome: Some("failure".to_string()),
+            ..AuditFilter::default()
+        };
+        let failures = log.query(&failures).await.unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].actor.as_deref(), Some("bob"));
+        assert_eq!(failures[0].ip.as_deref(), Some("192.0.2.1"));
+        let limited = AuditFilter {
+            limit: Some(1),
+            ..AuditFilter::default()
+        };
+        assert_eq!(log.query(&limited).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_append_only() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+        let log = DbAuditLog::new(db.clone(), false);
+        log.record(&login("alice", AuditOutcome::Success))
+            .await
+            .unwrap();
+
+        let conn = db.lock().await;
+        assert!(conn
+            .execute("UPDATE audit_log SET actor = 'mallory'", [])
+            .is_err());
+        assert!(conn.execute("DELETE FROM audit_log", []).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_hash_chain() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+        // Entries from before chaining was enabled are not part of the chain
+        DbAuditLog::new(db.clone(), false)
+            .append(&login("alice", AuditOutcome::Success))
+            .await
+            .unwrap();
+        let log = DbAuditLog::new(db.clone(), true);
+        for actor in ["alice", "bob", "carol"] {
+            log.append(&login(actor, AuditOutcome::Success))
+                .await
+                .unwrap();
+        }
+        let verification = log.verify_chain().await.unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.checked, 3);
+
+        let entries = log.query(&AuditFilter::default()).await.unwrap();
+        assert_eq!(entries[1].prev_hash, entries[2].entry_hash);
+        assert_eq!(entries[2].prev_hash.as_deref(), Some(""));
+
+        // Tampering requires dropping the triggers, but is then detected
+        {
+            let conn = db.lock().await;
+            conn.execute_batch(
+                "DROP TRIGGER audit_log_no_update;
+                 UPDATE audit_log SET actor = 'mallory' WHERE actor = 'bob';",
+            )
+            .unwrap();
+        }
+        let verification = log.verify_chain().await.unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.first_invalid_id, Some(entries[1].audit_id));
+    }
+}
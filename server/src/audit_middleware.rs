@@ -0,0 +1,73 @@
+//! Audit logging of sensitive routes
+//!
+//! `audit_request` is layered on the individual routes that download captures
+//! and keylogs, wipe or clean up data, or change administrative state. It runs
+//! after authentication, so it records who made the request (user or Magic
+//! Key survey session), from which IP, the request path and whether it
+//! succeeded.
+
+use crate::audit_log::{magic_key_actor, DbAuditLog};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use netpoke_auth::audit::{AuditEvent, AuditOutcome};
+use netpoke_auth::{SessionData, SurveySessionData};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// State for `audit_request`: the audit log and the action the route performs
+#[derive(Clone)]
+pub struct AuditState {
+    /// Audit log; requests are not recorded without one
+    pub log: Option<Arc<DbAuditLog>>,
+    /// Action recorded for the route, e.g. "keylog_download"
+    pub action: &'static str,
+}
+
+/// Outcome of a request by its response status
+fn outcome(status: StatusCode) -> AuditOutcome {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AuditOutcome::Denied,
+        status if status.is_success() || status.is_redirection() => AuditOutcome::Success,
+        _ => AuditOutcome::Failure,
+    }
+}
+
+/// Middleware recording the request and its outcome to the audit log
+pub async fn audit_request(
+    State(state): State<AuditState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(log) = state.log else {
+        return next.run(request).await;
+    };
+
+    let mut event = AuditEvent::new(state.action, AuditOutcome::Success)
+        .ip(request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip()))
+        .target(format!("{} {}", request.method(), request.uri()));
+    if let Some(session_data) = request.extensions().get::<SessionData>() {
+        event = event.session(session_data);
+    } else if let Some(token) = request.extensions().get::<SurveySessionData>() {
+        event = event
+            .actor(magic_key_actor(&token.magic_key))
+            .provider("magic_key");
+    }
+
+    let response = next.run(request).await;
+
+    event.outcome = outcome(response.status());
+    if event.outcome != AuditOutcome::Success {
+        event = event.detail(response.status().to_string());
+    }
+    if let Err(e) = log.append(&event).await {
+        tracing::error!("Failed to record audit event {}: {}", event.action, e);
+    }
+    response
+}
@@ -40,6 +40,10 @@ pub struct AuthenticatedAddress {
     pub last_authenticated: Instant,
     /// Source of authentication (e.g., "oauth", "magic_key", "webrtc")
     pub auth_source: String,
+    /// Survey session last seen from this address, if any
+    pub survey_session_id: Option<String>,
+    /// Magic key of the survey session token presented from this address, if any
+    pub magic_key: Option<String>,
 }
 
 /// Cache of recently authenticated addresses
@@ -73,15 +77,24 @@ impl AuthAddressCache {
         auth_source: String,
     ) {
         let normalized_ip = normalize_ip(ip);
-        let entry = AuthenticatedAddress {
-            ip: normalized_ip,
-            user_id: user_id.clone(),
-            display_name: display_name.clone(),
-            last_authenticated: Instant::now(),
-            auth_source: auth_source.clone(),
-        };
 
         if let Ok(mut cache) = self.cache.write() {
+            // Keep the survey session link: re-authentication from the same
+            // address is still the same survey
+            let (survey_session_id, magic_key) = cache
+                .get(&normalized_ip)
+                .map(|entry| (entry.survey_session_id.clone(), entry.magic_key.clone()))
+                .unwrap_or_default();
+            let entry = AuthenticatedAddress {
+                ip: normalized_ip,
+                user_id: user_id.clone(),
+                display_name: display_name.clone(),
+                last_authenticated: Instant::now(),
+                auth_source: auth_source.clone(),
+                survey_session_id,
+                magic_key,
+            };
+
             tracing::debug!(
                 "Recording authenticated address: {} (normalized from {}) for user '{}' via {}",
                 normalized_ip,
@@ -93,6 +106,30 @@ impl AuthAddressCache {
         }
     }
 
+    /// Associate an authenticated address with a survey session
+    pub fn set_survey_session(&self, ip: IpAddr, survey_session_id: String) -> bool {
+        let normalized_ip = normalize_ip(ip);
+        if let Ok(mut cache) = self.cache.write() {
+            if let Some(entry) = cache.get_mut(&normalized_ip) {
+                entry.survey_session_id = Some(survey_session_id);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Associate an authenticated address with the magic key of its survey session token
+    pub fn set_magic_key(&self, ip: IpAddr, magic_key: String) -> bool {
+        let normalized_ip = normalize_ip(ip);
+        if let Ok(mut cache) = self.cache.write() {
+            if let Some(entry) = cache.get_mut(&normalized_ip) {
+                entry.magic_key = Some(magic_key);
+                return true;
+            }
+        }
+        false
+    }
+
     /// Refresh an existing authenticated address (update timestamp only)
     pub fn refresh_auth(&self, ip: IpAddr) -> bool {
         let normalized_ip = normalize_ip(ip);
@@ -242,4 +279,32 @@ mod tests {
         assert!(auth_v6.is_some());
         assert_eq!(auth_v4.unwrap().user_id, auth_v6.unwrap().user_id);
     }
+
+    #[test]
+    fn test_survey_session_survives_reauth() {
+        let cache = AuthAddressCache::new(60);
+        let ip = "192.0.2.1".parse::<IpAddr>().unwrap();
+
+        // Unknown addresses cannot be linked
+        assert!(!cache.set_survey_session(ip, "survey-1".to_string()));
+
+        cache.record_auth(ip, "user1".to_string(), None, "webrtc".to_string());
+        assert!(cache.set_survey_session(ip, "survey-1".to_string()));
+
+        // Refreshing the auth keeps the link
+        cache.record_auth(ip, "user1".to_string(), None, "webrtc".to_string());
+        let ipv4_mapped = "::ffff:192.0.2.1".parse::<IpAddr>().unwrap();
+        assert_eq!(
+            cache.check_auth(ipv4_mapped).unwrap().survey_session_id,
+            Some("survey-1".to_string())
+        );
+
+        // ... and the magic key
+        assert!(cache.set_magic_key(ip, "DEMO-KEY".to_string()));
+        cache.record_auth(ip, "user1".to_string(), None, "webrtc".to_string());
+        assert_eq!(
+            cache.check_auth(ip).unwrap().magic_key,
+            Some("DEMO-KEY".to_string())
+        );
+    }
 }
@@ -1,21 +1,30 @@
+use crate::audit_log::magic_key_actor;
 use crate::auth_cache::SharedAuthAddressCache;
+use crate::magic_keys::{KeyRejection, MagicKeyStore};
+use crate::survey_middleware::validate_survey_session;
+use crate::survey_tokens::{new_survey_session, SurveyTokenStore};
 use axum::{
     extract::{ConnectInfo, FromRef, State},
-    http::{header, HeaderMap, StatusCode},
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use axum_extra::extract::cookie::{Key, PrivateCookieJar};
+use axum_extra::extract::cookie::{Cookie, Key, PrivateCookieJar, SameSite};
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
-use uuid::Uuid;
-use netpoke_auth::{AuthState, SessionData};
+use std::sync::Arc;
+use netpoke_auth::audit::{AuditEvent, AuditOutcome};
+use netpoke_auth::{AuthError, AuthState, ClientInfo, SessionData};
 
 /// Combined state for auth handlers that need both AuthState and auth cache
 #[derive(Clone)]
 pub struct AuthHandlerState {
     pub auth_state: AuthState,
     pub auth_cache: Option<SharedAuthAddressCache>,
+    /// Database-backed magic keys; the configured key list is used without one
+    pub magic_keys: Option<Arc<MagicKeyStore>>,
+    /// Issued survey session tokens, for revocation; unchecked without a database
+    pub survey_tokens: Option<Arc<SurveyTokenStore>>,
 }
 
 /// Implement FromRef to allow PrivateCookieJar to extract Key from AuthHandlerState
@@ -68,172 +77,11 @@ fn extract_session_from_jar(jar: &PrivateCookieJar, cookie_name: &str) -> Option
         .and_then(|cookie| serde_json::from_str(cookie.value()).ok())
 }
 
-/// Collect all cookies from potentially multiple cookie headers
-fn collect_all_cookies(headers: &HeaderMap) -> String {
-    headers
-        .get_all("cookie")
-        .iter()
-        .filter_map(|value| value.to_str().ok())
-        .fold(String::new(), |mut acc, s| {
-            if !acc.is_empty() {
-                acc.push_str("; ");
-            }
-            acc.push_str(s);
-            acc
-        })
-}
-
-/// Extract a session ID from a cookie string
-fn extract_session_id(cookie_str: &str, cookie_name: &str) -> Option<String> {
-    cookie_str.split(';').find_map(|cookie| {
-        let parts: Vec<&str> = cookie.trim().split('=').collect();
-        if parts.len() == 2 && parts[0] == cookie_name {
-            Some(parts[1].to_string())
-        } else {
-            None
-        }
-    })
-}
-
-/// Validate survey session format and check expiration
-/// Session format: "survey_{magic_key}_{timestamp}_{uuid}"
-fn validate_survey_session(
-    session_id: &str,
-    config: &netpoke_auth::config::MagicKeyConfig,
-) -> bool {
-    // Check if it starts with "survey_"
-    if !session_id.starts_with("survey_") {
-        return false;
-    }
-
-    // Parse the session format
-    let parts: Vec<&str> = session_id.split('_').collect();
-    if parts.len() < 4 {
-        return false;
-    }
-
-    // Extract timestamp (second-to-last part before UUID)
-    let timestamp_str = parts[parts.len() - 2];
-    let timestamp = match timestamp_str.parse::<u64>() {
-        Ok(t) => t,
-        Err(_) => return false,
-    };
-
-    // Check if session has expired
-    let current_time = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-
-    let elapsed = current_time.saturating_sub(timestamp);
-    if elapsed > config.survey_timeout_seconds {
-        return false;
-    }
-
-    // Extract and validate the Magic Key is still in the allowed list
-    let magic_key_parts: Vec<String> = parts[1..parts.len() - 2]
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
-    let magic_key = magic_key_parts.join("-");
-
-    if !config.magic_keys.contains(&magic_key) {
-        return false;
-    }
-
-    true
-}
-
-/// Extract the magic key from a survey session ID.
-/// Session format: "survey_{magic_key}_{timestamp}_{uuid}"
-/// Magic key hyphens are encoded as underscores in the session ID.
-fn extract_magic_key_from_session(session_id: &str) -> Option<String> {
-    if !session_id.starts_with("survey_") {
-        return None;
-    }
-    let parts: Vec<&str> = session_id.split('_').collect();
-    if parts.len() < 4 {
-        return None;
-    }
-    let key_parts = &parts[1..parts.len() - 2];
-    if key_parts.is_empty() {
-        return None;
-    }
-    Some(key_parts.join("-"))
-}
-
-/// Check authentication status
-pub async fn auth_status(
-    State(auth_state): State<AuthState>,
-    headers: HeaderMap,
-    jar: PrivateCookieJar,
-) -> Json<AuthStatusResponse> {
-    // Skip if auth is disabled
-    if !auth_state.is_enabled() {
-        return Json(AuthStatusResponse {
-            authenticated: false,
-            auth_type: None,
-            user: None,
-            stats: None,
-            magic_key: None,
-        });
-    }
-
-    // Try to extract session from private cookie (full auth takes precedence)
-    if let Some(session_data) =
-        extract_session_from_jar(&jar, &auth_state.config.session.cookie_name)
-    {
-        // Validate session
-        if auth_state.validate_session(&session_data).is_ok() {
-            // Check if user is allowed
-            if auth_state.is_user_allowed(&session_data.handle) {
-                return Json(AuthStatusResponse {
-                    authenticated: true,
-                    auth_type: Some("full".to_string()),
-                    user: Some(UserInfo {
-                        name: session_data.handle.clone(),
-                    }),
-                    stats: Some(StatsInfo {
-                        active_surveys: 0, // TODO: Implement real stats
-                        total_measurements: 0,
-                        active_surveyors: 0,
-                    }),
-                    magic_key: None,
-                });
-            }
-        }
-    }
-
-    // Check for magic key session (fallback)
-    if auth_state.config.magic_keys.enabled {
-        let cookie_str = collect_all_cookies(&headers);
-        if let Some(survey_session_id) =
-            extract_session_id(&cookie_str, &auth_state.config.magic_keys.survey_cookie_name)
-        {
-            if validate_survey_session(&survey_session_id, &auth_state.config.magic_keys) {
-                return Json(AuthStatusResponse {
-                    authenticated: true,
-                    auth_type: Some("magic_key".to_string()),
-                    user: None,
-                    stats: None,
-                    magic_key: extract_magic_key_from_session(&survey_session_id),
-                });
-            }
-        }
-    }
-
-    Json(AuthStatusResponse {
-        authenticated: false,
-        auth_type: None,
-        user: None,
-        stats: None,
-        magic_key: None,
-    })
-}
+/// Check authentication status, recording authenticated addresses to the auth cache
 pub async fn auth_status_with_cache(
     State(handler_state): State<AuthHandlerState>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    headers: HeaderMap,
+    client: ClientInfo,
     jar: PrivateCookieJar,
 ) -> Json<AuthStatusResponse> {
     let auth_state = &handler_state.auth_state;
@@ -253,8 +101,12 @@ pub async fn auth_status_with_cache(
     if let Some(session_data) =
         extract_session_from_jar(&jar, &auth_state.config.session.cookie_name)
     {
-        // Validate session
-        if auth_state.validate_session(&session_data).is_ok() {
+        // Validate session (expiration, and revocation with a session store)
+        if auth_state
+            .verify_session(&session_data, &client)
+            .await
+            .is_ok()
+        {
             // Check if user is allowed
             if auth_state.is_user_allowed(&session_data.handle) {
                 // Record the authenticated address to the cache
@@ -284,31 +136,36 @@ pub async fn auth_status_with_cache(
         }
     }
 
-    // Check for magic key session (fallback)
+    // Check for magic key survey session token (fallback)
     if auth_state.config.magic_keys.enabled {
-        let cookie_str = collect_all_cookies(&headers);
-        if let Some(survey_session_id) =
-            extract_session_id(&cookie_str, &auth_state.config.magic_keys.survey_cookie_name)
+        if let Some(token) = validate_survey_session(
+            &jar,
+            Some(addr.ip()),
+            &auth_state.config.magic_keys,
+            handler_state.magic_keys.as_deref(),
+            handler_state.survey_tokens.as_deref(),
+        )
+        .await
         {
-            if validate_survey_session(&survey_session_id, &auth_state.config.magic_keys) {
-                // Record magic key auth to cache
-                if let Some(cache) = &handler_state.auth_cache {
-                    cache.record_auth(
-                        addr.ip(),
-                        "magic_key_user".to_string(),
-                        None,
-                        "magic_key".to_string(),
-                    );
-                }
-
-                return Json(AuthStatusResponse {
-                    authenticated: true,
-                    auth_type: Some("magic_key".to_string()),
-                    user: None,
-                    stats: None,
-                    magic_key: extract_magic_key_from_session(&survey_session_id),
-                });
+            // Record magic key auth to cache
+            if let Some(cache) = &handler_state.auth_cache {
+                cache.record_auth(
+                    addr.ip(),
+                    format!("magic_key:{}", token.magic_key),
+                    None,
+                    "magic_key".to_string(),
+                );
+                // The magic key's iperf3 limits apply to this address
+                cache.set_magic_key(addr.ip(), token.magic_key.clone());
             }
+
+            return Json(AuthStatusResponse {
+                authenticated: true,
+                auth_type: Some("magic_key".to_string()),
+                user: None,
+                stats: None,
+                magic_key: Some(token.magic_key),
+            });
         }
     }
 
@@ -321,84 +178,127 @@ pub async fn auth_status_with_cache(
     })
 }
 
-/// Validate Magic Key and create survey session
-pub async fn magic_key_auth(
-    State(auth_state): State<AuthState>,
-    Json(payload): Json<MagicKeyRequest>,
-) -> Result<Response, StatusCode> {
-    // Check if Magic Key authentication is enabled
-    let magic_keys = auth_state.config.magic_keys.magic_keys.clone();
-
-    if !auth_state.config.magic_keys.enabled || magic_keys.is_empty() {
-        return Err(StatusCode::NOT_FOUND);
-    }
-
-    // Validate the Magic Key
-    if !magic_keys.contains(&payload.magic_key) {
-        let error = ErrorResponse {
-            message: "Invalid Magic Key. Please check your key and try again.".to_string(),
-        };
-        return Ok((StatusCode::UNAUTHORIZED, Json(error)).into_response());
-    }
-
-    // Create a survey session with the Magic Key stored in the session ID format
-    // Format: "survey_{magic_key}_{timestamp}_{uuid}"
-    // This allows us to validate the session later without a database
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    let uuid = Uuid::new_v4();
-    let survey_session_id = format!(
-        "survey_{}_{}_{}",
-        payload.magic_key.replace("-", "_"),
-        timestamp,
-        uuid
-    );
-
-    tracing::info!("Magic Key validated: {}", payload.magic_key);
-
-    Ok((
-        StatusCode::OK,
-        [(
-            header::SET_COOKIE,
-            format!(
-                "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
-                auth_state.config.magic_keys.survey_cookie_name,
-                survey_session_id,
-                auth_state.config.magic_keys.survey_timeout_seconds
-            ),
-        )],
-        Json(serde_json::json!({
-            "message": "Magic Key validated successfully"
-        })),
-    )
-        .into_response())
-}
-
-/// Validate Magic Key and create survey session, recording to auth cache
+/// Validate Magic Key and issue a survey session token, recording to auth cache
 pub async fn magic_key_auth_with_cache(
     State(handler_state): State<AuthHandlerState>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    jar: PrivateCookieJar,
     Json(payload): Json<MagicKeyRequest>,
 ) -> Result<Response, StatusCode> {
     let auth_state = &handler_state.auth_state;
 
     // Check if Magic Key authentication is enabled
-    let magic_keys = auth_state.config.magic_keys.magic_keys.clone();
+    let magic_key_config = &auth_state.config.magic_keys;
 
-    if !auth_state.config.magic_keys.enabled || magic_keys.is_empty() {
+    if !magic_key_config.enabled
+        || (handler_state.magic_keys.is_none() && magic_key_config.magic_keys.is_empty())
+    {
         return Err(StatusCode::NOT_FOUND);
     }
 
-    // Validate the Magic Key
-    if !magic_keys.contains(&payload.magic_key) {
+    // Magic keys are guessable, so clients failing repeatedly are locked out
+    if let Err(e) = auth_state.check_lockout(Some(addr.ip()), None).await {
+        tracing::warn!("Magic Key attempt from {} refused: {}", addr.ip(), e);
+        auth_state
+            .audit(
+                AuditEvent::new("magic_key_redeem", AuditOutcome::Denied)
+                    .actor(magic_key_actor(&payload.magic_key))
+                    .provider("magic_key")
+                    .ip(Some(addr.ip()))
+                    .detail(e.to_string()),
+            )
+            .await;
+        let retry_after = match e {
+            AuthError::LockedOut(seconds) => seconds,
+            _ => 0,
+        };
         let error = ErrorResponse {
-            message: "Invalid Magic Key. Please check your key and try again.".to_string(),
+            message: "Too many invalid Magic Keys. Please try again later.".to_string(),
+        };
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(error),
+        )
+            .into_response());
+    }
+
+    // Validate the Magic Key against the store, or the configured list without one
+    let validation = match &handler_state.magic_keys {
+        Some(store) => match store.validate(&payload.magic_key).await {
+            Ok(key) if key.sessions_exhausted() => Err(KeyRejection::SessionLimitReached),
+            Ok(_) => Ok(()),
+            Err(rejection) => Err(rejection),
+        },
+        None if magic_key_config.magic_keys.contains(&payload.magic_key) => Ok(()),
+        None => Err(KeyRejection::Unknown),
+    };
+
+    if let Err(rejection) = validation {
+        tracing::info!(
+            "Magic Key rejected: {} from {} ({})",
+            payload.magic_key,
+            addr.ip(),
+            rejection
+        );
+        auth_state
+            .audit(
+                AuditEvent::new("magic_key_redeem", AuditOutcome::Failure)
+                    .actor(magic_key_actor(&payload.magic_key))
+                    .provider("magic_key")
+                    .ip(Some(addr.ip()))
+                    .detail(rejection.to_string()),
+            )
+            .await;
+        // Only unknown keys are guesses; known but unusable ones are not counted
+        if matches!(rejection, KeyRejection::Unknown) {
+            auth_state.record_login_failure(Some(addr.ip()), None).await;
+        }
+        let message = match rejection {
+            KeyRejection::Unknown => "Invalid Magic Key. Please check your key and try again.",
+            KeyRejection::Expired => "This Magic Key has expired.",
+            KeyRejection::Disabled | KeyRejection::Revoked => "This Magic Key is no longer valid.",
+            KeyRejection::SessionLimitReached => {
+                "This Magic Key has reached its maximum number of surveys."
+            }
+        };
+        let error = ErrorResponse {
+            message: message.to_string(),
         };
         return Ok((StatusCode::UNAUTHORIZED, Json(error)).into_response());
     }
 
+    // Issue a random survey session token; the private cookie keeps it from being
+    // forged or altered, and the token store lets it be revoked
+    let bound_ip = magic_key_config
+        .bind_survey_session_to_ip
+        .then_some(addr.ip());
+    let token = new_survey_session(
+        &payload.magic_key,
+        magic_key_config.survey_timeout_seconds,
+        bound_ip,
+    );
+    if let Some(store) = &handler_state.survey_tokens {
+        if let Err(e) = store.record(&token, addr.ip()).await {
+            tracing::error!("Failed to record survey session token: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    let token_json = serde_json::to_string(&token).map_err(|e| {
+        tracing::error!("Failed to serialize survey session token: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let mut cookie = Cookie::new(magic_key_config.survey_cookie_name.clone(), token_json);
+    cookie.set_path("/");
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Lax);
+    if auth_state.config.session.secure {
+        cookie.set_secure(true);
+    }
+    cookie.set_max_age(time::Duration::seconds(
+        magic_key_config.survey_timeout_seconds as i64,
+    ));
+
     // Record the authenticated address to the cache
     if let Some(cache) = &handler_state.auth_cache {
         cache.record_auth(
@@ -407,40 +307,27 @@ pub async fn magic_key_auth_with_cache(
             None,
             "magic_key".to_string(),
         );
+        cache.set_magic_key(addr.ip(), payload.magic_key.clone());
     }
 
-    // Create a survey session with the Magic Key stored in the session ID format
-    // Format: "survey_{magic_key}_{timestamp}_{uuid}"
-    // This allows us to validate the session later without a database
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    let uuid = Uuid::new_v4();
-    let survey_session_id = format!(
-        "survey_{}_{}_{}",
-        payload.magic_key.replace("-", "_"),
-        timestamp,
-        uuid
-    );
-
     tracing::info!(
-        "Magic Key validated: {} from {}",
+        "Magic Key validated: {} from {} (token {})",
         payload.magic_key,
-        addr.ip()
+        addr.ip(),
+        token.token_id
     );
+    auth_state
+        .audit(
+            AuditEvent::new("magic_key_redeem", AuditOutcome::Success)
+                .actor(magic_key_actor(&payload.magic_key))
+                .provider("magic_key")
+                .ip(Some(addr.ip()))
+                .target(format!("survey_token:{}", token.token_id)),
+        )
+        .await;
 
     Ok((
-        StatusCode::OK,
-        [(
-            header::SET_COOKIE,
-            format!(
-                "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
-                auth_state.config.magic_keys.survey_cookie_name,
-                survey_session_id,
-                auth_state.config.magic_keys.survey_timeout_seconds
-            ),
-        )],
+        jar.add(cookie),
         Json(serde_json::json!({
             "message": "Magic Key validated successfully"
         })),
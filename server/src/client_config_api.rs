@@ -11,12 +11,18 @@ use std::sync::Arc;
 pub struct ClientConfigResponse {
     /// Delay in milliseconds between WebRTC connection establishment attempts
     pub webrtc_connection_delay_ms: u32,
+    /// Ports of the NAT behavior responder, the primary one first (empty when disabled)
+    pub nat_behavior_ports: Vec<u16>,
+    /// Alternate addresses of the NAT behavior responder
+    pub nat_behavior_alternate_addresses: Vec<String>,
 }
 
 /// Shared state for client config API
 #[derive(Clone)]
 pub struct ClientConfigState {
     pub webrtc_connection_delay_ms: u32,
+    pub nat_behavior_ports: Vec<u16>,
+    pub nat_behavior_alternate_addresses: Vec<String>,
 }
 
 /// Get client configuration
@@ -27,5 +33,7 @@ pub async fn get_client_config(
 ) -> Json<ClientConfigResponse> {
     Json(ClientConfigResponse {
         webrtc_connection_delay_ms: config_state.webrtc_connection_delay_ms,
+        nat_behavior_ports: config_state.nat_behavior_ports.clone(),
+        nat_behavior_alternate_addresses: config_state.nat_behavior_alternate_addresses.clone(),
     })
 }
@@ -26,6 +26,12 @@ pub struct Config {
     pub database: DatabaseConfig,
     #[serde(default)]
     pub storage: StorageConfig,
+    #[serde(default)]
+    pub enrichment: EnrichmentConfig,
+    #[serde(default)]
+    pub reverse_dns: ReverseDnsConfig,
+    #[serde(default)]
+    pub nat_behavior: NatBehaviorConfig,
     #[serde(default = "default_analyst_access")]
     pub analyst_access: HashMap<String, Vec<String>>,
 }
@@ -176,6 +182,137 @@ impl Default for StorageConfig {
     }
 }
 
+/// Offline datasets for ASN, prefix and geolocation enrichment of traceroute
+/// hops and client peer addresses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrichmentConfig {
+    /// ip2asn (range) or RouteViews pfx2as (prefix) table, tab separated
+    #[serde(default)]
+    pub asn_table_path: Option<String>,
+    /// MaxMind DB file (GeoLite2/GeoIP2 City, Country or ASN)
+    #[serde(default)]
+    pub geo_database_path: Option<String>,
+    /// How often to check the dataset files for changes, in seconds
+    #[serde(default = "default_enrichment_refresh_interval_secs")]
+    pub refresh_interval_secs: u64,
+}
+
+fn default_enrichment_refresh_interval_secs() -> u64 {
+    60
+}
+
+impl Default for EnrichmentConfig {
+    fn default() -> Self {
+        Self {
+            asn_table_path: None,
+            geo_database_path: None,
+            refresh_interval_secs: default_enrichment_refresh_interval_secs(),
+        }
+    }
+}
+
+/// Reverse DNS (PTR) resolution of traceroute hop addresses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReverseDnsConfig {
+    /// Resolve hop names
+    #[serde(default = "default_reverse_dns_enabled")]
+    pub enabled: bool,
+    /// Nameservers ("ip" or "ip:port"); empty means those of /etc/resolv.conf
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+    /// Timeout of a single query in milliseconds
+    #[serde(default = "default_reverse_dns_query_timeout_ms")]
+    pub query_timeout_ms: u64,
+    /// Time a traceroute round may wait for names in total, in milliseconds
+    #[serde(default = "default_reverse_dns_round_budget_ms")]
+    pub round_budget_ms: u64,
+    /// Maximum number of cached names
+    #[serde(default = "default_reverse_dns_cache_size")]
+    pub cache_size: usize,
+    /// Upper bound on the time a name is cached, whatever its TTL, in seconds
+    #[serde(default = "default_reverse_dns_max_ttl_secs")]
+    pub max_ttl_secs: u64,
+    /// Time addresses without a name are cached, in seconds
+    #[serde(default = "default_reverse_dns_negative_ttl_secs")]
+    pub negative_ttl_secs: u64,
+}
+
+fn default_reverse_dns_enabled() -> bool {
+    true
+}
+
+fn default_reverse_dns_query_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_reverse_dns_round_budget_ms() -> u64 {
+    1500
+}
+
+fn default_reverse_dns_cache_size() -> usize {
+    4096
+}
+
+fn default_reverse_dns_max_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_reverse_dns_negative_ttl_secs() -> u64 {
+    300
+}
+
+impl Default for ReverseDnsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_reverse_dns_enabled(),
+            nameservers: Vec::new(),
+            query_timeout_ms: default_reverse_dns_query_timeout_ms(),
+            round_budget_ms: default_reverse_dns_round_budget_ms(),
+            cache_size: default_reverse_dns_cache_size(),
+            max_ttl_secs: default_reverse_dns_max_ttl_secs(),
+            negative_ttl_secs: default_reverse_dns_negative_ttl_secs(),
+        }
+    }
+}
+
+/// STUN behavior discovery responder (RFC 5780) for NAT type classification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NatBehaviorConfig {
+    /// Run the responder and let clients classify their NAT
+    #[serde(default)]
+    pub enabled: bool,
+    /// UDP ports to listen on, the first being the primary port
+    #[serde(default = "default_nat_behavior_ports")]
+    pub ports: Vec<u16>,
+    /// Primary local addresses to listen on; unspecified addresses cannot
+    /// report the address a request arrived on
+    #[serde(default = "default_nat_behavior_addresses")]
+    pub addresses: Vec<String>,
+    /// Alternate local addresses, one per address family, to tell
+    /// address-dependent NAT behavior from endpoint-independent behavior
+    #[serde(default)]
+    pub alternate_addresses: Vec<String>,
+}
+
+fn default_nat_behavior_ports() -> Vec<u16> {
+    vec![3478, 3479]
+}
+
+fn default_nat_behavior_addresses() -> Vec<String> {
+    vec!["0.0.0.0".to_string(), "::".to_string()]
+}
+
+impl Default for NatBehaviorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ports: default_nat_behavior_ports(),
+            addresses: default_nat_behavior_addresses(),
+            alternate_addresses: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub host: String,
@@ -270,6 +407,9 @@ impl Default for Config {
             iperf3: Iperf3Config::default(),
             database: DatabaseConfig::default(),
             storage: StorageConfig::default(),
+            enrichment: EnrichmentConfig::default(),
+            reverse_dns: ReverseDnsConfig::default(),
+            nat_behavior: NatBehaviorConfig::default(),
             analyst_access: default_analyst_access(),
         }
     }
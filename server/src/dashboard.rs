@@ -141,6 +141,10 @@ async fn dashboard_ws(socket: WebSocket, state: AppState) {
                     ip_version: session.ip_version.clone(),
                     connected_at,
                     metrics,
+                    peer_enrichment: state
+                        .enrichment
+                        .as_ref()
+                        .and_then(|enrichment| enrichment.lookup_str(&peer_address_final)),
                     peer_address: Some(peer_address_final),
                     peer_port,
                     current_seq,
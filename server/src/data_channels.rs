@@ -195,44 +195,68 @@ async fn handle_control_message(session: Arc<ClientSession>, msg: DataChannelMes
                 return;
             }
 
+            // A surveyor's magic key comes from the survey session token presented at
+            // signaling and cannot be changed by the client; other users name the key
+            // the survey is recorded under
+            let authenticated_key = session.magic_key.read().await.clone();
+            let magic_key = match (authenticated_key, &start_survey_msg.magic_key) {
+                (Some(authenticated), Some(claimed)) if authenticated != *claimed => {
+                    tracing::warn!(
+                        "Session {} claimed magic key {} but authenticated with {}, using the latter",
+                        session.id,
+                        claimed,
+                        authenticated
+                    );
+                    Some(authenticated)
+                }
+                (Some(authenticated), _) => Some(authenticated),
+                (None, claimed) => claimed.clone(),
+            };
+
+            // Count the session against the magic key's usage limit, refusing it
+            // if the key has used up its sessions or is no longer valid
+            if let (Some(store), Some(magic_key)) = (&session.magic_key_store, &magic_key) {
+                match store.record_session_start(magic_key).await {
+                    Ok(key) => tracing::info!(
+                        "Magic key {} used for survey session {} ({} sessions)",
+                        magic_key,
+                        start_survey_msg.survey_session_id,
+                        key.session_count
+                    ),
+                    Err(rejection) => {
+                        tracing::warn!(
+                            "Refusing survey session {} for session {}: {}",
+                            start_survey_msg.survey_session_id,
+                            session.id,
+                            rejection
+                        );
+                        return;
+                    }
+                }
+            }
+
             // Store the survey session ID
             {
                 let mut survey_id = session.survey_session_id.write().await;
                 *survey_id = start_survey_msg.survey_session_id.clone();
             }
 
-            // Store the magic key if provided, or extract from session ID
-            if let Some(ref magic_key) = start_survey_msg.magic_key {
-                let mut mk = session.magic_key.write().await;
-                *mk = Some(magic_key.clone());
-            } else if let Some(extracted) = extract_magic_key_from_session_id(
-                &start_survey_msg.survey_session_id,
-            ) {
+            // Store the magic key
+            if magic_key.is_some() {
                 let mut mk = session.magic_key.write().await;
-                *mk = Some(extracted);
+                *mk = magic_key.clone();
             }
 
             // Create database session record if session manager is available
             if let Some(session_manager) = &session.session_manager {
                 // Magic key is required for proper database tracking
-                // If not provided by client, extract from the survey session ID format:
-                // "survey_{magic_key}_{timestamp}_{uuid}" (hyphens encoded as underscores)
-                let magic_key_owned = match start_survey_msg.magic_key.as_deref() {
-                    Some(key) => key.to_string(),
-                    None => {
-                        extract_magic_key_from_session_id(
-                            &start_survey_msg.survey_session_id,
-                        )
-                        .unwrap_or_else(|| {
-                            tracing::warn!(
-                                "Could not extract magic_key from session ID {}, using 'unknown'",
-                                start_survey_msg.survey_session_id
-                            );
-                            "unknown".to_string()
-                        })
-                    }
-                };
-                let magic_key = &magic_key_owned;
+                let magic_key = magic_key.as_deref().unwrap_or_else(|| {
+                    tracing::warn!(
+                        "No magic key for survey session {}, using 'unknown'",
+                        start_survey_msg.survey_session_id
+                    );
+                    "unknown"
+                });
                 // TODO: Extract user_login from authentication context when available
                 // This is deferred to a future issue as it requires passing auth state through the data channel flow
                 if let Err(e) = session_manager
@@ -410,6 +434,136 @@ async fn handle_control_message(session: Arc<ClientSession>, msg: DataChannelMes
             });
         }
 
+        common::ControlMessage::StartExtHeaderTest(ext_header_msg) => {
+            if ext_header_msg.conn_id != session.conn_id {
+                tracing::warn!(
+                    "StartExtHeaderTestMessage conn_id mismatch: received '{}' but session {} expects '{}', ignoring",
+                    ext_header_msg.conn_id, session.id, session.conn_id
+                );
+                return;
+            }
+
+            // Update survey session ID if provided
+            if !ext_header_msg.survey_session_id.is_empty() {
+                let mut survey_id = session.survey_session_id.write().await;
+                *survey_id = ext_header_msg.survey_session_id.clone();
+            }
+
+            tracing::info!(
+                "Received extension header test request for session {} with {} header of {} bytes",
+                session.id,
+                ext_header_msg.header_type.as_str(),
+                ext_header_msg.header_size
+            );
+
+            let session_clone = session.clone();
+            tokio::spawn(async move {
+                measurements::run_ext_header_test_round(
+                    session_clone,
+                    ext_header_msg.header_type,
+                    ext_header_msg.header_size,
+                    ext_header_msg.path_ttl,
+                    ext_header_msg.collect_timeout_ms,
+                )
+                .await;
+            });
+        }
+
+        common::ControlMessage::StartFragmentationTest(fragmentation_msg) => {
+            if fragmentation_msg.conn_id != session.conn_id {
+                tracing::warn!(
+                    "StartFragmentationTestMessage conn_id mismatch: received '{}' but session {} expects '{}', ignoring",
+                    fragmentation_msg.conn_id, session.id, session.conn_id
+                );
+                return;
+            }
+
+            // Update survey session ID if provided
+            if !fragmentation_msg.survey_session_id.is_empty() {
+                let mut survey_id = session.survey_session_id.write().await;
+                *survey_id = fragmentation_msg.survey_session_id.clone();
+            }
+
+            tracing::info!(
+                "Received fragmentation test request for session {} with packet_size={}, out_of_order={}",
+                session.id,
+                fragmentation_msg.packet_size,
+                fragmentation_msg.out_of_order
+            );
+
+            let session_clone = session.clone();
+            tokio::spawn(async move {
+                measurements::run_fragmentation_test_round(
+                    session_clone,
+                    fragmentation_msg.packet_size,
+                    fragmentation_msg.out_of_order,
+                    fragmentation_msg.collect_timeout_ms,
+                )
+                .await;
+            });
+        }
+
+        common::ControlMessage::StartNatLifetimeTest(nat_lifetime_msg) => {
+            if nat_lifetime_msg.conn_id != session.conn_id {
+                tracing::warn!(
+                    "StartNatLifetimeTestMessage conn_id mismatch: received '{}' but session {} expects '{}', ignoring",
+                    nat_lifetime_msg.conn_id, session.id, session.conn_id
+                );
+                return;
+            }
+
+            // Update survey session ID if provided
+            if !nat_lifetime_msg.survey_session_id.is_empty() {
+                let mut survey_id = session.survey_session_id.write().await;
+                *survey_id = nat_lifetime_msg.survey_session_id.clone();
+            }
+
+            tracing::info!(
+                "Received NAT lifetime test request for session {} with idle periods {}..{}ms",
+                session.id,
+                nat_lifetime_msg.min_idle_ms,
+                nat_lifetime_msg.max_idle_ms
+            );
+
+            let session_clone = session.clone();
+            tokio::spawn(async move {
+                measurements::run_nat_lifetime_test(
+                    session_clone,
+                    nat_lifetime_msg.min_idle_ms,
+                    nat_lifetime_msg.max_idle_ms,
+                    nat_lifetime_msg.resolution_ms,
+                )
+                .await;
+            });
+        }
+
+        common::ControlMessage::StartNatBehaviorTest(nat_behavior_msg) => {
+            if nat_behavior_msg.conn_id != session.conn_id {
+                tracing::warn!(
+                    "StartNatBehaviorTestMessage conn_id mismatch: received '{}' but session {} expects '{}', ignoring",
+                    nat_behavior_msg.conn_id, session.id, session.conn_id
+                );
+                return;
+            }
+
+            // Update survey session ID if provided
+            if !nat_behavior_msg.survey_session_id.is_empty() {
+                let mut survey_id = session.survey_session_id.write().await;
+                *survey_id = nat_behavior_msg.survey_session_id.clone();
+            }
+
+            tracing::info!(
+                "Received NAT behavior test request for session {} with {} mapped addresses",
+                session.id,
+                nat_behavior_msg.mapped_addresses.len()
+            );
+
+            let session_clone = session.clone();
+            tokio::spawn(async move {
+                measurements::run_nat_behavior_test(session_clone, nat_behavior_msg).await;
+            });
+        }
+
         common::ControlMessage::GetMeasuringTime(get_time_msg) => {
             if get_time_msg.conn_id != session.conn_id {
                 tracing::warn!(
@@ -424,22 +578,17 @@ async fn handle_control_message(session: Arc<ClientSession>, msg: DataChannelMes
                 session.id
             );
 
-            // Determine the max measuring time based on magic key configuration
-            let max_duration_ms = {
-                let magic_key = session.magic_key.read().await;
-                if let (Some(ref config), Some(ref key)) =
-                    (&session.magic_key_config, magic_key.as_ref())
-                {
-                    let seconds = config.get_max_measuring_time_seconds(key);
+            // Determine the max measuring time based on the magic key
+            let max_duration_ms = match max_measuring_time_seconds(&session).await {
+                Some((key, seconds)) => {
                     tracing::info!(
                         "Max measuring time for magic key '{}': {}s",
                         key,
                         seconds
                     );
                     seconds * 1000
-                } else {
-                    DEFAULT_MEASURING_TIME_MS
                 }
+                None => DEFAULT_MEASURING_TIME_MS,
             };
 
             // Send back the measuring time response
@@ -577,17 +726,9 @@ async fn handle_control_message(session: Arc<ClientSession>, msg: DataChannelMes
                 start_msg.survey_session_id
             );
 
-            // Set probe_streams_active flag
-            {
-                let mut state = session.measurement_state.write().await;
-                state.probe_streams_active = true;
-                state.probe_streams_started_at = Some(std::time::Instant::now());
-                // Determine max measuring duration from magic key config
-                let magic_key = session.magic_key.read().await;
-                state.max_measuring_duration = if let (Some(ref config), Some(ref key)) =
-                    (&session.magic_key_config, magic_key.as_ref())
-                {
-                    let seconds = config.get_max_measuring_time_seconds(key);
+            // Determine max measuring duration from the magic key (before taking the state lock)
+            let max_measuring_duration = match max_measuring_time_seconds(&session).await {
+                Some((key, seconds)) => {
                     tracing::info!(
                         "Setting max measuring duration for session {} (magic key '{}'): {}s",
                         session.id,
@@ -595,11 +736,19 @@ async fn handle_control_message(session: Arc<ClientSession>, msg: DataChannelMes
                         seconds
                     );
                     Some(std::time::Duration::from_secs(seconds))
-                } else {
-                    None
-                };
+                }
+                None => None,
+            };
+
+            // Set probe_streams_active flag
+            {
+                let mut state = session.measurement_state.write().await;
+                state.probe_streams_active = true;
+                state.probe_streams_started_at = Some(std::time::Instant::now());
+                state.max_measuring_duration = max_measuring_duration;
                 // Clear previous probe data for fresh measurement
                 state.measurement_probe_seq = 0;
+                state.sent_measurement_probes.clear();
                 state.received_measurement_probes.clear();
                 state.probe_stats.clear();
                 // Reset baseline calculation for fresh measurement
@@ -719,6 +868,10 @@ async fn handle_control_message(session: Arc<ClientSession>, msg: DataChannelMes
         common::ControlMessage::ServerSideReady(_)
         | common::ControlMessage::TraceHop(_)
         | common::ControlMessage::MtuHop(_)
+        | common::ControlMessage::ExtHeaderTestResult(_)
+        | common::ControlMessage::FragmentationTestResult(_)
+        | common::ControlMessage::NatLifetimeTestResult(_)
+        | common::ControlMessage::NatBehaviorResult(_)
         | common::ControlMessage::MeasuringTimeResponse(_) => {
             tracing::warn!(
                 "Received unexpected server-to-client message type on control channel for session {}",
@@ -732,77 +885,18 @@ async fn handle_testprobe_message(session: Arc<ClientSession>, msg: DataChannelM
     measurements::handle_testprobe_packet(session, msg).await;
 }
 
-/// Extract magic key from survey session ID format: "survey_{magic_key}_{timestamp}_{uuid}"
-///
-/// Magic key hyphens are encoded as underscores in the session ID, so we reconstruct
-/// them by joining the middle parts with hyphens.
-fn extract_magic_key_from_session_id(session_id: &str) -> Option<String> {
-    if !session_id.starts_with("survey_") {
-        return None;
-    }
-    let parts: Vec<&str> = session_id.split('_').collect();
-    if parts.len() < 4 {
-        return None;
-    }
-    // Parts: ["survey", key_part1, ..., key_partN, timestamp, uuid]
-    // Verify the second-to-last part is a valid timestamp (numeric)
-    let timestamp_idx = parts.len() - 2;
-    if parts[timestamp_idx].parse::<u64>().is_err() {
-        return None;
-    }
-    let magic_key_parts = &parts[1..timestamp_idx];
-    if magic_key_parts.is_empty() {
-        return None;
-    }
-    Some(magic_key_parts.join("-"))
+/// Maximum measuring time in seconds for the session's magic key, from the magic key
+/// store or else the magic key configuration. None if the session has no magic key.
+async fn max_measuring_time_seconds(session: &ClientSession) -> Option<(String, u64)> {
+    let magic_key = session.magic_key.read().await.clone()?;
+    let seconds = if let Some(store) = &session.magic_key_store {
+        store.max_measuring_time_seconds(&magic_key).await
+    } else {
+        session
+            .magic_key_config
+            .as_ref()?
+            .get_max_measuring_time_seconds(&magic_key)
+    };
+    Some((magic_key, seconds))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_extract_magic_key_simple() {
-        let session_id = "survey_MYKEY_1234567890_a1b2c3d4-e5f6-7890-abcd-ef1234567890";
-        assert_eq!(
-            extract_magic_key_from_session_id(session_id),
-            Some("MYKEY".to_string())
-        );
-    }
-
-    #[test]
-    fn test_extract_magic_key_with_hyphens() {
-        // Magic key "SURVEY-001" is encoded as "SURVEY_001" in the session ID
-        let session_id = "survey_SURVEY_001_1234567890_a1b2c3d4-e5f6-7890-abcd-ef1234567890";
-        assert_eq!(
-            extract_magic_key_from_session_id(session_id),
-            Some("SURVEY-001".to_string())
-        );
-    }
-
-    #[test]
-    fn test_extract_magic_key_multi_part() {
-        let session_id =
-            "survey_MY_LONG_KEY_1234567890_a1b2c3d4-e5f6-7890-abcd-ef1234567890";
-        assert_eq!(
-            extract_magic_key_from_session_id(session_id),
-            Some("MY-LONG-KEY".to_string())
-        );
-    }
-
-    #[test]
-    fn test_extract_magic_key_invalid_format() {
-        assert_eq!(extract_magic_key_from_session_id("not_a_survey_id"), None);
-        assert_eq!(extract_magic_key_from_session_id("survey_"), None);
-        assert_eq!(extract_magic_key_from_session_id(""), None);
-    }
-
-    #[test]
-    fn test_extract_magic_key_no_timestamp() {
-        // If the second-to-last part is not numeric, it should fail
-        assert_eq!(
-            extract_magic_key_from_session_id("survey_KEY_notanumber_uuid"),
-            None
-        );
-    }
-}
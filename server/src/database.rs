@@ -1,7 +1,8 @@
 //! Database module for survey data persistence
 //!
 //! Provides SQLite database initialization and connection management for
-//! storing survey sessions, metrics, and recording metadata.
+//! storing survey sessions, metrics, recording metadata, iperf3 test results,
+//! role assignments and magic keys.
 
 use rusqlite::Connection;
 use std::path::Path;
@@ -38,6 +39,41 @@ pub fn init_database(db_path: &Path) -> Result<DbConnection, Box<dyn std::error:
     // Run migrations
     let schema_sql = include_str!("../migrations/001_survey_upload_schema.sql");
     conn.execute_batch(schema_sql)?;
+    let iperf3_sql = include_str!("../migrations/002_iperf3_tests.sql");
+    conn.execute_batch(iperf3_sql)?;
+    let roles_sql = include_str!("../migrations/003_roles.sql");
+    conn.execute_batch(roles_sql)?;
+    let magic_keys_sql = include_str!("../migrations/004_magic_keys.sql");
+    conn.execute_batch(magic_keys_sql)?;
+    let survey_tokens_sql = include_str!("../migrations/005_survey_tokens.sql");
+    conn.execute_batch(survey_tokens_sql)?;
+    let user_sessions_sql = include_str!("../migrations/006_user_sessions.sql");
+    conn.execute_batch(user_sessions_sql)?;
+    let api_tokens_sql = include_str!("../migrations/007_api_tokens.sql");
+    conn.execute_batch(api_tokens_sql)?;
+    let totp_sql = include_str!("../migrations/008_totp.sql");
+    conn.execute_batch(totp_sql)?;
+    let local_users_sql = include_str!("../migrations/009_local_users.sql");
+    conn.execute_batch(local_users_sql)?;
+    let login_attempts_sql = include_str!("../migrations/010_login_attempts.sql");
+    conn.execute_batch(login_attempts_sql)?;
+    let audit_log_sql = include_str!("../migrations/011_audit_log.sql");
+    conn.execute_batch(audit_log_sql)?;
+    let traceroute_hops_sql = include_str!("../migrations/012_traceroute_hops.sql");
+    conn.execute_batch(traceroute_hops_sql)?;
+    let reverse_path_sql = include_str!("../migrations/013_reverse_path.sql");
+    conn.execute_batch(reverse_path_sql)?;
+
+    let ext_header_tests_sql = include_str!("../migrations/014_ext_header_tests.sql");
+    conn.execute_batch(ext_header_tests_sql)?;
+    let fragmentation_tests_sql = include_str!("../migrations/015_fragmentation_tests.sql");
+    conn.execute_batch(fragmentation_tests_sql)?;
+    let nat_lifetime_tests_sql = include_str!("../migrations/016_nat_lifetime_tests.sql");
+    conn.execute_batch(nat_lifetime_tests_sql)?;
+    let nat_behavior_tests_sql = include_str!("../migrations/017_nat_behavior_tests.sql");
+    conn.execute_batch(nat_behavior_tests_sql)?;
+    let websocket_probe_metrics_sql = include_str!("../migrations/018_websocket_probe_metrics.sql");
+    conn.execute_batch(websocket_probe_metrics_sql)?;
 
     Ok(Arc::new(Mutex::new(conn)))
 }
@@ -68,6 +104,26 @@ mod tests {
         assert!(tables.contains(&"survey_sessions".to_string()));
         assert!(tables.contains(&"survey_metrics".to_string()));
         assert!(tables.contains(&"recordings".to_string()));
+        assert!(tables.contains(&"iperf3_tests".to_string()));
+        assert!(tables.contains(&"role_assignments".to_string()));
+        assert!(tables.contains(&"magic_keys".to_string()));
+        assert!(tables.contains(&"survey_tokens".to_string()));
+        assert!(tables.contains(&"user_sessions".to_string()));
+        assert!(tables.contains(&"api_tokens".to_string()));
+        assert!(tables.contains(&"totp_enrollments".to_string()));
+        assert!(tables.contains(&"totp_recovery_codes".to_string()));
+        assert!(tables.contains(&"local_users".to_string()));
+        assert!(tables.contains(&"login_attempts".to_string()));
+        assert!(tables.contains(&"login_lockouts".to_string()));
+        assert!(tables.contains(&"audit_log".to_string()));
+        assert!(tables.contains(&"traceroute_hops".to_string()));
+        assert!(tables.contains(&"survey_peers".to_string()));
+        assert!(tables.contains(&"reverse_path_metrics".to_string()));
+        assert!(tables.contains(&"ext_header_tests".to_string()));
+        assert!(tables.contains(&"fragmentation_tests".to_string()));
+        assert!(tables.contains(&"nat_lifetime_tests".to_string()));
+        assert!(tables.contains(&"nat_behavior_tests".to_string()));
+        assert!(tables.contains(&"websocket_probe_metrics".to_string()));
     }
 
     #[tokio::test]
@@ -0,0 +1,490 @@
+//! Offline ASN, prefix and geolocation enrichment of IP addresses
+//!
+//! Traceroute hops and client peer addresses are annotated from local
+//! datasets, without network lookups at runtime:
+//! - a prefix table: ip2asn (`range_start range_end asn country name`) or
+//!   RouteViews pfx2as (`prefix length asn`), tab separated
+//! - a MaxMind DB (GeoLite2/GeoIP2 City or Country) for country and city;
+//!   an ASN database also fills in the ASN when the prefix table has none
+//!
+//! The files are loaded at startup and reloaded when their modification
+//! time changes.
+
+use crate::config::EnrichmentConfig;
+use common::IpEnrichment;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+type EnrichmentResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Address as an integer in the IPv6 space, with its bit length
+fn ip_bits(ip: IpAddr) -> (u128, u8) {
+    match ip {
+        IpAddr::V4(v4) => (u32::from(v4) as u128, 32),
+        IpAddr::V6(v6) => (u128::from(v6), 128),
+    }
+}
+
+fn ip_from_bits(bits: u128, width: u8) -> IpAddr {
+    if width == 32 {
+        IpAddr::V4((bits as u32).into())
+    } else {
+        IpAddr::V6(bits.into())
+    }
+}
+
+/// Network mask of a prefix length within an address of `width` bits
+fn prefix_mask(len: u8, width: u8) -> u128 {
+    if len == 0 {
+        0
+    } else {
+        (u128::MAX << (128 - len as u32)) >> (128 - width as u32)
+    }
+}
+
+/// An ip2asn address range
+#[derive(Debug, Clone)]
+struct AsnRange {
+    start: u128,
+    end: u128,
+    asn: u32,
+    as_name: Option<String>,
+}
+
+/// Routing table of origin ASNs by address range or prefix
+#[derive(Debug, Default)]
+pub struct PrefixTable {
+    /// Non-overlapping ranges per address width, sorted by start
+    ranges_v4: Vec<AsnRange>,
+    ranges_v6: Vec<AsnRange>,
+    /// Origin ASN by (network, prefix length, address width), for longest prefix match
+    prefixes: HashMap<(u128, u8, u8), u32>,
+}
+
+impl PrefixTable {
+    /// Load an ip2asn or pfx2as table; malformed lines are skipped
+    pub fn load(path: &Path) -> EnrichmentResult<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut table = Self::default();
+        let mut skipped = 0;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if !table.add_line(line) {
+                skipped += 1;
+            }
+        }
+        if skipped > 0 {
+            tracing::debug!("Skipped {} malformed prefix table lines", skipped);
+        }
+        table.ranges_v4.sort_by_key(|range| range.start);
+        table.ranges_v6.sort_by_key(|range| range.start);
+        table
+    }
+
+    fn add_line(&mut self, line: &str) -> bool {
+        let fields: Vec<&str> = if line.contains('\t') {
+            line.split('\t').collect()
+        } else {
+            line.split_whitespace().collect()
+        };
+        // pfx2as may list several origins ("1234_5678" or "1234,5678"); the first is used
+        let parse_asn =
+            |field: &str| -> Option<u32> { field.split(['_', ',']).next()?.trim().parse().ok() };
+
+        // "192.0.2.0/24 asn"
+        if let Some((network, len)) = fields.first().and_then(|f| f.split_once('/')) {
+            let (Ok(network), Ok(len)) = (network.parse::<IpAddr>(), len.parse::<u8>()) else {
+                return false;
+            };
+            let Some(asn) = fields.get(1).and_then(|f| parse_asn(f)) else {
+                return false;
+            };
+            return self.add_prefix(network, len, asn);
+        }
+
+        let (Some(first), Some(second)) = (fields.first(), fields.get(1)) else {
+            return false;
+        };
+        let Ok(first) = first.parse::<IpAddr>() else {
+            return false;
+        };
+        if let Ok(end) = second.parse::<IpAddr>() {
+            // ip2asn: start, end, asn, country, name
+            let Some(asn) = fields.get(2).and_then(|f| parse_asn(f)) else {
+                return false;
+            };
+            if asn == 0 || first.is_ipv4() != end.is_ipv4() {
+                // ASN 0 marks unrouted space
+                return asn == 0;
+            }
+            let as_name = fields
+                .get(4)
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty() && *name != "Not routed")
+                .map(str::to_string);
+            let (start, width) = ip_bits(first);
+            let (end, _) = ip_bits(end);
+            let range = AsnRange {
+                start,
+                end,
+                asn,
+                as_name,
+            };
+            if width == 32 {
+                self.ranges_v4.push(range);
+            } else {
+                self.ranges_v6.push(range);
+            }
+            return true;
+        }
+        // pfx2as: network, length, asn
+        let (Ok(len), Some(asn)) = (
+            second.parse::<u8>(),
+            fields.get(2).and_then(|f| parse_asn(f)),
+        ) else {
+            return false;
+        };
+        self.add_prefix(first, len, asn)
+    }
+
+    fn add_prefix(&mut self, network: IpAddr, len: u8, asn: u32) -> bool {
+        let (bits, width) = ip_bits(network);
+        if len > width {
+            return false;
+        }
+        self.prefixes
+            .insert((bits & prefix_mask(len, width), len, width), asn);
+        true
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.ranges_v4.len() + self.ranges_v6.len() + self.prefixes.len()
+    }
+
+    /// Origin ASN, AS name and prefix of an address
+    pub fn lookup(&self, ip: IpAddr) -> Option<(u32, Option<String>, String)> {
+        let (bits, width) = ip_bits(ip);
+
+        if !self.prefixes.is_empty() {
+            for len in (0..=width).rev() {
+                let network = bits & prefix_mask(len, width);
+                if let Some(asn) = self.prefixes.get(&(network, len, width)) {
+                    let prefix = format!("{}/{}", ip_from_bits(network, width), len);
+                    return Some((*asn, None, prefix));
+                }
+            }
+        }
+
+        let ranges = if width == 32 {
+            &self.ranges_v4
+        } else {
+            &self.ranges_v6
+        };
+        let index = ranges.partition_point(|range| range.start <= bits);
+        let range = ranges[..index].last().filter(|range| bits <= range.end)?;
+        Some((
+            range.asn,
+            range.as_name.clone(),
+            range_prefix(range.start, range.end, bits, width),
+        ))
+    }
+}
+
+/// The largest CIDR prefix within a range that contains an address
+///
+/// ip2asn ranges need not be aligned to a single prefix.
+fn range_prefix(start: u128, end: u128, bits: u128, width: u8) -> String {
+    for len in 0..=width {
+        let mask = prefix_mask(len, width);
+        let network = bits & mask;
+        let broadcast = network | (!mask & prefix_mask(width, width));
+        if network >= start && broadcast <= end {
+            return format!("{}/{}", ip_from_bits(network, width), len);
+        }
+    }
+    format!("{}/{}", ip_from_bits(bits, width), width)
+}
+
+/// Geolocation and ASN records of a MaxMind DB
+pub struct GeoDatabase {
+    reader: maxminddb::Reader<Vec<u8>>,
+}
+
+impl GeoDatabase {
+    pub fn load(path: &Path) -> EnrichmentResult<Self> {
+        Ok(Self {
+            reader: maxminddb::Reader::open_readfile(path)?,
+        })
+    }
+
+    /// Fill in country, city and, if still unknown, the ASN of an address
+    fn enrich(&self, ip: IpAddr, enrichment: &mut IpEnrichment) {
+        if let Ok(city) = self.reader.lookup::<maxminddb::geoip2::City>(ip) {
+            enrichment.country = city
+                .country
+                .and_then(|country| country.iso_code)
+                .map(str::to_string);
+            enrichment.city = city
+                .city
+                .and_then(|city| city.names)
+                .and_then(|names| names.get("en").map(|name| name.to_string()));
+        }
+        if enrichment.asn.is_none() {
+            if let Ok((asn, len)) = self.reader.lookup_prefix::<maxminddb::geoip2::Asn>(ip) {
+                if let Some(number) = asn.autonomous_system_number {
+                    let (bits, width) = ip_bits(ip);
+                    enrichment.asn = Some(number);
+                    enrichment.as_name = asn.autonomous_system_organization.map(str::to_string);
+                    enrichment.prefix = Some(format!(
+                        "{}/{}",
+                        ip_from_bits(bits & prefix_mask(len as u8, width), width),
+                        len
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// A dataset file with the modification time it was loaded at
+struct Dataset<T> {
+    path: Option<String>,
+    modified: Option<SystemTime>,
+    data: Option<Arc<T>>,
+    /// Whether the file being unreadable was logged already
+    unreadable: bool,
+}
+
+impl<T> Dataset<T> {
+    fn new(path: Option<String>) -> Self {
+        Self {
+            path,
+            modified: None,
+            data: None,
+            unreadable: false,
+        }
+    }
+}
+
+/// Enrichment of addresses from the configured datasets
+pub struct EnrichmentService {
+    prefix_table: RwLock<Dataset<PrefixTable>>,
+    geo_database: RwLock<Dataset<GeoDatabase>>,
+    refresh_interval: Duration,
+}
+
+impl EnrichmentService {
+    /// Create the service and load the configured datasets
+    pub fn new(config: &EnrichmentConfig) -> Self {
+        let service = Self {
+            prefix_table: RwLock::new(Dataset::new(config.asn_table_path.clone())),
+            geo_database: RwLock::new(Dataset::new(config.geo_database_path.clone())),
+            refresh_interval: Duration::from_secs(config.refresh_interval_secs.max(1)),
+        };
+        service.refresh();
+        service
+    }
+
+    /// Reload the datasets whose files changed since they were loaded
+    pub fn refresh(&self) {
+        reload_if_changed(
+            &self.prefix_table,
+            "prefix table",
+            PrefixTable::load,
+            |table| table.entry_count().to_string(),
+        );
+        reload_if_changed(
+            &self.geo_database,
+            "geolocation database",
+            GeoDatabase::load,
+            |db| db.reader.metadata.node_count.to_string(),
+        );
+    }
+
+    /// Check the dataset files for changes in the background
+    pub fn spawn_refresh(self: &Arc<Self>) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(service.refresh_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let service = service.clone();
+                if let Err(e) = tokio::task::spawn_blocking(move || service.refresh()).await {
+                    tracing::error!("Enrichment dataset refresh failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// ASN, prefix and location of an address; empty fields when unknown
+    pub fn lookup(&self, ip: IpAddr) -> IpEnrichment {
+        let prefix_table = self.prefix_table.read().unwrap().data.clone();
+        let geo_database = self.geo_database.read().unwrap().data.clone();
+
+        let mut enrichment = IpEnrichment::default();
+        if let Some((asn, as_name, prefix)) = prefix_table.and_then(|table| table.lookup(ip)) {
+            enrichment.asn = Some(asn);
+            enrichment.as_name = as_name;
+            enrichment.prefix = Some(prefix);
+        }
+        if let Some(geo_database) = geo_database {
+            geo_database.enrich(ip, &mut enrichment);
+        }
+        enrichment
+    }
+
+    /// Enrichment of an address string, if it parses and anything is known about it
+    pub fn lookup_str(&self, address: &str) -> Option<IpEnrichment> {
+        let ip: IpAddr = address.parse().ok()?;
+        Some(self.lookup(ip)).filter(|enrichment| !enrichment.is_empty())
+    }
+}
+
+/// Load a dataset if its file is new or changed; keeps the old data on errors
+fn reload_if_changed<T>(
+    dataset: &RwLock<Dataset<T>>,
+    name: &str,
+    load: impl Fn(&Path) -> EnrichmentResult<T>,
+    describe: impl Fn(&T) -> String,
+) {
+    let (path, loaded_modified) = {
+        let dataset = dataset.read().unwrap();
+        match &dataset.path {
+            Some(path) => (path.clone(), dataset.modified),
+            None => return,
+        }
+    };
+    let modified = match std::fs::metadata(&path).and_then(|m| m.modified()) {
+        Ok(modified) => modified,
+        Err(e) => {
+            let mut dataset = dataset.write().unwrap();
+            if !dataset.unreadable {
+                tracing::warn!("Cannot read {} {}: {}", name, path, e);
+                dataset.unreadable = true;
+            }
+            return;
+        }
+    };
+    if loaded_modified == Some(modified) {
+        return;
+    }
+
+    match load(Path::new(&path)) {
+        Ok(data) => {
+            tracing::info!("Loaded {} {} ({} entries)", name, path, describe(&data));
+            let mut dataset = dataset.write().unwrap();
+            dataset.data = Some(Arc::new(data));
+            dataset.modified = Some(modified);
+            dataset.unreadable = false;
+        }
+        Err(e) => {
+            tracing::error!("Failed to load {} {}: {}", name, path, e);
+            // Do not retry until the file changes again
+            let mut dataset = dataset.write().unwrap();
+            dataset.modified = Some(modified);
+            dataset.unreadable = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    const IP2ASN: &str = "\
+1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET
+1.0.4.0\t1.0.7.255\t38803\tAU\tWPL-AS-AP Wirefreebroadband Pty Ltd
+1.0.8.0\t1.0.15.255\t0\tNone\tNot routed
+2001:db8::\t2001:db8:ffff:ffff:ffff:ffff:ffff:ffff\t64496\tZZ\tEXAMPLE-V6
+";
+
+    const PFX2AS: &str = "\
+10.0.0.0\t8\t64500
+10.1.0.0\t16\t64501_64502
+2001:db8::\t32\t64503
+";
+
+    #[test]
+    fn test_ip2asn_ranges() {
+        let table = PrefixTable::parse(IP2ASN);
+        assert_eq!(table.entry_count(), 3);
+
+        let (asn, as_name, prefix) = table.lookup("1.0.0.1".parse().unwrap()).unwrap();
+        assert_eq!(asn, 13335);
+        assert_eq!(as_name.as_deref(), Some("CLOUDFLARENET"));
+        assert_eq!(prefix, "1.0.0.0/24");
+
+        let (asn, _, prefix) = table.lookup("1.0.6.9".parse().unwrap()).unwrap();
+        assert_eq!(asn, 38803);
+        assert_eq!(prefix, "1.0.4.0/22");
+
+        let (asn, _, prefix) = table.lookup("2001:db8::1".parse().unwrap()).unwrap();
+        assert_eq!(asn, 64496);
+        assert_eq!(prefix, "2001:db8::/32");
+
+        // Unrouted and unlisted space
+        assert!(table.lookup("1.0.9.1".parse().unwrap()).is_none());
+        assert!(table.lookup("8.8.8.8".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_pfx2as_longest_prefix_match() {
+        let table = PrefixTable::parse(PFX2AS);
+
+        let (asn, as_name, prefix) = table.lookup("10.1.2.3".parse().unwrap()).unwrap();
+        assert_eq!(asn, 64501);
+        assert_eq!(as_name, None);
+        assert_eq!(prefix, "10.1.0.0/16");
+
+        let (asn, _, prefix) = table.lookup("10.2.2.3".parse().unwrap()).unwrap();
+        assert_eq!(asn, 64500);
+        assert_eq!(prefix, "10.0.0.0/8");
+
+        let (asn, _, _) = table.lookup("2001:db8:1::1".parse().unwrap()).unwrap();
+        assert_eq!(asn, 64503);
+        assert!(table.lookup("192.0.2.1".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_service_reloads_changed_table() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "{}", PFX2AS).unwrap();
+        let config = EnrichmentConfig {
+            asn_table_path: Some(file.path().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let service = EnrichmentService::new(&config);
+
+        let enrichment = service.lookup_str("10.1.2.3").unwrap();
+        assert_eq!(enrichment.asn, Some(64501));
+        assert_eq!(enrichment.country, None);
+        assert_eq!(service.lookup_str("192.0.2.1"), None);
+        assert_eq!(service.lookup_str("not an address"), None);
+
+        // Rewrite the file with a different modification time
+        std::fs::write(file.path(), "192.0.2.0\t24\t64510\n").unwrap();
+        let modified = SystemTime::now() + Duration::from_secs(10);
+        std::fs::File::options()
+            .write(true)
+            .open(file.path())
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        service.refresh();
+
+        assert_eq!(service.lookup_str("192.0.2.1").unwrap().asn, Some(64510));
+        assert_eq!(service.lookup_str("10.1.2.3"), None);
+    }
+}
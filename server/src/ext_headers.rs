@@ -0,0 +1,171 @@
+//! IPv6 extension header survivability tests (RFC 9098)
+//!
+//! Many networks drop IPv6 packets carrying extension headers, Hop-by-Hop
+//! Options headers in particular. A test sends testprobes with a Hop-by-Hop or
+//! Destination Options header of a given size to the client, which echoes the
+//! ones it receives. With a TTL walk, probes are also sent with increasing
+//! TTLs: the Time Exceeded errors show how far they get, and routers rejecting
+//! them may say so with a Parameter Problem or Destination Unreachable.
+//!
+//! ICMPv6 errors quote at most 1280 bytes of the probe, so with headers of
+//! more than about a kilobyte the UDP header is not quoted and the errors
+//! cannot be correlated; such tests only tell whether the probes got through.
+
+use common::{ExtHeaderHop, ExtHeaderTestResultMessage, Ipv6ExtHeaderType, TrackedPacketEvent};
+
+/// Number of probes sent without a TTL per test
+pub const END_TO_END_PROBES: u32 = 3;
+
+const ICMPV6_DESTINATION_UNREACHABLE: u8 = 1;
+const ICMPV6_TIME_EXCEEDED: u8 = 3;
+const ICMPV6_PARAMETER_PROBLEM: u8 = 4;
+
+/// Hop-by-Hop and Destination Options header sizes to send for a test
+pub fn header_lengths(header_type: Ipv6ExtHeaderType, size: u16) -> (Option<u16>, Option<u16>) {
+    match header_type {
+        Ipv6ExtHeaderType::HopByHop => (Some(size), None),
+        Ipv6ExtHeaderType::DestinationOptions => (None, Some(size)),
+    }
+}
+
+/// Whether a packet was sent with an extension header
+pub fn has_ext_header(send_options: &common::SendOptions) -> bool {
+    send_options.ipv6_hop_by_hop_len.is_some() || send_options.ipv6_dest_opts_len.is_some()
+}
+
+/// ICMPv6 error of a tracked extension header test probe, if it is one
+///
+/// Raw ICMPv6 sockets receive the errors without the IPv6 header, so the
+/// type and code are the first two bytes.
+pub fn hop_from_event(event: &TrackedPacketEvent) -> Option<ExtHeaderHop> {
+    if !has_ext_header(&event.send_options) || event.icmp_packet.len() < 2 {
+        return None;
+    }
+    let rtt = event.icmp_received_at.duration_since(event.sent_at);
+
+    Some(ExtHeaderHop {
+        hop: event.send_options.ttl,
+        ip_address: event.router_ip.clone(),
+        rtt_ms: rtt.as_secs_f64() * 1000.0,
+        icmp_type: event.icmp_packet[0],
+        icmp_code: event.icmp_packet[1],
+    })
+}
+
+/// Work out from the echoes and ICMPv6 errors whether and where the probes
+/// were dropped
+///
+/// `echoed_ttls` are the TTLs of the TTL walk probes the client echoed. When
+/// no probe got through, the drop is placed at the first hop that rejected a
+/// probe explicitly, or else right after the last hop that returned Time
+/// Exceeded (routers not sending ICMPv6 at all make this an upper bound).
+pub fn conclude(result: &mut ExtHeaderTestResultMessage, echoed_ttls: &[u8], ttl_walk: bool) {
+    result.reached_at_ttl = echoed_ttls.iter().copied().min();
+    result.delivered = result.probes_delivered > 0 || result.reached_at_ttl.is_some();
+    result.last_responding_hop = result
+        .hops
+        .iter()
+        .filter(|hop| hop.icmp_type == ICMPV6_TIME_EXCEEDED)
+        .filter_map(|hop| hop.hop)
+        .filter(|&hop| result.reached_at_ttl.is_none_or(|reached| hop < reached))
+        .max();
+
+    let rejected_at = result
+        .hops
+        .iter()
+        .filter(|hop| {
+            matches!(
+                hop.icmp_type,
+                ICMPV6_PARAMETER_PROBLEM | ICMPV6_DESTINATION_UNREACHABLE
+            )
+        })
+        .filter_map(|hop| hop.hop)
+        .min();
+
+    result.drop_hop = if result.delivered || !ttl_walk {
+        None
+    } else {
+        rejected_at.or(result.last_responding_hop.map(|hop| hop.saturating_add(1)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(probes_delivered: u32, hops: Vec<ExtHeaderHop>) -> ExtHeaderTestResultMessage {
+        ExtHeaderTestResultMessage {
+            conn_id: String::new(),
+            survey_session_id: String::new(),
+            header_type: Ipv6ExtHeaderType::HopByHop,
+            header_size: 8,
+            probes_sent: END_TO_END_PROBES,
+            probes_delivered,
+            delivered: false,
+            reached_at_ttl: None,
+            last_responding_hop: None,
+            drop_hop: None,
+            hops,
+            error: None,
+        }
+    }
+
+    fn hop(hop: u8, icmp_type: u8) -> ExtHeaderHop {
+        ExtHeaderHop {
+            hop: Some(hop),
+            ip_address: Some(format!("2001:db8::{}", hop)),
+            rtt_ms: hop as f64,
+            icmp_type,
+            icmp_code: 0,
+        }
+    }
+
+    #[test]
+    fn test_delivered() {
+        let hops = (1..6).map(|ttl| hop(ttl, ICMPV6_TIME_EXCEEDED)).collect();
+        let mut result = result(3, hops);
+        conclude(&mut result, &[6, 7, 8], true);
+
+        assert!(result.delivered);
+        assert_eq!(result.reached_at_ttl, Some(6));
+        assert_eq!(result.last_responding_hop, Some(5));
+        assert_eq!(result.drop_hop, None);
+    }
+
+    #[test]
+    fn test_dropped_silently() {
+        // Hops 1-3 forward the probes, nothing is heard of them after that
+        let hops = vec![
+            hop(1, ICMPV6_TIME_EXCEEDED),
+            hop(3, ICMPV6_TIME_EXCEEDED),
+            hop(2, ICMPV6_TIME_EXCEEDED),
+        ];
+        let mut result = result(0, hops);
+        conclude(&mut result, &[], true);
+
+        assert!(!result.delivered);
+        assert_eq!(result.last_responding_hop, Some(3));
+        assert_eq!(result.drop_hop, Some(4));
+
+        // Without a TTL walk there is no telling where
+        let mut result = self::result(0, Vec::new());
+        conclude(&mut result, &[], false);
+        assert!(!result.delivered);
+        assert_eq!(result.drop_hop, None);
+    }
+
+    #[test]
+    fn test_rejected() {
+        let hops = vec![
+            hop(1, ICMPV6_TIME_EXCEEDED),
+            hop(2, ICMPV6_TIME_EXCEEDED),
+            hop(3, ICMPV6_PARAMETER_PROBLEM),
+            hop(4, ICMPV6_PARAMETER_PROBLEM),
+        ];
+        let mut result = result(0, hops);
+        conclude(&mut result, &[], true);
+
+        assert_eq!(result.last_responding_hop, Some(2));
+        assert_eq!(result.drop_hop, Some(3));
+    }
+}
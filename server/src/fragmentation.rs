@@ -0,0 +1,121 @@
+//! IP fragmentation delivery tests
+//!
+//! Firewalls dropping IP fragments break DNS over UDP and many VPNs. A test
+//! sends testprobes larger than the path MTU, which the kernel fragments as it
+//! is allowed to (DF clear on IPv4, Fragment headers on IPv6), and counts how
+//! many the client reassembles and echoes. In out of order mode the fragments
+//! are built by the server and sent last one first, as some middleboxes only
+//! cope with fragments arriving in order.
+//!
+//! The probes go through DTLS and SCTP like any testprobe, so their size is
+//! only approximately the requested one: the DTLS overhead depends on the
+//! cipher suite.
+
+use common::{FragmentationIcmpError, SendOptions, TrackedPacketEvent};
+
+/// Number of probes sent per test
+pub const PROBES: u32 = 3;
+
+/// Payload bytes per fragment of the out of order probes, small enough for
+/// the fragments to fit any IPv6 path and most IPv4 ones
+pub const OUT_OF_ORDER_FRAGMENT_SIZE: u16 = 512;
+
+/// UDP (8), SCTP common and DATA chunk headers (28), and DTLS 1.2 record
+/// header with AES-GCM nonce and tag (37)
+const PROBE_OVERHEAD: usize = 8 + 28 + 37;
+
+/// Whether a packet was sent as a fragmentation test probe
+///
+/// All other testprobes are sent with DF set.
+pub fn is_fragmentation_probe(send_options: &SendOptions) -> bool {
+    send_options.out_of_order_fragment_size.is_some() || send_options.df_bit == Some(false)
+}
+
+/// Length to pad a probe's JSON to for IP packets of `packet_size` bytes
+pub fn padded_length(packet_size: u32, ipv6: bool) -> usize {
+    let ip_header = if ipv6 { 40 } else { 20 };
+    (packet_size as usize).saturating_sub(ip_header + PROBE_OVERHEAD)
+}
+
+/// ICMP error of a tracked fragmentation test probe, if it is one
+///
+/// Raw ICMP sockets receive the errors with the IPv4 header and raw ICMPv6
+/// ones without the IPv6 header, whose first byte (the ICMPv6 type of an
+/// error) never has version 4 in its high nibble.
+pub fn icmp_error_from_event(event: &TrackedPacketEvent) -> Option<FragmentationIcmpError> {
+    if !is_fragmentation_probe(&event.send_options) {
+        return None;
+    }
+    let packet = &event.icmp_packet;
+    let offset = match packet.first() {
+        Some(&first) if first >> 4 == 4 => (first & 0x0f) as usize * 4,
+        _ => 0,
+    };
+    let icmp = packet.get(offset..offset + 2)?;
+
+    Some(FragmentationIcmpError {
+        ip_address: event.router_ip.clone(),
+        icmp_type: icmp[0],
+        icmp_code: icmp[1],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn event(icmp_packet: Vec<u8>, df_bit: Option<bool>) -> TrackedPacketEvent {
+        TrackedPacketEvent {
+            icmp_packet,
+            udp_packet: Vec::new(),
+            tracked_ip_length: 3000,
+            cleartext: Vec::new(),
+            sent_at: Instant::now(),
+            icmp_received_at: Instant::now(),
+            send_options: SendOptions {
+                ttl: None,
+                df_bit,
+                tos: None,
+                flow_label: None,
+                track_for_ms: 5000,
+                bypass_dtls: false,
+                bypass_sctp_fragmentation: true,
+                ipv6_hop_by_hop_len: None,
+                ipv6_dest_opts_len: None,
+                out_of_order_fragment_size: None,
+            },
+            router_ip: Some("192.0.2.1".to_string()),
+            conn_id: String::new(),
+            original_src_port: 3478,
+            original_dest_addr: "198.51.100.7:50000".to_string(),
+            icmp_extensions: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_padded_length() {
+        assert_eq!(padded_length(3000, false), 3000 - 20 - PROBE_OVERHEAD);
+        assert_eq!(padded_length(3000, true), 3000 - 40 - PROBE_OVERHEAD);
+        assert_eq!(padded_length(64, true), 0);
+    }
+
+    #[test]
+    fn test_icmp_error_from_event() {
+        // IPv4 reassembly timeout, after an IPv4 header with options
+        let mut packet = vec![0x46];
+        packet.resize(24, 0);
+        packet.extend_from_slice(&[11, 1, 0, 0]);
+        let error = icmp_error_from_event(&event(packet, Some(false))).unwrap();
+        assert_eq!((error.icmp_type, error.icmp_code), (11, 1));
+        assert_eq!(error.ip_address.as_deref(), Some("192.0.2.1"));
+
+        // ICMPv6 Destination Unreachable, administratively prohibited
+        let error = icmp_error_from_event(&event(vec![1, 1, 0, 0], Some(false))).unwrap();
+        assert_eq!((error.icmp_type, error.icmp_code), (1, 1));
+
+        // Other testprobes and truncated packets are not
+        assert!(icmp_error_from_event(&event(vec![3, 0, 0, 0], Some(true))).is_none());
+        assert!(icmp_error_from_event(&event(vec![0x45, 0, 0], Some(false))).is_none());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::packet_tracker::{
     EmbeddedUdpInfo, IcmpMessageClass, PacketTracker, MAX_PAYLOAD_PREFIX_SIZE,
 };
+use common::{IcmpExtensions, InterfaceInfo, InterfaceRole, MplsLabelEntry};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 /// ICMP error listener for packet tracking correlation
 ///
@@ -13,6 +14,11 @@ const IPV6_HEADER_SIZE: usize = 40;
 const UDP_HEADER_SIZE: usize = 8;
 const ICMPV6_HEADER_SIZE: usize = 8;
 const MIN_ICMPV6_PACKET_SIZE: usize = ICMPV6_HEADER_SIZE + IPV6_HEADER_SIZE + UDP_HEADER_SIZE; // 56 bytes
+const ICMP_EXTENSION_VERSION: u8 = 2;
+/// Original datagram length assumed for extensions of ICMP messages without a length field
+const ICMP_LEGACY_ORIGINAL_DATAGRAM_SIZE: usize = 128;
+const MPLS_LABEL_STACK_CLASS: u8 = 1;
+const INTERFACE_INFORMATION_CLASS: u8 = 2;
 
 /// Start the ICMP listener in the background
 pub fn start_icmp_listener(packet_tracker: Arc<PacketTracker>) {
@@ -84,8 +90,16 @@ async fn icmp_listener_task_v4(packet_tracker: Arc<PacketTracker>) -> std::io::R
                     tracing::trace!(
                         "Parsed IPv4 ICMP error successfully, matching against tracked packets"
                     );
+                    let extensions = parse_icmp_extensions_v4(&icmp_packet);
                     packet_tracker
-                        .match_icmp_error(icmp_packet, msg_class, false, embedded_info, router_ip)
+                        .match_icmp_error(
+                            icmp_packet,
+                            msg_class,
+                            false,
+                            embedded_info,
+                            router_ip,
+                            extensions,
+                        )
                         .await;
                 } else {
                     tracing::trace!("IPv4 ICMP packet is not an error or failed to parse");
@@ -141,8 +155,16 @@ async fn icmp_listener_task_v6(packet_tracker: Arc<PacketTracker>) -> std::io::R
                     tracing::trace!(
                         "Parsed IPv6 ICMPv6 error successfully, matching against tracked packets"
                     );
+                    let extensions = parse_icmpv6_extensions(&icmp_packet);
                     packet_tracker
-                        .match_icmp_error(icmp_packet, msg_class, true, embedded_info, router_ip)
+                        .match_icmp_error(
+                            icmp_packet,
+                            msg_class,
+                            true,
+                            embedded_info,
+                            router_ip,
+                            extensions,
+                        )
                         .await;
                 } else {
                     tracing::trace!("IPv6 ICMPv6 packet is not an error or failed to parse");
@@ -317,12 +339,13 @@ fn parse_icmpv6_error(packet: &[u8]) -> Option<(EmbeddedUdpInfo, IcmpMessageClas
     // ICMPv6 Time Exceeded = type 3
     // ICMPv6 Destination Unreachable = type 1
     // ICMPv6 Packet Too Big = type 2
-    if ![1, 2, 3].contains(&icmpv6_type) {
-        tracing::trace!("Not an ICMPv6 error type (expected 1, 2, or 3)");
+    // ICMPv6 Parameter Problem = type 4 (e.g. for extension header test probes)
+    if ![1, 2, 3, 4].contains(&icmpv6_type) {
+        tracing::trace!("Not an ICMPv6 error type (expected 1, 2, 3 or 4)");
         return None;
     }
     let icmp_class = match icmpv6_type {
-        1 => IcmpMessageClass::Error,
+        1 | 4 => IcmpMessageClass::Error,
         2 => IcmpMessageClass::TtlExpired,
         _ => IcmpMessageClass::Ignore,
     };
@@ -343,9 +366,13 @@ fn parse_icmpv6_error(packet: &[u8]) -> Option<(EmbeddedUdpInfo, IcmpMessageClas
         return None;
     }
 
-    // IPv6 header is fixed 40 bytes
-    // Next header field is at offset 6
-    let next_header = packet[embedded_ip_start + 6];
+    // IPv6 header is fixed 40 bytes, possibly followed by extension headers
+    let Some((embedded_udp_start, next_header)) =
+        skip_ipv6_extension_headers(packet, embedded_ip_start)
+    else {
+        tracing::trace!("Not enough data for embedded IPv6 extension headers");
+        return None;
+    };
 
     // Check if embedded packet is UDP (next header 17)
     if next_header != 17 {
@@ -359,9 +386,7 @@ fn parse_icmpv6_error(packet: &[u8]) -> Option<(EmbeddedUdpInfo, IcmpMessageClas
         .ok()?;
     let dest_ip = Ipv6Addr::from(dest_ip_bytes);
 
-    // Parse embedded UDP header (starts after IPv6 header)
-    let embedded_udp_start = embedded_ip_start + IPV6_HEADER_SIZE;
-
+    // Parse embedded UDP header (starts after IPv6 and extension headers)
     if packet.len() < embedded_udp_start + UDP_HEADER_SIZE {
         tracing::trace!("Not enough data for embedded UDP header");
         return None;
@@ -416,6 +441,206 @@ fn parse_icmpv6_error(packet: &[u8]) -> Option<(EmbeddedUdpInfo, IcmpMessageClas
     ))
 }
 
+/// Offset and protocol of the upper-layer header of the IPv6 packet at `start`
+///
+/// Skips the Hop-by-Hop Options, Routing and Destination Options headers,
+/// which all carry their length in 8-byte units after the first 8 bytes.
+#[cfg(target_os = "linux")]
+fn skip_ipv6_extension_headers(packet: &[u8], start: usize) -> Option<(usize, u8)> {
+    const HOP_BY_HOP_OPTIONS: u8 = 0;
+    const ROUTING: u8 = 43;
+    const DESTINATION_OPTIONS: u8 = 60;
+
+    // Next header field is at offset 6
+    let mut next_header = *packet.get(start + 6)?;
+    let mut offset = start + IPV6_HEADER_SIZE;
+    while matches!(
+        next_header,
+        HOP_BY_HOP_OPTIONS | ROUTING | DESTINATION_OPTIONS
+    ) {
+        let header = packet.get(offset..offset + 2)?;
+        next_header = header[0];
+        offset += (header[1] as usize + 1) * 8;
+    }
+
+    Some((offset, next_header))
+}
+
+/// Parse the extension objects (RFC 4884) of an ICMP error received with its IP header
+///
+/// The length of the original datagram is in 32-bit words at byte 5 of the
+/// ICMP header. Routers that predate RFC 4884 (but implement RFC 4950) leave
+/// it zero and append the extensions after 128 bytes of original datagram;
+/// those are only accepted with a valid checksum.
+#[cfg(target_os = "linux")]
+fn parse_icmp_extensions_v4(packet: &[u8]) -> IcmpExtensions {
+    if packet.len() < 28 || packet[0] >> 4 != 4 {
+        return IcmpExtensions::default();
+    }
+    let icmp = &packet[((packet[0] & 0x0F) as usize * 4).min(packet.len())..];
+    if icmp.len() < 8 || ![3, 11, 12].contains(&icmp[0]) {
+        return IcmpExtensions::default();
+    }
+
+    let original_len = icmp[5] as usize * 4;
+    if original_len > 0 {
+        return icmp
+            .get(8 + original_len..)
+            .map(|data| parse_icmp_extension_structure(data, false))
+            .unwrap_or_default();
+    }
+    icmp.get(8 + ICMP_LEGACY_ORIGINAL_DATAGRAM_SIZE..)
+        .map(|data| parse_icmp_extension_structure(data, true))
+        .unwrap_or_default()
+}
+
+/// Parse the extension objects (RFC 4884) of an ICMPv6 error
+///
+/// The length of the original datagram is in 64-bit words at byte 4 of the
+/// ICMPv6 header of Destination Unreachable and Time Exceeded messages.
+#[cfg(target_os = "linux")]
+fn parse_icmpv6_extensions(packet: &[u8]) -> IcmpExtensions {
+    if packet.len() < ICMPV6_HEADER_SIZE || ![1, 3].contains(&packet[0]) {
+        return IcmpExtensions::default();
+    }
+    let original_len = packet[4] as usize * 8;
+    if original_len == 0 {
+        return IcmpExtensions::default();
+    }
+    packet
+        .get(ICMPV6_HEADER_SIZE + original_len..)
+        .map(|data| parse_icmp_extension_structure(data, false))
+        .unwrap_or_default()
+}
+
+/// Parse an ICMP extension structure: a 4-byte header (version, checksum)
+/// followed by objects with a 4-byte header (length, class, C-type)
+///
+/// A zero checksum means it was not computed, unless `require_checksum`.
+/// Unknown and malformed objects are skipped.
+#[cfg(target_os = "linux")]
+fn parse_icmp_extension_structure(data: &[u8], require_checksum: bool) -> IcmpExtensions {
+    let mut extensions = IcmpExtensions::default();
+    if data.len() < 4 || data[0] >> 4 != ICMP_EXTENSION_VERSION {
+        return extensions;
+    }
+    let checksum = u16::from_be_bytes([data[2], data[3]]);
+    if (checksum != 0 || require_checksum) && internet_checksum(data) != 0 {
+        tracing::trace!("ICMP extension structure has an invalid checksum");
+        return extensions;
+    }
+
+    let mut offset = 4;
+    while offset + 4 <= data.len() {
+        let length = u16::from_be_bytes([data[offset], data[offset + 1]]) as usize;
+        if length < 4 || offset + length > data.len() {
+            tracing::trace!("Malformed ICMP extension object at offset {}", offset);
+            break;
+        }
+        let class = data[offset + 2];
+        let c_type = data[offset + 3];
+        let payload = &data[offset + 4..offset + length];
+        match class {
+            MPLS_LABEL_STACK_CLASS if c_type == 1 => {
+                extensions
+                    .mpls_labels
+                    .extend(payload.chunks_exact(4).map(|entry| {
+                        let value = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+                        MplsLabelEntry {
+                            label: value >> 12,
+                            tc: ((value >> 9) & 0x07) as u8,
+                            bottom_of_stack: value & 0x100 != 0,
+                            ttl: (value & 0xFF) as u8,
+                        }
+                    }));
+            }
+            INTERFACE_INFORMATION_CLASS => {
+                if let Some(interface) = parse_interface_information(c_type, payload) {
+                    extensions.interfaces.push(interface);
+                }
+            }
+            _ => tracing::trace!("Skipping ICMP extension object class={}", class),
+        }
+        offset += length;
+    }
+    extensions
+}
+
+/// Parse an Interface Information Object (RFC 5837)
+///
+/// The C-type holds the interface role in its top two bits and flags for the
+/// sub-objects that follow, in order: ifIndex, IP address, name and MTU.
+#[cfg(target_os = "linux")]
+fn parse_interface_information(c_type: u8, payload: &[u8]) -> Option<InterfaceInfo> {
+    let role = match c_type >> 6 {
+        0 => InterfaceRole::Incoming,
+        1 => InterfaceRole::IncomingSubIp,
+        2 => InterfaceRole::Outgoing,
+        _ => InterfaceRole::NextHop,
+    };
+    let mut interface = InterfaceInfo {
+        role,
+        if_index: None,
+        ip_address: None,
+        name: None,
+        mtu: None,
+    };
+    let read_u32 = |data: &[u8], at: usize| -> Option<u32> {
+        let bytes = data.get(at..at + 4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    let mut offset = 0;
+    if c_type & 0x08 != 0 {
+        interface.if_index = Some(read_u32(payload, offset)?);
+        offset += 4;
+    }
+    if c_type & 0x04 != 0 {
+        let afi = u16::from_be_bytes([*payload.get(offset)?, *payload.get(offset + 1)?]);
+        let address = payload.get(offset + 4..)?;
+        let (ip, len) = match afi {
+            1 => {
+                let bytes: [u8; 4] = address.get(..4)?.try_into().ok()?;
+                (IpAddr::V4(Ipv4Addr::from(bytes)), 4)
+            }
+            2 => {
+                let bytes: [u8; 16] = address.get(..16)?.try_into().ok()?;
+                (IpAddr::V6(Ipv6Addr::from(bytes)), 16)
+            }
+            _ => return None,
+        };
+        interface.ip_address = Some(ip.to_string());
+        offset += 4 + len;
+    }
+    if c_type & 0x02 != 0 {
+        let length = *payload.get(offset)? as usize;
+        if length == 0 || !length.is_multiple_of(4) {
+            return None;
+        }
+        let name = payload.get(offset + 1..offset + length)?;
+        let name = String::from_utf8_lossy(name);
+        interface.name = Some(name.trim_end_matches('\0').to_string());
+        offset += length;
+    }
+    if c_type & 0x01 != 0 {
+        interface.mtu = Some(read_u32(payload, offset)?);
+    }
+    Some(interface)
+}
+
+/// One's complement sum of 16-bit words (RFC 1071); zero over data including a valid checksum
+#[cfg(target_os = "linux")]
+fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -443,4 +668,141 @@ mod tests {
         let too_small = vec![0u8; 30];
         assert!(parse_icmpv6_error(&too_small).is_none());
     }
+
+    #[test]
+    fn test_skip_ipv6_extension_headers() {
+        // ICMPv6 Time Exceeded quoting a UDP packet with Hop-by-Hop (8 bytes)
+        // and Destination Options (16 bytes) headers
+        let mut packet = vec![3, 0, 0, 0, 0, 0, 0, 0];
+        let mut ip = vec![0u8; IPV6_HEADER_SIZE];
+        ip[0] = 0x60;
+        ip[6] = 0; // Hop-by-Hop Options
+        ip[24..40].copy_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        packet.extend_from_slice(&ip);
+        packet.extend_from_slice(&[60, 0, 0x1e, 4, 0, 0, 0, 0]);
+        packet.extend_from_slice(&[17, 1, 0x1e, 12]);
+        packet.resize(packet.len() + 12, 0);
+        packet.extend_from_slice(&[0xc3, 0x50, 0x13, 0x88, 0x00, 0x0c, 0xab, 0xcd]);
+        packet.extend_from_slice(b"abcd");
+
+        assert_eq!(
+            skip_ipv6_extension_headers(&packet, ICMPV6_HEADER_SIZE),
+            Some((ICMPV6_HEADER_SIZE + IPV6_HEADER_SIZE + 24, 17))
+        );
+
+        let (info, class) = parse_icmpv6_error(&packet).unwrap();
+        assert_eq!(class, IcmpMessageClass::Ignore);
+        assert_eq!(info.src_port, 50000);
+        assert_eq!(info.dest_addr, "[2001:db8::1]:5000".parse().unwrap());
+        assert_eq!(info.udp_length, 12);
+        assert_eq!(info.udp_checksum, 0xabcd);
+        assert_eq!(info.payload_prefix, b"abcd");
+
+        // Parameter Problem errors are errors too
+        packet[0] = 4;
+        assert_eq!(
+            parse_icmpv6_error(&packet).unwrap().1,
+            IcmpMessageClass::Error
+        );
+
+        // Quoted packet cut off in the extension headers
+        assert!(skip_ipv6_extension_headers(&packet[..57], ICMPV6_HEADER_SIZE).is_none());
+        assert!(parse_icmpv6_error(&packet[..78]).is_none());
+    }
+
+    /// Extension structure with a valid checksum holding the given objects
+    fn extension_structure(objects: &[(u8, u8, Vec<u8>)]) -> Vec<u8> {
+        let mut data = vec![0x20, 0x00, 0x00, 0x00];
+        for (class, c_type, payload) in objects {
+            data.extend_from_slice(&((payload.len() + 4) as u16).to_be_bytes());
+            data.push(*class);
+            data.push(*c_type);
+            data.extend_from_slice(payload);
+        }
+        let checksum = internet_checksum(&data);
+        data[2..4].copy_from_slice(&checksum.to_be_bytes());
+        data
+    }
+
+    /// ICMP Time Exceeded with IP header, a zero-padded original datagram and extensions
+    fn icmp_time_exceeded(length_field: u8, original_len: usize, extensions: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; 20];
+        packet[0] = 0x45;
+        packet.extend_from_slice(&[11, 0, 0, 0, 0, length_field, 0, 0]);
+        packet.extend(std::iter::repeat_n(0u8, original_len));
+        packet.extend_from_slice(extensions);
+        packet
+    }
+
+    #[test]
+    fn test_mpls_label_stack_extension() {
+        // Label 16003, TC 5, bottom of stack, TTL 1
+        let entry = (16003u32 << 12 | 5 << 9 | 1 << 8 | 1).to_be_bytes().to_vec();
+        let extensions = extension_structure(&[(1, 1, entry)]);
+
+        // RFC 4884: original datagram length in 32-bit words
+        let packet = icmp_time_exceeded(32, 128, &extensions);
+        let parsed = parse_icmp_extensions_v4(&packet);
+        assert_eq!(
+            parsed.mpls_labels,
+            vec![MplsLabelEntry {
+                label: 16003,
+                tc: 5,
+                bottom_of_stack: true,
+                ttl: 1,
+            }]
+        );
+
+        // Pre-RFC 4884 routers: zero length, extensions after 128 bytes
+        let legacy = icmp_time_exceeded(0, 128, &extensions);
+        assert_eq!(parse_icmp_extensions_v4(&legacy), parsed);
+
+        // Without a valid checksum the legacy layout is not trusted
+        let mut corrupted = legacy.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xFF;
+        assert!(parse_icmp_extensions_v4(&corrupted).is_empty());
+
+        // No extensions when the length covers the whole packet
+        let plain = icmp_time_exceeded(0, 64, &[]);
+        assert!(parse_icmp_extensions_v4(&plain).is_empty());
+    }
+
+    #[test]
+    fn test_interface_information_extension() {
+        // Incoming interface with ifIndex, IPv4 address, name and MTU
+        let mut payload = 7u32.to_be_bytes().to_vec();
+        payload.extend_from_slice(&[0, 1, 0, 0, 192, 0, 2, 1]);
+        payload.extend_from_slice(&[8, b'g', b'e', b'-', b'0', b'/', b'1', 0]);
+        payload.extend_from_slice(&1500u32.to_be_bytes());
+        let incoming = (2, 0x0F, payload);
+        // Outgoing interface with ifIndex only
+        let outgoing = (2, 0x80 | 0x08, 9u32.to_be_bytes().to_vec());
+        let extensions = extension_structure(&[incoming, outgoing]);
+
+        let mut packet = vec![3, 0, 0, 0, 16, 0, 0, 0];
+        packet.extend(std::iter::repeat_n(0u8, 128));
+        packet.extend_from_slice(&extensions);
+        let parsed = parse_icmpv6_extensions(&packet);
+        assert_eq!(
+            parsed.interfaces,
+            vec![
+                InterfaceInfo {
+                    role: InterfaceRole::Incoming,
+                    if_index: Some(7),
+                    ip_address: Some("192.0.2.1".to_string()),
+                    name: Some("ge-0/1".to_string()),
+                    mtu: Some(1500),
+                },
+                InterfaceInfo {
+                    role: InterfaceRole::Outgoing,
+                    if_index: Some(9),
+                    ip_address: None,
+                    name: None,
+                    mtu: None,
+                },
+            ]
+        );
+        assert!(parsed.mpls_labels.is_empty());
+    }
 }
@@ -0,0 +1,347 @@
+//! iperf3 result recorder for persisting completed iperf3 tests
+//!
+//! Records every test completed against the embedded iperf3 server to the SQLite
+//! database, linked to the survey session of the authenticated client address
+//! (from the `AuthAddressCache`) when one is known.
+
+use crate::auth_cache::AuthenticatedAddress;
+use crate::database::DbConnection;
+use iperf3_server::protocol::StreamResult;
+use iperf3_server::TestRecord;
+use rusqlite::params;
+
+/// Receiver-side summary of a test: the numbers an analyst looks at first
+#[derive(Debug, Default, PartialEq)]
+struct TestSummary {
+    bytes_sent: u64,
+    bytes_received: u64,
+    sent_bps: Option<f64>,
+    received_bps: Option<f64>,
+    jitter_ms: Option<f64>,
+    lost_packets: Option<u64>,
+    packets: Option<u64>,
+    lost_percent: Option<f64>,
+}
+
+impl TestSummary {
+    /// Build the summary from the server results, taking UDP receiver figures
+    /// from the client's results when the server was the sender (reverse mode)
+    fn from_record(record: &TestRecord) -> Self {
+        let end = &record.results.end;
+        let sum_sent = end.sum_sent.clone().unwrap_or_default();
+        let sum_received = end.sum_received.clone().unwrap_or_default();
+
+        let mut summary = TestSummary {
+            bytes_sent: sum_sent.bytes,
+            bytes_received: sum_received.bytes,
+            sent_bps: Some(sum_sent.bits_per_second),
+            received_bps: Some(sum_received.bits_per_second),
+            ..Default::default()
+        };
+
+        if !record.params.udp {
+            return summary;
+        }
+
+        if record.params.reverse {
+            // The client received: its exchanged per-stream results carry
+            // jitter (seconds), errors (lost packets) and packets
+            let streams = record
+                .client_results
+                .get("streams")
+                .and_then(|s| s.as_array())
+                .cloned()
+                .unwrap_or_default();
+            if !streams.is_empty() {
+                let field = |stream: &serde_json::Value, name: &str| {
+                    stream.get(name).and_then(|v| v.as_f64()).unwrap_or(0.0)
+                };
+                let jitter_total: f64 = streams.iter().map(|s| field(s, "jitter")).sum();
+                let lost: f64 = streams.iter().map(|s| field(s, "errors")).sum();
+                let packets: f64 = streams.iter().map(|s| field(s, "packets")).sum();
+                let bytes: f64 = streams.iter().map(|s| field(s, "bytes")).sum();
+
+                summary.bytes_received = bytes as u64;
+                summary.received_bps = Some(if record.duration_secs > 0.0 {
+                    bytes * 8.0 / record.duration_secs
+                } else {
+                    0.0
+                });
+                summary.jitter_ms = Some(jitter_total / streams.len() as f64 * 1000.0);
+                summary.lost_packets = Some(lost as u64);
+                summary.packets = Some(packets as u64);
+                summary.lost_percent = Some(if packets > 0.0 {
+                    100.0 * lost / packets
+                } else {
+                    0.0
+                });
+            }
+        } else {
+            let StreamResult {
+                jitter_ms,
+                lost_packets,
+                packets,
+                lost_percent,
+                ..
+            } = sum_received;
+            summary.jitter_ms = jitter_ms;
+            summary.lost_packets = lost_packets;
+            summary.packets = packets;
+            summary.lost_percent = lost_percent;
+        }
+
+        summary
+    }
+}
+
+/// Service for recording completed iperf3 tests to the database
+pub struct Iperf3ResultRecorder {
+    db: DbConnection,
+}
+
+impl Iperf3ResultRecorder {
+    /// Create a new Iperf3ResultRecorder with the given database connection
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// Record a completed iperf3 test
+    ///
+    /// # Arguments
+    /// * `record` - The completed test as reported by the iperf3 server
+    /// * `auth` - The authenticated address entry for the client, if any.
+    ///   The test is linked to its survey session only if that session exists.
+    pub async fn record_test(
+        &self,
+        record: &TestRecord,
+        auth: Option<&AuthenticatedAddress>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let summary = TestSummary::from_record(record);
+        let start_time_ms = record
+            .started_at
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let params_json = serde_json::to_string(&record.params)?;
+        let intervals_json = serde_json::to_string(&record.results.intervals)?;
+        let results_json = serde_json::to_string(&record.results)?;
+        let client_results_json = serde_json::to_string(&record.client_results)?;
+        let survey_session_id = auth.and_then(|a| a.survey_session_id.as_deref());
+
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+
+        db.execute(
+            "INSERT INTO iperf3_tests (
+                test_id, session_id, client_ip, client_port, user_id, auth_source,
+                protocol, reverse, parallel, requested_duration_secs, bandwidth_bps, blksize,
+                start_time, duration_secs, bytes_sent, bytes_received, sent_bps, received_bps,
+                jitter_ms, lost_packets, packets, lost_percent,
+                params_json, intervals_json, results_json, client_results_json, created_at
+            ) VALUES (
+                ?, (SELECT session_id FROM survey_sessions WHERE session_id = ? AND deleted = 0),
+                ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
+            )",
+            params![
+                record.cookie,
+                survey_session_id,
+                record.client_addr.ip().to_string(),
+                record.client_addr.port(),
+                auth.map(|a| a.user_id.as_str()),
+                auth.map(|a| a.auth_source.as_str()),
+                if record.params.udp { "UDP" } else { "TCP" },
+                record.params.reverse,
+                record.params.parallel,
+                record.params.time,
+                record.params.bandwidth,
+                record.params.blksize,
+                start_time_ms,
+                record.duration_secs,
+                summary.bytes_sent,
+                summary.bytes_received,
+                summary.sent_bps,
+                summary.received_bps,
+                summary.jitter_ms,
+                summary.lost_packets,
+                summary.packets,
+                summary.lost_percent,
+                params_json,
+                intervals_json,
+                results_json,
+                client_results_json,
+                now_ms
+            ],
+        )?;
+
+        tracing::info!(
+            "Recorded iperf3 test {} from {} (survey session: {})",
+            record.cookie,
+            record.client_addr,
+            survey_session_id.unwrap_or("none")
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_database;
+    use iperf3_server::protocol::{
+        EndInfo, ExchangeResultsData, ServerResults, StartInfo, TestParameters, TestStartInfo,
+    };
+    use std::time::Instant;
+    use tempfile::NamedTempFile;
+
+    fn create_test_record(cookie: &str, params: TestParameters) -> TestRecord {
+        TestRecord {
+            cookie: cookie.to_string(),
+            client_addr: "192.0.2.10:50000".parse().unwrap(),
+            params: params.clone(),
+            started_at: std::time::SystemTime::now(),
+            duration_secs: 10.0,
+            results: ServerResults {
+                start: StartInfo {
+                    connected: vec![],
+                    version: "test".to_string(),
+                    system_info: "test".to_string(),
+                    test_start: TestStartInfo {
+                        protocol: params.protocol.clone(),
+                        num_streams: params.parallel,
+                        blksize: params.blksize,
+                        omit: 0,
+                        duration: params.time,
+                        bytes: 0,
+                        blocks: 0,
+                        reverse: params.reverse,
+                    },
+                },
+                intervals: vec![],
+                end: EndInfo {
+                    streams: vec![],
+                    sum_sent: None,
+                    sum_received: Some(StreamResult {
+                        bytes: 1_250_000,
+                        seconds: 10.0,
+                        bits_per_second: 1_000_000.0,
+                        jitter_ms: Some(0.5),
+                        lost_packets: Some(3),
+                        packets: Some(100),
+                        lost_percent: Some(3.0),
+                        ..Default::default()
+                    }),
+                    cpu_utilization_percent: None,
+                },
+            },
+            exchange_results: ExchangeResultsData {
+                cpu_util_total: 0.0,
+                cpu_util_user: 0.0,
+                cpu_util_system: 0.0,
+                sender_has_retransmits: -1,
+                congestion_used: None,
+                streams: vec![],
+            },
+            client_results: serde_json::json!({
+                "streams": [
+                    {"id": 1, "bytes": 2_500_000, "jitter": 0.002, "errors": 5, "packets": 200}
+                ]
+            }),
+        }
+    }
+
+    fn udp_params(reverse: bool) -> TestParameters {
+        TestParameters {
+            protocol: "UDP".to_string(),
+            udp: true,
+            reverse,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_summary_udp_receiver() {
+        let record = create_test_record("cookie-1", udp_params(false));
+        let summary = TestSummary::from_record(&record);
+        assert_eq!(summary.bytes_received, 1_250_000);
+        assert_eq!(summary.jitter_ms, Some(0.5));
+        assert_eq!(summary.lost_packets, Some(3));
+        assert_eq!(summary.packets, Some(100));
+    }
+
+    #[test]
+    fn test_summary_udp_reverse_uses_client_results() {
+        let record = create_test_record("cookie-1", udp_params(true));
+        let summary = TestSummary::from_record(&record);
+        assert_eq!(summary.bytes_received, 2_500_000);
+        assert_eq!(summary.lost_packets, Some(5));
+        assert_eq!(summary.packets, Some(200));
+        assert!((summary.jitter_ms.unwrap() - 2.0).abs() < 1e-9);
+        assert!((summary.lost_percent.unwrap() - 2.5).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_record_test_links_existing_session() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+
+        {
+            let conn = db.lock().await;
+            let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+            conn.execute(
+                "INSERT INTO survey_sessions (session_id, magic_key, start_time, last_update_time, created_at)
+                 VALUES (?, ?, ?, ?, ?)",
+                params!["test-session", "TEST-001", now_ms, now_ms, now_ms],
+            )
+            .unwrap();
+        }
+
+        let recorder = Iperf3ResultRecorder::new(db.clone());
+        let mut auth = AuthenticatedAddress {
+            ip: "192.0.2.10".parse().unwrap(),
+            user_id: "webrtc:client-1".to_string(),
+            display_name: None,
+            last_authenticated: Instant::now(),
+            auth_source: "webrtc".to_string(),
+            survey_session_id: Some("test-session".to_string()),
+            magic_key: None,
+        };
+
+        recorder
+            .record_test(
+                &create_test_record("cookie-1", udp_params(false)),
+                Some(&auth),
+            )
+            .await
+            .unwrap();
+
+        // A survey session that was never stored is not linked
+        auth.survey_session_id = Some("unknown-session".to_string());
+        recorder
+            .record_test(
+                &create_test_record("cookie-2", udp_params(false)),
+                Some(&auth),
+            )
+            .await
+            .unwrap();
+
+        // Unauthenticated clients are still recorded
+        recorder
+            .record_test(
+                &create_test_record("cookie-3", TestParameters::default()),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let conn = db.lock().await;
+        let linked: Vec<Option<String>> = conn
+            .prepare("SELECT session_id FROM iperf3_tests ORDER BY test_id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(linked, vec![Some("test-session".to_string()), None, None]);
+    }
+}
@@ -0,0 +1,250 @@
+//! Admin API for managing local users
+//!
+//! Provides endpoints to list, create, enable/disable and reset the password
+//! of the plain login users kept in the database. Passwords must satisfy the
+//! configured password policy; if none is given, a random one is generated and
+//! returned once. Disabling a user or resetting their password also revokes
+//! their login sessions and API tokens.
+
+use crate::api_tokens::DbApiTokenStore;
+use crate::local_users::{DbLocalUserStore, LocalUserInfo};
+use crate::user_sessions::DbSessionStore;
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    Json,
+};
+use netpoke_auth::passwords::{generate_password, hash_password};
+use netpoke_auth::{AuthProvider, AuthState, SessionData};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Longest username accepted
+const MAX_USERNAME_LENGTH: usize = 64;
+
+/// State of the local user endpoints
+pub struct LocalUserApiState {
+    pub store: Arc<DbLocalUserStore>,
+    /// To log out disabled and reset users
+    pub sessions: Arc<DbSessionStore>,
+    pub api_tokens: Arc<DbApiTokenStore>,
+    /// For the password policy
+    pub auth_state: AuthState,
+}
+
+/// Request body for creating a user
+#[derive(Debug, Deserialize)]
+pub struct NewLocalUser {
+    pub username: String,
+    /// Initial password (random if omitted)
+    pub password: Option<String>,
+    pub display_name: Option<String>,
+}
+
+/// Request body for resetting a password
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    /// New password (random if omitted)
+    pub password: Option<String>,
+}
+
+/// Request body for enabling or disabling a user
+#[derive(Debug, Deserialize)]
+pub struct SetEnabledRequest {
+    pub enabled: bool,
+}
+
+/// Response of the create and reset endpoints
+#[derive(Debug, Serialize)]
+pub struct PasswordSet {
+    pub username: String,
+    /// The generated password, if none was given (shown only once)
+    pub generated_password: Option<String>,
+}
+
+/// Usernames are handles, so keep them to a safe set of characters
+fn is_valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.len() <= MAX_USERNAME_LENGTH
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '@'))
+}
+
+/// Check a given password against the policy, or generate one, and hash it
+///
+/// Returns the hash and the generated password, if any.
+fn new_password_hash(
+    state: &LocalUserApiState,
+    username: &str,
+    password: Option<&str>,
+) -> Result<(String, Option<String>), StatusCode> {
+    let (password, generated) = match password {
+        Some(password) => {
+            if let Err(reason) = state
+                .auth_state
+                .config
+                .plain_login
+                .password_policy
+                .check(username, password)
+            {
+                tracing::warn!("Rejected password for local user {}: {}", username, reason);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            (password.to_string(), None)
+        }
+        None => {
+            let password = generate_password();
+            (password.clone(), Some(password))
+        }
+    };
+    let hash = hash_password(&password).map_err(|e| {
+        tracing::error!("Failed to hash password: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok((hash, generated))
+}
+
+/// Revoke all login sessions and API tokens of a local user, leaving those
+/// of same-named users of other providers alone
+async fn log_out_everywhere(state: &LocalUserApiState, username: &str, admin: &str) {
+    let provider = AuthProvider::PlainLogin.name();
+    if let Err(e) = state
+        .sessions
+        .revoke_all_for(provider, username, Some(admin))
+        .await
+    {
+        tracing::error!("Failed to revoke sessions of {}: {}", username, e);
+    }
+    if let Err(e) = state
+        .api_tokens
+        .revoke_all_for(provider, username, Some(admin))
+        .await
+    {
+        tracing::error!("Failed to revoke API tokens of {}: {}", username, e);
+    }
+}
+
+// ============================================================================
+// List / Create Endpoints
+// ============================================================================
+
+/// List all local users
+pub async fn list_users(
+    State(state): State<Arc<LocalUserApiState>>,
+) -> Result<Json<Vec<LocalUserInfo>>, StatusCode> {
+    state.store.list().await.map(Json).map_err(|e| {
+        tracing::error!("Failed to list local users: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Create a local user
+pub async fn create_user(
+    State(state): State<Arc<LocalUserApiState>>,
+    Extension(admin): Extension<SessionData>,
+    Json(new_user): Json<NewLocalUser>,
+) -> Result<(StatusCode, Json<PasswordSet>), StatusCode> {
+    if !is_valid_username(&new_user.username) {
+        tracing::warn!("Rejected invalid username: {:?}", new_user.username);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let (hash, generated_password) =
+        new_password_hash(&state, &new_user.username, new_user.password.as_deref())?;
+
+    match state
+        .store
+        .create(
+            &new_user.username,
+            &hash,
+            new_user.display_name.as_deref(),
+            Some(&admin.handle),
+        )
+        .await
+    {
+        Ok(true) => {
+            tracing::info!(
+                "Local user {} created by {}",
+                new_user.username,
+                admin.handle
+            );
+            Ok((
+                StatusCode::CREATED,
+                Json(PasswordSet {
+                    username: new_user.username,
+                    generated_password,
+                }),
+            ))
+        }
+        Ok(false) => Err(StatusCode::CONFLICT),
+        Err(e) => {
+            tracing::error!("Failed to create local user: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// ============================================================================
+// Enable / Reset Endpoints
+// ============================================================================
+
+/// Enable or disable a local user; disabling logs them out everywhere
+pub async fn set_user_enabled(
+    State(state): State<Arc<LocalUserApiState>>,
+    Extension(admin): Extension<SessionData>,
+    Path(handle): Path<String>,
+    Json(request): Json<SetEnabledRequest>,
+) -> StatusCode {
+    match state.store.set_disabled(&handle, !request.enabled).await {
+        Ok(true) => {}
+        Ok(false) => return StatusCode::NOT_FOUND,
+        Err(e) => {
+            tracing::error!("Failed to update local user {}: {}", handle, e);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    if !request.enabled {
+        log_out_everywhere(&state, &handle, &admin.handle).await;
+    }
+    tracing::info!(
+        "Local user {} {} by {}",
+        handle,
+        if request.enabled {
+            "enabled"
+        } else {
+            "disabled"
+        },
+        admin.handle
+    );
+    StatusCode::NO_CONTENT
+}
+
+/// Reset the password of a local user and log them out everywhere
+pub async fn reset_user_password(
+    State(state): State<Arc<LocalUserApiState>>,
+    Extension(admin): Extension<SessionData>,
+    Path(handle): Path<String>,
+    Json(request): Json<ResetPasswordRequest>,
+) -> Result<Json<PasswordSet>, StatusCode> {
+    let (hash, generated_password) =
+        new_password_hash(&state, &handle, request.password.as_deref())?;
+
+    match state.store.update_password(&handle, &hash).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to reset password of {}: {}", handle, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    log_out_everywhere(&state, &handle, &admin.handle).await;
+    tracing::info!(
+        "Password of local user {} reset by {}",
+        handle,
+        admin.handle
+    );
+    Ok(Json(PasswordSet {
+        username: handle,
+        generated_password,
+    }))
+}
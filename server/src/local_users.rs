@@ -0,0 +1,217 @@
+//! Database-backed local users
+//!
+//! Plain login users kept in the `local_users` table, next to (and taking
+//! precedence over) the users in the configuration file. Admins create,
+//! disable and reset them at runtime; users change their own password.
+//! Only argon2id hashes of the passwords are stored.
+
+use crate::database::DbConnection;
+use netpoke_auth::local_users::{LocalUser, LocalUserFuture, LocalUserStore};
+use netpoke_auth::AuthError;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::Serialize;
+
+type StoreResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A local user as listed to admins (without the password hash)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LocalUserInfo {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub disabled: bool,
+    /// Creation time (Unix milliseconds)
+    pub created_at: i64,
+    /// Handle of the admin who created the user
+    pub created_by: Option<String>,
+    /// Last password change or reset (Unix milliseconds)
+    pub password_changed_at: i64,
+}
+
+impl LocalUserInfo {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            username: row.get(0)?,
+            display_name: row.get(1)?,
+            disabled: row.get(2)?,
+            created_at: row.get(3)?,
+            created_by: row.get(4)?,
+            password_changed_at: row.get(5)?,
+        })
+    }
+}
+
+/// Local user store over the `local_users` table
+pub struct DbLocalUserStore {
+    db: DbConnection,
+}
+
+impl DbLocalUserStore {
+    /// Create a new DbLocalUserStore with the given database connection
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// List all users by username
+    pub async fn list(&self) -> StoreResult<Vec<LocalUserInfo>> {
+        let db = self.db.lock().await;
+        let mut stmt = db.prepare(
+            "SELECT username, display_name, disabled, created_at, created_by, password_changed_at
+             FROM local_users ORDER BY username",
+        )?;
+        let users = stmt
+            .query_map([], LocalUserInfo::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(users)
+    }
+
+    /// Create a user with an already hashed password
+    ///
+    /// Returns false if a user with that name already exists.
+    pub async fn create(
+        &self,
+        username: &str,
+        password_hash: &str,
+        display_name: Option<&str>,
+        created_by: Option<&str>,
+    ) -> StoreResult<bool> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let db = self.db.lock().await;
+        let inserted = db.execute(
+            "INSERT INTO local_users
+                (username, password_hash, display_name, created_at, created_by, password_changed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?4)
+             ON CONFLICT(username) DO NOTHING",
+            params![username, password_hash, display_name, now_ms, created_by],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Enable or disable a user
+    ///
+    /// Returns false if there is no such user.
+    pub async fn set_disabled(&self, username: &str, disabled: bool) -> StoreResult<bool> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE local_users SET disabled = ? WHERE username = ?",
+            params![disabled, username],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Replace the password hash of a user
+    ///
+    /// Returns false if there is no such user.
+    pub async fn update_password(&self, username: &str, password_hash: &str) -> StoreResult<bool> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE local_users SET password_hash = ?, password_changed_at = ? WHERE username = ?",
+            params![
+                password_hash,
+                chrono::Utc::now().timestamp_millis(),
+                username
+            ],
+        )?;
+        Ok(updated > 0)
+    }
+
+    async fn load_user(&self, username: &str) -> rusqlite::Result<Option<LocalUser>> {
+        let db = self.db.lock().await;
+        db.query_row(
+            "SELECT username, password_hash, display_name, disabled
+             FROM local_users WHERE username = ?",
+            params![username],
+            |row| {
+                Ok(LocalUser {
+                    username: row.get(0)?,
+                    password_hash: row.get(1)?,
+                    display_name: row.get(2)?,
+                    disabled: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+    }
+}
+
+impl LocalUserStore for DbLocalUserStore {
+    fn user<'a>(&'a self, username: &'a str) -> LocalUserFuture<'a, Option<LocalUser>> {
+        Box::pin(async move {
+            self.load_user(username)
+                .await
+                .map_err(|e| AuthError::UserStoreError(e.to_string()))
+        })
+    }
+
+    fn set_password_hash<'a>(
+        &'a self,
+        username: &'a str,
+        password_hash: &'a str,
+    ) -> LocalUserFuture<'a, bool> {
+        Box::pin(async move {
+            self.update_password(username, password_hash)
+                .await
+                .map_err(|e| AuthError::UserStoreError(e.to_string()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_database;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_create_and_look_up() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbLocalUserStore::new(init_database(temp_file.path()).unwrap());
+        assert_eq!(store.user("alice").await.unwrap(), None);
+
+        assert!(store
+            .create("alice", "$argon2id$hash-1", Some("Alice"), Some("admin"))
+            .await
+            .unwrap());
+        // Usernames are unique
+        assert!(!store
+            .create("alice", "$argon2id$hash-2", None, Some("admin"))
+            .await
+            .unwrap());
+
+        let user = store.user("alice").await.unwrap().unwrap();
+        assert_eq!(user.password_hash, "$argon2id$hash-1");
+        assert_eq!(user.display_name.as_deref(), Some("Alice"));
+        assert!(!user.disabled);
+
+        let users = store.list().await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].created_by.as_deref(), Some("admin"));
+        assert_eq!(users[0].created_at, users[0].password_changed_at);
+    }
+
+    #[tokio::test]
+    async fn test_disable_and_password_change() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbLocalUserStore::new(init_database(temp_file.path()).unwrap());
+        store
+            .create("alice", "$argon2id$hash-1", None, None)
+            .await
+            .unwrap();
+
+        assert!(store.set_disabled("alice", true).await.unwrap());
+        assert!(store.user("alice").await.unwrap().unwrap().disabled);
+        assert!(store.set_disabled("alice", false).await.unwrap());
+        assert!(!store.set_disabled("bob", true).await.unwrap());
+
+        assert!(store
+            .set_password_hash("alice", "$argon2id$hash-2")
+            .await
+            .unwrap());
+        assert!(!store
+            .set_password_hash("bob", "$argon2id$hash-2")
+            .await
+            .unwrap());
+        let user = store.user("alice").await.unwrap().unwrap();
+        assert_eq!(user.password_hash, "$argon2id$hash-2");
+        assert!(!user.disabled);
+    }
+}
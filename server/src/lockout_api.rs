@@ -0,0 +1,71 @@
+//! Admin API for reviewing and clearing login lockouts
+//!
+//! Lists the lockouts of client IPs and accounts after repeated failed logins
+//! or Magic Key attempts, and lets admins end a lockout early, e.g. for a user
+//! who mistyped their password too often.
+
+use crate::login_attempts::{DbLoginAttemptStore, Lockout};
+use axum::{
+    extract::{Extension, Query, State},
+    http::StatusCode,
+    Json,
+};
+use netpoke_auth::SessionData;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Query parameters for listing lockouts
+#[derive(Debug, Deserialize)]
+pub struct ListLockoutsQuery {
+    /// Only list lockouts still in effect
+    #[serde(default)]
+    pub active: bool,
+}
+
+/// Request body for clearing a lockout
+#[derive(Debug, Deserialize)]
+pub struct ClearLockoutRequest {
+    /// "ip:<address>" or "account:<username>"
+    pub attempt_key: String,
+}
+
+/// List recent lockouts, newest first
+pub async fn list_lockouts(
+    State(store): State<Arc<DbLoginAttemptStore>>,
+    Query(query): Query<ListLockoutsQuery>,
+) -> Result<Json<Vec<Lockout>>, StatusCode> {
+    store
+        .list_lockouts(query.active)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to list lockouts: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Forget the failed attempts of an IP or account, ending its lockout
+pub async fn clear_lockout(
+    State(store): State<Arc<DbLoginAttemptStore>>,
+    Extension(admin): Extension<SessionData>,
+    Json(request): Json<ClearLockoutRequest>,
+) -> StatusCode {
+    match store
+        .clear_key(&request.attempt_key, Some(&admin.handle))
+        .await
+    {
+        Ok(true) => {
+            tracing::info!(
+                "Failed login attempts of {} cleared by {}",
+                request.attempt_key,
+                admin.handle
+            );
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            tracing::error!("Failed to clear lockout of {}: {}", request.attempt_key, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
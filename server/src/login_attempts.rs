@@ -0,0 +1,328 @@
+//! Database-backed failed login attempts
+//!
+//! Keeps the failed login and Magic Key attempts per client IP and account in
+//! the `login_attempts` table, so lockouts survive restarts and apply to every
+//! worker sharing the database. Each lockout is also appended to
+//! `login_lockouts`, where admins can review and clear them.
+
+use crate::database::DbConnection;
+use netpoke_auth::lockout::{AttemptRecord, AttemptUpdate, LoginAttemptFuture, LoginAttemptStore};
+use netpoke_auth::AuthError;
+use rusqlite::{params, OptionalExtension, Row, TransactionBehavior};
+use serde::Serialize;
+
+type StoreResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Number of lockouts listed at most
+const MAX_LISTED_LOCKOUTS: i64 = 500;
+
+/// A recorded lockout
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Lockout {
+    pub lockout_id: i64,
+    /// "ip:<address>" or "account:<username>"
+    pub attempt_key: String,
+    pub failures: u32,
+    /// Start of the lockout (Unix milliseconds)
+    pub locked_at: i64,
+    /// End of the lockout (Unix milliseconds)
+    pub locked_until: i64,
+    pub cleared_at: Option<i64>,
+    pub cleared_by: Option<String>,
+}
+
+impl Lockout {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            lockout_id: row.get(0)?,
+            attempt_key: row.get(1)?,
+            failures: row.get(2)?,
+            locked_at: row.get(3)?,
+            locked_until: row.get(4)?,
+            cleared_at: row.get(5)?,
+            cleared_by: row.get(6)?,
+        })
+    }
+}
+
+/// Records use Unix seconds, the database Unix milliseconds
+fn to_millis(seconds: u64) -> i64 {
+    seconds as i64 * 1000
+}
+
+fn to_seconds(millis: i64) -> u64 {
+    (millis / 1000) as u64
+}
+
+fn record_from_row(row: &Row) -> rusqlite::Result<AttemptRecord> {
+    Ok(AttemptRecord {
+        failures: row.get(0)?,
+        last_failure_at: to_seconds(row.get(1)?),
+        locked_until: row.get::<_, Option<i64>>(2)?.map(to_seconds),
+    })
+}
+
+/// Login attempt store over the `login_attempts` and `login_lockouts` tables
+pub struct DbLoginAttemptStore {
+    db: DbConnection,
+}
+
+impl DbLoginAttemptStore {
+    /// Create a new DbLoginAttemptStore with the given database connection
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// List lockouts, newest first, optionally only those still in effect
+    pub async fn list_lockouts(&self, active_only: bool) -> StoreResult<Vec<Lockout>> {
+        let db = self.db.lock().await;
+        let mut stmt = db.prepare(
+            "SELECT lockout_id, attempt_key, failures, locked_at, locked_until, cleared_at, cleared_by
+             FROM login_lockouts
+             WHERE NOT ?1 OR (locked_until > ?2 AND cleared_at IS NULL)
+             ORDER BY locked_at DESC, lockout_id DESC LIMIT ?3",
+        )?;
+        let lockouts = stmt
+            .query_map(
+                params![
+                    active_only,
+                    chrono::Utc::now().timestamp_millis(),
+                    MAX_LISTED_LOCKOUTS
+                ],
+                Lockout::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(lockouts)
+    }
+
+    /// Forget the failed attempts of a key, ending its lockout
+    ///
+    /// Returns false if the key had no failed attempts.
+    pub async fn clear_key(
+        &self,
+        attempt_key: &str,
+        cleared_by: Option<&str>,
+    ) -> StoreResult<bool> {
+        let mut db = self.db.lock().await;
+        let tx = db.transaction()?;
+        let deleted = tx.execute(
+            "DELETE FROM login_attempts WHERE attempt_key = ?",
+            params![attempt_key],
+        )?;
+        tx.execute(
+            "UPDATE login_lockouts SET cleared_at = ?, cleared_by = ?
+             WHERE attempt_key = ? AND cleared_at IS NULL",
+            params![
+                chrono::Utc::now().timestamp_millis(),
+                cleared_by,
+                attempt_key
+            ],
+        )?;
+        tx.commit()?;
+        Ok(deleted > 0)
+    }
+
+    async fn load(&self, attempt_key: &str) -> rusqlite::Result<Option<AttemptRecord>> {
+        let db = self.db.lock().await;
+        db.query_row(
+            "SELECT failures, last_failure_at, locked_until FROM login_attempts
+             WHERE attempt_key = ?",
+            params![attempt_key],
+            record_from_row,
+        )
+        .optional()
+    }
+
+    /// Read, update and write a record in one transaction, so concurrent
+    /// failures of other workers are not lost
+    async fn apply(
+        &self,
+        attempt_key: &str,
+        update: &AttemptUpdate<'_>,
+    ) -> rusqlite::Result<AttemptRecord> {
+        let mut db = self.db.lock().await;
+        let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let previous = tx
+            .query_row(
+                "SELECT failures, last_failure_at, locked_until FROM login_attempts
+                 WHERE attempt_key = ?",
+                params![attempt_key],
+                record_from_row,
+            )
+            .optional()?;
+        let record = update(previous);
+        tx.execute(
+            "INSERT INTO login_attempts
+                (attempt_key, failures, last_failure_at, locked_until, last_active_at)
+             VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT(attempt_key) DO UPDATE SET
+                failures = excluded.failures,
+                last_failure_at = excluded.last_failure_at,
+                locked_until = excluded.locked_until,
+                last_active_at = excluded.last_active_at",
+            params![
+                attempt_key,
+                record.failures,
+                to_millis(record.last_failure_at),
+                record.locked_until.map(to_millis),
+                to_millis(record.last_active())
+            ],
+        )?;
+        tx.commit()?;
+        Ok(record)
+    }
+
+    async fn delete(&self, attempt_key: &str) -> rusqlite::Result<()> {
+        let db = self.db.lock().await;
+        db.execute(
+            "DELETE FROM login_attempts WHERE attempt_key = ?",
+            params![attempt_key],
+        )?;
+        Ok(())
+    }
+
+    async fn delete_before(&self, before: u64) -> rusqlite::Result<()> {
+        let db = self.db.lock().await;
+        db.execute(
+            "DELETE FROM login_attempts WHERE last_active_at < ?",
+            params![to_millis(before)],
+        )?;
+        Ok(())
+    }
+
+    async fn insert_lockout(
+        &self,
+        attempt_key: &str,
+        record: &AttemptRecord,
+    ) -> rusqlite::Result<()> {
+        let db = self.db.lock().await;
+        db.execute(
+            "INSERT INTO login_lockouts (attempt_key, failures, locked_at, locked_until)
+             VALUES (?, ?, ?, ?)",
+            params![
+                attempt_key,
+                record.failures,
+                to_millis(record.last_failure_at),
+                record.locked_until.map(to_millis)
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+fn store_error(e: rusqlite::Error) -> AuthError {
+    AuthError::LoginAttemptStoreError(e.to_string())
+}
+
+impl LoginAttemptStore for DbLoginAttemptStore {
+    fn get<'a>(&'a self, key: &'a str) -> LoginAttemptFuture<'a, Option<AttemptRecord>> {
+        Box::pin(async move { self.load(key).await.map_err(store_error) })
+    }
+
+    fn update<'a>(
+        &'a self,
+        key: &'a str,
+        update: &'a AttemptUpdate<'a>,
+    ) -> LoginAttemptFuture<'a, AttemptRecord> {
+        Box::pin(async move { self.apply(key, update).await.map_err(store_error) })
+    }
+
+    fn clear<'a>(&'a self, key: &'a str) -> LoginAttemptFuture<'a, ()> {
+        Box::pin(async move { self.delete(key).await.map_err(store_error) })
+    }
+
+    fn prune<'a>(&'a self, before: u64) -> LoginAttemptFuture<'a, ()> {
+        Box::pin(async move { self.delete_before(before).await.map_err(store_error) })
+    }
+
+    fn record_lockout<'a>(
+        &'a self,
+        key: &'a str,
+        record: &'a AttemptRecord,
+    ) -> LoginAttemptFuture<'a, ()> {
+        Box::pin(async move { self.insert_lockout(key, record).await.map_err(store_error) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_database;
+    use tempfile::NamedTempFile;
+
+    fn fail(now: u64) -> impl Fn(Option<AttemptRecord>) -> AttemptRecord + Send + Sync {
+        move |previous| {
+            let mut record = previous.unwrap_or_default();
+            record.failures += 1;
+            record.last_failure_at = now;
+            if record.failures >= 2 {
+                record.locked_until = Some(now + 60);
+            }
+            record
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_and_prune() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbLoginAttemptStore::new(init_database(temp_file.path()).unwrap());
+        let now = 1_700_000_000;
+        assert_eq!(store.get("ip:192.0.2.1").await.unwrap(), None);
+
+        store.update("ip:192.0.2.1", &fail(now)).await.unwrap();
+        let record = store.update("ip:192.0.2.1", &fail(now + 1)).await.unwrap();
+        assert_eq!(record.failures, 2);
+        assert_eq!(record.retry_after(now + 1), Some(60));
+        assert_eq!(store.get("ip:192.0.2.1").await.unwrap(), Some(record));
+
+        store.update("account:alice", &fail(now)).await.unwrap();
+        store.clear("account:alice").await.unwrap();
+        assert_eq!(store.get("account:alice").await.unwrap(), None);
+
+        // Records are kept while locked out
+        store.prune(now + 61).await.unwrap();
+        assert!(store.get("ip:192.0.2.1").await.unwrap().is_some());
+        store.prune(now + 62).await.unwrap();
+        assert_eq!(store.get("ip:192.0.2.1").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_lockouts() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbLoginAttemptStore::new(init_database(temp_file.path()).unwrap());
+        let now = chrono::Utc::now().timestamp() as u64;
+        store.update("ip:192.0.2.1", &fail(now)).await.unwrap();
+        let record = store.update("ip:192.0.2.1", &fail(now)).await.unwrap();
+        store.record_lockout("ip:192.0.2.1", &record).await.unwrap();
+        store
+            .record_lockout(
+                "account:bob",
+                &AttemptRecord {
+                    failures: 5,
+                    last_failure_at: now - 3600,
+                    locked_until: Some(now - 3540),
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(store.list_lockouts(false).await.unwrap().len(), 2);
+        let active = store.list_lockouts(true).await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].attempt_key, "ip:192.0.2.1");
+        assert_eq!(active[0].failures, 2);
+
+        assert!(store
+            .clear_key("ip:192.0.2.1", Some("admin"))
+            .await
+            .unwrap());
+        assert!(!store
+            .clear_key("ip:192.0.2.1", Some("admin"))
+            .await
+            .unwrap());
+        assert_eq!(store.get("ip:192.0.2.1").await.unwrap(), None);
+        assert!(store.list_lockouts(true).await.unwrap().is_empty());
+        let cleared = store.list_lockouts(false).await.unwrap();
+        assert_eq!(cleared[0].cleared_by.as_deref(), Some("admin"));
+    }
+}
@@ -0,0 +1,231 @@
+//! Admin API for managing magic keys
+//!
+//! Provides endpoints to create, list, enable/disable, revoke and rotate the
+//! magic keys handed out to surveyors. Changes take effect immediately: a
+//! revoked or disabled key also invalidates the survey sessions created with it.
+//! Individual survey session tokens can be listed and revoked as well.
+
+use crate::magic_keys::{is_valid_key_format, MagicKey, MagicKeyStore, NewMagicKey};
+use crate::survey_tokens::{SurveyToken, SurveyTokenStore};
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use netpoke_auth::SessionData;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Handle of the logged-in admin, recorded as creator/revoker of keys
+fn admin_handle(session_data: &Option<Extension<SessionData>>) -> Option<&str> {
+    session_data
+        .as_ref()
+        .map(|Extension(session)| session.handle.as_str())
+}
+
+// ============================================================================
+// List / Create Endpoints
+// ============================================================================
+
+/// List all magic keys with their settings and usage
+pub async fn list_keys(
+    State(store): State<Arc<MagicKeyStore>>,
+) -> Result<Json<Vec<MagicKey>>, StatusCode> {
+    store.list().await.map(Json).map_err(|e| {
+        tracing::error!("Failed to list magic keys: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Create a magic key (random if no key is given)
+pub async fn create_key(
+    State(store): State<Arc<MagicKeyStore>>,
+    session_data: Option<Extension<SessionData>>,
+    Json(new_key): Json<NewMagicKey>,
+) -> Result<(StatusCode, Json<MagicKey>), StatusCode> {
+    if let Some(magic_key) = &new_key.magic_key {
+        if !is_valid_key_format(magic_key) {
+            tracing::warn!("Rejected magic key with invalid format: {:?}", magic_key);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    if new_key.max_sessions.is_some_and(|n| n < 0)
+        || new_key.max_measuring_time_secs.is_some_and(|n| n < 0)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let created_by = admin_handle(&session_data);
+    match store.create(&new_key, created_by).await {
+        Ok(Some(key)) => {
+            tracing::info!(
+                "Magic key {} created by {}",
+                key.magic_key,
+                created_by.unwrap_or("unknown")
+            );
+            Ok((StatusCode::CREATED, Json(key)))
+        }
+        Ok(None) => Err(StatusCode::CONFLICT),
+        Err(e) => {
+            tracing::error!("Failed to create magic key: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// ============================================================================
+// Enable / Revoke / Rotate Endpoints
+// ============================================================================
+
+/// Request body for enabling or disabling a key
+#[derive(Debug, Deserialize)]
+pub struct SetEnabledRequest {
+    pub enabled: bool,
+}
+
+/// Enable or disable a magic key
+pub async fn set_key_enabled(
+    State(store): State<Arc<MagicKeyStore>>,
+    Path(magic_key): Path<String>,
+    Json(request): Json<SetEnabledRequest>,
+) -> Result<Json<MagicKey>, StatusCode> {
+    match store.set_enabled(&magic_key, request.enabled).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to update magic key {}: {}", magic_key, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    tracing::info!(
+        "Magic key {} {}",
+        magic_key,
+        if request.enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    get_key(&store, &magic_key).await
+}
+
+/// Revoke a magic key permanently
+pub async fn revoke_key(
+    State(store): State<Arc<MagicKeyStore>>,
+    session_data: Option<Extension<SessionData>>,
+    Path(magic_key): Path<String>,
+) -> Result<Json<MagicKey>, StatusCode> {
+    let revoked_by = admin_handle(&session_data);
+    match store.revoke(&magic_key, revoked_by).await {
+        Ok(true) => {}
+        // Unknown or already revoked
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to revoke magic key {}: {}", magic_key, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    tracing::info!(
+        "Magic key {} revoked by {}",
+        magic_key,
+        revoked_by.unwrap_or("unknown")
+    );
+    get_key(&store, &magic_key).await
+}
+
+/// Rotate a magic key: revoke it and return a new key with the same settings
+pub async fn rotate_key(
+    State(store): State<Arc<MagicKeyStore>>,
+    session_data: Option<Extension<SessionData>>,
+    Path(magic_key): Path<String>,
+) -> Result<Json<MagicKey>, StatusCode> {
+    let rotated_by = admin_handle(&session_data);
+    match store.rotate(&magic_key, rotated_by).await {
+        Ok(Some(new_key)) => {
+            tracing::info!(
+                "Magic key {} rotated to {} by {}",
+                magic_key,
+                new_key.magic_key,
+                rotated_by.unwrap_or("unknown")
+            );
+            Ok(Json(new_key))
+        }
+        // Unknown or already revoked
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to rotate magic key {}: {}", magic_key, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// ============================================================================
+// Survey Session Token Endpoints
+// ============================================================================
+
+/// Query parameters for listing survey session tokens
+#[derive(Debug, Deserialize)]
+pub struct SurveyTokenQuery {
+    /// Only list the tokens issued for this magic key
+    pub magic_key: Option<String>,
+    /// Also list expired and revoked tokens
+    #[serde(default)]
+    pub include_inactive: bool,
+}
+
+/// List issued survey session tokens, newest first
+pub async fn list_survey_tokens(
+    State(store): State<Arc<SurveyTokenStore>>,
+    Query(query): Query<SurveyTokenQuery>,
+) -> Result<Json<Vec<SurveyToken>>, StatusCode> {
+    store
+        .list(query.magic_key.as_deref(), query.include_inactive)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to list survey session tokens: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Revoke a single survey session token, signing out the surveyor using it
+pub async fn revoke_survey_token(
+    State(store): State<Arc<SurveyTokenStore>>,
+    session_data: Option<Extension<SessionData>>,
+    Path(token_id): Path<String>,
+) -> Result<Json<SurveyToken>, StatusCode> {
+    let revoked_by = admin_handle(&session_data);
+    match store.revoke(&token_id, revoked_by).await {
+        Ok(true) => {}
+        // Unknown or already revoked
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to revoke survey session token {}: {}", token_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    tracing::info!(
+        "Survey session token {} revoked by {}",
+        token_id,
+        revoked_by.unwrap_or("unknown")
+    );
+    match store.get(&token_id).await {
+        Ok(Some(token)) => Ok(Json(token)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to look up survey session token {}: {}", token_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_key(store: &MagicKeyStore, magic_key: &str) -> Result<Json<MagicKey>, StatusCode> {
+    match store.get(magic_key).await {
+        Ok(Some(key)) => Ok(Json(key)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to look up magic key {}: {}", magic_key, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
@@ -0,0 +1,528 @@
+//! Magic key store for surveyor access
+//!
+//! Magic keys live in the `magic_keys` table with a label, owner, expiry,
+//! session limit, measuring time limit and enabled/revoked state, so they can
+//! be created, revoked and rotated at runtime through the admin API. Keys from
+//! the `[auth.magic_keys]` configuration are imported on startup.
+
+use crate::database::DbConnection;
+use netpoke_auth::config::MagicKeyConfig;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+type StoreResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A magic key as stored in the database
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct MagicKey {
+    pub magic_key: String,
+    pub label: Option<String>,
+    pub owner: Option<String>,
+    /// Creation time (Unix milliseconds)
+    pub created_at: i64,
+    pub created_by: Option<String>,
+    /// Expiry time (Unix milliseconds), None = never expires
+    pub expires_at: Option<i64>,
+    /// Maximum number of survey sessions, None = unlimited
+    pub max_sessions: Option<i64>,
+    /// Survey sessions started with this key
+    pub session_count: i64,
+    pub last_used_at: Option<i64>,
+    /// Maximum measuring time in seconds, None = global default
+    pub max_measuring_time_secs: Option<i64>,
+    pub enabled: bool,
+    pub revoked_at: Option<i64>,
+    pub revoked_by: Option<String>,
+    /// Key that replaced this one when it was rotated
+    pub replaced_by: Option<String>,
+}
+
+impl MagicKey {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            magic_key: row.get(0)?,
+            label: row.get(1)?,
+            owner: row.get(2)?,
+            created_at: row.get(3)?,
+            created_by: row.get(4)?,
+            expires_at: row.get(5)?,
+            max_sessions: row.get(6)?,
+            session_count: row.get(7)?,
+            last_used_at: row.get(8)?,
+            max_measuring_time_secs: row.get(9)?,
+            enabled: row.get(10)?,
+            revoked_at: row.get(11)?,
+            revoked_by: row.get(12)?,
+            replaced_by: row.get(13)?,
+        })
+    }
+
+    /// Check that the key may be used at the given time (Unix milliseconds)
+    pub fn check_usable(&self, now_ms: i64) -> Result<(), KeyRejection> {
+        if self.revoked_at.is_some() {
+            return Err(KeyRejection::Revoked);
+        }
+        if !self.enabled {
+            return Err(KeyRejection::Disabled);
+        }
+        if self
+            .expires_at
+            .is_some_and(|expires_at| now_ms >= expires_at)
+        {
+            return Err(KeyRejection::Expired);
+        }
+        Ok(())
+    }
+
+    /// Check whether the key has used up its survey sessions
+    pub fn sessions_exhausted(&self) -> bool {
+        self.max_sessions
+            .is_some_and(|max_sessions| self.session_count >= max_sessions)
+    }
+}
+
+const SELECT_COLUMNS: &str = "SELECT magic_key, label, owner, created_at, created_by, expires_at,
+            max_sessions, session_count, last_used_at, max_measuring_time_secs,
+            enabled, revoked_at, revoked_by, replaced_by
+     FROM magic_keys";
+
+/// Settings for a new magic key (admin API request body)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NewMagicKey {
+    /// Key to create; a random key is generated if omitted
+    #[serde(default)]
+    pub magic_key: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Expiry time (Unix milliseconds)
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    #[serde(default)]
+    pub max_sessions: Option<i64>,
+    #[serde(default)]
+    pub max_measuring_time_secs: Option<i64>,
+}
+
+/// Reason a magic key was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRejection {
+    Unknown,
+    Disabled,
+    Revoked,
+    Expired,
+    SessionLimitReached,
+}
+
+impl fmt::Display for KeyRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyRejection::Unknown => write!(f, "unknown magic key"),
+            KeyRejection::Disabled => write!(f, "magic key disabled"),
+            KeyRejection::Revoked => write!(f, "magic key revoked"),
+            KeyRejection::Expired => write!(f, "magic key expired"),
+            KeyRejection::SessionLimitReached => write!(f, "magic key session limit reached"),
+        }
+    }
+}
+
+/// Check that a magic key is 1 to 64 ASCII letters, digits and hyphens, so it
+/// can be used unescaped in admin API paths (`/admin/api/keys/{magic_key}/...`)
+pub fn is_valid_key_format(magic_key: &str) -> bool {
+    !magic_key.is_empty()
+        && magic_key.len() <= 64
+        && magic_key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Generate a random magic key
+pub fn generate_key() -> String {
+    let uuid = uuid::Uuid::new_v4().simple().to_string().to_uppercase();
+    format!("KEY-{}-{}", &uuid[..6], &uuid[6..12])
+}
+
+/// Service for magic key persistence and validation
+pub struct MagicKeyStore {
+    db: DbConnection,
+    /// Measuring time defaults for keys without their own limit
+    config: MagicKeyConfig,
+}
+
+impl MagicKeyStore {
+    /// Create a new MagicKeyStore with the given database connection
+    pub fn new(db: DbConnection, config: MagicKeyConfig) -> Self {
+        Self { db, config }
+    }
+
+    /// Import the keys listed in the configuration that are not in the database yet,
+    /// taking their measuring time from `magic_key_max_measuring_time`
+    ///
+    /// Returns the number of keys imported.
+    pub async fn import_config_keys(&self) -> StoreResult<usize> {
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let mut imported = 0;
+
+        for magic_key in &self.config.magic_keys {
+            let max_measuring_time = self
+                .config
+                .magic_key_max_measuring_time
+                .get(magic_key)
+                .map(|&secs| secs as i64);
+            imported += db.execute(
+                "INSERT OR IGNORE INTO magic_keys (
+                    magic_key, label, created_at, created_by, max_measuring_time_secs
+                ) VALUES (?, 'Imported from configuration', ?, 'config', ?)",
+                params![magic_key, now_ms, max_measuring_time],
+            )?;
+        }
+
+        Ok(imported)
+    }
+
+    /// Get a magic key by value
+    pub async fn get(&self, magic_key: &str) -> StoreResult<Option<MagicKey>> {
+        let db = self.db.lock().await;
+        let key = db
+            .query_row(
+                &format!("{} WHERE magic_key = ?", SELECT_COLUMNS),
+                params![magic_key],
+                MagicKey::from_row,
+            )
+            .optional()?;
+        Ok(key)
+    }
+
+    /// Validate a magic key: it must exist, be enabled, not revoked and not expired
+    pub async fn validate(&self, magic_key: &str) -> Result<MagicKey, KeyRejection> {
+        let key = match self.get(magic_key).await {
+            Ok(Some(key)) => key,
+            Ok(None) => return Err(KeyRejection::Unknown),
+            Err(e) => {
+                tracing::error!("Failed to look up magic key {}: {}", magic_key, e);
+                return Err(KeyRejection::Unknown);
+            }
+        };
+        key.check_usable(chrono::Utc::now().timestamp_millis())?;
+        Ok(key)
+    }
+
+    /// Count a survey session started with a magic key
+    ///
+    /// Fails with `SessionLimitReached` (without counting) if the key has used up
+    /// its sessions, or with the reason the key is no longer usable.
+    pub async fn record_session_start(&self, magic_key: &str) -> Result<MagicKey, KeyRejection> {
+        let key = self.validate(magic_key).await?;
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis();
+
+        // Single statement so concurrent session starts cannot exceed the limit
+        let updated = db
+            .execute(
+                "UPDATE magic_keys SET session_count = session_count + 1, last_used_at = ?
+                 WHERE magic_key = ? AND (max_sessions IS NULL OR session_count < max_sessions)",
+                params![now_ms, magic_key],
+            )
+            .map_err(|e| {
+                tracing::error!("Failed to count session for magic key {}: {}", magic_key, e);
+                KeyRejection::Unknown
+            })?;
+
+        if updated == 0 {
+            return Err(KeyRejection::SessionLimitReached);
+        }
+        Ok(MagicKey {
+            session_count: key.session_count + 1,
+            last_used_at: Some(now_ms),
+            ..key
+        })
+    }
+
+    /// Maximum measuring time in seconds for a magic key: the key's own limit,
+    /// or the configured default
+    pub async fn max_measuring_time_seconds(&self, magic_key: &str) -> u64 {
+        match self.get(magic_key).await {
+            Ok(Some(MagicKey {
+                max_measuring_time_secs: Some(secs),
+                ..
+            })) => secs.max(0) as u64,
+            Ok(_) => self.config.get_max_measuring_time_seconds(magic_key),
+            Err(e) => {
+                tracing::error!("Failed to look up magic key {}: {}", magic_key, e);
+                self.config.get_max_measuring_time_seconds(magic_key)
+            }
+        }
+    }
+
+    /// List all magic keys, newest first
+    pub async fn list(&self) -> StoreResult<Vec<MagicKey>> {
+        let db = self.db.lock().await;
+        let mut stmt = db.prepare(&format!("{} ORDER BY created_at DESC", SELECT_COLUMNS))?;
+        let keys = stmt
+            .query_map([], MagicKey::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(keys)
+    }
+
+    /// Create a magic key
+    ///
+    /// Returns None if the key already exists.
+    pub async fn create(
+        &self,
+        new_key: &NewMagicKey,
+        created_by: Option<&str>,
+    ) -> StoreResult<Option<MagicKey>> {
+        let magic_key = new_key.magic_key.clone().unwrap_or_else(generate_key);
+        {
+            let db = self.db.lock().await;
+            let inserted = db.execute(
+                "INSERT OR IGNORE INTO magic_keys (
+                    magic_key, label, owner, created_at, created_by, expires_at,
+                    max_sessions, max_measuring_time_secs
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    magic_key,
+                    new_key.label,
+                    new_key.owner,
+                    chrono::Utc::now().timestamp_millis(),
+                    created_by,
+                    new_key.expires_at,
+                    new_key.max_sessions,
+                    new_key.max_measuring_time_secs,
+                ],
+            )?;
+            if inserted == 0 {
+                return Ok(None);
+            }
+        }
+        self.get(&magic_key).await
+    }
+
+    /// Revoke a magic key so it can no longer be used, including by existing
+    /// survey sessions
+    ///
+    /// Returns false if the key does not exist or was already revoked.
+    pub async fn revoke(&self, magic_key: &str, revoked_by: Option<&str>) -> StoreResult<bool> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE magic_keys SET revoked_at = ?, revoked_by = ?
+             WHERE magic_key = ? AND revoked_at IS NULL",
+            params![chrono::Utc::now().timestamp_millis(), revoked_by, magic_key],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Enable or disable a magic key without revoking it
+    ///
+    /// Returns false if the key does not exist.
+    pub async fn set_enabled(&self, magic_key: &str, enabled: bool) -> StoreResult<bool> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE magic_keys SET enabled = ? WHERE magic_key = ?",
+            params![enabled, magic_key],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Rotate a magic key: create a new random key with the same settings and
+    /// revoke the old one
+    ///
+    /// Returns None if the key does not exist or was already revoked.
+    pub async fn rotate(
+        &self,
+        magic_key: &str,
+        rotated_by: Option<&str>,
+    ) -> StoreResult<Option<MagicKey>> {
+        let new_key = generate_key();
+        {
+            let mut db = self.db.lock().await;
+            let tx = db.transaction()?;
+            let now_ms = chrono::Utc::now().timestamp_millis();
+
+            let inserted = tx.execute(
+                "INSERT INTO magic_keys (
+                    magic_key, label, owner, created_at, created_by, expires_at,
+                    max_sessions, max_measuring_time_secs, enabled
+                 )
+                 SELECT ?, label, owner, ?, ?, expires_at, max_sessions,
+                        max_measuring_time_secs, enabled
+                 FROM magic_keys WHERE magic_key = ? AND revoked_at IS NULL",
+                params![new_key, now_ms, rotated_by, magic_key],
+            )?;
+            if inserted == 0 {
+                return Ok(None);
+            }
+
+            tx.execute(
+                "UPDATE magic_keys SET revoked_at = ?, revoked_by = ?, replaced_by = ?
+                 WHERE magic_key = ?",
+                params![now_ms, rotated_by, new_key, magic_key],
+            )?;
+            tx.commit()?;
+        }
+        self.get(&new_key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_database;
+    use tempfile::NamedTempFile;
+
+    fn test_store(temp_file: &NamedTempFile) -> MagicKeyStore {
+        let db = init_database(temp_file.path()).unwrap();
+        let mut config = MagicKeyConfig {
+            magic_keys: vec!["SURVEY-001".to_string(), "DEMO".to_string()],
+            ..Default::default()
+        };
+        config
+            .magic_key_max_measuring_time
+            .insert("SURVEY-001".to_string(), 7200);
+        MagicKeyStore::new(db, config)
+    }
+
+    #[tokio::test]
+    async fn test_import_config_keys() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = test_store(&temp_file);
+
+        assert_eq!(store.import_config_keys().await.unwrap(), 2);
+        // Importing again leaves existing keys alone
+        assert_eq!(store.import_config_keys().await.unwrap(), 0);
+
+        assert!(store.validate("SURVEY-001").await.is_ok());
+        assert_eq!(store.max_measuring_time_seconds("SURVEY-001").await, 7200);
+        // DEMO keeps its built-in default
+        assert_eq!(store.max_measuring_time_seconds("DEMO").await, 120);
+        assert_eq!(
+            store.validate("SURVEY-002").await,
+            Err(KeyRejection::Unknown)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_limit() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = test_store(&temp_file);
+        let key = store
+            .create(
+                &NewMagicKey {
+                    magic_key: Some("FIELD-1".to_string()),
+                    max_sessions: Some(2),
+                    ..Default::default()
+                },
+                Some("admin"),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(key.created_by.as_deref(), Some("admin"));
+        assert!(!key.sessions_exhausted());
+
+        assert_eq!(
+            store
+                .record_session_start("FIELD-1")
+                .await
+                .unwrap()
+                .session_count,
+            1
+        );
+        assert!(store.record_session_start("FIELD-1").await.is_ok());
+        assert_eq!(
+            store.record_session_start("FIELD-1").await,
+            Err(KeyRejection::SessionLimitReached)
+        );
+
+        let key = store.validate("FIELD-1").await.unwrap();
+        assert_eq!(key.session_count, 2);
+        assert!(key.sessions_exhausted());
+    }
+
+    #[tokio::test]
+    async fn test_expired_and_disabled_keys() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = test_store(&temp_file);
+        let now_ms = chrono::Utc::now().timestamp_millis();
+
+        store
+            .create(
+                &NewMagicKey {
+                    magic_key: Some("OLD".to_string()),
+                    expires_at: Some(now_ms - 1000),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.validate("OLD").await, Err(KeyRejection::Expired));
+
+        let key = store
+            .create(&NewMagicKey::default(), None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(is_valid_key_format(&key.magic_key));
+        assert!(store.set_enabled(&key.magic_key, false).await.unwrap());
+        assert_eq!(
+            store.validate(&key.magic_key).await,
+            Err(KeyRejection::Disabled)
+        );
+
+        // Creating an existing key fails
+        assert!(store
+            .create(
+                &NewMagicKey {
+                    magic_key: Some("OLD".to_string()),
+                    ..Default::default()
+                },
+                None
+            )
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_and_rotate() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = test_store(&temp_file);
+        store.import_config_keys().await.unwrap();
+
+        let rotated = store
+            .rotate("SURVEY-001", Some("admin"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(rotated.magic_key, "SURVEY-001");
+        assert_eq!(rotated.max_measuring_time_secs, Some(7200));
+        assert_eq!(rotated.session_count, 0);
+        assert!(store.validate(&rotated.magic_key).await.is_ok());
+
+        let old = store.get("SURVEY-001").await.unwrap().unwrap();
+        assert_eq!(old.replaced_by.as_deref(), Some(rotated.magic_key.as_str()));
+        assert_eq!(
+            store.validate("SURVEY-001").await,
+            Err(KeyRejection::Revoked)
+        );
+        // A revoked key cannot be rotated or revoked again
+        assert!(store.rotate("SURVEY-001", None).await.unwrap().is_none());
+        assert!(!store.revoke("SURVEY-001", None).await.unwrap());
+
+        assert!(store.revoke("DEMO", Some("admin")).await.unwrap());
+        assert_eq!(store.validate("DEMO").await, Err(KeyRejection::Revoked));
+        assert_eq!(store.list().await.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_key_format() {
+        assert!(is_valid_key_format("SURVEY-2024-001"));
+        assert!(!is_valid_key_format(""));
+        assert!(!is_valid_key_format("SURVEY_001"));
+        assert!(!is_valid_key_format("key with spaces"));
+    }
+}
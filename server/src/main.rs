@@ -1,5 +1,10 @@
 #![deny(unused_must_use)]
 mod analyst_api;
+mod api_token_api;
+mod api_tokens;
+mod audit_api;
+mod audit_log;
+mod audit_middleware;
 mod auth_cache;
 mod auth_handlers;
 mod capture_api;
@@ -12,27 +17,47 @@ mod database;
 mod dtls_keylog;
 mod dtls_keylog_api;
 mod embedded;
+mod enrichment;
+mod ext_headers;
+mod fragmentation;
 mod icmp_listener;
+mod iperf3_results;
+mod local_user_api;
+mod local_users;
+mod lockout_api;
+mod login_attempts;
+mod magic_key_api;
+mod magic_keys;
 mod measurements;
 mod metrics_recorder;
+mod nat_behavior;
+mod nat_lifetime;
 mod packet_capture;
 mod packet_tracker;
 mod packet_tracking_api;
+mod reverse_dns;
+mod reverse_path;
+mod role_store;
 mod session_manager;
 mod signaling;
 mod state;
 mod survey_middleware;
+mod survey_tokens;
+mod totp_store;
 mod tracing_api;
 mod tracing_buffer;
 mod tracking_channel;
 mod upload_api;
 mod upload_utils;
+mod user_session_api;
+mod user_sessions;
 mod webrtc_manager;
+mod websocket_probes;
 
 use axum::{
     extract::State,
     middleware,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
     Json, Router,
 };
 use common::{ClientInfo, DashboardMessage};
@@ -47,7 +72,9 @@ use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
 use webrtc::ice_transport::ice_gathering_state::RTCIceGatheringState;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::stats::StatsReportType;
-use netpoke_auth::{auth_routes, require_auth, AuthService, AuthState};
+use netpoke_auth::{
+    auth_routes, require_auth, require_permission, AuthService, AuthState, Permission,
+};
 
 use axum::{http::uri::Uri, response::Redirect};
 use axum_server::tls_rustls::RustlsConfig;
@@ -76,6 +103,26 @@ fn get_make_service(
     storage_base_path: String,
     analyst_access: std::collections::HashMap<String, Vec<String>>,
 ) -> IntoMakeServiceWithConnectInfo<axum::Router, SocketAddr> {
+    // Database-backed magic keys, shared by survey session validation and the admin API
+    let magic_key_store = app_state.magic_key_store.clone();
+    // Issued survey session tokens, checked for revocation (only if database is available)
+    let survey_token_store = db
+        .clone()
+        .map(|db| Arc::new(survey_tokens::SurveyTokenStore::new(db)));
+
+    // Security audit log (only if database is available) - sensitive routes record
+    // who called them, from where and with what outcome
+    let audit_log = app_state.audit_log.clone();
+    let audited = |action| {
+        middleware::from_fn_with_state(
+            audit_middleware::AuditState {
+                log: audit_log.clone(),
+                action,
+            },
+            audit_middleware::audit_request,
+        )
+    };
+
     // Signaling API routes - these need to be accessible by survey users (Magic Key)
     let signaling_routes = Router::new()
         .route("/api/signaling/start", post(signaling::signaling_start))
@@ -84,6 +131,10 @@ fn get_make_service(
             "/api/signaling/ice/remote",
             post(signaling::get_ice_candidates),
         )
+        .route(
+            "/api/probe/ws",
+            get(websocket_probes::websocket_probe_handler),
+        )
         .with_state(app_state.clone());
 
     // Dashboard and admin routes - these should only be accessible by authenticated users
@@ -91,7 +142,6 @@ fn get_make_service(
         .route("/api/dashboard/ws", get(dashboard::dashboard_ws_handler))
         .route("/api/dashboard/debug", get(dashboard_debug))
         .route("/api/diagnostics", get(server_diagnostics))
-        .route("/api/clients/{id}", delete(cleanup::cleanup_client_handler))
         .route(
             "/api/tracking/events",
             get(packet_tracking_api::get_tracked_events),
@@ -100,34 +150,51 @@ fn get_make_service(
             "/api/tracking/stats",
             get(packet_tracking_api::get_tracked_stats),
         )
+        .with_state(app_state.clone());
+
+    // Client management routes - disconnecting clients requires more than viewing the dashboard
+    let client_admin_routes = Router::new()
+        .route(
+            "/api/clients/{id}",
+            delete(cleanup::cleanup_client_handler).route_layer(audited("client_cleanup")),
+        )
         .with_state(app_state);
 
     // Capture API routes for session-specific downloads - accessible with hybrid auth (both user and magic key)
     let capture_session_routes = Router::new()
         .route(
             "/api/capture/download/session",
-            get(capture_api::download_pcap_for_session),
+            get(capture_api::download_pcap_for_session).route_layer(audited("pcap_download")),
         )
         .route("/api/capture/stats", get(capture_api::capture_stats))
-        .route("/api/capture/clear", post(capture_api::clear_capture))
+        .route(
+            "/api/capture/clear",
+            post(capture_api::clear_capture).route_layer(audited("capture_clear")),
+        )
         .with_state(capture_service.clone());
 
     // Capture API routes for global download - requires full auth only
     let capture_global_routes = Router::new()
-        .route("/api/capture/download", get(capture_api::download_pcap))
+        .route(
+            "/api/capture/download",
+            get(capture_api::download_pcap).route_layer(audited("pcap_download")),
+        )
         .with_state(capture_service.clone());
 
     // Tracing API routes for session-specific stats - accessible with hybrid auth (both user and magic key)
     let tracing_session_routes = Router::new()
         .route("/api/tracing/stats", get(tracing_api::tracing_stats))
-        .route("/api/tracing/clear", post(tracing_api::clear_tracing))
+        .route(
+            "/api/tracing/clear",
+            post(tracing_api::clear_tracing).route_layer(audited("tracing_clear")),
+        )
         .with_state(tracing_service.clone());
 
     // Tracing API routes for global download - requires full auth only
     let tracing_global_routes = Router::new()
         .route(
             "/api/tracing/download",
-            get(tracing_api::download_tracing_buffer),
+            get(tracing_api::download_tracing_buffer).route_layer(audited("tracing_download")),
         )
         .with_state(tracing_service);
 
@@ -135,10 +202,14 @@ fn get_make_service(
     let keylog_routes = Router::new()
         .route(
             "/api/keylog/download/session",
-            get(dtls_keylog_api::download_keylog_for_session),
+            get(dtls_keylog_api::download_keylog_for_session)
+                .route_layer(audited("keylog_download")),
         )
         .route("/api/keylog/stats", get(dtls_keylog_api::keylog_stats))
-        .route("/api/keylog/clear", post(dtls_keylog_api::clear_keylog))
+        .route(
+            "/api/keylog/clear",
+            post(dtls_keylog_api::clear_keylog).route_layer(audited("keylog_clear")),
+        )
         .with_state(keylog_service.clone());
 
     // Upload API routes for survey recordings - always registered
@@ -157,25 +228,85 @@ fn get_make_service(
             .with_state(upload_state)
     };
 
-    // Analyst API routes for browsing survey data (only if database is available)
-    let analyst_routes: Option<Router> = db.as_ref().map(|db_conn| {
-        let analyst_state = Arc::new(analyst_api::AnalystState {
+    // Analyst API state for browsing survey data (only if database is available)
+    let analyst_state: Option<Arc<analyst_api::AnalystState>> = db.as_ref().map(|db_conn| {
+        Arc::new(analyst_api::AnalystState {
             db: db_conn.clone(),
             analyst_access: analyst_access.clone(),
             capture_service: Some(capture_service.clone()),
             keylog_service: Some(keylog_service.clone()),
-        });
+        })
+    });
+
+    // Analyst API routes for browsing survey data
+    let analyst_routes: Option<Router> = analyst_state.clone().map(|analyst_state| {
         Router::new()
             .route("/admin/api/sessions", get(analyst_api::list_sessions))
-            .route("/admin/api/sessions/{session_id}", get(analyst_api::get_session).delete(analyst_api::wipe_session))
+            .route("/admin/api/sessions/{session_id}", get(analyst_api::get_session))
             .route("/admin/api/sessions/{session_id}/metrics", get(analyst_api::get_session_metrics))
+            .route("/admin/api/sessions/{session_id}/transport", get(analyst_api::get_session_transport))
+            .route("/admin/api/sessions/{session_id}/iperf3", get(analyst_api::get_session_iperf3_tests))
+            .route("/admin/api/sessions/{session_id}/traceroute", get(analyst_api::get_session_traceroute))
+            .route("/admin/api/iperf3", get(analyst_api::list_iperf3_tests))
+            .route("/admin/api/iperf3/{test_id}", get(analyst_api::get_iperf3_test))
             .route("/admin/api/magic-keys", get(analyst_api::list_magic_keys))
             .route("/admin/api/allowed-keys", get(analyst_api::get_allowed_keys))
-            .route("/admin/api/recordings/{recording_id}/video", get(analyst_api::download_recording_video))
-            .route("/admin/api/recordings/{recording_id}/sensor", get(analyst_api::download_recording_sensor))
+            .route(
+                "/admin/api/recordings/{recording_id}/video",
+                get(analyst_api::download_recording_video).route_layer(audited("recording_download")),
+            )
+            .route(
+                "/admin/api/recordings/{recording_id}/sensor",
+                get(analyst_api::download_recording_sensor).route_layer(audited("recording_download")),
+            )
+            .with_state(analyst_state)
+    });
+
+    // Analyst API route for wiping survey sessions - destructive, kept separate from browsing
+    let analyst_wipe_routes: Option<Router> = analyst_state.map(|analyst_state| {
+        Router::new()
+            .route(
+                "/admin/api/sessions/{session_id}",
+                delete(analyst_api::wipe_session).route_layer(audited("session_wipe")),
+            )
             .with_state(analyst_state)
     });
 
+    // Magic key and survey session token admin API routes (only if database is available)
+    let magic_key_admin_routes: Option<Router> = magic_key_store
+        .clone()
+        .zip(survey_token_store.clone())
+        .map(|(store, token_store)| {
+            let token_routes = Router::new()
+                .route("/admin/api/survey-tokens", get(magic_key_api::list_survey_tokens))
+                .route(
+                    "/admin/api/survey-tokens/{token_id}/revoke",
+                    post(magic_key_api::revoke_survey_token).route_layer(audited("survey_token_revoke")),
+                )
+                .with_state(token_store);
+            Router::new()
+                .route(
+                    "/admin/api/keys",
+                    get(magic_key_api::list_keys).merge(
+                        post(magic_key_api::create_key).route_layer(audited("magic_key_create")),
+                    ),
+                )
+                .route(
+                    "/admin/api/keys/{magic_key}/enabled",
+                    put(magic_key_api::set_key_enabled).route_layer(audited("magic_key_enable")),
+                )
+                .route(
+                    "/admin/api/keys/{magic_key}/revoke",
+                    post(magic_key_api::revoke_key).route_layer(audited("magic_key_revoke")),
+                )
+                .route(
+                    "/admin/api/keys/{magic_key}/rotate",
+                    post(magic_key_api::rotate_key).route_layer(audited("magic_key_rotate")),
+                )
+                .with_state(store)
+                .merge(token_routes)
+        });
+
     // Client config API routes - public, no auth required (needed by WASM client)
     let client_config_routes = Router::new()
         .route(
@@ -196,6 +327,8 @@ fn get_make_service(
             let auth_handler_state = auth_handlers::AuthHandlerState {
                 auth_state: auth_state.clone(),
                 auth_cache: auth_cache.clone(),
+                magic_keys: magic_key_store.clone(),
+                survey_tokens: survey_token_store.clone(),
             };
 
             // Public API routes (auth status and magic key) - use _with_cache handlers
@@ -211,80 +344,252 @@ fn get_make_service(
                 )
                 .with_state(auth_handler_state);
 
+            // Each route group declares the permission it requires; regular users must
+            // have a role granting it, survey sessions (Magic Key) pass on hybrid routes
+            let hybrid_state = |permission| survey_middleware::SurveyAuthState {
+                permission: auth_state.with_permission(permission),
+                magic_keys: magic_key_store.clone(),
+                survey_tokens: survey_token_store.clone(),
+            };
+
             // Signaling routes with hybrid auth - allow EITHER regular auth OR survey session (Magic Key)
             let hybrid_signaling = signaling_routes.route_layer(middleware::from_fn_with_state(
-                auth_state.clone(),
+                hybrid_state(Permission::ViewDashboard),
                 survey_middleware::require_auth_or_survey_session,
             ));
 
             // Capture session routes with hybrid auth - allow EITHER regular auth OR survey session (Magic Key)
             let hybrid_capture_session =
                 capture_session_routes.route_layer(middleware::from_fn_with_state(
-                    auth_state.clone(),
+                    hybrid_state(Permission::ViewDashboard),
                     survey_middleware::require_auth_or_survey_session,
                 ));
 
-            // Capture global routes - require full authentication only
+            // Capture global routes - every captured packet, operators only
             let protected_capture_global =
                 capture_global_routes.route_layer(middleware::from_fn_with_state(
-                    auth_state.clone(),
-                    require_auth,
+                    auth_state.with_permission(Permission::DownloadCaptures),
+                    require_permission,
                 ));
 
             // Tracing session routes with hybrid auth - allow EITHER regular auth OR survey session (Magic Key)
             let hybrid_tracing_session =
                 tracing_session_routes.route_layer(middleware::from_fn_with_state(
-                    auth_state.clone(),
+                    hybrid_state(Permission::ViewDashboard),
                     survey_middleware::require_auth_or_survey_session,
                 ));
 
-            // Tracing global routes - require full authentication only
+            // Tracing global routes - the whole tracing buffer, operators only
             let protected_tracing_global =
                 tracing_global_routes.route_layer(middleware::from_fn_with_state(
-                    auth_state.clone(),
-                    require_auth,
+                    auth_state.with_permission(Permission::DownloadCaptures),
+                    require_permission,
                 ));
 
-            // Keylog routes with hybrid auth - allow EITHER regular auth OR survey session (Magic Key)
+            // Keylog routes with hybrid auth - survey sessions OR regular users allowed to download keylogs
             let hybrid_keylog = keylog_routes.route_layer(middleware::from_fn_with_state(
-                auth_state.clone(),
+                hybrid_state(Permission::DownloadKeylogs),
                 survey_middleware::require_auth_or_survey_session,
             ));
 
             // Protected dashboard routes - require full authentication
             let protected_dashboard = dashboard_routes.route_layer(middleware::from_fn_with_state(
-                auth_state.clone(),
-                require_auth,
+                auth_state.with_permission(Permission::ViewDashboard),
+                require_permission,
             ));
 
+            // Client cleanup routes - operators only
+            let protected_client_admin =
+                client_admin_routes.route_layer(middleware::from_fn_with_state(
+                    auth_state.with_permission(Permission::ManageClients),
+                    require_permission,
+                ));
+
             // Upload routes with hybrid auth - allow EITHER regular auth OR survey session (Magic Key)
             let hybrid_upload = upload_routes.route_layer(middleware::from_fn_with_state(
-                auth_state.clone(),
+                hybrid_state(Permission::ViewDashboard),
                 survey_middleware::require_auth_or_survey_session,
             ));
 
-            // Analyst routes - require full authentication
+            // Analyst routes - require a role allowed to browse surveys
             let protected_analyst = analyst_routes.map(|routes| {
                 routes.route_layer(middleware::from_fn_with_state(
-                    auth_state.clone(),
-                    require_auth,
+                    auth_state.with_permission(Permission::ViewSurveys),
+                    require_permission,
                 ))
             });
 
-            // Admin surveys page - require full authentication
+            // Magic key management - admins only
+            let protected_magic_key_admin = magic_key_admin_routes.map(|routes| {
+                routes.route_layer(middleware::from_fn_with_state(
+                    auth_state.with_permission(Permission::ManageMagicKeys),
+                    require_permission,
+                ))
+            });
+
+            // Analyst wipe route - admins only
+            let protected_analyst_wipe = analyst_wipe_routes.map(|routes| {
+                routes.route_layer(middleware::from_fn_with_state(
+                    auth_state.with_permission(Permission::WipeSessions),
+                    require_permission,
+                ))
+            });
+
+            // Login sessions (only if tracked server-side in the database) - every user
+            // manages their own sessions, admins those of any user
+            let session_db = db.clone().filter(|_| auth_state.config.session.server_side);
+            let user_session_routes: Option<Router> = session_db.map(|db| {
+                let store = Arc::new(user_sessions::DbSessionStore::new(
+                    db,
+                    auth_state.config.session.timeout_seconds,
+                ));
+                let own_sessions = Router::new()
+                    .route("/api/sessions", get(user_session_api::list_own_sessions))
+                    .route(
+                        "/api/sessions/revoke-all",
+                        post(user_session_api::revoke_own_sessions).route_layer(audited("session_revoke")),
+                    )
+                    .route(
+                        "/api/sessions/{session_id}/revoke",
+                        post(user_session_api::revoke_own_session).route_layer(audited("session_revoke")),
+                    )
+                    .route_layer(middleware::from_fn_with_state(auth_state.clone(), require_auth))
+                    .with_state(store.clone());
+                let user_sessions_admin = Router::new()
+                    .route(
+                        "/admin/api/users/{handle}/sessions",
+                        get(user_session_api::list_user_sessions),
+                    )
+                    .route(
+                        "/admin/api/users/{handle}/sessions/revoke",
+                        post(user_session_api::revoke_user_sessions).route_layer(audited("session_revoke")),
+                    )
+                    .route(
+                        "/admin/api/users/{handle}/sessions/{session_id}/revoke",
+                        post(user_session_api::revoke_user_session).route_layer(audited("session_revoke")),
+                    )
+                    .route_layer(middleware::from_fn_with_state(
+                        auth_state.with_permission(Permission::ManageSessions),
+                        require_permission,
+                    ))
+                    .with_state(store);
+                own_sessions.merge(user_sessions_admin)
+            });
+
+            // Personal API tokens (only if database is available) - every user mints and
+            // revokes their own tokens, admins can revoke those of any user
+            let api_token_routes: Option<Router> = db.clone().map(|db| {
+                let state = Arc::new(api_token_api::ApiTokenApiState {
+                    store: Arc::new(api_tokens::DbApiTokenStore::new(db)),
+                    auth_state: auth_state.clone(),
+                });
+                let own_tokens = Router::new()
+                    .route("/admin/tokens", get(serve_admin_tokens))
+                    .route(
+                        "/api/tokens",
+                        get(api_token_api::list_own_tokens).merge(
+                            post(api_token_api::create_own_token).route_layer(audited("api_token_create")),
+                        ),
+                    )
+                    .route(
+                        "/api/tokens/{token_id}/revoke",
+                        post(api_token_api::revoke_own_token).route_layer(audited("api_token_revoke")),
+                    )
+                    .route_layer(middleware::from_fn_with_state(auth_state.clone(), require_auth))
+                    .with_state(state.clone());
+                let api_tokens_admin = Router::new()
+                    .route("/admin/api/tokens", get(api_token_api::list_all_tokens))
+                    .route(
+                        "/admin/api/tokens/{token_id}/revoke",
+                        post(api_token_api::revoke_any_token).route_layer(audited("api_token_revoke")),
+                    )
+                    .route_layer(middleware::from_fn_with_state(
+                        auth_state.with_permission(Permission::ManageSessions),
+                        require_permission,
+                    ))
+                    .with_state(state);
+                own_tokens.merge(api_tokens_admin)
+            });
+
+            // Local users (only if database is available) - admins only
+            let local_user_routes: Option<Router> = db.clone().map(|db| {
+                let state = Arc::new(local_user_api::LocalUserApiState {
+                    store: Arc::new(local_users::DbLocalUserStore::new(db.clone())),
+                    sessions: Arc::new(user_sessions::DbSessionStore::new(
+                        db.clone(),
+                        auth_state.config.session.timeout_seconds,
+                    )),
+                    api_tokens: Arc::new(api_tokens::DbApiTokenStore::new(db)),
+                    auth_state: auth_state.clone(),
+                });
+                Router::new()
+                    .route(
+                        "/admin/api/users",
+                        get(local_user_api::list_users).merge(
+                            post(local_user_api::create_user).route_layer(audited("user_create")),
+                        ),
+                    )
+                    .route(
+                        "/admin/api/users/{handle}/enabled",
+                        put(local_user_api::set_user_enabled).route_layer(audited("user_enable")),
+                    )
+                    .route(
+                        "/admin/api/users/{handle}/reset-password",
+                        post(local_user_api::reset_user_password)
+                            .route_layer(audited("user_password_reset")),
+                    )
+                    .route_layer(middleware::from_fn_with_state(
+                        auth_state.with_permission(Permission::ManageUsers),
+                        require_permission,
+                    ))
+                    .with_state(state)
+            });
+
+            // Login lockouts (only if database is available) - admins only
+            let lockout_routes: Option<Router> = db.clone().map(|db| {
+                Router::new()
+                    .route("/admin/api/lockouts", get(lockout_api::list_lockouts))
+                    .route(
+                        "/admin/api/lockouts/clear",
+                        post(lockout_api::clear_lockout).route_layer(audited("lockout_clear")),
+                    )
+                    .route_layer(middleware::from_fn_with_state(
+                        auth_state.with_permission(Permission::ManageUsers),
+                        require_permission,
+                    ))
+                    .with_state(Arc::new(login_attempts::DbLoginAttemptStore::new(db)))
+            });
+
+            // Security audit log (only if database is available) - admins only
+            let audit_routes: Option<Router> = audit_log.clone().map(|log| {
+                Router::new()
+                    .route("/admin/api/audit", get(audit_api::query_audit_log))
+                    .route(
+                        "/admin/api/audit/export",
+                        get(audit_api::export_audit_log).route_layer(audited("audit_export")),
+                    )
+                    .route("/admin/api/audit/verify", get(audit_api::verify_audit_log))
+                    .route_layer(middleware::from_fn_with_state(
+                        auth_state.with_permission(Permission::ViewAuditLog),
+                        require_permission,
+                    ))
+                    .with_state(log)
+            });
+
+            // Admin surveys page - require a role allowed to browse surveys
             let admin_surveys_route = Router::new()
                 .route("/admin/surveys", get(serve_admin_surveys))
                 .route_layer(middleware::from_fn_with_state(
-                    auth_state.clone(),
-                    require_auth,
+                    auth_state.with_permission(Permission::ViewSurveys),
+                    require_permission,
                 ));
 
             // Protected static files - require authentication
             let protected_static = Router::new()
                 .route("/static/{*path}", get(embedded::serve_static))
                 .route_layer(middleware::from_fn_with_state(
-                    auth_state.clone(),
-                    require_auth,
+                    auth_state.with_permission(Permission::ViewDashboard),
+                    require_permission,
                 ));
 
             // Network test page and its dependencies - allow EITHER regular auth OR survey session (Magic Key)
@@ -292,11 +597,11 @@ fn get_make_service(
                 .route("/static/nettest.html", get(serve_nettest_html))
                 .route("/static/lib/{*path}", get(serve_static_lib))
                 .route_layer(middleware::from_fn_with_state(
-                    auth_state.clone(),
+                    hybrid_state(Permission::ViewDashboard),
                     survey_middleware::require_auth_or_survey_session,
                 ));
 
-            // Combine: auth routes (public) + public API + public static files + client config (public) + nettest (hybrid auth) + signaling (hybrid auth) + capture session (hybrid auth) + capture global (protected) + tracing session (hybrid auth) + tracing global (protected) + keylog (hybrid auth) + upload (hybrid auth) + analyst (protected) + dashboard (protected) + static (protected)
+            // Combine: auth routes (public) + public API + public static files + client config (public) + nettest (hybrid auth) + signaling (hybrid auth) + capture session (hybrid auth) + capture global (protected) + tracing session (hybrid auth) + tracing global (protected) + keylog (hybrid auth) + upload (hybrid auth) + analyst (protected) + dashboard (protected) + client cleanup (protected) + static (protected)
             let mut router = Router::new()
                 .nest("/auth", auth_router)
                 .merge(public_api)
@@ -312,6 +617,7 @@ fn get_make_service(
                 .merge(protected_tracing_global)
                 .merge(hybrid_keylog)
                 .merge(protected_dashboard)
+                .merge(protected_client_admin)
                 .merge(protected_static)
                 .merge(hybrid_upload) // Upload routes always registered
                 .merge(admin_surveys_route);
@@ -320,6 +626,27 @@ fn get_make_service(
             if let Some(analyst) = protected_analyst {
                 router = router.merge(analyst);
             }
+            if let Some(analyst_wipe) = protected_analyst_wipe {
+                router = router.merge(analyst_wipe);
+            }
+            if let Some(magic_key_admin) = protected_magic_key_admin {
+                router = router.merge(magic_key_admin);
+            }
+            if let Some(user_sessions) = user_session_routes {
+                router = router.merge(user_sessions);
+            }
+            if let Some(api_tokens) = api_token_routes {
+                router = router.merge(api_tokens);
+            }
+            if let Some(local_users) = local_user_routes {
+                router = router.merge(local_users);
+            }
+            if let Some(lockouts) = lockout_routes {
+                router = router.merge(lockouts);
+            }
+            if let Some(audit) = audit_routes {
+                router = router.merge(audit);
+            }
 
             router.layer(TraceLayer::new_for_http())
         } else {
@@ -328,6 +655,7 @@ fn get_make_service(
                 .route("/health", get(health_check))
                 .merge(signaling_routes)
                 .merge(dashboard_routes)
+                .merge(client_admin_routes)
                 .merge(capture_session_routes)
                 .merge(capture_global_routes)
                 .merge(tracing_session_routes)
@@ -344,6 +672,12 @@ fn get_make_service(
             if let Some(analyst) = analyst_routes {
                 router = router.merge(analyst);
             }
+            if let Some(analyst_wipe) = analyst_wipe_routes {
+                router = router.merge(analyst_wipe);
+            }
+            if let Some(magic_key_admin) = magic_key_admin_routes {
+                router = router.merge(magic_key_admin);
+            }
 
             router.layer(TraceLayer::new_for_http())
         }
@@ -353,6 +687,7 @@ fn get_make_service(
             .route("/health", get(health_check))
             .merge(signaling_routes)
             .merge(dashboard_routes)
+            .merge(client_admin_routes)
             .merge(capture_session_routes)
             .merge(capture_global_routes)
             .merge(tracing_session_routes)
@@ -369,6 +704,12 @@ fn get_make_service(
         if let Some(analyst) = analyst_routes {
             router = router.merge(analyst);
         }
+        if let Some(analyst_wipe) = analyst_wipe_routes {
+            router = router.merge(analyst_wipe);
+        }
+        if let Some(magic_key_admin) = magic_key_admin_routes {
+            router = router.merge(magic_key_admin);
+        }
 
         router.layer(TraceLayer::new_for_http())
     };
@@ -721,40 +1062,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Initialize the global tracking callback for UDP-to-ICMP communication
     let tracking_sender = app_state.tracking_sender.clone();
-    tracking_channel::init_tracking_callback(
-        move |dest_addr, src_addr, udp_length, ttl, cleartext, sent_at, conn_id, udp_checksum| {
-            use crate::packet_tracker::UdpPacketInfo;
-            use common::SendOptions;
-
-            // Only track if TTL is set (indicating traceroute probe)
-            if let Some(ttl_value) = ttl {
-                let info = UdpPacketInfo {
-                    dest_addr,
-                    src_addr,
-                    udp_length,
-                    cleartext,
-                    send_options: SendOptions {
-                        ttl: Some(ttl_value),
-                        df_bit: Some(true),
-                        tos: None,
-                        flow_label: None,
-                        track_for_ms: 5000,               // Track for 5 seconds
-                        bypass_dtls: false,               // For testing, use DTLS encryption
-                        bypass_sctp_fragmentation: false, // Use normal SCTP fragmentation
-                    },
-                    sent_at,
-                    conn_id,
-                    udp_checksum,
-                };
-
-                if let Err(e) = tracking_sender.send(info) {
-                    tracing::error!("Failed to send tracking info: {}", e);
-                }
-            }
-        },
-    );
+    tracking_channel::init_tracking_callback(move |info| {
+        // The UDP layer only reports packets whose send options ask for tracking
+        if let Err(e) = tracking_sender.send(info) {
+            tracing::error!("Failed to send tracking info: {}", e);
+        }
+    });
     tracing::info!("Global tracking callback initialized");
 
+    // Let the UDP layer report the TTL of received packets for reverse path analysis
+    reverse_path::init_received_ttls(app_state.received_ttls.clone());
+
     icmp_listener::start_icmp_listener(app_state.packet_tracker.clone());
     tracing::info!("Packet tracking and ICMP listener initialized");
 
@@ -831,9 +1149,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         None
     };
 
+    // STUN behavior discovery responder for NAT type classification
+    if config.nat_behavior.enabled {
+        match nat_behavior::NatBehaviorResponder::start(&config.nat_behavior) {
+            Ok(responder) => {
+                tracing::info!(
+                    "NAT behavior responder listening on ports {:?}",
+                    responder.ports()
+                );
+                app_state.set_nat_behavior(responder);
+            }
+            Err(e) => tracing::error!("Failed to start NAT behavior responder: {}", e),
+        }
+    }
+
     // Create client config state from configuration
     let client_config_state = Arc::new(ClientConfigState {
         webrtc_connection_delay_ms: config.client.webrtc_connection_delay_ms,
+        nat_behavior_ports: app_state
+            .nat_behavior
+            .as_ref()
+            .map(|responder| responder.ports().to_vec())
+            .unwrap_or_default(),
+        nat_behavior_alternate_addresses: app_state
+            .nat_behavior
+            .as_ref()
+            .map(|responder| {
+                responder
+                    .alternate_addresses()
+                    .iter()
+                    .map(|address| address.to_string())
+                    .collect()
+            })
+            .unwrap_or_default(),
     });
     tracing::info!("Client configuration:");
     tracing::info!(
@@ -871,6 +1219,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                         None,
                                         "webrtc".to_string(),
                                     );
+
+                                    // Remember the survey session so iperf3 results can be linked to it
+                                    let survey_session_id = session.survey_session_id.read().await.clone();
+                                    if !survey_session_id.is_empty() {
+                                        cache_updater.set_survey_session(peer_ip, survey_session_id);
+                                    }
                                 }
                             }
                         }
@@ -906,6 +1260,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 config.iperf3.auth_timeout_secs
             );
         }
+        tracing::info!("  Max tests per IP: {}", config.iperf3.max_tests_per_ip);
+        tracing::info!("  Daily bytes per IP: {}", config.iperf3.daily_bytes_per_ip);
+        tracing::info!("  Cooldown: {} seconds", config.iperf3.cooldown_secs);
+        if !config.iperf3.magic_key_max_bandwidth.is_empty() {
+            tracing::info!(
+                "  Per-magic-key bandwidth limits: {} key(s)",
+                config.iperf3.magic_key_max_bandwidth.len()
+            );
+        }
 
         let iperf3 = Arc::new(iperf3_server::Iperf3Server::new(config.iperf3.clone()));
 
@@ -913,6 +1276,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // to check against the authenticated address cache
         if config.iperf3.require_auth {
             if let Some(cache) = auth_cache.clone() {
+                let key_cache = cache.clone();
+
                 // Set up the sync auth callback that checks the cache and logs the user
                 let auth_callback: iperf3_server::server::AuthCallback = Arc::new(move |ip| {
                     // Check if this IP is in the authenticated cache
@@ -934,10 +1299,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 });
 
-                // Set the callback asynchronously
+                // Look up the magic key of the survey session token that authorized the
+                // address, so the key's bandwidth limit applies to its tests
+                let magic_key_callback: iperf3_server::server::MagicKeyCallback =
+                    Arc::new(move |ip| key_cache.check_auth(ip)?.magic_key);
+
+                // Set the callbacks asynchronously
                 let iperf3_clone = iperf3.clone();
                 tokio::spawn(async move {
                     iperf3_clone.set_auth_callback(auth_callback).await;
+                    iperf3_clone.set_magic_key_callback(magic_key_callback).await;
                 });
             }
         }
@@ -976,6 +1347,76 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    // Security audit log of logins, downloads and administrative actions
+    let audit_log = db
+        .as_ref()
+        .filter(|_| config.auth.audit.enabled)
+        .map(|db_conn| {
+            Arc::new(audit_log::DbAuditLog::new(
+                db_conn.clone(),
+                config.auth.audit.hash_chain,
+            ))
+        });
+    if let Some(audit_log) = &audit_log {
+        app_state.set_audit_log(audit_log.clone());
+        tracing::info!(
+            "Audit log: database{}",
+            if config.auth.audit.hash_chain { " (hash-chained)" } else { "" }
+        );
+    }
+
+    // Role assignments from the database complement the [auth.roles] configuration
+    if let (Some(auth_state), Some(db_conn)) = (&auth_service, &db) {
+        auth_state.set_role_store(Arc::new(role_store::DbRoleStore::new(db_conn.clone())));
+        tracing::info!("Role assignments: configuration and database");
+
+        // Sessions tracked server-side can be revoked and time out when idle
+        if auth_state.config.session.server_side {
+            auth_state.set_session_store(Arc::new(user_sessions::DbSessionStore::new(
+                db_conn.clone(),
+                auth_state.config.session.timeout_seconds,
+            )));
+            tracing::info!("Login sessions: database");
+        } else if auth_state.config.session.idle_timeout_seconds.is_some() {
+            tracing::warn!("Session idle_timeout_seconds requires server_side sessions, ignoring it");
+        }
+
+        // Personal API tokens are accepted as bearer tokens
+        auth_state.set_api_token_store(Arc::new(api_tokens::DbApiTokenStore::new(db_conn.clone())));
+        tracing::info!("API tokens: database");
+
+        if auth_state.config.plain_login.totp.enabled {
+            auth_state.set_totp_store(Arc::new(totp_store::DbTotpStore::new(db_conn.clone())));
+            tracing::info!("TOTP second factor: database");
+        }
+
+        if auth_state.config.plain_login.enabled {
+            auth_state.set_local_user_store(Arc::new(local_users::DbLocalUserStore::new(db_conn.clone())));
+            tracing::info!("Plain login users: configuration and database");
+        }
+
+        // Logins, lockouts and denied permissions go to the security audit log
+        if let Some(audit_log) = &audit_log {
+            auth_state.set_audit_log(audit_log.clone());
+        }
+
+        // Failed login attempts are shared by all workers and survive restarts
+        if auth_state.config.lockout.enabled {
+            auth_state.set_login_attempt_store(Arc::new(login_attempts::DbLoginAttemptStore::new(db_conn.clone())));
+            tracing::info!("Login lockouts: database");
+        }
+    } else if let Some(auth_state) = &auth_service {
+        if auth_state.config.session.server_side {
+            tracing::warn!("Server-side sessions require the database, sessions are cookie-only");
+        }
+        if auth_state.config.session.idle_timeout_seconds.is_some() {
+            tracing::warn!("Session idle_timeout_seconds requires the database, ignoring it");
+        }
+        if auth_state.config.plain_login.totp.enabled {
+            tracing::warn!("TOTP requires the database; users it is required for cannot log in");
+        }
+    }
+
     // Initialize session manager and metrics recorder if database is available
     if let Some(ref db_conn) = db {
         let session_manager = Arc::new(SessionManager::new(db_conn.clone()));
@@ -985,6 +1426,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         tracing::info!("Session manager and metrics recorder initialized");
     }
 
+    // Reverse DNS names of traceroute hops
+    if config.reverse_dns.enabled {
+        let resolver = Arc::new(reverse_dns::ReverseDnsResolver::new(&config.reverse_dns));
+        app_state.set_reverse_dns(
+            resolver,
+            std::time::Duration::from_millis(config.reverse_dns.round_budget_ms),
+        );
+        tracing::info!("Reverse DNS of traceroute hops enabled");
+    }
+
+    // ASN, prefix and geolocation enrichment of traceroute hops and peer addresses
+    if config.enrichment.asn_table_path.is_some() || config.enrichment.geo_database_path.is_some() {
+        let enrichment = Arc::new(enrichment::EnrichmentService::new(&config.enrichment));
+        enrichment.spawn_refresh();
+        app_state.set_enrichment(enrichment);
+        tracing::info!(
+            "Address enrichment initialized (refresh every {}s)",
+            config.enrichment.refresh_interval_secs
+        );
+    }
+
+    // Persist completed iperf3 tests if both the iperf3 server and database are available
+    if let (Some(iperf3), Some(db_conn)) = (&iperf3_server, &db) {
+        let recorder = Arc::new(iperf3_results::Iperf3ResultRecorder::new(db_conn.clone()));
+        let cache = auth_cache.clone();
+        let result_callback: iperf3_server::server::ResultCallback = Arc::new(move |record| {
+            let recorder = recorder.clone();
+            let auth_info = cache
+                .as_ref()
+                .and_then(|c| c.check_auth(record.client_addr.ip()));
+            tokio::spawn(async move {
+                if let Err(e) = recorder.record_test(&record, auth_info.as_ref()).await {
+                    tracing::error!("Failed to record iperf3 test {}: {}", record.cookie, e);
+                }
+            });
+        });
+        iperf3.set_result_callback(result_callback).await;
+        tracing::info!("iperf3 result recorder initialized");
+    }
+
     // Set magic key configuration for measuring time limits
     app_state.set_magic_key_config(config.auth.magic_keys.clone());
     tracing::info!(
@@ -993,6 +1474,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config.auth.magic_keys.magic_key_max_measuring_time
     );
 
+    // Magic keys are managed in the database when it is available; keys from the
+    // configuration are imported once and then managed through the admin API
+    if let Some(ref db_conn) = db {
+        let store = Arc::new(magic_keys::MagicKeyStore::new(
+            db_conn.clone(),
+            config.auth.magic_keys.clone(),
+        ));
+        match store.import_config_keys().await {
+            Ok(0) => {}
+            Ok(imported) => tracing::info!("Imported {} magic keys from configuration", imported),
+            Err(e) => tracing::error!("Failed to import magic keys from configuration: {}", e),
+        }
+        app_state.set_magic_key_store(store);
+        tracing::info!("Magic keys: database");
+
+        // Survey sessions recorded before survey session tokens carried the magic
+        // key in their session id; recover it for those stored without one
+        let token_store = survey_tokens::SurveyTokenStore::new(db_conn.clone());
+        match token_store.migrate_legacy_session_ids().await {
+            Ok(0) => {}
+            Ok(migrated) => tracing::info!(
+                "Recovered magic key of {} survey sessions with legacy session ids",
+                migrated
+            ),
+            Err(e) => tracing::error!("Failed to migrate legacy survey session ids: {}", e),
+        }
+    }
+
     // Storage path for uploads
     let storage_base_path = config.storage.base_path.clone();
     if db.is_some() {
@@ -1110,6 +1619,11 @@ async fn serve_admin_surveys() -> impl axum::response::IntoResponse {
     embedded::embedded_file_response("admin/surveys.html")
 }
 
+/// Serve the API tokens page from embedded assets
+async fn serve_admin_tokens() -> impl axum::response::IntoResponse {
+    embedded::embedded_file_response("admin/tokens.html")
+}
+
 /// Serve files from the static/lib directory
 async fn serve_static_lib(
     axum::extract::Path(path): axum::extract::Path<String>,
@@ -1228,6 +1742,10 @@ async fn dashboard_debug(State(state): State<AppState>) -> Json<DashboardMessage
             ip_version: session.ip_version.clone(),
             connected_at,
             metrics,
+            peer_enrichment: state
+                .enrichment
+                .as_ref()
+                .and_then(|enrichment| enrichment.lookup_str(&peer_address_final)),
             peer_address: Some(peer_address_final),
             peer_port,
             current_seq,
@@ -1373,6 +1891,7 @@ async fn server_diagnostics(State(state): State<AppState>) -> Json<common::Serve
             data_channels: data_channel_status,
             icmp_error_count,
             last_icmp_error_secs_ago,
+            reverse_path: reverse_path::session_reverse_path(session).await,
         });
     }
 
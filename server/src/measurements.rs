@@ -1,10 +1,12 @@
-use crate::state::{ClientSession, ReceivedBulk, ReceivedProbe, SentBulk};
+use crate::reverse_dns::ResolveBudget;
+use crate::state::{ClientSession, MeasurementState, ReceivedBulk, ReceivedProbe, SentBulk};
 use common::{BulkPacket, ClientMetrics, Direction, ProbePacket};
 use std::sync::Arc;
 use tokio::time::{interval, Duration};
 use webrtc::data_channel::data_channel_message::DataChannelMessage;
 use webrtc::data_channel::data_channel_state::RTCDataChannelState;
 use webrtc::data_channel::RTCDataChannel;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 
 pub async fn start_probe_sender(session: Arc<ClientSession>) {
     let mut interval = interval(Duration::from_millis(50)); // 20 Hz
@@ -375,11 +377,98 @@ async fn calculate_metrics(session: Arc<ClientSession>) {
     *session.metrics.write().await = metrics;
 }
 
-fn format_traceroute_message(hop: u8, router_ip: &Option<String>, rtt_ms: f64) -> String {
-    if let Some(ip) = router_ip {
+fn format_traceroute_message(
+    hop: u8,
+    router_ip: &Option<String>,
+    hostname: Option<&str>,
+    rtt_ms: f64,
+    mpls_labels: &[common::MplsLabelEntry],
+) -> String {
+    let mut message = if let (Some(ip), Some(hostname)) = (router_ip, hostname) {
+        format!("Hop {} via {} ({}) ({:.2}ms)", hop, hostname, ip, rtt_ms)
+    } else if let Some(ip) = router_ip {
         format!("Hop {} via {} ({:.2}ms)", hop, ip, rtt_ms)
     } else {
         format!("Hop {} received ({:.2}ms)", hop, rtt_ms)
+    };
+    if !mpls_labels.is_empty() {
+        let labels: Vec<String> = mpls_labels
+            .iter()
+            .map(|entry| {
+                format!(
+                    "L={} TC={} S={} TTL={}",
+                    entry.label, entry.tc, entry.bottom_of_stack as u8, entry.ttl
+                )
+            })
+            .collect();
+        message.push_str(&format!(" [MPLS {}]", labels.join(", ")));
+    }
+    message
+}
+
+/// Record the client peer address of the session, with its enrichment, once
+/// per survey session and address
+pub async fn record_peer_address(session: &ClientSession, survey_session_id: &str) {
+    let Some(metrics_recorder) = &session.metrics_recorder else {
+        return;
+    };
+    if survey_session_id.is_empty() {
+        return;
+    }
+    let Some((address, _)) = session.peer_address.lock().await.clone() else {
+        return;
+    };
+    let key = format!("{}|{}", survey_session_id, address);
+    let mut recorded_peer = session.recorded_peer.lock().await;
+    if recorded_peer.as_deref() == Some(key.as_str()) {
+        return;
+    }
+
+    let enrichment = session
+        .enrichment
+        .as_ref()
+        .and_then(|enrichment| enrichment.lookup_str(&address));
+    match metrics_recorder
+        .record_peer_address(
+            survey_session_id,
+            &session.conn_id,
+            &address,
+            enrichment.as_ref(),
+        )
+        .await
+    {
+        Ok(()) => *recorded_peer = Some(key),
+        Err(e) => tracing::error!("Failed to record peer address: {}", e),
+    }
+}
+
+/// Record the reverse path hop count of the session, once packets were
+/// received from the client
+async fn record_reverse_path(session: &ClientSession, survey_session_id: &str, timestamp_ms: u64) {
+    let Some(metrics_recorder) = &session.metrics_recorder else {
+        return;
+    };
+    let Some(reverse_path) = crate::reverse_path::session_reverse_path(session).await else {
+        return;
+    };
+    if reverse_path.asymmetric == Some(true) {
+        tracing::debug!(
+            "Asymmetric path for session {}: {} hops back, {:?} hops there",
+            session.id,
+            reverse_path.reverse_hops,
+            reverse_path.forward_hops
+        );
+    }
+    if let Err(e) = metrics_recorder
+        .record_reverse_path(
+            survey_session_id,
+            &session.conn_id,
+            timestamp_ms,
+            &reverse_path,
+        )
+        .await
+    {
+        tracing::error!("Failed to record reverse path: {}", e);
     }
 }
 
@@ -387,6 +476,7 @@ pub async fn drain_traceroute_events(
     session: Arc<ClientSession>,
     control_channel: Arc<RTCDataChannel>,
     survey_session_id: &str,
+    dns_budget: &mut ResolveBudget,
 ) -> i32 {
     let mut n_events = 0;
 
@@ -396,23 +486,68 @@ pub async fn drain_traceroute_events(
         .drain_events_for_conn_id(&session.conn_id)
         .await;
 
+    // Resolve the hop names of this batch, within the budget of the round
+    let hostnames = match &session.reverse_dns {
+        Some((resolver, _)) if !events.is_empty() => {
+            let ips: Vec<std::net::IpAddr> = events
+                .iter()
+                .filter_map(|event| event.router_ip.as_deref()?.parse().ok())
+                .collect();
+            resolver.resolve_within(&ips, dns_budget).await
+        }
+        _ => Default::default(),
+    };
+
     for event in events {
         let hop = event.send_options.ttl.expect("TTL should be set");
         let rtt = event.icmp_received_at.duration_since(event.sent_at);
         let rtt_ms = rtt.as_secs_f64() * 1000.0;
 
-        let hop_message = common::ControlMessage::TraceHop(common::TraceHopMessage {
+        let enrichment = session
+            .enrichment
+            .as_ref()
+            .zip(event.router_ip.as_deref())
+            .and_then(|(enrichment, ip)| enrichment.lookup_str(ip));
+        let hostname = event
+            .router_ip
+            .as_deref()
+            .and_then(|ip| ip.parse().ok())
+            .and_then(|ip| hostnames.get(&ip).cloned());
+
+        let hop_message = common::TraceHopMessage {
             hop,
             ip_address: event.router_ip.clone(),
             rtt_ms,
-            message: format_traceroute_message(hop, &event.router_ip, rtt_ms),
+            message: format_traceroute_message(
+                hop,
+                &event.router_ip,
+                hostname.as_deref(),
+                rtt_ms,
+                &event.icmp_extensions.mpls_labels,
+            ),
             conn_id: event.conn_id.clone(),
             survey_session_id: survey_session_id.to_string(),
             original_src_port: event.original_src_port,
             original_dest_addr: event.original_dest_addr.clone(),
-        });
+            mpls_labels: event.icmp_extensions.mpls_labels.clone(),
+            interfaces: event.icmp_extensions.interfaces.clone(),
+            enrichment,
+            hostname,
+        };
         n_events += 1;
 
+        if let Some(metrics_recorder) = &session.metrics_recorder {
+            if !survey_session_id.is_empty() {
+                if let Err(e) = metrics_recorder
+                    .record_traceroute_hop(survey_session_id, current_time_ms(), &hop_message)
+                    .await
+                {
+                    tracing::error!("Failed to record traceroute hop: {}", e);
+                }
+            }
+        }
+        let hop_message = common::ControlMessage::TraceHop(hop_message);
+
         if let Ok(msg_json) = serde_json::to_vec(&hop_message) {
             if let Err(e) = control_channel.send(&msg_json.into()).await {
                 tracing::error!("Failed to send hop message: {}", e);
@@ -434,6 +569,14 @@ pub async fn run_single_traceroute_round(session: Arc<ClientSession>) {
 
     // Get the survey session ID for messages
     let survey_session_id = session.survey_session_id.read().await.clone();
+    record_peer_address(&session, &survey_session_id).await;
+    let mut dns_budget = ResolveBudget::new(
+        session
+            .reverse_dns
+            .as_ref()
+            .map(|(_, round_budget)| *round_budget)
+            .unwrap_or_default(),
+    );
 
     let control_channel = {
         // Check if control channel is ready
@@ -506,6 +649,9 @@ pub async fn run_single_traceroute_round(session: Arc<ClientSession>) {
             track_for_ms: 5000,
             bypass_dtls: false, // Regular traceroute uses DTLS encryption
             bypass_sctp_fragmentation: false, // Use normal SCTP fragmentation
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         let testprobe = common::TestProbePacket {
@@ -533,6 +679,10 @@ pub async fn run_single_traceroute_round(session: Arc<ClientSession>) {
                     conn_id: session.conn_id.clone(),
                     bypass_dtls: false, // Regular traceroute uses DTLS encryption
                     bypass_sctp_fragmentation: false, // Use normal SCTP fragmentation
+                    ipv6_hop_by_hop_len: None,
+                    ipv6_dest_opts_len: None,
+                    out_of_order_fragment_size: None,
+                    track_for_ms: None, // Tracked by default as the TTL is set
                 });
                 testprobe_channel
                     .send_with_options(&json.into(), options)
@@ -554,6 +704,7 @@ pub async fn run_single_traceroute_round(session: Arc<ClientSession>) {
                 session.clone(),
                 control_channel.clone(),
                 &survey_session_id,
+                &mut dns_budget,
             )
             .await;
         }
@@ -561,9 +712,13 @@ pub async fn run_single_traceroute_round(session: Arc<ClientSession>) {
 
     let mut trace_drain_count = 2000 / TRC_DRAIN_INTERVAL_MS;
     loop {
-        n_probes_out -=
-            drain_traceroute_events(session.clone(), control_channel.clone(), &survey_session_id)
-                .await;
+        n_probes_out -= drain_traceroute_events(
+            session.clone(),
+            control_channel.clone(),
+            &survey_session_id,
+            &mut dns_budget,
+        )
+        .await;
         tokio::time::sleep(Duration::from_millis(TRC_DRAIN_INTERVAL_MS)).await;
         let path_ttl = {
             let mut state = session.measurement_state.read().await;
@@ -641,6 +796,11 @@ pub async fn drain_mtu_events(
             conn_id: event.conn_id.clone(),
             survey_session_id: survey_session_id.to_string(),
             packet_size,
+            enrichment: session
+                .enrichment
+                .as_ref()
+                .zip(event.router_ip.as_deref())
+                .and_then(|(enrichment, ip)| enrichment.lookup_str(ip)),
         });
 
         if let Ok(msg_json) = serde_json::to_vec(&mtu_message) {
@@ -664,9 +824,805 @@ pub async fn run_mtu_traceroute_round(
     const TTL_DRAIN_INTERVAL_MS: u64 = 500;
 
     tracing::info!(
-        "Running MTU traceroute round for session {} with packet_size={}",
+        "Running MTU traceroute round for session {} with packet_size={}",
+        session.id,
+        packet_size
+    );
+
+    // Get the survey session ID for messages
+    let survey_session_id = session.survey_session_id.read().await.clone();
+
+    let control_channel = {
+        // Check if control channel is ready
+        let channels = session.data_channels.read().await;
+        let control_channel = match &channels.control {
+            Some(ch) if ch.ready_state() == RTCDataChannelState::Open => ch.clone(),
+            _ => {
+                drop(channels);
+                tracing::error!("Control channel not ready, session aborted");
+                return;
+            }
+        };
+        drop(channels);
+        control_channel
+    };
+
+    for current_ttl in 1..path_ttl {
+        tracing::debug!(
+            "MTU traceroute tick for session {}, TTL {}, size {}",
+            session.id,
+            current_ttl,
+            packet_size
+        );
+
+        // Get testprobe channel
+        let channels = session.data_channels.read().await;
+        let testprobe_channel = match &channels.testprobe {
+            Some(ch) if ch.ready_state() == RTCDataChannelState::Open => ch.clone(),
+            _ => {
+                drop(channels);
+                continue;
+            }
+        };
+        drop(channels);
+
+        let sent_at_ms = current_time_ms();
+        let seq = {
+            let mut state = session.measurement_state.write().await;
+            let seq = state.testprobe_seq;
+            state.testprobe_seq += 1;
+
+            let sent_testprobe = crate::state::SentProbe { seq, sent_at_ms };
+            state.sent_testprobes.push_back(sent_testprobe.clone());
+            state.sent_testprobes_map.insert(seq, sent_testprobe);
+
+            let cutoff = sent_at_ms - 60_000;
+            while let Some(p) = state.sent_testprobes.front() {
+                if p.sent_at_ms < cutoff {
+                    let old_probe = state.sent_testprobes.pop_front().unwrap();
+                    state.sent_testprobes_map.remove(&old_probe.seq);
+                } else {
+                    break;
+                }
+            }
+
+            seq
+        };
+
+        let ttl = Some(current_ttl as u8);
+
+        let send_options = common::SendOptions {
+            ttl,
+            df_bit: Some(true), // DF bit is essential for MTU discovery
+            tos: None,
+            flow_label: None,
+            track_for_ms: 5000,
+            bypass_dtls: true, // Bypass DTLS for MTU tests to control exact packet sizes
+            bypass_sctp_fragmentation: true, // Bypass SCTP fragmentation for MTU tests
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
+        };
+
+        let testprobe = common::TestProbePacket {
+            test_seq: seq,
+            timestamp_ms: sent_at_ms,
+            direction: Direction::ServerToClient,
+            send_options: Some(send_options),
+            conn_id: session.conn_id.clone(),
+        };
+
+        // Serialize and pad to packet_size
+        if let Ok(mut json) = serde_json::to_vec(&testprobe) {
+            // Pad the packet to the desired size
+            let current_len = json.len();
+            let mut target_len = packet_size as usize;
+            if let Some(ip_ver) = &session.ip_version {
+                if ip_ver == "ipv4" {
+                    // IPv4: 20 + 8 + 28(SCTP)
+                    target_len -= 20 + 8 + 28;
+                } else {
+                    // IPv6: 40 + 8 + 28(SCTP)
+                    target_len -= 40 + 8 + 28;
+                }
+            }
+            if current_len < target_len {
+                // resize with something other than 0 to hopefully change checksum
+                json.resize(target_len, 0x23);
+            }
+
+            tracing::debug!(
+                "Sending MTU traceroute probe: TTL={}, seq={}, size={}",
+                current_ttl,
+                seq,
+                json.len()
+            );
+
+            #[cfg(target_os = "linux")]
+            let send_result = {
+                use webrtc_util::UdpSendOptions;
+                let options = Some(UdpSendOptions {
+                    ttl,
+                    tos: None,
+                    df_bit: Some(true), // DF bit set for MTU discovery
+                    conn_id: session.conn_id.clone(),
+                    bypass_dtls: true, // Bypass DTLS for MTU tests to control exact packet sizes
+                    bypass_sctp_fragmentation: true, // Bypass SCTP fragmentation for MTU tests
+                    ipv6_hop_by_hop_len: None,
+                    ipv6_dest_opts_len: None,
+                    out_of_order_fragment_size: None,
+                    // Track also the probes sent without a TTL, for PTB errors
+                    track_for_ms: Some(send_options.track_for_ms),
+                });
+                testprobe_channel
+                    .send_with_options(&json.into(), options)
+                    .await
+            };
+
+            #[cfg(not(target_os = "linux"))]
+            let send_result = testprobe_channel.send(&json.into()).await;
+
+            if let Err(e) = send_result {
+                tracing::error!("Failed to send MTU traceroute probe: {}", e);
+                continue;
+            }
+
+            tokio::time::sleep(Duration::from_millis(TTL_SEND_INTERVAL_MS)).await;
+            drain_mtu_events(session.clone(), control_channel.clone(), &survey_session_id).await;
+        }
+    }
+    let mut drain_count = collect_timeout_ms as u64 / TTL_DRAIN_INTERVAL_MS;
+    loop {
+        tracing::trace!(
+            "Draining ICMP event queue for packet size {} conn id: {}",
+            packet_size,
+            &session.conn_id
+        );
+        drain_mtu_events(session.clone(), control_channel.clone(), &survey_session_id).await;
+        if drain_count == 0 {
+            break;
+        }
+        drain_count -= 1;
+        tokio::time::sleep(Duration::from_millis(TTL_DRAIN_INTERVAL_MS)).await;
+    }
+
+    let mtu_traceroute_completed_message =
+        common::ControlMessage::MtuTracerouteCompleted(common::MtuTracerouteCompletedMessage {
+            conn_id: session.conn_id.clone(),
+            survey_session_id: survey_session_id.to_string(),
+            packet_size,
+        });
+
+    if let Ok(msg_json) = serde_json::to_vec(&mtu_traceroute_completed_message) {
+        if let Err(e) = control_channel.send(&msg_json.into()).await {
+            tracing::error!("Failed to send traceroute completed message: {}", e);
+        } else {
+            tracing::debug!(
+                "Sent traceroute completed event to client: {:?}",
+                &mtu_traceroute_completed_message
+            );
+        }
+    }
+
+    tracing::info!(
+        "Completed MTU traceroute round for session {} with packet_size={}",
+        session.id,
+        packet_size
+    );
+}
+
+/// Collect the ICMPv6 errors of extension header test probes
+async fn drain_ext_header_events(session: &ClientSession, hops: &mut Vec<common::ExtHeaderHop>) {
+    let events = session
+        .packet_tracker
+        .drain_events_for_conn_id(&session.conn_id)
+        .await;
+    hops.extend(events.iter().filter_map(crate::ext_headers::hop_from_event));
+}
+
+/// Send the result of an extension header test to the client, and record it
+async fn report_ext_header_test(
+    session: &ClientSession,
+    control_channel: &RTCDataChannel,
+    result: common::ExtHeaderTestResultMessage,
+) {
+    if let Some(metrics_recorder) = &session.metrics_recorder {
+        if !result.survey_session_id.is_empty() && result.error.is_none() {
+            if let Err(e) = metrics_recorder
+                .record_ext_header_test(current_time_ms(), &result)
+                .await
+            {
+                tracing::error!("Failed to record extension header test: {}", e);
+            }
+        }
+    }
+
+    let result_message = common::ControlMessage::ExtHeaderTestResult(result);
+    if let Ok(msg_json) = serde_json::to_vec(&result_message) {
+        if let Err(e) = control_channel.send(&msg_json.into()).await {
+            tracing::error!("Failed to send extension header test result: {}", e);
+        } else {
+            tracing::debug!(
+                "Sent extension header test result to client: {:?}",
+                &result_message
+            );
+        }
+    }
+}
+
+/// Run an IPv6 extension header survivability test (RFC 9098)
+///
+/// Sends testprobes carrying the extension header without a TTL and, if
+/// `path_ttl` is positive, with each TTL up to it, then reports which reached
+/// the client and where the others were dropped.
+pub async fn run_ext_header_test_round(
+    session: Arc<ClientSession>,
+    header_type: common::Ipv6ExtHeaderType,
+    header_size: u16,
+    path_ttl: i32,
+    collect_timeout_ms: usize,
+) {
+    const TTL_SEND_INTERVAL_MS: u64 = 50;
+    const TTL_DRAIN_INTERVAL_MS: u64 = 500;
+
+    let header_size = webrtc_util::ipv6_options_header_len(header_size);
+    tracing::info!(
+        "Running extension header test for session {} with {} header of {} bytes",
+        session.id,
+        header_type.as_str(),
+        header_size
+    );
+
+    // Get the survey session ID for messages
+    let survey_session_id = session.survey_session_id.read().await.clone();
+
+    let control_channel = {
+        // Check if control channel is ready
+        let channels = session.data_channels.read().await;
+        let control_channel = match &channels.control {
+            Some(ch) if ch.ready_state() == RTCDataChannelState::Open => ch.clone(),
+            _ => {
+                drop(channels);
+                tracing::error!("Control channel not ready, session aborted");
+                return;
+            }
+        };
+        drop(channels);
+        control_channel
+    };
+
+    let mut result = common::ExtHeaderTestResultMessage {
+        conn_id: session.conn_id.clone(),
+        survey_session_id: survey_session_id.clone(),
+        header_type,
+        header_size,
+        probes_sent: 0,
+        probes_delivered: 0,
+        delivered: false,
+        reached_at_ttl: None,
+        last_responding_hop: None,
+        drop_hop: None,
+        hops: Vec::new(),
+        error: None,
+    };
+
+    if session.ip_version.as_deref() != Some("ipv6") {
+        result.error = Some("Extension headers can only be tested on IPv6 connections".to_string());
+        report_ext_header_test(&session, &control_channel, result).await;
+        return;
+    }
+
+    let (ipv6_hop_by_hop_len, ipv6_dest_opts_len) =
+        crate::ext_headers::header_lengths(header_type, header_size);
+    let walk_ttls = 1..=path_ttl.clamp(0, u8::MAX as i32) as u8;
+    let probe_ttls = std::iter::repeat_n(None, crate::ext_headers::END_TO_END_PROBES as usize)
+        .chain(walk_ttls.clone().map(Some));
+
+    let mut seqs = Vec::new();
+    for ttl in probe_ttls {
+        // Get testprobe channel
+        let channels = session.data_channels.read().await;
+        let testprobe_channel = match &channels.testprobe {
+            Some(ch) if ch.ready_state() == RTCDataChannelState::Open => ch.clone(),
+            _ => {
+                drop(channels);
+                continue;
+            }
+        };
+        drop(channels);
+
+        let sent_at_ms = current_time_ms();
+        let seq = {
+            let mut state = session.measurement_state.write().await;
+            let seq = state.testprobe_seq;
+            state.testprobe_seq += 1;
+            seq
+        };
+
+        let send_options = common::SendOptions {
+            ttl,
+            df_bit: None,
+            tos: None,
+            flow_label: None,
+            track_for_ms: 5000,
+            bypass_dtls: false,
+            bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len,
+            ipv6_dest_opts_len,
+            out_of_order_fragment_size: None,
+        };
+
+        let testprobe = common::TestProbePacket {
+            test_seq: seq,
+            timestamp_ms: sent_at_ms,
+            direction: Direction::ServerToClient,
+            send_options: Some(send_options),
+            conn_id: session.conn_id.clone(),
+        };
+
+        if let Ok(json) = serde_json::to_vec(&testprobe) {
+            tracing::debug!(
+                "Sending extension header test probe: TTL={:?}, seq={}",
+                ttl,
+                seq
+            );
+
+            #[cfg(target_os = "linux")]
+            let send_result = {
+                use webrtc_util::UdpSendOptions;
+                let options = Some(UdpSendOptions {
+                    ttl,
+                    tos: None,
+                    df_bit: None,
+                    conn_id: session.conn_id.clone(),
+                    bypass_dtls: false,
+                    bypass_sctp_fragmentation: false,
+                    // Track also the probes sent without a TTL, for Parameter Problem errors
+                    track_for_ms: Some(send_options.track_for_ms),
+                    ipv6_hop_by_hop_len,
+                    ipv6_dest_opts_len,
+                    out_of_order_fragment_size: None,
+                });
+                testprobe_channel
+                    .send_with_options(&json.into(), options)
+                    .await
+            };
+
+            #[cfg(not(target_os = "linux"))]
+            let send_result = testprobe_channel.send(&json.into()).await;
+
+            if let Err(e) = send_result {
+                tracing::error!("Failed to send extension header test probe: {}", e);
+                continue;
+            }
+            if ttl.is_none() {
+                result.probes_sent += 1;
+            }
+            seqs.push(seq);
+
+            tokio::time::sleep(Duration::from_millis(TTL_SEND_INTERVAL_MS)).await;
+            drain_ext_header_events(&session, &mut result.hops).await;
+        }
+    }
+
+    // Sending extension headers takes CAP_NET_RAW
+    if seqs.is_empty() {
+        result.error = Some("Could not send probes with extension headers".to_string());
+        report_ext_header_test(&session, &control_channel, result).await;
+        return;
+    }
+
+    let mut drain_count = collect_timeout_ms as u64 / TTL_DRAIN_INTERVAL_MS;
+    loop {
+        drain_ext_header_events(&session, &mut result.hops).await;
+        if drain_count == 0 {
+            break;
+        }
+        drain_count -= 1;
+        tokio::time::sleep(Duration::from_millis(TTL_DRAIN_INTERVAL_MS)).await;
+    }
+
+    let echoed_ttls = {
+        let mut state = session.measurement_state.write().await;
+        let mut echoed_ttls = Vec::new();
+        for seq in &seqs {
+            match state.echoed_ext_header_probes.remove(seq) {
+                Some(Some(ttl)) => echoed_ttls.push(ttl),
+                Some(None) => result.probes_delivered += 1,
+                None => {}
+            }
+        }
+        // Echoes of earlier tests arriving late are of no use anymore
+        if let Some(&first_seq) = seqs.first() {
+            state
+                .echoed_ext_header_probes
+                .retain(|&seq, _| seq >= first_seq);
+        }
+        echoed_ttls
+    };
+    crate::ext_headers::conclude(&mut result, &echoed_ttls, !walk_ttls.is_empty());
+
+    tracing::info!(
+        "Completed extension header test for session {}: {} header of {} bytes delivered={}, drop_hop={:?}",
+        session.id,
+        header_type.as_str(),
+        header_size,
+        result.delivered,
+        result.drop_hop
+    );
+    report_ext_header_test(&session, &control_channel, result).await;
+}
+
+/// Collect the ICMP errors of fragmentation test probes
+async fn drain_fragmentation_events(
+    session: &ClientSession,
+    icmp_errors: &mut Vec<common::FragmentationIcmpError>,
+) {
+    let events = session
+        .packet_tracker
+        .drain_events_for_conn_id(&session.conn_id)
+        .await;
+    icmp_errors.extend(
+        events
+            .iter()
+            .filter_map(crate::fragmentation::icmp_error_from_event),
+    );
+}
+
+/// Send the result of a fragmentation test to the client, and record it
+async fn report_fragmentation_test(
+    session: &ClientSession,
+    control_channel: &RTCDataChannel,
+    result: common::FragmentationTestResultMessage,
+) {
+    if let Some(metrics_recorder) = &session.metrics_recorder {
+        if !result.survey_session_id.is_empty() && result.error.is_none() {
+            let ip_version = session.ip_version.as_deref().unwrap_or("unknown");
+            if let Err(e) = metrics_recorder
+                .record_fragmentation_test(current_time_ms(), ip_version, &result)
+                .await
+            {
+                tracing::error!("Failed to record fragmentation test: {}", e);
+            }
+        }
+    }
+
+    let result_message = common::ControlMessage::FragmentationTestResult(result);
+    if let Ok(msg_json) = serde_json::to_vec(&result_message) {
+        if let Err(e) = control_channel.send(&msg_json.into()).await {
+            tracing::error!("Failed to send fragmentation test result: {}", e);
+        } else {
+            tracing::debug!(
+                "Sent fragmentation test result to client: {:?}",
+                &result_message
+            );
+        }
+    }
+}
+
+/// Run an IP fragmentation delivery test
+///
+/// Sends testprobes of `packet_size` bytes with fragmentation allowed, or as
+/// fragments in reverse order, and reports how many the client reassembled
+/// and echoed.
+pub async fn run_fragmentation_test_round(
+    session: Arc<ClientSession>,
+    packet_size: u32,
+    out_of_order: bool,
+    collect_timeout_ms: usize,
+) {
+    const SEND_INTERVAL_MS: u64 = 50;
+    const DRAIN_INTERVAL_MS: u64 = 500;
+
+    tracing::info!(
+        "Running fragmentation test for session {} with packet_size={}, out_of_order={}",
+        session.id,
+        packet_size,
+        out_of_order
+    );
+
+    // Get the survey session ID for messages
+    let survey_session_id = session.survey_session_id.read().await.clone();
+
+    let control_channel = {
+        // Check if control channel is ready
+        let channels = session.data_channels.read().await;
+        let control_channel = match &channels.control {
+            Some(ch) if ch.ready_state() == RTCDataChannelState::Open => ch.clone(),
+            _ => {
+                drop(channels);
+                tracing::error!("Control channel not ready, session aborted");
+                return;
+            }
+        };
+        drop(channels);
+        control_channel
+    };
+
+    let fragment_size = out_of_order.then_some(crate::fragmentation::OUT_OF_ORDER_FRAGMENT_SIZE);
+    let mut result = common::FragmentationTestResultMessage {
+        conn_id: session.conn_id.clone(),
+        survey_session_id: survey_session_id.clone(),
+        packet_size,
+        out_of_order,
+        fragment_size,
+        probes_sent: 0,
+        probes_delivered: 0,
+        delivered: false,
+        icmp_errors: Vec::new(),
+        error: None,
+    };
+    let ipv6 = session.ip_version.as_deref() == Some("ipv6");
+
+    let mut seqs = Vec::new();
+    for _ in 0..crate::fragmentation::PROBES {
+        // Get testprobe channel
+        let channels = session.data_channels.read().await;
+        let testprobe_channel = match &channels.testprobe {
+            Some(ch) if ch.ready_state() == RTCDataChannelState::Open => ch.clone(),
+            _ => {
+                drop(channels);
+                continue;
+            }
+        };
+        drop(channels);
+
+        let sent_at_ms = current_time_ms();
+        let seq = {
+            let mut state = session.measurement_state.write().await;
+            let seq = state.testprobe_seq;
+            state.testprobe_seq += 1;
+            seq
+        };
+
+        let send_options = common::SendOptions {
+            ttl: None,
+            df_bit: Some(false), // Fragmentation is the point
+            tos: None,
+            flow_label: None,
+            track_for_ms: 5000,
+            bypass_dtls: false, // The client has to receive the probes
+            bypass_sctp_fragmentation: true, // Leave the fragmentation to IP
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: fragment_size,
+        };
+
+        let testprobe = common::TestProbePacket {
+            test_seq: seq,
+            timestamp_ms: sent_at_ms,
+            direction: Direction::ServerToClient,
+            send_options: Some(send_options),
+            conn_id: session.conn_id.clone(),
+        };
+
+        if let Ok(mut json) = serde_json::to_vec(&testprobe) {
+            // Pad with whitespace, which the client's JSON parser skips
+            let target_len = crate::fragmentation::padded_length(packet_size, ipv6);
+            if json.len() < target_len {
+                json.resize(target_len, b' ');
+            }
+
+            tracing::debug!(
+                "Sending fragmentation test probe: seq={}, size={}",
+                seq,
+                json.len()
+            );
+
+            #[cfg(target_os = "linux")]
+            let send_result = {
+                use webrtc_util::UdpSendOptions;
+                let options = Some(UdpSendOptions {
+                    ttl: None,
+                    tos: None,
+                    df_bit: Some(false), // Let the kernel fragment the probes
+                    conn_id: session.conn_id.clone(),
+                    bypass_dtls: false,
+                    bypass_sctp_fragmentation: true,
+                    track_for_ms: Some(send_options.track_for_ms),
+                    ipv6_hop_by_hop_len: None,
+                    ipv6_dest_opts_len: None,
+                    out_of_order_fragment_size: fragment_size,
+                });
+                testprobe_channel
+                    .send_with_options(&json.into(), options)
+                    .await
+            };
+
+            #[cfg(not(target_os = "linux"))]
+            let send_result = testprobe_channel.send(&json.into()).await;
+
+            if let Err(e) = send_result {
+                tracing::error!("Failed to send fragmentation test probe: {}", e);
+                continue;
+            }
+            result.probes_sent += 1;
+            seqs.push(seq);
+
+            tokio::time::sleep(Duration::from_millis(SEND_INTERVAL_MS)).await;
+            drain_fragmentation_events(&session, &mut result.icmp_errors).await;
+        }
+    }
+
+    // Sending fragments out of order takes CAP_NET_RAW
+    if seqs.is_empty() {
+        result.error = Some("Could not send fragmented probes".to_string());
+        report_fragmentation_test(&session, &control_channel, result).await;
+        return;
+    }
+
+    let mut drain_count = collect_timeout_ms as u64 / DRAIN_INTERVAL_MS;
+    loop {
+        drain_fragmentation_events(&session, &mut result.icmp_errors).await;
+        if drain_count == 0 {
+            break;
+        }
+        drain_count -= 1;
+        tokio::time::sleep(Duration::from_millis(DRAIN_INTERVAL_MS)).await;
+    }
+
+    {
+        let mut state = session.measurement_state.write().await;
+        for seq in &seqs {
+            if state.echoed_fragmentation_probes.remove(seq) {
+                result.probes_delivered += 1;
+            }
+        }
+        // Echoes of earlier tests arriving late are of no use anymore
+        if let Some(&first_seq) = seqs.first() {
+            state
+                .echoed_fragmentation_probes
+                .retain(|&seq| seq >= first_seq);
+        }
+    }
+    result.delivered = result.probes_delivered > 0;
+
+    tracing::info!(
+        "Completed fragmentation test for session {}: packet_size={}, out_of_order={}, delivered {}/{}",
+        session.id,
+        packet_size,
+        out_of_order,
+        result.probes_delivered,
+        result.probes_sent
+    );
+    report_fragmentation_test(&session, &control_channel, result).await;
+}
+
+/// Send NAT lifetime test probes, and wait for the client to echo one
+async fn nat_lifetime_probes_delivered(session: &ClientSession) -> bool {
+    const POLL_INTERVAL_MS: u64 = 100;
+
+    let mut seqs = Vec::new();
+    for _ in 0..crate::nat_lifetime::PROBES {
+        // Get testprobe channel
+        let channels = session.data_channels.read().await;
+        let testprobe_channel = match &channels.testprobe {
+            Some(ch) if ch.ready_state() == RTCDataChannelState::Open => ch.clone(),
+            _ => {
+                drop(channels);
+                return false;
+            }
+        };
+        drop(channels);
+
+        let seq = {
+            let mut state = session.measurement_state.write().await;
+            let seq = state.testprobe_seq;
+            state.testprobe_seq += 1;
+            seq
+        };
+
+        let testprobe = common::TestProbePacket {
+            test_seq: seq,
+            timestamp_ms: current_time_ms(),
+            direction: Direction::ServerToClient,
+            send_options: None,
+            conn_id: session.conn_id.clone(),
+        };
+
+        if let Ok(json) = serde_json::to_vec(&testprobe) {
+            if let Err(e) = testprobe_channel.send(&json.into()).await {
+                tracing::error!("Failed to send NAT lifetime test probe: {}", e);
+            } else {
+                seqs.push(seq);
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(
+            crate::nat_lifetime::PROBE_INTERVAL_MS,
+        ))
+        .await;
+    }
+
+    let mut delivered = false;
+    let mut poll_count = crate::nat_lifetime::ECHO_TIMEOUT_MS / POLL_INTERVAL_MS;
+    while !seqs.is_empty() {
+        {
+            let state = session.measurement_state.read().await;
+            delivered = seqs
+                .iter()
+                .any(|seq| state.echoed_nat_lifetime_probes.contains(seq));
+        }
+        if delivered || poll_count == 0 {
+            break;
+        }
+        poll_count -= 1;
+        tokio::time::sleep(Duration::from_millis(POLL_INTERVAL_MS)).await;
+    }
+
+    // Echoes arriving after the wait would be mistaken for later ones
+    session
+        .measurement_state
+        .write()
+        .await
+        .echoed_nat_lifetime_probes
+        .clear();
+    delivered
+}
+
+/// Leave a connection idle for `idle_ms`, returning false if it closes meanwhile
+async fn idle_connection(session: &ClientSession, idle_ms: u64) -> bool {
+    const CHECK_INTERVAL_MS: u64 = 1000;
+
+    let idle_until = std::time::Instant::now() + Duration::from_millis(idle_ms);
+    loop {
+        match session.peer_connection.connection_state() {
+            RTCPeerConnectionState::Closed | RTCPeerConnectionState::Failed => return false,
+            _ => {}
+        }
+        let now = std::time::Instant::now();
+        if now >= idle_until {
+            return true;
+        }
+        tokio::time::sleep((idle_until - now).min(Duration::from_millis(CHECK_INTERVAL_MS))).await;
+    }
+}
+
+/// Send the result of a NAT lifetime test to the client, and record it
+async fn report_nat_lifetime_test(
+    session: &ClientSession,
+    control_channel: &RTCDataChannel,
+    result: common::NatLifetimeTestResultMessage,
+) {
+    if let Some(metrics_recorder) = &session.metrics_recorder {
+        if !result.survey_session_id.is_empty() && result.error.is_none() {
+            if let Err(e) = metrics_recorder
+                .record_nat_lifetime_test(current_time_ms(), &result)
+                .await
+            {
+                tracing::error!("Failed to record NAT lifetime test: {}", e);
+            }
+        }
+    }
+
+    let result_message = common::ControlMessage::NatLifetimeTestResult(result);
+    if let Ok(msg_json) = serde_json::to_vec(&result_message) {
+        if let Err(e) = control_channel.send(&msg_json.into()).await {
+            tracing::error!("Failed to send NAT lifetime test result: {}", e);
+        } else {
+            tracing::debug!(
+                "Sent NAT lifetime test result to client: {:?}",
+                &result_message
+            );
+        }
+    }
+}
+
+/// Run a NAT binding timeout test
+///
+/// Leaves the connection idle for increasing periods between `min_idle_ms`
+/// and `max_idle_ms`, checking after each whether testprobes still reach the
+/// client, until the binding timeout is known within `resolution_ms`.
+pub async fn run_nat_lifetime_test(
+    session: Arc<ClientSession>,
+    min_idle_ms: u64,
+    max_idle_ms: u64,
+    resolution_ms: u64,
+) {
+    tracing::info!(
+        "Running NAT lifetime test for session {} with idle periods {}..{}ms",
         session.id,
-        packet_size
+        min_idle_ms,
+        max_idle_ms
     );
 
     // Get the survey session ID for messages
@@ -687,160 +1643,219 @@ pub async fn run_mtu_traceroute_round(
         control_channel
     };
 
-    for current_ttl in 1..path_ttl {
-        tracing::debug!(
-            "MTU traceroute tick for session {}, TTL {}, size {}",
-            session.id,
-            current_ttl,
-            packet_size
-        );
+    let mut result = common::NatLifetimeTestResultMessage {
+        conn_id: session.conn_id.clone(),
+        survey_session_id,
+        ip_version: session
+            .ip_version
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string()),
+        trials: Vec::new(),
+        survived_idle_ms: None,
+        expired_idle_ms: None,
+        binding_timeout_ms: None,
+        error: None,
+    };
 
-        // Get testprobe channel
-        let channels = session.data_channels.read().await;
-        let testprobe_channel = match &channels.testprobe {
-            Some(ch) if ch.ready_state() == RTCDataChannelState::Open => ch.clone(),
-            _ => {
-                drop(channels);
-                continue;
-            }
-        };
-        drop(channels);
+    // Keepalives are only off on connections made for the test
+    if session.mode.as_deref() != Some(common::NAT_LIFETIME_MODE) {
+        result.error = Some("Not a NAT lifetime test connection".to_string());
+        report_nat_lifetime_test(&session, &control_channel, result).await;
+        return;
+    }
 
-        let sent_at_ms = current_time_ms();
-        let seq = {
-            let mut state = session.measurement_state.write().await;
-            let seq = state.testprobe_seq;
-            state.testprobe_seq += 1;
+    if !nat_lifetime_probes_delivered(&session).await {
+        result.error = Some("Probes did not reach the client".to_string());
+        report_nat_lifetime_test(&session, &control_channel, result).await;
+        return;
+    }
 
-            let sent_testprobe = crate::state::SentProbe { seq, sent_at_ms };
-            state.sent_testprobes.push_back(sent_testprobe.clone());
-            state.sent_testprobes_map.insert(seq, sent_testprobe);
+    let mut search = crate::nat_lifetime::IdleSearch::new(min_idle_ms, max_idle_ms, resolution_ms);
+    while let Some(idle_ms) = search.next_idle_ms() {
+        // What was found until the client left is still worth recording
+        if !idle_connection(&session, idle_ms).await {
+            tracing::info!(
+                "Connection of session {} closed during NAT lifetime test",
+                session.id
+            );
+            break;
+        }
 
-            let cutoff = sent_at_ms - 60_000;
-            while let Some(p) = state.sent_testprobes.front() {
-                if p.sent_at_ms < cutoff {
-                    let old_probe = state.sent_testprobes.pop_front().unwrap();
-                    state.sent_testprobes_map.remove(&old_probe.seq);
-                } else {
+        let delivered = nat_lifetime_probes_delivered(&session).await;
+        tracing::info!(
+            "NAT lifetime test for session {}: idle for {}ms, delivered={}",
+            session.id,
+            idle_ms,
+            delivered
+        );
+        search.record(idle_ms, delivered);
+        result
+            .trials
+            .push(common::NatLifetimeTrial { idle_ms, delivered });
+
+        // The client's consent checks or probes open a new binding
+        if !delivered {
+            let mut recovered = false;
+            for _ in 0..crate::nat_lifetime::RECOVERY_ATTEMPTS {
+                if nat_lifetime_probes_delivered(&session).await {
+                    recovered = true;
                     break;
                 }
             }
-
-            seq
-        };
-
-        let ttl = Some(current_ttl as u8);
-
-        let send_options = common::SendOptions {
-            ttl,
-            df_bit: Some(true), // DF bit is essential for MTU discovery
-            tos: None,
-            flow_label: None,
-            track_for_ms: 5000,
-            bypass_dtls: true, // Bypass DTLS for MTU tests to control exact packet sizes
-            bypass_sctp_fragmentation: true, // Bypass SCTP fragmentation for MTU tests
-        };
-
-        let testprobe = common::TestProbePacket {
-            test_seq: seq,
-            timestamp_ms: sent_at_ms,
-            direction: Direction::ServerToClient,
-            send_options: Some(send_options),
-            conn_id: session.conn_id.clone(),
-        };
-
-        // Serialize and pad to packet_size
-        if let Ok(mut json) = serde_json::to_vec(&testprobe) {
-            // Pad the packet to the desired size
-            let current_len = json.len();
-            let mut target_len = packet_size as usize;
-            if let Some(ip_ver) = &session.ip_version {
-                if ip_ver == "ipv4" {
-                    // IPv4: 20 + 8 + 28(SCTP)
-                    target_len -= 20 + 8 + 28;
-                } else {
-                    // IPv6: 40 + 8 + 28(SCTP)
-                    target_len -= 40 + 8 + 28;
-                }
-            }
-            if current_len < target_len {
-                // resize with something other than 0 to hopefully change checksum
-                json.resize(target_len, 0x23);
+            if !recovered {
+                result.error = Some("Probes no longer reach the client".to_string());
+                break;
             }
+        }
+    }
 
-            tracing::debug!(
-                "Sending MTU traceroute probe: TTL={}, seq={}, size={}",
-                current_ttl,
-                seq,
-                json.len()
-            );
-
-            #[cfg(target_os = "linux")]
-            let send_result = {
-                use webrtc_util::UdpSendOptions;
-                let options = Some(UdpSendOptions {
-                    ttl,
-                    tos: None,
-                    df_bit: Some(true), // DF bit set for MTU discovery
-                    conn_id: session.conn_id.clone(),
-                    bypass_dtls: true, // Bypass DTLS for MTU tests to control exact packet sizes
-                    bypass_sctp_fragmentation: true, // Bypass SCTP fragmentation for MTU tests
-                });
-                testprobe_channel
-                    .send_with_options(&json.into(), options)
-                    .await
-            };
+    result.survived_idle_ms = search.survived_idle_ms();
+    result.expired_idle_ms = search.expired_idle_ms();
+    result.binding_timeout_ms = search.binding_timeout_ms();
 
-            #[cfg(not(target_os = "linux"))]
-            let send_result = testprobe_channel.send(&json.into()).await;
+    tracing::info!(
+        "Completed NAT lifetime test for session {}: survived {:?}ms, expired {:?}ms",
+        session.id,
+        result.survived_idle_ms,
+        result.expired_idle_ms
+    );
+    report_nat_lifetime_test(&session, &control_channel, result).await;
+}
 
-            if let Err(e) = send_result {
-                tracing::error!("Failed to send MTU traceroute probe: {}", e);
-                continue;
+async fn report_nat_behavior_test(
+    session: &ClientSession,
+    control_channel: &RTCDataChannel,
+    result: common::NatBehaviorResultMessage,
+) {
+    if let Some(metrics_recorder) = &session.metrics_recorder {
+        if !result.survey_session_id.is_empty() && result.error.is_none() {
+            if let Err(e) = metrics_recorder
+                .record_nat_behavior_test(current_time_ms(), &result)
+                .await
+            {
+                tracing::error!("Failed to record NAT behavior test: {}", e);
             }
-
-            tokio::time::sleep(Duration::from_millis(TTL_SEND_INTERVAL_MS)).await;
-            drain_mtu_events(session.clone(), control_channel.clone(), &survey_session_id).await;
-        }
-    }
-    let mut drain_count = collect_timeout_ms as u64 / TTL_DRAIN_INTERVAL_MS;
-    loop {
-        tracing::trace!(
-            "Draining ICMP event queue for packet size {} conn id: {}",
-            packet_size,
-            &session.conn_id
-        );
-        drain_mtu_events(session.clone(), control_channel.clone(), &survey_session_id).await;
-        if drain_count == 0 {
-            break;
         }
-        drain_count -= 1;
-        tokio::time::sleep(Duration::from_millis(TTL_DRAIN_INTERVAL_MS)).await;
     }
 
-    let mtu_traceroute_completed_message =
-        common::ControlMessage::MtuTracerouteCompleted(common::MtuTracerouteCompletedMessage {
-            conn_id: session.conn_id.clone(),
-            survey_session_id: survey_session_id.to_string(),
-            packet_size,
-        });
-
-    if let Ok(msg_json) = serde_json::to_vec(&mtu_traceroute_completed_message) {
+    let result_message = common::ControlMessage::NatBehaviorResult(result);
+    if let Ok(msg_json) = serde_json::to_vec(&result_message) {
         if let Err(e) = control_channel.send(&msg_json.into()).await {
-            tracing::error!("Failed to send traceroute completed message: {}", e);
+            tracing::error!("Failed to send NAT behavior test result: {}", e);
         } else {
             tracing::debug!(
-                "Sent traceroute completed event to client: {:?}",
-                &mtu_traceroute_completed_message
+                "Sent NAT behavior test result to client: {:?}",
+                &result_message
             );
         }
     }
+}
 
+/// ICE credentials of a connection, from its local and remote descriptions
+async fn ice_credentials(session: &ClientSession) -> Option<crate::nat_behavior::IceCredentials> {
+    let local = session.peer_connection.local_description().await?;
+    let remote = session.peer_connection.remote_description().await?;
+    let (local_ufrag, _) = crate::nat_behavior::ice_credentials_from_sdp(&local.sdp)?;
+    let (remote_ufrag, remote_pwd) = crate::nat_behavior::ice_credentials_from_sdp(&remote.sdp)?;
+    Some(crate::nat_behavior::IceCredentials {
+        local_ufrag,
+        remote_ufrag,
+        remote_pwd,
+    })
+}
+
+/// Run a NAT type classification test
+///
+/// Classifies the mapping behavior from the mapped addresses the client got
+/// from the NAT behavior responder, and the filtering behavior by sending
+/// connectivity checks from the responder's other sockets to the client's
+/// mapped address on this connection.
+pub async fn run_nat_behavior_test(
+    session: Arc<ClientSession>,
+    msg: common::StartNatBehaviorTestMessage,
+) {
     tracing::info!(
-        "Completed MTU traceroute round for session {} with packet_size={}",
+        "Running NAT behavior test for session {} with {} mapped addresses",
         session.id,
-        packet_size
+        msg.mapped_addresses.len()
+    );
+
+    // Get the survey session ID for messages
+    let survey_session_id = session.survey_session_id.read().await.clone();
+
+    let control_channel = {
+        // Check if control channel is ready
+        let channels = session.data_channels.read().await;
+        let control_channel = match &channels.control {
+            Some(ch) if ch.ready_state() == RTCDataChannelState::Open => ch.clone(),
+            _ => {
+                drop(channels);
+                tracing::error!("Control channel not ready, session aborted");
+                return;
+            }
+        };
+        drop(channels);
+        control_channel
+    };
+
+    let ip_version = session
+        .ip_version
+        .clone()
+        .unwrap_or_else(|| "unknown".to_string());
+    let mut result = common::NatBehaviorResultMessage {
+        conn_id: session.conn_id.clone(),
+        survey_session_id,
+        ip_version: ip_version.clone(),
+        mapping: common::NatBehavior::Unknown,
+        filtering: common::NatBehavior::Unknown,
+        symmetric: None,
+        hairpinning: msg.hairpinning,
+        port_preservation: None,
+        mapped_addresses: msg.mapped_addresses.clone(),
+        error: None,
+    };
+
+    let Some(responder) = session.nat_behavior.clone() else {
+        result.error = Some("NAT behavior responder not enabled".to_string());
+        report_nat_behavior_test(&session, &control_channel, result).await;
+        return;
+    };
+
+    let mapping = responder.classify_mapping(
+        ip_version == "ipv6",
+        &msg.mapped_addresses,
+        &msg.failed_servers,
+    );
+    result.mapping = mapping.mapping;
+    result.symmetric = mapping.symmetric;
+    result.port_preservation = mapping.port_preservation;
+
+    let peer_address = session.peer_address.lock().await.clone();
+    let client = peer_address.and_then(|(address, port)| {
+        address
+            .parse::<std::net::IpAddr>()
+            .ok()
+            .map(|ip| std::net::SocketAddr::new(ip, port))
+    });
+    let Some(client) = client else {
+        result.error = Some("Client address unknown".to_string());
+        report_nat_behavior_test(&session, &control_channel, result).await;
+        return;
+    };
+    let Some(credentials) = ice_credentials(&session).await else {
+        result.error = Some("ICE credentials unknown".to_string());
+        report_nat_behavior_test(&session, &control_channel, result).await;
+        return;
+    };
+    result.filtering = responder.test_filtering(client, &credentials).await;
+
+    tracing::info!(
+        "Completed NAT behavior test for session {}: mapping {}, filtering {}",
+        session.id,
+        result.mapping.as_str(),
+        result.filtering.as_str()
     );
+    report_nat_behavior_test(&session, &control_channel, result).await;
 }
 
 /// Extract MTU value from an ICMP packet
@@ -895,7 +1910,7 @@ fn extract_mtu_from_icmp(icmp_packet: &[u8]) -> Option<u16> {
     }
 }
 
-fn current_time_ms() -> u64 {
+pub(crate) fn current_time_ms() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
@@ -938,7 +1953,25 @@ pub async fn handle_testprobe_echo_packet(
                 session.id
             );
 
+            // NAT lifetime test connections carry nothing but the test's probes
+            if session.mode.as_deref() == Some(common::NAT_LIFETIME_MODE) {
+                state.echoed_nat_lifetime_probes.insert(testprobe.test_seq);
+                return;
+            }
+
             if let Some(opts) = testprobe.send_options {
+                // Extension header and fragmentation test probes are
+                // accounted for by their test
+                if crate::ext_headers::has_ext_header(&opts) {
+                    state
+                        .echoed_ext_header_probes
+                        .insert(testprobe.test_seq, opts.ttl);
+                    return;
+                }
+                if crate::fragmentation::is_fragmentation_probe(&opts) {
+                    state.echoed_fragmentation_probes.insert(testprobe.test_seq);
+                    return;
+                }
                 if let Some(ttl) = opts.ttl {
                     if state.path_ttl.is_none() {
                         tracing::trace!("Got an echoed TTL: {}, setting state TTL", &ttl);
@@ -1003,7 +2036,7 @@ pub async fn start_measurement_probe_sender(session: Arc<ClientSession>) {
         interval.tick().await;
 
         // Check if probe streams should still be active
-        let (active, seq, feedback) = {
+        let (active, probe) = {
             let mut state = session.measurement_state.write().await;
             if !state.probe_streams_active {
                 tracing::debug!(
@@ -1025,10 +2058,13 @@ pub async fn start_measurement_probe_sender(session: Arc<ClientSession>) {
                     return;
                 }
             }
-            let seq = state.measurement_probe_seq;
-            state.measurement_probe_seq += 1;
-            let feedback = state.last_feedback.clone();
-            (true, seq, feedback)
+            let probe = next_measurement_probe(
+                &mut state,
+                Direction::ServerToClient,
+                &session.conn_id,
+                current_time_ms(),
+            );
+            (true, probe)
         };
 
         if !active {
@@ -1046,17 +2082,7 @@ pub async fn start_measurement_probe_sender(session: Arc<ClientSession>) {
         };
         drop(channels);
 
-        // Create and send measurement probe packet
-        let sent_at_ms = current_time_ms();
-
-        let probe = common::MeasurementProbePacket {
-            seq,
-            sent_at_ms,
-            direction: Direction::ServerToClient,
-            conn_id: session.conn_id.clone(),
-            feedback,
-        };
-
+        // Send measurement probe packet
         if let Ok(json) = serde_json::to_vec(&probe) {
             if let Err(e) = probe_channel.send(&json.into()).await {
                 tracing::error!("Failed to send measurement probe: {}", e);
@@ -1075,78 +2101,142 @@ pub async fn handle_measurement_probe_packet(session: Arc<ClientSession>, msg: D
         }
 
         let now_ms = current_time_ms();
-        // Use signed arithmetic to handle clock skew between client and server
-        // If client clock is ahead, delay will be negative; if behind, it will be larger than actual
-        // The baseline calculation will capture the clock offset, and deviations will be meaningful
-        let delay = (now_ms as i64 - probe.sent_at_ms as i64) as f64;
-
         let mut state = session.measurement_state.write().await;
 
         if !state.probe_streams_active {
             return;
         }
 
-        // Store received probe
-        state
-            .received_measurement_probes
-            .push_back(crate::state::ReceivedMeasurementProbe {
-                seq: probe.seq,
-                sent_at_ms: probe.sent_at_ms,
-                received_at_ms: now_ms,
-                feedback: probe.feedback.clone(),
-            });
+        record_measurement_probe(&mut state, &probe, now_ms);
+    }
+}
 
-        // Update baseline delay (exponential moving average with outlier exclusion)
-        // Only include delays within BASELINE_OUTLIER_MULTIPLIER of current baseline
-        // Use absolute difference to handle negative delays due to clock skew
-        let baseline = if state.baseline_delay_count > 0 {
-            state.baseline_delay_sum / state.baseline_delay_count as f64
+/// Next measurement probe to send, carrying feedback about received probes
+///
+/// The probe is remembered until it falls out of the stats window, so that
+/// the RTT can be taken from the feedback about it.
+pub fn next_measurement_probe(
+    state: &mut MeasurementState,
+    direction: Direction,
+    conn_id: &str,
+    now_ms: u64,
+) -> common::MeasurementProbePacket {
+    let seq = state.measurement_probe_seq;
+    state.measurement_probe_seq += 1;
+
+    state
+        .sent_measurement_probes
+        .push_back(crate::state::SentProbe {
+            seq,
+            sent_at_ms: now_ms,
+        });
+    let cutoff = now_ms.saturating_sub(common::PROBE_STATS_WINDOW_MS);
+    while let Some(p) = state.sent_measurement_probes.front() {
+        if p.sent_at_ms < cutoff {
+            state.sent_measurement_probes.pop_front();
         } else {
-            delay
-        };
-
-        // Use absolute difference from baseline for outlier detection
-        // This works correctly even with clock skew (negative delays)
-        let deviation_from_baseline = (delay - baseline).abs();
-        let baseline_threshold = baseline.abs() * common::BASELINE_OUTLIER_MULTIPLIER;
-        if state.baseline_delay_count < common::BASELINE_MIN_SAMPLES
-            || deviation_from_baseline < baseline_threshold.max(common::BASELINE_MIN_THRESHOLD_MS)
-        {
-            state.baseline_delay_sum += delay;
-            state.baseline_delay_count += 1;
+            break;
         }
+    }
+
+    common::MeasurementProbePacket {
+        seq,
+        sent_at_ms: now_ms,
+        direction,
+        conn_id: conn_id.to_string(),
+        feedback: state.last_feedback.clone(),
+    }
+}
+
+/// Record a received measurement probe: baseline delay, RTT from its
+/// feedback, and the feedback for outgoing probes
+pub fn record_measurement_probe(
+    state: &mut MeasurementState,
+    probe: &common::MeasurementProbePacket,
+    now_ms: u64,
+) {
+    // Use signed arithmetic to handle clock skew between client and server
+    // If client clock is ahead, delay will be negative; if behind, it will be larger than actual
+    // The baseline calculation will capture the clock offset, and deviations will be meaningful
+    let delay = (now_ms as i64 - probe.sent_at_ms as i64) as f64;
+
+    // RTT from the feedback about the highest probe the peer has received from us
+    let rtt_ms = state
+        .sent_measurement_probes
+        .binary_search_by_key(&probe.feedback.highest_seq, |p| p.seq)
+        .ok()
+        .and_then(|index| {
+            common::feedback_rtt_ms(
+                state.sent_measurement_probes[index].sent_at_ms,
+                probe,
+                now_ms,
+            )
+        });
+
+    // Store received probe
+    state
+        .received_measurement_probes
+        .push_back(crate::state::ReceivedMeasurementProbe {
+            seq: probe.seq,
+            sent_at_ms: probe.sent_at_ms,
+            received_at_ms: now_ms,
+            feedback: probe.feedback.clone(),
+            rtt_ms,
+        });
+
+    // Update baseline delay (exponential moving average with outlier exclusion)
+    // Only include delays within BASELINE_OUTLIER_MULTIPLIER of current baseline
+    // Use absolute difference to handle negative delays due to clock skew
+    let baseline = if state.baseline_delay_count > 0 {
+        state.baseline_delay_sum / state.baseline_delay_count as f64
+    } else {
+        delay
+    };
 
-        // Update feedback for outgoing probes
-        state.last_feedback.highest_seq = state.last_feedback.highest_seq.max(probe.seq);
+    // Use absolute difference from baseline for outlier detection
+    // This works correctly even with clock skew (negative delays)
+    let deviation_from_baseline = (delay - baseline).abs();
+    let baseline_threshold = baseline.abs() * common::BASELINE_OUTLIER_MULTIPLIER;
+    if state.baseline_delay_count < common::BASELINE_MIN_SAMPLES
+        || deviation_from_baseline < baseline_threshold.max(common::BASELINE_MIN_THRESHOLD_MS)
+    {
+        state.baseline_delay_sum += delay;
+        state.baseline_delay_count += 1;
+    }
+
+    // Update feedback for outgoing probes; the receive time is that of the
+    // highest seq, which the peer needs to take the RTT from it
+    if probe.seq >= state.last_feedback.highest_seq {
+        state.last_feedback.highest_seq = probe.seq;
         state.last_feedback.highest_seq_received_at_ms = now_ms;
+    }
 
-        // Keep only last PROBE_STATS_WINDOW_MS of probes for stats calculation
-        let cutoff = now_ms.saturating_sub(common::PROBE_STATS_WINDOW_MS);
-        while let Some(p) = state.received_measurement_probes.front() {
-            if p.received_at_ms < cutoff {
-                state.received_measurement_probes.pop_front();
-            } else {
-                break;
-            }
+    // Keep only last PROBE_STATS_WINDOW_MS of probes for stats calculation
+    let cutoff = now_ms.saturating_sub(common::PROBE_STATS_WINDOW_MS);
+    while let Some(p) = state.received_measurement_probes.front() {
+        if p.received_at_ms < cutoff {
+            state.received_measurement_probes.pop_front();
+        } else {
+            break;
         }
+    }
 
-        // Count recent probes and reorders for feedback
-        let mut recent_count = 0u32;
-        let mut recent_reorders = 0u32;
-        let mut last_seq = 0u64;
-        let feedback_cutoff = now_ms.saturating_sub(common::PROBE_FEEDBACK_WINDOW_MS);
-        for p in state.received_measurement_probes.iter() {
-            if p.received_at_ms >= feedback_cutoff {
-                recent_count += 1;
-                if p.seq < last_seq {
-                    recent_reorders += 1;
-                }
-                last_seq = last_seq.max(p.seq);
+    // Count recent probes and reorders for feedback
+    let mut recent_count = 0u32;
+    let mut recent_reorders = 0u32;
+    let mut last_seq = 0u64;
+    let feedback_cutoff = now_ms.saturating_sub(common::PROBE_FEEDBACK_WINDOW_MS);
+    for p in state.received_measurement_probes.iter() {
+        if p.received_at_ms >= feedback_cutoff {
+            recent_count += 1;
+            if p.seq < last_seq {
+                recent_reorders += 1;
             }
+            last_seq = last_seq.max(p.seq);
         }
-        state.last_feedback.recent_count = recent_count;
-        state.last_feedback.recent_reorders = recent_reorders;
     }
+    state.last_feedback.recent_count = recent_count;
+    state.last_feedback.recent_reorders = recent_reorders;
 }
 
 /// Start the per-second stats reporter
@@ -1217,7 +2307,10 @@ pub async fn start_probe_stats_reporter(session: Arc<ClientSession>) {
         }
 
         // Calculate stats from received probes
-        let c2s_stats = calculate_probe_stream_stats(&session).await;
+        let c2s_stats = {
+            let state = session.measurement_state.read().await;
+            probe_stream_stats(&state, current_time_ms())
+        };
 
         // Get survey session ID
         let survey_session_id = session.survey_session_id.read().await.clone();
@@ -1245,6 +2338,8 @@ pub async fn start_probe_stats_reporter(session: Arc<ClientSession>) {
                 {
                     tracing::error!("Failed to record probe stats: {}", e);
                 }
+                record_peer_address(&session, &survey_session_id).await;
+                record_reverse_path(&session, &survey_session_id, timestamp_ms).await;
 
                 // Update session timestamp
                 if let Some(session_manager) = &session.session_manager {
@@ -1282,10 +2377,7 @@ pub async fn start_probe_stats_reporter(session: Arc<ClientSession>) {
 }
 
 /// Calculate probe stream stats from received measurement probes
-async fn calculate_probe_stream_stats(session: &Arc<ClientSession>) -> common::DirectionStats {
-    let state = session.measurement_state.read().await;
-
-    let now_ms = current_time_ms();
+pub fn probe_stream_stats(state: &MeasurementState, now_ms: u64) -> common::DirectionStats {
     let stats_cutoff = now_ms.saturating_sub(common::PROBE_FEEDBACK_WINDOW_MS);
 
     // Filter to probes received in the last PROBE_FEEDBACK_WINDOW_MS
@@ -1380,9 +2472,13 @@ async fn calculate_probe_stream_stats(session: &Arc<ClientSession>) -> common::D
         0.0
     };
 
+    // RTT from the feedback the peer sent with the probes
+    let mut rtts: Vec<f64> = recent_probes.iter().filter_map(|p| p.rtt_ms).collect();
+    let rtt_ms = common::percentiles(&mut rtts);
+
     common::DirectionStats {
         delay_deviation_ms,
-        rtt_ms: [0.0; 4], // RTT requires echo, not calculated here
+        rtt_ms,
         jitter_ms,
         loss_rate,
         reorder_rate,
@@ -1488,4 +2584,42 @@ mod tests {
             assert_eq!(s.testprobe_seq, 3);
         }
     }
+
+    #[test]
+    fn test_probe_stream_rtt_from_feedback() {
+        let mut state = MeasurementState::new();
+        let sent = next_measurement_probe(&mut state, Direction::ServerToClient, "conn", 1_000);
+        assert_eq!(sent.seq, 0);
+
+        // Client clock 300ms behind; it received probe 0 and sent its own 5ms later
+        let received = common::MeasurementProbePacket {
+            seq: 0,
+            sent_at_ms: 725,
+            direction: Direction::ClientToServer,
+            conn_id: "conn".to_string(),
+            feedback: common::ProbeFeedback {
+                highest_seq: 0,
+                highest_seq_received_at_ms: 720,
+                recent_count: 1,
+                recent_reorders: 0,
+            },
+        };
+        record_measurement_probe(&mut state, &received, 1_035);
+        assert_eq!(state.last_feedback.highest_seq_received_at_ms, 1_035);
+
+        let stats = probe_stream_stats(&state, 1_040);
+        assert_eq!(stats.probe_count, 1);
+        assert_eq!(stats.rtt_ms, [30.0; 4]);
+
+        // A late lower seq does not move the receive time of the highest seq
+        let late = common::MeasurementProbePacket {
+            seq: 1,
+            ..received.clone()
+        };
+        let newer = common::MeasurementProbePacket { seq: 2, ..received };
+        record_measurement_probe(&mut state, &newer, 1_060);
+        record_measurement_probe(&mut state, &late, 1_070);
+        assert_eq!(state.last_feedback.highest_seq, 2);
+        assert_eq!(state.last_feedback.highest_seq_received_at_ms, 1_060);
+    }
 }
@@ -1,10 +1,15 @@
 //! Metrics recorder service for persisting survey probe statistics
 //!
 //! Records both server-side and client-side network metrics to the SQLite database
-//! for later analysis and export.
+//! for later analysis and export, along with traceroute hops, client peer
+//! addresses, IPv6 extension header, IP fragmentation, NAT binding timeout and
+//! NAT type test results.
 
 use crate::database::DbConnection;
-use common::DirectionStats;
+use common::{
+    DirectionStats, ExtHeaderTestResultMessage, FragmentationTestResultMessage, IpEnrichment,
+    NatBehaviorResultMessage, NatLifetimeTestResultMessage, ReversePathInfo, TraceHopMessage,
+};
 use rusqlite::params;
 
 /// Service for recording survey metrics to the database
@@ -166,6 +171,308 @@ impl MetricsRecorder {
 
         Ok(())
     }
+
+    /// Record a traceroute hop with the name and enrichment of its address
+    ///
+    /// # Arguments
+    /// * `session_id` - Survey session identifier
+    /// * `timestamp_ms` - Timestamp in milliseconds
+    /// * `hop` - Hop message as sent to the client
+    pub async fn record_traceroute_hop(
+        &self,
+        session_id: &str,
+        timestamp_ms: u64,
+        hop: &TraceHopMessage,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+        let enrichment = hop.enrichment.clone().unwrap_or_default();
+
+        db.execute(
+            "INSERT INTO traceroute_hops (
+                session_id, conn_id, timestamp_ms, hop, ip_address, hostname, rtt_ms,
+                asn, as_name, prefix, country, city, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                session_id,
+                hop.conn_id,
+                timestamp_ms,
+                hop.hop,
+                hop.ip_address,
+                hop.hostname,
+                hop.rtt_ms,
+                enrichment.asn,
+                enrichment.as_name,
+                enrichment.prefix,
+                enrichment.country,
+                enrichment.city,
+                now_ms
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record a client peer address of a connection; repeated addresses are ignored
+    ///
+    /// # Arguments
+    /// * `session_id` - Survey session identifier
+    /// * `conn_id` - Connection identifier for multi-path testing
+    /// * `peer_address` - Client address as seen by the server
+    /// * `enrichment` - ASN, prefix and location of the address
+    pub async fn record_peer_address(
+        &self,
+        session_id: &str,
+        conn_id: &str,
+        peer_address: &str,
+        enrichment: Option<&IpEnrichment>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+        let enrichment = enrichment.cloned().unwrap_or_default();
+
+        db.execute(
+            "INSERT OR IGNORE INTO survey_peers (
+                session_id, conn_id, peer_address,
+                asn, as_name, prefix, country, city, first_seen_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                session_id,
+                conn_id,
+                peer_address,
+                enrichment.asn,
+                enrichment.as_name,
+                enrichment.prefix,
+                enrichment.country,
+                enrichment.city,
+                now_ms
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record the reverse path hop count of a connection and its asymmetry
+    ///
+    /// # Arguments
+    /// * `session_id` - Survey session identifier
+    /// * `conn_id` - Connection identifier for multi-path testing
+    /// * `timestamp_ms` - Timestamp in milliseconds
+    /// * `reverse_path` - Reverse path inferred from the received TTLs
+    pub async fn record_reverse_path(
+        &self,
+        session_id: &str,
+        conn_id: &str,
+        timestamp_ms: u64,
+        reverse_path: &ReversePathInfo,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+
+        db.execute(
+            "INSERT INTO reverse_path_metrics (
+                session_id, conn_id, timestamp_ms,
+                received_ttl, min_received_ttl, max_received_ttl, initial_ttl,
+                reverse_hops, forward_hops, hop_difference, asymmetric, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                session_id,
+                conn_id,
+                timestamp_ms,
+                reverse_path.received_ttl,
+                reverse_path.min_received_ttl,
+                reverse_path.max_received_ttl,
+                reverse_path.initial_ttl,
+                reverse_path.reverse_hops,
+                reverse_path.forward_hops,
+                reverse_path.hop_difference,
+                reverse_path.asymmetric,
+                now_ms
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record the result of an IPv6 extension header test
+    pub async fn record_ext_header_test(
+        &self,
+        timestamp_ms: u64,
+        result: &ExtHeaderTestResultMessage,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let hops_json = serde_json::to_string(&result.hops)?;
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+
+        db.execute(
+            "INSERT INTO ext_header_tests (
+                session_id, conn_id, timestamp_ms, header_type, header_size,
+                probes_sent, probes_delivered, delivered, reached_at_ttl,
+                last_responding_hop, drop_hop, hops_json, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                result.survey_session_id,
+                result.conn_id,
+                timestamp_ms,
+                result.header_type.as_str(),
+                result.header_size,
+                result.probes_sent,
+                result.probes_delivered,
+                result.delivered,
+                result.reached_at_ttl,
+                result.last_responding_hop,
+                result.drop_hop,
+                hops_json,
+                now_ms
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record the result of an IP fragmentation test
+    pub async fn record_fragmentation_test(
+        &self,
+        timestamp_ms: u64,
+        ip_version: &str,
+        result: &FragmentationTestResultMessage,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let icmp_errors_json = serde_json::to_string(&result.icmp_errors)?;
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+
+        db.execute(
+            "INSERT INTO fragmentation_tests (
+                session_id, conn_id, timestamp_ms, ip_version, packet_size,
+                out_of_order, fragment_size, probes_sent, probes_delivered,
+                delivered, icmp_errors_json, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                result.survey_session_id,
+                result.conn_id,
+                timestamp_ms,
+                ip_version,
+                result.packet_size,
+                result.out_of_order,
+                result.fragment_size,
+                result.probes_sent,
+                result.probes_delivered,
+                result.delivered,
+                icmp_errors_json,
+                now_ms
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record the result of a NAT binding timeout test
+    pub async fn record_nat_lifetime_test(
+        &self,
+        timestamp_ms: u64,
+        result: &NatLifetimeTestResultMessage,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let trials_json = serde_json::to_string(&result.trials)?;
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+
+        db.execute(
+            "INSERT INTO nat_lifetime_tests (
+                session_id, conn_id, timestamp_ms, ip_version, survived_idle_ms,
+                expired_idle_ms, binding_timeout_ms, trials_json, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                result.survey_session_id,
+                result.conn_id,
+                timestamp_ms,
+                result.ip_version,
+                result.survived_idle_ms,
+                result.expired_idle_ms,
+                result.binding_timeout_ms,
+                trials_json,
+                now_ms
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record the result of a NAT type classification test
+    pub async fn record_nat_behavior_test(
+        &self,
+        timestamp_ms: u64,
+        result: &NatBehaviorResultMessage,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mapped_addresses_json = serde_json::to_string(&result.mapped_addresses)?;
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+
+        db.execute(
+            "INSERT INTO nat_behavior_tests (
+                session_id, conn_id, timestamp_ms, ip_version, mapping, filtering, symmetric,
+                hairpinning, port_preservation, mapped_addresses_json, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                result.survey_session_id,
+                result.conn_id,
+                timestamp_ms,
+                result.ip_version,
+                result.mapping.as_str(),
+                result.filtering.as_str(),
+                result.symmetric,
+                result.hairpinning,
+                result.port_preservation,
+                mapped_addresses_json,
+                now_ms
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record per-second stats of a probe stream over WebSocket
+    pub async fn record_websocket_probe_stats(
+        &self,
+        session_id: &str,
+        stream_id: &str,
+        timestamp_ms: u64,
+        c2s_stats: &DirectionStats,
+        s2c_stats: &DirectionStats,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let db = self.db.lock().await;
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+
+        for (direction, stats) in [("c2s", c2s_stats), ("s2c", s2c_stats)] {
+            db.execute(
+                "INSERT INTO websocket_probe_metrics (
+                    session_id, stream_id, timestamp_ms, direction,
+                    delay_p50_ms, delay_p99_ms, jitter_p50_ms, jitter_p99_ms,
+                    rtt_p50_ms, rtt_p99_ms, rtt_min_ms, rtt_max_ms,
+                    loss_rate, reorder_rate, probe_count, created_at
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    session_id,
+                    stream_id,
+                    timestamp_ms,
+                    direction,
+                    stats.delay_deviation_ms[0],
+                    stats.delay_deviation_ms[1],
+                    stats.jitter_ms[0],
+                    stats.jitter_ms[1],
+                    stats.rtt_ms[0],
+                    stats.rtt_ms[1],
+                    stats.rtt_ms[2],
+                    stats.rtt_ms[3],
+                    stats.loss_rate,
+                    stats.reorder_rate,
+                    stats.probe_count,
+                    now_ms
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -260,4 +567,399 @@ mod tests {
             .unwrap();
         assert_eq!(count, 1);
     }
+
+    #[tokio::test]
+    async fn test_record_traceroute_hop_and_peer() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+
+        {
+            let conn = db.lock().await;
+            let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+            conn.execute(
+                "INSERT INTO survey_sessions (session_id, magic_key, start_time, last_update_time, created_at)
+                 VALUES (?, ?, ?, ?, ?)",
+                params!["test-session", "TEST-001", now_ms, now_ms, now_ms],
+            )
+            .unwrap();
+        }
+
+        let recorder = MetricsRecorder::new(db.clone());
+        let enrichment = IpEnrichment {
+            asn: Some(64500),
+            as_name: Some("EXAMPLE".to_string()),
+            prefix: Some("192.0.2.0/24".to_string()),
+            country: Some("NL".to_string()),
+            city: None,
+        };
+        let hop = TraceHopMessage {
+            hop: 3,
+            ip_address: Some("192.0.2.1".to_string()),
+            rtt_ms: 12.5,
+            message: "Hop 3 via 192.0.2.1 (12.50ms)".to_string(),
+            conn_id: "conn-1".to_string(),
+            survey_session_id: "test-session".to_string(),
+            original_src_port: 40000,
+            original_dest_addr: "198.51.100.1:50000".to_string(),
+            mpls_labels: Vec::new(),
+            interfaces: Vec::new(),
+            enrichment: Some(enrichment.clone()),
+            hostname: Some("ae-1.r23.example.net".to_string()),
+        };
+        recorder
+            .record_traceroute_hop("test-session", 1234567890, &hop)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            recorder
+                .record_peer_address("test-session", "conn-1", "198.51.100.7", Some(&enrichment))
+                .await
+                .unwrap();
+        }
+
+        let conn = db.lock().await;
+        let (hostname, asn, prefix): (String, u32, String) = conn
+            .query_row(
+                "SELECT hostname, asn, prefix FROM traceroute_hops WHERE session_id = ? AND hop = 3",
+                params!["test-session"],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(hostname, "ae-1.r23.example.net");
+        assert_eq!(asn, 64500);
+        assert_eq!(prefix, "192.0.2.0/24");
+        let peers: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM survey_peers WHERE session_id = ?",
+                params!["test-session"],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(peers, 1);
+    }
+
+    #[tokio::test]
+    async fn test_record_reverse_path() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+
+        {
+            let conn = db.lock().await;
+            let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+            conn.execute(
+                "INSERT INTO survey_sessions (session_id, magic_key, start_time, last_update_time, created_at)
+                 VALUES (?, ?, ?, ?, ?)",
+                params!["test-session", "TEST-001", now_ms, now_ms, now_ms],
+            )
+            .unwrap();
+        }
+
+        let recorder = MetricsRecorder::new(db.clone());
+        let reverse_path = ReversePathInfo {
+            received_ttl: 113,
+            min_received_ttl: 112,
+            max_received_ttl: 113,
+            initial_ttl: 128,
+            reverse_hops: 15,
+            forward_hops: Some(9),
+            hop_difference: Some(6),
+            asymmetric: Some(true),
+        };
+        recorder
+            .record_reverse_path("test-session", "conn-1", 1234567890, &reverse_path)
+            .await
+            .unwrap();
+
+        let conn = db.lock().await;
+        let (reverse_hops, forward_hops, asymmetric): (u8, Option<u8>, bool) = conn
+            .query_row(
+                "SELECT reverse_hops, forward_hops, asymmetric FROM reverse_path_metrics WHERE session_id = ?",
+                params!["test-session"],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(reverse_hops, 15);
+        assert_eq!(forward_hops, Some(9));
+        assert!(asymmetric);
+    }
+
+    #[tokio::test]
+    async fn test_record_ext_header_test() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+
+        {
+            let conn = db.lock().await;
+            let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+            conn.execute(
+                "INSERT INTO survey_sessions (session_id, magic_key, start_time, last_update_time, created_at)
+                 VALUES (?, ?, ?, ?, ?)",
+                params!["test-session", "TEST-001", now_ms, now_ms, now_ms],
+            )
+            .unwrap();
+        }
+
+        let recorder = MetricsRecorder::new(db.clone());
+        let result = ExtHeaderTestResultMessage {
+            conn_id: "conn-1".to_string(),
+            survey_session_id: "test-session".to_string(),
+            header_type: common::Ipv6ExtHeaderType::HopByHop,
+            header_size: 8,
+            probes_sent: 3,
+            probes_delivered: 0,
+            delivered: false,
+            reached_at_ttl: None,
+            last_responding_hop: Some(4),
+            drop_hop: Some(5),
+            hops: vec![common::ExtHeaderHop {
+                hop: Some(4),
+                ip_address: Some("2001:db8::4".to_string()),
+                rtt_ms: 12.5,
+                icmp_type: 3,
+                icmp_code: 0,
+            }],
+            error: None,
+        };
+        recorder
+            .record_ext_header_test(1234567890, &result)
+            .await
+            .unwrap();
+
+        let conn = db.lock().await;
+        let (header_type, delivered, drop_hop, hops_json): (String, bool, Option<u8>, String) =
+            conn.query_row(
+                "SELECT header_type, delivered, drop_hop, hops_json FROM ext_header_tests WHERE session_id = ?",
+                params!["test-session"],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(header_type, "hop_by_hop");
+        assert!(!delivered);
+        assert_eq!(drop_hop, Some(5));
+        let hops: Vec<common::ExtHeaderHop> = serde_json::from_str(&hops_json).unwrap();
+        assert_eq!(hops, result.hops);
+    }
+
+    #[tokio::test]
+    async fn test_record_fragmentation_test() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+
+        {
+            let conn = db.lock().await;
+            let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+            conn.execute(
+                "INSERT INTO survey_sessions (session_id, magic_key, start_time, last_update_time, created_at)
+                 VALUES (?, ?, ?, ?, ?)",
+                params!["test-session", "TEST-001", now_ms, now_ms, now_ms],
+            )
+            .unwrap();
+        }
+
+        let recorder = MetricsRecorder::new(db.clone());
+        let result = FragmentationTestResultMessage {
+            conn_id: "conn-1".to_string(),
+            survey_session_id: "test-session".to_string(),
+            packet_size: 3000,
+            out_of_order: true,
+            fragment_size: Some(512),
+            probes_sent: 3,
+            probes_delivered: 0,
+            delivered: false,
+            icmp_errors: vec![common::FragmentationIcmpError {
+                ip_address: Some("198.51.100.7".to_string()),
+                icmp_type: 11,
+                icmp_code: 1,
+            }],
+            error: None,
+        };
+        recorder
+            .record_fragmentation_test(1234567890, "ipv4", &result)
+            .await
+            .unwrap();
+
+        let conn = db.lock().await;
+        let (ip_version, out_of_order, fragment_size, delivered, icmp_errors_json): (
+            String,
+            bool,
+            Option<u16>,
+            bool,
+            String,
+        ) = conn
+            .query_row(
+                "SELECT ip_version, out_of_order, fragment_size, delivered, icmp_errors_json
+                 FROM fragmentation_tests WHERE session_id = ?",
+                params!["test-session"],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(ip_version, "ipv4");
+        assert!(out_of_order);
+        assert_eq!(fragment_size, Some(512));
+        assert!(!delivered);
+        let icmp_errors: Vec<common::FragmentationIcmpError> =
+            serde_json::from_str(&icmp_errors_json).unwrap();
+        assert_eq!(icmp_errors, result.icmp_errors);
+    }
+
+    #[tokio::test]
+    async fn test_record_nat_lifetime_test() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+
+        {
+            let conn = db.lock().await;
+            let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+            conn.execute(
+                "INSERT INTO survey_sessions (session_id, magic_key, start_time, last_update_time, created_at)
+                 VALUES (?, ?, ?, ?, ?)",
+                params!["test-session", "TEST-001", now_ms, now_ms, now_ms],
+            )
+            .unwrap();
+        }
+
+        let recorder = MetricsRecorder::new(db.clone());
+        let result = NatLifetimeTestResultMessage {
+            conn_id: "conn-1".to_string(),
+            survey_session_id: "test-session".to_string(),
+            ip_version: "ipv4".to_string(),
+            trials: vec![
+                common::NatLifetimeTrial {
+                    idle_ms: 5000,
+                    delivered: true,
+                },
+                common::NatLifetimeTrial {
+                    idle_ms: 10000,
+                    delivered: false,
+                },
+            ],
+            survived_idle_ms: Some(5000),
+            expired_idle_ms: Some(10000),
+            binding_timeout_ms: Some(7500),
+            error: None,
+        };
+        recorder
+            .record_nat_lifetime_test(1234567890, &result)
+            .await
+            .unwrap();
+
+        let conn = db.lock().await;
+        let (ip_version, binding_timeout_ms, trials_json): (String, Option<u64>, String) = conn
+            .query_row(
+                "SELECT ip_version, binding_timeout_ms, trials_json
+                 FROM nat_lifetime_tests WHERE session_id = ?",
+                params!["test-session"],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(ip_version, "ipv4");
+        assert_eq!(binding_timeout_ms, Some(7500));
+        let trials: Vec<common::NatLifetimeTrial> = serde_json::from_str(&trials_json).unwrap();
+        assert_eq!(trials, result.trials);
+    }
+
+    #[tokio::test]
+    async fn test_record_nat_behavior_test() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+
+        {
+            let conn = db.lock().await;
+            let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+            conn.execute(
+                "INSERT INTO survey_sessions (session_id, magic_key, start_time, last_update_time, created_at)
+                 VALUES (?, ?, ?, ?, ?)",
+                params!["test-session", "TEST-001", now_ms, now_ms, now_ms],
+            )
+            .unwrap();
+        }
+
+        let recorder = MetricsRecorder::new(db.clone());
+        let result = NatBehaviorResultMessage {
+            conn_id: "conn-1".to_string(),
+            survey_session_id: "test-session".to_string(),
+            ip_version: "ipv4".to_string(),
+            mapping: common::NatBehavior::EndpointIndependent,
+            filtering: common::NatBehavior::AddressAndPortDependent,
+            symmetric: Some(false),
+            hairpinning: None,
+            port_preservation: Some(true),
+            mapped_addresses: vec![common::NatMappedAddress {
+                server: "192.0.2.1:3478".to_string(),
+                mapped_address: "198.51.100.7:50000".to_string(),
+                local_port: Some(50000),
+            }],
+            error: None,
+        };
+        recorder
+            .record_nat_behavior_test(1234567890, &result)
+            .await
+            .unwrap();
+
+        let conn = db.lock().await;
+        let (mapping, filtering, symmetric): (String, String, Option<bool>) = conn
+            .query_row(
+                "SELECT mapping, filtering, symmetric
+                 FROM nat_behavior_tests WHERE session_id = ?",
+                params!["test-session"],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(mapping, "endpoint_independent");
+        assert_eq!(filtering, "address_and_port_dependent");
+        assert_eq!(symmetric, Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_record_websocket_probe_stats() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+
+        {
+            let conn = db.lock().await;
+            let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+            conn.execute(
+                "INSERT INTO survey_sessions (session_id, magic_key, start_time, last_update_time, created_at)
+                 VALUES (?, ?, ?, ?, ?)",
+                params!["test-session", "TEST-001", now_ms, now_ms, now_ms],
+            )
+            .unwrap();
+        }
+
+        let recorder = MetricsRecorder::new(db.clone());
+        let stats = create_test_stats();
+        recorder
+            .record_websocket_probe_stats("test-session", "ws-1", 1234567890, &stats, &stats)
+            .await
+            .unwrap();
+
+        let conn = db.lock().await;
+        let count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM websocket_probe_metrics WHERE session_id = ?",
+                params!["test-session"],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 2);
+
+        let (stream_id, rtt_p50): (String, f64) = conn
+            .query_row(
+                "SELECT stream_id, rtt_p50_ms FROM websocket_probe_metrics
+                 WHERE session_id = ? AND direction = 'c2s'",
+                params!["test-session"],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(stream_id, "ws-1");
+        assert_eq!(rtt_p50, 10.0);
+    }
 }
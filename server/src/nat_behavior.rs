@@ -0,0 +1,683 @@
+//! NAT type classification (RFC 4787)
+//!
+//! A STUN behavior discovery responder (RFC 5780) listens on a primary and an
+//! alternate port, on the primary and, if configured, an alternate address.
+//! It answers Binding requests with the mapped address, and honors
+//! CHANGE-REQUEST by answering from another of its sockets.
+//!
+//! Browsers cannot send CHANGE-REQUEST, so classification is split:
+//! - Mapping: the client gathers server reflexive candidates from several of
+//!   the responder's sockets on one local socket, and reports the mapped
+//!   addresses. Browsers report an address once however many servers saw it.
+//! - Filtering: the server sends ICE connectivity checks, with the credentials
+//!   of the client's WebRTC connection, from its other sockets to the mapped
+//!   address of that connection. The browser answers the checks that get
+//!   through the NAT.
+
+use crate::config::NatBehaviorConfig;
+use common::{NatBehavior, NatMappedAddress};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+use webrtc::ice::control::AttrControlled;
+use webrtc::ice::priority::PriorityAttr;
+use webrtc::stun::addr::MappedAddress;
+use webrtc::stun::agent::TransactionId;
+use webrtc::stun::attributes::{
+    ATTR_CHANGE_REQUEST, ATTR_OTHER_ADDRESS, ATTR_RESPONSE_ORIGIN, ATTR_USERNAME,
+};
+use webrtc::stun::error_code::{ErrorCodeAttribute, CODE_UNKNOWN_ATTRIBUTE};
+use webrtc::stun::fingerprint::FINGERPRINT;
+use webrtc::stun::integrity::MessageIntegrity;
+use webrtc::stun::message::{
+    is_message, Message, Setter, BINDING_ERROR, BINDING_REQUEST, BINDING_SUCCESS,
+};
+use webrtc::stun::textattrs::TextAttribute;
+use webrtc::stun::xoraddr::XorMappedAddress;
+
+/// How long the filtering test waits for a client to answer from one socket
+pub const FILTERING_TIMEOUT_MS: u64 = 2000;
+
+/// Connectivity checks sent per socket, as a single one may be lost
+const FILTERING_CHECKS: u64 = 3;
+
+/// CHANGE-REQUEST flags (RFC 5780 Section 7.2)
+const CHANGE_IP: u8 = 0x04;
+const CHANGE_PORT: u8 = 0x02;
+
+/// Priority of a peer reflexive candidate (RFC 8445 Section 5.1.2)
+const PEER_REFLEXIVE_PRIORITY: u32 = (110 << 24) | (65535 << 8) | 255;
+
+/// ICE credentials of both ends of a WebRTC connection
+pub struct IceCredentials {
+    pub local_ufrag: String,
+    pub remote_ufrag: String,
+    pub remote_pwd: String,
+}
+
+/// `a=ice-ufrag` and `a=ice-pwd` of an SDP
+pub fn ice_credentials_from_sdp(sdp: &str) -> Option<(String, String)> {
+    let attribute = |name: &str| {
+        sdp.lines()
+            .find_map(|line| line.trim().strip_prefix(name))
+            .map(|value| value.to_string())
+    };
+    Some((attribute("a=ice-ufrag:")?, attribute("a=ice-pwd:")?))
+}
+
+/// Filtering behavior from whether the alternate address and the alternate
+/// port of the server reached the client, none when there is no such socket
+pub fn classify_filtering(
+    from_alternate_address: Option<bool>,
+    from_alternate_port: Option<bool>,
+) -> NatBehavior {
+    match (from_alternate_address, from_alternate_port) {
+        (Some(true), _) => NatBehavior::EndpointIndependent,
+        (Some(false), Some(true)) => NatBehavior::AddressDependent,
+        (_, Some(false)) => NatBehavior::AddressAndPortDependent,
+        _ => NatBehavior::Unknown,
+    }
+}
+
+/// Mapped address a client got from one of the responder's sockets
+#[derive(Debug, Clone, PartialEq)]
+pub struct MappingObservation {
+    /// Whether the server socket was on the alternate address
+    pub alternate_address: bool,
+    pub mapped_address: SocketAddr,
+    pub local_port: Option<u16>,
+}
+
+/// Mapping behavior of a NAT
+#[derive(Debug, Clone, PartialEq)]
+pub struct MappingClassification {
+    pub mapping: NatBehavior,
+    pub symmetric: Option<bool>,
+    pub port_preservation: Option<bool>,
+}
+
+/// Mapping behavior from the mapped addresses of the local socket with the
+/// most of them
+///
+/// `servers_answered` counts the servers that answered, including those whose
+/// mapped address the browser did not report again, and
+/// `both_addresses_answered` whether servers on both addresses did.
+pub fn classify_mapping(
+    observations: &[MappingObservation],
+    servers_answered: usize,
+    both_addresses_answered: bool,
+) -> MappingClassification {
+    let mut sockets: HashMap<Option<u16>, Vec<&MappingObservation>> = HashMap::new();
+    for observation in observations {
+        sockets
+            .entry(observation.local_port)
+            .or_default()
+            .push(observation);
+    }
+    let Some(socket) = sockets.into_values().max_by_key(|socket| socket.len()) else {
+        return MappingClassification {
+            mapping: NatBehavior::Unknown,
+            symmetric: None,
+            port_preservation: None,
+        };
+    };
+
+    let first = socket[0];
+    let port_preservation = first
+        .local_port
+        .map(|local_port| first.mapped_address.port() == local_port);
+    let differs = |same_address: bool| {
+        socket.iter().any(|a| {
+            socket.iter().any(|b| {
+                (a.alternate_address == b.alternate_address) == same_address
+                    && a.mapped_address != b.mapped_address
+            })
+        })
+    };
+
+    let (mapping, symmetric) = if differs(true) {
+        (NatBehavior::AddressAndPortDependent, Some(true))
+    } else if differs(false) {
+        (NatBehavior::AddressDependent, Some(false))
+    } else if servers_answered < 2 {
+        (NatBehavior::Unknown, None)
+    } else if both_addresses_answered {
+        (NatBehavior::EndpointIndependent, Some(false))
+    } else {
+        // Only ports were compared: not port dependent, but maybe address dependent
+        (NatBehavior::Unknown, Some(false))
+    };
+    MappingClassification {
+        mapping,
+        symmetric,
+        port_preservation,
+    }
+}
+
+/// Host and port of a "host:port" or "[v6]:port" STUN server
+fn split_server(server: &str) -> Option<(&str, u16)> {
+    let (host, port) = server.rsplit_once(':')?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Some((host, port.parse().ok()?))
+}
+
+struct ResponderSocket {
+    socket: Arc<UdpSocket>,
+    local: SocketAddr,
+    alternate_address: bool,
+    alternate_port: bool,
+}
+
+/// STUN behavior discovery responder, and filtering tests from its sockets
+pub struct NatBehaviorResponder {
+    sockets: Vec<ResponderSocket>,
+    ports: Vec<u16>,
+    alternate_addresses: Vec<IpAddr>,
+    /// Connectivity checks of filtering tests waiting for an answer
+    pending: Mutex<HashMap<TransactionId, oneshot::Sender<()>>>,
+}
+
+impl NatBehaviorResponder {
+    /// Bind the responder's sockets and start answering requests
+    pub fn start(
+        config: &NatBehaviorConfig,
+    ) -> Result<Arc<Self>, Box<dyn std::error::Error + Send + Sync>> {
+        let ports: Vec<u16> = config.ports.iter().copied().take(2).collect();
+        let primary_addresses = config
+            .addresses
+            .iter()
+            .map(|address| address.parse::<IpAddr>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let alternate_addresses = config
+            .alternate_addresses
+            .iter()
+            .map(|address| address.parse::<IpAddr>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut sockets = Vec::new();
+        for (addresses, alternate_address) in
+            [(&primary_addresses, false), (&alternate_addresses, true)]
+        {
+            for &address in addresses.iter() {
+                for (index, &port) in ports.iter().enumerate() {
+                    let socket = bind(SocketAddr::new(address, port))?;
+                    sockets.push(ResponderSocket {
+                        local: socket.local_addr()?,
+                        socket: Arc::new(socket),
+                        alternate_address,
+                        alternate_port: index == 1,
+                    });
+                }
+            }
+        }
+
+        let responder = Arc::new(Self {
+            sockets,
+            ports,
+            alternate_addresses,
+            pending: Mutex::new(HashMap::new()),
+        });
+        for index in 0..responder.sockets.len() {
+            tokio::spawn(responder.clone().serve(index));
+        }
+        Ok(responder)
+    }
+
+    /// Ports of the responder, the primary one first
+    pub fn ports(&self) -> &[u16] {
+        &self.ports
+    }
+
+    /// Alternate addresses of the responder
+    pub fn alternate_addresses(&self) -> &[IpAddr] {
+        &self.alternate_addresses
+    }
+
+    /// Local address of the first socket, for tests binding port 0
+    #[cfg(test)]
+    fn local_addr(&self, alternate_address: bool, alternate_port: bool) -> SocketAddr {
+        self.socket(false, alternate_address, alternate_port)
+            .unwrap()
+            .local
+    }
+
+    fn socket(
+        &self,
+        ipv6: bool,
+        alternate_address: bool,
+        alternate_port: bool,
+    ) -> Option<&ResponderSocket> {
+        self.sockets.iter().find(|socket| {
+            socket.local.is_ipv6() == ipv6
+                && socket.alternate_address == alternate_address
+                && socket.alternate_port == alternate_port
+        })
+    }
+
+    async fn serve(self: Arc<Self>, index: usize) {
+        let mut buf = vec![0u8; 1500];
+        loop {
+            let (len, from) = match self.sockets[index].socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(e) => {
+                    tracing::debug!("NAT behavior responder receive error: {}", e);
+                    continue;
+                }
+            };
+            if let Some((socket, response)) = self.handle(index, &buf[..len], from) {
+                if let Err(e) = self.sockets[socket].socket.send_to(&response, from).await {
+                    tracing::debug!("NAT behavior responder failed to answer {}: {}", from, e);
+                }
+            }
+        }
+    }
+
+    /// Socket to answer from and response to a packet, if it needs one
+    fn handle(&self, index: usize, packet: &[u8], from: SocketAddr) -> Option<(usize, Vec<u8>)> {
+        if !is_message(packet) {
+            return None;
+        }
+        let mut request = Message::new();
+        request.unmarshal_binary(packet).ok()?;
+
+        if request.typ == BINDING_SUCCESS {
+            if let Some(answered) = self.pending.lock().unwrap().remove(&request.transaction_id) {
+                let _ = answered.send(());
+            }
+            return None;
+        }
+        // Connectivity checks of browsers that learned a socket from a
+        // filtering test are not for the responder
+        if request.typ != BINDING_REQUEST || request.contains(ATTR_USERNAME) {
+            return None;
+        }
+
+        let this = &self.sockets[index];
+        let change = request
+            .get(ATTR_CHANGE_REQUEST)
+            .ok()
+            .and_then(|value| value.get(3).copied())
+            .unwrap_or(0);
+        let ipv6 = this.local.is_ipv6();
+        let target = self.sockets.iter().position(|socket| {
+            socket.local.is_ipv6() == ipv6
+                && socket.alternate_address == (this.alternate_address ^ (change & CHANGE_IP != 0))
+                && socket.alternate_port == (this.alternate_port ^ (change & CHANGE_PORT != 0))
+        });
+
+        let mut response = Message::new();
+        let Some(target) = target else {
+            response
+                .build(&[
+                    Box::new(BINDING_ERROR),
+                    Box::new(request.transaction_id),
+                    Box::new(ErrorCodeAttribute {
+                        code: CODE_UNKNOWN_ATTRIBUTE,
+                        reason: b"Unknown Attribute".to_vec(),
+                    }),
+                    Box::new(FINGERPRINT),
+                ])
+                .ok()?;
+            return Some((index, response.raw));
+        };
+
+        response
+            .build(&[
+                Box::new(BINDING_SUCCESS),
+                Box::new(request.transaction_id),
+                Box::new(XorMappedAddress {
+                    ip: from.ip(),
+                    port: from.port(),
+                }),
+                Box::new(MappedAddress {
+                    ip: from.ip(),
+                    port: from.port(),
+                }),
+            ])
+            .ok()?;
+        let origin = self.sockets[target].local;
+        if !origin.ip().is_unspecified() {
+            address_attribute(origin)
+                .add_to_as(&mut response, ATTR_RESPONSE_ORIGIN)
+                .ok()?;
+        }
+        if let Some(other) = self.socket(ipv6, !this.alternate_address, !this.alternate_port) {
+            if !other.local.ip().is_unspecified() {
+                address_attribute(other.local)
+                    .add_to_as(&mut response, ATTR_OTHER_ADDRESS)
+                    .ok()?;
+            }
+        }
+        FINGERPRINT.add_to(&mut response).ok()?;
+        Some((target, response.raw))
+    }
+
+    /// Filtering behavior of the NAT a WebRTC connection's client is behind,
+    /// from whether connectivity checks from the responder's other sockets
+    /// reach the client's mapped address
+    pub async fn test_filtering(
+        &self,
+        client: SocketAddr,
+        credentials: &IceCredentials,
+    ) -> NatBehavior {
+        let ipv6 = client.is_ipv6();
+        let from_alternate_address = async {
+            match self.socket(ipv6, true, false) {
+                Some(socket) => Some(self.reaches(socket, client, credentials).await),
+                None => None,
+            }
+        };
+        let from_alternate_port = async {
+            match self.socket(ipv6, false, true) {
+                Some(socket) => Some(self.reaches(socket, client, credentials).await),
+                None => None,
+            }
+        };
+        let (from_alternate_address, from_alternate_port) =
+            tokio::join!(from_alternate_address, from_alternate_port);
+        classify_filtering(from_alternate_address, from_alternate_port)
+    }
+
+    async fn reaches(
+        &self,
+        socket: &ResponderSocket,
+        client: SocketAddr,
+        credentials: &IceCredentials,
+    ) -> bool {
+        for _ in 0..FILTERING_CHECKS {
+            let Ok((transaction_id, check)) = connectivity_check(credentials) else {
+                return false;
+            };
+            let (answered_tx, answered_rx) = oneshot::channel();
+            self.pending
+                .lock()
+                .unwrap()
+                .insert(transaction_id, answered_tx);
+
+            let answered = socket.socket.send_to(&check, client).await.is_ok()
+                && tokio::time::timeout(
+                    Duration::from_millis(FILTERING_TIMEOUT_MS / FILTERING_CHECKS),
+                    answered_rx,
+                )
+                .await
+                .is_ok_and(|answer| answer.is_ok());
+            self.pending.lock().unwrap().remove(&transaction_id);
+            if answered {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Mapping behavior from the mapped addresses a client reported for one
+    /// address family
+    pub fn classify_mapping(
+        &self,
+        ipv6: bool,
+        mapped_addresses: &[NatMappedAddress],
+        failed_servers: &[String],
+    ) -> MappingClassification {
+        let is_alternate = |host: &str| {
+            host.parse::<IpAddr>()
+                .is_ok_and(|ip| self.alternate_addresses.contains(&ip))
+        };
+        let observations: Vec<MappingObservation> = mapped_addresses
+            .iter()
+            .filter_map(|mapped| {
+                let (host, _) = split_server(&mapped.server)?;
+                let mapped_address: SocketAddr = mapped.mapped_address.parse().ok()?;
+                (mapped_address.is_ipv6() == ipv6).then(|| MappingObservation {
+                    alternate_address: is_alternate(host),
+                    mapped_address,
+                    local_port: mapped.local_port,
+                })
+            })
+            .collect();
+
+        // Servers the client queried: the primary address on each port, and
+        // the alternate address of the family on the primary port
+        let has_alternate = self
+            .alternate_addresses
+            .iter()
+            .any(|address| address.is_ipv6() == ipv6);
+        let failed_alternate = failed_servers.iter().any(|server| {
+            split_server(server).is_some_and(|(host, _)| {
+                host.parse::<IpAddr>()
+                    .is_ok_and(|ip| ip.is_ipv6() == ipv6 && is_alternate(host))
+            })
+        });
+        let failed_primary = failed_servers
+            .iter()
+            .filter(|server| split_server(server).is_some_and(|(host, _)| !is_alternate(host)))
+            .count();
+        let primary_answered = self.ports.len().saturating_sub(failed_primary);
+        let alternate_answered = has_alternate && !failed_alternate;
+        let servers_answered = primary_answered + alternate_answered as usize;
+
+        classify_mapping(
+            &observations,
+            servers_answered,
+            primary_answered > 0 && alternate_answered,
+        )
+    }
+}
+
+fn address_attribute(address: SocketAddr) -> MappedAddress {
+    MappedAddress {
+        ip: address.ip(),
+        port: address.port(),
+    }
+}
+
+/// Bind a responder socket, sharing the port with sockets on other addresses
+fn bind(address: SocketAddr) -> std::io::Result<UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(address),
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// ICE connectivity check to the client of a WebRTC connection
+fn connectivity_check(
+    credentials: &IceCredentials,
+) -> Result<(TransactionId, Vec<u8>), webrtc::stun::Error> {
+    let transaction_id = TransactionId::new();
+    let tie_breaker = u64::from_be_bytes(transaction_id.0[..8].try_into().unwrap());
+    let mut check = Message::new();
+    check.build(&[
+        Box::new(BINDING_REQUEST),
+        Box::new(transaction_id),
+        Box::new(TextAttribute::new(
+            ATTR_USERNAME,
+            format!("{}:{}", credentials.remote_ufrag, credentials.local_ufrag),
+        )),
+        Box::new(AttrControlled(tie_breaker)),
+        Box::new(PriorityAttr(PEER_REFLEXIVE_PRIORITY)),
+        Box::new(MessageIntegrity::new_short_term_integrity(
+            credentials.remote_pwd.clone(),
+        )),
+        Box::new(FINGERPRINT),
+    ])?;
+    Ok((transaction_id, check.raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webrtc::stun::message::Getter;
+
+    fn observation(alternate_address: bool, mapped_address: &str) -> MappingObservation {
+        MappingObservation {
+            alternate_address,
+            mapped_address: mapped_address.parse().unwrap(),
+            local_port: Some(50000),
+        }
+    }
+
+    #[test]
+    fn test_ice_credentials_from_sdp() {
+        let sdp = "v=0\r\na=ice-ufrag:abcd\r\na=ice-pwd:secretsecretsecret\r\n";
+        assert_eq!(
+            ice_credentials_from_sdp(sdp),
+            Some(("abcd".to_string(), "secretsecretsecret".to_string()))
+        );
+        assert_eq!(
+            ice_credentials_from_sdp("v=0\r\na=ice-ufrag:abcd\r\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_classify_filtering() {
+        use NatBehavior::*;
+        assert_eq!(
+            classify_filtering(Some(true), Some(true)),
+            EndpointIndependent
+        );
+        assert_eq!(
+            classify_filtering(Some(false), Some(true)),
+            AddressDependent
+        );
+        assert_eq!(
+            classify_filtering(Some(false), Some(false)),
+            AddressAndPortDependent
+        );
+        assert_eq!(
+            classify_filtering(None, Some(false)),
+            AddressAndPortDependent
+        );
+        assert_eq!(classify_filtering(None, Some(true)), Unknown);
+        assert_eq!(classify_filtering(None, None), Unknown);
+    }
+
+    #[test]
+    fn test_classify_mapping() {
+        // One address reported by servers on both addresses
+        let mapped = [observation(false, "198.51.100.7:50000")];
+        let classification = classify_mapping(&mapped, 3, true);
+        assert_eq!(classification.mapping, NatBehavior::EndpointIndependent);
+        assert_eq!(classification.symmetric, Some(false));
+        assert_eq!(classification.port_preservation, Some(true));
+
+        // Without the alternate address only port dependence is known
+        let classification = classify_mapping(&mapped, 2, false);
+        assert_eq!(classification.mapping, NatBehavior::Unknown);
+        assert_eq!(classification.symmetric, Some(false));
+
+        // A new mapping per server address
+        let mapped = [
+            observation(false, "198.51.100.7:61000"),
+            observation(true, "198.51.100.7:61001"),
+        ];
+        let classification = classify_mapping(&mapped, 3, true);
+        assert_eq!(classification.mapping, NatBehavior::AddressDependent);
+        assert_eq!(classification.port_preservation, Some(false));
+
+        // A new mapping per server port, as with symmetric NATs
+        let mapped = [
+            observation(false, "198.51.100.7:61000"),
+            observation(false, "198.51.100.7:61002"),
+            observation(true, "198.51.100.7:61001"),
+        ];
+        let classification = classify_mapping(&mapped, 3, true);
+        assert_eq!(classification.mapping, NatBehavior::AddressAndPortDependent);
+        assert_eq!(classification.symmetric, Some(true));
+
+        assert_eq!(classify_mapping(&[], 3, true).mapping, NatBehavior::Unknown);
+    }
+
+    #[tokio::test]
+    async fn test_responder_change_request() {
+        let config = NatBehaviorConfig {
+            enabled: true,
+            ports: vec![0, 0],
+            addresses: vec!["127.0.0.1".to_string()],
+            alternate_addresses: vec!["127.0.0.2".to_string()],
+        };
+        let responder = NatBehaviorResponder::start(&config).unwrap();
+        let primary = responder.local_addr(false, false);
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        for (change, origin) in [
+            (0, primary),
+            (CHANGE_PORT, responder.local_addr(false, true)),
+            (CHANGE_IP | CHANGE_PORT, responder.local_addr(true, true)),
+        ] {
+            let mut request = Message::new();
+            request
+                .build(&[Box::new(BINDING_REQUEST), Box::new(TransactionId::new())])
+                .unwrap();
+            request.add(ATTR_CHANGE_REQUEST, &[0, 0, 0, change]);
+            request.write_length();
+            client.send_to(&request.raw, primary).await.unwrap();
+
+            let mut buf = [0u8; 1500];
+            let (len, from) =
+                tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(from, origin);
+
+            let mut response = Message::new();
+            response.unmarshal_binary(&buf[..len]).unwrap();
+            assert_eq!(response.typ, BINDING_SUCCESS);
+            assert_eq!(response.transaction_id, request.transaction_id);
+            let mut mapped = XorMappedAddress::default();
+            mapped.get_from(&response).unwrap();
+            assert_eq!(
+                SocketAddr::new(mapped.ip, mapped.port),
+                client.local_addr().unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filtering_checks_answered() {
+        let config = NatBehaviorConfig {
+            enabled: true,
+            ports: vec![0, 0],
+            addresses: vec!["127.0.0.1".to_string()],
+            alternate_addresses: Vec::new(),
+        };
+        let responder = NatBehaviorResponder::start(&config).unwrap();
+
+        // A client answering connectivity checks like a browser would
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_addr = client.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1500];
+            while let Ok((len, from)) = client.recv_from(&mut buf).await {
+                let mut check = Message::new();
+                check.unmarshal_binary(&buf[..len]).unwrap();
+                let mut answer = Message::new();
+                answer
+                    .build(&[Box::new(BINDING_SUCCESS), Box::new(check.transaction_id)])
+                    .unwrap();
+                client.send_to(&answer.raw, from).await.unwrap();
+            }
+        });
+
+        let credentials = IceCredentials {
+            local_ufrag: "server".to_string(),
+            remote_ufrag: "client".to_string(),
+            remote_pwd: "clientpasswordclientpassword".to_string(),
+        };
+        // Reached from the alternate port, no alternate address to tell more
+        assert_eq!(
+            responder.test_filtering(client_addr, &credentials).await,
+            NatBehavior::Unknown
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! NAT binding timeout measurement
+//!
+//! Runs on a dedicated connection of `common::NAT_LIFETIME_MODE`, on which the
+//! server sends no ICE keepalives and no application traffic. The server leaves
+//! the connection idle for a while, then checks whether testprobes it sends
+//! still reach the client through the client's NAT binding. The idle period is
+//! doubled from the shortest one until the binding expires, then bisected.
+//!
+//! Browsers keep sending ICE consent checks (RFC 7675) and SCTP heartbeats
+//! whatever the application does, and NATs refreshing their bindings on
+//! outgoing packets keep the binding alive on them: what the test finds are
+//! bindings with a hard lifetime, or that outgoing traffic does not refresh.
+
+/// Probes sent after each idle period
+pub const PROBES: u32 = 3;
+
+/// Interval between the probes sent after an idle period
+pub const PROBE_INTERVAL_MS: u64 = 200;
+
+/// How long to wait for the client to echo a probe
+pub const ECHO_TIMEOUT_MS: u64 = 3000;
+
+/// Attempts at getting probes through again once a binding has expired
+pub const RECOVERY_ATTEMPTS: u32 = 5;
+
+/// Search for the longest idle period a NAT binding survives
+#[derive(Debug, Clone)]
+pub struct IdleSearch {
+    min_idle_ms: u64,
+    max_idle_ms: u64,
+    resolution_ms: u64,
+    survived: Option<u64>,
+    expired: Option<u64>,
+}
+
+impl IdleSearch {
+    pub fn new(min_idle_ms: u64, max_idle_ms: u64, resolution_ms: u64) -> Self {
+        let min_idle_ms = min_idle_ms.max(1000);
+        Self {
+            min_idle_ms,
+            max_idle_ms: max_idle_ms.max(min_idle_ms),
+            resolution_ms: resolution_ms.max(1000),
+            survived: None,
+            expired: None,
+        }
+    }
+
+    /// Idle period to test next, none once the search is over
+    pub fn next_idle_ms(&self) -> Option<u64> {
+        match (self.survived, self.expired) {
+            (None, None) => Some(self.min_idle_ms),
+            (Some(survived), None) if survived >= self.max_idle_ms => None,
+            (Some(survived), None) => Some((survived * 2).min(self.max_idle_ms)),
+            // Expired after the shortest idle period already
+            (None, Some(_)) => None,
+            (Some(survived), Some(expired)) if expired - survived <= self.resolution_ms => None,
+            (Some(survived), Some(expired)) => Some(survived + (expired - survived) / 2),
+        }
+    }
+
+    /// Record whether probes got through after `idle_ms`
+    pub fn record(&mut self, idle_ms: u64, delivered: bool) {
+        if delivered {
+            self.survived = Some(
+                self.survived
+                    .map_or(idle_ms, |survived| survived.max(idle_ms)),
+            );
+        } else {
+            self.expired = Some(self.expired.map_or(idle_ms, |expired| expired.min(idle_ms)));
+        }
+    }
+
+    /// Longest idle period after which probes still got through
+    pub fn survived_idle_ms(&self) -> Option<u64> {
+        self.survived
+    }
+
+    /// Shortest idle period after which probes no longer got through
+    pub fn expired_idle_ms(&self) -> Option<u64> {
+        self.expired
+    }
+
+    /// Binding timeout, estimated as the middle of the bounds found
+    pub fn binding_timeout_ms(&self) -> Option<u64> {
+        let expired = self.expired?;
+        Some((self.survived.unwrap_or(0) + expired) / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a search against a NAT with bindings of `timeout_ms`
+    fn search(timeout_ms: u64) -> (IdleSearch, Vec<u64>) {
+        let mut search = IdleSearch::new(5_000, 600_000, 5_000);
+        let mut trials = Vec::new();
+        while let Some(idle_ms) = search.next_idle_ms() {
+            trials.push(idle_ms);
+            search.record(idle_ms, idle_ms < timeout_ms);
+        }
+        (search, trials)
+    }
+
+    #[test]
+    fn test_binding_timeout_found() {
+        let (search, trials) = search(65_000);
+        assert_eq!(&trials[..5], &[5_000, 10_000, 20_000, 40_000, 80_000]);
+        assert_eq!(
+            search.expired_idle_ms().unwrap() - search.survived_idle_ms().unwrap(),
+            5_000
+        );
+        let timeout_ms = search.binding_timeout_ms().unwrap();
+        assert!(timeout_ms.abs_diff(65_000) <= 5_000, "{}", timeout_ms);
+    }
+
+    #[test]
+    fn test_binding_outlives_longest_idle_period() {
+        let (search, trials) = search(u64::MAX);
+        assert_eq!(trials.last(), Some(&600_000));
+        assert_eq!(search.survived_idle_ms(), Some(600_000));
+        assert_eq!(search.binding_timeout_ms(), None);
+    }
+
+    #[test]
+    fn test_binding_expires_before_shortest_idle_period() {
+        let (search, trials) = search(2_000);
+        assert_eq!(trials, vec![5_000]);
+        assert_eq!(search.survived_idle_ms(), None);
+        assert_eq!(search.binding_timeout_ms(), Some(2_500));
+    }
+}
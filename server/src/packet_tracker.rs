@@ -1,4 +1,4 @@
-use common::{SendOptions, TrackedPacketEvent};
+use common::{IcmpExtensions, SendOptions, TrackedPacketEvent};
 /// Packet tracking for ICMP correlation
 ///
 /// This module manages tracking of UDP packets for correlation with ICMP errors.
@@ -20,6 +20,9 @@ pub struct UdpPacketInfo {
     /// Actual UDP packet length (UDP header + payload)
     pub udp_length: u16,
 
+    /// Length of the packet on the wire (IP header + UDP header + payload)
+    pub ip_length: u16,
+
     /// Original cleartext data before encryption
     pub cleartext: Vec<u8>,
 
@@ -71,6 +74,9 @@ pub struct TrackedPacket {
 
     /// UDP checksum for matching with ICMP errors
     pub udp_checksum: u16,
+
+    /// Length of the packet on the wire as sent, 0 if unknown
+    pub ip_length: u16,
 }
 
 /// The class of ICMP/ICMPv6 message - either a TTL-related one or an error - or ignore
@@ -233,6 +239,7 @@ impl PacketTracker {
             payload_prefix: payload_prefix.clone(),
             conn_id,
             udp_checksum,
+            ip_length: 0,
         };
 
         // Add to checksum index
@@ -254,6 +261,7 @@ impl PacketTracker {
         is_ip6: bool,
         embedded_udp_info: EmbeddedUdpInfo,
         router_ip: Option<String>,
+        icmp_extensions: IcmpExtensions,
     ) {
         tracing::trace!("match_icmp_error called: src_port={}, dest={}, udp_length={}, udp_checksum={:#06x}, payload_prefix_len={}", 
             embedded_udp_info.src_port, embedded_udp_info.dest_addr, embedded_udp_info.udp_length,
@@ -300,7 +308,11 @@ impl PacketTracker {
                 embedded_udp_info.udp_length,
                 tracked.conn_id
             );
-            let tracked_ip_length = if is_ip6 {
+            // Prefer the length the packet was sent with over the one
+            // reconstructed from the quoted UDP header
+            let tracked_ip_length = if tracked.ip_length > 0 {
+                tracked.ip_length
+            } else if is_ip6 {
                 embedded_udp_info.udp_length + 40
             } else {
                 embedded_udp_info.udp_length + 20
@@ -319,6 +331,7 @@ impl PacketTracker {
                 conn_id: tracked.conn_id,
                 original_src_port: embedded_udp_info.src_port,
                 original_dest_addr: embedded_udp_info.dest_addr.to_string(),
+                icmp_extensions,
             };
 
             tracing::trace!(
@@ -443,6 +456,7 @@ impl PacketTracker {
                 payload_prefix: payload_prefix.clone(),
                 conn_id,
                 udp_checksum: info.udp_checksum,
+                ip_length: info.ip_length,
             };
 
             // Add to checksum index if we have a non-zero checksum
@@ -544,6 +558,9 @@ mod tests {
             track_for_ms: 5000,
             bypass_dtls: false,
             bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         let dest = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8080);
@@ -576,6 +593,9 @@ mod tests {
             track_for_ms: 5000,
             bypass_dtls: false,
             bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         let dest = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8080);
@@ -631,6 +651,9 @@ mod tests {
             track_for_ms: 5000,
             bypass_dtls: false,
             bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         let dest = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8080);
@@ -788,6 +811,9 @@ mod tests {
             track_for_ms: 5000,
             bypass_dtls: false,
             bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         // Track a packet
@@ -842,6 +868,9 @@ mod tests {
             track_for_ms: 5000,
             bypass_dtls: false,
             bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         let dest = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8080);
@@ -904,6 +933,9 @@ mod tests {
             track_for_ms: 5000,
             bypass_dtls: false,
             bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         let dest = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8080);
@@ -956,6 +988,9 @@ mod tests {
             track_for_ms: 5000,
             bypass_dtls: false,
             bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         let dest1 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8080);
@@ -1047,6 +1082,9 @@ mod tests {
             track_for_ms: 5000,
             bypass_dtls: false,
             bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         let dest = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8080);
@@ -1108,6 +1146,9 @@ mod tests {
             track_for_ms: 5000,
             bypass_dtls: false,
             bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         let dest = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8080);
@@ -1162,6 +1203,9 @@ mod tests {
             track_for_ms: 5000,
             bypass_dtls: false,
             bypass_sctp_fragmentation: false,
+            ipv6_hop_by_hop_len: None,
+            ipv6_dest_opts_len: None,
+            out_of_order_fragment_size: None,
         };
 
         let dest = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8080);
@@ -22,6 +22,9 @@ pub struct TrackedEventInfo {
     pub rtt_ms: u64,
     pub send_options: common::SendOptions,
     pub router_ip: Option<String>,
+    /// MPLS label stack and interface information from ICMP extensions
+    #[serde(skip_serializing_if = "common::IcmpExtensions::is_empty")]
+    pub icmp_extensions: common::IcmpExtensions,
 
     /// Base64 encoded packets for inspection
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -54,6 +57,7 @@ pub async fn get_tracked_events(
                 rtt_ms: rtt.as_millis() as u64,
                 send_options: event.send_options,
                 router_ip: event.router_ip,
+                icmp_extensions: event.icmp_extensions,
                 icmp_packet_b64: Some(general_purpose::STANDARD.encode(&event.icmp_packet)),
                 udp_packet_b64: Some(general_purpose::STANDARD.encode(&event.udp_packet)),
                 cleartext_b64: Some(general_purpose::STANDARD.encode(&event.cleartext)),
@@ -0,0 +1,357 @@
+//! Reverse DNS (PTR) resolution of traceroute hop addresses
+//!
+//! Router PTR names often encode the location and interface of a hop
+//! (`ae-1.r23.londen12.nl.bb.example.net`). Queries go to the configured
+//! nameservers, or those in /etc/resolv.conf, through a trust-dns resolver
+//! whose bounded cache keeps answers for their TTL (capped) and addresses
+//! without a name for a shorter, fixed time.
+//!
+//! Lookups for a traceroute round share a [`ResolveBudget`]: once it is used
+//! up, hops are reported without names instead of waiting, and lookups still
+//! in flight complete in the background to fill the cache for later rounds.
+
+use crate::config::ReverseDnsConfig;
+use futures::future::BoxFuture;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use trust_dns_resolver::config::{
+    NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts,
+};
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::TokioAsyncResolver;
+
+const DNS_PORT: u16 = 53;
+
+/// Time a round may spend waiting for reverse DNS answers
+pub struct ResolveBudget {
+    remaining: Duration,
+}
+
+impl ResolveBudget {
+    pub fn new(budget: Duration) -> Self {
+        Self { remaining: budget }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining.is_zero()
+    }
+}
+
+/// Asynchronous PTR resolver with a TTL-respecting cache
+pub struct ReverseDnsResolver {
+    resolver: Option<TokioAsyncResolver>,
+    /// Addresses with a lookup in flight
+    pending: Mutex<HashSet<IpAddr>>,
+}
+
+impl ReverseDnsResolver {
+    /// Create a resolver; without configured nameservers those of
+    /// /etc/resolv.conf are used
+    pub fn new(config: &ReverseDnsConfig) -> Self {
+        let nameservers: Vec<SocketAddr> = config
+            .nameservers
+            .iter()
+            .filter_map(|server| {
+                let parsed = server.parse::<SocketAddr>().or_else(|_| {
+                    server
+                        .parse::<IpAddr>()
+                        .map(|ip| SocketAddr::new(ip, DNS_PORT))
+                });
+                if parsed.is_err() {
+                    tracing::warn!("Ignoring invalid reverse DNS nameserver '{}'", server);
+                }
+                parsed.ok()
+            })
+            .collect();
+
+        let resolver_config = if nameservers.is_empty() {
+            match trust_dns_resolver::system_conf::read_system_conf() {
+                Ok((resolver_config, _)) => Some(resolver_config),
+                Err(e) => {
+                    tracing::warn!("Failed to read the system resolver configuration: {}", e);
+                    None
+                }
+            }
+        } else {
+            // TCP is only used for answers truncated over UDP
+            let group: NameServerConfigGroup = nameservers
+                .iter()
+                .flat_map(|server| {
+                    [
+                        NameServerConfig::new(*server, Protocol::Udp),
+                        NameServerConfig::new(*server, Protocol::Tcp),
+                    ]
+                })
+                .collect::<Vec<_>>()
+                .into();
+            Some(ResolverConfig::from_parts(None, vec![], group))
+        };
+        let resolver = resolver_config
+            .filter(|resolver_config| !resolver_config.name_servers().is_empty())
+            .map(|resolver_config| {
+                let mut opts = ResolverOpts::default();
+                opts.timeout = Duration::from_millis(config.query_timeout_ms);
+                opts.attempts = 1;
+                opts.cache_size = config.cache_size;
+                opts.positive_max_ttl = Some(Duration::from_secs(config.max_ttl_secs));
+                opts.negative_min_ttl = Some(Duration::from_secs(config.negative_ttl_secs));
+                opts.negative_max_ttl = Some(Duration::from_secs(config.negative_ttl_secs));
+                opts.use_hosts_file = false;
+                TokioAsyncResolver::tokio(resolver_config, opts)
+            });
+        if resolver.is_none() {
+            tracing::warn!("No nameservers for reverse DNS, hop names will not be resolved");
+        }
+
+        Self {
+            resolver,
+            pending: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Resolve the name of an address, from the cache if possible
+    pub async fn resolve(&self, ip: IpAddr) -> Option<String> {
+        let resolver = self.resolver.as_ref()?;
+        match resolver.reverse_lookup(ip).await {
+            Ok(lookup) => lookup
+                .iter()
+                .next()
+                .map(|name| name.to_utf8().trim_end_matches('.').to_string()),
+            Err(e) => {
+                if !matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) {
+                    tracing::debug!("PTR lookup for {} failed: {}", ip, e);
+                }
+                None
+            }
+        }
+    }
+
+    /// Resolve the names of addresses concurrently, waiting no longer than
+    /// the budget allows; unresolved addresses are left out
+    pub async fn resolve_within(
+        self: &Arc<Self>,
+        ips: &[IpAddr],
+        budget: &mut ResolveBudget,
+    ) -> HashMap<IpAddr, String> {
+        let mut names = HashMap::new();
+        let mut lookups: Vec<(IpAddr, BoxFuture<'static, Option<String>>)> = Vec::new();
+        for ip in ips.iter().copied().collect::<HashSet<_>>() {
+            if !self.pending.lock().unwrap().insert(ip) {
+                continue;
+            }
+            let resolver = self.clone();
+            lookups.push((
+                ip,
+                Box::pin(async move {
+                    let name = resolver.resolve(ip).await;
+                    resolver.pending.lock().unwrap().remove(&ip);
+                    name
+                }),
+            ));
+        }
+
+        // Cached names come back on the first poll, even without budget left
+        let started = Instant::now();
+        let wait = futures::future::poll_fn(|cx| {
+            lookups.retain_mut(|(ip, lookup)| match lookup.as_mut().poll(cx) {
+                Poll::Ready(name) => {
+                    if let Some(name) = name {
+                        names.insert(*ip, name);
+                    }
+                    false
+                }
+                Poll::Pending => true,
+            });
+            if lookups.is_empty() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        });
+        if tokio::time::timeout(budget.remaining, wait).await.is_ok() {
+            budget.remaining = budget.remaining.saturating_sub(started.elapsed());
+        } else {
+            if !budget.is_exhausted() {
+                tracing::debug!("Reverse DNS budget used up, continuing without names");
+            }
+            budget.remaining = Duration::ZERO;
+        }
+
+        // Lookups that outlast the budget keep running and fill the cache
+        for (_, lookup) in lookups {
+            tokio::spawn(lookup);
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::UdpSocket;
+    use trust_dns_resolver::proto::op::{Message, MessageType, ResponseCode};
+    use trust_dns_resolver::proto::rr::rdata::{PTR, SOA};
+    use trust_dns_resolver::proto::rr::{Name, RData, Record};
+
+    /// Stub DNS server answering PTR queries from a table; unlisted names get
+    /// NXDOMAIN and names mapped to `None` are never answered
+    async fn stub_dns_server(
+        records: Vec<(&'static str, Option<(&'static str, u32)>)>,
+    ) -> (SocketAddr, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counter = queries.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 512];
+            loop {
+                let Ok((len, from)) = socket.recv_from(&mut buf).await else {
+                    return;
+                };
+                counter.fetch_add(1, Ordering::SeqCst);
+                let query = Message::from_vec(&buf[..len]).unwrap();
+                let question = query.queries()[0].clone();
+                let name = question.name().to_utf8();
+
+                let record = records
+                    .iter()
+                    .find(|(n, _)| name.trim_end_matches('.') == *n)
+                    .map(|(_, r)| *r);
+                let mut response = Message::new();
+                response
+                    .set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_desired(true)
+                    .set_recursion_available(true)
+                    .add_query(question.clone());
+                match record {
+                    Some(None) => continue,
+                    Some(Some((target, ttl))) => {
+                        let target = Name::from_utf8(target).unwrap();
+                        response.add_answer(Record::from_rdata(
+                            question.name().clone(),
+                            ttl,
+                            RData::PTR(PTR(target)),
+                        ));
+                    }
+                    None => {
+                        let zone = Name::from_utf8("in-addr.arpa").unwrap();
+                        let soa = SOA::new(zone.clone(), zone.clone(), 1, 3600, 600, 86400, 60);
+                        response
+                            .set_response_code(ResponseCode::NXDomain)
+                            .add_name_server(Record::from_rdata(zone, 60, RData::SOA(soa)));
+                    }
+                }
+                let _ = socket.send_to(&response.to_vec().unwrap(), from).await;
+            }
+        });
+        (addr, queries)
+    }
+
+    fn resolver_config(server: SocketAddr) -> ReverseDnsConfig {
+        ReverseDnsConfig {
+            nameservers: vec![server.to_string()],
+            query_timeout_ms: 200,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_and_cache() {
+        let (server, queries) = stub_dns_server(vec![(
+            "1.2.0.192.in-addr.arpa",
+            Some(("ae-1.r23.londen12.nl.bb.example.net", 300)),
+        )])
+        .await;
+        let resolver = ReverseDnsResolver::new(&resolver_config(server));
+
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        assert_eq!(
+            resolver.resolve(ip).await.as_deref(),
+            Some("ae-1.r23.londen12.nl.bb.example.net")
+        );
+        assert_eq!(
+            resolver.resolve(ip).await.as_deref(),
+            Some("ae-1.r23.londen12.nl.bb.example.net")
+        );
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+
+        // NXDOMAIN is cached as having no name
+        let unnamed: IpAddr = "192.0.2.2".parse().unwrap();
+        assert_eq!(resolver.resolve(unnamed).await, None);
+        assert_eq!(resolver.resolve(unnamed).await, None);
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_respects_ttl_and_capacity() {
+        let (server, queries) = stub_dns_server(vec![
+            ("1.2.0.192.in-addr.arpa", Some(("short.example.net", 0))),
+            ("2.2.0.192.in-addr.arpa", Some(("two.example.net", 300))),
+            ("3.2.0.192.in-addr.arpa", Some(("three.example.net", 300))),
+        ])
+        .await;
+        let config = ReverseDnsConfig {
+            cache_size: 1,
+            ..resolver_config(server)
+        };
+        let resolver = ReverseDnsResolver::new(&config);
+
+        // A zero TTL is not cached
+        let short: IpAddr = "192.0.2.1".parse().unwrap();
+        resolver.resolve(short).await;
+        resolver.resolve(short).await;
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        // The cache holds one entry
+        let two: IpAddr = "192.0.2.2".parse().unwrap();
+        let three: IpAddr = "192.0.2.3".parse().unwrap();
+        resolver.resolve(two).await;
+        resolver.resolve(three).await;
+        assert_eq!(
+            resolver.resolve(three).await.as_deref(),
+            Some("three.example.net")
+        );
+        assert_eq!(queries.load(Ordering::SeqCst), 4);
+        resolver.resolve(two).await;
+        assert_eq!(queries.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_budget_bounds_waiting() {
+        let (server, _) = stub_dns_server(vec![
+            ("1.2.0.192.in-addr.arpa", Some(("fast.example.net", 300))),
+            // Never answered
+            ("2.2.0.192.in-addr.arpa", None),
+        ])
+        .await;
+        let config = ReverseDnsConfig {
+            query_timeout_ms: 2000,
+            ..resolver_config(server)
+        };
+        let resolver = Arc::new(ReverseDnsResolver::new(&config));
+        let fast: IpAddr = "192.0.2.1".parse().unwrap();
+        let slow: IpAddr = "192.0.2.2".parse().unwrap();
+
+        let mut budget = ResolveBudget::new(Duration::from_millis(300));
+        let started = Instant::now();
+        let names = resolver.resolve_within(&[fast, slow], &mut budget).await;
+        assert!(started.elapsed() < Duration::from_millis(1000));
+        assert!(budget.is_exhausted());
+        assert_eq!(
+            names.get(&fast).map(String::as_str),
+            Some("fast.example.net")
+        );
+        assert!(!names.contains_key(&slow));
+
+        // Without budget left, only cached names are returned
+        let started = Instant::now();
+        let names = resolver.resolve_within(&[fast, slow], &mut budget).await;
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert_eq!(names.len(), 1);
+    }
+}
@@ -0,0 +1,262 @@
+//! Reverse path hop count from the TTL / Hop Limit of received packets
+//!
+//! Traceroute measures the forward (server to client) path only. The vendored
+//! UDP socket reads the IP TTL / IPv6 Hop Limit of every packet it receives
+//! (IP_RECVTTL / IPV6_RECVHOPLIMIT) and reports it here per source address.
+//!
+//! Hosts send with one of a few well-known initial TTLs (64 for Linux, macOS,
+//! iOS and Android, 128 for Windows, 255 for network equipment), so the
+//! smallest of them at or above the received TTL gives the number of routers
+//! the packet went through. Compared with the forward hop count found by
+//! traceroute, this shows asymmetric routing.
+
+use crate::state::ClientSession;
+use common::ReversePathInfo;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Initial TTLs / Hop Limits in use, in increasing order
+pub const INITIAL_TTLS: [u8; 4] = [32, 64, 128, 255];
+
+/// Difference in hop count tolerated before the paths are reported asymmetric;
+/// a single hop is common with load balancers and tunnels on one side only
+pub const ASYMMETRY_TOLERANCE_HOPS: u16 = 1;
+
+/// Maximum number of peer addresses kept
+const MAX_PEERS: usize = 16384;
+
+/// Time after which a peer address without packets may be dropped
+const PEER_EXPIRY: Duration = Duration::from_secs(600);
+
+/// TTLs / Hop Limits received from one peer address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceivedTtl {
+    pub last: u8,
+    pub min: u8,
+    pub max: u8,
+    pub updated_at: Instant,
+}
+
+/// TTLs / Hop Limits received per peer address
+#[derive(Default)]
+pub struct ReceivedTtlTable {
+    peers: Mutex<HashMap<SocketAddr, ReceivedTtl>>,
+}
+
+/// IPv4-mapped IPv6 addresses as IPv4, as dual-stack sockets report them
+fn normalize(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(v4) => SocketAddr::new(IpAddr::V4(v4), v6.port()),
+            None => addr,
+        },
+        SocketAddr::V4(_) => addr,
+    }
+}
+
+impl ReceivedTtlTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the TTL of a packet received from `peer`
+    pub fn record(&self, peer: SocketAddr, ttl: u8) {
+        let peer = normalize(peer);
+        let now = Instant::now();
+        let mut peers = self.peers.lock().unwrap();
+
+        if let Some(received) = peers.get_mut(&peer) {
+            received.last = ttl;
+            received.min = received.min.min(ttl);
+            received.max = received.max.max(ttl);
+            received.updated_at = now;
+            return;
+        }
+
+        if peers.len() >= MAX_PEERS {
+            peers.retain(|_, received| now.duration_since(received.updated_at) < PEER_EXPIRY);
+        }
+        if peers.len() >= MAX_PEERS {
+            let oldest = peers
+                .iter()
+                .min_by_key(|(_, received)| received.updated_at)
+                .map(|(peer, _)| *peer);
+            if let Some(oldest) = oldest {
+                peers.remove(&oldest);
+            }
+        }
+        peers.insert(
+            peer,
+            ReceivedTtl {
+                last: ttl,
+                min: ttl,
+                max: ttl,
+                updated_at: now,
+            },
+        );
+    }
+
+    /// TTLs received from `peer`, if any
+    pub fn get(&self, peer: SocketAddr) -> Option<ReceivedTtl> {
+        self.peers.lock().unwrap().get(&normalize(peer)).copied()
+    }
+}
+
+/// Initial TTL / Hop Limit a packet received with `received_ttl` was sent with
+pub fn initial_ttl(received_ttl: u8) -> u8 {
+    INITIAL_TTLS
+        .into_iter()
+        .find(|&initial| initial >= received_ttl)
+        .unwrap_or(u8::MAX)
+}
+
+/// Compare the reverse path length with the forward one
+///
+/// `path_ttl` is the TTL of the first traceroute probe that reached the
+/// client; such a probe went through `path_ttl - 1` routers.
+pub fn analyze(received: &ReceivedTtl, path_ttl: Option<u8>) -> ReversePathInfo {
+    let initial_ttl = initial_ttl(received.last);
+    let reverse_hops = initial_ttl - received.last;
+    let forward_hops = path_ttl.map(|ttl| ttl.saturating_sub(1));
+    let hop_difference = forward_hops.map(|forward| reverse_hops as i16 - forward as i16);
+
+    ReversePathInfo {
+        received_ttl: received.last,
+        min_received_ttl: received.min,
+        max_received_ttl: received.max,
+        initial_ttl,
+        reverse_hops,
+        forward_hops,
+        hop_difference,
+        asymmetric: hop_difference
+            .map(|difference| difference.unsigned_abs() > ASYMMETRY_TOLERANCE_HOPS),
+    }
+}
+
+/// Reverse path of a session, once packets were received from its peer address
+pub async fn session_reverse_path(session: &ClientSession) -> Option<ReversePathInfo> {
+    let (address, port) = session.peer_address.lock().await.clone()?;
+    let ip: IpAddr = address.parse().ok()?;
+    let received = session.received_ttls.get(SocketAddr::new(ip, port))?;
+    let path_ttl = session.measurement_state.read().await.path_ttl;
+    Some(analyze(&received, path_ttl))
+}
+
+static RECEIVED_TTLS: OnceLock<Arc<ReceivedTtlTable>> = OnceLock::new();
+
+/// Initialize the global table the UDP layer reports received TTLs to
+/// Should be called once at application startup
+pub fn init_received_ttls(table: Arc<ReceivedTtlTable>) {
+    if RECEIVED_TTLS.set(table).is_err() {
+        panic!("Received TTL table already initialized");
+    }
+}
+
+fn record_received_ttl(peer: SocketAddr, ttl: u8) {
+    if let Some(table) = RECEIVED_TTLS.get() {
+        table.record(peer, ttl);
+    }
+}
+
+/// C-compatible FFI function recording the TTL of a received IPv4 packet
+/// This is called from the vendored webrtc-util code
+#[no_mangle]
+pub extern "C" fn netpoke_record_received_ttl(
+    src_ip_v4: u32, // Source IPv4 address as u32
+    src_port: u16,  // Source port in host byte order
+    ttl: u8,        // TTL of the received packet
+) {
+    let src_addr = SocketAddr::from((std::net::Ipv4Addr::from(src_ip_v4), src_port));
+    record_received_ttl(src_addr, ttl);
+}
+
+/// C-compatible FFI function recording the Hop Limit of a received IPv6 packet
+/// This is called from the vendored webrtc-util code
+///
+/// # Safety
+/// `src_ip_v6_ptr` must be null or point to a 16-byte IPv6 address.
+#[no_mangle]
+pub unsafe extern "C" fn netpoke_record_received_ttl_v6(
+    src_ip_v6_ptr: *const u8, // Pointer to 16-byte source IPv6 address
+    src_port: u16,            // Source port in host byte order
+    hop_limit: u8,            // Hop Limit of the received packet
+) {
+    if src_ip_v6_ptr.is_null() {
+        return;
+    }
+
+    let mut src_ip_bytes = [0u8; 16];
+    src_ip_bytes.copy_from_slice(std::slice::from_raw_parts(src_ip_v6_ptr, 16));
+    let src_addr = SocketAddr::from((std::net::Ipv6Addr::from(src_ip_bytes), src_port));
+    record_received_ttl(src_addr, hop_limit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initial_ttl() {
+        assert_eq!(initial_ttl(1), 32);
+        assert_eq!(initial_ttl(52), 64);
+        assert_eq!(initial_ttl(64), 64);
+        assert_eq!(initial_ttl(116), 128);
+        assert_eq!(initial_ttl(244), 255);
+        assert_eq!(initial_ttl(255), 255);
+    }
+
+    #[test]
+    fn test_analyze_symmetry() {
+        let received = |ttl| ReceivedTtl {
+            last: ttl,
+            min: ttl,
+            max: ttl,
+            updated_at: Instant::now(),
+        };
+
+        // Linux client 11 routers away, traceroute reached it with TTL 12
+        let info = analyze(&received(53), Some(12));
+        assert_eq!(info.initial_ttl, 64);
+        assert_eq!(info.reverse_hops, 11);
+        assert_eq!(info.forward_hops, Some(11));
+        assert_eq!(info.hop_difference, Some(0));
+        assert_eq!(info.asymmetric, Some(false));
+
+        // One hop more on the way back is tolerated
+        assert_eq!(analyze(&received(117), Some(11)).asymmetric, Some(false));
+
+        // Windows client 15 routers away on the return path, 9 on the way there
+        let info = analyze(&received(113), Some(10));
+        assert_eq!(info.reverse_hops, 15);
+        assert_eq!(info.hop_difference, Some(6));
+        assert_eq!(info.asymmetric, Some(true));
+
+        // No traceroute result yet
+        let info = analyze(&received(60), None);
+        assert_eq!(info.reverse_hops, 4);
+        assert_eq!(info.forward_hops, None);
+        assert_eq!(info.asymmetric, None);
+    }
+
+    #[test]
+    fn test_received_ttl_table() {
+        let table = ReceivedTtlTable::new();
+        let peer: SocketAddr = "198.51.100.7:50000".parse().unwrap();
+        assert!(table.get(peer).is_none());
+
+        table.record(peer, 52);
+        table.record(peer, 54);
+        // Dual-stack sockets report IPv4 peers as IPv4-mapped addresses
+        table.record("[::ffff:198.51.100.7]:50000".parse().unwrap(), 53);
+
+        let received = table.get(peer).unwrap();
+        assert_eq!((received.last, received.min, received.max), (53, 52, 54));
+        assert!(table.get("198.51.100.7:50001".parse().unwrap()).is_none());
+
+        let peer_v6: SocketAddr = "[2001:db8::7]:50000".parse().unwrap();
+        table.record(peer_v6, 57);
+        assert_eq!(table.get(peer_v6).unwrap().last, 57);
+    }
+}
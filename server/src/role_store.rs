@@ -0,0 +1,125 @@
+//! Database-backed role assignments
+//!
+//! Roles stored in the `role_assignments` table are granted in addition to the
+//! ones from the `[auth.roles]` configuration, so roles can be handed out
+//! without editing the configuration and restarting the server. User rows are
+//! keyed `"<provider>:<handle>"`, so a handle registered with another provider
+//! does not inherit them.
+
+use crate::database::DbConnection;
+use netpoke_auth::roles::{Role, RoleStore, RolesFuture};
+use rusqlite::params;
+
+/// Role store reading the `role_assignments` table
+pub struct DbRoleStore {
+    db: DbConnection,
+}
+
+impl DbRoleStore {
+    /// Create a new DbRoleStore with the given database connection
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// Load the roles assigned to a user of a provider or any of its groups
+    async fn load_roles(
+        &self,
+        provider: &str,
+        handle: &str,
+        groups: &[String],
+    ) -> Result<Vec<Role>, rusqlite::Error> {
+        let db = self.db.lock().await;
+        let mut stmt =
+            db.prepare("SELECT role FROM role_assignments WHERE subject_type = ? AND subject = ?")?;
+
+        let mut names: Vec<String> = Vec::new();
+        let user = format!("{}:{}", provider, handle);
+        let subjects = std::iter::once(("user", user.as_str()))
+            .chain(groups.iter().map(|group| ("group", group.as_str())));
+        for (subject_type, subject) in subjects {
+            let rows = stmt.query_map(params![subject_type, subject], |row| row.get(0))?;
+            for name in rows {
+                names.push(name?);
+            }
+        }
+
+        Ok(names
+            .iter()
+            .filter_map(|name| match name.parse() {
+                Ok(role) => Some(role),
+                Err(e) => {
+                    tracing::warn!("Ignoring role assignment: {}", e);
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+impl RoleStore for DbRoleStore {
+    fn roles_for<'a>(
+        &'a self,
+        provider: &'a str,
+        handle: &'a str,
+        groups: &'a [String],
+    ) -> RolesFuture<'a> {
+        Box::pin(async move {
+            self.load_roles(provider, handle, groups)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!("Failed to load roles for {}:{}: {}", provider, handle, e);
+                    Vec::new()
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_database;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_roles_for_user_and_groups() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+        {
+            let conn = db.lock().await;
+            conn.execute_batch(
+                "INSERT INTO role_assignments (subject_type, subject, role) VALUES
+                    ('user', 'plain:alice', 'analyst'),
+                    ('group', 'noc', 'operator'),
+                    ('group', 'sre', 'admin');",
+            )
+            .unwrap();
+        }
+        let store = DbRoleStore::new(db);
+
+        assert_eq!(
+            store.roles_for("plain", "alice", &[]).await,
+            vec![Role::Analyst]
+        );
+        assert!(store.roles_for("plain", "bob", &[]).await.is_empty());
+        // The same handle on another provider is a different user
+        assert!(store.roles_for("github", "alice", &[]).await.is_empty());
+        assert_eq!(
+            store
+                .roles_for("github", "bob", &["noc".to_string(), "dev".to_string()])
+                .await,
+            vec![Role::Operator]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_role_rejected() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+        let conn = db.lock().await;
+        let result = conn.execute(
+            "INSERT INTO role_assignments (subject_type, subject, role) VALUES ('user', 'alice', 'root')",
+            [],
+        );
+        assert!(result.is_err());
+    }
+}
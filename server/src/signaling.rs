@@ -1,8 +1,13 @@
 use crate::data_channels;
 use crate::state::AppState;
 use crate::webrtc_manager;
-use axum::{extract::State, http::StatusCode, Json};
-use common::{get_candidate_ip_version, is_name_based_candidate, IpFamily};
+use axum::{
+    extract::{Extension, State},
+    http::StatusCode,
+    Json,
+};
+use common::{get_candidate_ip_version, is_name_based_candidate, IpFamily, NAT_LIFETIME_MODE};
+use netpoke_auth::SurveySessionData;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use webrtc::ice::candidate::CandidatePairState;
@@ -70,7 +75,7 @@ pub struct SignalingStartRequest {
     pub sdp: String,
     pub parent_client_id: Option<String>, // For grouping multiple sessions
     pub ip_version: Option<String>,       // "ipv4" or "ipv6"
-    pub mode: Option<String>,             // "measurement" or "traceroute"
+    pub mode: Option<String>,             // "measurement", "traceroute" or "nat_lifetime"
     /// Connection ID (UUID) for multi-path ECMP testing
     /// Generated by client, used to identify the connection
     #[serde(default)]
@@ -95,6 +100,7 @@ pub struct IceCandidateRequest {
 
 pub async fn signaling_start(
     State(state): State<AppState>,
+    survey_session: Option<Extension<SurveySessionData>>,
     Json(req): Json<SignalingStartRequest>,
 ) -> Result<Json<SignalingStartResponse>, StatusCode> {
     tracing::info!("Received signaling start request: {:?}", &req);
@@ -106,8 +112,11 @@ pub async fn signaling_start(
     // and defaults to IpFamily::Both for any other value.
     let ip_family = req.ip_version.as_ref().map(|v| IpFamily::from_str_loose(v));
 
+    // NAT binding timeout test connections must stay idle
+    let ice_keepalives = req.mode.as_deref() != Some(NAT_LIFETIME_MODE);
+
     // Create peer connection with IP family filtering
-    let peer = webrtc_manager::create_peer_connection(ip_family)
+    let peer = webrtc_manager::create_peer_connection(ip_family, ice_keepalives)
         .await
         .map_err(|e| {
             tracing::error!("Failed to create peer connection: {}", e);
@@ -151,14 +160,24 @@ pub async fn signaling_start(
         ice_candidates: ice_candidates.clone(),
         peer_address: peer_address.clone(),
         packet_tracker: state.packet_tracker.clone(), // Share global packet tracker
+        received_ttls: state.received_ttls.clone(),   // Share global received TTLs
         icmp_error_count: Arc::new(tokio::sync::Mutex::new(0)),
         last_icmp_error: Arc::new(tokio::sync::Mutex::new(None)),
         capture_service: state.capture_service.clone(),   // For survey-specific pcap
         keylog_service: state.keylog_service.clone(),     // For DTLS key storage
         session_manager: state.session_manager.clone(),   // For survey session lifecycle
         metrics_recorder: state.metrics_recorder.clone(), // For metrics persistence
-        magic_key: Arc::new(tokio::sync::RwLock::new(None)), // Set when survey starts
+        // Surveyors are bound to the magic key of their survey session token;
+        // for other users it is set when the survey starts
+        magic_key: Arc::new(tokio::sync::RwLock::new(
+            survey_session.map(|Extension(token)| token.magic_key),
+        )),
         magic_key_config: state.magic_key_config.clone(), // For measuring time limits
+        magic_key_store: state.magic_key_store.clone(),   // For key usage and limits
+        enrichment: state.enrichment.clone(),             // For hop and peer annotations
+        reverse_dns: state.reverse_dns.clone(),           // For hop names
+        nat_behavior: state.nat_behavior.clone(),         // For NAT type classification
+        recorded_peer: Arc::new(tokio::sync::Mutex::new(None)),
     });
 
     // Set up data channel handlers
@@ -1,10 +1,16 @@
+use crate::audit_log::DbAuditLog;
 use crate::dtls_keylog::DtlsKeylogService;
+use crate::enrichment::EnrichmentService;
+use crate::magic_keys::MagicKeyStore;
 use crate::metrics_recorder::MetricsRecorder;
+use crate::nat_behavior::NatBehaviorResponder;
 use crate::packet_capture::PacketCaptureService;
 use crate::packet_tracker::{PacketTracker, UdpPacketInfo};
+use crate::reverse_dns::ReverseDnsResolver;
+use crate::reverse_path::ReceivedTtlTable;
 use crate::session_manager::SessionManager;
 use common::ClientMetrics;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::mpsc;
@@ -18,6 +24,8 @@ pub struct AppState {
     pub clients: Arc<InstrumentedRwLock<HashMap<String, Arc<ClientSession>>>>,
     pub packet_tracker: Arc<PacketTracker>,
     pub tracking_sender: mpsc::UnboundedSender<UdpPacketInfo>,
+    /// TTLs of packets received per peer address, for reverse path analysis
+    pub received_ttls: Arc<ReceivedTtlTable>,
     pub server_start_time: Instant,
     /// Channel for sending peer connections that need to be closed
     /// This is used when signaling fails to prevent resource leaks
@@ -32,6 +40,16 @@ pub struct AppState {
     pub metrics_recorder: Option<Arc<MetricsRecorder>>,
     /// Magic key configuration for measuring time limits
     pub magic_key_config: Option<Arc<netpoke_auth::config::MagicKeyConfig>>,
+    /// Magic key store for validation, usage counting and per-key limits (database persistence)
+    pub magic_key_store: Option<Arc<MagicKeyStore>>,
+    /// Security audit log of downloads, wipes and administrative actions (database persistence)
+    pub audit_log: Option<Arc<DbAuditLog>>,
+    /// ASN, prefix and geolocation enrichment of hop and peer addresses
+    pub enrichment: Option<Arc<EnrichmentService>>,
+    /// Reverse DNS resolver for hop names, with the wait budget of a traceroute round
+    pub reverse_dns: Option<(Arc<ReverseDnsResolver>, std::time::Duration)>,
+    /// STUN behavior discovery responder for NAT type classification
+    pub nat_behavior: Option<Arc<NatBehaviorResponder>>,
 }
 
 #[derive(Debug)]
@@ -83,7 +101,7 @@ pub struct ClientSession {
     pub id: String,
     pub parent_id: Option<String>,
     pub ip_version: Option<String>,
-    pub mode: Option<String>, // "measurement" or "traceroute"
+    pub mode: Option<String>, // "measurement", "traceroute" or "nat_lifetime"
     /// Connection ID (UUID) for multi-path ECMP testing
     pub conn_id: String,
     /// Survey session ID (UUID) for cross-correlation across multiple connections
@@ -96,6 +114,7 @@ pub struct ClientSession {
     pub ice_candidates: Arc<Mutex<VecDeque<String>>>,
     pub peer_address: Arc<Mutex<Option<(String, u16)>>>, // (address, port)
     pub packet_tracker: Arc<PacketTracker>,              // For ICMP correlation
+    pub received_ttls: Arc<ReceivedTtlTable>,            // For reverse path analysis
     // ICMP error tracking for session cleanup
     pub icmp_error_count: Arc<Mutex<u32>>,
     pub last_icmp_error: Arc<Mutex<Option<Instant>>>,
@@ -111,6 +130,16 @@ pub struct ClientSession {
     pub magic_key: Arc<RwLock<Option<String>>>,
     /// Magic key configuration for measuring time limits
     pub magic_key_config: Option<Arc<netpoke_auth::config::MagicKeyConfig>>,
+    /// Magic key store for usage counting and per-key measuring time limits
+    pub magic_key_store: Option<Arc<MagicKeyStore>>,
+    /// ASN, prefix and geolocation enrichment of hop and peer addresses
+    pub enrichment: Option<Arc<EnrichmentService>>,
+    /// Reverse DNS resolver for hop names, with the wait budget of a traceroute round
+    pub reverse_dns: Option<(Arc<ReverseDnsResolver>, std::time::Duration)>,
+    /// STUN behavior discovery responder for NAT type classification
+    pub nat_behavior: Option<Arc<NatBehaviorResponder>>,
+    /// Peer address already recorded for the survey session
+    pub recorded_peer: Arc<Mutex<Option<String>>>,
 }
 
 pub struct DataChannels {
@@ -138,12 +167,16 @@ pub struct MeasurementState {
     pub sent_testprobes: VecDeque<SentProbe>, // Track sent test probes for traceroute
     pub sent_testprobes_map: HashMap<u64, SentProbe>, // Fast lookup by seq for test probes
     pub echoed_testprobes: VecDeque<EchoedProbe>, // Track echoed test probes
+    pub echoed_ext_header_probes: HashMap<u64, Option<u8>>, // Echoed extension header test probes (seq -> TTL)
+    pub echoed_fragmentation_probes: HashSet<u64>, // Echoed fragmentation test probes
+    pub echoed_nat_lifetime_probes: HashSet<u64>, // Echoed NAT lifetime test probes
     pub last_received_seq: Option<u64>,
     // Probe stream measurement fields
     pub probe_streams_active: bool, // Flag to indicate probe streams are active
     pub probe_streams_started_at: Option<Instant>, // When probe streams started (for duration enforcement)
     pub max_measuring_duration: Option<std::time::Duration>, // Maximum measuring duration (from magic key config)
     pub measurement_probe_seq: u64, // Sequence for measurement probes
+    pub sent_measurement_probes: VecDeque<SentProbe>, // Sent measurement probes, for RTT from feedback
     pub received_measurement_probes: VecDeque<ReceivedMeasurementProbe>, // Received measurement probes
     pub probe_stats: VecDeque<common::DirectionStats>, // Per-second calculated stats
     pub client_reported_s2c_stats: Option<common::DirectionStats>, // Stats reported by client
@@ -191,6 +224,7 @@ pub struct ReceivedMeasurementProbe {
     pub sent_at_ms: u64,
     pub received_at_ms: u64,
     pub feedback: common::ProbeFeedback,
+    pub rtt_ms: Option<f64>, // RTT from the feedback in this probe
 }
 
 impl AppState {
@@ -203,6 +237,7 @@ impl AppState {
             clients: Arc::new(InstrumentedRwLock::new("clients", HashMap::new())),
             packet_tracker: Arc::new(tracker),
             tracking_sender: tx,
+            received_ttls: Arc::new(ReceivedTtlTable::new()),
             server_start_time: Instant::now(),
             peer_cleanup_sender: cleanup_tx,
             capture_service: None,      // Will be set after initialization
@@ -210,6 +245,11 @@ impl AppState {
             session_manager: None,      // Will be set after initialization
             metrics_recorder: None,     // Will be set after initialization
             magic_key_config: None,     // Will be set after initialization
+            magic_key_store: None,      // Will be set after initialization
+            audit_log: None,            // Will be set after initialization
+            enrichment: None,           // Will be set after initialization
+            reverse_dns: None,          // Will be set after initialization
+            nat_behavior: None,         // Will be set after initialization
         };
         (state, cleanup_rx)
     }
@@ -238,6 +278,35 @@ impl AppState {
     pub fn set_magic_key_config(&mut self, config: netpoke_auth::config::MagicKeyConfig) {
         self.magic_key_config = Some(Arc::new(config));
     }
+
+    /// Set the magic key store for database-backed magic keys
+    pub fn set_magic_key_store(&mut self, magic_key_store: Arc<MagicKeyStore>) {
+        self.magic_key_store = Some(magic_key_store);
+    }
+
+    /// Set the security audit log
+    pub fn set_audit_log(&mut self, audit_log: Arc<DbAuditLog>) {
+        self.audit_log = Some(audit_log);
+    }
+
+    /// Set the ASN, prefix and geolocation enrichment service
+    pub fn set_enrichment(&mut self, enrichment: Arc<EnrichmentService>) {
+        self.enrichment = Some(enrichment);
+    }
+
+    /// Set the reverse DNS resolver and the wait budget of a traceroute round
+    pub fn set_reverse_dns(
+        &mut self,
+        resolver: Arc<ReverseDnsResolver>,
+        round_budget: std::time::Duration,
+    ) {
+        self.reverse_dns = Some((resolver, round_budget));
+    }
+
+    /// Set the STUN behavior discovery responder for NAT type classification
+    pub fn set_nat_behavior(&mut self, responder: Arc<NatBehaviorResponder>) {
+        self.nat_behavior = Some(responder);
+    }
 }
 
 impl DataChannels {
@@ -278,12 +347,16 @@ impl MeasurementState {
             sent_testprobes: VecDeque::new(),
             sent_testprobes_map: HashMap::new(),
             echoed_testprobes: VecDeque::new(),
+            echoed_ext_header_probes: HashMap::new(),
+            echoed_fragmentation_probes: HashSet::new(),
+            echoed_nat_lifetime_probes: HashSet::new(),
             last_received_seq: None,
             // Probe stream fields
             probe_streams_active: false,
             probe_streams_started_at: None,
             max_measuring_duration: None,
             measurement_probe_seq: 0,
+            sent_measurement_probes: VecDeque::new(),
             received_measurement_probes: VecDeque::new(),
             probe_stats: VecDeque::new(),
             client_reported_s2c_stats: None,
@@ -1,11 +1,37 @@
+use crate::magic_keys::MagicKeyStore;
+use crate::survey_tokens::SurveyTokenStore;
 use axum::{
-    extract::{FromRequestParts, Request, State},
+    extract::{ConnectInfo, FromRef, FromRequestParts, Request, State},
+    http::StatusCode,
     middleware::Next,
-    response::{IntoResponse, Redirect, Response},
+    response::{Html, IntoResponse, Redirect, Response},
 };
-use axum_extra::extract::cookie::PrivateCookieJar;
-use netpoke_auth::AuthState;
-use netpoke_auth::SessionData;
+use axum_extra::extract::cookie::{Key, PrivateCookieJar};
+use netpoke_auth::audit::{AuditEvent, AuditOutcome};
+use netpoke_auth::views::access_denied_page_html;
+use netpoke_auth::PermissionState;
+use netpoke_auth::config::MagicKeyConfig;
+use netpoke_auth::{ClientInfo, SessionData, SurveySessionData};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// State for `require_auth_or_survey_session`: the permission regular users need
+/// on the route group, and the stores survey session tokens are checked against
+#[derive(Clone)]
+pub struct SurveyAuthState {
+    pub permission: PermissionState,
+    /// Database-backed magic keys; the configured key list is used without one
+    pub magic_keys: Option<Arc<MagicKeyStore>>,
+    /// Issued survey session tokens, for revocation; unchecked without a database
+    pub survey_tokens: Option<Arc<SurveyTokenStore>>,
+}
+
+/// Implement FromRef to allow PrivateCookieJar to extract Key from SurveyAuthState
+impl FromRef<SurveyAuthState> for Key {
+    fn from_ref(state: &SurveyAuthState) -> Self {
+        state.permission.auth_state.cookie_key()
+    }
+}
 
 /// Middleware to require either regular authentication OR Magic Key survey session
 ///
@@ -19,72 +45,113 @@ use netpoke_auth::SessionData;
 /// - If regular auth is invalid/expired, Magic Key is checked as fallback
 /// - This ensures privileges from login+password session have precedence over magic key
 ///
+/// Regular users must also have a role granting the permission declared by the route
+/// group. A user without it is refused unless a valid survey session is present too.
+///
+/// Requests carry the `SessionData` of the regular user, or the `SurveySessionData` of
+/// the survey session, that authorized them as an extension.
+/// Scripts may use an API token (`Authorization: Bearer`) with the route's permission instead.
+///
 /// This is specifically for the network test page and signaling API which can be accessed
 /// by both authenticated users and surveyors with a Magic Key
 pub async fn require_auth_or_survey_session(
-    State(auth_state): State<AuthState>,
+    State(survey_state): State<SurveyAuthState>,
     request: Request,
     next: Next,
 ) -> Response {
+    let state = &survey_state.permission;
+    let auth_state = &state.auth_state;
+
     // Skip authentication if disabled
     if !auth_state.is_enabled() {
         return next.run(request).await;
     }
 
-    let headers = request.headers();
-    tracing::debug!("Request headers: {:?}", &headers);
-
     // Track authentication status for both methods
     let mut regular_auth_valid = false;
     let mut magic_key_valid = false;
+    let mut denied_user: Option<SessionData> = None;
 
-    // Extract PrivateCookieJar from request for regular auth
+    // Extract PrivateCookieJar from request (regular session and survey session token)
     let (mut parts, body) = request.into_parts();
 
-    if let Ok(jar) = PrivateCookieJar::from_request_parts(&mut parts, &auth_state).await {
-        // Check for regular authentication session (takes precedence)
-        if let Some(session_data) =
-            extract_session_from_jar(&jar, &auth_state.config.session.cookie_name)
+    let jar = PrivateCookieJar::from_request_parts(&mut parts, auth_state)
+        .await
+        .unwrap_or_else(|never| match never {});
+
+    // Check for regular authentication session (takes precedence)
+    if let Some(session_data) =
+        extract_session_from_jar(&jar, &auth_state.config.session.cookie_name)
+    {
+        let client = ClientInfo::from_parts(&parts);
+        if auth_state
+            .verify_session(&session_data, &client)
+            .await
+            .is_ok()
         {
-            if auth_state.validate_session(&session_data).is_ok() {
-                // Check if user is in allowed list
-                if auth_state.is_user_allowed(&session_data.handle) {
-                    tracing::debug!(
-                        "Regular authentication valid for user: {}",
-                        session_data.handle
-                    );
-                    regular_auth_valid = true;
-                } else {
-                    tracing::debug!(
-                        "User {} has valid session but is not in allowed list",
-                        session_data.handle
-                    );
-                }
+            // Check if user is in allowed list and has the route's permission
+            if !auth_state.is_user_allowed(&session_data.handle) {
+                tracing::debug!(
+                    "User {} has valid session but is not in allowed list",
+                    session_data.handle
+                );
+            } else if auth_state
+                .has_permission(&session_data, state.permission)
+                .await
+            {
+                tracing::debug!(
+                    "Regular authentication valid for user: {}",
+                    session_data.handle
+                );
+                regular_auth_valid = true;
+                parts.extensions.insert(session_data);
+            } else {
+                tracing::debug!(
+                    "User {} lacks permission {:?}",
+                    session_data.handle,
+                    state.permission
+                );
+                denied_user = Some(session_data);
             }
         }
     }
 
-    // Check for survey session (Magic Key) - uses regular cookies, not private
-    // We need to extract from all cookie headers (may have multiple)
+    // Scripts authenticate with an API token instead of a session cookie
+    if !regular_auth_valid {
+        if let Some(result) = auth_state
+            .authorize_api_token(&parts.headers, Some(state.permission))
+            .await
+        {
+            return match result {
+                Ok(session_data) => {
+                    tracing::debug!("API token authentication valid for user: {}", session_data.handle);
+                    let mut request = Request::from_parts(parts, body);
+                    request.extensions_mut().insert(session_data);
+                    next.run(request).await
+                }
+                Err(e) => StatusCode::from(e).into_response(),
+            };
+        }
+    }
+
+    // Check for survey session (Magic Key) token in its own private cookie
     if auth_state.config.magic_keys.enabled && !regular_auth_valid {
-        let cookie_str = collect_all_cookies(&parts.headers);
-
-        if let Some(survey_session_id) = extract_session_id(
-            &cookie_str,
-            &auth_state.config.magic_keys.survey_cookie_name,
-        ) {
-            // Validate the survey session format and expiration
-            if validate_survey_session(&survey_session_id, &auth_state.config.magic_keys) {
-                tracing::debug!("Magic Key authentication valid: {}", survey_session_id);
-                magic_key_valid = true;
-            } else {
-                tracing::debug!("Invalid or expired survey session: {}", survey_session_id);
-            }
-        } else {
-            tracing::debug!(
-                "Could not extract survey session id({}) from cookies",
-                &auth_state.config.magic_keys.survey_cookie_name
-            );
+        let client_ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        if let Some(token) = validate_survey_session(
+            &jar,
+            client_ip,
+            &auth_state.config.magic_keys,
+            survey_state.magic_keys.as_deref(),
+            survey_state.survey_tokens.as_deref(),
+        )
+        .await
+        {
+            tracing::debug!("Magic Key authentication valid: token {}", token.token_id);
+            parts.extensions.insert(token);
+            magic_key_valid = true;
         }
     }
 
@@ -100,6 +167,26 @@ pub async fn require_auth_or_survey_session(
         return next.run(request).await;
     }
 
+    // Authenticated user without the permission (and no survey session to fall back on)
+    if let Some(session_data) = denied_user {
+        tracing::warn!(
+            "Permission {:?} denied for user: {}",
+            state.permission,
+            session_data.handle
+        );
+        auth_state
+            .audit(
+                AuditEvent::new("access", AuditOutcome::Denied)
+                    .session(&session_data)
+                    .ip(ClientInfo::from_parts(&parts).ip)
+                    .target(format!("{} {}", parts.method, parts.uri.path()))
+                    .detail(format!("missing permission {}", state.permission.as_str())),
+            )
+            .await;
+        let html = access_denied_page_html(&session_data.handle);
+        return (StatusCode::FORBIDDEN, Html(html)).into_response();
+    }
+
     // No valid session, redirect to landing page
     tracing::debug!("Access denied - no valid authentication or survey session");
     Redirect::to("/").into_response()
@@ -111,79 +198,72 @@ fn extract_session_from_jar(jar: &PrivateCookieJar, cookie_name: &str) -> Option
         .and_then(|cookie| serde_json::from_str(cookie.value()).ok())
 }
 
-/// Collect all cookies from potentially multiple cookie headers
-fn collect_all_cookies(headers: &axum::http::HeaderMap) -> String {
-    headers
-        .get_all("cookie")
-        .iter()
-        .filter_map(|value| value.to_str().ok())
-        .fold(String::new(), |mut acc, s| {
-            if !acc.is_empty() {
-                acc.push_str("; ");
-            }
-            acc.push_str(s);
-            acc
-        })
-}
-
-fn extract_session_id(cookie_str: &str, cookie_name: &str) -> Option<String> {
-    cookie_str.split(';').find_map(|cookie| {
-        let parts: Vec<&str> = cookie.trim().split('=').collect();
-        if parts.len() == 2 && parts[0] == cookie_name {
-            Some(parts[1].to_string())
-        } else {
-            None
-        }
-    })
-}
+/// Look up and validate the survey session token in the private cookie jar
+///
+/// The token must not have expired, must be presented from the client IP it is
+/// bound to (if any) and must not have been revoked. Its Magic Key must still be
+/// valid in the magic key store (not revoked, disabled or expired), or in the
+/// configured key list when there is no store.
+pub(crate) async fn validate_survey_session(
+    jar: &PrivateCookieJar,
+    client_ip: Option<IpAddr>,
+    config: &MagicKeyConfig,
+    magic_keys: Option<&MagicKeyStore>,
+    survey_tokens: Option<&SurveyTokenStore>,
+) -> Option<SurveySessionData> {
+    let cookie = jar.get(&config.survey_cookie_name)?;
+    let Ok(token) = serde_json::from_str::<SurveySessionData>(cookie.value()) else {
+        tracing::debug!("Unreadable survey session token");
+        return None;
+    };
 
-/// Validate survey session format and check expiration
-/// Session format: "survey_{magic_key}_{timestamp}_{uuid}"
-fn validate_survey_session(
-    session_id: &str,
-    config: &netpoke_auth::config::MagicKeyConfig,
-) -> bool {
-    // Check if it starts with "survey_"
-    if !session_id.starts_with("survey_") {
-        return false;
+    if token.is_expired() {
+        tracing::debug!("Survey session token {} expired", token.token_id);
+        return None;
     }
 
-    // Parse the session format
-    let parts: Vec<&str> = session_id.split('_').collect();
-    if parts.len() < 4 {
-        return false;
+    if token.client_ip.is_some() && !client_ip.is_some_and(|ip| token.is_valid_from(ip)) {
+        tracing::debug!(
+            "Survey session token {} presented from {:?}, bound to {:?}",
+            token.token_id,
+            client_ip,
+            token.client_ip
+        );
+        return None;
     }
 
-    // Extract timestamp (second-to-last part before UUID)
-    let timestamp_str = parts[parts.len() - 2];
-    let timestamp = match timestamp_str.parse::<u64>() {
-        Ok(t) => t,
-        Err(_) => return false,
-    };
-
-    // Check if session has expired
-    let current_time = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-
-    let elapsed = current_time.saturating_sub(timestamp);
-    if elapsed > config.survey_timeout_seconds {
-        tracing::debug!("Survey session expired: {} seconds old", elapsed);
-        return false;
+    if let Some(store) = survey_tokens {
+        match store.is_active(&token.token_id).await {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::debug!("Survey session token {} revoked or unknown", token.token_id);
+                return None;
+            }
+            Err(e) => {
+                tracing::error!("Failed to check survey session token {}: {}", token.token_id, e);
+                return None;
+            }
+        }
     }
 
-    // Extract and validate the Magic Key is still in the allowed list
-    let magic_key_parts: Vec<String> = parts[1..parts.len() - 2]
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
-    let magic_key = magic_key_parts.join("-");
-
-    if !config.magic_keys.contains(&magic_key) {
-        tracing::debug!("Magic Key no longer valid: {}", magic_key);
-        return false;
+    match magic_keys {
+        Some(store) => {
+            if let Err(rejection) = store.validate(&token.magic_key).await {
+                tracing::debug!(
+                    "Magic Key no longer valid: {} ({})",
+                    token.magic_key,
+                    rejection
+                );
+                return None;
+            }
+        }
+        None => {
+            if !config.magic_keys.contains(&token.magic_key) {
+                tracing::debug!("Magic Key no longer valid: {}", token.magic_key);
+                return None;
+            }
+        }
     }
 
-    true
+    Some(token)
 }
@@ -0,0 +1,363 @@
+//! Survey session tokens
+//!
+//! Surveyors entering a magic key get a survey session token: a random token id
+//! with the magic key, issue and expiry times and an optional client IP binding,
+//! stored in an encrypted private cookie (see `SurveySessionData`). Every issued
+//! token is also recorded in the `survey_tokens` table, so tokens can be listed
+//! and revoked individually through the admin API.
+
+use crate::database::DbConnection;
+use netpoke_auth::SurveySessionData;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::Serialize;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+type StoreResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Minimum time between two last-seen updates of a token, to avoid a
+/// database write on every request
+const TOUCH_INTERVAL_MS: i64 = 60_000;
+
+/// Create a survey session token for a magic key
+///
+/// `bound_ip` binds the token to the client address it is issued to.
+pub fn new_survey_session(
+    magic_key: &str,
+    timeout_seconds: u64,
+    bound_ip: Option<IpAddr>,
+) -> SurveySessionData {
+    let issued_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    SurveySessionData {
+        token_id: Uuid::new_v4().to_string(),
+        magic_key: magic_key.to_string(),
+        issued_at,
+        expires_at: issued_at + timeout_seconds,
+        client_ip: bound_ip,
+    }
+}
+
+/// An issued survey session token as stored in the database
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SurveyToken {
+    pub token_id: String,
+    pub magic_key: String,
+    /// Issue time (Unix milliseconds)
+    pub issued_at: i64,
+    /// Expiry time (Unix milliseconds)
+    pub expires_at: i64,
+    /// Client address the token was issued to
+    pub issued_to_ip: Option<String>,
+    /// Client address the token is bound to, None = usable from any address
+    pub bound_ip: Option<String>,
+    /// Time the token was last used (Unix milliseconds, updated at most once a minute)
+    pub last_seen_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    pub revoked_by: Option<String>,
+}
+
+impl SurveyToken {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            token_id: row.get(0)?,
+            magic_key: row.get(1)?,
+            issued_at: row.get(2)?,
+            expires_at: row.get(3)?,
+            issued_to_ip: row.get(4)?,
+            bound_ip: row.get(5)?,
+            last_seen_at: row.get(6)?,
+            revoked_at: row.get(7)?,
+            revoked_by: row.get(8)?,
+        })
+    }
+}
+
+const SELECT_TOKEN: &str = "SELECT token_id, magic_key, issued_at, expires_at, issued_to_ip,
+        bound_ip, last_seen_at, revoked_at, revoked_by
+     FROM survey_tokens";
+
+/// Database-backed record of issued survey session tokens
+pub struct SurveyTokenStore {
+    db: DbConnection,
+}
+
+impl SurveyTokenStore {
+    /// Create a new SurveyTokenStore with the given database connection
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+
+    /// Record a newly issued token
+    pub async fn record(&self, token: &SurveySessionData, issued_to: IpAddr) -> StoreResult<()> {
+        let db = self.db.lock().await;
+        db.execute(
+            "INSERT INTO survey_tokens
+                (token_id, magic_key, issued_at, expires_at, issued_to_ip, bound_ip)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                token.token_id,
+                token.magic_key,
+                (token.issued_at * 1000) as i64,
+                (token.expires_at * 1000) as i64,
+                issued_to.to_string(),
+                token.client_ip.map(|ip| ip.to_string()),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Check that a token was issued by this server and has not been revoked,
+    /// recording its use
+    pub async fn is_active(&self, token_id: &str) -> StoreResult<bool> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let db = self.db.lock().await;
+        let token: Option<(Option<i64>, Option<i64>)> = db
+            .query_row(
+                "SELECT last_seen_at, revoked_at FROM survey_tokens WHERE token_id = ?",
+                params![token_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        let Some((last_seen_at, None)) = token else {
+            return Ok(false);
+        };
+        if last_seen_at.is_none_or(|last_seen_at| now_ms - last_seen_at >= TOUCH_INTERVAL_MS) {
+            db.execute(
+                "UPDATE survey_tokens SET last_seen_at = ? WHERE token_id = ?",
+                params![now_ms, token_id],
+            )?;
+        }
+        Ok(true)
+    }
+
+    /// Look up a single token
+    pub async fn get(&self, token_id: &str) -> StoreResult<Option<SurveyToken>> {
+        let db = self.db.lock().await;
+        let token = db
+            .query_row(
+                &format!("{} WHERE token_id = ?", SELECT_TOKEN),
+                params![token_id],
+                SurveyToken::from_row,
+            )
+            .optional()?;
+        Ok(token)
+    }
+
+    /// List tokens, newest first, optionally only those of one magic key
+    ///
+    /// Expired and revoked tokens are included only if `include_inactive` is set.
+    pub async fn list(
+        &self,
+        magic_key: Option<&str>,
+        include_inactive: bool,
+    ) -> StoreResult<Vec<SurveyToken>> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let db = self.db.lock().await;
+        let mut stmt = db.prepare(&format!(
+            "{} WHERE (?1 IS NULL OR magic_key = ?1)
+               AND (?2 OR (revoked_at IS NULL AND expires_at > ?3))
+             ORDER BY issued_at DESC",
+            SELECT_TOKEN
+        ))?;
+        let tokens = stmt
+            .query_map(
+                params![magic_key, include_inactive, now_ms],
+                SurveyToken::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tokens)
+    }
+
+    /// Revoke a token
+    ///
+    /// Returns false if the token does not exist or was already revoked.
+    pub async fn revoke(&self, token_id: &str, revoked_by: Option<&str>) -> StoreResult<bool> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE survey_tokens SET revoked_at = ?, revoked_by = ?
+             WHERE token_id = ? AND revoked_at IS NULL",
+            params![chrono::Utc::now().timestamp_millis(), revoked_by, token_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Fill in the magic key of survey sessions recorded with legacy
+    /// `survey_{magic_key}_{timestamp}_{uuid}` session ids and no known key
+    ///
+    /// Returns the number of sessions updated.
+    pub async fn migrate_legacy_session_ids(&self) -> StoreResult<usize> {
+        let db = self.db.lock().await;
+        let mut stmt = db.prepare(
+            "SELECT session_id FROM survey_sessions
+             WHERE magic_key = 'unknown' AND session_id LIKE 'survey\\_%' ESCAPE '\\'",
+        )?;
+        let session_ids = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut migrated = 0;
+        for session_id in session_ids {
+            if let Some(magic_key) = legacy_magic_key(&session_id) {
+                migrated += db.execute(
+                    "UPDATE survey_sessions SET magic_key = ? WHERE session_id = ?",
+                    params![magic_key, session_id],
+                )?;
+            }
+        }
+        Ok(migrated)
+    }
+}
+
+/// Extract magic key from legacy survey session ID format: "survey_{magic_key}_{timestamp}_{uuid}"
+///
+/// Magic key hyphens are encoded as underscores in the session ID, so we reconstruct
+/// them by joining the middle parts with hyphens.
+fn legacy_magic_key(session_id: &str) -> Option<String> {
+    if !session_id.starts_with("survey_") {
+        return None;
+    }
+    let parts: Vec<&str> = session_id.split('_').collect();
+    if parts.len() < 4 {
+        return None;
+    }
+    // Parts: ["survey", key_part1, ..., key_partN, timestamp, uuid]
+    // Verify the second-to-last part is a valid timestamp (numeric)
+    let timestamp_idx = parts.len() - 2;
+    if parts[timestamp_idx].parse::<u64>().is_err() {
+        return None;
+    }
+    let magic_key_parts = &parts[1..timestamp_idx];
+    if magic_key_parts.is_empty() {
+        return None;
+    }
+    Some(magic_key_parts.join("-"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_database;
+    use tempfile::NamedTempFile;
+
+    fn create_store() -> (NamedTempFile, SurveyTokenStore) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = init_database(temp_file.path()).unwrap();
+        (temp_file, SurveyTokenStore::new(db))
+    }
+
+    #[tokio::test]
+    async fn test_issue_and_revoke_token() {
+        let (_temp_file, store) = create_store();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        let token = new_survey_session("SURVEY-001", 3600, Some(ip));
+        assert_eq!(token.expires_at, token.issued_at + 3600);
+        assert!(!token.is_expired());
+        assert!(token.is_valid_from(ip));
+        assert!(!token.is_valid_from("192.0.2.2".parse().unwrap()));
+
+        // Tokens not issued by this server are rejected
+        assert!(!store.is_active(&token.token_id).await.unwrap());
+
+        store.record(&token, ip).await.unwrap();
+        let recorded = store.get(&token.token_id).await.unwrap().unwrap();
+        assert_eq!(recorded.last_seen_at, None);
+        assert!(store.is_active(&token.token_id).await.unwrap());
+
+        // Use is recorded, at most once a minute
+        let last_seen_at = store
+            .get(&token.token_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .last_seen_at
+            .unwrap();
+        assert!(last_seen_at >= recorded.issued_at);
+        assert!(store.is_active(&token.token_id).await.unwrap());
+
+        let listed = store.list(Some("SURVEY-001"), false).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].bound_ip.as_deref(), Some("192.0.2.1"));
+        assert_eq!(listed[0].last_seen_at, Some(last_seen_at));
+        assert!(store.list(Some("OTHER"), false).await.unwrap().is_empty());
+
+        assert!(store.revoke(&token.token_id, Some("admin")).await.unwrap());
+        assert!(!store.revoke(&token.token_id, Some("admin")).await.unwrap());
+        assert!(!store.is_active(&token.token_id).await.unwrap());
+
+        // Revoked tokens are only listed on request
+        assert!(store.list(None, false).await.unwrap().is_empty());
+        let revoked = store.get(&token.token_id).await.unwrap().unwrap();
+        assert_eq!(revoked.revoked_by.as_deref(), Some("admin"));
+        assert_eq!(store.list(None, true).await.unwrap(), vec![revoked]);
+    }
+
+    #[test]
+    fn test_unbound_token() {
+        let token = new_survey_session("SURVEY-001", 0, None);
+        assert!(token.is_expired());
+        assert!(token.is_valid_from("192.0.2.2".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy_session_ids() {
+        let (_temp_file, store) = create_store();
+        {
+            let conn = store.db.lock().await;
+            conn.execute_batch(
+                "INSERT INTO survey_sessions (session_id, magic_key, start_time, last_update_time, created_at) VALUES
+                    ('survey_SURVEY_001_1234567890_a1b2c3d4', 'unknown', 0, 0, 0),
+                    ('survey_notakey', 'unknown', 0, 0, 0),
+                    ('a1b2c3d4-e5f6-7890-abcd-ef1234567890', 'unknown', 0, 0, 0),
+                    ('survey_OTHER_1234567890_a1b2c3d4', 'KNOWN-KEY', 0, 0, 0);",
+            )
+            .unwrap();
+        }
+
+        assert_eq!(store.migrate_legacy_session_ids().await.unwrap(), 1);
+        assert_eq!(store.migrate_legacy_session_ids().await.unwrap(), 0);
+
+        let conn = store.db.lock().await;
+        let magic_key: String = conn
+            .query_row(
+                "SELECT magic_key FROM survey_sessions WHERE session_id = 'survey_SURVEY_001_1234567890_a1b2c3d4'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(magic_key, "SURVEY-001");
+    }
+
+    #[test]
+    fn test_legacy_magic_key_with_hyphens() {
+        // Magic key "SURVEY-001" is encoded as "SURVEY_001" in the session ID
+        let session_id = "survey_SURVEY_001_1234567890_a1b2c3d4-e5f6-7890-abcd-ef1234567890";
+        assert_eq!(legacy_magic_key(session_id), Some("SURVEY-001".to_string()));
+    }
+
+    #[test]
+    fn test_legacy_magic_key_multi_part() {
+        let session_id = "survey_MY_LONG_KEY_1234567890_a1b2c3d4-e5f6-7890-abcd-ef1234567890";
+        assert_eq!(
+            legacy_magic_key(session_id),
+            Some("MY-LONG-KEY".to_string())
+        );
+    }
+
+    #[test]
+    fn test_legacy_magic_key_invalid_format() {
+        assert_eq!(legacy_magic_key("not_a_survey_id"), None);
+        assert_eq!(legacy_magic_key("survey_"), None);
+        assert_eq!(legacy_magic_key(""), None);
+    }
+
+    #[test]
+    fn test_legacy_magic_key_no_timestamp() {
+        // If the second-to-last part is not numeric, it should fail
+        assert_eq!(legacy_magic_key("survey_KEY_notanumber_uuid"), None);
+    }
+}
@@ -0,0 +1,221 @@
+//! Database-backed TOTP enrollments
+//!
+//! Keeps the (encrypted) TOTP secrets of plain login users in the
+//! `totp_enrollments` table, along with the last used time step so a code
+//! cannot be replayed, and their hashed recovery codes in `totp_recovery_codes`.
+
+use crate::database::DbConnection;
+use netpoke_auth::totp::{TotpEnrollment, TotpStore, TotpStoreFuture};
+use netpoke_auth::AuthError;
+use rusqlite::{params, OptionalExtension};
+
+/// TOTP store over the `totp_enrollments` and `totp_recovery_codes` tables
+pub struct DbTotpStore {
+    db: DbConnection,
+}
+
+impl DbTotpStore {
+    /// Create a new DbTotpStore with the given database connection
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+
+    async fn load_enrollment(&self, username: &str) -> rusqlite::Result<Option<TotpEnrollment>> {
+        let db = self.db.lock().await;
+        db.query_row(
+            "SELECT secret, confirmed_at FROM totp_enrollments WHERE username = ?",
+            params![username],
+            |row| {
+                Ok(TotpEnrollment {
+                    secret: row.get(0)?,
+                    confirmed: row.get::<_, Option<i64>>(1)?.is_some(),
+                })
+            },
+        )
+        .optional()
+    }
+
+    async fn insert_enrollment(&self, username: &str, secret: &str) -> rusqlite::Result<()> {
+        let db = self.db.lock().await;
+        db.execute(
+            "INSERT INTO totp_enrollments (username, secret, created_at) VALUES (?, ?, ?)
+             ON CONFLICT(username) DO UPDATE SET
+                secret = excluded.secret, created_at = excluded.created_at, last_used_step = NULL
+             WHERE confirmed_at IS NULL",
+            params![username, secret, chrono::Utc::now().timestamp_millis()],
+        )?;
+        Ok(())
+    }
+
+    async fn confirm(
+        &self,
+        username: &str,
+        recovery_code_hashes: &[String],
+    ) -> rusqlite::Result<()> {
+        let mut db = self.db.lock().await;
+        let tx = db.transaction()?;
+        tx.execute(
+            "UPDATE totp_enrollments SET confirmed_at = ? WHERE username = ?",
+            params![chrono::Utc::now().timestamp_millis(), username],
+        )?;
+        tx.execute(
+            "DELETE FROM totp_recovery_codes WHERE username = ?",
+            params![username],
+        )?;
+        for code_hash in recovery_code_hashes {
+            tx.execute(
+                "INSERT INTO totp_recovery_codes (username, code_hash) VALUES (?, ?)",
+                params![username, code_hash],
+            )?;
+        }
+        tx.commit()
+    }
+
+    async fn record_step(&self, username: &str, step: u64) -> rusqlite::Result<bool> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE totp_enrollments SET last_used_step = ?1
+             WHERE username = ?2 AND (last_used_step IS NULL OR last_used_step < ?1)",
+            params![step as i64, username],
+        )?;
+        Ok(updated > 0)
+    }
+
+    async fn consume_recovery_code(
+        &self,
+        username: &str,
+        code_hash: &str,
+    ) -> rusqlite::Result<bool> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE totp_recovery_codes SET used_at = ?
+             WHERE username = ? AND code_hash = ? AND used_at IS NULL",
+            params![chrono::Utc::now().timestamp_millis(), username, code_hash],
+        )?;
+        Ok(updated > 0)
+    }
+
+    async fn delete(&self, username: &str) -> rusqlite::Result<()> {
+        let db = self.db.lock().await;
+        db.execute(
+            "DELETE FROM totp_recovery_codes WHERE username = ?",
+            params![username],
+        )?;
+        db.execute(
+            "DELETE FROM totp_enrollments WHERE username = ?",
+            params![username],
+        )?;
+        Ok(())
+    }
+}
+
+fn store_error(e: rusqlite::Error) -> AuthError {
+    AuthError::TotpError(e.to_string())
+}
+
+impl TotpStore for DbTotpStore {
+    fn enrollment<'a>(&'a self, username: &'a str) -> TotpStoreFuture<'a, Option<TotpEnrollment>> {
+        Box::pin(async move { self.load_enrollment(username).await.map_err(store_error) })
+    }
+
+    fn begin_enrollment<'a>(
+        &'a self,
+        username: &'a str,
+        secret: &'a str,
+    ) -> TotpStoreFuture<'a, ()> {
+        Box::pin(async move {
+            self.insert_enrollment(username, secret)
+                .await
+                .map_err(store_error)
+        })
+    }
+
+    fn confirm_enrollment<'a>(
+        &'a self,
+        username: &'a str,
+        recovery_code_hashes: &'a [String],
+    ) -> TotpStoreFuture<'a, ()> {
+        Box::pin(async move {
+            self.confirm(username, recovery_code_hashes)
+                .await
+                .map_err(store_error)
+        })
+    }
+
+    fn use_step<'a>(&'a self, username: &'a str, step: u64) -> TotpStoreFuture<'a, bool> {
+        Box::pin(async move { self.record_step(username, step).await.map_err(store_error) })
+    }
+
+    fn use_recovery_code<'a>(
+        &'a self,
+        username: &'a str,
+        code_hash: &'a str,
+    ) -> TotpStoreFuture<'a, bool> {
+        Box::pin(async move {
+            self.consume_recovery_code(username, code_hash)
+                .await
+                .map_err(store_error)
+        })
+    }
+
+    fn remove<'a>(&'a self, username: &'a str) -> TotpStoreFuture<'a, ()> {
+        Box::pin(async move { self.delete(username).await.map_err(store_error) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_database;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_enrollment_lifecycle() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbTotpStore::new(init_database(temp_file.path()).unwrap());
+        assert_eq!(store.enrollment("alice").await.unwrap(), None);
+
+        // An unconfirmed enrollment can be restarted with a new secret
+        store.begin_enrollment("alice", "secret-1").await.unwrap();
+        store.begin_enrollment("alice", "secret-2").await.unwrap();
+        let enrollment = store.enrollment("alice").await.unwrap().unwrap();
+        assert_eq!(enrollment.secret, "secret-2");
+        assert!(!enrollment.confirmed);
+
+        let hashes = vec!["hash-1".to_string(), "hash-2".to_string()];
+        store.confirm_enrollment("alice", &hashes).await.unwrap();
+        assert!(store.enrollment("alice").await.unwrap().unwrap().confirmed);
+
+        // ... but a confirmed one is kept
+        store.begin_enrollment("alice", "secret-3").await.unwrap();
+        let enrollment = store.enrollment("alice").await.unwrap().unwrap();
+        assert_eq!(enrollment.secret, "secret-2");
+        assert!(enrollment.confirmed);
+
+        store.remove("alice").await.unwrap();
+        assert_eq!(store.enrollment("alice").await.unwrap(), None);
+        assert!(!store.use_recovery_code("alice", "hash-1").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_replay_and_recovery_codes() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbTotpStore::new(init_database(temp_file.path()).unwrap());
+        store.begin_enrollment("alice", "secret").await.unwrap();
+        store
+            .confirm_enrollment("alice", &["hash-1".to_string()])
+            .await
+            .unwrap();
+
+        // Each time step can be used once, and never an earlier one
+        assert!(store.use_step("alice", 100).await.unwrap());
+        assert!(!store.use_step("alice", 100).await.unwrap());
+        assert!(!store.use_step("alice", 99).await.unwrap());
+        assert!(store.use_step("alice", 101).await.unwrap());
+        assert!(!store.use_step("bob", 101).await.unwrap());
+
+        assert!(store.use_recovery_code("alice", "hash-1").await.unwrap());
+        assert!(!store.use_recovery_code("alice", "hash-1").await.unwrap());
+        assert!(!store.use_recovery_code("alice", "unknown").await.unwrap());
+    }
+}
@@ -1,3 +1,5 @@
+use crate::packet_tracker::UdpPacketInfo;
+use common::SendOptions;
 use std::net::SocketAddr;
 /// Global tracking channel for UDP-to-ICMP packet tracking communication
 ///
@@ -6,14 +8,10 @@ use std::net::SocketAddr;
 /// to pass context through multiple layers or create circular dependencies.
 use std::sync::OnceLock;
 use std::time::Instant;
+use webrtc_util::TrackedSendOptions;
 
 /// Callback type for tracking UDP packets
-/// Parameters: (dest_addr, src_addr, udp_length, ttl, cleartext_data, sent_at, conn_id, udp_checksum)
-pub type TrackingCallback = Box<
-    dyn Fn(SocketAddr, Option<SocketAddr>, u16, Option<u8>, Vec<u8>, Instant, String, u16)
-        + Send
-        + Sync,
->;
+pub type TrackingCallback = Box<dyn Fn(UdpPacketInfo) + Send + Sync>;
 
 static TRACKING_CALLBACK: OnceLock<TrackingCallback> = OnceLock::new();
 
@@ -21,10 +19,7 @@ static TRACKING_CALLBACK: OnceLock<TrackingCallback> = OnceLock::new();
 /// Should be called once at application startup
 pub fn init_tracking_callback<F>(callback: F)
 where
-    F: Fn(SocketAddr, Option<SocketAddr>, u16, Option<u8>, Vec<u8>, Instant, String, u16)
-        + Send
-        + Sync
-        + 'static,
+    F: Fn(UdpPacketInfo) + Send + Sync + 'static,
 {
     if TRACKING_CALLBACK.set(Box::new(callback)).is_err() {
         panic!("Tracking callback already initialized");
@@ -33,27 +28,40 @@ where
 
 /// Track a UDP packet by invoking the global callback
 /// This is meant to be called from the UDP sending layer
-pub fn track_udp_packet(
-    dest_addr: SocketAddr,
-    src_addr: Option<SocketAddr>,
-    udp_length: u16,
-    ttl: Option<u8>,
-    cleartext: Vec<u8>,
-    sent_at: Instant,
-    conn_id: String,
-    udp_checksum: u16,
-) {
+pub fn track_udp_packet(info: UdpPacketInfo) {
     if let Some(callback) = TRACKING_CALLBACK.get() {
-        callback(
-            dest_addr,
-            src_addr,
-            udp_length,
-            ttl,
-            cleartext,
-            sent_at,
-            conn_id,
-            udp_checksum,
-        );
+        callback(info);
+    }
+}
+
+/// Send options of a packet as reported by the UDP layer
+pub fn send_options_from_tracked(options: &TrackedSendOptions) -> SendOptions {
+    SendOptions {
+        ttl: options.has_ttl.then_some(options.ttl),
+        df_bit: options.has_df_bit.then_some(options.df_bit),
+        tos: options.has_tos.then_some(options.tos),
+        flow_label: None, // Not settable on the UDP send path
+        track_for_ms: options.track_for_ms,
+        bypass_dtls: options.bypass_dtls,
+        bypass_sctp_fragmentation: options.bypass_sctp_fragmentation,
+        ipv6_hop_by_hop_len: (options.ipv6_hop_by_hop_len > 0)
+            .then_some(options.ipv6_hop_by_hop_len),
+        ipv6_dest_opts_len: (options.ipv6_dest_opts_len > 0).then_some(options.ipv6_dest_opts_len),
+        out_of_order_fragment_size: (options.out_of_order_fragment_size > 0)
+            .then_some(options.out_of_order_fragment_size),
+    }
+}
+
+/// Extract the conn_id string passed over FFI
+///
+/// # Safety
+/// `conn_id_ptr` must be null or point to `conn_id_len` readable bytes.
+unsafe fn conn_id_from_raw(conn_id_ptr: *const u8, conn_id_len: usize) -> String {
+    if conn_id_ptr.is_null() || conn_id_len == 0 {
+        String::new()
+    } else {
+        let bytes = std::slice::from_raw_parts(conn_id_ptr, conn_id_len);
+        String::from_utf8_lossy(bytes).to_string()
     }
 }
 
@@ -198,33 +206,25 @@ pub fn calculate_udp_checksum_v6(
 /// This can be called from the vendored webrtc-util code
 #[no_mangle]
 pub extern "C" fn netpoke_track_udp_packet(
-    src_ip_v4: u32,         // Source IPv4 address as u32
-    src_port: u16,          // Source port in host byte order
-    dest_ip_v4: u32,        // Destination IPv4 address as u32
-    dest_port: u16,         // Destination port in host byte order
-    udp_length: u16,        // UDP packet length
-    ttl: u8,                // TTL value
-    buf_ptr: *const u8,     // Pointer to buffer data
-    buf_len: usize,         // Buffer length
-    conn_id_ptr: *const u8, // Pointer to conn_id string
-    conn_id_len: usize,     // conn_id string length
+    src_ip_v4: u32,                     // Source IPv4 address as u32
+    src_port: u16,                      // Source port in host byte order
+    dest_ip_v4: u32,                    // Destination IPv4 address as u32
+    dest_port: u16,                     // Destination port in host byte order
+    udp_length: u16,                    // UDP packet length
+    options: *const TrackedSendOptions, // Send options and on-wire length
+    buf_ptr: *const u8,                 // Pointer to buffer data
+    buf_len: usize,                     // Buffer length
+    conn_id_ptr: *const u8,             // Pointer to conn_id string
+    conn_id_len: usize,                 // conn_id string length
 ) {
-    if buf_ptr.is_null() || buf_len == 0 {
+    if options.is_null() || buf_ptr.is_null() || buf_len == 0 {
         return;
     }
 
     // Safety: We trust the caller to provide valid pointers
+    let options = unsafe { *options };
     let cleartext = unsafe { std::slice::from_raw_parts(buf_ptr, buf_len).to_vec() };
-
-    // Extract conn_id from pointer
-    let conn_id = if conn_id_ptr.is_null() || conn_id_len == 0 {
-        String::new()
-    } else {
-        unsafe {
-            let bytes = std::slice::from_raw_parts(conn_id_ptr, conn_id_len);
-            String::from_utf8_lossy(bytes).to_string()
-        }
-    };
+    let conn_id = unsafe { conn_id_from_raw(conn_id_ptr, conn_id_len) };
 
     let dest_addr = SocketAddr::from((std::net::Ipv4Addr::from(dest_ip_v4), dest_port));
 
@@ -240,36 +240,42 @@ pub extern "C" fn netpoke_track_udp_packet(
         &cleartext,
     );
 
-    track_udp_packet(
+    track_udp_packet(UdpPacketInfo {
         dest_addr,
         src_addr,
         udp_length,
-        Some(ttl),
+        ip_length: options.ip_length,
         cleartext,
-        Instant::now(),
+        send_options: send_options_from_tracked(&options),
+        sent_at: Instant::now(),
         conn_id,
         udp_checksum,
-    );
+    });
 }
 
 /// C-compatible FFI function for tracking IPv6 UDP packets
 /// This can be called from the vendored webrtc-util code
 #[no_mangle]
 pub extern "C" fn netpoke_track_udp_packet_v6(
-    src_ip_v6_ptr: *const u8,  // Pointer to 16-byte source IPv6 address
-    src_port: u16,             // Source port in host byte order
-    dest_ip_v6_ptr: *const u8, // Pointer to 16-byte destination IPv6 address
-    dest_port: u16,            // Destination port in host byte order
-    udp_length: u16,           // UDP packet length
-    hop_limit: u8,             // IPv6 Hop Limit (equivalent to IPv4 TTL)
-    buf_ptr: *const u8,        // Pointer to buffer data
-    buf_len: usize,            // Buffer length
-    conn_id_ptr: *const u8,    // Pointer to conn_id string
-    conn_id_len: usize,        // conn_id string length
+    src_ip_v6_ptr: *const u8,           // Pointer to 16-byte source IPv6 address
+    src_port: u16,                      // Source port in host byte order
+    dest_ip_v6_ptr: *const u8,          // Pointer to 16-byte destination IPv6 address
+    dest_port: u16,                     // Destination port in host byte order
+    udp_length: u16,                    // UDP packet length
+    options: *const TrackedSendOptions, // Send options and on-wire length
+    buf_ptr: *const u8,                 // Pointer to buffer data
+    buf_len: usize,                     // Buffer length
+    conn_id_ptr: *const u8,             // Pointer to conn_id string
+    conn_id_len: usize,                 // conn_id string length
 ) {
     const IPV6_ADDR_LEN: usize = 16;
 
-    if src_ip_v6_ptr.is_null() || dest_ip_v6_ptr.is_null() || buf_ptr.is_null() || buf_len == 0 {
+    if src_ip_v6_ptr.is_null()
+        || dest_ip_v6_ptr.is_null()
+        || options.is_null()
+        || buf_ptr.is_null()
+        || buf_len == 0
+    {
         return;
     }
 
@@ -288,17 +294,9 @@ pub extern "C" fn netpoke_track_udp_packet_v6(
         arr
     };
 
+    let options = unsafe { *options };
     let cleartext = unsafe { std::slice::from_raw_parts(buf_ptr, buf_len).to_vec() };
-
-    // Extract conn_id from pointer
-    let conn_id = if conn_id_ptr.is_null() || conn_id_len == 0 {
-        String::new()
-    } else {
-        unsafe {
-            let bytes = std::slice::from_raw_parts(conn_id_ptr, conn_id_len);
-            String::from_utf8_lossy(bytes).to_string()
-        }
-    };
+    let conn_id = unsafe { conn_id_from_raw(conn_id_ptr, conn_id_len) };
 
     let dest_addr = SocketAddr::from((std::net::Ipv6Addr::from(dest_ip_bytes), dest_port));
 
@@ -314,16 +312,17 @@ pub extern "C" fn netpoke_track_udp_packet_v6(
         &cleartext,
     );
 
-    track_udp_packet(
+    track_udp_packet(UdpPacketInfo {
         dest_addr,
         src_addr,
         udp_length,
-        Some(hop_limit),
+        ip_length: options.ip_length,
         cleartext,
-        Instant::now(),
+        send_options: send_options_from_tracked(&options),
+        sent_at: Instant::now(),
         conn_id,
         udp_checksum,
-    );
+    });
 }
 
 #[cfg(test)]
@@ -0,0 +1,155 @@
+//! API for listing and revoking login sessions
+//!
+//! Users can list their own sessions, revoke one of them or all of them
+//! ("logout everywhere"). Admins can do the same for any user.
+
+use crate::user_sessions::{DbSessionStore, UserSession};
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    Json,
+};
+use netpoke_auth::SessionData;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// A session of the logged-in user
+#[derive(Debug, Serialize)]
+pub struct OwnSession {
+    #[serde(flatten)]
+    pub session: UserSession,
+    /// Whether this is the session making the request
+    pub current: bool,
+}
+
+/// Response of the revoke-all endpoints
+#[derive(Debug, Serialize)]
+pub struct RevokeAllResponse {
+    pub revoked: usize,
+}
+
+// ============================================================================
+// Own Sessions Endpoints
+// ============================================================================
+
+/// List the sessions of the logged-in user
+pub async fn list_own_sessions(
+    State(store): State<Arc<DbSessionStore>>,
+    Extension(session_data): Extension<SessionData>,
+) -> Result<Json<Vec<OwnSession>>, StatusCode> {
+    let sessions = list_sessions(&store, &session_data.handle).await?;
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| OwnSession {
+                current: session_data.session_id.as_deref() == Some(session.session_id.as_str()),
+                session,
+            })
+            .collect(),
+    ))
+}
+
+/// Revoke one of the sessions of the logged-in user
+pub async fn revoke_own_session(
+    State(store): State<Arc<DbSessionStore>>,
+    Extension(session_data): Extension<SessionData>,
+    Path(session_id): Path<String>,
+) -> StatusCode {
+    let handle = &session_data.handle;
+    revoke_session(&store, handle, &session_id, handle).await
+}
+
+/// Revoke all sessions of the logged-in user, including the current one
+pub async fn revoke_own_sessions(
+    State(store): State<Arc<DbSessionStore>>,
+    Extension(session_data): Extension<SessionData>,
+) -> Result<Json<RevokeAllResponse>, StatusCode> {
+    let handle = &session_data.handle;
+    revoke_all_sessions(&store, handle, handle).await
+}
+
+// ============================================================================
+// Admin Endpoints
+// ============================================================================
+
+/// List the sessions of a user
+pub async fn list_user_sessions(
+    State(store): State<Arc<DbSessionStore>>,
+    Path(handle): Path<String>,
+) -> Result<Json<Vec<UserSession>>, StatusCode> {
+    list_sessions(&store, &handle).await.map(Json)
+}
+
+/// Revoke a session of a user
+pub async fn revoke_user_session(
+    State(store): State<Arc<DbSessionStore>>,
+    Extension(admin): Extension<SessionData>,
+    Path((handle, session_id)): Path<(String, String)>,
+) -> StatusCode {
+    revoke_session(&store, &handle, &session_id, &admin.handle).await
+}
+
+/// Revoke all sessions of a user
+pub async fn revoke_user_sessions(
+    State(store): State<Arc<DbSessionStore>>,
+    Extension(admin): Extension<SessionData>,
+    Path(handle): Path<String>,
+) -> Result<Json<RevokeAllResponse>, StatusCode> {
+    revoke_all_sessions(&store, &handle, &admin.handle).await
+}
+
+async fn list_sessions(
+    store: &DbSessionStore,
+    handle: &str,
+) -> Result<Vec<UserSession>, StatusCode> {
+    store.list_for_user(handle).await.map_err(|e| {
+        tracing::error!("Failed to list sessions of {}: {}", handle, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn revoke_session(
+    store: &DbSessionStore,
+    handle: &str,
+    session_id: &str,
+    revoked_by: &str,
+) -> StatusCode {
+    match store.revoke(handle, session_id, Some(revoked_by)).await {
+        Ok(true) => {
+            tracing::info!(
+                "Session {} of {} revoked by {}",
+                session_id,
+                handle,
+                revoked_by
+            );
+            StatusCode::NO_CONTENT
+        }
+        // Unknown, not the user's, or already revoked
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            tracing::error!("Failed to revoke session {}: {}", session_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+async fn revoke_all_sessions(
+    store: &DbSessionStore,
+    handle: &str,
+    revoked_by: &str,
+) -> Result<Json<RevokeAllResponse>, StatusCode> {
+    let revoked = store
+        .revoke_all(handle, Some(revoked_by))
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to revoke sessions of {}: {}", handle, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    tracing::info!(
+        "{} sessions of {} revoked by {}",
+        revoked,
+        handle,
+        revoked_by
+    );
+    Ok(Json(RevokeAllResponse { revoked }))
+}
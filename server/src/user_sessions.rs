@@ -0,0 +1,412 @@
+//! Database-backed session store
+//!
+//! Every login of a regular user is recorded in the `user_sessions` table with
+//! the client IP and user agent, and checked on every request, so sessions can
+//! be listed, revoked one by one or all at once ("logout everywhere"), and
+//! ended after the idle timeout.
+
+use crate::database::DbConnection;
+use netpoke_auth::session_store::{ClientInfo, SessionStore, SessionStoreFuture};
+use netpoke_auth::{AuthError, SessionData};
+use rusqlite::{params, OptionalExtension, Row};
+use serde::Serialize;
+
+type StoreResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Minimum time between two last-seen updates of a session, to avoid a
+/// database write on every request
+const TOUCH_INTERVAL_MS: i64 = 60_000;
+
+/// A login session as stored in the database
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UserSession {
+    pub session_id: String,
+    pub handle: String,
+    pub user_id: String,
+    /// Provider the user logged in with (`AuthProvider::name`)
+    pub auth_provider: String,
+    /// Login time (Unix milliseconds)
+    pub created_at: i64,
+    /// Absolute expiry time (Unix milliseconds)
+    pub expires_at: i64,
+    /// Last request (Unix milliseconds, updated at most once a minute)
+    pub last_seen_at: i64,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub revoked_at: Option<i64>,
+    pub revoked_by: Option<String>,
+}
+
+impl UserSession {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            session_id: row.get(0)?,
+            handle: row.get(1)?,
+            user_id: row.get(2)?,
+            auth_provider: row.get(3)?,
+            created_at: row.get(4)?,
+            expires_at: row.get(5)?,
+            last_seen_at: row.get(6)?,
+            ip: row.get(7)?,
+            user_agent: row.get(8)?,
+            revoked_at: row.get(9)?,
+            revoked_by: row.get(10)?,
+        })
+    }
+}
+
+/// Session store over the `user_sessions` table
+pub struct DbSessionStore {
+    db: DbConnection,
+    /// Absolute session timeout, to record when sessions expire
+    timeout_seconds: u64,
+}
+
+impl DbSessionStore {
+    /// Create a new DbSessionStore with the given database connection and session timeout
+    pub fn new(db: DbConnection, timeout_seconds: u64) -> Self {
+        Self {
+            db,
+            timeout_seconds,
+        }
+    }
+
+    async fn create_session(
+        &self,
+        session: &SessionData,
+        session_id: &str,
+        client: &ClientInfo,
+    ) -> Result<(), rusqlite::Error> {
+        let created_at = (session.created_at * 1000) as i64;
+        let expires_at = created_at + (self.timeout_seconds * 1000) as i64;
+        let db = self.db.lock().await;
+        db.execute(
+            "INSERT INTO user_sessions
+                (session_id, handle, user_id, auth_provider, created_at, expires_at,
+                 last_seen_at, ip, user_agent)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                session_id,
+                session.handle,
+                session.user_id,
+                session.auth_provider.name(),
+                created_at,
+                expires_at,
+                chrono::Utc::now().timestamp_millis(),
+                client.ip.map(|ip| ip.to_string()),
+                client.user_agent,
+            ],
+        )?;
+        Ok(())
+    }
+
+    async fn touch_session(
+        &self,
+        session_id: &str,
+        idle_timeout_seconds: Option<u64>,
+        client: &ClientInfo,
+    ) -> Result<bool, rusqlite::Error> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let db = self.db.lock().await;
+        let session: Option<(i64, i64, Option<i64>)> = db
+            .query_row(
+                "SELECT expires_at, last_seen_at, revoked_at FROM user_sessions WHERE session_id = ?",
+                params![session_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+
+        let Some((expires_at, last_seen_at, revoked_at)) = session else {
+            return Ok(false);
+        };
+        if revoked_at.is_some() || expires_at <= now_ms {
+            return Ok(false);
+        }
+        if let Some(idle_timeout) = idle_timeout_seconds {
+            if now_ms - last_seen_at > (idle_timeout * 1000) as i64 {
+                return Ok(false);
+            }
+        }
+
+        if now_ms - last_seen_at >= TOUCH_INTERVAL_MS {
+            db.execute(
+                "UPDATE user_sessions SET last_seen_at = ?, ip = COALESCE(?, ip),
+                    user_agent = COALESCE(?, user_agent)
+                 WHERE session_id = ?",
+                params![
+                    now_ms,
+                    client.ip.map(|ip| ip.to_string()),
+                    client.user_agent,
+                    session_id
+                ],
+            )?;
+        }
+        Ok(true)
+    }
+
+    /// List the unexpired, unrevoked sessions of a user, most recently used first
+    pub async fn list_for_user(&self, handle: &str) -> StoreResult<Vec<UserSession>> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let db = self.db.lock().await;
+        let mut stmt = db.prepare(
+            "SELECT session_id, handle, user_id, auth_provider, created_at, expires_at,
+                    last_seen_at, ip, user_agent, revoked_at, revoked_by
+             FROM user_sessions
+             WHERE handle = ? AND revoked_at IS NULL AND expires_at > ?
+             ORDER BY last_seen_at DESC",
+        )?;
+        let sessions = stmt
+            .query_map(params![handle, now_ms], UserSession::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(sessions)
+    }
+
+    /// Revoke a session of a user
+    ///
+    /// Returns false if the user has no such session or it was already revoked.
+    pub async fn revoke(
+        &self,
+        handle: &str,
+        session_id: &str,
+        revoked_by: Option<&str>,
+    ) -> StoreResult<bool> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE user_sessions SET revoked_at = ?, revoked_by = ?
+             WHERE session_id = ? AND handle = ? AND revoked_at IS NULL",
+            params![
+                chrono::Utc::now().timestamp_millis(),
+                revoked_by,
+                session_id,
+                handle
+            ],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Revoke all sessions of a user
+    ///
+    /// Returns the number of sessions revoked.
+    pub async fn revoke_all(&self, handle: &str, revoked_by: Option<&str>) -> StoreResult<usize> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE user_sessions SET revoked_at = ?, revoked_by = ?
+             WHERE handle = ? AND revoked_at IS NULL",
+            params![chrono::Utc::now().timestamp_millis(), revoked_by, handle],
+        )?;
+        Ok(updated)
+    }
+
+    /// Revoke all sessions of a user of one provider (`AuthProvider::name`),
+    /// leaving same-named users of other providers logged in
+    ///
+    /// Returns the number of sessions revoked.
+    pub async fn revoke_all_for(
+        &self,
+        provider: &str,
+        handle: &str,
+        revoked_by: Option<&str>,
+    ) -> StoreResult<usize> {
+        let db = self.db.lock().await;
+        let updated = db.execute(
+            "UPDATE user_sessions SET revoked_at = ?, revoked_by = ?
+             WHERE auth_provider = ? AND handle = ? AND revoked_at IS NULL",
+            params![
+                chrono::Utc::now().timestamp_millis(),
+                revoked_by,
+                provider,
+                handle
+            ],
+        )?;
+        Ok(updated)
+    }
+}
+
+impl SessionStore for DbSessionStore {
+    fn create<'a>(
+        &'a self,
+        session: &'a SessionData,
+        client: &'a ClientInfo,
+    ) -> SessionStoreFuture<'a, Result<(), AuthError>> {
+        Box::pin(async move {
+            let session_id = session
+                .session_id
+                .as_deref()
+                .ok_or(AuthError::InvalidSession)?;
+            self.create_session(session, session_id, client)
+                .await
+                .map_err(|e| AuthError::SessionStoreError(e.to_string()))
+        })
+    }
+
+    fn touch<'a>(
+        &'a self,
+        session_id: &'a str,
+        idle_timeout_seconds: Option<u64>,
+        client: &'a ClientInfo,
+    ) -> SessionStoreFuture<'a, bool> {
+        Box::pin(async move {
+            self.touch_session(session_id, idle_timeout_seconds, client)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!("Failed to check session {}: {}", session_id, e);
+                    false
+                })
+        })
+    }
+
+    fn revoke<'a>(&'a self, session_id: &'a str) -> SessionStoreFuture<'a, bool> {
+        Box::pin(async move {
+            let db = self.db.lock().await;
+            db.execute(
+                "UPDATE user_sessions SET revoked_at = ?, revoked_by = handle
+                 WHERE session_id = ? AND revoked_at IS NULL",
+                params![chrono::Utc::now().timestamp_millis(), session_id],
+            )
+            .map(|updated| updated > 0)
+            .unwrap_or_else(|e| {
+                tracing::error!("Failed to revoke session {}: {}", session_id, e);
+                false
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_database;
+    use netpoke_auth::AuthProvider;
+    use tempfile::NamedTempFile;
+
+    fn create_session(handle: &str, session_id: &str) -> SessionData {
+        SessionData {
+            auth_provider: AuthProvider::PlainLogin,
+            user_id: handle.to_string(),
+            handle: handle.to_string(),
+            display_name: None,
+            groups: vec![],
+            created_at: chrono::Utc::now().timestamp() as u64,
+            session_id: Some(session_id.to_string()),
+        }
+    }
+
+    fn client() -> ClientInfo {
+        ClientInfo {
+            ip: Some("192.0.2.1".parse().unwrap()),
+            user_agent: Some("test-agent".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_touch_and_revoke() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbSessionStore::new(init_database(temp_file.path()).unwrap(), 3600);
+
+        // Unknown sessions are rejected
+        assert!(!store.touch("s1", None, &client()).await);
+
+        store
+            .create(&create_session("alice", "s1"), &client())
+            .await
+            .unwrap();
+        store
+            .create(&create_session("alice", "s2"), &client())
+            .await
+            .unwrap();
+        store
+            .create(&create_session("bob", "s3"), &client())
+            .await
+            .unwrap();
+        assert!(store.touch("s1", None, &client()).await);
+
+        let sessions = store.list_for_user("alice").await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].ip.as_deref(), Some("192.0.2.1"));
+        assert_eq!(sessions[0].user_agent.as_deref(), Some("test-agent"));
+
+        // Users can only revoke their own sessions
+        assert!(!store.revoke("bob", "s1", Some("bob")).await.unwrap());
+        assert!(store.revoke("alice", "s1", Some("alice")).await.unwrap());
+        assert!(!store.touch("s1", None, &client()).await);
+
+        // Logout (through the store trait)
+        assert!(SessionStore::revoke(&store, "s2").await);
+        assert!(!SessionStore::revoke(&store, "s2").await);
+        assert!(store.list_for_user("alice").await.unwrap().is_empty());
+
+        assert_eq!(store.revoke_all("bob", Some("admin")).await.unwrap(), 1);
+        assert!(!store.touch("s3", None, &client()).await);
+    }
+
+    #[tokio::test]
+    async fn test_revoke_all_for_provider() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbSessionStore::new(init_database(temp_file.path()).unwrap(), 3600);
+        store
+            .create(&create_session("alice", "s1"), &client())
+            .await
+            .unwrap();
+        let github_alice = SessionData {
+            auth_provider: AuthProvider::GitHub,
+            ..create_session("alice", "s2")
+        };
+        store.create(&github_alice, &client()).await.unwrap();
+        let mut providers: Vec<String> = store
+            .list_for_user("alice")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|session| session.auth_provider)
+            .collect();
+        providers.sort();
+        assert_eq!(providers, vec!["github", "plain"]);
+
+        assert_eq!(
+            store
+                .revoke_all_for("plain", "alice", Some("admin"))
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(!store.touch("s1", None, &client()).await);
+        assert!(store.touch("s2", None, &client()).await);
+    }
+
+    #[tokio::test]
+    async fn test_idle_and_absolute_timeout() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = DbSessionStore::new(init_database(temp_file.path()).unwrap(), 3600);
+        store
+            .create(&create_session("alice", "s1"), &client())
+            .await
+            .unwrap();
+        store
+            .create(&create_session("alice", "s2"), &client())
+            .await
+            .unwrap();
+
+        {
+            let conn = store.db.lock().await;
+            let now_ms = chrono::Utc::now().timestamp_millis();
+            conn.execute(
+                "UPDATE user_sessions SET last_seen_at = ? WHERE session_id = 's1'",
+                params![now_ms - 600_000],
+            )
+            .unwrap();
+            conn.execute(
+                "UPDATE user_sessions SET expires_at = ? WHERE session_id = 's2'",
+                params![now_ms - 1],
+            )
+            .unwrap();
+        }
+
+        // Idle for 10 minutes: too long for a 5 minute idle timeout, not for 15 minutes
+        assert!(!store.touch("s1", Some(300), &client()).await);
+        assert!(store.touch("s1", Some(900), &client()).await);
+        // ... and the activity was recorded
+        assert!(store.touch("s1", Some(300), &client()).await);
+
+        assert!(!store.touch("s2", None, &client()).await);
+    }
+}
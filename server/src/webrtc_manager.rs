@@ -19,8 +19,11 @@ use webrtc::peer_connection::RTCPeerConnection;
 ///   - `IpFamily::IPv4` - Only gather IPv4 (UDP4) candidates
 ///   - `IpFamily::IPv6` - Only gather IPv6 (UDP6) candidates
 ///   - `IpFamily::Both` or `None` - Gather both IPv4 and IPv6 candidates (default)
+/// * `ice_keepalives` - Whether to send ICE keepalives, which NAT binding timeout
+///   test connections must not
 pub async fn create_peer_connection(
     ip_family: Option<IpFamily>,
+    ice_keepalives: bool,
 ) -> Result<Arc<RTCPeerConnection>, Box<dyn std::error::Error>> {
     let mut media_engine = MediaEngine::default();
     let registry = Registry::new();
@@ -42,11 +45,17 @@ pub async fn create_peer_connection(
     // Configure ICE timeouts for more robust connections
     // - disconnected_timeout: 10s (default 5s) - more tolerance for temporary disconnections
     // - failed_timeout: 30s (default 25s) - give more time to recover from disconnected state
-    // - keepalive_interval: 2s (default 2s) - keep connections alive with regular traffic
+    // - keepalive_interval: 2s (default 2s) - keep connections alive with regular traffic,
+    //   zero disables keepalives
+    let keepalive_interval = if ice_keepalives {
+        Duration::from_secs(2)
+    } else {
+        Duration::ZERO
+    };
     setting_engine.set_ice_timeouts(
         Some(Duration::from_secs(10)), // disconnected_timeout
         Some(Duration::from_secs(30)), // failed_timeout
-        Some(Duration::from_secs(2)),  // keepalive_interval
+        Some(keepalive_interval),      // keepalive_interval
     );
 
     // Apply IP family filtering if specified
@@ -186,25 +195,25 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_peer_connection() {
-        let result = create_peer_connection(None).await;
+        let result = create_peer_connection(None, true).await;
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     async fn test_create_peer_connection_ipv4_only() {
-        let result = create_peer_connection(Some(IpFamily::IPv4)).await;
+        let result = create_peer_connection(Some(IpFamily::IPv4), true).await;
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     async fn test_create_peer_connection_ipv6_only() {
-        let result = create_peer_connection(Some(IpFamily::IPv6)).await;
+        let result = create_peer_connection(Some(IpFamily::IPv6), true).await;
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     async fn test_create_peer_connection_both() {
-        let result = create_peer_connection(Some(IpFamily::Both)).await;
+        let result = create_peer_connection(Some(IpFamily::Both), true).await;
         assert!(result.is_ok());
     }
 }
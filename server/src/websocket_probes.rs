@@ -0,0 +1,309 @@
+//! Probe stream over WebSocket
+//!
+//! The counterpart of the probe channel stream over TCP: the same probes at
+//! the same rate, with the same feedback and per-second stats, recorded in
+//! `websocket_probe_metrics`. Comparing the two streams of a survey session
+//! tells UDP throttling or blocking apart from the path itself.
+//!
+//! When ICE fails on every connection no control channel ever opens, so the
+//! stream also starts the survey session itself, and is then all the survey
+//! session measures.
+
+use crate::measurements::{
+    current_time_ms, next_measurement_probe, probe_stream_stats, record_measurement_probe,
+};
+use crate::state::{AppState, MeasurementState};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Extension, State,
+    },
+    response::Response,
+};
+use common::{Direction, ProbeStatsReport, StartSurveySessionMessage, WebSocketProbeMessage};
+use futures::{stream::StreamExt, SinkExt};
+use netpoke_auth::SurveySessionData;
+use std::time::Instant;
+use tokio::time::{interval, Duration};
+
+/// The stream ends when the client has sent nothing for this long
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn websocket_probe_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    survey_session: Option<Extension<SurveySessionData>>,
+) -> Response {
+    let authenticated_key = survey_session.map(|Extension(token)| token.magic_key);
+    ws.on_upgrade(move |socket| websocket_probe_stream(socket, state, authenticated_key))
+}
+
+async fn websocket_probe_stream(
+    socket: WebSocket,
+    state: AppState,
+    authenticated_key: Option<String>,
+) {
+    let (mut sender, mut receiver) = socket.split();
+
+    // The client names the survey session and the stream first
+    let start = loop {
+        match tokio::time::timeout(IDLE_TIMEOUT, receiver.next()).await {
+            Ok(Some(Ok(Message::Text(text)))) => {
+                match serde_json::from_str::<WebSocketProbeMessage>(&text) {
+                    Ok(WebSocketProbeMessage::Start(start)) => break start,
+                    _ => {
+                        tracing::warn!("WebSocket probe stream did not start with a start message");
+                        return;
+                    }
+                }
+            }
+            Ok(Some(Ok(_))) => continue,
+            _ => {
+                tracing::debug!("WebSocket probe stream closed before it started");
+                return;
+            }
+        }
+    };
+
+    let magic_key = match join_survey_session(&state, &start, authenticated_key).await {
+        Ok(magic_key) => magic_key,
+        Err(reason) => {
+            tracing::warn!(
+                "Refusing WebSocket probe stream {} of survey session {}: {}",
+                start.conn_id,
+                start.survey_session_id,
+                reason
+            );
+            return;
+        }
+    };
+
+    let max_measuring_duration = match &magic_key {
+        Some(magic_key) => max_measuring_time_seconds(&state, magic_key)
+            .await
+            .map(Duration::from_secs),
+        None => None,
+    };
+    tracing::info!(
+        "Started WebSocket probe stream {} of survey session {} (max measuring duration {:?})",
+        start.conn_id,
+        start.survey_session_id,
+        max_measuring_duration
+    );
+
+    let mut measurement = MeasurementState::new();
+    measurement.probe_streams_active = true;
+    measurement.probe_streams_started_at = Some(Instant::now());
+    measurement.max_measuring_duration = max_measuring_duration;
+
+    let mut probe_interval = interval(Duration::from_millis(common::PROBE_INTERVAL_MS as u64));
+    let mut stats_interval = interval(Duration::from_millis(1000)); // 1 Hz
+    let mut last_message_at = Instant::now();
+
+    loop {
+        tokio::select! {
+            _ = probe_interval.tick() => {
+                let probe = next_measurement_probe(
+                    &mut measurement,
+                    Direction::ServerToClient,
+                    &start.conn_id,
+                    current_time_ms(),
+                );
+                if send(&mut sender, &WebSocketProbeMessage::Probe(probe)).await.is_err() {
+                    break;
+                }
+            }
+            _ = stats_interval.tick() => {
+                if last_message_at.elapsed() >= IDLE_TIMEOUT {
+                    tracing::info!(
+                        "Stopping WebSocket probe stream {} (client idle)",
+                        start.conn_id
+                    );
+                    break;
+                }
+                if let (Some(started_at), Some(max_duration)) = (
+                    measurement.probe_streams_started_at,
+                    measurement.max_measuring_duration,
+                ) {
+                    if started_at.elapsed() >= max_duration {
+                        tracing::info!(
+                            "Stopping WebSocket probe stream {} (max measuring time exceeded)",
+                            start.conn_id
+                        );
+                        break;
+                    }
+                }
+
+                let timestamp_ms = current_time_ms();
+                let report = Box::new(ProbeStatsReport {
+                    conn_id: start.conn_id.clone(),
+                    survey_session_id: start.survey_session_id.clone(),
+                    timestamp_ms,
+                    c2s_stats: probe_stream_stats(&measurement, timestamp_ms),
+                    s2c_stats: measurement
+                        .client_reported_s2c_stats
+                        .clone()
+                        .unwrap_or_default(),
+                });
+                record_stats(&state, &report).await;
+                if send(&mut sender, &WebSocketProbeMessage::Stats(report)).await.is_err() {
+                    break;
+                }
+            }
+            message = receiver.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                last_message_at = Instant::now();
+                match serde_json::from_str::<WebSocketProbeMessage>(&text) {
+                    Ok(WebSocketProbeMessage::Probe(probe)) => {
+                        record_measurement_probe(&mut measurement, &probe, current_time_ms());
+                    }
+                    Ok(WebSocketProbeMessage::Stats(report)) => {
+                        measurement.client_reported_s2c_stats = Some(report.s2c_stats);
+                    }
+                    Ok(WebSocketProbeMessage::Start(_)) => {
+                        tracing::debug!(
+                            "WebSocket probe stream {} already started",
+                            start.conn_id
+                        );
+                    }
+                    Err(e) => {
+                        tracing::debug!("Invalid WebSocket probe message: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    tracing::info!(
+        "WebSocket probe stream {} of survey session {} ended",
+        start.conn_id,
+        start.survey_session_id
+    );
+}
+
+async fn send(
+    sender: &mut futures::stream::SplitSink<WebSocket, Message>,
+    message: &WebSocketProbeMessage,
+) -> Result<(), axum::Error> {
+    match serde_json::to_string(message) {
+        Ok(json) => sender.send(Message::Text(json.into())).await,
+        Err(e) => {
+            tracing::error!("Failed to serialize WebSocket probe message: {}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Check the survey session of a stream, and start it when no WebRTC
+/// connection did; returns the magic key the survey session runs under
+async fn join_survey_session(
+    state: &AppState,
+    start: &StartSurveySessionMessage,
+    authenticated_key: Option<String>,
+) -> Result<Option<String>, String> {
+    if start.survey_session_id.is_empty() {
+        return Err("no survey session ID".to_string());
+    }
+
+    // A surveyor's magic key comes from the survey session token and cannot
+    // be changed by the client; other users name the key themselves
+    let magic_key = match (authenticated_key.clone(), &start.magic_key) {
+        (Some(authenticated), _) => Some(authenticated),
+        (None, claimed) => claimed.clone(),
+    };
+
+    let Some(session_manager) = &state.session_manager else {
+        return Ok(magic_key);
+    };
+
+    let exists = session_manager
+        .session_exists(&start.survey_session_id)
+        .await
+        .map_err(|e| format!("failed to look up survey session: {}", e))?;
+    if exists {
+        let recorded_key = session_manager
+            .get_session_magic_key(&start.survey_session_id)
+            .await
+            .map_err(|e| format!("failed to look up survey session: {}", e))?;
+        if let (Some(authenticated), Some(recorded)) = (&authenticated_key, &recorded_key) {
+            if authenticated != recorded {
+                return Err(format!(
+                    "survey session belongs to magic key {}, not {}",
+                    recorded, authenticated
+                ));
+            }
+        }
+        return Ok(recorded_key.or(magic_key));
+    }
+
+    // No WebRTC connection started the survey session: count it against the
+    // magic key's usage limit and record it here
+    if let (Some(store), Some(magic_key)) = (&state.magic_key_store, &magic_key) {
+        let key = store
+            .record_session_start(magic_key)
+            .await
+            .map_err(|rejection| rejection.to_string())?;
+        tracing::info!(
+            "Magic key {} used for survey session {} over WebSocket only ({} sessions)",
+            magic_key,
+            start.survey_session_id,
+            key.session_count
+        );
+    }
+    session_manager
+        .create_session(
+            &start.survey_session_id,
+            magic_key.as_deref().unwrap_or("unknown"),
+            None,
+        )
+        .await
+        .map_err(|e| format!("failed to create survey session record: {}", e))?;
+    tracing::info!(
+        "Created survey session record {} for a WebSocket only survey",
+        start.survey_session_id
+    );
+
+    Ok(magic_key)
+}
+
+async fn max_measuring_time_seconds(state: &AppState, magic_key: &str) -> Option<u64> {
+    if let Some(store) = &state.magic_key_store {
+        Some(store.max_measuring_time_seconds(magic_key).await)
+    } else {
+        state
+            .magic_key_config
+            .as_ref()
+            .map(|config| config.get_max_measuring_time_seconds(magic_key))
+    }
+}
+
+async fn record_stats(state: &AppState, report: &ProbeStatsReport) {
+    let Some(metrics_recorder) = &state.metrics_recorder else {
+        return;
+    };
+    if let Err(e) = metrics_recorder
+        .record_websocket_probe_stats(
+            &report.survey_session_id,
+            &report.conn_id,
+            report.timestamp_ms,
+            &report.c2s_stats,
+            &report.s2c_stats,
+        )
+        .await
+    {
+        tracing::error!("Failed to record WebSocket probe stats: {}", e);
+    }
+
+    if let Some(session_manager) = &state.session_manager {
+        if let Err(e) = session_manager
+            .update_session_timestamp(&report.survey_session_id)
+            .await
+        {
+            tracing::error!("Failed to update session timestamp: {}", e);
+        }
+    }
+}
@@ -32,6 +32,23 @@ impl Conn for UdpSocket {
         Ok(self.recv(buf).await?)
     }
 
+    #[cfg(target_os = "linux")]
+    async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        // Receive with recvmsg() to learn the TTL / Hop Limit of the packet
+        // and pass it on to netpoke (added for netpoke)
+        let fd = self.as_raw_fd();
+        let (n, src, ttl) = self
+            .async_io(tokio::io::Interest::READABLE, || {
+                recvmsg_with_ttl(fd, &mut *buf)
+            })
+            .await?;
+        if let Some(ttl) = ttl {
+            report_received_ttl(src, ttl);
+        }
+        Ok((n, src))
+    }
+
+    #[cfg(not(target_os = "linux"))]
     async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
         Ok(self.recv_from(buf).await?)
     }
@@ -109,6 +126,9 @@ impl Conn for UdpSocket {
 pub struct UdpSendOptions {
     pub ttl: Option<u8>,
     pub tos: Option<u8>,
+    /// `Some(false)` lets the kernel fragment datagrams larger than the path MTU
+    /// (IPv4 fragments with DF clear, IPv6 Fragment headers); otherwise the
+    /// socket's path MTU discovery setting applies
     pub df_bit: Option<bool>,
     /// Connection ID for ICMP correlation (passed through to packet tracker)
     /// Defaults to empty string for backward compatibility
@@ -138,6 +158,212 @@ pub struct UdpSendOptions {
     /// WARNING: Sending very large packets may exceed the path MTU and get dropped
     /// or fragmented by intermediate routers. Only use this for controlled testing.
     pub bypass_sctp_fragmentation: bool,
+
+    /// Track the packet for ICMP correlation for this many milliseconds
+    ///
+    /// `None` tracks packets with a TTL/Hop Limit set (traceroute probes) for
+    /// `DEFAULT_TRACK_FOR_MS`. `Some(ms)` tracks the packet for `ms` whatever its
+    /// TTL, so that e.g. PTB and port unreachable errors on MTU and measurement
+    /// probes are correlated too; `Some(0)` disables tracking.
+    pub track_for_ms: Option<u32>,
+
+    /// Add an IPv6 Hop-by-Hop Options header of this many bytes (IPv6 only)
+    ///
+    /// The size is rounded up as by `ipv6_options_header_len`. Linux only
+    /// lets processes with CAP_NET_RAW send extension headers.
+    pub ipv6_hop_by_hop_len: Option<u16>,
+
+    /// Add an IPv6 Destination Options header of this many bytes (IPv6 only)
+    ///
+    /// The size is rounded up as by `ipv6_options_header_len`. Linux only
+    /// lets processes with CAP_NET_RAW send extension headers.
+    pub ipv6_dest_opts_len: Option<u16>,
+
+    /// Send the datagram as IP fragments of this many payload bytes, last one first
+    ///
+    /// The size is rounded down as by `ip_fragment_size`. The fragments are built
+    /// here and sent on a raw socket, which Linux only lets processes with
+    /// CAP_NET_RAW open; extension header options do not apply to them.
+    pub out_of_order_fragment_size: Option<u16>,
+}
+
+/// How long packets with a TTL/Hop Limit are tracked when `track_for_ms` is unset
+pub const DEFAULT_TRACK_FOR_MS: u32 = 5000;
+
+/// Send options of a tracked packet, as passed to the netpoke packet tracker
+///
+/// C-compatible mirror of `UdpSendOptions` for `netpoke_track_udp_packet` and
+/// `netpoke_track_udp_packet_v6`; unset options have their `has_*` flag clear.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrackedSendOptions {
+    pub has_ttl: bool,
+    pub ttl: u8,
+    pub has_tos: bool,
+    pub tos: u8,
+    pub has_df_bit: bool,
+    pub df_bit: bool,
+    pub bypass_dtls: bool,
+    pub bypass_sctp_fragmentation: bool,
+    /// Milliseconds to track the packet for
+    pub track_for_ms: u32,
+    /// Length of the packet on the wire (IP header + UDP header + payload)
+    pub ip_length: u16,
+    /// Length of the IPv6 Hop-by-Hop Options header sent, 0 for none
+    pub ipv6_hop_by_hop_len: u16,
+    /// Length of the IPv6 Destination Options header sent, 0 for none
+    pub ipv6_dest_opts_len: u16,
+    /// Payload bytes per fragment of a datagram sent out of order, 0 if it was not
+    pub out_of_order_fragment_size: u16,
+}
+
+/// Largest IPv6 Hop-by-Hop or Destination Options header (8-bit length in 8-byte units)
+pub const IPV6_OPTIONS_HEADER_MAX_LEN: u16 = 2048;
+
+/// Experimental IPv6 option type (RFC 4727) filling the options headers
+///
+/// Its two high-order bits tell nodes that do not recognize it to skip it, and
+/// unlike PadN it may be longer than 5 bytes without receivers dropping the packet.
+pub const IPV6_EXPERIMENTAL_OPTION: u8 = 0x1e;
+
+/// Length of the IPv6 options header sent for a requested size: a multiple of 8
+/// between 8 and `IPV6_OPTIONS_HEADER_MAX_LEN` bytes
+pub fn ipv6_options_header_len(len: u16) -> u16 {
+    len.clamp(8, IPV6_OPTIONS_HEADER_MAX_LEN).div_ceil(8) * 8
+}
+
+/// IPv6 Hop-by-Hop or Destination Options header of `ipv6_options_header_len(len)` bytes
+///
+/// The options are experimental ones of up to 255 data bytes, so that a 2048-byte
+/// header holds the 8 options Linux accepts by default. The Next Header field is
+/// left zero for the kernel to fill in.
+pub fn ipv6_options_header(len: u16) -> Vec<u8> {
+    let len = ipv6_options_header_len(len) as usize;
+    let mut header = Vec::with_capacity(len);
+    header.push(0);
+    header.push((len / 8 - 1) as u8);
+
+    let mut remaining = len - 2;
+    while remaining > 0 {
+        // Options take at least 2 bytes, so never leave a single byte over
+        let mut option_len = remaining.min(2 + u8::MAX as usize);
+        if remaining - option_len == 1 {
+            option_len -= 1;
+        }
+        header.push(IPV6_EXPERIMENTAL_OPTION);
+        header.push((option_len - 2) as u8);
+        header.resize(header.len() + option_len - 2, 0);
+        remaining -= option_len;
+    }
+
+    header
+}
+
+/// Payload bytes per IP fragment for a requested size: a multiple of 8, at least 8
+pub fn ip_fragment_size(size: u16) -> u16 {
+    size.max(8) / 8 * 8
+}
+
+/// Offsets and lengths of the fragments of a `len`-byte IP payload, last one first
+pub fn out_of_order_fragments(len: usize, fragment_size: u16) -> Vec<(usize, usize)> {
+    let fragment_size = ip_fragment_size(fragment_size) as usize;
+    (0..len)
+        .step_by(fragment_size)
+        .map(|offset| (offset, fragment_size.min(len - offset)))
+        .rev()
+        .collect()
+}
+
+/// UDP header and payload, with the checksum computed over the pseudo header
+/// of `src` and `dest` (which must be of the same family)
+#[cfg(target_os = "linux")]
+fn udp_datagram(src: SocketAddr, dest: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let udp_length = 8 + payload.len();
+    let mut datagram = Vec::with_capacity(udp_length);
+    datagram.extend_from_slice(&src.port().to_be_bytes());
+    datagram.extend_from_slice(&dest.port().to_be_bytes());
+    datagram.extend_from_slice(&(udp_length as u16).to_be_bytes());
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(payload);
+
+    let mut pseudo_header = Vec::with_capacity(40);
+    match (src, dest) {
+        (SocketAddr::V4(src), SocketAddr::V4(dest)) => {
+            pseudo_header.extend_from_slice(&src.ip().octets());
+            pseudo_header.extend_from_slice(&dest.ip().octets());
+            pseudo_header.extend_from_slice(&[0, libc::IPPROTO_UDP as u8]);
+            pseudo_header.extend_from_slice(&(udp_length as u16).to_be_bytes());
+        }
+        _ => {
+            let ipv6 = |addr: SocketAddr| match addr.ip() {
+                std::net::IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                std::net::IpAddr::V6(ip) => ip,
+            };
+            pseudo_header.extend_from_slice(&ipv6(src).octets());
+            pseudo_header.extend_from_slice(&ipv6(dest).octets());
+            pseudo_header.extend_from_slice(&(udp_length as u32).to_be_bytes());
+            pseudo_header.extend_from_slice(&[0, 0, 0, libc::IPPROTO_UDP as u8]);
+        }
+    }
+
+    let mut sum: u32 = pseudo_header
+        .chunks(2)
+        .chain(datagram.chunks(2))
+        .map(|word| u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    // A zero checksum means none, so it is sent as all ones
+    let checksum = match !(sum as u16) {
+        0 => 0xffff,
+        checksum => checksum,
+    };
+    datagram[6..8].copy_from_slice(&checksum.to_be_bytes());
+
+    datagram
+}
+
+impl UdpSendOptions {
+    /// Milliseconds the packet is to be tracked for ICMP correlation, 0 for not at all
+    pub fn effective_track_for_ms(&self) -> u32 {
+        match self.track_for_ms {
+            Some(track_for_ms) => track_for_ms,
+            None if self.ttl.is_some() => DEFAULT_TRACK_FOR_MS,
+            None => 0,
+        }
+    }
+
+    /// Options to pass to the packet tracker for a packet of `ip_length` bytes
+    pub fn tracked(&self, ip_length: u16) -> TrackedSendOptions {
+        TrackedSendOptions {
+            has_ttl: self.ttl.is_some(),
+            ttl: self.ttl.unwrap_or(0),
+            has_tos: self.tos.is_some(),
+            tos: self.tos.unwrap_or(0),
+            has_df_bit: self.df_bit.is_some(),
+            df_bit: self.df_bit.unwrap_or(false),
+            bypass_dtls: self.bypass_dtls,
+            bypass_sctp_fragmentation: self.bypass_sctp_fragmentation,
+            track_for_ms: self.effective_track_for_ms(),
+            ip_length,
+            ipv6_hop_by_hop_len: self.ipv6_hop_by_hop_len.map_or(0, ipv6_options_header_len),
+            ipv6_dest_opts_len: self.ipv6_dest_opts_len.map_or(0, ipv6_options_header_len),
+            out_of_order_fragment_size: self.out_of_order_fragment_size.map_or(0, ip_fragment_size),
+        }
+    }
+
+    /// IPv6 extension headers to send, as control message types and header bytes
+    #[cfg(target_os = "linux")]
+    fn ipv6_ext_headers(&self) -> Vec<(libc::c_int, Vec<u8>)> {
+        [
+            (libc::IPV6_HOPOPTS, self.ipv6_hop_by_hop_len),
+            (libc::IPV6_DSTOPTS, self.ipv6_dest_opts_len),
+        ]
+        .into_iter()
+        .filter_map(|(cmsg_type, len)| Some((cmsg_type, ipv6_options_header(len?))))
+        .collect()
+    }
 }
 
 #[cfg(target_os = "linux")]
@@ -233,9 +459,17 @@ fn get_local_addr(fd: std::os::unix::io::RawFd) -> Result<SocketAddr> {
             return Err(std::io::Error::last_os_error().into());
         }
 
+        Ok(sockaddr_to_socket_addr(&addr)?)
+    }
+}
+
+#[cfg(target_os = "linux")]
+/// Convert a socket address filled in by the kernel to a `SocketAddr`
+fn sockaddr_to_socket_addr(addr: &libc::sockaddr_storage) -> std::io::Result<SocketAddr> {
+    unsafe {
         // Parse the address based on family
         if addr.ss_family == libc::AF_INET as libc::sa_family_t {
-            let addr_in = &addr as *const libc::sockaddr_storage as *const libc::sockaddr_in;
+            let addr_in = addr as *const libc::sockaddr_storage as *const libc::sockaddr_in;
             // s_addr is in network byte order, convert to octets for Ipv4Addr
             let s_addr_bytes = (*addr_in).sin_addr.s_addr.to_ne_bytes();
             let ip = std::net::Ipv4Addr::new(
@@ -247,19 +481,336 @@ fn get_local_addr(fd: std::os::unix::io::RawFd) -> Result<SocketAddr> {
             let port = u16::from_be((*addr_in).sin_port);
             Ok(SocketAddr::V4(std::net::SocketAddrV4::new(ip, port)))
         } else if addr.ss_family == libc::AF_INET6 as libc::sa_family_t {
-            let addr_in6 = &addr as *const libc::sockaddr_storage as *const libc::sockaddr_in6;
+            let addr_in6 = addr as *const libc::sockaddr_storage as *const libc::sockaddr_in6;
             let ip = std::net::Ipv6Addr::from((*addr_in6).sin6_addr.s6_addr);
             let port = u16::from_be((*addr_in6).sin6_port);
             Ok(SocketAddr::V6(std::net::SocketAddrV6::new(ip, port, 0, 0)))
         } else {
-            Err(
-                std::io::Error::new(std::io::ErrorKind::InvalidData, "Unknown address family")
-                    .into(),
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Unknown address family",
+            ))
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+/// Ask the kernel to report the TTL / Hop Limit of received packets
+///
+/// Enables IP_RECVTTL on IPv4 sockets, and IPV6_RECVHOPLIMIT plus IP_RECVTTL
+/// (for IPv4-mapped traffic) on IPv6 sockets, so that `recv_from` can pass
+/// the TTL of each received packet to netpoke for reverse path analysis.
+pub fn enable_recv_ttl(socket: &UdpSocket) -> Result<()> {
+    let fd = socket.as_raw_fd();
+    let enable = |level: libc::c_int, name: libc::c_int| {
+        let on: libc::c_int = 1;
+        let result = unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                &on as *const libc::c_int as *const c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
             )
+        };
+        if result < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    };
+
+    if get_socket_family(fd)? == libc::AF_INET6 as libc::sa_family_t {
+        enable(IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT)?;
+        // Only applies to dual-stack sockets; IPv6-only ones refuse it
+        let _ = enable(IPPROTO_IP, libc::IP_RECVTTL);
+    } else {
+        enable(IPPROTO_IP, libc::IP_RECVTTL)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+/// Receive a datagram with recvmsg(), along with its TTL / Hop Limit when the
+/// socket reports it (see `enable_recv_ttl`)
+fn recvmsg_with_ttl(
+    fd: std::os::unix::io::RawFd,
+    buf: &mut [u8],
+) -> std::io::Result<(usize, SocketAddr, Option<u8>)> {
+    unsafe {
+        let mut addr: libc::sockaddr_storage = std::mem::zeroed();
+        let mut iov = iovec {
+            iov_base: buf.as_mut_ptr() as *mut c_void,
+            iov_len: buf.len(),
+        };
+        // u64 elements keep the control message buffer aligned for cmsghdr
+        let mut cmsg_buf = [0u64; 16];
+
+        let mut msg: msghdr = std::mem::zeroed();
+        msg.msg_name = &mut addr as *mut libc::sockaddr_storage as *mut c_void;
+        msg.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsg_buf.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = std::mem::size_of_val(&cmsg_buf) as msg_controllen_type;
+
+        let result = libc::recvmsg(fd, &mut msg, 0);
+        if result < 0 {
+            return Err(std::io::Error::last_os_error());
         }
+
+        let mut ttl = None;
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            let level = (*cmsg).cmsg_level;
+            let cmsg_type = (*cmsg).cmsg_type;
+            if (level == IPPROTO_IP && cmsg_type == IP_TTL)
+                || (level == IPPROTO_IPV6 && cmsg_type == IPV6_HOPLIMIT)
+            {
+                // Both are delivered as int
+                let value = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                ttl = u8::try_from(value).ok();
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+
+        Ok((result as usize, sockaddr_to_socket_addr(&addr)?, ttl))
+    }
+}
+
+#[cfg(target_os = "linux")]
+/// Pass the TTL / Hop Limit of a received packet to netpoke-server
+fn report_received_ttl(src: SocketAddr, ttl: u8) {
+    extern "C" {
+        fn netpoke_record_received_ttl(src_ip_v4: u32, src_port: u16, ttl: u8);
+
+        fn netpoke_record_received_ttl_v6(src_ip_v6_ptr: *const u8, src_port: u16, hop_limit: u8);
+    }
+
+    match src {
+        SocketAddr::V4(addr_v4) => unsafe {
+            netpoke_record_received_ttl(
+                u32::from_be_bytes(addr_v4.ip().octets()),
+                addr_v4.port(),
+                ttl,
+            );
+        },
+        SocketAddr::V6(addr_v6) => match addr_v6.ip().to_ipv4_mapped() {
+            // IPv4 traffic on a dual-stack socket
+            Some(v4) => unsafe {
+                netpoke_record_received_ttl(u32::from_be_bytes(v4.octets()), addr_v6.port(), ttl);
+            },
+            None => unsafe {
+                netpoke_record_received_ttl_v6(addr_v6.ip().octets().as_ptr(), addr_v6.port(), ttl);
+            },
+        },
     }
 }
 
+#[cfg(target_os = "linux")]
+/// Set an integer socket option, returning its previous value
+fn replace_socket_option(
+    fd: std::os::unix::io::RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> std::io::Result<libc::c_int> {
+    unsafe {
+        let mut previous: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        if libc::getsockopt(
+            fd,
+            level,
+            name,
+            &mut previous as *mut libc::c_int as *mut c_void,
+            &mut len,
+        ) < 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        if libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const libc::c_int as *const c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        ) < 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(previous)
+    }
+}
+
+#[cfg(target_os = "linux")]
+/// Send a UDP datagram from the address `fd` is bound to as IP fragments,
+/// last fragment first (see `UdpSendOptions::out_of_order_fragment_size`)
+///
+/// IPv4 fragments are sent whole on an IPPROTO_RAW socket. IPv6 ones are sent
+/// on a raw socket of the Fragment header protocol, so that the kernel builds
+/// the IPv6 header; it is bound to the source address so that the peer sees
+/// the datagram come from the usual address.
+fn send_fragments_out_of_order(
+    fd: std::os::unix::io::RawFd,
+    buf: &[u8],
+    dest: SocketAddr,
+    options: &UdpSendOptions,
+    fragment_size: u16,
+) -> Result<()> {
+    use std::os::fd::FromRawFd;
+
+    // IPv4-mapped addresses go out as IPv4
+    let unmapped = |addr: SocketAddr| match addr {
+        SocketAddr::V6(addr_v6) => match addr_v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(ip.into(), addr_v6.port()),
+            None => addr,
+        },
+        addr => addr,
+    };
+    let dest = unmapped(dest);
+    let mut src = unmapped(get_local_addr(fd)?);
+    // The checksum needs the source address routing picks for unbound sockets
+    if src.ip().is_unspecified() {
+        let unspecified: std::net::IpAddr = match dest {
+            SocketAddr::V4(_) => std::net::Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => std::net::Ipv6Addr::UNSPECIFIED.into(),
+        };
+        let route_probe = std::net::UdpSocket::bind(SocketAddr::new(unspecified, 0))?;
+        route_probe.connect(dest)?;
+        src.set_ip(route_probe.local_addr()?.ip());
+    }
+    if src.is_ipv4() != dest.is_ipv4() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Cannot fragment a datagram between address families",
+        )
+        .into());
+    }
+
+    let datagram = udp_datagram(src, dest, buf);
+    let identification = rand::random::<u32>();
+    let last_error = || crate::Error::from(std::io::Error::last_os_error());
+
+    unsafe {
+        let (family, protocol) = match dest {
+            SocketAddr::V4(_) => (libc::AF_INET, libc::IPPROTO_RAW),
+            SocketAddr::V6(_) => (libc::AF_INET6, libc::IPPROTO_FRAGMENT),
+        };
+        let raw_fd = libc::socket(family, libc::SOCK_RAW | libc::SOCK_CLOEXEC, protocol);
+        if raw_fd < 0 {
+            return Err(last_error());
+        }
+        let raw_socket = std::os::fd::OwnedFd::from_raw_fd(raw_fd);
+
+        let mut addr_v4: libc::sockaddr_in = std::mem::zeroed();
+        let mut addr_v6: libc::sockaddr_in6 = std::mem::zeroed();
+        let (addr_ptr, addr_len) = match (src, dest) {
+            (_, SocketAddr::V4(dest)) => {
+                addr_v4.sin_family = libc::AF_INET as libc::sa_family_t;
+                addr_v4.sin_addr = libc::in_addr {
+                    s_addr: u32::from_ne_bytes(dest.ip().octets()),
+                };
+                (
+                    &addr_v4 as *const libc::sockaddr_in as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                )
+            }
+            (SocketAddr::V6(src), SocketAddr::V6(dest)) => {
+                let mut bind_addr: libc::sockaddr_in6 = std::mem::zeroed();
+                bind_addr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                bind_addr.sin6_addr = libc::in6_addr {
+                    s6_addr: src.ip().octets(),
+                };
+                bind_addr.sin6_scope_id = src.scope_id();
+                if libc::bind(
+                    raw_socket.as_raw_fd(),
+                    &bind_addr as *const libc::sockaddr_in6 as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                ) < 0
+                {
+                    return Err(last_error());
+                }
+                for (name, value) in [
+                    (libc::IPV6_UNICAST_HOPS, options.ttl),
+                    (libc::IPV6_TCLASS, options.tos),
+                ] {
+                    if let Some(value) = value {
+                        replace_socket_option(
+                            raw_socket.as_raw_fd(),
+                            IPPROTO_IPV6,
+                            name,
+                            value as libc::c_int,
+                        )?;
+                    }
+                }
+
+                addr_v6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                addr_v6.sin6_addr = libc::in6_addr {
+                    s6_addr: dest.ip().octets(),
+                };
+                addr_v6.sin6_scope_id = dest.scope_id();
+                (
+                    &addr_v6 as *const libc::sockaddr_in6 as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                )
+            }
+            _ => unreachable!("source and destination are of the same family"),
+        };
+
+        for (offset, len) in out_of_order_fragments(datagram.len(), fragment_size) {
+            let more_fragments = offset + len < datagram.len();
+            let mut packet = Vec::with_capacity(20 + len);
+            match (src, dest) {
+                (SocketAddr::V4(src), SocketAddr::V4(dest)) => {
+                    // The kernel fills in the header checksum
+                    let flags_offset =
+                        (offset / 8) as u16 | if more_fragments { 0x2000 } else { 0 };
+                    packet.extend_from_slice(&[0x45, options.tos.unwrap_or(0)]);
+                    packet.extend_from_slice(&((20 + len) as u16).to_be_bytes());
+                    packet.extend_from_slice(&(identification as u16).to_be_bytes());
+                    packet.extend_from_slice(&flags_offset.to_be_bytes());
+                    packet.extend_from_slice(&[
+                        options.ttl.unwrap_or(64),
+                        libc::IPPROTO_UDP as u8,
+                        0,
+                        0,
+                    ]);
+                    packet.extend_from_slice(&src.ip().octets());
+                    packet.extend_from_slice(&dest.ip().octets());
+                }
+                _ => {
+                    // The offset in 8-byte units takes the top 13 bits
+                    let offset_flags = (offset as u16) | more_fragments as u16;
+                    packet.extend_from_slice(&[libc::IPPROTO_UDP as u8, 0]);
+                    packet.extend_from_slice(&offset_flags.to_be_bytes());
+                    packet.extend_from_slice(&identification.to_be_bytes());
+                }
+            }
+            packet.extend_from_slice(&datagram[offset..offset + len]);
+
+            log::debug!(
+                "sendto: Sending fragment at offset {} of {} bytes to {}",
+                offset,
+                len,
+                dest
+            );
+            if libc::sendto(
+                raw_socket.as_raw_fd(),
+                packet.as_ptr() as *const c_void,
+                packet.len(),
+                0,
+                addr_ptr,
+                addr_len,
+            ) < 0
+            {
+                return Err(last_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(target_os = "linux")]
 fn sendmsg_with_options(
     fd: std::os::unix::io::RawFd,
@@ -296,8 +847,25 @@ fn sendmsg_with_options(
             iov_len: buf.len(),
         };
 
+        // Extension headers are only sent on IPv6 sockets to IPv6 destinations
+        let ext_headers = options.ipv6_ext_headers();
+        let ipv6_dest =
+            matches!(dest, SocketAddr::V6(addr) if addr.ip().to_ipv4_mapped().is_none());
+        if !ext_headers.is_empty() && (!is_ipv6_socket || !ipv6_dest) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "IPv6 extension headers need an IPv6 socket and destination",
+            )
+            .into());
+        }
+        let ext_headers_length: usize = ext_headers.iter().map(|(_, header)| header.len()).sum();
+
         // Prepare control message buffer
-        let mut cmsg_buf = vec![0u8; 256];
+        let ext_headers_space: usize = ext_headers
+            .iter()
+            .map(|(_, header)| libc::CMSG_SPACE(header.len() as u32) as usize)
+            .sum();
+        let mut cmsg_buf = vec![0u8; 256 + ext_headers_space];
         let mut cmsg_len = 0usize as msg_controllen_type;
 
         // Build the msghdr structure
@@ -386,6 +954,27 @@ fn sendmsg_with_options(
                     cmsg_len += (*cmsg).cmsg_len;
                 }
             }
+
+            // Extension headers go after the hop limit and traffic class
+            // messages, each of which takes CMSG_SPACE(int) bytes
+            let mut offset = libc::CMSG_SPACE(std::mem::size_of::<i32>() as u32) as usize
+                * (options.ttl.is_some() as usize + options.tos.is_some() as usize);
+            for (cmsg_type, header) in &ext_headers {
+                log::debug!(
+                    "sendmsg: Adding IPv6 extension header control message: type={}, len={}",
+                    cmsg_type,
+                    header.len()
+                );
+                let cmsg = cmsg_buf.as_mut_ptr().add(offset) as *mut libc::cmsghdr;
+                (*cmsg).cmsg_level = IPPROTO_IPV6;
+                (*cmsg).cmsg_type = *cmsg_type;
+                (*cmsg).cmsg_len =
+                    libc::CMSG_LEN(header.len() as u32) as usize as msg_controllen_type;
+                std::ptr::copy_nonoverlapping(header.as_ptr(), libc::CMSG_DATA(cmsg), header.len());
+
+                offset += libc::CMSG_SPACE(header.len() as u32) as usize;
+                cmsg_len = offset as msg_controllen_type;
+            }
         } else {
             // IPv4 socket: use IPPROTO_IP control messages
             if let Some(ttl) = options.ttl {
@@ -439,8 +1028,33 @@ fn sendmsg_with_options(
 
         log::debug!("sendmsg: Calling sendmsg with msg_controllen={}", cmsg_len);
 
-        // Send the message
-        let result = sendmsg(fd, &msg, 0);
+        // Send the message, or its fragments in reverse order
+        let result = if let Some(fragment_size) = options.out_of_order_fragment_size {
+            send_fragments_out_of_order(fd, buf, dest, options, fragment_size)?;
+            buf.len() as isize
+        } else if options.df_bit == Some(false) {
+            // Let the kernel fragment the datagram rather than refuse it
+            let (level, name, dont) = if is_ipv6_socket && ipv6_dest {
+                (
+                    IPPROTO_IPV6,
+                    libc::IPV6_MTU_DISCOVER,
+                    libc::IPV6_PMTUDISC_DONT,
+                )
+            } else {
+                (IPPROTO_IP, libc::IP_MTU_DISCOVER, libc::IP_PMTUDISC_DONT)
+            };
+            let previous = replace_socket_option(fd, level, name, dont)?;
+            let result = sendmsg(fd, &msg, 0);
+            let err = std::io::Error::last_os_error();
+            replace_socket_option(fd, level, name, previous)?;
+            if result < 0 {
+                log::error!("❌ sendmsg FAILED with error: {}", err);
+                return Err(err.into());
+            }
+            result
+        } else {
+            sendmsg(fd, &msg, 0)
+        };
 
         if result < 0 {
             let err = std::io::Error::last_os_error();
@@ -455,11 +1069,27 @@ fn sendmsg_with_options(
         log::debug!("sendmsg SUCCEEDED: sent {} bytes", result);
 
         // Track this packet for ICMP/ICMPv6 correlation if TTL/Hop Limit is set
+        // or the caller asked for it
         // Call the extern function from netpoke-server
-        if let Some(ttl_value) = options.ttl {
+        let track_for_ms = options.effective_track_for_ms();
+        if track_for_ms > 0 {
             // Get the local address (source IP and port) from the socket
             let local_addr = get_local_addr(fd);
 
+            // Lengths of what was actually sent: UDP header (8 bytes) + payload,
+            // and IP header on top of that (IPv4-mapped destinations go out as IPv4)
+            let udp_length = (8 + result as usize) as u16;
+            let ip_header_length = match dest {
+                SocketAddr::V4(_) => 20,
+                SocketAddr::V6(addr_v6) if addr_v6.ip().to_ipv4_mapped().is_some() => 20,
+                SocketAddr::V6(_) => 40,
+            };
+            let tracked_options = options.tracked(
+                udp_length
+                    .saturating_add(ip_header_length)
+                    .saturating_add(ext_headers_length as u16),
+            );
+
             // Declare extern functions once
             extern "C" {
                 fn netpoke_track_udp_packet(
@@ -468,7 +1098,7 @@ fn sendmsg_with_options(
                     dest_ip_v4: u32,
                     dest_port: u16,
                     udp_length: u16,
-                    ttl: u8,
+                    options: *const TrackedSendOptions,
                     buf_ptr: *const u8,
                     buf_len: usize,
                     conn_id_ptr: *const u8,
@@ -481,7 +1111,7 @@ fn sendmsg_with_options(
                     dest_ip_v6_ptr: *const u8,
                     dest_port: u16,
                     udp_length: u16,
-                    hop_limit: u8,
+                    options: *const TrackedSendOptions,
                     buf_ptr: *const u8,
                     buf_len: usize,
                     conn_id_ptr: *const u8,
@@ -493,7 +1123,6 @@ fn sendmsg_with_options(
                 SocketAddr::V4(addr_v4) => {
                     // Track IPv4 packet
                     let dest_ip = u32::from_be_bytes(addr_v4.ip().octets());
-                    let udp_length = (8 + buf.len()) as u16; // UDP header (8 bytes) + payload
 
                     // Get source IP and port (use 0.0.0.0:-1 if we can't get local addr)
                     let (src_ip, src_port) = match local_addr {
@@ -511,9 +1140,9 @@ fn sendmsg_with_options(
                         Err(_) => (0, 0xffff),
                     };
 
-                    log::debug!("Calling netpoke_track_udp_packet (IPv4): src={}:{}, dest={}:{}, udp_length={}, ttl={}, conn_id={}",
+                    log::debug!("Calling netpoke_track_udp_packet (IPv4): src={}:{}, dest={}:{}, udp_length={}, ttl={:?}, track_for_ms={}, conn_id={}",
                         std::net::Ipv4Addr::from(src_ip), src_port,
-                        addr_v4.ip(), addr_v4.port(), udp_length, ttl_value, options.conn_id);
+                        addr_v4.ip(), addr_v4.port(), udp_length, options.ttl, track_for_ms, options.conn_id);
 
                     unsafe {
                         netpoke_track_udp_packet(
@@ -522,7 +1151,7 @@ fn sendmsg_with_options(
                             dest_ip,
                             addr_v4.port(),
                             udp_length,
-                            ttl_value,
+                            &tracked_options,
                             buf.as_ptr(),
                             buf.len(),
                             options.conn_id.as_ptr(),
@@ -533,7 +1162,6 @@ fn sendmsg_with_options(
                 SocketAddr::V6(addr_v6) => {
                     // Track IPv6 packet
                     let dest_ip = addr_v6.ip().octets();
-                    let udp_length = (8 + buf.len()) as u16; // UDP header (8 bytes) + payload
 
                     // Get source IP and port
                     let (src_ip, src_port): ([u8; 16], u16) = match local_addr {
@@ -546,9 +1174,9 @@ fn sendmsg_with_options(
                         Err(_) => ([0u8; 16], 0),
                     };
 
-                    log::debug!("Calling netpoke_track_udp_packet_v6 (IPv6): src=[{}]:{}, dest=[{}]:{}, udp_length={}, hop_limit={}, conn_id={}",
+                    log::debug!("Calling netpoke_track_udp_packet_v6 (IPv6): src=[{}]:{}, dest=[{}]:{}, udp_length={}, hop_limit={:?}, track_for_ms={}, conn_id={}",
                         std::net::Ipv6Addr::from(src_ip), src_port,
-                        addr_v6.ip(), addr_v6.port(), udp_length, ttl_value, options.conn_id);
+                        addr_v6.ip(), addr_v6.port(), udp_length, options.ttl, track_for_ms, options.conn_id);
 
                     unsafe {
                         netpoke_track_udp_packet_v6(
@@ -557,7 +1185,7 @@ fn sendmsg_with_options(
                             dest_ip.as_ptr(),
                             addr_v6.port(),
                             udp_length,
-                            ttl_value,
+                            &tracked_options,
                             buf.as_ptr(),
                             buf.len(),
                             options.conn_id.as_ptr(),
@@ -611,4 +1239,129 @@ mod tests {
 
         println!("✓ IPv6 socket correctly identified with family: {}", family);
     }
+
+    #[test]
+    fn test_tracked_send_options() {
+        // Packets with a TTL are tracked by default, others only on request
+        let traceroute = UdpSendOptions {
+            ttl: Some(3),
+            df_bit: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(traceroute.effective_track_for_ms(), DEFAULT_TRACK_FOR_MS);
+        assert_eq!(UdpSendOptions::default().effective_track_for_ms(), 0);
+
+        let mtu_probe = UdpSendOptions {
+            tos: Some(0x10),
+            df_bit: Some(true),
+            bypass_dtls: true,
+            bypass_sctp_fragmentation: true,
+            track_for_ms: Some(2000),
+            ..Default::default()
+        };
+        let tracked = mtu_probe.tracked(1500);
+        assert!(!tracked.has_ttl);
+        assert!(tracked.has_tos && tracked.tos == 0x10);
+        assert!(tracked.has_df_bit && tracked.df_bit);
+        assert!(tracked.bypass_dtls && tracked.bypass_sctp_fragmentation);
+        assert_eq!(tracked.track_for_ms, 2000);
+        assert_eq!(tracked.ip_length, 1500);
+
+        let untracked = UdpSendOptions {
+            ttl: Some(3),
+            track_for_ms: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(untracked.effective_track_for_ms(), 0);
+    }
+
+    #[test]
+    fn test_ipv6_options_header() {
+        assert_eq!(ipv6_options_header_len(0), 8);
+        assert_eq!(ipv6_options_header_len(9), 16);
+        assert_eq!(ipv6_options_header_len(4000), IPV6_OPTIONS_HEADER_MAX_LEN);
+
+        let header = ipv6_options_header(8);
+        assert_eq!(header, [0, 0, IPV6_EXPERIMENTAL_OPTION, 4, 0, 0, 0, 0]);
+
+        for len in [16, 264, 520, IPV6_OPTIONS_HEADER_MAX_LEN] {
+            let header = ipv6_options_header(len);
+            assert_eq!(header.len(), len as usize);
+            assert_eq!((header[1] as usize + 1) * 8, header.len());
+
+            // The options fill the header exactly, 8 of them at most
+            let mut offset = 2;
+            let mut options = 0;
+            while offset < header.len() {
+                assert_eq!(header[offset], IPV6_EXPERIMENTAL_OPTION);
+                offset += 2 + header[offset + 1] as usize;
+                options += 1;
+            }
+            assert_eq!(offset, header.len());
+            assert!(options <= 8, "{} options in {} bytes", options, len);
+        }
+
+        let options = UdpSendOptions {
+            ipv6_dest_opts_len: Some(20),
+            ..Default::default()
+        };
+        let tracked = options.tracked(1300);
+        assert_eq!(tracked.ipv6_hop_by_hop_len, 0);
+        assert_eq!(tracked.ipv6_dest_opts_len, 24);
+    }
+
+    #[test]
+    fn test_out_of_order_fragments() {
+        assert_eq!(ip_fragment_size(0), 8);
+        assert_eq!(ip_fragment_size(1001), 1000);
+        assert_eq!(
+            out_of_order_fragments(2508, 1000),
+            vec![(2000, 508), (1000, 1000), (0, 1000)]
+        );
+        assert_eq!(out_of_order_fragments(1000, 1000), vec![(0, 1000)]);
+
+        let options = UdpSendOptions {
+            out_of_order_fragment_size: Some(1020),
+            ..Default::default()
+        };
+        assert_eq!(options.tracked(3000).out_of_order_fragment_size, 1016);
+    }
+
+    #[test]
+    fn test_udp_datagram_checksum() {
+        // Summing a datagram along with its pseudo header gives all ones
+        let verify = |src: SocketAddr, dest: SocketAddr, payload: &[u8]| {
+            let datagram = udp_datagram(src, dest, payload);
+            assert_eq!(&datagram[8..], payload);
+            let mut words = Vec::new();
+            for addr in [src, dest] {
+                match addr.ip() {
+                    std::net::IpAddr::V4(ip) => words.extend_from_slice(&ip.octets()),
+                    std::net::IpAddr::V6(ip) => words.extend_from_slice(&ip.octets()),
+                }
+            }
+            words.extend_from_slice(&[0, 17]);
+            words.extend_from_slice(&datagram[4..6]);
+            words.extend_from_slice(&datagram);
+            let mut sum: u32 = words
+                .chunks(2)
+                .map(|word| u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32)
+                .sum();
+            while sum > 0xffff {
+                sum = (sum & 0xffff) + (sum >> 16);
+            }
+            assert_eq!(sum, 0xffff);
+        };
+
+        verify(
+            "192.0.2.1:3478".parse().unwrap(),
+            "198.51.100.7:50000".parse().unwrap(),
+            b"an odd-length payload",
+        );
+        verify(
+            "[2001:db8::1]:3478".parse().unwrap(),
+            "[2001:db8::2]:50000".parse().unwrap(),
+            &[0xab; 1500],
+        );
+    }
 }
@@ -5,7 +5,10 @@ pub mod conn_udp;
 pub mod conn_udp_listener;
 
 // Re-export UDP socket options support (added for netpoke)
-pub use conn_udp::UdpSendOptions;
+pub use conn_udp::{
+    ip_fragment_size, ipv6_options_header_len, TrackedSendOptions, UdpSendOptions,
+    DEFAULT_TRACK_FOR_MS, IPV6_OPTIONS_HEADER_MAX_LEN,
+};
 
 #[cfg(test)]
 mod conn_bridge_test;
@@ -65,7 +65,10 @@ pub mod buffer;
 #[cfg(feature = "conn")]
 pub mod conn;
 #[cfg(feature = "conn")]
-pub use crate::conn::UdpSendOptions; // Added for netpoke
+pub use crate::conn::{
+    ip_fragment_size, ipv6_options_header_len, TrackedSendOptions, UdpSendOptions,
+    DEFAULT_TRACK_FOR_MS, IPV6_OPTIONS_HEADER_MAX_LEN,
+}; // Added for netpoke
 
 #[cfg(feature = "ifaces")]
 pub mod ifaces;
@@ -520,7 +520,15 @@ impl Net {
                 let net = vnet.lock().await;
                 net.bind(addr).await
             }
-            Net::Ifs(_) => Ok(Arc::new(UdpSocket::bind(addr).await?)),
+            Net::Ifs(_) => {
+                let socket = UdpSocket::bind(addr).await?;
+                // Report the TTL of received packets (added for netpoke)
+                #[cfg(target_os = "linux")]
+                if let Err(err) = conn::conn_udp::enable_recv_ttl(&socket) {
+                    log::warn!("Failed to enable receiving the TTL on {}: {}", addr, err);
+                }
+                Ok(Arc::new(socket))
+            }
         }
     }
 